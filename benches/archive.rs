@@ -0,0 +1,47 @@
+// Packing a tree of 500 small files into an archive with `encryptor encrypt --archive`, run through the binary
+// (archives are part of the command line tool, not the library) with a key file, so the KDF doesn't count.
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::fs;
+use std::process::Command;
+
+fn archive(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("encryptor-bench-{}", std::process::id()));
+    let tree = dir.join("tree");
+    let text = b"2024-01-01 00001 INFO request 4711 served in 12 ms for user 977\n";
+    let mut tree_len = 0;
+    for subdir in 0..10 {
+        let subdir = tree.join(format!("dir{}", subdir));
+        fs::create_dir_all(&subdir).unwrap();
+        for file in 0..50 {
+            let contents: Vec<u8> = text
+                .iter()
+                .copied()
+                .cycle()
+                .take(4096 + file * 64)
+                .collect();
+            tree_len += contents.len();
+            fs::write(subdir.join(format!("file{}.log", file)), contents).unwrap();
+        }
+    }
+    fs::write(dir.join("bench.key"), [7u8; 32]).unwrap();
+
+    let mut group = c.benchmark_group("archive");
+    group.throughput(Throughput::Bytes(tree_len as u64));
+    group.bench_function("pack", |b| {
+        b.iter(|| {
+            let status = Command::new(env!("CARGO_BIN_EXE_encryptor"))
+                .current_dir(&dir)
+                .args(["encrypt", "--archive", "tree", "--out", "tree.enc"])
+                .args(["--keyfile", "bench.key", "--color", "never"])
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success());
+        })
+    });
+    group.finish();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(benches, archive);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+// Sealing and opening small records with a raw key, as a service encrypting many of them would: the allocating
+// encrypt_bytes/decrypt_bytes against encrypt_in_place/decrypt_in_place reusing one buffer.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use encryptor::Encryptor;
+use std::hint::black_box;
+
+const SIZES: &[usize] = &[64, 1024, 16 * 1024];
+
+fn seal(c: &mut Criterion) {
+    let encryptor = Encryptor::from_key([7; 32]);
+    let mut group = c.benchmark_group("seal");
+    for &size in SIZES {
+        let record = vec![0x5a; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("encrypt_bytes", size),
+            &record,
+            |b, record| b.iter(|| encryptor.encrypt_bytes(black_box(record)).unwrap()),
+        );
+        let mut buffer = Vec::with_capacity(size + encryptor.in_place_overhead());
+        group.bench_with_input(
+            BenchmarkId::new("encrypt_in_place", size),
+            &record,
+            |b, record| {
+                b.iter(|| {
+                    buffer.clear();
+                    buffer.extend_from_slice(black_box(record));
+                    encryptor.encrypt_in_place(&mut buffer).unwrap();
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn open(c: &mut Criterion) {
+    let encryptor = Encryptor::from_key([7; 32]);
+    let mut group = c.benchmark_group("open");
+    for &size in SIZES {
+        let mut sealed = vec![0x5a; size];
+        encryptor.encrypt_in_place(&mut sealed).unwrap();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("decrypt_bytes", size),
+            &sealed,
+            |b, sealed| b.iter(|| encryptor.decrypt_bytes(black_box(sealed)).unwrap()),
+        );
+        let mut buffer = Vec::with_capacity(sealed.len());
+        group.bench_with_input(
+            BenchmarkId::new("decrypt_in_place", size),
+            &sealed,
+            |b, sealed| {
+                b.iter(|| {
+                    buffer.clear();
+                    buffer.extend_from_slice(black_box(sealed));
+                    encryptor.decrypt_in_place(&mut buffer).unwrap();
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, seal, open);
+criterion_main!(benches);
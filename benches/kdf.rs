@@ -0,0 +1,29 @@
+// What a password costs: a fresh Encryptor derives its key with Argon2id once, on its first operation.
+// The default cost, and a lower and a higher memory setting at the same iteration count.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use encryptor::{Encryptor, KdfParams};
+
+fn kdf(c: &mut Criterion) {
+    let default = KdfParams::default();
+    let mut group = c.benchmark_group("kdf");
+    group.sample_size(10);
+    for memory in [default.memory / 4, default.memory, default.memory * 4] {
+        let params = KdfParams { memory, ..default };
+        group.bench_with_input(
+            BenchmarkId::new("argon2id", memory),
+            &params,
+            |b, params| {
+                b.iter(|| {
+                    Encryptor::new("correct horse battery staple")
+                        .with_kdf_params(*params)
+                        .encrypt_bytes(b"")
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, kdf);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+// Streaming a large file from disk through encrypt_file_with and decrypt_file_with with a raw key, so the KDF
+// doesn't count. Both write their output next to the input in the system's temporary directory.
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use encryptor::{CancellationToken, Encryptor};
+use std::fs;
+
+const FILE_LEN: usize = 64 * 1024 * 1024;
+
+fn streaming(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("encryptor-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let plain_path = dir.join("stream.bin").to_string_lossy().into_owned();
+    let text = b"2024-01-01 00001 INFO request 4711 served in 12 ms for user 977\n";
+    let contents: Vec<u8> = text.iter().copied().cycle().take(FILE_LEN).collect();
+    fs::write(&plain_path, contents).unwrap();
+
+    let encryptor = Encryptor::from_key([7; 32]);
+    let cancel = CancellationToken::new();
+    let mut group = c.benchmark_group("stream");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(FILE_LEN as u64));
+    group.bench_function("encrypt", |b| {
+        b.iter(|| {
+            encryptor
+                .encrypt_file_with(&plain_path, |_| {}, &cancel)
+                .unwrap()
+        })
+    });
+    let sealed_path = format!("{}.enc", plain_path);
+    group.bench_function("decrypt", |b| {
+        b.iter(|| {
+            encryptor
+                .decrypt_file_with(&sealed_path, |_| {}, &cancel)
+                .unwrap()
+        })
+    });
+    group.finish();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(benches, streaming);
+criterion_main!(benches);
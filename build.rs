@@ -0,0 +1,18 @@
+// With the ffi feature, regenerate the C header of the functions in src/ffi.rs, include/encryptor.h, so that it
+// never falls behind them. The header is checked in for applications that only link the library.
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+            .expect("cbindgen.toml is invalid");
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{}/src/ffi.rs", crate_dir))
+            .generate()
+            .expect("src/ffi.rs can't be turned into a C header")
+            .write_to_file(format!("{}/include/encryptor.h", crate_dir));
+    }
+}
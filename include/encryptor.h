@@ -0,0 +1,65 @@
+#ifndef ENCRYPTOR_H
+#define ENCRYPTOR_H
+
+/* Generated from src/ffi.rs by cbindgen when building with the ffi feature; don't edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// Returned on success
+#define ENCRYPTOR_OK 0
+
+// Returned when the library panicked
+#define ENCRYPTOR_FAILED 1
+
+// Returned for invalid arguments
+#define ENCRYPTOR_USAGE 2
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Encrypt the `input_len` bytes at `input` with a key derived from the NUL-terminated UTF-8 `password`, storing
+// a newly allocated buffer of the output in `*output` and its length in `*output_len`. Free the buffer with
+// [`encryptor_free_buf`].
+//
+// # Safety
+//
+// `password` must be a NUL-terminated string, `input` must point to `input_len` readable bytes (or may be null if
+// `input_len` is 0), and `output` and `output_len` must be writable.
+int encryptor_encrypt_buf(const char *password,
+                          const uint8_t *input,
+                          size_t input_len,
+                          uint8_t **output,
+                          size_t *output_len);
+
+// Decrypt the `input_len` bytes at `input` with a key derived from the NUL-terminated UTF-8 `password`, storing
+// a newly allocated buffer of the plaintext in `*output` and its length in `*output_len`. Free the buffer with
+// [`encryptor_free_buf`]. Nothing is stored if the input doesn't authenticate.
+//
+// # Safety
+//
+// The same as for [`encryptor_encrypt_buf`].
+int encryptor_decrypt_buf(const char *password,
+                          const uint8_t *input,
+                          size_t input_len,
+                          uint8_t **output,
+                          size_t *output_len);
+
+// Free a buffer returned by [`encryptor_encrypt_buf`] or [`encryptor_decrypt_buf`]. Null is ignored.
+//
+// # Safety
+//
+// `buf` and `len` must be a buffer and its length as returned by this library, not freed before.
+void encryptor_free_buf(uint8_t *buf,
+                        size_t len);
+
+// A description of the last failure on the calling thread, starting with its error code (e.g. `ENC_AUTH_FAIL`),
+// or null if nothing failed yet. The string stays valid until the next call into the library on the same thread.
+const char *encryptor_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ENCRYPTOR_H */
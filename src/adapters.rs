@@ -0,0 +1,568 @@
+//! [`std::io`] adapters that encrypt what is written to them and decrypt what is read from them.
+//!
+//! [`EncryptingWriter`] seals what is written to it into any [`Write`] in the chunked format of the [`stream`]
+//! module, one chunk in memory at a time, and [`DecryptingReader`] yields the plaintext of any [`Read`], so both
+//! compose with [`io::copy`], buffered readers and writers, sockets, pipes and compressors:
+//!
+//! ```no_run
+//! use encryptor::{DecryptingReader, EncryptingWriter, Encryptor};
+//! use std::fs::File;
+//! use std::io;
+//!
+//! # fn main() -> Result<(), encryptor::EncryptError> {
+//! let encryptor = Encryptor::new("correct horse battery staple");
+//! let mut writer = EncryptingWriter::new(File::create("backup.tar.enc")?, &encryptor)?;
+//! io::copy(&mut File::open("backup.tar")?, &mut writer)?;
+//! writer.finish()?;
+//!
+//! let mut reader = DecryptingReader::new(File::open("backup.tar.enc")?, &encryptor)?;
+//! io::copy(&mut reader, &mut io::stdout())?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The output is what [`Encryptor::encrypt_file`] writes for a large file, except that the length of the payload
+//! isn't known up front and so isn't recorded in the header; the chunk marked as the last one still makes a stream
+//! that was cut off at a chunk boundary fail. With a password, every writer derives a new key (see
+//! [`Encryptor::with_kdf_params`]), so creating one costs as much as encrypting a file.
+//!
+//! [`SeekableReader`] reads any byte range of a chunked file that can seek, such as a [`File`](std::fs::File),
+//! decrypting only the chunks that cover it. Every chunk is sealed on its own and has the same size but the last,
+//! so where each one starts follows from the header, without an index:
+//!
+//! ```no_run
+//! use encryptor::{Encryptor, SeekableReader};
+//! use std::fs::File;
+//!
+//! # fn main() -> Result<(), encryptor::EncryptError> {
+//! let encryptor = Encryptor::new("correct horse battery staple");
+//! let mut reader = SeekableReader::new(File::open("disk.img.enc")?, &encryptor)?;
+//! let mut sector = [0; 4096];
+//! reader.read_at(7 << 30, &mut sector)?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::header::{Header, HeaderError};
+use crate::progress::IO_CHUNK;
+use crate::{
+    check_ciphertext_len, log_layout, padding, sealed_name_complete, stream, unseal_name,
+    ChunkIndex, Codec, EncryptError, Encryptor, TAG_LEN,
+};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// A [`Write`] that encrypts what is written to it into another one, in chunks of [`Encryptor::with_chunk_size`]
+/// bytes whatever the stream threshold. Compression and the encoder don't apply, and there is no name to record
+/// or hide.
+///
+/// The header is written by [`EncryptingWriter::new`], and each chunk once it is full and more is written. The
+/// last chunk is only sealed by [`EncryptingWriter::finish`]: a writer dropped without it leaves a stream that
+/// doesn't decrypt, as it can't tell a finished stream from an interrupted one. After an error the stream is
+/// unusable, and every later write fails too.
+pub struct EncryptingWriter<W: Write> {
+    inner: W,
+    chunks: stream::Chunks,
+    chunk_size: usize,
+    chunk: Vec<u8>,
+    header_len: u64,
+    trailer: Option<Vec<u8>>,
+    failed: bool,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    /// Derive a key with the settings of `encryptor` and write the header of a new stream to `inner`.
+    pub fn new(mut inner: W, encryptor: &Encryptor) -> Result<Self, EncryptError> {
+        let chunk_size = encryptor.chunk_size;
+        let (header, header_bytes, key_bytes) = encryptor.new_header(
+            None,
+            (None, false, false),
+            None,
+            Some(chunk_size),
+            (None, None, None),
+        )?;
+        inner.write_all(&header_bytes)?;
+        let aad = header.associated_data(&header_bytes);
+        Ok(EncryptingWriter {
+            inner,
+            chunks: stream::Chunks::new(header.algorithm, &key_bytes, &header.nonce, &aad),
+            chunk_size: chunk_size as usize,
+            chunk: Vec::with_capacity(chunk_size as usize + TAG_LEN),
+            header_len: header_bytes.len() as u64,
+            trailer: encryptor
+                .header_backup
+                .then(|| Header::backup_trailer(&header_bytes)),
+            failed: false,
+        })
+    }
+
+    /// Seal the last chunk, write the header backup if [`Encryptor::with_header_backup`] is set, and flush,
+    /// returning the inner writer.
+    pub fn finish(mut self) -> Result<W, EncryptError> {
+        if self.failed {
+            return Err(failed().into());
+        }
+        self.seal_chunk(true)?;
+        if let Some(trailer) = &self.trailer {
+            self.inner.write_all(trailer)?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// How long the stream is once `plaintext_len` bytes in all are written and it is finished, for a
+    /// Content-Length sent before the stream is.
+    pub fn output_len(&self, plaintext_len: u64) -> u64 {
+        let chunks = stream::chunk_count(plaintext_len, self.chunk_size as u32);
+        let trailer_len = self.trailer.as_ref().map_or(0, Vec::len) as u64;
+        self.header_len + plaintext_len + chunks * TAG_LEN as u64 + trailer_len
+    }
+
+    /// The writer the stream is written to.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    // Seal the chunk gathered so far and write it out
+    fn seal_chunk(&mut self, last: bool) -> Result<(), EncryptError> {
+        self.failed = true;
+        self.chunks.seal(&mut self.chunk, last)?;
+        self.inner.write_all(&self.chunk)?;
+        self.chunk.clear();
+        self.failed = false;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.failed {
+            return Err(failed());
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // A full chunk is only sealed once more follows it, as the last one is sealed differently
+        if self.chunk.len() == self.chunk_size {
+            self.seal_chunk(false).map_err(io_error)?;
+        }
+        let len = buf.len().min(self.chunk_size - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    // Flushes the inner writer; the chunk being gathered stays buffered until it is full
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A [`Read`] that yields the plaintext of an encrypted stream read from another one.
+///
+/// A chunked payload is decrypted one chunk at a time, each yielded as soon as it authenticates, so when a read
+/// fails the plaintext read so far must be thrown away, like a broken download. Anything else (a payload sealed in
+/// one piece or compressed, or an encoded file) is read whole by [`DecryptingReader::new`]. A name sealed with the
+/// contents is dropped. A failure is returned as an [`io::ErrorKind::InvalidData`] error wrapping the
+/// [`EncryptError`] (errors of the inner reader are returned as they are), and every later read fails too, so that
+/// a damaged stream never looks like one that ended.
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    opener: Option<ChunkOpener>,
+    ended: bool,
+    plaintext: Vec<u8>,
+    position: usize,
+    failed: bool,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    /// Read the header of the stream from `inner` and derive its key with the settings of `encryptor`.
+    pub fn new(mut inner: R, encryptor: &Encryptor) -> Result<Self, EncryptError> {
+        // Read as much of the start as the header turns out to need
+        let mut start = Vec::new();
+        let mut wanted = 4096;
+        let mut ended = false;
+        let parsed = loop {
+            while start.len() < wanted && !ended {
+                ended = read_more(&mut inner, &mut start)? == 0;
+            }
+            match Header::parse(&start) {
+                Err(HeaderError::Truncated) if !ended => wanted *= 2,
+                Ok(parsed) => break Some(parsed),
+                Err(_) => break None,
+            }
+        };
+        let (opener, plaintext) = match parsed.filter(|(header, _)| ChunkOpener::applies(header)) {
+            Some((header, header_len)) => {
+                let pending = start.split_off(header_len);
+                let key_bytes = encryptor.key(&header)?;
+                let opener = ChunkOpener::new(header, start, &key_bytes, pending);
+                (Some(opener), Vec::new())
+            }
+            None => {
+                inner.read_to_end(&mut start)?;
+                (None, encryptor.open(start, None)?.0)
+            }
+        };
+        Ok(DecryptingReader {
+            inner,
+            opener,
+            ended,
+            plaintext,
+            position: 0,
+            failed: false,
+        })
+    }
+
+    /// The reader the stream is read from.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Return the inner reader, positioned wherever decrypting stopped.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // Read and open the next chunk, returning None at the end of the stream
+    fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, EncryptError> {
+        let Some(opener) = &mut self.opener else {
+            return Ok(None);
+        };
+        while opener.wants_more() && !self.ended {
+            self.ended = read_more(&mut self.inner, opener.buffer())? == 0;
+        }
+        opener.next(self.ended)
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.failed {
+            return Err(failed());
+        }
+        while self.position == self.plaintext.len() {
+            match self.next_chunk() {
+                Ok(Some(chunk)) => {
+                    self.plaintext = chunk;
+                    self.position = 0;
+                }
+                Ok(None) => return Ok(0),
+                Err(err) => {
+                    self.failed = true;
+                    return Err(io_error(err));
+                }
+            }
+        }
+        let len = buf.len().min(self.plaintext.len() - self.position);
+        buf[..len].copy_from_slice(&self.plaintext[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+// Opens the chunks of a chunked payload as the stream they are read from comes in, whatever reads it (see
+// DecryptingReader and async_io::decrypt_reader): bytes read are appended to buffer(), as long as wants_more()
+pub(crate) struct ChunkOpener {
+    chunks: stream::Chunks,
+    header: Header,
+    header_bytes: Vec<u8>,
+    pending: Vec<u8>,
+    sealed_chunk_len: usize,
+    trailer_len: usize,
+    expected_len: Option<u64>,
+    unpadded_len: Option<u64>, // How much of the payload is left once its padding is cut off
+    // The start of the payload, until the sealed name in front of it is complete
+    name_prefix: Option<Vec<u8>>,
+    ciphertext_len: u64,
+    index: u64,
+    done: bool,
+}
+
+impl ChunkOpener {
+    // Whether a payload with `header` can be opened chunk by chunk, rather than read whole. A padded payload needs
+    // its length recorded, to tell where the padding starts before the stream ends.
+    pub(crate) fn applies(header: &Header) -> bool {
+        header.chunk_size.is_some()
+            && header.compression == Codec::None
+            && (header.padding.is_none() || header.payload_len.is_some())
+    }
+
+    // `pending` is what was read after the header already
+    pub(crate) fn new(
+        header: Header,
+        header_bytes: Vec<u8>,
+        key_bytes: &[u8; crate::KEY_LEN],
+        pending: Vec<u8>,
+    ) -> Self {
+        log_layout("Opening", &header);
+        let aad = header.associated_data(&header_bytes);
+        ChunkOpener {
+            chunks: stream::Chunks::new(header.algorithm, key_bytes, &header.nonce, &aad),
+            sealed_chunk_len: header.chunk_size.unwrap_or_default() as usize + TAG_LEN,
+            trailer_len: match header.header_backup {
+                true => header_bytes.len() + 4,
+                false => 0,
+            },
+            expected_len: header.ciphertext_len(),
+            unpadded_len: header
+                .payload_len
+                .map(|len| len.saturating_sub(padding::recorded(&header, key_bytes))),
+            name_prefix: header.sealed_name.then(Vec::new),
+            header,
+            header_bytes,
+            pending,
+            ciphertext_len: 0,
+            index: 0,
+            done: false,
+        }
+    }
+
+    // Whether more of the stream is needed to tell whether the next chunk is the last one
+    pub(crate) fn wants_more(&self) -> bool {
+        !self.done && self.pending.len() <= self.sealed_chunk_len + self.trailer_len
+    }
+
+    pub(crate) fn buffer(&mut self) -> &mut Vec<u8> {
+        &mut self.pending
+    }
+
+    // Open the next chunk, `ended` being whether the stream has been read to its end, returning its plaintext
+    // (empty while a sealed name is incomplete) or None after the last one
+    pub(crate) fn next(&mut self, ended: bool) -> Result<Option<Vec<u8>>, EncryptError> {
+        if self.done {
+            return Ok(None);
+        }
+        // A chunk is the last one once only the header backup follows it
+        let last = ended && self.wants_more();
+        let mut chunk = match last {
+            false => {
+                let rest = self.pending.split_off(self.sealed_chunk_len);
+                std::mem::replace(&mut self.pending, rest)
+            }
+            true if self.pending.len() < self.trailer_len + TAG_LEN => {
+                return Err(HeaderError::Truncated.into())
+            }
+            true => {
+                let trailer = self
+                    .pending
+                    .split_off(self.pending.len() - self.trailer_len);
+                if self.header.header_backup
+                    && trailer != Header::backup_trailer(&self.header_bytes)
+                {
+                    return Err(EncryptError::MetadataMismatch(
+                        "the header copy at the end of the file doesn't match the header at the start"
+                            .to_string(),
+                    ));
+                }
+                std::mem::take(&mut self.pending)
+            }
+        };
+
+        // A stream longer than the header says fails as soon as it is, not at its end
+        let offset = self.ciphertext_len;
+        self.ciphertext_len += chunk.len() as u64;
+        if last
+            || self
+                .expected_len
+                .is_some_and(|expected| self.ciphertext_len > expected)
+        {
+            check_ciphertext_len(self.expected_len, self.ciphertext_len)?;
+        }
+        let index = self.index;
+        self.chunks
+            .open(&mut chunk, last)
+            .map_err(|err| match err {
+                EncryptError::AeadError(_) => EncryptError::CorruptChunk {
+                    index,
+                    offset: self.header_bytes.len() as u64 + offset,
+                },
+                err => err,
+            })?;
+        self.index += 1;
+        self.done = last;
+        if let Some(unpadded_len) = self.unpadded_len {
+            let end = unpadded_len.saturating_sub(index * (self.sealed_chunk_len - TAG_LEN) as u64);
+            chunk.truncate(end.try_into().unwrap_or(usize::MAX));
+        }
+
+        if let Some(prefix) = &mut self.name_prefix {
+            prefix.append(&mut chunk);
+            if !sealed_name_complete(prefix) && !last {
+                return Ok(Some(Vec::new()));
+            }
+            chunk = unseal_name(std::mem::take(prefix), self.header.escaped_name)?.0;
+            self.name_prefix = None;
+        }
+        Ok(Some(chunk))
+    }
+}
+
+/// A [`Read`] and [`Seek`] over the plaintext of an encrypted file with a chunked payload, that only reads and
+/// decrypts the chunks covering what is read, for partial restores from huge files.
+///
+/// The payload must be chunked and uncompressed, as files of at least
+/// [`Encryptor::with_stream_threshold`](crate::Encryptor::with_stream_threshold) bytes and the output of
+/// [`EncryptingWriter`] are, and written raw rather than encoded. The length recorded in the header and the header
+/// copy at the end are checked up front, and each chunk is authenticated before any of it is returned, so a range
+/// never includes plaintext that was tampered with; chunks outside it are not checked. A name sealed with the
+/// contents is skipped. [`Read`] and [`Seek`] return failures as [`io::ErrorKind::InvalidData`] errors wrapping the
+/// [`EncryptError`], like [`DecryptingReader`].
+pub struct SeekableReader<R: Read + Seek> {
+    inner: R,
+    index: ChunkIndex,
+    position: u64,
+    // The index and plaintext of the chunk opened last
+    cached: Option<(u64, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> SeekableReader<R> {
+    /// Read the header of the file from the start of `inner` and derive its key with the settings of `encryptor`.
+    pub fn new(mut inner: R, encryptor: &Encryptor) -> Result<Self, EncryptError> {
+        inner.seek(SeekFrom::Start(0))?;
+        let mut head = Vec::new();
+        let mut wanted = 4096;
+        let mut ended = false;
+        let (header, header_len) = loop {
+            while head.len() < wanted && !ended {
+                ended = read_more(&mut inner, &mut head)? == 0;
+            }
+            match Header::parse(&head) {
+                Err(HeaderError::Truncated) if !ended => wanted *= 2,
+                parsed => break parsed?,
+            }
+        };
+        let file_len = inner.seek(SeekFrom::End(0))?;
+        // A sealed name is read with the chunks holding the longest name there can be
+        let name_len = match (header.sealed_name, header.chunk_size) {
+            (true, Some(chunk_size)) => {
+                let chunks = (2 + u16::MAX as u64).div_ceil(chunk_size as u64);
+                chunks * (chunk_size as u64 + TAG_LEN as u64)
+            }
+            _ => 0,
+        };
+        head.truncate(header_len);
+        let head_len = file_len.min(header_len as u64 + name_len);
+        inner.seek(SeekFrom::Start(header_len as u64))?;
+        (&mut inner)
+            .take(head_len.saturating_sub(header_len as u64))
+            .read_to_end(&mut head)?;
+        let index = ChunkIndex::new(&head, file_len, encryptor)?;
+
+        if header.header_backup {
+            let mut trailer = vec![0; header_len + 4];
+            inner.seek(SeekFrom::Start(file_len - trailer.len() as u64))?;
+            inner.read_exact(&mut trailer)?;
+            if trailer != Header::backup_trailer(&head[..header_len]) {
+                return Err(EncryptError::MetadataMismatch(
+                    "the header copy at the end of the file doesn't match the header at the start"
+                        .to_string(),
+                ));
+            }
+        }
+        Ok(SeekableReader {
+            inner,
+            index,
+            position: 0,
+            cached: None,
+        })
+    }
+
+    /// The length of the plaintext.
+    pub fn len(&self) -> u64 {
+        self.index.len()
+    }
+
+    /// Whether the plaintext is empty.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Decrypt the plaintext from `offset` into `buf`, returning how many bytes were read: as many as fit, unless
+    /// the plaintext ends first (0 at or after its end). Doesn't move the position [`Read`] reads from.
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize, EncryptError> {
+        let mut done = 0;
+        while done < buf.len() && offset.saturating_add(done as u64) < self.len() {
+            let position = offset + done as u64;
+            let index = self.index.chunk_at(position);
+            let start = (position - self.index.plaintext_of(index).start) as usize;
+            let chunk = self.chunk(index)?;
+            let len = (buf.len() - done).min(chunk.len() - start);
+            buf[done..done + len].copy_from_slice(&chunk[start..start + len]);
+            done += len;
+        }
+        Ok(done)
+    }
+
+    /// Return the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // Read and open the chunk at `index`, returning its plaintext
+    fn chunk(&mut self, index: u64) -> Result<&[u8], EncryptError> {
+        if self
+            .cached
+            .as_ref()
+            .is_none_or(|(cached, _)| *cached != index)
+        {
+            self.cached = None;
+            let range = self.index.sealed_chunk(index);
+            let mut sealed = vec![0; (range.end - range.start) as usize];
+            self.inner.seek(SeekFrom::Start(range.start))?;
+            self.inner.read_exact(&mut sealed)?;
+            self.cached = Some((index, self.index.decrypt_chunk(index, &sealed)?));
+        }
+        Ok(self.cached.as_ref().map_or(&[], |(_, chunk)| chunk))
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.read_at(self.position, buf).map_err(io_error)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableReader<R> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match position {
+            SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (self.len(), offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        self.position = base.checked_add_signed(offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't seek before the start of the plaintext",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+// Read what `reader` has next onto the end of `buffer`, returning how much (0 at its end)
+fn read_more(reader: &mut impl Read, buffer: &mut Vec<u8>) -> io::Result<usize> {
+    let len = buffer.len();
+    buffer.resize(len + IO_CHUNK, 0);
+    let read = loop {
+        match reader.read(&mut buffer[len..]) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            read => break read,
+        }
+    };
+    buffer.truncate(len + *read.as_ref().unwrap_or(&0));
+    read
+}
+
+fn io_error(error: EncryptError) -> io::Error {
+    match error {
+        EncryptError::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+fn failed() -> io::Error {
+    io::Error::other("the stream already failed")
+}
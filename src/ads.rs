@@ -0,0 +1,133 @@
+// NTFS alternate data streams, for `encrypt --archive --ads`: the named streams a file or directory holds besides
+// its contents, like the Zone.Identifier stream Windows adds to downloads, which packing only the contents would
+// silently drop. Only Windows has them; elsewhere nothing has any, and `--extract` skips those an archive holds with
+// a warning.
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The names of the alternate data streams of `path`, without the `:` and `:$DATA` around them
+#[cfg(windows)]
+pub fn list(path: &Path) -> io::Result<Vec<OsString>> {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+    // Kernel32's stream enumeration, which std doesn't expose
+    #[repr(C)]
+    struct FindStreamData {
+        _size: i64,
+        name: [u16; 260 + 36],
+    }
+    const INVALID_HANDLE_VALUE: isize = -1;
+    const ERROR_HANDLE_EOF: i32 = 38;
+    const ERROR_INVALID_PARAMETER: i32 = 87;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn FindFirstStreamW(
+            file_name: *const u16,
+            info_level: i32,
+            data: *mut FindStreamData,
+            flags: u32,
+        ) -> isize;
+        fn FindNextStreamW(handle: isize, data: *mut FindStreamData) -> i32;
+        fn FindClose(handle: isize) -> i32;
+    }
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    let mut data = FindStreamData {
+        _size: 0,
+        name: [0; 296],
+    };
+    // SAFETY: `wide` is NUL-terminated and `data` is a WIN32_FIND_STREAM_DATA, FindStreamInfoStandard's output
+    let handle = unsafe { FindFirstStreamW(wide.as_ptr(), 0, &mut data, 0) };
+    if handle == INVALID_HANDLE_VALUE {
+        let err = io::Error::last_os_error();
+        // A directory without streams has none to find, and a FAT volume none at all
+        return match err.raw_os_error() {
+            Some(ERROR_HANDLE_EOF | ERROR_INVALID_PARAMETER) => Ok(Vec::new()),
+            _ => Err(err),
+        };
+    }
+    let mut streams = Vec::new();
+    loop {
+        let len = data
+            .name
+            .iter()
+            .position(|unit| *unit == 0)
+            .unwrap_or(data.name.len());
+        // ":name:$DATA", or "::$DATA" for the contents themselves
+        let full = OsString::from_wide(&data.name[..len]);
+        if let Some(name) = full
+            .to_str()
+            .and_then(|full| full.strip_prefix(':'))
+            .and_then(|full| full.strip_suffix(":$DATA"))
+            .filter(|name| !name.is_empty())
+        {
+            streams.push(OsString::from(name));
+        } else if !full.as_encoded_bytes().starts_with(b"::") {
+            log::warn!(
+                "Skipping the stream {:?} of {}: its name is not valid UTF-8",
+                full,
+                path.display()
+            );
+        }
+        // SAFETY: `handle` came from FindFirstStreamW and is still open
+        if unsafe { FindNextStreamW(handle, &mut data) } == 0 {
+            break;
+        }
+    }
+    let err = io::Error::last_os_error();
+    // SAFETY: as above, and it isn't used again
+    unsafe { FindClose(handle) };
+    match err.raw_os_error() {
+        Some(ERROR_HANDLE_EOF) => Ok(streams),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(windows))]
+pub fn list(_path: &Path) -> io::Result<Vec<OsString>> {
+    Ok(Vec::new())
+}
+
+// The path of the stream `name` of `path`, which opens like a file of its own on NTFS
+pub fn path(path: &Path, name: &str) -> PathBuf {
+    let mut stream = path.as_os_str().to_os_string();
+    stream.push(":");
+    stream.push(name);
+    PathBuf::from(stream)
+}
+
+// Whether `name` can be a stream name: separators and colons would make it a path elsewhere
+pub fn is_valid(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['/', '\\', ':', '\0'])
+}
+
+// Create the stream `name` of `path` with `contents`, returning whether this platform could. An existing stream
+// is never overwritten.
+#[cfg(windows)]
+pub fn create(path: &Path, name: &str, contents: &[u8]) -> io::Result<bool> {
+    use std::io::Write;
+
+    let mut stream = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(self::path(path, name))?;
+    stream.write_all(contents)?;
+    Ok(true)
+}
+
+#[cfg(not(windows))]
+pub fn create(path: &Path, name: &str, _contents: &[u8]) -> io::Result<bool> {
+    log::warn!(
+        "Skipping the alternate data stream {} of {}: they are only restored on Windows",
+        name,
+        path.display()
+    );
+    Ok(false)
+}
+
+// Reopen the file at `path` to write its times back, which writing one of its streams changed
+pub fn reopen(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).open(path)
+}
@@ -0,0 +1,226 @@
+// Files in the age format (https://age-encryption.org/v1), for `encrypt --format age` and decrypting age files.
+// Their output can be decrypted by the `age` and `rage` tools, and decrypt detects age files (binary or armored)
+// written by them. Passwords become scrypt recipients and X25519 keys become X25519 recipients, so the keys from
+// `keygen --asymmetric` and age-keygen work with either tool. Needs the `age` cargo feature; without it
+// age files are still detected, but encrypting or decrypting them fails with an explanation.
+#[cfg(feature = "age")]
+use crate::streamed;
+use crate::streamed::Existing;
+use crate::Secret;
+use encryptor::EncryptError;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+// How an age file starts: the version line of the binary format, or the armor around it
+const MAGIC: &[u8] = b"age-encryption.org/";
+const ARMOR: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+
+// Whether the file at `path` is an age file rather than one of ours
+pub fn is_age(path: &Path) -> bool {
+    let mut start = Vec::with_capacity(ARMOR.len());
+    let read = fs::File::open(path).and_then(|file| {
+        file.take(ARMOR.len() as u64)
+            .read_to_end(&mut start)
+            .map(|_| ())
+    });
+    read.is_ok() && (start.starts_with(MAGIC) || start.starts_with(ARMOR))
+}
+
+// Encrypt the file at `file_path` into `<file_path>.age`, in age's own armor with `armor`, returning the path written
+#[cfg(feature = "age")]
+pub fn encrypt(
+    file_path: &Path,
+    secret: &Secret,
+    armor: bool,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    use age::armor::{ArmoredWriter, Format};
+    use age::secrecy::SecretString;
+    use std::io::Write;
+
+    // age can't mix a passphrase with public keys in one file, and has no raw key mode
+    let recipients: Vec<Box<dyn age::Recipient + Send>> = match secret {
+        Secret::Password(password, extra) if extra.is_empty() => vec![Box::new(
+            age::scrypt::Recipient::new(SecretString::from(password.as_str())),
+        )],
+        Secret::Password(..) => {
+            return Err(unsupported(
+                "age files can't be encrypted to a password and public keys at once",
+            ))
+        }
+        Secret::Key(_) => return Err(unsupported("age files can't be encrypted with a raw key")),
+        Secret::Identity(identity) => vec![Box::new(recipient(&identity.recipient().to_bytes()))],
+        Secret::Recipients(recipients) => recipients
+            .iter()
+            .map(|target| Box::new(recipient(&target.to_bytes())) as Box<dyn age::Recipient + Send>)
+            .collect(),
+        Secret::Certificates(_) => {
+            return Err(unsupported("age files can't be encrypted to certificates"))
+        }
+        Secret::Grants(_) => return Err(unsupported("age files can't be encrypted with a grant")),
+        Secret::Fido2(_) => {
+            return Err(unsupported(
+                "age files can't be encrypted with a security key",
+            ))
+        }
+    };
+    let encryptor = age::Encryptor::with_recipients(
+        recipients
+            .iter()
+            .map(|recipient| recipient.as_ref() as &dyn age::Recipient),
+    )
+    .map_err(|err| match err {
+        age::EncryptError::Io(err) => EncryptError::IoError(err),
+        err => unsupported(&err.to_string()),
+    })?;
+
+    // Nothing is written until the whole file has been encrypted, so a failure leaves no partial output
+    let plaintext = fs::read(file_path)?;
+    let mut output = Vec::with_capacity(plaintext.len() + 256);
+    let format = match armor {
+        true => Format::AsciiArmor,
+        false => Format::Binary,
+    };
+    let mut writer = encryptor.wrap_output(ArmoredWriter::wrap_output(&mut output, format)?)?;
+    writer.write_all(&plaintext)?;
+    writer.finish()?.finish()?;
+
+    let output_path = streamed::claim(encryptor::names::with_suffix(file_path, ".age"), existing)?;
+    streamed::write(&output[..], &output_path)?;
+    Ok(output_path)
+}
+
+// Decrypt the age file at `file_path` into the same path without its last extension, returning the path written
+#[cfg(feature = "age")]
+pub fn decrypt(
+    file_path: &Path,
+    secret: &Secret,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    use age::secrecy::SecretString;
+    use encryptor::CryptoError;
+
+    let identity: Box<dyn age::Identity> = match secret {
+        Secret::Password(password, _) => Box::new(age::scrypt::Identity::new(SecretString::from(
+            password.as_str(),
+        ))),
+        Secret::Identity(identity) => Box::new(self::identity(&identity.to_bytes())),
+        Secret::Key(_) => return Err(unsupported("age files can't be decrypted with a raw key")),
+        Secret::Recipients(_) => {
+            return Err(unsupported(
+                "age files are decrypted with an identity, not public keys",
+            ))
+        }
+        Secret::Certificates(_) => {
+            return Err(unsupported(
+                "age files can't be decrypted with certificates",
+            ))
+        }
+        Secret::Grants(_) => return Err(unsupported("age files can't be decrypted with a grant")),
+        Secret::Fido2(_) => {
+            return Err(unsupported(
+                "age files can't be decrypted with a security key",
+            ))
+        }
+    };
+
+    // A wrong password or identity is reported like one for our own files
+    let failed = |err: age::DecryptError| match err {
+        age::DecryptError::Io(err) => EncryptError::IoError(err),
+        age::DecryptError::DecryptionFailed
+        | age::DecryptError::InvalidMac
+        | age::DecryptError::KeyDecryptionFailed
+        | age::DecryptError::NoMatchingKeys => EncryptError::AeadError(CryptoError),
+        err => EncryptError::IoError(io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+    };
+    let contents = fs::read(file_path)?;
+    let decryptor =
+        age::Decryptor::new(age::armor::ArmoredReader::new(&contents[..])).map_err(failed)?;
+    let mut plaintext = Vec::new();
+    decryptor
+        .decrypt(std::iter::once(identity.as_ref()))
+        .map_err(failed)?
+        .read_to_end(&mut plaintext)?;
+
+    let output_path = encryptor::names::strip_extension(file_path).to_path_buf();
+    let output_path = streamed::claim(output_path, existing)?;
+    streamed::write(&plaintext[..], &output_path)?;
+    Ok(output_path)
+}
+
+// The 32 bytes of a key in age's Bech32 encoding: an `age1...` public key, or an `AGE-SECRET-KEY-1...` identity.
+// Identity files from age-keygen have comment lines around the key, which are skipped.
+#[cfg(feature = "age")]
+pub fn decode_key(text: &str) -> Option<[u8; 32]> {
+    use bech32::FromBase32;
+
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))?;
+    let (hrp, data, _) = bech32::decode(line).ok()?;
+    if hrp != "age" && hrp != "age-secret-key-" {
+        return None;
+    }
+    Vec::<u8>::from_base32(&data).ok()?.try_into().ok()
+}
+
+#[cfg(feature = "age")]
+fn recipient(public_key: &[u8; 32]) -> age::x25519::Recipient {
+    bech32_key("age", public_key)
+        .parse()
+        .expect("a Bech32 public key always parses")
+}
+
+#[cfg(feature = "age")]
+fn identity(secret_key: &[u8; 32]) -> age::x25519::Identity {
+    bech32_key("age-secret-key-", secret_key)
+        .to_uppercase()
+        .parse()
+        .expect("a Bech32 identity always parses")
+}
+
+#[cfg(feature = "age")]
+fn bech32_key(hrp: &str, key: &[u8; 32]) -> String {
+    use bech32::ToBase32;
+
+    bech32::encode(hrp, key.to_base32(), bech32::Variant::Bech32).expect("the prefix is valid")
+}
+
+#[cfg(feature = "age")]
+fn unsupported(message: &str) -> EncryptError {
+    EncryptError::KeyModeMismatch(message.to_string())
+}
+
+#[cfg(not(feature = "age"))]
+pub fn encrypt(
+    _file_path: &Path,
+    _secret: &Secret,
+    _armor: bool,
+    _existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "age"))]
+pub fn decrypt(
+    _file_path: &Path,
+    _secret: &Secret,
+    _existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "age"))]
+pub fn decode_key(_text: &str) -> Option<[u8; 32]> {
+    None
+}
+
+#[cfg(not(feature = "age"))]
+fn unavailable() -> EncryptError {
+    EncryptError::IoError(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "age support is not built in (enable the 'age' feature)",
+    ))
+}
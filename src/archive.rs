@@ -0,0 +1,763 @@
+// `encrypt --archive` and `decrypt --extract`: whole directory trees as one encrypted file.
+//
+// The files are packed into a simple container, which is encrypted as it is packed, through the library's
+// EncryptingWriter like s3:// objects: in chunks, uncompressed and unarmored, with no file held in memory whole. So
+// file names, sizes and the shape of the tree are hidden too. Extracting decrypts it the same way, through
+// DecryptingReader, which also reads the archives sealed in one piece by earlier builds.
+//
+//   magic "ENCA" and version 1, 2 if it may hold symbolic links (--preserve-symlinks), or 3 if it may hold
+//   alternate data streams (--ads on Windows)
+//   entries: kind (1 byte: 1 = file, 2 = directory, 3 = symbolic link, 4 = alternate data stream), path (u16 LE
+//            length + UTF-8, relative and '/'-separated), mode (u32 LE Unix permission bits, 0 elsewhere), mtime
+//            (i64 LE seconds since the Unix epoch), and for files the length (u64 LE) followed by the contents, for
+//            links the target (u16 LE length + UTF-8), for streams the stream name (u16 LE length + UTF-8), the
+//            length and the contents. A stream follows the entry of the file or directory it belongs to, whose
+//            path, mode and mtime it repeats.
+//   kind 0 after the last entry
+//
+// Symbolic links are skipped with a warning unless --follow-symlinks packs what they point to or
+// --preserve-symlinks stores the links themselves. FIFOs, sockets and devices are always skipped with a warning,
+// and so are the files and directories the --exclude, --include and --respect-gitignore filters leave out (see the
+// path_filter module). Extraction refuses paths that would leave the target directory, never overwrites an
+// existing file, creates the links last and refuses any entry under a link, so nothing is ever written through one.
+// The NTFS alternate data streams of files and directories are only packed with --ads (see the ads module), and
+// restored on Windows. Paths too long for Windows' usual limit are handled in their \\?\ form. `mount` lists the
+// entries without reading the contents of the files, and reads those at their offset when asked for; it leaves out
+// links and streams.
+use crate::ads;
+use crate::manifest::Manifest;
+use crate::path_filter::{PathFilter, Walk};
+use crate::streamed;
+use encryptor::{names, DecryptingReader, EncryptError, EncryptingWriter, Encryptor};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+const MAGIC: &[u8] = b"ENCA";
+const VERSION: u8 = 1;
+const LINKS_VERSION: u8 = 2; // Written only when links are kept, so other archives stay readable by older builds
+const STREAMS_VERSION: u8 = 3; // Likewise, when alternate data streams are packed
+const END: u8 = 0;
+const FILE: u8 = 1;
+const DIRECTORY: u8 = 2;
+const SYMLINK: u8 = 3;
+const STREAM: u8 = 4;
+// How much of a file is read at once to pack it
+const COPY_BUFFER: usize = 64 * 1024;
+
+// What --archive does with symbolic links
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Symlinks {
+    #[default]
+    Skip, // Leave them out, with a warning
+    Follow,   // Pack the file or directory they point to under their name (--follow-symlinks)
+    Preserve, // Store the link and its target, and recreate it on extraction (--preserve-symlinks)
+}
+
+// Pack `roots` (files or directories, each stored under its own name) and encrypt them into `out_path`,
+// by default the first root with `.enc` appended, recording every file in `manifest` if given. What is under the
+// directories is packed if `filter` takes it, symbolic links are handled as `symlinks` says, and alternate data
+// streams are packed too if `streams`. Returns a summary of what was archived.
+pub fn encrypt(
+    encryptor: &Encryptor,
+    roots: &[PathBuf],
+    out_path: Option<&Path>,
+    manifest: Option<&mut Manifest>,
+    filter: &PathFilter,
+    symlinks: Symlinks,
+    streams: bool,
+) -> Result<String, EncryptError> {
+    // Components drop a trailing separator
+    let out_path = match out_path {
+        Some(out_path) => out_path.to_path_buf(),
+        None => names::with_suffix(roots[0].components().as_path(), ".enc"),
+    };
+    let out_path = names::long_path(&out_path).into_owned();
+    if streams && !cfg!(windows) {
+        log::warn!(
+            "--ads: only NTFS on Windows has alternate data streams, so there are none to pack"
+        );
+    }
+    // Streamed, so the version is settled before the first entry: only an archive that may hold links or streams
+    // gets a version older builds can't read
+    let version = match (symlinks, streams && cfg!(windows)) {
+        (_, true) => STREAMS_VERSION,
+        (Symlinks::Preserve, false) => LINKS_VERSION,
+        _ => VERSION,
+    };
+    let packer = streamed::write_with(&out_path, |output| {
+        let mut packer = Packer {
+            out: EncryptingWriter::new(output, encryptor)?,
+            pending: Vec::new(),
+            files: 0,
+            links: 0,
+            streams: 0,
+            skipped: 0,
+            symlinks,
+            ads: streams,
+            ancestors: Vec::new(),
+            out_path: &out_path,
+            manifest,
+        };
+        packer.write(MAGIC)?;
+        packer.write(&[version])?;
+        for path in roots {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "{} has no file name (in UTF-8) to archive it under",
+                        path.display()
+                    ))
+                })?;
+            // Verifying against the manifest also looks for files added under the archived directories
+            if let (Some(manifest), true) = (
+                packer.manifest.as_deref_mut(),
+                fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir()),
+            ) {
+                manifest.add_root(name);
+            }
+            packer.add(&names::long_path(path), name, &mut filter.walk())?;
+        }
+        packer.write(&[END])?;
+        packer.finish()
+    })?;
+
+    let mut summary = format!("Archived {} files", packer.files);
+    if packer.links > 0 {
+        summary.push_str(&format!(" and {} symbolic links", packer.links));
+    }
+    if packer.streams > 0 {
+        summary.push_str(&format!(" with {} alternate data streams", packer.streams));
+    }
+    summary.push_str(&format!(" -> {}", out_path.display()));
+    if packer.skipped > 0 {
+        summary.push_str(&format!(
+            " ({} skipped, see the warnings above)",
+            packer.skipped
+        ));
+    }
+    Ok(summary)
+}
+
+// Decrypt the archive at `file_path` and restore its tree into `target`, by default the archive path without
+// its extension. Returns a summary of what was extracted. The archive is decrypted as it is extracted, so one that
+// fails to authenticate partway keeps the files restored from before the damage, and only those.
+pub fn extract(
+    encryptor: &Encryptor,
+    file_path: &Path,
+    target: Option<&Path>,
+) -> Result<String, EncryptError> {
+    let file_path = &*names::long_path(file_path);
+    let mut container = DecryptingReader::new(File::open(file_path)?, encryptor)?;
+    let target = match (target, names::strip_extension(file_path)) {
+        (Some(target), _) => target.to_path_buf(),
+        (None, stem) if stem == file_path => names::with_suffix(file_path, ".d"),
+        (None, stem) => stem.to_path_buf(),
+    };
+    let target = names::long_path(&target).into_owned();
+
+    if read_bytes(&mut container, MAGIC.len())? != MAGIC {
+        return Err(invalid("not an archive made with --archive".to_string()));
+    }
+    let version = read_bytes(&mut container, 1)?[0];
+    if !(VERSION..=STREAMS_VERSION).contains(&version) {
+        return Err(invalid(format!("unsupported archive version {}", version)));
+    }
+
+    fs::create_dir_all(&target)?;
+    let mut files = 0;
+    let mut streams = 0;
+    // Directory times are set last, since creating the files inside them changes them
+    let mut directories = Vec::new();
+    // Links are made once everything else is, so no file of the archive can be written through one
+    let mut links = Vec::new();
+    loop {
+        let kind = read_bytes(&mut container, 1)?[0];
+        if kind == END {
+            break;
+        }
+        let path_len = u16::from_le_bytes(read_array(&mut container)?) as usize;
+        let path = String::from_utf8(read_bytes(&mut container, path_len)?)
+            .map_err(|_| invalid("an archived path is not valid UTF-8".to_string()))?;
+        let destination = destination(&target, &path)?;
+        refuse_links(&target, &path)?;
+        let mode = u32::from_le_bytes(read_array(&mut container)?);
+        let mtime = i64::from_le_bytes(read_array(&mut container)?);
+
+        match kind {
+            FILE => {
+                let len = u64::from_le_bytes(read_array(&mut container)?);
+                if let Some(parent) = destination.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&destination)
+                    .map_err(|err| match err.kind() {
+                        io::ErrorKind::AlreadyExists => invalid(format!(
+                            "{} already exists and is not overwritten",
+                            destination.display()
+                        )),
+                        _ => err.into(),
+                    })?;
+                // A file cut short by a damaged archive isn't left behind
+                let copied = io::copy(&mut (&mut container).take(len), &mut file);
+                if !matches!(copied, Ok(copied) if copied == len) {
+                    drop(file);
+                    let _ = fs::remove_file(&destination);
+                    return Err(copied.map_or_else(streamed::from_io, |_| damaged()));
+                }
+                restore(&file, mode, mtime)?;
+                files += 1;
+            }
+            DIRECTORY => {
+                fs::create_dir_all(&destination)?;
+                directories.push((destination, mode, mtime));
+            }
+            SYMLINK => {
+                let target_len = u16::from_le_bytes(read_array(&mut container)?) as usize;
+                let link_target = String::from_utf8(read_bytes(&mut container, target_len)?)
+                    .map_err(|_| invalid("a link target is not valid UTF-8".to_string()))?;
+                links.push((destination, path, link_target));
+            }
+            STREAM => {
+                let name_len = u16::from_le_bytes(read_array(&mut container)?) as usize;
+                let name = String::from_utf8(read_bytes(&mut container, name_len)?)
+                    .ok()
+                    .filter(|name| ads::is_valid(name))
+                    .ok_or_else(|| {
+                        invalid(format!("{} has a stream with an invalid name", path))
+                    })?;
+                let len = u64::from_le_bytes(read_array(&mut container)?);
+                let contents =
+                    read_bytes(&mut container, usize::try_from(len).map_err(|_| damaged())?)?;
+                if !destination.exists() {
+                    return Err(damaged());
+                }
+                let created =
+                    ads::create(&destination, &name, &contents).map_err(|err| {
+                        match err.kind() {
+                            io::ErrorKind::AlreadyExists => invalid(format!(
+                                "{} already exists and is not overwritten",
+                                ads::path(&destination, &name).display()
+                            )),
+                            _ => err.into(),
+                        }
+                    })?;
+                // Writing a stream changes the time of its file; directories get theirs back last anyway
+                if created && destination.is_file() {
+                    restore(&ads::reopen(&destination)?, mode, mtime)?;
+                }
+                streams += usize::from(created);
+            }
+            _ => return Err(damaged()),
+        }
+    }
+    // The end of the entries must be the end of the archive, which also authenticates its last chunk
+    if container.read(&mut [0]).map_err(streamed::from_io)? != 0 {
+        return Err(damaged());
+    }
+    let mut made = 0;
+    for (destination, path, link_target) in &links {
+        // A link made just before may be a directory on the way to this one
+        refuse_links(&target, path)?;
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        made += usize::from(make_link(link_target, destination)?);
+    }
+    for (directory, mode, mtime) in directories.iter().rev() {
+        restore(&File::open(directory)?, *mode, *mtime)?;
+    }
+    let links = match made {
+        0 => String::new(),
+        made => format!(" and {} symbolic links", made),
+    };
+    let streams = match streams {
+        0 => String::new(),
+        streams => format!(" with {} alternate data streams", streams),
+    };
+    Ok(format!(
+        "Extracted {} files{}{} from {} -> {}",
+        files,
+        links,
+        streams,
+        file_path.display(),
+        target.display()
+    ))
+}
+
+// An entry of an archive as `mount` lists it, with where a file's contents are in the container
+#[cfg_attr(not(all(feature = "fuse", target_os = "linux")), allow(dead_code))]
+pub struct Entry {
+    pub path: String,
+    pub directory: bool,
+    pub mode: u32,
+    pub mtime: i64,
+    pub offset: u64,
+    pub len: u64,
+}
+
+// The entries of the container `container` reads, seeking past the contents of files rather than reading them
+#[cfg_attr(not(all(feature = "fuse", target_os = "linux")), allow(dead_code))]
+pub fn entries(container: &mut (impl Read + Seek)) -> Result<Vec<Entry>, EncryptError> {
+    if read_bytes(container, MAGIC.len())? != MAGIC {
+        return Err(invalid("not an archive made with --archive".to_string()));
+    }
+    let version = read_bytes(container, 1)?[0];
+    if !(VERSION..=STREAMS_VERSION).contains(&version) {
+        return Err(invalid(format!("unsupported archive version {}", version)));
+    }
+    let mut entries = Vec::new();
+    loop {
+        let kind = read_bytes(container, 1)?[0];
+        if kind == END {
+            break;
+        }
+        let path_len = u16::from_le_bytes(read_array(container)?) as usize;
+        let path = String::from_utf8(read_bytes(container, path_len)?)
+            .map_err(|_| invalid("an archived path is not valid UTF-8".to_string()))?;
+        destination(Path::new("."), &path)?;
+        let mode = u32::from_le_bytes(read_array(container)?);
+        let mtime = i64::from_le_bytes(read_array(container)?);
+        let (offset, len) = match kind {
+            FILE => {
+                let len = u64::from_le_bytes(read_array(container)?);
+                let offset = container.stream_position()?;
+                let skip = i64::try_from(len).map_err(|_| damaged())?;
+                container.seek(SeekFrom::Current(skip))?;
+                (offset, len)
+            }
+            DIRECTORY => (0, 0),
+            // Links aren't shown
+            SYMLINK => {
+                let target_len = u16::from_le_bytes(read_array(container)?);
+                container.seek(SeekFrom::Current(target_len as i64))?;
+                continue;
+            }
+            // Nor are streams
+            STREAM => {
+                let name_len = u16::from_le_bytes(read_array(container)?);
+                container.seek(SeekFrom::Current(name_len as i64))?;
+                let len = u64::from_le_bytes(read_array(container)?);
+                container.seek(SeekFrom::Current(
+                    i64::try_from(len).map_err(|_| damaged())?,
+                ))?;
+                continue;
+            }
+            _ => return Err(damaged()),
+        };
+        entries.push(Entry {
+            path,
+            directory: kind == DIRECTORY,
+            mode,
+            mtime,
+            offset,
+            len,
+        });
+    }
+    Ok(entries)
+}
+
+// The next `len` bytes of `container`, the archive being damaged if it ends before. Read as they come rather than
+// into a buffer of the length the archive claims.
+fn read_bytes(container: &mut impl Read, len: usize) -> Result<Vec<u8>, EncryptError> {
+    let mut bytes = Vec::new();
+    container
+        .take(len as u64)
+        .read_to_end(&mut bytes)
+        .map_err(streamed::from_io)?;
+    match bytes.len() == len {
+        true => Ok(bytes),
+        false => Err(damaged()),
+    }
+}
+
+fn read_array<const N: usize>(container: &mut impl Read) -> Result<[u8; N], EncryptError> {
+    Ok(read_bytes(container, N)?
+        .try_into()
+        .expect("read exactly N bytes"))
+}
+
+// What was packed into an archive
+struct Packed {
+    files: usize,
+    links: usize,
+    streams: usize,
+    skipped: usize,
+}
+
+struct Packer<'a, W: Write> {
+    out: EncryptingWriter<W>,
+    pending: Vec<u8>, // Entries of directories held back until something under them is written, see `add`
+    files: usize,
+    links: usize,
+    streams: usize,
+    skipped: usize,
+    symlinks: Symlinks,
+    ads: bool,               // Whether alternate data streams are packed (--ads)
+    ancestors: Vec<PathBuf>, // The directories being packed, to catch a followed link back to one of them
+    out_path: &'a Path,
+    manifest: Option<&'a mut Manifest>,
+}
+
+impl<W: Write> Packer<'_, W> {
+    // Add `path` under the archive path `name`, and everything below it that `walk` takes if it's a directory
+    fn add(&mut self, path: &Path, name: &str, walk: &mut Walk) -> Result<(), EncryptError> {
+        let mut metadata = fs::symlink_metadata(path)?;
+        let mut link_target = None;
+        if metadata.file_type().is_symlink() {
+            match self.symlinks {
+                Symlinks::Skip => {
+                    self.skip(path, "a symbolic link (see --follow-symlinks)");
+                    return Ok(());
+                }
+                Symlinks::Follow => match fs::metadata(path) {
+                    Ok(followed) => metadata = followed,
+                    Err(_) => {
+                        self.skip(path, "a symbolic link to nothing");
+                        return Ok(());
+                    }
+                },
+                Symlinks::Preserve => match fs::read_link(path)?.to_str() {
+                    Some(target) => link_target = Some(target.to_string()),
+                    None => {
+                        self.skip(path, "a symbolic link to a path that isn't UTF-8");
+                        return Ok(());
+                    }
+                },
+            }
+        }
+        let kind = if link_target.is_some() {
+            SYMLINK
+        } else if metadata.is_file() {
+            FILE
+        } else if metadata.is_dir() {
+            DIRECTORY
+        } else {
+            self.skip(path, special(&metadata));
+            return Ok(());
+        };
+        // Only a followed link can lead back into a directory being packed
+        let directory = match kind {
+            DIRECTORY => Some(fs::canonicalize(path)?),
+            _ => None,
+        };
+        if directory
+            .as_ref()
+            .is_some_and(|directory| self.ancestors.contains(directory))
+        {
+            self.skip(path, "a symbolic link to a directory it is in");
+            return Ok(());
+        }
+
+        let name_len = u16::try_from(name.len())
+            .map_err(|_| invalid(format!("the path {} is too long to archive", name)))?;
+        let mut entry = vec![kind];
+        entry.extend_from_slice(&name_len.to_le_bytes());
+        entry.extend_from_slice(name.as_bytes());
+        entry.extend_from_slice(&mode(&metadata).to_le_bytes());
+        entry.extend_from_slice(&mtime(&metadata).to_le_bytes());
+
+        if let Some(target) = link_target {
+            let target_len = u16::try_from(target.len()).map_err(|_| {
+                invalid(format!(
+                    "the target of the link {} is too long to archive",
+                    name
+                ))
+            })?;
+            entry.extend_from_slice(&target_len.to_le_bytes());
+            entry.extend_from_slice(target.as_bytes());
+            self.write(&entry)?;
+            self.links += 1;
+            return Ok(());
+        }
+        if kind == FILE {
+            let mut file = File::open(path)?;
+            let len = file.metadata()?.len();
+            entry.extend_from_slice(&len.to_le_bytes());
+            self.write(&entry)?;
+            let hash = self.copy(&mut file, path, len)?;
+            self.files += 1;
+            if let Some(manifest) = self.manifest.as_deref_mut() {
+                manifest.add_file(Path::new(name), hash, len, self.out_path);
+            }
+            self.add_streams(path, name, &metadata, false)?;
+            return Ok(());
+        }
+
+        // Sorted, so the same tree always packs the same way
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        // Filters match the path under the root, which is stored under its own name
+        let relative = name.split_once('/').map_or("", |(_, relative)| relative);
+        // A directory whose files were all filtered out is left out, like build directories full of artifacts,
+        // but the root is kept. So its entry is held back until something under it is written, and dropped if
+        // nothing is.
+        let held_back = walk.is_active() && !relative.is_empty();
+        let start = self.pending.len();
+        self.put(&entry, held_back)?;
+        self.add_streams(path, name, &metadata, held_back)?;
+        self.ancestors.extend(directory);
+        walk.enter(path, relative);
+        for entry in entries {
+            let entry_name = entry.to_str().ok_or_else(|| {
+                invalid(format!(
+                    "{} has a name that is not valid UTF-8",
+                    path.join(&entry).display()
+                ))
+            })?;
+            let entry_path = path.join(entry_name);
+            let entry_relative = match relative {
+                "" => entry_name.to_string(),
+                relative => format!("{}/{}", relative, entry_name),
+            };
+            // A followed link is filtered like what it points to
+            let is_dir = match self.symlinks {
+                Symlinks::Follow => entry_path.is_dir(),
+                _ => fs::symlink_metadata(&entry_path).is_ok_and(|metadata| metadata.is_dir()),
+            };
+            if walk.keeps(&entry_relative, is_dir) {
+                self.add(&entry_path, &format!("{}/{}", name, entry_name), walk)?;
+            }
+        }
+        walk.leave(relative);
+        self.ancestors.pop();
+        // Anything written under it wrote the held back entries out first
+        if held_back && self.pending.len() > start {
+            self.pending.truncate(start);
+        }
+        Ok(())
+    }
+
+    // Add the alternate data streams of `path`, stored under the archive path `name`, if they are packed. They are
+    // held back with the entry of their directory if it is.
+    fn add_streams(
+        &mut self,
+        path: &Path,
+        name: &str,
+        metadata: &fs::Metadata,
+        held_back: bool,
+    ) -> Result<(), EncryptError> {
+        if !self.ads {
+            return Ok(());
+        }
+        for stream in ads::list(path)? {
+            let stream = match stream.to_str() {
+                Some(stream) => stream,
+                None => {
+                    self.skip(path, "a stream whose name is not valid UTF-8");
+                    continue;
+                }
+            };
+            let contents = fs::read(ads::path(path, stream))?;
+            // The name's length was checked when its own entry was added, and stream names are short
+            let mut entry = vec![STREAM];
+            entry.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            entry.extend_from_slice(name.as_bytes());
+            entry.extend_from_slice(&mode(metadata).to_le_bytes());
+            entry.extend_from_slice(&mtime(metadata).to_le_bytes());
+            entry.extend_from_slice(&(stream.len() as u16).to_le_bytes());
+            entry.extend_from_slice(stream.as_bytes());
+            entry.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+            entry.extend_from_slice(&contents);
+            self.put(&entry, held_back)?;
+            self.streams += 1;
+        }
+        Ok(())
+    }
+
+    // Copy the `len` bytes of `file`, the one at `path`, into the archive, returning their BLAKE3 hash
+    fn copy(
+        &mut self,
+        file: &mut File,
+        path: &Path,
+        len: u64,
+    ) -> Result<blake3::Hash, EncryptError> {
+        let mut hasher = blake3::Hasher::new();
+        let mut buffer = vec![0; COPY_BUFFER.min(len as usize)];
+        let mut left = len;
+        while left > 0 {
+            let wanted = left.min(buffer.len() as u64) as usize;
+            let read = file.read(&mut buffer[..wanted])?;
+            if read == 0 {
+                return Err(invalid(format!(
+                    "{} got shorter while it was archived",
+                    path.display()
+                )));
+            }
+            hasher.update(&buffer[..read]);
+            self.write(&buffer[..read])?;
+            left -= read as u64;
+        }
+        Ok(hasher.finalize())
+    }
+
+    // Write `bytes` into the archive, or hold them back with the entries of directories that may be left out
+    fn put(&mut self, bytes: &[u8], held_back: bool) -> Result<(), EncryptError> {
+        match held_back {
+            true => {
+                self.pending.extend_from_slice(bytes);
+                Ok(())
+            }
+            false => self.write(bytes),
+        }
+    }
+
+    // Write `bytes` into the archive, after the held back entries of the directories they are under
+    fn write(&mut self, bytes: &[u8]) -> Result<(), EncryptError> {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.out.write_all(&pending).map_err(streamed::from_io)?;
+        }
+        self.out.write_all(bytes).map_err(streamed::from_io)
+    }
+
+    // Seal the end of the archive, returning what was packed
+    fn finish(self) -> Result<Packed, EncryptError> {
+        self.out.finish()?;
+        Ok(Packed {
+            files: self.files,
+            links: self.links,
+            streams: self.streams,
+            skipped: self.skipped,
+        })
+    }
+
+    fn skip(&mut self, path: &Path, what: &str) {
+        log::warn!("Skipping {}: {}", path.display(), what);
+        self.skipped += 1;
+    }
+}
+
+// What kind of special file `metadata` describes
+fn special(metadata: &fs::Metadata) -> &'static str {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        let kind = metadata.file_type();
+        if kind.is_fifo() {
+            return "a FIFO";
+        } else if kind.is_socket() {
+            return "a socket";
+        } else if kind.is_block_device() || kind.is_char_device() {
+            return "a device";
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    "not a regular file or directory"
+}
+
+// Recreate an archived link, returning whether this platform could
+#[cfg(unix)]
+fn make_link(target: &str, destination: &Path) -> Result<bool, EncryptError> {
+    std::os::unix::fs::symlink(target, destination).map_err(|err| match err.kind() {
+        io::ErrorKind::AlreadyExists => invalid(format!(
+            "{} already exists and is not overwritten",
+            destination.display()
+        )),
+        _ => err.into(),
+    })?;
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn make_link(target: &str, destination: &Path) -> Result<bool, EncryptError> {
+    log::warn!(
+        "Skipping {}: symbolic links (to {}) are only recreated on Unix",
+        destination.display(),
+        target
+    );
+    Ok(false)
+}
+
+// Where an archived path goes under `target`. Only plain names are allowed, so nothing lands outside of it.
+fn destination(target: &Path, path: &str) -> Result<PathBuf, EncryptError> {
+    let relative = Path::new(path);
+    let plain = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if !plain || path.is_empty() {
+        return Err(invalid(format!(
+            "refusing to extract '{}', which would leave the target directory",
+            path
+        )));
+    }
+    // Pushed one by one, since a \\?\ target takes '/' literally
+    Ok(relative
+        .components()
+        .fold(target.to_path_buf(), |path, component| path.join(component)))
+}
+
+// Refuse the archived `path` if a symbolic link is on its way under `target`, or is the path itself: one made by
+// an earlier entry (or there before) would have what follows written outside the target
+fn refuse_links(target: &Path, path: &str) -> Result<(), EncryptError> {
+    let mut on_the_way = target.to_path_buf();
+    for component in Path::new(path).components() {
+        on_the_way.push(component);
+        if fs::symlink_metadata(&on_the_way).is_ok_and(|metadata| metadata.file_type().is_symlink())
+        {
+            return Err(invalid(format!(
+                "refusing to extract '{}', which is under the symbolic link {}",
+                path,
+                on_the_way.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode(_metadata: &fs::Metadata) -> u32 {
+    0
+}
+
+fn mtime(metadata: &fs::Metadata) -> i64 {
+    match metadata
+        .modified()
+        .map(|time| time.duration_since(UNIX_EPOCH))
+    {
+        Ok(Ok(since)) => since.as_secs() as i64,
+        Ok(Err(before)) => -(before.duration().as_secs() as i64),
+        Err(_) => 0,
+    }
+}
+
+// Put back the permissions and modification time of an extracted file or directory
+fn restore(file: &File, mode: u32, mtime: i64) -> io::Result<()> {
+    let offset = Duration::from_secs(mtime.unsigned_abs());
+    let time = match mtime >= 0 {
+        true => UNIX_EPOCH + offset,
+        false => UNIX_EPOCH - offset,
+    };
+    file.set_modified(time)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
+
+fn invalid(message: String) -> EncryptError {
+    EncryptError::IoError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+fn damaged() -> EncryptError {
+    invalid("the archive is damaged".to_string())
+}
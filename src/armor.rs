@@ -0,0 +1,67 @@
+//! ASCII armor: an encrypted file as base64 text between BEGIN and END lines, so it can be pasted into emails,
+//! tickets or YAML files that aren't binary-safe.
+//!
+//! ```text
+//! -----BEGIN ENCRYPTOR FILE-----
+//! RU5DUgMBAQBMAAACAAAAEA...
+//! -----END ENCRYPTOR FILE-----
+//! ```
+//!
+//! The armored bytes are exactly the binary file, so armoring changes nothing about what is authenticated.
+//! Decoding ignores whitespace around and inside the base64 lines, since pasted text is often re-indented or
+//! re-wrapped. [`crate::Encryptor::with_armor`] writes armored outputs, and decryption detects them on its own.
+use crate::header::HeaderError;
+use base64::Engine;
+
+/// The line an armored file starts with
+pub const BEGIN: &str = "-----BEGIN ENCRYPTOR FILE-----";
+/// The line an armored file ends with
+pub const END: &str = "-----END ENCRYPTOR FILE-----";
+// Base64 characters per line, as in PEM
+pub(crate) const LINE_LEN: usize = 64;
+
+/// Wrap `data` in armor
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+    let mut text = String::with_capacity(encoded.len() + encoded.len() / LINE_LEN + 64);
+    text.push_str(BEGIN);
+    text.push('\n');
+    for line in encoded.as_bytes().chunks(LINE_LEN) {
+        // Base64 is ASCII, so every chunk is valid UTF-8
+        text.push_str(std::str::from_utf8(line).unwrap_or_default());
+        text.push('\n');
+    }
+    text.push_str(END);
+    text.push('\n');
+    text.into_bytes()
+}
+
+/// Whether `data` is armored, i.e. starts with the BEGIN line (leading whitespace is allowed)
+pub fn is_armored(data: &[u8]) -> bool {
+    data.trim_ascii_start().starts_with(BEGIN.as_bytes())
+}
+
+/// The binary file inside armored `data`, or `data` itself when it isn't armored
+pub fn dearmor(data: Vec<u8>) -> Result<Vec<u8>, HeaderError> {
+    if is_armored(&data) {
+        decode(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+/// The binary file inside armored `data`. Fails with [`HeaderError::InvalidArmor`] when the END line is
+/// missing or the text between the lines isn't base64.
+pub fn decode(data: &[u8]) -> Result<Vec<u8>, HeaderError> {
+    let text = std::str::from_utf8(data).map_err(|_| HeaderError::InvalidArmor)?;
+    let body = text
+        .trim_start()
+        .strip_prefix(BEGIN)
+        .and_then(|rest| rest.split_once(END))
+        .map(|(body, _)| body)
+        .ok_or(HeaderError::InvalidArmor)?;
+    let encoded: String = body.split_whitespace().collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|_| HeaderError::InvalidArmor)
+}
@@ -0,0 +1,173 @@
+//! Encrypting and decrypting tokio streams, for async servers.
+//!
+//! [`Encryptor::encrypt_reader`] seals what an [`AsyncRead`] yields into an [`AsyncWrite`] in the chunked format of
+//! the [`stream`] module, one chunk in memory at a time, so a web service can encrypt an upload as it arrives, and
+//! [`Encryptor::decrypt_reader`] streams it back. The output is the same as what
+//! [`Encryptor::encrypt_file`] writes for a large file, so the other functions and the command line tool read it too.
+//!
+//! Neither blocks the runtime for long: deriving a key from a password with Argon2id runs on tokio's blocking
+//! threads, and only sealing or opening a chunk (about a millisecond per MiB) runs on the task itself.
+//!
+//! ```no_run
+//! use encryptor::Encryptor;
+//!
+//! # async fn upload(body: &[u8]) -> Result<(), encryptor::EncryptError> {
+//! let encryptor = Encryptor::new("correct horse battery staple");
+//! let mut sealed = Vec::new();
+//! encryptor.encrypt_reader(body, &mut sealed, body.len() as u64).await?;
+//!
+//! let mut plaintext = Vec::new();
+//! encryptor.decrypt_reader(&sealed[..], &mut plaintext).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Available with the `async` feature.
+use crate::adapters::ChunkOpener;
+use crate::header::{Header, HeaderError};
+use crate::progress::IO_CHUNK;
+use crate::{stream, EncryptError, Encryptor, TAG_LEN};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+impl Encryptor {
+    /// Encrypt the `len` bytes that `reader` yields into `writer`, in chunks of [`Encryptor::with_chunk_size`]
+    /// bytes whatever the stream threshold. The length is recorded in the header like a file's, so it must be known
+    /// up front (e.g. from a Content-Length); a reader that yields fewer or more bytes fails with an
+    /// [`io::ErrorKind::InvalidData`] error. Compression and the encoder don't apply, and there is no name to record
+    /// or hide. `writer` is flushed, but not shut down.
+    pub async fn encrypt_reader<R, W>(
+        &self,
+        mut reader: R,
+        mut writer: W,
+        len: u64,
+    ) -> Result<(), EncryptError>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let chunk_size = self.chunk_size;
+        let encryptor = self.clone();
+        let (header, header_bytes, key_bytes) = blocking(move || {
+            encryptor.new_header(
+                Some(len),
+                (None, false, false),
+                None,
+                Some(chunk_size),
+                (None, None, None),
+            )
+        })
+        .await?;
+        writer.write_all(&header_bytes).await?;
+
+        let aad = header.associated_data(&header_bytes);
+        let mut chunks = stream::Chunks::new(header.algorithm, &key_bytes, &header.nonce, &aad);
+        let count = stream::chunk_count(len, chunk_size);
+        let mut chunk = Vec::with_capacity(chunk_size as usize + TAG_LEN);
+        for index in 0..count {
+            let chunk_len = (len - index * chunk_size as u64).min(chunk_size as u64) as usize;
+            chunk.clear();
+            chunk.resize(chunk_len, 0);
+            match reader.read_exact(&mut chunk).await {
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(other_length(len))
+                }
+                result => result?,
+            };
+            chunks.seal(&mut chunk, index == count - 1)?;
+            writer.write_all(&chunk).await?;
+        }
+        if reader.read(&mut [0]).await? != 0 {
+            return Err(other_length(len));
+        }
+        if self.header_backup {
+            writer
+                .write_all(&Header::backup_trailer(&header_bytes))
+                .await?;
+        }
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// Decrypt what `reader` yields into `writer`, returning how many bytes of plaintext were written. A chunked
+    /// payload is decrypted one chunk at a time, each written as soon as it authenticates, so when this fails the
+    /// plaintext written so far must be thrown away, like a broken download. Anything else (a payload sealed in one
+    /// piece or compressed, or an encoded file) is read whole first. A name sealed with the contents is dropped.
+    /// `writer` is flushed, but not shut down.
+    pub async fn decrypt_reader<R, W>(
+        &self,
+        mut reader: R,
+        mut writer: W,
+    ) -> Result<u64, EncryptError>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        // Read as much of the start as the header turns out to need
+        let mut start = Vec::new();
+        let mut wanted = 4096;
+        let mut ended = false;
+        let parsed = loop {
+            while start.len() < wanted && !ended {
+                ended = read_more(&mut reader, &mut start).await? == 0;
+            }
+            match Header::parse(&start) {
+                Err(HeaderError::Truncated) if !ended => wanted *= 2,
+                Ok(parsed) => break Some(parsed),
+                Err(_) => break None,
+            }
+        };
+        let chunked = parsed.filter(|(header, _)| ChunkOpener::applies(header));
+        let Some((header, header_len)) = chunked else {
+            reader.read_to_end(&mut start).await?;
+            let encryptor = self.clone();
+            let plaintext = blocking(move || encryptor.open(start, None)).await?.0;
+            writer.write_all(&plaintext).await?;
+            writer.flush().await?;
+            return Ok(plaintext.len() as u64);
+        };
+
+        let pending = start.split_off(header_len);
+        let encryptor = self.clone();
+        let key_header = header.clone();
+        let key_bytes = blocking(move || encryptor.key(&key_header)).await?;
+        let mut opener = ChunkOpener::new(header, start, &key_bytes, pending);
+        let mut written = 0;
+        loop {
+            while opener.wants_more() && !ended {
+                ended = read_more(&mut reader, opener.buffer()).await? == 0;
+            }
+            let Some(chunk) = opener.next(ended)? else {
+                break;
+            };
+            writer.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        writer.flush().await?;
+        Ok(written)
+    }
+}
+
+// Run `work` on tokio's blocking threads, so that deriving a key doesn't stall the tasks of the runtime
+async fn blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+// Read what `reader` has next onto the end of `buffer`, returning how much (0 at its end)
+async fn read_more(
+    reader: &mut (impl AsyncRead + Unpin),
+    buffer: &mut Vec<u8>,
+) -> io::Result<usize> {
+    buffer.reserve(IO_CHUNK);
+    reader.read_buf(buffer).await
+}
+
+fn other_length(len: u64) -> EncryptError {
+    EncryptError::IoError(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("the reader didn't yield the {} bytes it was said to", len),
+    ))
+}
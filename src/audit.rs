@@ -0,0 +1,209 @@
+// `--audit-log <path>`: an append-only record of every file encrypted, decrypted or verified, for users who have to
+// show what was done to which files. Each file adds one line as soon as it is done:
+//
+//   {"at": 1760000000, "error": null, "input": "a.txt", "key_id": null, "mac": "<hex>", "operation": "encrypt",
+//    "output": "a.txt.enc", "prev": "0000...", "seq": 0, "status": "ok"}
+//
+// `mac` is an HMAC-SHA256 of the rest of the line (as serialized, without `mac`) under the audit key, and `prev` the
+// `mac` of the line before it, so changing, removing or reordering a line breaks the chain from there on, and
+// `encryptor audit verify` points at it. The key is --audit-key, or <config>/audit.key, which is created on first use;
+// whoever holds it can rewrite the history, so keep it away from the users being audited. Cutting lines off the end
+// can't be told apart from a shorter history: `audit verify` prints the last `mac`, to be kept somewhere else.
+// A run that has the log open keeps the chain, but two runs writing the same log at once break it.
+use crate::keyfile;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+// The `prev` of the first line
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+struct Log {
+    path: String,
+    file: File,
+    key: Zeroizing<[u8; 32]>,
+    seq: u64,
+    prev: String,
+}
+
+// The log of this run, when --audit-log was given
+static LOG: Mutex<Option<Log>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<Log>> {
+    // A worker that panicked while appending leaves the chain consistent
+    LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Open the log at `path` to append to it, after the last line it has. Fails when the log can't be written, or when
+// its last line wasn't written with this key, so a run is never left unaudited.
+pub fn start(path: &str, key_path: Option<&str>) -> Result<(), String> {
+    let key = load_key(key_path, true)?;
+    let (seq, prev) = match fs::read_to_string(path) {
+        Ok(history) => match history.lines().rev().find(|line| !line.trim().is_empty()) {
+            Some(line) => {
+                let (seq, _, mac) = check(line, &key).map_err(|reason| {
+                    format!("Cannot append to the audit log {}: {}", path, reason)
+                })?;
+                (seq + 1, mac)
+            }
+            None => (0, GENESIS.to_string()),
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (0, GENESIS.to_string()),
+        Err(err) => return Err(format!("Cannot read the audit log {}: {}", path, err)),
+    };
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| format!("Cannot open the audit log {}: {}", path, err))?;
+    *lock() = Some(Log {
+        path: path.to_string(),
+        file,
+        key,
+        seq,
+        prev,
+    });
+    Ok(())
+}
+
+// Whether an audit log is being written
+pub fn active() -> bool {
+    lock().is_some()
+}
+
+// Append the outcome of one file, as reported by --json, with the ID of the key it was encrypted under if its header
+// records one. Failing to do so doesn't change the exit status, but is reported.
+pub fn file(outcome: &Value, key_id: Option<String>) {
+    let mut guard = lock();
+    let Some(log) = guard.as_mut() else {
+        return;
+    };
+    let at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let mut entry = json!({
+        "seq": log.seq,
+        "at": at,
+        "operation": outcome["operation"],
+        "input": outcome["input"],
+        "output": outcome["output"],
+        "key_id": key_id,
+        "status": outcome["status"],
+        "error": outcome["error"]["code"],
+        "prev": log.prev,
+    });
+    let mac = mac(&log.key, &entry.to_string());
+    entry["mac"] = json!(mac);
+    // One write per line, so a run that dies leaves whole lines
+    match log
+        .file
+        .write_all(format!("{}\n", entry).as_bytes())
+        .and_then(|()| log.file.flush())
+    {
+        Ok(()) => {
+            log.seq += 1;
+            log.prev = mac;
+        }
+        Err(err) => eprintln!("Cannot append to the audit log {}: {}", log.path, err),
+    }
+}
+
+// Check every line of the log at `path` against the key and the line before it, returning how many there are and
+// the last `mac`
+pub fn verify(path: &Path, key_path: Option<&str>) -> Result<String, String> {
+    let key = load_key(key_path, false)?;
+    let history = fs::read_to_string(path)
+        .map_err(|err| format!("Cannot read the audit log {}: {}", path.display(), err))?;
+    let mut prev = GENESIS.to_string();
+    let mut entries = 0;
+    for (number, line) in history.lines().enumerate() {
+        let broken = |reason: String| {
+            format!(
+                "The audit log {} was tampered with at line {}: {}",
+                path.display(),
+                number + 1,
+                reason
+            )
+        };
+        let (seq, entry_prev, mac) = check(line, &key).map_err(broken)?;
+        if seq != entries {
+            return Err(broken(format!(
+                "it is entry {} where entry {} should be",
+                seq, entries
+            )));
+        }
+        if entry_prev != prev {
+            return Err(broken(
+                "it doesn't follow the line before it; lines were removed or reordered".to_string(),
+            ));
+        }
+        entries += 1;
+        prev = mac;
+    }
+    Ok(format!(
+        "{}: {} entries, chain intact. Keep the last MAC elsewhere to detect lines cut off the end: {}",
+        path.display(),
+        entries,
+        prev
+    ))
+}
+
+// The key of --audit-key, or the one in the config directory, created there on first use if `create` says so
+fn load_key(key_path: Option<&str>, create: bool) -> Result<Zeroizing<[u8; 32]>, String> {
+    if let Some(key_path) = key_path {
+        return keyfile::load(key_path);
+    }
+    let path = default_key_path()?;
+    let path_text = path.to_string_lossy();
+    if !path.exists() && create {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| format!("Cannot create {}: {}", parent.display(), err))?;
+        }
+        let key = Zeroizing::new(encryptor::generate_key().map_err(|err| err.to_string())?);
+        keyfile::write(&path_text, &key, keyfile::Format::Hex)?;
+        log::info!("Created the audit key {}", path.display());
+    }
+    keyfile::load(&path_text)
+}
+
+// Where the audit key is kept unless --audit-key says otherwise
+fn default_key_path() -> Result<PathBuf, String> {
+    Ok(crate::config_dir()?.join("audit.key"))
+}
+
+// Whether `line` carries a valid MAC under `key`, returning its `seq`, `prev` and `mac`
+fn check(line: &str, key: &[u8; 32]) -> Result<(u64, String, String), String> {
+    let mut entry: Value =
+        serde_json::from_str(line).map_err(|_| "it is not an audit entry".to_string())?;
+    let (Some(seq), Some(prev), Some(mac)) = (
+        entry["seq"].as_u64(),
+        entry["prev"].as_str().map(str::to_string),
+        entry["mac"].as_str().map(str::to_string),
+    ) else {
+        return Err("it is not an audit entry".to_string());
+    };
+    if let Some(members) = entry.as_object_mut() {
+        members.remove("mac");
+    }
+    let expected = hex::decode(&mac).map_err(|_| "its MAC isn't hex".to_string())?;
+    let mut hmac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    hmac.update(entry.to_string().as_bytes());
+    hmac.verify_slice(&expected).map_err(|_| {
+        "its MAC doesn't match: the line was changed, or written with another audit key".to_string()
+    })?;
+    Ok((seq, prev, mac))
+}
+
+// The HMAC-SHA256 of `text` under `key`, in hex
+fn mac(key: &[u8; 32], text: &str) -> String {
+    let mut hmac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    hmac.update(text.as_bytes());
+    hex::encode(hmac.finalize().into_bytes())
+}
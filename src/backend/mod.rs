@@ -0,0 +1,75 @@
+// The crypto backends: where AES-GCM, ChaCha20-Poly1305 and random numbers come from.
+//
+// The backend is picked at compile time with the `ring` (default), `rustcrypto` or `openssl` feature. They all implement
+// the same algorithms with the same nonce and tag layout, so files written with one can be read with the others.
+// AES-256-GCM-SIV and Argon2id always come from the RustCrypto crates, whichever backend is used.
+use crate::{Algorithm, KEY_LEN};
+
+#[cfg(feature = "openssl")]
+mod openssl_crypto;
+#[cfg(all(
+    feature = "ring",
+    not(any(feature = "rustcrypto", feature = "openssl"))
+))]
+mod ring_crypto;
+#[cfg(all(feature = "rustcrypto", not(feature = "openssl")))]
+mod rust_crypto;
+
+// The backend this build uses. A non-default backend wins over ring when both are on, so enabling
+// `rustcrypto` or `openssl` is enough to switch even when default features are left on.
+#[cfg(feature = "openssl")]
+pub(crate) type Active = openssl_crypto::OpensslBackend;
+#[cfg(all(
+    feature = "ring",
+    not(any(feature = "rustcrypto", feature = "openssl"))
+))]
+pub(crate) type Active = ring_crypto::RingBackend;
+#[cfg(all(feature = "rustcrypto", not(feature = "openssl")))]
+pub(crate) type Active = rust_crypto::RustCryptoBackend;
+
+#[cfg(not(any(feature = "ring", feature = "rustcrypto", feature = "openssl")))]
+compile_error!("enable a crypto backend: the `ring`, `rustcrypto` or `openssl` feature");
+
+/// An AEAD or random number operation failed.
+/// Deliberately carries no detail, so a failed decryption doesn't tell an attacker why it failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CryptoError;
+
+impl std::fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cryptographic operation failed")
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+// The operations every backend provides. The functions take no `self`, since backends are selected
+// at compile time and hold no state.
+pub(crate) trait Backend {
+    // The name of the backend, as its feature is called
+    const NAME: &'static str;
+
+    // Fill `dest` with cryptographically secure random bytes
+    fn fill_random(dest: &mut [u8]) -> Result<(), CryptoError>;
+
+    // Encrypt the contents in place and append the authentication tag.
+    // Only called for AES-GCM and ChaCha20-Poly1305, always with the whole 256-bit key: an algorithm
+    // with a shorter key (see Algorithm::key_len) uses its first bytes.
+    fn seal_in_place(
+        algorithm: Algorithm,
+        key_bytes: &[u8; KEY_LEN],
+        nonce: &[u8],
+        aad: &[u8],
+        contents: &mut Vec<u8>,
+    ) -> Result<(), CryptoError>;
+
+    // Decrypt the contents in place and remove the authentication tag, leaving only the plaintext.
+    // Only called for AES-GCM and ChaCha20-Poly1305.
+    fn open_in_place(
+        algorithm: Algorithm,
+        key_bytes: &[u8; KEY_LEN],
+        nonce: &[u8],
+        aad: &[u8],
+        contents: &mut Vec<u8>,
+    ) -> Result<(), CryptoError>;
+}
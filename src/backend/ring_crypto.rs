@@ -0,0 +1,95 @@
+// The default backend, built on the 'ring' crate
+use super::{Backend, CryptoError};
+use crate::{Algorithm, KEY_LEN};
+use ring::aead;
+use ring::error::Unspecified;
+use ring::rand::{SecureRandom, SystemRandom};
+
+pub(crate) struct RingBackend;
+
+impl From<Unspecified> for CryptoError {
+    fn from(_: Unspecified) -> Self {
+        CryptoError
+    }
+}
+
+impl Backend for RingBackend {
+    const NAME: &'static str = "ring";
+
+    fn fill_random(dest: &mut [u8]) -> Result<(), CryptoError> {
+        Ok(SystemRandom::new().fill(dest)?)
+    }
+
+    fn seal_in_place(
+        algorithm: Algorithm,
+        key_bytes: &[u8; KEY_LEN],
+        nonce: &[u8],
+        aad: &[u8],
+        contents: &mut Vec<u8>,
+    ) -> Result<(), CryptoError> {
+        // Create a new instance of an unbound key using the chosen algorithm and the derived key bytes
+        // (as many of them as the algorithm takes).
+        // The `new` function returns a `Result` type, so the `?` operator is used to propagate any potential error.
+        let key = aead::UnboundKey::new(
+            ring_algorithm(algorithm)?,
+            &key_bytes[..algorithm.key_len()],
+        )?;
+
+        // Create a new instance of a less safe key from the unbound key.
+        // The `LessSafeKey` is a wrapper around `UnboundKey` that can be used for encryption and decryption operations.
+        // In programming, a wrapper is a class, function, or data structure that contains (or “wraps”) another item to provide a
+        // simpler or more compatible interface.
+        let key = aead::LessSafeKey::new(key);
+
+        // @terminology: In place” is a term used in programming to describe an operation that modifies data directly in the memory where it already resides,
+        // instead of creating a copy of the data and performing the operation on the copy.
+
+        // When an operation is performed “in place”, it means that the original data is modified. This can be more efficient because it avoids the need to
+        // allocate additional memory for a copy of the data. However, it also means that the original data is lost, because it has been overwritten by the
+        // result of the operation.
+
+        // In this Rust code, the seal_in_place_append_tag and open_in_place methods from the ring crate are examples of in-place operations. They encrypt
+        // and decrypt data directly in the buffer where the data already resides, instead of creating a new buffer for the encrypted or decrypted data.
+        // This can make the code more efficient, especially when working with large amounts of data. I hope this helps!
+        key.seal_in_place_append_tag(
+            aead::Nonce::try_assume_unique_for_key(nonce)?,
+            aead::Aad::from(aad),
+            contents,
+        )?;
+        Ok(())
+    }
+
+    fn open_in_place(
+        algorithm: Algorithm,
+        key_bytes: &[u8; KEY_LEN],
+        nonce: &[u8],
+        aad: &[u8],
+        contents: &mut Vec<u8>,
+    ) -> Result<(), CryptoError> {
+        let key = aead::LessSafeKey::new(aead::UnboundKey::new(
+            ring_algorithm(algorithm)?,
+            &key_bytes[..algorithm.key_len()],
+        )?);
+
+        // The returned slice is the plaintext without the trailing tag, so the buffer is shortened to its length
+        let plaintext_len = key
+            .open_in_place(
+                aead::Nonce::try_assume_unique_for_key(nonce)?,
+                aead::Aad::from(aad),
+                contents,
+            )?
+            .len();
+        contents.truncate(plaintext_len);
+        Ok(())
+    }
+}
+
+// The matching algorithm from the 'ring' crate
+fn ring_algorithm(algorithm: Algorithm) -> Result<&'static aead::Algorithm, CryptoError> {
+    match algorithm {
+        Algorithm::Aes256Gcm => Ok(&aead::AES_256_GCM),
+        Algorithm::Aes128Gcm => Ok(&aead::AES_128_GCM),
+        Algorithm::ChaCha20Poly1305 => Ok(&aead::CHACHA20_POLY1305),
+        Algorithm::Aes256GcmSiv => Err(CryptoError),
+    }
+}
@@ -0,0 +1,76 @@
+// A pure Rust backend built on the RustCrypto 'aes-gcm' and 'chacha20poly1305' crates,
+// for platforms that can't build ring. Random numbers come from the operating system through 'getrandom'.
+use super::{Backend, CryptoError};
+use crate::{Algorithm, KEY_LEN, NONCE_LEN};
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes128Gcm, Aes256Gcm};
+use chacha20poly1305::ChaCha20Poly1305;
+
+pub(crate) struct RustCryptoBackend;
+
+impl Backend for RustCryptoBackend {
+    const NAME: &'static str = "rustcrypto";
+
+    fn fill_random(dest: &mut [u8]) -> Result<(), CryptoError> {
+        getrandom::getrandom(dest).map_err(|_| CryptoError)
+    }
+
+    fn seal_in_place(
+        algorithm: Algorithm,
+        key_bytes: &[u8; KEY_LEN],
+        nonce: &[u8],
+        aad: &[u8],
+        contents: &mut Vec<u8>,
+    ) -> Result<(), CryptoError> {
+        let nonce = checked_nonce(nonce)?;
+        match algorithm {
+            Algorithm::Aes256Gcm => {
+                Aes256Gcm::new(key_bytes.into()).encrypt_in_place(nonce, aad, contents)
+            }
+            Algorithm::Aes128Gcm => aes128_gcm(key_bytes).encrypt_in_place(nonce, aad, contents),
+            Algorithm::ChaCha20Poly1305 => {
+                ChaCha20Poly1305::new(key_bytes.into()).encrypt_in_place(nonce, aad, contents)
+            }
+            Algorithm::Aes256GcmSiv => return Err(CryptoError),
+        }
+        .map_err(|_| CryptoError)
+    }
+
+    fn open_in_place(
+        algorithm: Algorithm,
+        key_bytes: &[u8; KEY_LEN],
+        nonce: &[u8],
+        aad: &[u8],
+        contents: &mut Vec<u8>,
+    ) -> Result<(), CryptoError> {
+        let nonce = checked_nonce(nonce)?;
+        match algorithm {
+            Algorithm::Aes256Gcm => {
+                Aes256Gcm::new(key_bytes.into()).decrypt_in_place(nonce, aad, contents)
+            }
+            Algorithm::Aes128Gcm => aes128_gcm(key_bytes).decrypt_in_place(nonce, aad, contents),
+            Algorithm::ChaCha20Poly1305 => {
+                ChaCha20Poly1305::new(key_bytes.into()).decrypt_in_place(nonce, aad, contents)
+            }
+            Algorithm::Aes256GcmSiv => return Err(CryptoError),
+        }
+        .map_err(|_| CryptoError)
+    }
+}
+
+// AES-128-GCM keyed with the first half of the key
+fn aes128_gcm(key_bytes: &[u8; KEY_LEN]) -> Aes128Gcm {
+    Aes128Gcm::new(aes_gcm::Key::<Aes128Gcm>::from_slice(
+        &key_bytes[..Algorithm::Aes128Gcm.key_len()],
+    ))
+}
+
+// The nonce comes from the file header, so check its length instead of letting the conversion panic.
+// All the algorithms use the same 96-bit nonce type.
+fn checked_nonce(nonce: &[u8]) -> Result<&aes_gcm::Nonce<aes_gcm::aead::consts::U12>, CryptoError> {
+    if nonce.len() == NONCE_LEN {
+        Ok(aes_gcm::Nonce::from_slice(nonce))
+    } else {
+        Err(CryptoError)
+    }
+}
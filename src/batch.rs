@@ -0,0 +1,40 @@
+// Running one operation over many files on a pool of worker threads.
+//
+// Each worker repeatedly takes the next file that nobody has started yet, so a few large files don't hold up
+// the rest of the batch. A failing file only affects its own result; the other files keep going.
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// Call `work` for every file using up to `jobs` threads, returning the results in the same order as `files`
+pub fn run<T: Send>(files: &[PathBuf], jobs: usize, work: impl Fn(&Path) -> T + Sync) -> Vec<T> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<T>>> = Mutex::new(files.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(file) = files.get(index) else {
+                    break;
+                };
+                let result = work(file);
+                results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(result);
+            });
+        }
+    });
+
+    // Every index below files.len() was taken by exactly one worker, and the scope waited for all of them
+    results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .map(|result| result.expect("every file is processed"))
+        .collect()
+}
+
+// The default number of jobs: one per CPU
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
@@ -0,0 +1,452 @@
+// `encryptor bench --compression [<file>]`: measures every built-in compression codec at a few levels,
+// so users can pick a --compress setting for their data. Uses the given file, or generated text when none is given.
+//
+// `encryptor bench [--save <baseline.json>] [--compare <baseline.json>]`: measures the operations that matter for
+// performance work (sealing small buffers, the throughput of each cipher, streaming a large file, the KDF at a few
+// memory costs and packing an archive), and saves them as a baseline or reports what got slower than a saved one by
+// more than its threshold. Then it suggests the cipher and the --kdf-* settings that suit this machine.
+// The criterion benchmarks in benches/ cover the same ground in more detail.
+use crate::{archive, units};
+use encryptor::compression::{self, Codec, Compression};
+use encryptor::{Algorithm, CancellationToken, Encryptor, KdfParams};
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Size of the generated sample when no file is given
+const SAMPLE_LEN: usize = 4 * 1024 * 1024;
+
+// How long each performance benchmark runs for, and the fewest runs its median is taken from
+const BUDGET: Duration = Duration::from_secs(1);
+const MIN_RUNS: usize = 5;
+// How much slower than the baseline counts as a regression when neither --threshold nor the baseline says
+const DEFAULT_THRESHOLD: f64 = 10.0;
+const BASELINE_VERSION: u64 = 1;
+
+// The size of the buffer each cipher seals, big enough that the header doesn't count
+const CIPHER_LEN: usize = 1024 * 1024;
+// The ciphers measured
+const CIPHERS: [(&str, Algorithm); 4] = [
+    ("cipher/aes256gcm", Algorithm::Aes256Gcm),
+    ("cipher/chacha20poly1305", Algorithm::ChaCha20Poly1305),
+    ("cipher/aes256gcmsiv", Algorithm::Aes256GcmSiv),
+    ("cipher/aes128gcm", Algorithm::Aes128Gcm),
+];
+// The Argon2id memory costs (in KiB) a single pass is timed at, from the default up
+const KDF_LEVELS: [(&str, u32); 3] = [
+    ("kdf/19MiB-pass", 19 * 1024),
+    ("kdf/64MiB-pass", 64 * 1024),
+    ("kdf/256MiB-pass", 256 * 1024),
+];
+// How long unlocking a file should take with the suggested --kdf-* settings
+const KDF_TARGET: Duration = Duration::from_secs(1);
+
+// One benchmark's result: the median time of one run, and how many bytes one run processes
+struct Measurement {
+    name: &'static str,
+    time: Duration,
+    bytes: u64,
+}
+
+pub fn compression(file_path: Option<&str>) -> io::Result<()> {
+    let data = match file_path {
+        Some(path) => fs::read(path)?,
+        None => sample(),
+    };
+    println!(
+        "{} of {}",
+        units::size(data.len() as u64),
+        file_path.unwrap_or("generated text")
+    );
+    println!(
+        "{:<10} {:>8} {:>14} {:>14}",
+        "codec", "ratio", "compress", "decompress"
+    );
+
+    for compression in settings() {
+        if !compression.codec.is_available() {
+            println!("{:<10} (not built in)", compression.to_string());
+            continue;
+        }
+        let start = Instant::now();
+        let compressed = compression::compress(&data, compression, None)?;
+        let compress_time = start.elapsed();
+
+        let start = Instant::now();
+        let decompressed = compression::decompress(&compressed, compression.codec, None)?;
+        let decompress_time = start.elapsed();
+        if decompressed != data {
+            return Err(io::Error::other(format!(
+                "{} did not round-trip",
+                compression
+            )));
+        }
+
+        println!(
+            "{:<10} {:>8.3} {:>14} {:>14}",
+            compression.to_string(),
+            compressed.len() as f64 / data.len().max(1) as f64,
+            units::speed(data.len() as u64, compress_time),
+            units::speed(data.len() as u64, decompress_time),
+        );
+    }
+    Ok(())
+}
+
+// The codec and level combinations to measure: each codec's fastest, default and strongest settings
+fn settings() -> Vec<Compression> {
+    let mut settings = Vec::new();
+    for codec in Codec::all() {
+        match codec.levels() {
+            Some(levels) => {
+                let mut chosen = vec![*levels.start(), codec.default_level(), *levels.end()];
+                chosen.dedup();
+                for level in chosen {
+                    settings.push(Compression { codec, level });
+                }
+            }
+            None => settings.push(Compression::new(codec)),
+        }
+    }
+    settings
+}
+
+// Log-like text with some variation, so the codecs have something realistic to work with
+fn sample() -> Vec<u8> {
+    let mut data = Vec::with_capacity(SAMPLE_LEN);
+    let mut i: u64 = 0;
+    while data.len() < SAMPLE_LEN {
+        let line = format!(
+            "2024-01-{:02} {:05} INFO request {} served in {} ms for user {}\n",
+            i % 28 + 1,
+            i,
+            i.wrapping_mul(2654435761) % 100_000,
+            i * 7 % 250,
+            i * 31 % 977
+        );
+        data.extend_from_slice(line.as_bytes());
+        i += 1;
+    }
+    data.truncate(SAMPLE_LEN);
+    data
+}
+
+// Run the performance benchmarks, then write them to `save` and compare them with the baseline in `compare`.
+// Fails if anything is slower than the baseline by more than `threshold` percent (or the baseline's own threshold).
+pub fn performance(
+    save: Option<&str>,
+    compare: Option<&str>,
+    threshold: Option<f64>,
+) -> Result<(), String> {
+    // Read the baseline first, so a bad path fails before minutes of measuring
+    let baseline = compare
+        .map(|path| {
+            let text =
+                fs::read_to_string(path).map_err(|err| format!("Cannot read {}: {}", path, err))?;
+            serde_json::from_str::<Value>(&text)
+                .ok()
+                .filter(|baseline| baseline["version"] == BASELINE_VERSION)
+                .ok_or_else(|| format!("{} is not a baseline written by `bench --save`", path))
+        })
+        .transpose()?;
+    if cfg!(debug_assertions) {
+        println!(
+            "This is a debug build, whose timings say little; build with --release to measure"
+        );
+    }
+
+    let scratch = std::env::temp_dir().join(format!("encryptor-bench-{}", std::process::id()));
+    fs::create_dir_all(&scratch).map_err(|err| err.to_string())?;
+    let measurements = measure_all(&scratch);
+    let _ = fs::remove_dir_all(&scratch);
+    let measurements = measurements?;
+
+    // With a baseline, the comparison below shows the times instead
+    if baseline.is_none() {
+        print_measurements(&measurements);
+    }
+
+    let threshold = threshold
+        .or_else(|| baseline.as_ref()?["threshold_percent"].as_f64())
+        .unwrap_or(DEFAULT_THRESHOLD);
+    if let Some(path) = save {
+        let results: serde_json::Map<String, Value> = measurements
+            .iter()
+            .map(|measurement| {
+                let result = json!({
+                    "nanoseconds": measurement.time.as_nanos() as u64,
+                    "bytes": measurement.bytes,
+                });
+                (measurement.name.to_string(), result)
+            })
+            .collect();
+        let baseline = json!({
+            "version": BASELINE_VERSION,
+            "threshold_percent": threshold,
+            "results": results,
+        });
+        let text = serde_json::to_string_pretty(&baseline).map_err(|err| err.to_string())?;
+        fs::write(path, text + "\n").map_err(|err| format!("Cannot write {}: {}", path, err))?;
+        println!("Saved the baseline to {}", path);
+    }
+    let compared = match baseline {
+        Some(baseline) => compare_with(&measurements, &baseline, threshold),
+        None => Ok(()),
+    };
+    print_suggestions(&measurements);
+    compared
+}
+
+fn measure_all(scratch: &Path) -> Result<Vec<Measurement>, String> {
+    let error = |err: encryptor::EncryptError| err.to_string();
+    let key = encryptor::generate_key().map_err(error)?;
+    let sealer = Encryptor::from_key(key);
+    let text = sample();
+    let mut measurements = Vec::new();
+
+    // Small records sealed in place, as a service encrypting many of them would
+    for (name, size) in [("seal/64B", 64), ("seal/16KiB", 16 * 1024)] {
+        let mut buffer = Vec::with_capacity(size + sealer.in_place_overhead());
+        let time = measure(|| {
+            buffer.clear();
+            buffer.resize(size, 0x5a);
+            sealer.encrypt_in_place(&mut buffer).map_err(error)
+        })?;
+        measurements.push(Measurement {
+            name,
+            time,
+            bytes: size as u64,
+        });
+    }
+
+    // Each cipher on its own, on buffers large enough to show its throughput on this CPU
+    for (name, algorithm) in CIPHERS {
+        let sealer = sealer.clone().with_algorithm(algorithm);
+        let mut buffer = Vec::with_capacity(CIPHER_LEN + sealer.in_place_overhead());
+        let time = measure(|| {
+            buffer.clear();
+            buffer.resize(CIPHER_LEN, 0x5a);
+            sealer.encrypt_in_place(&mut buffer).map_err(error)
+        })?;
+        measurements.push(Measurement {
+            name,
+            time,
+            bytes: CIPHER_LEN as u64,
+        });
+    }
+
+    // A large file streamed from disk and back, without the KDF
+    let stream_len = 64 * 1024 * 1024;
+    let plain_path = scratch.join("stream.bin").to_string_lossy().into_owned();
+    fs::write(&plain_path, sample_of(&text, stream_len)).map_err(|err| err.to_string())?;
+    let cancel = CancellationToken::new();
+    let time = measure(|| {
+        sealer
+            .encrypt_file_with(&plain_path, |_| {}, &cancel)
+            .map(drop)
+            .map_err(error)
+    })?;
+    measurements.push(Measurement {
+        name: "stream/encrypt",
+        time,
+        bytes: stream_len as u64,
+    });
+    let sealed_path = format!("{}.enc", plain_path);
+    let time = measure(|| {
+        sealer
+            .decrypt_file_with(&sealed_path, |_| {}, &cancel)
+            .map(drop)
+            .map_err(error)
+    })?;
+    measurements.push(Measurement {
+        name: "stream/decrypt",
+        time,
+        bytes: stream_len as u64,
+    });
+
+    // Deriving a key with the default Argon2id cost, which a fresh Encryptor does once
+    let time = measure(|| {
+        Encryptor::new("correct horse battery staple")
+            .encrypt_bytes(b"")
+            .map(drop)
+            .map_err(error)
+    })?;
+    measurements.push(Measurement {
+        name: "kdf/default",
+        time,
+        bytes: 0,
+    });
+    // One pass over more memory, from which the iterations for KDF_TARGET follow
+    for (name, memory) in KDF_LEVELS {
+        let params = KdfParams {
+            memory,
+            iterations: 1,
+        };
+        // A new Encryptor every time, since one derives its key only once
+        let time = measure(|| {
+            Encryptor::new("correct horse battery staple")
+                .with_kdf_params(params)
+                .encrypt_bytes(b"")
+                .map(drop)
+                .map_err(error)
+        })?;
+        measurements.push(Measurement {
+            name,
+            time,
+            bytes: 0,
+        });
+    }
+
+    // Packing a tree of small files into an archive
+    let tree = scratch.join("tree");
+    let mut tree_len = 0;
+    for dir in 0..10 {
+        let dir_path = tree.join(format!("dir{}", dir));
+        fs::create_dir_all(&dir_path).map_err(|err| err.to_string())?;
+        for file in 0..50 {
+            let contents = &text[..4096 + file * 64];
+            tree_len += contents.len();
+            fs::write(dir_path.join(format!("file{}.log", file)), contents)
+                .map_err(|err| err.to_string())?;
+        }
+    }
+    let roots = [tree.clone()];
+    let archive_path = scratch.join("tree.enc");
+    let time = measure(|| {
+        archive::encrypt(
+            &sealer,
+            &roots,
+            Some(archive_path.as_path()),
+            None,
+            &Default::default(),
+            Default::default(),
+            false,
+        )
+        .map(drop)
+        .map_err(error)
+    })?;
+    measurements.push(Measurement {
+        name: "archive/pack",
+        time,
+        bytes: tree_len as u64,
+    });
+    Ok(measurements)
+}
+
+fn print_measurements(measurements: &[Measurement]) {
+    println!("{:<24} {:>12} {:>14}", "benchmark", "time", "throughput");
+    for measurement in measurements {
+        let throughput = match measurement.bytes {
+            0 => "-".to_string(),
+            bytes => units::speed(bytes, measurement.time),
+        };
+        println!(
+            "{:<24} {:>12} {:>14}",
+            measurement.name,
+            units::duration(measurement.time),
+            throughput
+        );
+    }
+}
+
+// Suggest the fastest cipher, and the most memory whose passes still fit KDF_TARGET at least twice over, with the
+// iterations that make unlocking take about KDF_TARGET
+fn print_suggestions(measurements: &[Measurement]) {
+    let time_of = |name: &str| {
+        measurements
+            .iter()
+            .find(|measurement| measurement.name == name)
+            .map(|measurement| measurement.time)
+    };
+    let fastest = CIPHERS
+        .iter()
+        .filter_map(|(name, algorithm)| Some((time_of(name)?, algorithm)))
+        .min_by_key(|(time, _)| *time);
+    if let Some((_, algorithm)) = fastest {
+        println!();
+        println!("Fastest cipher on this CPU: --cipher {}", algorithm.name());
+    }
+    let level = KDF_LEVELS
+        .iter()
+        .rev()
+        .filter_map(|(name, memory)| Some((time_of(name)?, *memory)))
+        .find(|(pass, _)| *pass * 2 <= KDF_TARGET);
+    if let Some((pass, memory)) = level {
+        let iterations = (KDF_TARGET.as_secs_f64() / pass.as_secs_f64().max(f64::EPSILON)).round();
+        println!(
+            "For about {} of unlocking: --kdf-memory {} --kdf-iterations {}",
+            units::duration(KDF_TARGET),
+            memory,
+            iterations.max(1.0) as u32
+        );
+    }
+}
+
+// Run `operation` once to warm up, then for about BUDGET (at least MIN_RUNS times), returning the median time
+fn measure(mut operation: impl FnMut() -> Result<(), String>) -> Result<Duration, String> {
+    operation()?;
+    let mut times = Vec::new();
+    let start = Instant::now();
+    while times.len() < MIN_RUNS || start.elapsed() < BUDGET {
+        let run = Instant::now();
+        operation()?;
+        times.push(run.elapsed());
+    }
+    times.sort();
+    Ok(times[times.len() / 2])
+}
+
+// Print how every benchmark compares with the baseline, failing if any got slower than the threshold allows
+fn compare_with(
+    measurements: &[Measurement],
+    baseline: &Value,
+    threshold: f64,
+) -> Result<(), String> {
+    println!(
+        "{:<24} {:>12} {:>12} {:>9}",
+        "benchmark", "baseline", "now", "change"
+    );
+    let mut regressions = 0;
+    for measurement in measurements {
+        let Some(nanoseconds) = baseline["results"][measurement.name]["nanoseconds"].as_u64()
+        else {
+            println!(
+                "{:<24} {:>12} {:>12} {:>9}",
+                measurement.name,
+                "-",
+                units::duration(measurement.time),
+                "new"
+            );
+            continue;
+        };
+        let before = Duration::from_nanos(nanoseconds);
+        let change =
+            (measurement.time.as_secs_f64() / before.as_secs_f64().max(f64::EPSILON) - 1.0) * 100.0;
+        let regressed = change > threshold;
+        regressions += regressed as usize;
+        println!(
+            "{:<24} {:>12} {:>12} {:>+8.1}%{}",
+            measurement.name,
+            units::duration(before),
+            units::duration(measurement.time),
+            change,
+            if regressed { "  regression" } else { "" }
+        );
+    }
+    match regressions {
+        0 => Ok(()),
+        _ => Err(format!(
+            "{} of {} benchmarks are more than {}% slower than the baseline",
+            regressions,
+            measurements.len(),
+            threshold
+        )),
+    }
+}
+
+// `len` bytes of `text`, repeated as often as needed
+fn sample_of(text: &[u8], len: usize) -> Vec<u8> {
+    text.iter().copied().cycle().take(len).collect()
+}
@@ -0,0 +1,360 @@
+// Team key bundles, to onboard a teammate to a shared encrypted store in one step. `bundle create` wraps the team's
+// shared key for every member's X25519 public key and signs it, together with the members' keys and the team's
+// policy, with an Ed25519 key from `keygen --signing`. `bundle import` checks the signature against the signers
+// the teammate trusts and installs the team into their local config, under <config>/teams/<name>/:
+//
+//   key.enc          the shared key, encrypted to the members (`decrypt --identity` opens it as well)
+//   recipients.txt   the members' public keys, one per line, for --recipient
+//   policy.json      the settings that files encrypted with --team are written with
+//   issuer.pub       the public key that signed the bundle
+//
+// `--team <name> --identity <key>` then uses the shared key. See `config_dir` for where the config is. A bundle is its JSON and the Ed25519 signature of it,
+// each base64url-encoded and joined by a dot, like a grant token. Signing and importing bundles needs the `grant`
+// cargo feature; without it they fail with an explanation.
+use crate::Options;
+use encryptor::recipient::Identity;
+use encryptor::{Algorithm, Compression, Encryptor};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+#[cfg(feature = "grant")]
+const TYPE: &str = "encryptor-bundle";
+#[cfg(feature = "grant")]
+const VERSION: u64 = 1;
+
+// A team installed by `bundle import`
+pub struct Team {
+    pub key: Zeroizing<[u8; 32]>,
+    pub policy: Policy,
+}
+
+// The settings a team's new files are written with. They take precedence over the flags.
+#[derive(Default)]
+pub struct Policy {
+    cipher: Option<Algorithm>,
+    compression: Option<Compression>,
+    wrap_key: Option<bool>,
+}
+
+impl Policy {
+    // Parse `setting=value` pairs, as given to `bundle create --policy` or read from a bundle
+    fn parse<'a>(settings: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Policy, String> {
+        let mut policy = Policy::default();
+        for (setting, value) in settings {
+            let invalid =
+                |expected: &str| format!("Invalid policy {}={}: {}", setting, value, expected);
+            match setting {
+                "cipher" => {
+                    policy.cipher = Some(Algorithm::from_name(value).ok_or_else(|| {
+                        invalid(&format!("expected one of: {}", Algorithm::names()))
+                    })?)
+                }
+                "compression" => {
+                    policy.compression =
+                        Some(Compression::parse(value).map_err(|err| invalid(&err))?)
+                }
+                "wrap-key" => {
+                    policy.wrap_key = Some(
+                        value
+                            .parse()
+                            .map_err(|_| invalid("expected true or false"))?,
+                    )
+                }
+                _ => {
+                    return Err(format!(
+                        "Unknown policy setting '{}' (expected cipher, compression or wrap-key)",
+                        setting
+                    ))
+                }
+            }
+        }
+        Ok(policy)
+    }
+
+    fn from_json(value: &Value) -> Result<Policy, String> {
+        let settings = value
+            .as_object()
+            .ok_or_else(|| "The policy is damaged".to_string())?;
+        Policy::parse(
+            settings
+                .iter()
+                .map(|(setting, value)| (setting.as_str(), value.as_str().unwrap_or_default())),
+        )
+    }
+
+    #[cfg(feature = "grant")]
+    fn to_json(&self) -> Value {
+        let mut settings = serde_json::Map::new();
+        if let Some(cipher) = self.cipher {
+            settings.insert("cipher".into(), cipher.name().into());
+        }
+        if let Some(compression) = self.compression {
+            settings.insert("compression".into(), compression.to_string().into());
+        }
+        if let Some(wrap_key) = self.wrap_key {
+            settings.insert("wrap-key".into(), wrap_key.to_string().into());
+        }
+        Value::Object(settings)
+    }
+
+    // Write new files with the team's settings, whatever the flags said
+    pub fn apply(&self, options: &mut Options) {
+        if let Some(cipher) = self.cipher {
+            options.algorithm = cipher;
+        }
+        if let Some(compression) = self.compression {
+            options.compression = compression;
+        }
+        if let Some(wrap_key) = self.wrap_key {
+            options.wrap_keys = wrap_key;
+        }
+    }
+}
+
+// Load the team installed under `name`, unlocking its key with `identity`
+pub fn load_team(name: &str, identity: Identity) -> Result<Team, String> {
+    let dir = team_dir(name)?;
+    let read = |file: &str| {
+        fs::read(dir.join(file)).map_err(|err| {
+            format!(
+                "Cannot read the team '{}' (install it with `bundle import`): {}: {}",
+                name,
+                dir.join(file).display(),
+                err
+            )
+        })
+    };
+    let key = unwrap_key(&read("key.enc")?, identity)
+        .map_err(|message| format!("Cannot use the team '{}': {}", name, message))?;
+    let policy = serde_json::from_slice(&read("policy.json")?)
+        .map_err(|err| err.to_string())
+        .and_then(|policy| Policy::from_json(&policy))
+        .map_err(|message| format!("Cannot use the policy of the team '{}': {}", name, message))?;
+    Ok(Team { key, policy })
+}
+
+// Sign a bundle of the team key at `args.key`, wrapped for the members in `args.recipients`, and write it
+#[cfg(feature = "grant")]
+pub fn create(args: &crate::cli::BundleCreateArgs) -> Result<String, String> {
+    use crate::keyfile;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use encryptor::grant::IssuerKey;
+    use encryptor::recipient::Recipient;
+    use serde_json::json;
+
+    check_name(&args.name)?;
+    let policy = Policy::parse(
+        args.policy
+            .iter()
+            .map(|setting| setting.split_once('=').unwrap_or((setting.as_str(), ""))),
+    )?;
+    let text = fs::read_to_string(&args.recipients)
+        .map_err(|err| format!("Cannot read the recipients {}: {}", args.recipients, err))?;
+    let recipients = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(keyfile::load_recipient)
+        .collect::<Result<Vec<_>, _>>()?;
+    if recipients.is_empty() {
+        return Err(format!("{} lists no recipients", args.recipients));
+    }
+    let key = keyfile::load(&args.key)?;
+    let signing_key = IssuerKey::from_bytes(*keyfile::load(&args.signing_key)?);
+
+    // The wrapped key is an ordinary encrypted file, with a stanza for every member
+    let wrapped = Encryptor::for_recipients(
+        recipients
+            .iter()
+            .copied()
+            .map(Recipient::from_bytes)
+            .collect(),
+    )
+    .with_metadata_binding(false)
+    .encrypt_bytes(&key[..])
+    .map_err(|err| format!("Cannot wrap the team key: {}", err))?;
+    let bundle = json!({
+        "type": TYPE,
+        "version": VERSION,
+        "name": args.name,
+        "issuer": keyfile::encode(&signing_key.issuer().to_bytes()),
+        "created_at": std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        "recipients": recipients.iter().map(keyfile::encode).collect::<Vec<_>>(),
+        "key": URL_SAFE_NO_PAD.encode(wrapped),
+        "policy": policy.to_json(),
+    });
+    let payload = URL_SAFE_NO_PAD.encode(bundle.to_string());
+    let signature = URL_SAFE_NO_PAD.encode(signing_key.sign(payload.as_bytes()));
+    fs::write(&args.out, format!("{}.{}\n", payload, signature))
+        .map_err(|err| format!("Cannot write {}: {}", args.out, err))?;
+    Ok(format!(
+        "Bundled the key of the team '{}' for {} members -> {}",
+        args.name,
+        recipients.len(),
+        args.out
+    ))
+}
+
+// Verify the bundle at `args.bundle` against the trusted issuers and install its team for --team
+#[cfg(feature = "grant")]
+pub fn import(args: &crate::cli::BundleImportArgs) -> Result<String, String> {
+    use crate::keyfile;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use encryptor::grant::Issuer;
+
+    let rejected = |reason: &str| format!("Cannot import the bundle {}: {}", args.bundle, reason);
+    let issuers = args
+        .issuers
+        .iter()
+        .map(|value| {
+            keyfile::load_recipient(value)
+                .and_then(|bytes| Issuer::from_bytes(bytes).map_err(|err| err.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("--issuer: {}", err))?;
+    let identity = Identity::from_bytes(*keyfile::load_identity(&args.identity)?);
+    let text = fs::read_to_string(&args.bundle)
+        .map_err(|err| format!("Cannot read {}: {}", args.bundle, err))?;
+
+    // The signature is checked before anything else in the bundle is trusted
+    let (payload, signature) = text
+        .trim()
+        .split_once('.')
+        .ok_or_else(|| rejected("the bundle is damaged"))?;
+    let decode = |part: &str| {
+        URL_SAFE_NO_PAD
+            .decode(part)
+            .map_err(|_| rejected("the bundle is damaged"))
+    };
+    let bundle: Value =
+        serde_json::from_slice(&decode(payload)?).map_err(|_| rejected("the bundle is damaged"))?;
+    if bundle["type"] != TYPE || bundle["version"] != VERSION {
+        return Err(rejected("not a key bundle, or from a newer version"));
+    }
+    let issuer = bundle["issuer"]
+        .as_str()
+        .and_then(|issuer| keyfile::load_recipient(issuer).ok())
+        .and_then(|bytes| Issuer::from_bytes(bytes).ok())
+        .ok_or_else(|| rejected("the bundle's issuer is damaged"))?;
+    if !issuers.contains(&issuer) {
+        return Err(rejected("the bundle was signed by an untrusted key"));
+    }
+    if !issuer.verify(payload.as_bytes(), &decode(signature)?) {
+        return Err(rejected("the bundle's signature is invalid"));
+    }
+
+    let name = bundle["name"].as_str().unwrap_or_default();
+    check_name(name).map_err(|message| rejected(&message))?;
+    let recipients = bundle["recipients"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|recipient| {
+            recipient
+                .as_str()
+                .and_then(|recipient| keyfile::load_recipient(recipient).ok())
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| rejected("the bundle's recipients are damaged"))?;
+    let wrapped = bundle["key"]
+        .as_str()
+        .and_then(|key| URL_SAFE_NO_PAD.decode(key).ok())
+        .ok_or_else(|| rejected("the bundle's key is damaged"))?;
+    // Only a member can use the team, and a policy this version doesn't understand isn't silently dropped
+    unwrap_key(&wrapped, identity).map_err(|message| rejected(&message))?;
+    let policy = Policy::from_json(&bundle["policy"]).map_err(|message| rejected(&message))?;
+
+    let dir = team_dir(name)?;
+    if dir.exists() && !args.force {
+        return Err(format!(
+            "The team '{}' is already installed in {}; give --force to replace it",
+            name,
+            dir.display()
+        ));
+    }
+    let recipients = recipients
+        .iter()
+        .map(|recipient| keyfile::encode(recipient) + "\n")
+        .collect::<String>();
+    let policy_json = serde_json::to_string_pretty(&policy.to_json()).unwrap_or_default() + "\n";
+    fs::create_dir_all(&dir)
+        .and_then(|()| fs::write(dir.join("key.enc"), &wrapped))
+        .and_then(|()| fs::write(dir.join("recipients.txt"), &recipients))
+        .and_then(|()| fs::write(dir.join("policy.json"), &policy_json))
+        .and_then(|()| {
+            fs::write(
+                dir.join("issuer.pub"),
+                keyfile::encode(&issuer.to_bytes()) + "\n",
+            )
+        })
+        .map_err(|err| {
+            format!(
+                "Cannot install the team '{}' in {}: {}",
+                name,
+                dir.display(),
+                err
+            )
+        })?;
+    Ok(format!(
+        "Installed the team '{}' with {} members and the policy {} in {}",
+        name,
+        recipients.lines().count(),
+        policy.to_json(),
+        dir.display()
+    ))
+}
+
+#[cfg(not(feature = "grant"))]
+pub fn create(_args: &crate::cli::BundleCreateArgs) -> Result<String, String> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "grant"))]
+pub fn import(_args: &crate::cli::BundleImportArgs) -> Result<String, String> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "grant"))]
+fn unavailable() -> String {
+    "Key bundle support is not built in (enable the 'grant' feature)".to_string()
+}
+
+// Open the wrapped team key with a member's identity
+fn unwrap_key(wrapped: &[u8], identity: Identity) -> Result<Zeroizing<[u8; 32]>, String> {
+    let key = Encryptor::from_identity(identity)
+        .decrypt_bytes(wrapped)
+        .map(Zeroizing::new)
+        .map_err(|err| format!("your identity is not one of the team's members ({})", err))?;
+    <[u8; 32]>::try_from(&key[..])
+        .map(Zeroizing::new)
+        .map_err(|_| "the team key is damaged".to_string())
+}
+
+// Team names become directory names, so they are kept to letters, digits, dots, dashes and underscores
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    match valid {
+        true => Ok(()),
+        false => Err(format!(
+            "Invalid team name '{}': use letters, digits, '.', '-' and '_'",
+            name
+        )),
+    }
+}
+
+// Where the team `name` is installed
+fn team_dir(name: &str) -> Result<PathBuf, String> {
+    check_name(name)?;
+    Ok(crate::config_dir()?.join("teams").join(name))
+}
@@ -0,0 +1,110 @@
+// BLAKE3 checksums of the plaintext, recorded in the header by `Encryptor::with_checksum` and checked again after
+// decrypting. The AEAD tags already catch any change to the ciphertext, chunk by chunk; the checksum covers the whole
+// file end to end, through decompression, the sealed name and the chunks put back together, so neither a chunk
+// dropped or repeated by a bug nor a plaintext damaged on its way out goes unnoticed.
+//
+// The header can be read without the key, and a bare hash of the plaintext in it would let anyone with a guess at the
+// contents confirm it. So the hash is recorded masked, XORed with a BLAKE3 key derived from the file key and the
+// nonce, which differ for every output: only a holder of the key can read it, and rekeying a file moves it under the
+// new key without reading the plaintext again. The header is authenticated with the payload, so the masked hash
+// can't be replaced either. Without the `checksum` feature nothing is recorded, and a recorded checksum isn't checked.
+use crate::header::Header;
+use crate::{EncryptError, KEY_LEN};
+
+// The length of a checksum, in bytes
+pub(crate) const LEN: usize = 32;
+
+// A BLAKE3 hash of a plaintext
+pub(crate) type Checksum = [u8; LEN];
+
+#[cfg(feature = "checksum")]
+const MASK_CONTEXT: &str = "encryptor 2026-10-16 plaintext checksum mask";
+
+// Hashes a plaintext given piece by piece
+#[cfg(feature = "checksum")]
+pub(crate) struct Hasher(blake3::Hasher);
+
+// Can't be created without the checksum feature
+#[cfg(not(feature = "checksum"))]
+pub(crate) enum Hasher {}
+
+#[cfg(feature = "checksum")]
+impl Hasher {
+    // A new hasher, or None when this build has no BLAKE3
+    pub(crate) fn new() -> Option<Hasher> {
+        Some(Hasher(blake3::Hasher::new()))
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    pub(crate) fn finish(&self) -> Checksum {
+        *self.0.finalize().as_bytes()
+    }
+}
+
+#[cfg(not(feature = "checksum"))]
+impl Hasher {
+    pub(crate) fn new() -> Option<Hasher> {
+        None
+    }
+
+    pub(crate) fn update(&mut self, _bytes: &[u8]) {
+        match *self {}
+    }
+
+    pub(crate) fn finish(&self) -> Checksum {
+        match *self {}
+    }
+}
+
+// The checksum of `plaintext`, or None when this build has no BLAKE3
+pub(crate) fn hash(plaintext: &[u8]) -> Option<Checksum> {
+    Hasher::new().map(|mut hasher| {
+        hasher.update(plaintext);
+        hasher.finish()
+    })
+}
+
+// Mask `checksum` for the output sealed with `key` and `nonce`, or unmask it again: XORing twice with the same mask
+// gives it back. None when this build has no BLAKE3.
+pub(crate) fn mask(checksum: &Checksum, key: &[u8; KEY_LEN], nonce: &[u8]) -> Option<Checksum> {
+    #[cfg(feature = "checksum")]
+    {
+        let mut hasher = blake3::Hasher::new_derive_key(MASK_CONTEXT);
+        hasher.update(key).update(nonce);
+        let mask = hasher.finalize();
+        let mut masked = *checksum;
+        for (byte, mask) in masked.iter_mut().zip(mask.as_bytes()) {
+            *byte ^= mask;
+        }
+        Some(masked)
+    }
+    #[cfg(not(feature = "checksum"))]
+    {
+        let _ = (checksum, key, nonce);
+        None
+    }
+}
+
+// The checksum `header` records, unmasked with the file's `key`. None when it records none, or when this build can't
+// check it.
+pub(crate) fn recorded(header: &Header, key: &[u8; KEY_LEN]) -> Option<Checksum> {
+    let checksum = mask(header.checksum.as_ref()?, key, &header.nonce);
+    if checksum.is_none() {
+        log::warn!("The file records a checksum of its plaintext, which this build can't check (enable the 'checksum' feature)");
+    }
+    checksum
+}
+
+// Fail unless the plaintext hashed to `actual` is the one the `recorded` checksum was taken of
+pub(crate) fn check(recorded: &Checksum, actual: Option<Checksum>) -> Result<(), EncryptError> {
+    match actual {
+        Some(actual) if actual == *recorded => Ok(()),
+        _ => Err(EncryptError::MetadataMismatch(
+            "the plaintext doesn't match the BLAKE3 checksum recorded when it was encrypted"
+                .to_string(),
+        )),
+    }
+}
@@ -0,0 +1,69 @@
+// The AEAD operations behind each Algorithm.
+//
+// AES-256-GCM, AES-128-GCM and ChaCha20-Poly1305 come from the crypto backend selected at compile time (see backend).
+// AES-256-GCM-SIV comes from the RustCrypto 'aes-gcm-siv' crate with every backend. GCM-SIV is nonce-misuse resistant:
+// if the same nonce were ever used twice with the same key, an attacker would only learn whether the two plaintexts
+// are identical, instead of being able to recover plaintext (and forge messages) as with plain GCM or ChaCha20-Poly1305.
+use crate::backend::{Active, Backend, CryptoError};
+use crate::{Algorithm, KEY_LEN, NONCE_LEN};
+use aes_gcm_siv::aead::{AeadInPlace, KeyInit};
+use aes_gcm_siv::Aes256GcmSiv;
+use zeroize::Zeroize;
+
+// Encrypt the contents in place and append the authentication tag.
+// `aad` is authenticated along with the contents but not encrypted or included in the output.
+pub(crate) fn seal_in_place(
+    algorithm: Algorithm,
+    key_bytes: &[u8; KEY_LEN],
+    nonce: &[u8],
+    aad: &[u8],
+    contents: &mut Vec<u8>,
+) -> Result<(), CryptoError> {
+    match algorithm {
+        Algorithm::Aes256GcmSiv => Aes256GcmSiv::new(key_bytes.into())
+            .encrypt_in_place(gcm_siv_nonce(nonce)?, aad, contents)
+            .map_err(|_| CryptoError),
+        _ => Active::seal_in_place(algorithm, key_bytes, nonce, aad, contents),
+    }
+}
+
+// Decrypt the contents in place and remove the authentication tag, leaving only the plaintext.
+// `aad` must be the same associated data the contents were sealed with. If the tag doesn't check out the contents
+// are wiped and left empty, so no backend's half-decrypted bytes can be mistaken for plaintext or written out.
+pub(crate) fn open_in_place(
+    algorithm: Algorithm,
+    key_bytes: &[u8; KEY_LEN],
+    nonce: &[u8],
+    aad: &[u8],
+    contents: &mut Vec<u8>,
+) -> Result<(), CryptoError> {
+    let opened = match algorithm {
+        Algorithm::Aes256GcmSiv => Aes256GcmSiv::new(key_bytes.into())
+            .decrypt_in_place(gcm_siv_nonce(nonce)?, aad, contents)
+            .map_err(|_| CryptoError),
+        _ => Active::open_in_place(algorithm, key_bytes, nonce, aad, contents),
+    };
+    if opened.is_err() {
+        contents.zeroize();
+    }
+    opened
+}
+
+// Fill `dest` with cryptographically secure random bytes from the backend
+pub(crate) fn fill_random(dest: &mut [u8]) -> Result<(), CryptoError> {
+    Active::fill_random(dest)
+}
+
+// The name of the backend AES-GCM, ChaCha20-Poly1305 and random numbers come from
+pub(crate) fn backend_name() -> &'static str {
+    Active::NAME
+}
+
+// The nonce comes from the file header, so check its length instead of letting the conversion panic
+fn gcm_siv_nonce(nonce: &[u8]) -> Result<&aes_gcm_siv::Nonce, CryptoError> {
+    if nonce.len() == NONCE_LEN {
+        Ok(aes_gcm_siv::Nonce::from_slice(nonce))
+    } else {
+        Err(CryptoError)
+    }
+}
@@ -0,0 +1,1780 @@
+// The command line definition: every subcommand, its typed flags and the generated `--help` and `--version`.
+// Values are parsed and checked here, so a missing argument, a bad value or a flag the subcommand doesn't take is
+// reported with a usage error (exit status 2) before any password is asked for or any file is touched.
+// The commands that process files are turned into `Options` for the shared pipeline in main.
+use crate::output::ColorChoice;
+use crate::password::Source;
+use crate::path_filter::{PathFilter, Rule};
+use crate::text::Text;
+use crate::vault::{Action, Vault};
+use crate::{archive, keyfile, qr, Format, Options};
+use clap::error::ErrorKind;
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand};
+use encryptor::{encoding, Algorithm, Compression, Encoder, KdfParams, Padding};
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(
+    name = "encryptor",
+    version,
+    about = "Encrypt and decrypt files with a password, a key file or public keys",
+    after_help = "Without a --pass*, --use-keyring, --keyfile, --identity or --recipient option the password is read from the command in $ENCRYPTOR_PASSWORD_CMD if it is set, or else from a hidden prompt."
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// Color the status lines: auto (on a terminal, unless NO_COLOR is set), always or never
+    #[arg(long, global = true, value_name = "WHEN", default_value = "auto", value_parser = parse_color)]
+    pub color: ColorChoice,
+
+    /// Log what happens to each file on stderr; twice adds KDF parameters, progress and timing
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Print nothing when files succeed: no status lines, warnings or log messages, only failures
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Show sizes, durations and times for people, e.g. 12.1 KiB, 3 min 20 s and UTC dates (the default)
+    #[arg(long, global = true, conflicts_with = "raw")]
+    pub human: bool,
+
+    /// Show sizes in bytes, durations in seconds and times in seconds since the Unix epoch, for scripts
+    #[arg(long, global = true)]
+    pub raw: bool,
+
+    /// Write a JSON record of the settings, files, timings, log messages and errors of this run to PATH, for bug
+    /// reports; passwords, keys and file contents are left out
+    #[arg(long, global = true, value_name = "PATH")]
+    pub debug_transcript: Option<String>,
+
+    /// Show the paths of files as salted hashes of their names in all output, logs and reports [env:
+    /// ENCRYPTOR_REDACT_PATHS=1]
+    #[arg(long, global = true)]
+    pub redact_paths: bool,
+
+    /// Lock passwords and keys into RAM and turn off core dumps, for hosts shared with untrusted users [env:
+    /// ENCRYPTOR_HARDEN=1]
+    #[arg(long, global = true)]
+    pub harden: bool,
+
+    /// Read default settings from this TOML file instead of config.toml in the config directory
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<String>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Encrypt files, each into <file>.enc
+    // Boxed, as it has far more options than the other commands
+    Encrypt(Box<EncryptArgs>),
+    /// Decrypt files written by encrypt, age, JWE or CMS, each into its name without the extension
+    Decrypt(DecryptArgs),
+    /// Encrypt a short text into ASCII armor on stdout, without writing a file
+    EncryptText(EncryptTextArgs),
+    /// Decrypt the armor printed by encrypt-text back into the text
+    DecryptText(DecryptTextArgs),
+    /// Hold the key of a password for a while, encrypting and decrypting files for `client` without running the KDF
+    /// again (Unix)
+    Daemon(DaemonArgs),
+    /// Encrypt or decrypt files through a running `daemon`, or stop it
+    Client {
+        #[command(subcommand)]
+        command: ClientCommand,
+    },
+    /// Keep named secrets such as API tokens together in one encrypted vault file
+    Vault {
+        #[command(subcommand)]
+        command: VaultCommand,
+    },
+    /// Check that files decrypt, without writing the plaintext anywhere
+    Verify(VerifyArgs),
+    /// Re-encrypt files under a new password or key, replacing each without writing its plaintext to disk
+    Rekey(RekeyArgs),
+    /// Rekey the encrypted files in directory trees that aren't recorded under the new key ID yet
+    Rotate(RotateArgs),
+    /// Re-encrypt files written by the first releases, which had no header, in the current format
+    Migrate(MigrateArgs),
+    /// Show what the headers of encrypted files record, without decrypting them
+    Info {
+        #[arg(required = true, value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Report on encrypted files, such as those whose password is due for a change
+    Report {
+        #[command(subcommand)]
+        command: ReportCommand,
+    },
+    /// Create a key file for --keyfile, an X25519 identity or a grant signing key
+    Keygen(KeygenArgs),
+    /// Back up a key file on paper as 24 words, or restore it from them
+    Key {
+        #[command(subcommand)]
+        command: KeyCommand,
+    },
+    /// Let an operator decrypt some files for a limited time, without sharing the password
+    Grant(GrantArgs),
+    /// Share a team's key, members and policy in one signed bundle, or install one for --team
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommand,
+    },
+    /// Train a zstd dictionary on sample files, written encrypted, for --dict
+    TrainDict(TrainDictArgs),
+    /// Store or delete a password in the platform keychain, for --use-keyring
+    Keyring {
+        #[command(subcommand)]
+        command: KeyringCommand,
+    },
+    /// Export the header of a file as JSON, or graft one onto a file
+    Header {
+        #[command(subcommand)]
+        command: HeaderCommand,
+    },
+    /// Restore the damaged header of a file from its backup, or from what you know about it
+    Recover(RecoverArgs),
+    /// Diagnose a file that won't decrypt, step by step, and suggest the command that fixes it
+    Doctor(DoctorArgs),
+    /// Finish or undo an `encrypt --shred` batch that was interrupted
+    ResumeJournal(ResumeJournalArgs),
+    /// Put back the originals the last `encrypt --remove-to-trash` moved to the trash, and remove their outputs
+    Undo,
+    /// Encrypt the files that appear or change in a directory into another one, until stopped
+    Watch(WatchArgs),
+    /// Browse an archive made with `encrypt --archive` as a read-only filesystem, until unmounted (Linux)
+    Mount(MountArgs),
+    /// Encrypt (clean) or decrypt (smudge) a file from stdin to stdout, as the filter of a git attribute
+    GitFilter(GitFilterArgs),
+    /// Measure performance, suggest a cipher and KDF cost for this machine, or compare the compression codecs
+    Bench(BenchArgs),
+    /// Check this build's ciphers, key derivation and file format against known answers
+    Selftest,
+    /// Record usage statistics on this machine only, or summarize them
+    Stats {
+        #[command(subcommand)]
+        command: StatsCommand,
+    },
+    /// Check that an --audit-log wasn't tampered with
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+}
+
+#[derive(Args)]
+pub struct EncryptArgs {
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+    #[command(flatten)]
+    secret: SecretArgs,
+    #[command(flatten)]
+    certificates: CertificateArgs,
+    #[command(flatten)]
+    settings: EncryptSettings,
+    #[command(flatten)]
+    batch: BatchArgs,
+    #[command(flatten)]
+    output: OutputArgs,
+    #[command(flatten)]
+    filter: FilterArgs,
+    #[command(flatten)]
+    http: HttpArgs,
+    /// Upload to an http(s):// --out with this request: put or post
+    #[arg(long, value_name = "METHOD", default_value = "put", value_parser = ["put", "post"])]
+    http_method: String,
+    /// Store the objects of an s3:// --out in this storage class, e.g. STANDARD_IA, GLACIER or DEEP_ARCHIVE
+    /// [default: storage-class in the config file, or the bucket's]
+    #[arg(long, value_name = "CLASS", value_parser = parse_storage_class)]
+    storage_class: Option<String>,
+    /// Write the file in another format: age, jwe, jwe-json, cms or openssl (`openssl enc`, not authenticated)
+    #[arg(long, value_enum, default_value = "native")]
+    format: Format,
+    /// The PBKDF2 iterations of --format openssl, like `openssl enc -iter` [default: 10000]
+    #[arg(long = "pbkdf2-iter", value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pbkdf2_iterations: Option<u32>,
+    /// Hide the file name: encrypt it with the contents and give the output a random name
+    // The journal of --shred needs each output's name up front, and archives hide the names inside them already
+    #[arg(long, conflicts_with_all = ["in_place", "archive", "shred"])]
+    encrypt_names: bool,
+    /// Name each output after the BLAKE3 hash of its bytes, <hash>.enc, keeping the original name in the header
+    // Neither an output resumed or journalled for --shred nor a volume has its name until it is complete
+    #[arg(long, conflicts_with_all = ["in_place", "archive", "shred", "encrypt_names", "split_size", "resume", "auto_rename", "incremental"])]
+    name_by_hash: bool,
+    /// Overwrite and delete each original once its output is verified
+    #[arg(long, conflicts_with_all = ["archive", "out"])]
+    shred: bool,
+    /// Move each original to the trash once its output is written, where `encryptor undo` can put it back
+    #[arg(long, conflicts_with_all = ["in_place", "archive", "shred", "split_size", "incremental"])]
+    remove_to_trash: bool,
+    /// Where --shred records its progress [default: encryptor.journal]
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "shred",
+        conflicts_with = "in_place"
+    )]
+    journal: Option<String>,
+    /// Pack the given files and directory trees into one encrypted archive (--out names it)
+    #[arg(long, conflicts_with = "in_place")]
+    archive: bool,
+    /// Pack what the symbolic links in the archive point to, instead of skipping them
+    #[arg(long, requires = "archive", conflicts_with = "preserve_symlinks")]
+    follow_symlinks: bool,
+    /// Store the symbolic links in the archive as links, recreated by --extract
+    #[arg(long, requires = "archive")]
+    preserve_symlinks: bool,
+    /// Also pack the NTFS alternate data streams of the archived files and directories (Windows)
+    #[arg(long, requires = "archive")]
+    ads: bool,
+    /// Also write an encrypted manifest of every file's path, BLAKE3 hash, size and output to PATH
+    #[arg(long, value_name = "PATH")]
+    manifest: Option<String>,
+    /// Write each output as volumes of at most SIZE bytes, <output>.001, .002..., e.g. 4095M for FAT32
+    #[arg(long, value_name = "SIZE", value_parser = parse_split_size, conflicts_with_all = ["in_place", "archive", "shred", "encrypt_names", "resume"])]
+    split_size: Option<u64>,
+    /// Refuse a password whose strength (0 to 4) is below SCORE; weaker ones than 3 are only warned about
+    #[arg(long, value_name = "SCORE", value_parser = clap::value_parser!(u8).range(0..=4))]
+    min_strength: Option<u8>,
+    /// Encrypt with a random key split into N shares, <FILE>.1.share and on, to hand to different custodians
+    #[arg(long, value_name = "N", requires = "threshold", value_parser = clap::value_parser!(u8).range(2..), conflicts_with_all = ["password_source", "keyfile", "identity", "team", "recipients", "ssh_recipients", "fido2", "certificates", "private_key"])]
+    shares: Option<u8>,
+    /// How many of the --shares it takes to decrypt
+    #[arg(long, value_name = "K", requires = "shares", value_parser = clap::value_parser!(u8).range(2..))]
+    threshold: Option<u8>,
+    /// Write the --shares to <PREFIX>.1.share and on instead [default: the first FILE]
+    #[arg(long, value_name = "PREFIX", requires = "shares")]
+    share_out: Option<String>,
+    /// Also let a random recovery code, printed once, decrypt the files if the password is forgotten
+    #[arg(long, conflicts_with_all = ["keyfile", "identity", "team", "fido2", "certificates", "private_key", "shares"])]
+    recovery_code: bool,
+    /// Back up the directory trees into a mirror of them in --out, encrypting only the files changed since the last run
+    #[arg(long, requires_all = ["state", "out"], conflicts_with_all = ["in_place", "archive", "shred", "encrypt_names", "split_size", "dry_run"])]
+    incremental: bool,
+    /// Where --incremental records the size, time and hash of every file it encrypted, encrypted like the files
+    #[arg(long, value_name = "PATH", requires = "incremental")]
+    state: Option<PathBuf>,
+    /// Remove the outputs of the files deleted since the last --incremental run
+    #[arg(long, requires = "incremental")]
+    delete: bool,
+}
+
+#[derive(Args)]
+pub struct DecryptArgs {
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+    #[command(flatten)]
+    secret: SecretArgs,
+    #[command(flatten)]
+    certificates: CertificateArgs,
+    #[command(flatten)]
+    batch: BatchArgs,
+    #[command(flatten)]
+    output: OutputArgs,
+    #[command(flatten)]
+    http: HttpArgs,
+    /// The zstd dictionary the files were compressed with
+    #[arg(long, value_name = "DICT")]
+    dict: Option<String>,
+    /// The PBKDF2 iterations OpenSSL enc files were encrypted with, like `openssl enc -iter` [default: 10000]
+    #[arg(long = "pbkdf2-iter", value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pbkdf2_iterations: Option<u32>,
+    /// Overwrite the old blocks of each file replaced with --in-place
+    #[arg(long, requires = "in_place")]
+    shred: bool,
+    /// Unpack an archive written by `encrypt --archive` (into --out, or the current directory)
+    #[arg(long, conflicts_with = "in_place")]
+    extract: bool,
+    /// Decrypt with a grant token, or a directory of *.tok tokens, minted for --identity
+    #[arg(long, value_name = "TOKEN", requires_all = ["identity", "grant_issuers"], conflicts_with = "extract")]
+    grant: Option<String>,
+    /// The public key of an issuer whose grants you trust; repeatable
+    #[arg(long = "grant-issuer", value_name = "PUBKEY", requires = "grant")]
+    grant_issuers: Vec<String>,
+    /// Where each use of a grant is recorded [default: <token>.log]
+    #[arg(long, value_name = "PATH", requires = "grant")]
+    grant_log: Option<String>,
+    /// Decrypt only LEN bytes from OFFSET, e.g. 1G:4M, or the rest from OFFSET with OFFSET: (chunked files only)
+    #[arg(long, value_name = "OFFSET:LEN", value_parser = parse_range, conflicts_with_all = ["in_place", "extract", "grant", "resume"])]
+    range: Option<(u64, Option<u64>)>,
+    /// Write what authenticates of a damaged chunked file anyway, zero-filling the damaged chunks
+    #[arg(long, conflicts_with_all = ["in_place", "extract", "range"])]
+    keep_going: bool,
+    /// Decrypt files written by the first releases, which had no header, with the password and --nonce
+    #[arg(long, requires = "nonce", conflicts_with_all = ["in_place", "extract", "grant", "range", "keep_going", "resume", "keyfile", "identity"])]
+    legacy: bool,
+    /// The nonce the --legacy files were encrypted with, as the JSON array of 12 bytes given to those releases
+    #[arg(long, value_name = "JSON", value_parser = parse_legacy_nonce, requires = "legacy")]
+    nonce: Option<[u8; 12]>,
+    /// Decrypt with the key rebuilt from the share files of `encrypt --shares`, at least as many as its threshold
+    #[arg(long = "share", value_name = "PATH", conflicts_with_all = ["password_source", "keyfile", "identity", "team", "recipients", "ssh_recipients", "fido2", "certificates", "private_key", "grant", "legacy"])]
+    share_files: Vec<String>,
+    /// Ask for the recovery code printed by `encrypt --recovery-code` instead of the password
+    #[arg(long, conflicts_with_all = ["keyfile", "identity", "team", "recipients", "ssh_recipients", "fido2", "certificates", "private_key", "share_files", "grant", "legacy"])]
+    recovery_code: bool,
+    /// How many times a password typed at the prompt may be tried before giving up, when it fails the file's password check
+    #[arg(long, value_name = "N", default_value = "3", value_parser = clap::value_parser!(u32).range(1..))]
+    password_attempts: u32,
+    /// Warn about files whose password was set longer ago than this, e.g. 365d
+    #[arg(long, value_name = "AGE", value_parser = parse_duration)]
+    max_password_age: Option<Duration>,
+    /// When a password typed at the prompt is wrong, also try it with caps lock inverted, with or without spaces
+    /// around it, as typed on a QWERTZ or AZERTY keyboard, and with two neighbouring characters swapped, and say which
+    /// one worked
+    #[arg(long)]
+    try_typos: bool,
+}
+
+#[derive(Args)]
+#[command(
+    after_help = "Each file is decrypted with the password, used as the key like the first releases did, and the nonce, and replaced with the same contents encrypted under the password in the current format. Decrypt it without --legacy from then on."
+)]
+pub struct MigrateArgs {
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+    /// The nonce the files were encrypted with, as the JSON array of 12 bytes given to those releases
+    #[arg(long, value_name = "JSON", value_parser = parse_legacy_nonce)]
+    nonce: [u8; 12],
+    #[command(flatten)]
+    secret: SecretArgs,
+    #[command(flatten)]
+    batch: BatchArgs,
+}
+
+#[derive(Args)]
+#[command(
+    after_help = "A text given on the command line may be kept in the shell's history; pipe it to stdin or use --clipboard for anything sensitive."
+)]
+pub struct EncryptTextArgs {
+    /// The text to encrypt; read from stdin (or the clipboard with --clipboard) when left out
+    #[arg(value_name = "TEXT", allow_hyphen_values = true)]
+    text: Option<String>,
+    /// Read the text from the clipboard when none is given, and put the armor onto it instead of printing it
+    #[arg(long)]
+    clipboard: bool,
+    /// Print the encrypted text as a QR code to scan instead of as armor (needs the qr feature)
+    #[arg(long, conflicts_with = "clipboard")]
+    qr: bool,
+    /// Write the QR code to this PNG image instead of printing anything
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["clipboard", "qr"])]
+    qr_out: Option<String>,
+    #[command(flatten)]
+    secret: SecretArgs,
+    #[command(flatten)]
+    kdf: KdfArgs,
+}
+
+#[derive(Args)]
+pub struct DecryptTextArgs {
+    /// The armor, or the line scanned from a QR code, to decrypt; read from stdin (or the clipboard with --clipboard)
+    /// when left out
+    #[arg(value_name = "ARMOR", allow_hyphen_values = true)]
+    armor: Option<String>,
+    /// Read the armor from the clipboard when none is given, and put the text onto it instead of printing it
+    #[arg(long)]
+    clipboard: bool,
+    #[command(flatten)]
+    secret: SecretArgs,
+}
+
+#[derive(Args)]
+pub struct DaemonArgs {
+    /// The socket to listen on [default: encryptor.sock in $XDG_RUNTIME_DIR or the config directory]
+    #[arg(long, value_name = "PATH")]
+    socket: Option<String>,
+    /// How long to keep the key before forgetting it and exiting, e.g. 30m or 8h
+    #[arg(long, value_name = "TIME", default_value = "15m", value_parser = parse_duration)]
+    ttl: Duration,
+    #[command(flatten)]
+    secret: SecretArgs,
+    #[command(flatten)]
+    kdf: KdfArgs,
+}
+
+#[derive(Subcommand)]
+pub enum ClientCommand {
+    /// Have the daemon encrypt files, each into <file>.enc
+    Encrypt(ClientFileArgs),
+    /// Have the daemon decrypt files, each into its name without the extension
+    Decrypt(ClientFileArgs),
+    /// Print how long the daemon keeps its key
+    Status(ClientArgs),
+    /// Stop the daemon, which forgets its key
+    Stop(ClientArgs),
+}
+
+#[derive(Args)]
+pub struct ClientArgs {
+    /// The socket the daemon listens on [default: encryptor.sock in $XDG_RUNTIME_DIR or the config directory]
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<String>,
+}
+
+#[derive(Args)]
+pub struct ClientFileArgs {
+    #[arg(required = true, value_name = "FILE")]
+    pub files: Vec<PathBuf>,
+    /// Where to write the output of a single file, or the directory the outputs of several go into
+    #[arg(short, long, value_name = "PATH")]
+    pub out: Option<PathBuf>,
+    /// Replace outputs that already exist
+    #[arg(long)]
+    pub force: bool,
+    #[command(flatten)]
+    pub client: ClientArgs,
+}
+
+#[derive(Subcommand)]
+pub enum VaultCommand {
+    /// Create an empty vault, sealed with a new password or key
+    Init(VaultInitArgs),
+    /// Store a secret under a name, replacing any value it had
+    Set(VaultSetArgs),
+    /// Print the value of a secret
+    Get(VaultNameArgs),
+    /// Print the names of the secrets in the vault, one per line
+    List(VaultArgs),
+    /// Remove a secret from the vault
+    Rm(VaultNameArgs),
+}
+
+#[derive(Subcommand)]
+pub enum ReportCommand {
+    /// List the files whose password was set longer ago than --max-password-age, opening each with the password
+    StaleKeys(StaleKeysArgs),
+}
+
+#[derive(Args)]
+pub struct StaleKeysArgs {
+    /// Files, and directories searched recursively
+    #[arg(required = true, value_name = "PATH")]
+    paths: Vec<PathBuf>,
+    #[command(flatten)]
+    secret: SecretArgs,
+    /// How long ago a password may have been set before its files are listed, e.g. 180d [default: 365d]
+    #[arg(long, value_name = "AGE", value_parser = parse_duration)]
+    max_password_age: Option<Duration>,
+    #[command(flatten)]
+    filter: FilterArgs,
+}
+
+#[derive(Args)]
+pub struct VaultArgs {
+    /// The vault file [default: vault.enc in the config directory]
+    #[arg(long, value_name = "PATH")]
+    vault: Option<String>,
+    #[command(flatten)]
+    secret: SecretArgs,
+}
+
+#[derive(Args)]
+pub struct VaultInitArgs {
+    #[command(flatten)]
+    vault: VaultArgs,
+    #[command(flatten)]
+    kdf: KdfArgs,
+    /// Replace a vault that already exists, and every secret in it
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args)]
+#[command(
+    after_help = "A value given on the command line may be kept in the shell's history; pipe it to stdin or type it at the prompt for anything sensitive."
+)]
+pub struct VaultSetArgs {
+    #[arg(value_name = "NAME", value_parser = parse_vault_name)]
+    name: String,
+    /// The value; read from stdin, or a hidden prompt on the terminal, when left out
+    #[arg(value_name = "VALUE", allow_hyphen_values = true)]
+    value: Option<String>,
+    #[command(flatten)]
+    vault: VaultArgs,
+}
+
+#[derive(Args)]
+pub struct VaultNameArgs {
+    #[arg(value_name = "NAME", value_parser = parse_vault_name)]
+    name: String,
+    #[command(flatten)]
+    vault: VaultArgs,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// Files, or with --manifest the directories a backup was restored into
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+    #[command(flatten)]
+    secret: SecretArgs,
+    #[command(flatten)]
+    batch: BatchArgs,
+    #[command(flatten)]
+    http: HttpArgs,
+    /// The zstd dictionary the files were compressed with
+    #[arg(long, value_name = "DICT")]
+    dict: Option<String>,
+    /// Check files that were encrypted with --in-place, under their own name
+    #[arg(long)]
+    in_place: bool,
+    /// Check that the directories match the manifest written by `encrypt --manifest` exactly
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["in_place", "json", "report"])]
+    manifest: Option<String>,
+    /// Only check the decrypted file next to each FILE against the checksum of `encrypt --checksum`, without
+    /// decrypting FILE again
+    #[arg(long, conflicts_with_all = ["in_place", "manifest"])]
+    checksum_only: bool,
+    /// Warn about files whose password was set longer ago than this, e.g. 365d
+    #[arg(long, value_name = "AGE", value_parser = parse_duration)]
+    max_password_age: Option<Duration>,
+}
+
+#[derive(Args)]
+#[command(
+    after_help = "OLD and NEW are each one of: prompt, file:PATH, env:VAR, fd:N, keyring:NAME, cmd:COMMAND or keyfile:PATH (a raw key). The new password is asked for twice at the prompt."
+)]
+pub struct RekeyArgs {
+    /// Where the current password or key comes from
+    #[arg(value_name = "OLD", value_parser = parse_secret_source)]
+    old: SecretSource,
+    /// Where the new password or key comes from
+    #[arg(value_name = "NEW", value_parser = parse_secret_source)]
+    new: SecretSource,
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+    /// Wrap the data key of files that don't have one yet too, so the next rekey only rewrites their header
+    #[arg(long)]
+    wrap_key: bool,
+    /// Record this ID of the new password or key in each file [default: the ID the file has]
+    #[arg(long, value_name = "ID", value_parser = parse_key_id)]
+    key_id: Option<String>,
+    #[command(flatten)]
+    batch: BatchArgs,
+}
+
+#[derive(Args)]
+#[command(
+    after_help = "OLD and NEW are given like those of `rekey`. Files that aren't in the native format, or can't be read, are skipped."
+)]
+pub struct RotateArgs {
+    /// Where the current password or key comes from
+    #[arg(value_name = "OLD", value_parser = parse_secret_source)]
+    old: SecretSource,
+    /// Where the new password or key comes from
+    #[arg(value_name = "NEW", value_parser = parse_secret_source)]
+    new: SecretSource,
+    /// Files, and directories searched recursively
+    #[arg(required = true, value_name = "PATH")]
+    paths: Vec<PathBuf>,
+    /// The ID of the new password or key; files already recorded under it are left alone
+    #[arg(long, value_name = "ID", value_parser = parse_key_id)]
+    key_id: String,
+    /// Only rotate the files recorded under this old key ID [default: every file under another ID, or none]
+    #[arg(long, value_name = "ID")]
+    from: Option<String>,
+    /// Wrap the data key of files that don't have one yet, so their next rotation only rewrites the header
+    #[arg(long)]
+    wrap_key: bool,
+    #[command(flatten)]
+    filter: FilterArgs,
+    #[command(flatten)]
+    batch: BatchArgs,
+}
+
+#[derive(Args)]
+pub struct KeygenArgs {
+    /// Where to write the key; an existing file is never overwritten
+    #[arg(short, long, value_name = "PATH")]
+    pub out: String,
+    /// Create an X25519 identity for --identity, and its public key in <out>.pub
+    #[arg(long, conflicts_with = "signing")]
+    pub asymmetric: bool,
+    /// Create an Ed25519 key for signing grants, and its public key in <out>.pub
+    #[arg(long)]
+    pub signing: bool,
+    /// Make a credential on the FIDO2 security key plugged in, for --fido2, and write its ID to <out>
+    #[arg(long, conflicts_with_all = ["asymmetric", "signing"])]
+    pub fido2: bool,
+    /// Also print the key as 24 words to write down, for `key import --mnemonic`
+    #[arg(long, conflicts_with_all = ["signing", "fido2"])]
+    pub mnemonic: bool,
+    /// How to write the key: raw, hex or base64
+    #[arg(long, value_name = "FORMAT", default_value = "raw", value_parser = parse_key_format)]
+    pub format: keyfile::Format,
+}
+
+#[derive(Args)]
+pub struct GrantArgs {
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+    #[command(flatten)]
+    secret: SecretArgs,
+    /// The public key of the operator the grant is for
+    #[arg(long, value_name = "PUBKEY")]
+    grant_to: String,
+    /// The issuer's key from `keygen --signing`
+    #[arg(long, value_name = "PATH")]
+    signing_key: String,
+    /// How long the grant is valid, e.g. 30m or 8h (at most 7d)
+    #[arg(long, value_name = "TIME", value_parser = parse_duration)]
+    expires: Duration,
+    /// Why the grant is issued, recorded in it and in the usage log
+    #[arg(long, value_name = "TEXT")]
+    purpose: String,
+    /// Where to write the token
+    #[arg(short, long, value_name = "TOKEN")]
+    out: PathBuf,
+}
+
+#[derive(Args)]
+pub struct TrainDictArgs {
+    /// Where to write the dictionary
+    #[arg(value_name = "DICT")]
+    dict: PathBuf,
+    #[arg(required = true, value_name = "SAMPLE")]
+    samples: Vec<PathBuf>,
+    #[command(flatten)]
+    secret: SecretArgs,
+    #[command(flatten)]
+    kdf: KdfArgs,
+    /// The largest the dictionary may be, in bytes [default: 112640]
+    #[arg(long, value_name = "BYTES")]
+    dict_size: Option<usize>,
+}
+
+#[derive(Subcommand)]
+pub enum BundleCommand {
+    /// Wrap the team key for every member and sign it into one bundle, with the members and the policy
+    Create(BundleCreateArgs),
+    /// Check the signature of a bundle and install its team into the local config
+    Import(BundleImportArgs),
+}
+
+#[derive(Args)]
+#[command(
+    after_help = "Policy settings: cipher=aes256gcm|chacha20poly1305|aes256gcmsiv|aes128gcm, compression=<codec>[:<level>] and wrap-key=true|false. Files encrypted with --team are written with them."
+)]
+pub struct BundleCreateArgs {
+    /// The team's name, which teammates give as --team once the bundle is imported
+    #[arg(long, value_name = "NAME")]
+    pub name: String,
+    /// The members' X25519 public keys, one per line (hex, base64 or .pub files; # starts a comment)
+    #[arg(long, value_name = "PATH")]
+    pub recipients: String,
+    /// The team's shared key, a key file from `keygen`
+    #[arg(long, value_name = "PATH")]
+    pub key: String,
+    /// The key to sign the bundle with, from `keygen --signing`
+    #[arg(long, value_name = "PATH")]
+    pub signing_key: String,
+    /// A setting of the team's policy, e.g. cipher=chacha20poly1305; repeatable
+    #[arg(long, value_name = "SETTING=VALUE")]
+    pub policy: Vec<String>,
+    /// Where to write the bundle
+    #[arg(short, long, value_name = "PATH")]
+    pub out: String,
+}
+
+#[derive(Args)]
+pub struct BundleImportArgs {
+    #[arg(value_name = "BUNDLE")]
+    pub bundle: String,
+    /// Your X25519 identity, which must be one of the team's members
+    #[arg(long, value_name = "PATH")]
+    pub identity: String,
+    /// The public key of a signer whose bundles you trust; repeatable
+    #[arg(long = "issuer", value_name = "PUBKEY", required = true)]
+    pub issuers: Vec<String>,
+    /// Replace a team of the same name that is already installed
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Subcommand)]
+pub enum KeyringCommand {
+    /// Ask for a password and store it under a name
+    Set { name: String },
+    /// Delete the password stored under a name
+    Delete { name: String },
+}
+
+#[derive(Subcommand)]
+pub enum KeyCommand {
+    /// Print a key file or identity as 24 words to write down
+    Export(KeyExportArgs),
+    /// Write a key file back from its 24 words, typed at the prompt or piped to stdin
+    Import(KeyImportArgs),
+}
+
+#[derive(Args)]
+pub struct KeyExportArgs {
+    /// Print the key as words of the BIP39 English list, with a checksum
+    #[arg(long, required = true)]
+    pub mnemonic: bool,
+    /// The key file, from `keygen` or `keygen --asymmetric`
+    #[arg(value_name = "KEYFILE")]
+    pub key: String,
+}
+
+#[derive(Args)]
+pub struct KeyImportArgs {
+    /// Read the key as the words printed by `key export --mnemonic` or `keygen --mnemonic`
+    #[arg(long, required = true)]
+    pub mnemonic: bool,
+    /// Where to write the key; an existing file is never overwritten
+    #[arg(short, long, value_name = "PATH")]
+    pub out: String,
+    /// The key is an X25519 identity: also write its public key to <out>.pub
+    #[arg(long)]
+    pub asymmetric: bool,
+    /// How to write the key: raw, hex or base64
+    #[arg(long, value_name = "FORMAT", default_value = "raw", value_parser = parse_key_format)]
+    pub format: keyfile::Format,
+}
+
+#[derive(Subcommand)]
+pub enum StatsCommand {
+    /// Start recording the counts, sizes and error codes of every batch of files, with no paths or names
+    Enable,
+    /// Stop recording, and delete what was recorded
+    Disable,
+    /// Summarize what was recorded per day and per command, offline
+    Local {
+        /// Only the last DAYS days, today included
+        #[arg(long, value_name = "DAYS", value_parser = clap::value_parser!(u64).range(1..))]
+        days: Option<u64>,
+        /// Print the summary as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommand {
+    /// Check the MAC of every entry and that each follows the one before it
+    Verify {
+        #[arg(value_name = "LOG")]
+        log: PathBuf,
+        /// The key the log was written with [default: audit.key in the config directory]
+        #[arg(long, value_name = "PATH")]
+        audit_key: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HeaderCommand {
+    /// Print the header of a file as JSON
+    Export {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+    /// Put the header from a JSON file in front of a file's ciphertext
+    Graft {
+        #[arg(value_name = "HEADER_JSON")]
+        json: PathBuf,
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Where to write the result
+        #[arg(short, long, value_name = "PATH")]
+        out: PathBuf,
+    },
+}
+
+#[derive(Args)]
+pub struct RecoverArgs {
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+    /// Where to write the repaired file
+    #[arg(short, long, value_name = "PATH")]
+    pub out: PathBuf,
+    /// Header fields to assume when no backup survives, e.g. cipher=aes256gcm,kdf-memory=19456
+    #[arg(long, value_name = "FIELD=VALUE,...")]
+    pub assume: Option<String>,
+}
+
+#[derive(Args)]
+#[command(
+    after_help = "SECRET is given like the secrets of `rekey`, e.g. file:pass.txt or keyfile:backup.key. Without --try, doctor asks for passwords to try when it runs on a terminal."
+)]
+pub struct DoctorArgs {
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+    /// A password or key that may open the file, tried in turn (repeatable)
+    #[arg(long = "try", value_name = "SECRET", value_parser = parse_tried_secret)]
+    pub tries: Vec<TriedSecret>,
+}
+
+// A secret given to `doctor --try`, with the text it was given as, to repeat in the commands doctor suggests
+#[derive(Clone)]
+pub struct TriedSecret {
+    pub spec: String,
+    pub source: SecretSource,
+}
+
+#[derive(Args)]
+pub struct ResumeJournalArgs {
+    #[arg(value_name = "JOURNAL")]
+    journal: PathBuf,
+    /// Undo the batch instead: restore every original and remove the outputs
+    #[arg(long)]
+    rollback: bool,
+    #[command(flatten)]
+    secret: SecretArgs,
+    #[command(flatten)]
+    settings: EncryptSettings,
+    #[command(flatten)]
+    retry: RetryArgs,
+}
+
+#[derive(Args)]
+pub struct WatchArgs {
+    /// The directory to watch, with its subdirectories
+    #[arg(value_name = "DIR")]
+    dir: PathBuf,
+    /// The directory the encrypted files are written into, in the same tree as the watched one
+    #[arg(short, long, value_name = "DIR")]
+    out: PathBuf,
+    /// How long a file must go unchanged before it is encrypted, e.g. 500ms or 2s
+    #[arg(long, value_name = "TIME", default_value = "500ms", value_parser = parse_duration)]
+    debounce: Duration,
+    /// Leave out files whose path, or any directory or name in it, matches this glob pattern, e.g. '*.tmp' or
+    /// .git; repeatable
+    #[arg(long, value_name = "PATTERN", value_parser = parse_pattern)]
+    exclude: Vec<glob::Pattern>,
+    #[command(flatten)]
+    secret: SecretArgs,
+    #[command(flatten)]
+    settings: EncryptSettings,
+    #[command(flatten)]
+    retry: RetryArgs,
+}
+
+#[derive(Args)]
+pub struct MountArgs {
+    /// The archive to mount
+    #[arg(value_name = "ARCHIVE")]
+    archive: PathBuf,
+    /// The empty directory to mount it on
+    #[arg(value_name = "MOUNTPOINT")]
+    mountpoint: PathBuf,
+    #[command(flatten)]
+    secret: SecretArgs,
+}
+
+#[derive(Args)]
+#[command(
+    after_help = "Set it up with `git config filter.encryptor.clean 'encryptor git-filter clean %f --passfile PATH'`, the same with smudge, `git config filter.encryptor.required true`, and `secrets/** filter=encryptor` in .gitattributes."
+)]
+pub struct GitFilterArgs {
+    /// clean encrypts a file for the repository, smudge decrypts it for the working tree
+    #[arg(value_name = "MODE", value_enum)]
+    mode: crate::git_filter::Mode,
+    /// The file's path in the repository (git's %f), which clean derives the file's salt from
+    #[arg(value_name = "PATH", default_value = "")]
+    path: PathBuf,
+    #[command(flatten)]
+    secret: SecretArgs,
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Measure the compression codecs at a few levels each, instead of the performance benchmarks
+    #[arg(long, conflicts_with_all = ["save", "compare", "threshold"])]
+    pub compression: bool,
+    /// The file to measure the codecs on [default: generated text]
+    #[arg(value_name = "FILE", requires = "compression")]
+    pub file: Option<String>,
+    /// Save the measurements to this file, as a baseline for --compare
+    #[arg(long, value_name = "BASELINE")]
+    pub save: Option<String>,
+    /// Compare the measurements with a baseline saved by --save, failing if any got slower than the threshold
+    #[arg(long, value_name = "BASELINE")]
+    pub compare: Option<String>,
+    /// How much slower than the baseline counts as a regression [default: the baseline's, or 10]
+    #[arg(long, value_name = "PERCENT", value_parser = parse_threshold)]
+    pub threshold: Option<f64>,
+}
+
+// Where the password or keys come from. Only one source may be given, except that --recipient may be repeated
+// and combined with a password.
+#[derive(Args)]
+struct SecretArgs {
+    /// Read the password from a file (one trailing newline is ignored)
+    #[arg(
+        long,
+        alias = "pass-file",
+        value_name = "PATH",
+        group = "password_source"
+    )]
+    passfile: Option<String>,
+    /// Read the password from an environment variable
+    #[arg(long, value_name = "VAR", group = "password_source")]
+    pass_env: Option<String>,
+    /// Read the password from an inherited file descriptor until it is closed (Unix only)
+    #[arg(long, value_name = "FD", group = "password_source")]
+    pass_fd: Option<i32>,
+    /// Read the password stored under this name in the platform keychain
+    #[arg(long, value_name = "NAME", group = "password_source")]
+    use_keyring: Option<String>,
+    /// Run this command (e.g. `op read op://vault/item/password`) and use the first line it prints as the password
+    #[arg(long, value_name = "COMMAND", group = "password_source")]
+    password_cmd: Option<String>,
+    /// Deprecated: the password itself, which other users can see through ps
+    #[arg(long, value_name = "PASSWORD", group = "password_source")]
+    password: Option<String>,
+    /// Use a random 256-bit key from this file instead of a password
+    #[arg(long, alias = "key-file", value_name = "PATH", conflicts_with_all = ["password_source", "identity", "recipients", "ssh_recipients"])]
+    keyfile: Option<String>,
+    /// Decrypt with an X25519 identity from `keygen --asymmetric`, or an SSH Ed25519 private key like ~/.ssh/id_ed25519
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["password_source", "recipients", "ssh_recipients"])]
+    identity: Option<String>,
+    /// Use the key and policy of a team installed with `bundle import`, unlocked with --identity
+    #[arg(long, value_name = "NAME", requires = "identity", conflicts_with_all = ["password_source", "keyfile", "recipients", "ssh_recipients"])]
+    team: Option<String>,
+    /// Encrypt to an X25519 public key (hex, base64 or a .pub file); repeatable
+    #[arg(long = "recipient", value_name = "PUBKEY")]
+    recipients: Vec<String>,
+    /// Encrypt to an SSH Ed25519 public key (a .pub file like ~/.ssh/id_ed25519.pub, or the key itself); repeatable
+    #[arg(long = "recipient-ssh", value_name = "PUBKEY")]
+    ssh_recipients: Vec<String>,
+    /// Use the credential from `keygen --fido2` on a FIDO2 security key instead of a password, which must be plugged in and touched
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["password_source", "keyfile", "identity", "team", "recipients", "ssh_recipients"])]
+    fido2: Option<String>,
+}
+
+// A password source or key file named by a positional argument of `rekey`, see parse_secret_source
+#[derive(Clone)]
+pub enum SecretSource {
+    Password(Source),
+    Keyfile(String),
+}
+
+// X.509 certificates, for CMS files only
+#[derive(Args)]
+struct CertificateArgs {
+    /// Encrypt a CMS file to this certificate (repeatable), or decrypt one with it and --private-key
+    #[arg(long = "recipient-cert", value_name = "CERT", conflicts_with_all = ["password_source", "keyfile", "identity", "recipients", "ssh_recipients"])]
+    certificates: Vec<String>,
+    /// The private key of the --recipient-cert to decrypt a CMS file with
+    #[arg(long, value_name = "KEY", conflicts_with_all = ["password_source", "keyfile", "identity", "recipients", "ssh_recipients"])]
+    private_key: Option<String>,
+}
+
+// The cipher and the Argon2id cost of new files. Decryption reads them from the header.
+#[derive(Args)]
+struct KdfArgs {
+    /// The AEAD algorithm: aes256gcm, chacha20poly1305, aes256gcmsiv or aes128gcm (also written aes128-gcm)
+    #[arg(long, value_name = "CIPHER", default_value = "aes256gcm", value_parser = parse_algorithm)]
+    cipher: Algorithm,
+    /// Argon2id memory cost in KiB
+    #[arg(long, value_name = "KiB", default_value_t = KdfParams::default().memory)]
+    kdf_memory: u32,
+    /// Argon2id iteration count
+    #[arg(long, value_name = "N", default_value_t = KdfParams::default().iterations)]
+    kdf_iterations: u32,
+    /// Pick the iterations that make unlocking take about this long on this machine, e.g. 500ms or 2s
+    #[arg(long, value_name = "TIME", value_parser = parse_duration)]
+    kdf_target: Option<Duration>,
+}
+
+// How new files are written
+#[derive(Args)]
+struct EncryptSettings {
+    #[command(flatten)]
+    kdf: KdfArgs,
+    /// Compress before encrypting: zstd alone, a zstd level, or none|zstd|lz4|brotli[:<level>]
+    #[arg(long, value_name = "CODEC", num_args = 0..=1, require_equals = true, default_missing_value = "zstd", value_parser = parse_compression)]
+    compress: Option<Compression>,
+    /// Compress with this zstd dictionary from `train-dict` (implies --compress=zstd)
+    #[arg(long, value_name = "DICT")]
+    dict: Option<String>,
+    /// Don't record the file name, length and time of encryption in the header
+    #[arg(long)]
+    no_bind_metadata: bool,
+    /// Don't keep a copy of the header at the end of the file
+    #[arg(long)]
+    no_header_backup: bool,
+    /// Record a BLAKE3 checksum of each file's plaintext in its header, checked again after decrypting
+    #[arg(long)]
+    checksum: bool,
+    /// Pad each file's contents before sealing them, so its size gives less away: padme (at most 12% more) or
+    /// block:<SIZE>, a multiple of SIZE bytes, e.g. block:64K
+    #[arg(long, value_name = "SCHEME", value_parser = parse_padding)]
+    pad: Option<Padding>,
+    /// Seal each file with its own random data key, wrapped with the password or key, so that `rekey` only has
+    /// to rewrite the header
+    #[arg(long)]
+    wrap_key: bool,
+    /// Record this ID of the password or key in each file, e.g. 2026-q3, so `rotate` can find the files under it
+    #[arg(long, value_name = "ID", value_parser = parse_key_id)]
+    key_id: Option<String>,
+    /// The day the password was set, e.g. 2026-03-01, recorded in each file for --max-password-age to check
+    /// [default: not recorded]
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    password_set: Option<u64>,
+    /// Stream files of at least this size in chunks instead of reading them whole, e.g. 64M (0 streams every file)
+    #[arg(long, value_name = "SIZE", default_value = "8M", value_parser = parse_size)]
+    stream_threshold: u64,
+    /// The size of each chunk of a streamed file, e.g. 256K
+    #[arg(long, value_name = "SIZE", default_value = "1M", value_parser = parse_chunk_size)]
+    chunk_size: u32,
+    /// Write ASCII armor (same as --encoding armor)
+    #[arg(long, conflicts_with = "encoding")]
+    armor: bool,
+    /// raw, armor, armored-split[:<lines>] or json
+    #[arg(long, value_name = "ENCODING", value_parser = encoding::from_name)]
+    encoding: Option<Arc<dyn Encoder>>,
+}
+
+// Running over many files
+#[derive(Args)]
+struct BatchArgs {
+    /// How many threads to use: files processed at once, then threads sealing the chunks of each [default: one per CPU]
+    #[arg(long, value_name = "N", value_parser = parse_jobs)]
+    jobs: Option<usize>,
+    /// Write newline-delimited JSON progress events to this file descriptor (Unix only)
+    #[arg(long, value_name = "FD")]
+    progress_fd: Option<i32>,
+    /// Print one JSON object per file on stdout instead of the status lines
+    #[arg(long)]
+    json: bool,
+    /// Write a summary of the run to PATH when it finishes: JSON for a .json path, otherwise an HTML page
+    #[arg(long, value_name = "PATH")]
+    report: Option<String>,
+    /// Append each file's operation, paths, key ID and result to the tamper-evident log at PATH
+    #[arg(long, value_name = "PATH")]
+    audit_log: Option<String>,
+    /// The key that chains the --audit-log entries [default: audit.key in the config directory, created on first use]
+    #[arg(long, value_name = "PATH", requires = "audit_log")]
+    audit_key: Option<String>,
+    /// Read the files through a memory map, for multi-gigabyte local files
+    #[arg(long)]
+    mmap: bool,
+    /// How much of each file to read or write at once, e.g. 16M for a network filesystem (0 turns buffering off)
+    #[arg(long, value_name = "SIZE", default_value = "4M", value_parser = parse_io_buffer)]
+    io_buffer: usize,
+    #[command(flatten)]
+    retry: RetryArgs,
+}
+
+#[derive(Args)]
+struct RetryArgs {
+    /// Retry reads and writes up to this many times after a transient I/O error
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+    /// The longest wait before the first retry, doubled for every further one
+    #[arg(long, value_name = "TIME", default_value = "1s", value_parser = parse_duration)]
+    retry_backoff: Duration,
+}
+
+// What is left out of the directory trees walked
+#[derive(Args)]
+struct FilterArgs {
+    /// Leave out what matches this gitignore-style glob under the directories, e.g. '*.o' or 'target/**'; repeatable
+    #[arg(long, value_name = "PATTERN", value_parser = parse_filter_pattern)]
+    exclude: Vec<Rule>,
+    /// Only take the files under the directories that match one of these globs, e.g. '*.pdf'; repeatable
+    #[arg(long, value_name = "PATTERN", value_parser = parse_filter_pattern)]
+    include: Vec<Rule>,
+    /// Also leave out what the .gitignore files in the directories ignore, and .git itself
+    #[arg(long)]
+    respect_gitignore: bool,
+}
+
+// Where the outputs of encrypt and decrypt go
+#[derive(Args)]
+struct OutputArgs {
+    /// Replace each file with its own output
+    #[arg(long, conflicts_with = "out")]
+    in_place: bool,
+    /// Write the output to this path, or into this directory with several files or a trailing /
+    #[arg(short, long, value_name = "PATH")]
+    out: Option<PathBuf>,
+    /// Replace outputs that already exist, which are otherwise left alone and the file fails
+    #[arg(long)]
+    force: bool,
+    /// Write to `name (1).ext` and so on when an output already exists, instead of failing
+    #[arg(long, conflicts_with_all = ["force", "in_place"])]
+    auto_rename: bool,
+    /// Keep a streamed output that fails midway as <output>.partial, and continue one left by an earlier run
+    #[arg(long)]
+    resume: bool,
+    /// Print what would be written where, checking the files, outputs and free space, without doing any of it
+    #[arg(long, conflicts_with_all = ["json", "report"])]
+    dry_run: bool,
+    /// Start without first checking that the outputs fit in the free space of their filesystems
+    #[arg(long, conflicts_with = "dry_run")]
+    no_preflight: bool,
+}
+
+// The requests made for http(s):// paths
+#[derive(Args)]
+struct HttpArgs {
+    /// Send this header with each request of an http(s):// path, e.g. 'Authorization: Bearer <token>'; repeatable
+    #[arg(long = "http-header", value_name = "NAME: VALUE", value_parser = parse_http_header)]
+    http_headers: Vec<(String, String)>,
+}
+
+// A file command for the pipeline in main: its name, its files and its settings
+pub struct FileCommand {
+    pub name: &'static str,
+    pub files: Vec<PathBuf>,
+    pub options: Options,
+}
+
+impl Command {
+    // The pipeline settings of the commands that process files, or the command itself for the others
+    pub fn into_file_command(self) -> Result<FileCommand, Box<Command>> {
+        let mut options = Options::default();
+        let (name, files) = match self {
+            Command::Encrypt(args) => {
+                args.secret.apply(&mut options);
+                args.certificates.apply(&mut options);
+                args.settings.apply(&mut options);
+                args.batch.apply(&mut options);
+                args.output.apply(&mut options);
+                args.filter.apply(&mut options);
+                args.http.apply(&mut options);
+                options.http_method = args.http_method.to_uppercase();
+                options.storage_class = args.storage_class;
+                options.format = args.format;
+                options.pbkdf2_iterations = args.pbkdf2_iterations;
+                options.encrypt_names = args.encrypt_names;
+                options.name_by_hash = args.name_by_hash;
+                options.shred = args.shred;
+                options.remove_to_trash = args.remove_to_trash;
+                options.journal = args.journal;
+                options.archive = args.archive;
+                options.symlinks = match (args.follow_symlinks, args.preserve_symlinks) {
+                    (true, _) => archive::Symlinks::Follow,
+                    (_, true) => archive::Symlinks::Preserve,
+                    _ => archive::Symlinks::Skip,
+                };
+                options.ads = args.ads;
+                options.manifest = args.manifest;
+                options.min_strength = args.min_strength;
+                options.split_size = args.split_size;
+                options.shares = args.shares.zip(args.threshold);
+                options.share_out = args.share_out;
+                options.recovery_code = args.recovery_code;
+                options.incremental = args.state;
+                options.delete = args.delete;
+                ("encrypt", args.files)
+            }
+            Command::Decrypt(args) => {
+                args.secret.apply(&mut options);
+                args.certificates.apply(&mut options);
+                args.batch.apply(&mut options);
+                args.output.apply(&mut options);
+                args.http.apply(&mut options);
+                options.dict_path = args.dict;
+                options.pbkdf2_iterations = args.pbkdf2_iterations;
+                options.shred = args.shred;
+                options.extract = args.extract;
+                options.grant = args.grant;
+                options.grant_issuers = args.grant_issuers;
+                options.grant_log = args.grant_log;
+                options.range = args.range;
+                options.keep_going = args.keep_going;
+                options.legacy_nonce = args.nonce;
+                options.share_files = args.share_files;
+                options.recovery_code = args.recovery_code;
+                options.password_attempts = args.password_attempts;
+                options.try_typos = args.try_typos;
+                options.max_password_age = args.max_password_age;
+                ("decrypt", args.files)
+            }
+            Command::EncryptText(args) => {
+                args.secret.apply(&mut options);
+                args.kdf.apply(&mut options);
+                let qr = match (args.qr, args.qr_out) {
+                    (_, Some(path)) => Some(qr::Target::Png(path)),
+                    (true, None) => Some(qr::Target::Terminal),
+                    (false, None) => None,
+                };
+                options.text = Some(Text {
+                    argument: args.text,
+                    clipboard: args.clipboard,
+                    qr,
+                });
+                ("encrypt-text", Vec::new())
+            }
+            Command::DecryptText(args) => {
+                args.secret.apply(&mut options);
+                options.text = Some(Text {
+                    argument: args.armor,
+                    clipboard: args.clipboard,
+                    qr: None,
+                });
+                ("decrypt-text", Vec::new())
+            }
+            Command::Daemon(args) => {
+                args.secret.apply(&mut options);
+                args.kdf.apply(&mut options);
+                options.socket = args.socket;
+                options.ttl = args.ttl;
+                ("daemon", Vec::new())
+            }
+            Command::Vault { command } => {
+                let (name, vault, action) = match command {
+                    VaultCommand::Init(args) => {
+                        args.kdf.apply(&mut options);
+                        let action = Action::Init { force: args.force };
+                        ("vault init", args.vault, action)
+                    }
+                    VaultCommand::Set(args) => {
+                        let action = Action::Set {
+                            name: args.name,
+                            value: args.value,
+                        };
+                        ("vault set", args.vault, action)
+                    }
+                    VaultCommand::Get(args) => {
+                        ("vault get", args.vault, Action::Get { name: args.name })
+                    }
+                    VaultCommand::List(args) => ("vault list", args, Action::List),
+                    VaultCommand::Rm(args) => {
+                        ("vault rm", args.vault, Action::Remove { name: args.name })
+                    }
+                };
+                vault.secret.apply(&mut options);
+                options.vault = Some(Vault {
+                    path: vault.vault,
+                    action,
+                });
+                (name, Vec::new())
+            }
+            Command::Verify(args) => {
+                args.secret.apply(&mut options);
+                args.batch.apply(&mut options);
+                args.http.apply(&mut options);
+                options.dict_path = args.dict;
+                options.in_place = args.in_place;
+                options.manifest = args.manifest;
+                options.checksum_only = args.checksum_only;
+                options.max_password_age = args.max_password_age;
+                ("verify", args.files)
+            }
+            Command::Rekey(args) => {
+                args.old.apply(&mut options.password, &mut options.keyfile);
+                args.new
+                    .apply(&mut options.new_password, &mut options.new_keyfile);
+                args.batch.apply(&mut options);
+                options.wrap_keys = args.wrap_key;
+                options.key_id = args.key_id;
+                ("rekey", args.files)
+            }
+            Command::Rotate(args) => {
+                args.old.apply(&mut options.password, &mut options.keyfile);
+                args.new
+                    .apply(&mut options.new_password, &mut options.new_keyfile);
+                args.batch.apply(&mut options);
+                args.filter.apply(&mut options);
+                options.wrap_keys = args.wrap_key;
+                options.key_id = Some(args.key_id);
+                options.rotate_from = args.from;
+                ("rotate", args.paths)
+            }
+            Command::Migrate(args) => {
+                args.secret.apply(&mut options);
+                args.batch.apply(&mut options);
+                options.legacy_nonce = Some(args.nonce);
+                ("migrate", args.files)
+            }
+            Command::Grant(args) => {
+                args.secret.apply(&mut options);
+                options.grant_to = Some(args.grant_to);
+                options.signing_key = Some(args.signing_key);
+                options.expires = Some(args.expires);
+                options.purpose = Some(args.purpose);
+                options.out = Some(args.out);
+                ("grant", args.files)
+            }
+            Command::TrainDict(args) => {
+                args.secret.apply(&mut options);
+                args.kdf.apply(&mut options);
+                options.dict_size = args.dict_size;
+                let mut files = vec![args.dict];
+                files.extend(args.samples);
+                ("train-dict", files)
+            }
+            // The outputs a crashed run left behind are written again
+            Command::ResumeJournal(args) => {
+                args.secret.apply(&mut options);
+                args.settings.apply(&mut options);
+                args.retry.apply(&mut options);
+                options.rollback = args.rollback;
+                options.force = true;
+                ("resume-journal", vec![args.journal])
+            }
+            // A changed file replaces the output of its earlier version
+            Command::Watch(args) => {
+                args.secret.apply(&mut options);
+                args.settings.apply(&mut options);
+                args.retry.apply(&mut options);
+                options.out = Some(args.out);
+                options.force = true;
+                options.debounce = args.debounce;
+                options.excludes = args.exclude;
+                ("watch", vec![args.dir])
+            }
+            Command::Mount(args) => {
+                args.secret.apply(&mut options);
+                options.out = Some(args.mountpoint);
+                ("mount", vec![args.archive])
+            }
+            // Clean seals with AES-256-GCM-SIV, since equal contents get equal nonces
+            Command::GitFilter(args) => {
+                args.secret.apply(&mut options);
+                options.algorithm = Algorithm::Aes256GcmSiv;
+                options.git_filter = Some(args.mode);
+                ("git-filter", vec![args.path])
+            }
+            Command::Report {
+                command: ReportCommand::StaleKeys(args),
+            } => {
+                args.secret.apply(&mut options);
+                args.filter.apply(&mut options);
+                options.max_password_age = args.max_password_age;
+                ("report stale-keys", args.paths)
+            }
+            command => return Err(Box::new(command)),
+        };
+        Ok(FileCommand {
+            name,
+            files,
+            options,
+        })
+    }
+}
+
+impl SecretArgs {
+    fn apply(self, options: &mut Options) {
+        options.password = [
+            self.passfile.map(Source::File),
+            self.pass_env.map(Source::Env),
+            self.pass_fd.map(Source::Fd),
+            self.use_keyring.map(Source::Keyring),
+            self.password_cmd.map(Source::Command),
+            self.password.map(Source::Argv),
+        ]
+        .into_iter()
+        .flatten()
+        .next();
+        options.keyfile = self.keyfile;
+        options.identity = self.identity;
+        options.team = self.team;
+        options.recipients = self.recipients;
+        options.ssh_recipients = self.ssh_recipients;
+        options.fido2 = self.fido2;
+    }
+}
+
+impl SecretSource {
+    fn apply(self, password: &mut Option<Source>, keyfile: &mut Option<String>) {
+        match self {
+            SecretSource::Password(source) => *password = Some(source),
+            SecretSource::Keyfile(path) => *keyfile = Some(path),
+        }
+    }
+}
+
+impl CertificateArgs {
+    fn apply(self, options: &mut Options) {
+        options.certificates = self.certificates;
+        options.private_key = self.private_key;
+    }
+}
+
+impl KdfArgs {
+    fn apply(self, options: &mut Options) {
+        options.algorithm = self.cipher;
+        options.kdf_params = KdfParams {
+            memory: self.kdf_memory,
+            iterations: self.kdf_iterations,
+        };
+        options.kdf_target = self.kdf_target;
+    }
+}
+
+impl EncryptSettings {
+    fn apply(self, options: &mut Options) {
+        self.kdf.apply(options);
+        if let Some(compression) = self.compress {
+            options.compression = compression;
+        }
+        options.dict_path = self.dict;
+        options.bind_metadata = !self.no_bind_metadata;
+        options.header_backup = !self.no_header_backup;
+        options.checksum = self.checksum;
+        options.padding = self.pad;
+        options.wrap_keys = self.wrap_key;
+        options.key_id = self.key_id;
+        options.password_set = self.password_set;
+        options.stream_threshold = self.stream_threshold;
+        options.chunk_size = self.chunk_size;
+        if self.armor {
+            options.encoder = Arc::new(encoding::Armor);
+        }
+        if let Some(encoder) = self.encoding {
+            options.encoder = encoder;
+        }
+    }
+}
+
+impl BatchArgs {
+    fn apply(self, options: &mut Options) {
+        options.jobs = self.jobs;
+        options.progress_fd = self.progress_fd;
+        options.json = self.json;
+        options.report = self.report;
+        options.audit_log = self.audit_log;
+        options.audit_key = self.audit_key;
+        options.mmap = self.mmap;
+        options.io_buffer = self.io_buffer;
+        self.retry.apply(options);
+    }
+}
+
+impl RetryArgs {
+    fn apply(self, options: &mut Options) {
+        options.retry.retries = self.retries;
+        options.retry.backoff = self.retry_backoff;
+    }
+}
+
+impl FilterArgs {
+    fn apply(self, options: &mut Options) {
+        options.filter = PathFilter {
+            excludes: self.exclude,
+            includes: self.include,
+            gitignore: self.respect_gitignore,
+        };
+    }
+}
+
+impl HttpArgs {
+    fn apply(self, options: &mut Options) {
+        options.http_headers = self.http_headers;
+    }
+}
+
+impl OutputArgs {
+    fn apply(self, options: &mut Options) {
+        options.in_place = self.in_place;
+        options.out = self.out;
+        options.force = self.force;
+        options.auto_rename = self.auto_rename;
+        options.resume = self.resume;
+        options.dry_run = self.dry_run;
+        options.no_preflight = self.no_preflight;
+    }
+}
+
+// Rewrite the positional form of the first releases, `encryptor encrypt|decrypt <password> <file> <nonce>` with the
+// nonce as a JSON array of 12 bytes, printing a deprecation notice as one line of JSON on stderr. `encrypt` becomes
+// `encryptor encrypt --password <password> <file>`: the nonce is dropped, since every output now gets a random one
+// recorded in its header. `decrypt` becomes `encryptor decrypt --password <password> --legacy --nonce <nonce> <file>`
+// for the files of those releases, which have no header, and `encryptor decrypt --password <password> <file>` for
+// the ones the same script encrypted since. Any other command line is returned as it is.
+pub fn legacy_shim(args: Vec<OsString>) -> Vec<OsString> {
+    let strings: Option<Vec<&str>> = args.iter().map(|arg| arg.to_str()).collect();
+    let Some([program, command @ ("encrypt" | "decrypt"), password, file, nonce]) =
+        strings.as_deref()
+    else {
+        return args;
+    };
+    if parse_legacy_nonce(nonce).is_err() || password.starts_with('-') || file.starts_with('-') {
+        return args;
+    }
+
+    let (replacement, nonce_use, rewritten) = match *command {
+        "encrypt" => (
+            "encryptor encrypt --passfile <path> <file>",
+            "ignored: every output gets a random nonce, recorded in its header",
+            vec![*program, "encrypt", "--password", *password, *file],
+        ),
+        _ if has_header(Path::new(file)) => (
+            "encryptor decrypt --passfile <path> <file>",
+            "ignored: the file was encrypted since, with its nonce recorded in its header",
+            vec![*program, "decrypt", "--password", *password, *file],
+        ),
+        _ => (
+            "encryptor decrypt --legacy --nonce <nonce> --passfile <path> <file>",
+            "used to read the file, which has no header",
+            vec![
+                *program,
+                "decrypt",
+                "--password",
+                *password,
+                "--legacy",
+                "--nonce",
+                *nonce,
+                *file,
+            ],
+        ),
+    };
+    let notice = serde_json::json!({
+        "deprecated": format!("encryptor {} <password> <file> <nonce>", command),
+        "replacement": replacement,
+        "nonce": nonce_use,
+        "files": "the outputs of releases that took a nonce have no header: read them with decrypt --legacy --nonce <nonce>, or convert them with migrate --nonce <nonce>",
+    });
+    eprintln!("{}", notice);
+    rewritten.into_iter().map(OsString::from).collect()
+}
+
+// Whether the file at `path` starts with the header of the current format, rather than being the bare ciphertext of
+// the first releases
+fn has_header(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|_| &magic == encryptor::header::MAGIC)
+}
+
+// Exit with a usage error of the subcommand `name`, for a combination of flags that clap can't check on its own
+pub fn usage_error(name: &str, message: &str) -> ! {
+    let mut command = Cli::command();
+    command.build();
+    match command.find_subcommand_mut(name) {
+        Some(subcommand) => subcommand.error(ErrorKind::ArgumentConflict, message),
+        None => command.error(ErrorKind::ArgumentConflict, message),
+    }
+    .exit()
+}
+
+// The nonce of a file from the first releases, given as they took it: a JSON array of 12 bytes
+fn parse_legacy_nonce(value: &str) -> Result<[u8; 12], String> {
+    serde_json::from_str::<Vec<u8>>(value)
+        .ok()
+        .and_then(|nonce| nonce.try_into().ok())
+        .ok_or_else(|| "expected a JSON array of 12 bytes, e.g. \"[246, 231, 118, 136, 232, 16, 173, 214, 11, 241, 220, 114]\"".to_string())
+}
+
+fn parse_color(value: &str) -> Result<ColorChoice, String> {
+    ColorChoice::parse(value).ok_or_else(|| "expected one of: auto, always, never".to_string())
+}
+
+pub fn parse_algorithm(value: &str) -> Result<Algorithm, String> {
+    Algorithm::from_name(value).ok_or_else(|| format!("expected one of: {}", Algorithm::names()))
+}
+
+// A key ID is recorded with a u8 length, and shown by `info`
+fn parse_key_id(value: &str) -> Result<String, String> {
+    match value.len() {
+        1..=255 if !value.chars().any(char::is_control) => Ok(value.to_string()),
+        _ => Err("expected 1 to 255 bytes of text, such as 2026-q3".to_string()),
+    }
+}
+
+// A day as the seconds since the Unix epoch of its midnight in UTC, from its year, month and day (Howard Hinnant's
+// days_from_civil, the inverse of units::utc)
+pub fn parse_date(value: &str) -> Result<u64, String> {
+    let error = || "expected a date such as 2026-03-01".to_string();
+    let parts: Vec<&str> = value.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return Err(error());
+    };
+    let (Ok(year), Ok(month), Ok(day)) = (
+        year.parse::<i64>(),
+        month.parse::<i64>(),
+        day.parse::<i64>(),
+    ) else {
+        return Err(error());
+    };
+    if !(1970..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(error());
+    }
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    Ok(days as u64 * 86400)
+}
+
+// The name of a secret in the vault, printed one per line by `vault list`
+fn parse_vault_name(value: &str) -> Result<String, String> {
+    match value.is_empty() || value.chars().any(char::is_control) {
+        false => Ok(value.to_string()),
+        true => Err("expected a name without line breaks, such as github-token".to_string()),
+    }
+}
+
+fn parse_key_format(value: &str) -> Result<keyfile::Format, String> {
+    keyfile::Format::from_name(value).ok_or_else(|| "expected one of: raw, hex, base64".to_string())
+}
+
+// Parse a secret of `rekey`: `prompt`, or a kind and its value such as `file:old.txt`, `env:NEW_PASSWORD`, `fd:3`,
+// `keyring:backups`, `cmd:pass show backups` or `keyfile:backup.key`
+fn parse_secret_source(value: &str) -> Result<SecretSource, String> {
+    let password = |source| Ok(SecretSource::Password(source));
+    match value.split_once(':') {
+        None if value == "prompt" => password(Source::Prompt),
+        Some(("file", path)) => password(Source::File(path.to_string())),
+        Some(("env", name)) => password(Source::Env(name.to_string())),
+        Some(("fd", fd)) => match fd.parse() {
+            Ok(fd) => password(Source::Fd(fd)),
+            Err(_) => Err("expected fd:<number>".to_string()),
+        },
+        Some(("keyring", name)) => password(Source::Keyring(name.to_string())),
+        Some(("cmd", command)) => password(Source::Command(command.to_string())),
+        Some(("keyfile", path)) => Ok(SecretSource::Keyfile(path.to_string())),
+        _ => Err(
+            "expected prompt, file:PATH, env:VAR, fd:N, keyring:NAME, cmd:COMMAND or keyfile:PATH"
+                .to_string(),
+        ),
+    }
+}
+
+fn parse_tried_secret(value: &str) -> Result<TriedSecret, String> {
+    Ok(TriedSecret {
+        spec: value.to_string(),
+        source: parse_secret_source(value)?,
+    })
+}
+
+pub fn parse_compression(value: &str) -> Result<Compression, String> {
+    Compression::parse(value)
+}
+
+fn parse_threshold(value: &str) -> Result<f64, String> {
+    value
+        .trim_end_matches('%')
+        .parse()
+        .ok()
+        .filter(|percent: &f64| percent.is_finite() && *percent >= 0.0)
+        .ok_or_else(|| "expected a percentage such as 10 or 2.5".to_string())
+}
+
+fn parse_pattern(value: &str) -> Result<glob::Pattern, String> {
+    glob::Pattern::new(value).map_err(|err| err.to_string())
+}
+
+fn parse_filter_pattern(value: &str) -> Result<Rule, String> {
+    match value.starts_with('!') {
+        true => Err("a pattern can't be negated here; --include takes files back in".to_string()),
+        false => Rule::parse(value),
+    }
+}
+
+// Parse a header such as `Authorization: Bearer abc`
+fn parse_http_header(value: &str) -> Result<(String, String), String> {
+    let (name, header_value) = value
+        .split_once(':')
+        .ok_or_else(|| "expected NAME: VALUE, e.g. 'Authorization: Bearer abc'".to_string())?;
+    let name = name.trim();
+    let token = |byte: u8| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte);
+    if name.is_empty() || !name.bytes().all(token) {
+        return Err(format!("{:?} is not a header name", name));
+    }
+    Ok((name.to_string(), header_value.trim().to_string()))
+}
+
+fn parse_jobs(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(0) => Err("expected at least 1".to_string()),
+        Ok(jobs) => Ok(jobs),
+        Err(_) => Err("expected an integer".to_string()),
+    }
+}
+
+// Parse a size such as `4096`, `256K`, `1M` or `2G` (powers of 1024)
+fn parse_size(value: &str) -> Result<u64, String> {
+    let units = [("K", 10), ("M", 20), ("G", 30)];
+    let (number, shift) = units
+        .iter()
+        .find_map(|(suffix, shift)| Some((value.strip_suffix(suffix)?, *shift)))
+        .unwrap_or((value, 0));
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(1 << shift))
+        .ok_or_else(|| "expected a size such as 4096, 256K, 1M or 2G".to_string())
+}
+
+fn parse_chunk_size(value: &str) -> Result<u32, String> {
+    let size = parse_size(value)?;
+    match u32::try_from(size) {
+        Ok(0) => Err("expected at least 1 byte".to_string()),
+        Ok(size) => Ok(size),
+        Err(_) => Err("a chunk can be at most 4G - 1 bytes".to_string()),
+    }
+}
+
+// `padme`, or `block:` followed by a size such as 4096 or 64K
+fn parse_padding(value: &str) -> Result<Padding, String> {
+    let expected = || "expected padme or block:<size>, such as block:64K".to_string();
+    match value.split_once(':') {
+        Some(("block", size)) => match parse_size(size).map(u32::try_from) {
+            Ok(Ok(size @ 1..)) => Ok(Padding::Block(size)),
+            _ => Err(expected()),
+        },
+        _ => Padding::from_name(value).ok_or_else(expected),
+    }
+}
+
+// Parse a byte range such as `1G:4M`, or `1G:` for the rest from an offset
+fn parse_range(value: &str) -> Result<(u64, Option<u64>), String> {
+    let (offset, len) = value
+        .split_once(':')
+        .ok_or_else(|| "expected OFFSET:LEN, e.g. 1G:4M, or OFFSET: for the rest".to_string())?;
+    let len = match len {
+        "" => None,
+        len => Some(parse_size(len)?),
+    };
+    Ok((parse_size(offset)?, len))
+}
+
+fn parse_io_buffer(value: &str) -> Result<usize, String> {
+    match parse_size(value)? {
+        size if size > 1 << 30 => Err("a buffer can be at most 1G".to_string()),
+        size => Ok(size as usize),
+    }
+}
+
+// Parse an S3 storage class, such as STANDARD_IA, in any case. Other stores have classes of their own, so any name
+// of letters, digits and underscores goes.
+pub fn parse_storage_class(value: &str) -> Result<String, String> {
+    let class = value.to_ascii_uppercase();
+    match !class.is_empty() && class.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        true => Ok(class),
+        false => {
+            Err("expected a storage class such as STANDARD_IA, GLACIER or DEEP_ARCHIVE".to_string())
+        }
+    }
+}
+
+fn parse_split_size(value: &str) -> Result<u64, String> {
+    match parse_size(value)? {
+        size if size < 1 << 10 => Err("a volume must be at least 1K".to_string()),
+        size => Ok(size),
+    }
+}
+
+// Parse a duration such as `500ms`, `2s`, `1.5s`, `30m`, `8h` or `1d`. A bare number is taken as milliseconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let units = [
+        ("ms", 0.001),
+        ("s", 1.0),
+        ("m", 60.0),
+        ("h", 3600.0),
+        ("d", 86400.0),
+    ];
+    let (number, unit) = units
+        .iter()
+        .find_map(|(suffix, unit)| Some((value.strip_suffix(suffix)?, *unit)))
+        .unwrap_or((value, 0.001));
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite() && *number > 0.0)
+        .map(|number| Duration::from_secs_f64(number * unit))
+        .ok_or_else(|| "expected a duration such as 500ms, 2s or 8h".to_string())
+}
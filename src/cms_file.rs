@@ -0,0 +1,161 @@
+// Files in CMS EnvelopedData form (RFC 5652), for `encrypt --format cms` and decrypting CMS files.
+// They are encrypted to the X.509 certificates given with --recipient-cert, and decrypted with one certificate
+// and its --private-key, like `openssl cms -encrypt` and `-decrypt -recip <cert> -inkey <key>`. Needs the `cms`
+// cargo feature; without it CMS files are still detected, but encrypting or decrypting them fails with an explanation.
+#[cfg(feature = "cms")]
+use crate::streamed;
+use crate::streamed::Existing;
+use crate::Secret;
+use encryptor::EncryptError;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// How a CMS file starts: PEM, or a DER SEQUENCE whose first field is the envelopedData content type
+const PEM_LABELS: &[&[u8]] = &[b"-----BEGIN CMS-----", b"-----BEGIN PKCS7-----"];
+const ENVELOPED_DATA: &[u8] = &[
+    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x03,
+];
+
+// The certificates and private key from --recipient-cert and --private-key
+#[cfg(feature = "cms")]
+pub struct Keys {
+    certificates: Vec<encryptor::cms::Certificate>,
+    private_key: Option<encryptor::cms::PrivateKey>,
+}
+
+#[cfg(not(feature = "cms"))]
+pub struct Keys;
+
+// Whether the file at `path` is a CMS file rather than one of ours
+pub fn is_cms(path: &Path) -> bool {
+    let mut start = Vec::with_capacity(24);
+    let read = fs::File::open(path).and_then(|file| file.take(24).read_to_end(&mut start));
+    if read.is_err() {
+        return false;
+    }
+    // The length of the outer SEQUENCE takes 1 to 5 bytes
+    PEM_LABELS.iter().any(|label| start.starts_with(label))
+        || (start.first() == Some(&0x30)
+            && start
+                .windows(ENVELOPED_DATA.len())
+                .take(6)
+                .any(|window| window == ENVELOPED_DATA))
+}
+
+// Load the certificate and private key files, in PEM or DER form
+#[cfg(feature = "cms")]
+pub fn load(certificates: &[String], private_key: Option<&str>) -> Result<Keys, String> {
+    use encryptor::cms::{Certificate, PrivateKey};
+
+    let read = |path: &str| fs::read(path).map_err(|err| format!("Cannot read {}: {}", path, err));
+    let certificates = certificates
+        .iter()
+        .map(|path| {
+            Certificate::from_pem_or_der(&read(path)?)
+                .map_err(|err| format!("Cannot load the certificate {}: {}", path, err))
+        })
+        .collect::<Result<_, _>>()?;
+    let private_key = private_key
+        .map(|path| {
+            PrivateKey::from_pem_or_der(&read(path)?)
+                .map_err(|err| format!("Cannot load the private key {}: {}", path, err))
+        })
+        .transpose()?;
+    Ok(Keys {
+        certificates,
+        private_key,
+    })
+}
+
+// Encrypt the file at `file_path` into `<file_path>.p7m`, as PEM with `pem`, returning the path written
+#[cfg(feature = "cms")]
+pub fn encrypt(
+    file_path: &Path,
+    secret: &Secret,
+    pem: bool,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    use encryptor::cms;
+
+    let Secret::Certificates(keys) = secret else {
+        return Err(unsupported(
+            "CMS files are encrypted to certificates given with --recipient-cert",
+        ));
+    };
+    let plaintext = fs::read(file_path)?;
+    let mut output = cms::encrypt(&plaintext, &keys.certificates)?;
+    if pem {
+        output = cms::to_pem(&output)?;
+    }
+    let output_path = streamed::claim(encryptor::names::with_suffix(file_path, ".p7m"), existing)?;
+    streamed::write(&output[..], &output_path)?;
+    Ok(output_path)
+}
+
+// Decrypt the CMS file at `file_path` into the same path without its last extension, returning the path written
+#[cfg(feature = "cms")]
+pub fn decrypt(
+    file_path: &Path,
+    secret: &Secret,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    let Secret::Certificates(keys) = secret else {
+        return Err(unsupported(
+            "CMS files are decrypted with --recipient-cert and --private-key",
+        ));
+    };
+    let (certificates, Some(private_key)) = (&keys.certificates, &keys.private_key) else {
+        return Err(unsupported(
+            "CMS files are decrypted with --recipient-cert and --private-key",
+        ));
+    };
+    let [certificate] = &certificates[..] else {
+        return Err(unsupported(
+            "CMS files are decrypted with exactly one --recipient-cert, the one the private key belongs to",
+        ));
+    };
+    let plaintext = encryptor::cms::decrypt(&fs::read(file_path)?, certificate, private_key)?;
+
+    let output_path = encryptor::names::strip_extension(file_path).to_path_buf();
+    let output_path = streamed::claim(output_path, existing)?;
+    streamed::write(&plaintext[..], &output_path)?;
+    Ok(output_path)
+}
+
+#[cfg(feature = "cms")]
+fn unsupported(message: &str) -> EncryptError {
+    EncryptError::KeyModeMismatch(message.to_string())
+}
+
+#[cfg(not(feature = "cms"))]
+pub fn load(_certificates: &[String], _private_key: Option<&str>) -> Result<Keys, String> {
+    Err(unavailable().to_string())
+}
+
+#[cfg(not(feature = "cms"))]
+pub fn encrypt(
+    _file_path: &Path,
+    _secret: &Secret,
+    _pem: bool,
+    _existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    Err(EncryptError::IoError(unavailable()))
+}
+
+#[cfg(not(feature = "cms"))]
+pub fn decrypt(
+    _file_path: &Path,
+    _secret: &Secret,
+    _existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    Err(EncryptError::IoError(unavailable()))
+}
+
+#[cfg(not(feature = "cms"))]
+fn unavailable() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "CMS support is not built in (enable the 'cms' feature)",
+    )
+}
@@ -0,0 +1,320 @@
+//! Optional compression applied to the plaintext before it is encrypted.
+//!
+//! Encrypted data doesn't compress, so compression has to happen first. The codec is recorded in the file header
+//! and decryption decompresses automatically; the level only affects encryption and isn't stored.
+//! Each codec is behind a cargo feature of the same name (`zstd`, `lz4`, `brotli`, all part of `compression`).
+//!
+//! Small files compress poorly on their own because each one starts from scratch. For many similar small files
+//! (JSON documents, log fragments), a zstd [`Dictionary`] trained on samples of them gives much better ratios.
+//! A dictionary is made of fragments of the samples, so it should be stored encrypted like the files themselves.
+use std::io;
+use std::sync::Arc;
+
+/// A compression codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    None,
+    Zstd,
+    Lz4,
+    Brotli,
+}
+
+impl Codec {
+    /// The id stored in the header for this codec
+    pub fn id(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Zstd => 1,
+            Codec::Lz4 => 2,
+            Codec::Brotli => 3,
+        }
+    }
+
+    /// Look up a codec from its header id
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Codec::None),
+            1 => Some(Codec::Zstd),
+            2 => Some(Codec::Lz4),
+            3 => Some(Codec::Brotli),
+            _ => None,
+        }
+    }
+
+    /// The name used for this codec on the command line (--compress)
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::None => "none",
+            Codec::Zstd => "zstd",
+            Codec::Lz4 => "lz4",
+            Codec::Brotli => "brotli",
+        }
+    }
+
+    /// Look up a codec from its command line name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Codec::None),
+            "zstd" => Some(Codec::Zstd),
+            "lz4" => Some(Codec::Lz4),
+            "brotli" => Some(Codec::Brotli),
+            _ => None,
+        }
+    }
+
+    /// Every codec, in id order
+    pub fn all() -> [Codec; 4] {
+        [Codec::None, Codec::Zstd, Codec::Lz4, Codec::Brotli]
+    }
+
+    /// The levels this codec accepts, or None if it has no levels
+    pub fn levels(self) -> Option<std::ops::RangeInclusive<u32>> {
+        match self {
+            Codec::Zstd => Some(1..=22),
+            Codec::Brotli => Some(0..=11),
+            Codec::None | Codec::Lz4 => None,
+        }
+    }
+
+    /// The level used when none is given
+    pub fn default_level(self) -> u32 {
+        match self {
+            Codec::Zstd => 3,
+            Codec::Brotli => 6,
+            Codec::None | Codec::Lz4 => 0,
+        }
+    }
+
+    /// Whether this build includes the codec
+    pub fn is_available(self) -> bool {
+        match self {
+            Codec::None => true,
+            Codec::Zstd => cfg!(feature = "zstd"),
+            Codec::Lz4 => cfg!(feature = "lz4"),
+            Codec::Brotli => cfg!(feature = "brotli"),
+        }
+    }
+}
+
+/// A codec together with the level to compress at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    pub codec: Codec,
+    pub level: u32,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::none()
+    }
+}
+
+impl Compression {
+    /// No compression
+    pub fn none() -> Self {
+        Compression {
+            codec: Codec::None,
+            level: 0,
+        }
+    }
+
+    /// A codec at its default level
+    pub fn new(codec: Codec) -> Self {
+        Compression {
+            codec,
+            level: codec.default_level(),
+        }
+    }
+
+    /// Parse a `codec` or `codec:level` spec, e.g. `zstd:19`. A level alone, e.g. `19`, means zstd.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        if !spec.is_empty() && spec.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Compression::parse(&format!("zstd:{}", spec));
+        }
+        let (name, level) = match spec.split_once(':') {
+            Some((name, level)) => (name, Some(level)),
+            None => (spec, None),
+        };
+        let codec = Codec::from_name(name).ok_or_else(|| {
+            format!(
+                "unknown codec '{}' (expected none, zstd, lz4 or brotli)",
+                name
+            )
+        })?;
+        let Some(level) = level else {
+            return Ok(Compression::new(codec));
+        };
+
+        let range = codec
+            .levels()
+            .ok_or_else(|| format!("{} doesn't take a level", codec.name()))?;
+        let level = level
+            .parse()
+            .ok()
+            .filter(|level| range.contains(level))
+            .ok_or_else(|| {
+                format!(
+                    "{} level must be between {} and {}",
+                    codec.name(),
+                    range.start(),
+                    range.end()
+                )
+            })?;
+        Ok(Compression { codec, level })
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.codec.levels() {
+            Some(_) => write!(f, "{}:{}", self.codec.name(), self.level),
+            None => write!(f, "{}", self.codec.name()),
+        }
+    }
+}
+
+/// A zstd dictionary, trained on samples of the kind of files it will compress.
+/// Cloning is cheap, since clones share the dictionary bytes.
+#[derive(Clone)]
+pub struct Dictionary {
+    bytes: Arc<[u8]>,
+}
+
+// Magic number at the start of a trained zstd dictionary, followed by its u32 id (both little endian)
+const DICTIONARY_MAGIC: u32 = 0xEC30_A437;
+
+impl Dictionary {
+    /// Train a dictionary of at most `max_size` bytes on sample files. zstd needs a reasonable number
+    /// of samples (dozens at least) that together are much larger than the dictionary.
+    pub fn train(samples: &[Vec<u8>], max_size: usize) -> io::Result<Self> {
+        #[cfg(feature = "zstd")]
+        {
+            zstd::dict::from_samples(samples, max_size).map(Dictionary::from_bytes)
+        }
+        #[cfg(not(feature = "zstd"))]
+        {
+            let _ = (samples, max_size);
+            Err(unavailable(Codec::Zstd))
+        }
+    }
+
+    /// Use previously trained dictionary bytes
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Dictionary {
+            bytes: bytes.into(),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The id zstd stored in the dictionary, recorded in the header of every file compressed with it.
+    /// 0 for raw content that wasn't produced by training.
+    pub fn id(&self) -> u32 {
+        match self.bytes.get(..8) {
+            Some(start) if start[..4] == DICTIONARY_MAGIC.to_le_bytes() => {
+                u32::from_le_bytes([start[4], start[5], start[6], start[7]])
+            }
+            _ => 0,
+        }
+    }
+}
+
+// The dictionary contents are as sensitive as the files it was trained on, so keep them out of Debug output
+impl std::fmt::Debug for Dictionary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dictionary")
+            .field("id", &self.id())
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// Compress `data`. The dictionary is only used by zstd and ignored by the other codecs.
+/// Fails if the codec isn't built in.
+#[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+pub fn compress(
+    data: &[u8],
+    compression: Compression,
+    dictionary: Option<&Dictionary>,
+) -> io::Result<Vec<u8>> {
+    match compression.codec {
+        Codec::None => Ok(data.to_vec()),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => {
+            use std::io::Write;
+            let dictionary = dictionary.map_or(&[][..], Dictionary::as_bytes);
+            let mut encoder = zstd::stream::Encoder::with_dictionary(
+                Vec::new(),
+                compression.level as i32,
+                dictionary,
+            )?;
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "lz4")]
+        Codec::Lz4 => {
+            use std::io::Write;
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+            encoder.write_all(data)?;
+            encoder.finish().map_err(io::Error::other)
+        }
+        #[cfg(feature = "brotli")]
+        Codec::Brotli => {
+            use std::io::Write;
+            // 4 KiB internal buffer and a 4 MiB window (lgwin 22), brotli's usual defaults
+            let mut encoder =
+                brotli::CompressorWriter::new(Vec::new(), 4096, compression.level, 22);
+            encoder.write_all(data)?;
+            encoder.flush()?;
+            Ok(encoder.into_inner())
+        }
+        #[allow(unreachable_patterns)]
+        codec => Err(unavailable(codec)),
+    }
+}
+
+/// Decompress `data` that was compressed with `codec` (and, for zstd, the same dictionary).
+/// Fails if the codec isn't built in or the data is corrupt.
+#[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+pub fn decompress(
+    data: &[u8],
+    codec: Codec,
+    dictionary: Option<&Dictionary>,
+) -> io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    match codec {
+        Codec::None => output.extend_from_slice(data),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => {
+            use std::io::Read;
+            let dictionary = dictionary.map_or(&[][..], Dictionary::as_bytes);
+            zstd::stream::Decoder::with_dictionary(data, dictionary)?.read_to_end(&mut output)?;
+        }
+        #[cfg(feature = "lz4")]
+        Codec::Lz4 => {
+            use std::io::Read;
+            lz4_flex::frame::FrameDecoder::new(data).read_to_end(&mut output)?;
+        }
+        #[cfg(feature = "brotli")]
+        Codec::Brotli => {
+            use std::io::Read;
+            brotli::Decompressor::new(data, 4096).read_to_end(&mut output)?;
+        }
+        #[allow(unreachable_patterns)]
+        codec => return Err(unavailable(codec)),
+    }
+    Ok(output)
+}
+
+fn unavailable(codec: Codec) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{} support is not built in (enable the '{}' feature)",
+            codec.name(),
+            codec.name()
+        ),
+    )
+}
@@ -0,0 +1,203 @@
+// Default settings for the commands that process files, from `config.toml` in the config directory (or the file
+// given with --config) and from environment variables. A flag given on the command line wins over its environment
+// variable, which wins over the config file, which wins over the built-in default:
+//
+//     cipher = "chacha20poly1305"
+//     kdf-memory = 65536
+//     kdf-iterations = 3
+//     compress = "zstd:9"
+//     output-dir = "/srv/encrypted"
+//     json = true
+//     redact-paths = true
+//     password-set = "2026-03-01"
+//     max-password-age = "180d"
+//     storage-class = "DEEP_ARCHIVE"
+//
+// A missing config.toml is no error, but a missing --config file, an unknown key or a bad value is, so that a typo
+// never silently falls back to the built-in defaults.
+use crate::{cli, config_dir, s3, streamed, Format, Options};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use encryptor::{Algorithm, Compression};
+use std::path::PathBuf;
+use std::time::Duration;
+
+const FILE_NAME: &str = "config.toml";
+
+// Each key of the config file, the environment variable that overrides it, and the id of the flag it's the
+// default of
+const KEYS: &[(&str, &str, &str)] = &[
+    ("cipher", "ENCRYPTOR_CIPHER", "cipher"),
+    ("kdf-memory", "ENCRYPTOR_KDF_MEMORY", "kdf_memory"),
+    (
+        "kdf-iterations",
+        "ENCRYPTOR_KDF_ITERATIONS",
+        "kdf_iterations",
+    ),
+    ("compress", "ENCRYPTOR_COMPRESS", "compress"),
+    ("output-dir", "ENCRYPTOR_OUTPUT_DIR", "out"),
+    ("json", "ENCRYPTOR_JSON", "json"),
+    ("redact-paths", "ENCRYPTOR_REDACT_PATHS", "redact_paths"),
+    ("password-set", "ENCRYPTOR_PASSWORD_SET", "password_set"),
+    (
+        "max-password-age",
+        "ENCRYPTOR_MAX_PASSWORD_AGE",
+        "max_password_age",
+    ),
+    ("storage-class", "ENCRYPTOR_STORAGE_CLASS", "storage_class"),
+];
+
+// The settings found, each only if it was set somewhere
+#[derive(Default)]
+pub struct Defaults {
+    cipher: Option<Algorithm>,
+    kdf_memory: Option<u32>,
+    kdf_iterations: Option<u32>,
+    compression: Option<Compression>,
+    output_dir: Option<String>,
+    json: Option<bool>,
+    redact_paths: Option<bool>,
+    password_set: Option<u64>,
+    max_password_age: Option<Duration>,
+    storage_class: Option<String>,
+}
+
+// Read the config file at `path`, or config.toml in the config directory if there is one, and the environment
+pub fn load(path: Option<&str>) -> Result<Defaults, String> {
+    let mut defaults = Defaults::default();
+    let path = match path {
+        Some(path) => Some(PathBuf::from(path)),
+        None => config_dir()
+            .ok()
+            .map(|dir| dir.join(FILE_NAME))
+            .filter(|path| path.is_file()),
+    };
+    if let Some(path) = path {
+        let text = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Cannot read the config file {}: {}", path.display(), err))?;
+        let table: toml::Table = text
+            .parse()
+            .map_err(|err| format!("Invalid config file {}: {}", path.display(), err))?;
+        for (key, value) in &table {
+            let value = match value {
+                toml::Value::String(text) => text.clone(),
+                toml::Value::Integer(_) | toml::Value::Boolean(_) => value.to_string(),
+                _ => String::new(),
+            };
+            defaults
+                .set(key, &value)
+                .map_err(|err| format!("Config file {}: {}: {}", path.display(), key, err))?;
+        }
+        log::debug!("Read the defaults in {}", path.display());
+    }
+    for (key, variable, _) in KEYS {
+        if let Some(value) = std::env::var(variable)
+            .ok()
+            .filter(|value| !value.is_empty())
+        {
+            defaults
+                .set(key, &value)
+                .map_err(|err| format!("{}: {}", variable, err))?;
+        }
+    }
+    Ok(defaults)
+}
+
+impl Defaults {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let number = || value.parse().map_err(|_| "expected an integer".to_string());
+        let flag = || match value {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err("expected true or false".to_string()),
+        };
+        match key {
+            "cipher" => self.cipher = Some(cli::parse_algorithm(value)?),
+            "kdf-memory" => self.kdf_memory = Some(number()?),
+            "kdf-iterations" => self.kdf_iterations = Some(number()?),
+            "compress" => self.compression = Some(cli::parse_compression(value)?),
+            "output-dir" if !value.is_empty() => self.output_dir = Some(value.to_string()),
+            "output-dir" => return Err("expected a directory".to_string()),
+            "json" => self.json = Some(flag()?),
+            "redact-paths" => self.redact_paths = Some(flag()?),
+            "password-set" => self.password_set = Some(cli::parse_date(value)?),
+            "max-password-age" => self.max_password_age = Some(cli::parse_duration(value)?),
+            "storage-class" => self.storage_class = Some(cli::parse_storage_class(value)?),
+            _ => {
+                let keys: Vec<&str> = KEYS.iter().map(|(key, _, _)| *key).collect();
+                return Err(format!(
+                    "unknown setting, expected one of: {}",
+                    keys.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Whether paths are redacted when --redact-paths isn't given. It applies to every command, so it's read before
+    // the command runs rather than set by `apply`.
+    pub fn redact_paths(&self) -> bool {
+        self.redact_paths.unwrap_or(false)
+    }
+
+    // Set the options the command parsed into `matches` takes but wasn't given on its command line.
+    // The output directory is left out where --out would be refused or mean something else: with --in-place,
+    // --shred, --archive or another --format, and the storage class where --out isn't an s3:// URI.
+    pub fn apply(&self, matches: &ArgMatches, options: &mut Options) {
+        let unset = |id: &str| {
+            matches.try_get_raw(id).is_ok()
+                && matches.value_source(id) != Some(ValueSource::CommandLine)
+        };
+        if let Some(cipher) = self.cipher.filter(|_| unset("cipher")) {
+            options.algorithm = cipher;
+        }
+        if let Some(memory) = self.kdf_memory.filter(|_| unset("kdf_memory")) {
+            options.kdf_params.memory = memory;
+        }
+        if let Some(iterations) = self.kdf_iterations.filter(|_| unset("kdf_iterations")) {
+            options.kdf_params.iterations = iterations;
+        }
+        // --dict implies zstd, and the streams of s3:// objects, URLs and split volumes aren't compressed
+        let streamed =
+            options.split_size.is_some() || options.out.as_deref().is_some_and(streamed::is_remote);
+        if let Some(compression) = self
+            .compression
+            .filter(|_| unset("compress") && options.dict_path.is_none() && !streamed)
+        {
+            options.compression = compression;
+        }
+        let takes_out = !options.in_place
+            && !options.shred
+            && !options.archive
+            && options.format == Format::Native;
+        if let Some(dir) = self
+            .output_dir
+            .as_ref()
+            .filter(|_| unset("out") && takes_out)
+        {
+            // A trailing separator makes --out a directory even for a single file
+            let mut dir = dir.clone();
+            if !dir.ends_with(['/', std::path::MAIN_SEPARATOR]) {
+                dir.push(std::path::MAIN_SEPARATOR);
+            }
+            options.out = Some(dir.into());
+        }
+        if let Some(json) = self.json.filter(|_| unset("json")) {
+            options.json = json;
+        }
+        if let Some(secs) = self.password_set.filter(|_| unset("password_set")) {
+            options.password_set = Some(secs);
+        }
+        if let Some(age) = self.max_password_age.filter(|_| unset("max_password_age")) {
+            options.max_password_age = Some(age);
+        }
+        let to_s3 = options.out.as_deref().is_some_and(s3::is_uri);
+        if let Some(class) = self
+            .storage_class
+            .as_ref()
+            .filter(|_| unset("storage_class") && to_s3)
+        {
+            options.storage_class = Some(class.clone());
+        }
+    }
+}
@@ -0,0 +1,339 @@
+// `daemon` and `client`: a long-running process that holds the key of one password, so that scripts encrypting or
+// decrypting files one at a time in a loop don't run Argon2id for every file. `encryptor daemon` asks for the password
+// once, listens on a Unix domain socket and serves what `encryptor client` asks of it, until its --ttl runs out,
+// `client stop` is run or a signal stops it. The daemon runs hardened (see --harden), so the keys it derives are locked
+// into RAM, and they are wiped when it exits. Outputs are sealed under the salt, and so the key, the daemon picked
+// when it started; files from elsewhere get their key derived once and kept while the daemon runs.
+//
+// The daemon reads and writes the files itself, under the paths the client made absolute, so both run as the same
+// user on the same machine: the socket is created readable and writable by its owner alone. Unix only. Each
+// connection carries one request and its response, a line of JSON each:
+//
+//   {"op": "encrypt", "file": "/home/me/a.txt", "out": null, "force": false}
+//   {"ok": true, "output": "/home/me/a.txt.enc"}
+//   {"ok": false, "code": "ENC_AUTH_FAIL", "exit": 4, "message": "..."}
+//
+// `status` answers with the seconds the daemon has left, and `stop` ends it once the requests being served finish. A
+// path that isn't valid UTF-8 goes as {"escaped": "..."}, escaped like a file name in a header (see
+// `encryptor::names`).
+use crate::output::Printer;
+use crate::units;
+use encryptor::{names, EncryptError};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// How long the daemon keeps its key by default
+pub const DEFAULT_TTL: Duration = Duration::from_secs(15 * 60);
+
+// The socket given with --socket, or else encryptor.sock in $XDG_RUNTIME_DIR or the config directory
+pub fn socket_path(socket: Option<&str>) -> Result<PathBuf, String> {
+    if let Some(socket) = socket {
+        return Ok(PathBuf::from(socket));
+    }
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Ok(PathBuf::from(dir).join("encryptor.sock")),
+        None => Ok(crate::config_dir()?.join("encryptor.sock")),
+    }
+}
+
+// Ask the daemon listening on `socket` to encrypt or decrypt each of `files` (`operation`), into `out` if given,
+// printing each result like the file commands do. Returns the exit code of the first file that failed, or 0.
+pub fn client(
+    socket: &Path,
+    operation: &str,
+    files: &[PathBuf],
+    out: Option<&Path>,
+    force: bool,
+    printer: &Printer,
+) -> i32 {
+    let (done, failed) = match operation {
+        "encrypt" => ("Encrypted", "Encryption"),
+        _ => ("Decrypted", "Decryption"),
+    };
+    if let Some(out) = out.filter(|_| files.len() > 1) {
+        if let Err(err) = std::fs::create_dir_all(out) {
+            printer.failed(&format!(
+                "Cannot create the output directory {}: {}",
+                out.display(),
+                err
+            ));
+            return crate::EXIT_FAILURE;
+        }
+    }
+    let mut code = 0;
+    for file in files {
+        let request = absolute(file).and_then(|file| {
+            let out = out.map(absolute).transpose()?;
+            Ok(json!({ "op": operation, "file": to_json(&file), "out": out.as_deref().map(to_json), "force": force }))
+        });
+        let response = request.and_then(|request| send(socket, &request));
+        match response {
+            Ok(response) if response["ok"] == true => printer.ok(&format!(
+                "{} {} -> {}",
+                done,
+                file.display(),
+                from_json(&response["output"]).unwrap_or_default().display()
+            )),
+            Ok(response) => {
+                printer.failed(&format!(
+                    "{} error: {}: {}",
+                    failed,
+                    file.display(),
+                    response["message"].as_str().unwrap_or_default()
+                ));
+                if code == 0 {
+                    code = response["exit"]
+                        .as_i64()
+                        .map_or(crate::EXIT_FAILURE, |exit| exit as i32);
+                }
+            }
+            // Without a daemon no other file can go through either
+            Err(message) => {
+                printer.failed(&message);
+                return crate::EXIT_FAILURE;
+            }
+        }
+    }
+    code
+}
+
+// Ask the daemon listening on `socket` how long it keeps its key (`status`), or to stop (`stop`)
+pub fn control(socket: &Path, operation: &str) -> Result<String, String> {
+    let response = send(socket, &json!({ "op": operation }))?;
+    match (operation, response["expires_in"].as_u64()) {
+        ("status", Some(seconds)) => Ok(format!(
+            "The daemon on {} keeps its key for another {}",
+            socket.display(),
+            units::duration(Duration::from_secs(seconds))
+        )),
+        _ => Ok(format!(
+            "Stopped the daemon on {}, which forgot its key",
+            socket.display()
+        )),
+    }
+}
+
+// `path` as an absolute path, since the daemon runs in another directory
+fn absolute(path: &Path) -> Result<PathBuf, String> {
+    std::path::absolute(path).map_err(|err| format!("Cannot resolve {}: {}", path.display(), err))
+}
+
+// `path` as a request or response carries it
+fn to_json(path: &Path) -> Value {
+    match names::record(path.as_os_str()) {
+        (path, false) => json!(path),
+        (path, true) => json!({ "escaped": path }),
+    }
+}
+
+// The path a request or response carries as `value`, if it is one
+fn from_json(value: &Value) -> Option<PathBuf> {
+    match value {
+        Value::String(path) => Some(PathBuf::from(path)),
+        _ => names::unescape(value["escaped"].as_str()?).map(PathBuf::from),
+    }
+}
+
+// The response of the daemon as JSON, for one that failed with `err`
+fn error_response(err: &EncryptError) -> Value {
+    json!({
+        "ok": false,
+        "code": err.code().as_str(),
+        "exit": err.code().number(),
+        "message": err.to_string(),
+    })
+}
+
+#[cfg(unix)]
+pub use unix::{send, serve};
+
+#[cfg(unix)]
+mod unix {
+    use super::{error_response, from_json, to_json};
+    use crate::interrupt;
+    use crate::output::Printer;
+    use crate::units;
+    use encryptor::{CancellationToken, Encryptor};
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // How often the daemon checks for the end of its TTL and for signals between connections
+    const POLL: Duration = Duration::from_millis(50);
+
+    // The longest request line read, far more than the longest paths need
+    const MAX_REQUEST: u64 = 64 * 1024;
+
+    // Serve the requests sent to `socket` with `encryptor` for `ttl`, or until stopped
+    pub fn serve(
+        encryptor: Encryptor,
+        socket: &Path,
+        ttl: Duration,
+        printer: &Printer,
+    ) -> Result<(), String> {
+        // A socket left by a daemon that died is replaced, but not one a daemon still listens on
+        if UnixStream::connect(socket).is_ok() {
+            return Err(format!(
+                "A daemon is already listening on {}: stop it with `encryptor client stop`",
+                socket.display()
+            ));
+        }
+        if fs::symlink_metadata(socket).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            let _ = fs::remove_file(socket);
+        }
+        if let Some(dir) = socket.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("Cannot create {}: {}", dir.display(), err))?;
+        }
+        // The socket is created for its owner alone, with no moment where others could connect
+        // SAFETY: umask only changes the mode of the files this process creates
+        let umask = unsafe { libc::umask(0o177) };
+        let listener = UnixListener::bind(socket);
+        // SAFETY: as above, restoring the mask that was in effect
+        unsafe { libc::umask(umask) };
+        let listener =
+            listener.map_err(|err| format!("Cannot listen on {}: {}", socket.display(), err))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|err| format!("Cannot listen on {}: {}", socket.display(), err))?;
+
+        printer.note(&format!(
+            "Listening on {} for {}; stop with `encryptor client stop`",
+            socket.display(),
+            units::duration(ttl)
+        ));
+        let deadline = Instant::now() + ttl;
+        let stop = Arc::new(AtomicBool::new(false));
+        let cancel = CancellationToken::new();
+        interrupt::cancel_on_signals(&cancel);
+        let mut workers: Vec<thread::JoinHandle<()>> = Vec::new();
+        while !cancel.is_cancelled() && !stop.load(Ordering::SeqCst) && Instant::now() < deadline {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let (encryptor, stop, cancel) =
+                        (encryptor.clone(), stop.clone(), cancel.clone());
+                    workers.push(thread::spawn(move || {
+                        if let Err(err) = handle(stream, &encryptor, deadline, &stop, &cancel) {
+                            log::debug!("Cannot answer a request: {}", err);
+                        }
+                    }));
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL),
+                Err(err) => log::warn!("Cannot accept a connection: {}", err),
+            }
+            workers.retain(|worker| !worker.is_finished());
+        }
+        // The requests being served are finished, or stop at their next chunk after a signal
+        let _ = fs::remove_file(socket);
+        for worker in workers {
+            let _ = worker.join();
+        }
+        let why = match (cancel.is_cancelled(), stop.load(Ordering::SeqCst)) {
+            (true, _) => "Interrupted",
+            (_, true) => "Stopped",
+            _ => "The TTL ran out",
+        };
+        printer.note(&format!("{}: the daemon forgot its key", why));
+        Ok(())
+    }
+
+    // Answer the one request sent over `stream`
+    fn handle(
+        stream: UnixStream,
+        encryptor: &Encryptor,
+        deadline: Instant,
+        stop: &AtomicBool,
+        cancel: &CancellationToken,
+    ) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        let mut line = String::new();
+        BufReader::new((&stream).take(MAX_REQUEST)).read_line(&mut line)?;
+        let request: Value = serde_json::from_str(&line).unwrap_or_default();
+        let operation = request["op"].as_str().unwrap_or_default();
+        let response = match (operation, from_json(&request["file"])) {
+            ("status" | "stop", _) => {
+                stop.fetch_or(operation == "stop", Ordering::SeqCst);
+                let left = deadline.saturating_duration_since(Instant::now());
+                json!({ "ok": true, "expires_in": left.as_secs() })
+            }
+            ("encrypt" | "decrypt", Some(file)) => {
+                let encryptor = encryptor.clone().with_overwrite(request["force"] == true);
+                let result = match (operation, from_json(&request["out"])) {
+                    ("encrypt", None) => encryptor.encrypt_file_with(&file, |_| {}, cancel),
+                    ("encrypt", Some(out)) => {
+                        encryptor.encrypt_file_to_with(&file, out, |_| {}, cancel)
+                    }
+                    (_, None) => encryptor.decrypt_file_with(&file, |_| {}, cancel),
+                    (_, Some(out)) => encryptor.decrypt_file_to_with(&file, out, |_| {}, cancel),
+                };
+                match result {
+                    Ok(output) => {
+                        log::info!("{} {} -> {}", operation, file.display(), output.display());
+                        json!({ "ok": true, "output": to_json(&output) })
+                    }
+                    Err(err) => {
+                        log::info!("{} {}: {}", operation, file.display(), err);
+                        error_response(&err)
+                    }
+                }
+            }
+            _ => json!({
+                "ok": false,
+                "exit": crate::EXIT_FAILURE,
+                "message": "the daemon doesn't understand this request",
+            }),
+        };
+        writeln!(&stream, "{}", response)
+    }
+
+    // Send `request` to the daemon listening on `socket` and return its response
+    pub fn send(socket: &Path, request: &Value) -> Result<Value, String> {
+        let no_daemon = |err: io::Error| match err.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused => format!(
+                "No daemon is listening on {}: start one with `encryptor daemon`",
+                socket.display()
+            ),
+            _ => format!("Cannot reach the daemon on {}: {}", socket.display(), err),
+        };
+        let stream = UnixStream::connect(socket).map_err(no_daemon)?;
+        writeln!(&stream, "{}", request).map_err(no_daemon)?;
+        let mut line = String::new();
+        BufReader::new(&stream)
+            .read_line(&mut line)
+            .map_err(no_daemon)?;
+        serde_json::from_str(&line).map_err(|_| {
+            format!(
+                "The daemon on {} stopped before answering",
+                socket.display()
+            )
+        })
+    }
+}
+
+#[cfg(not(unix))]
+pub fn serve(
+    _encryptor: encryptor::Encryptor,
+    _socket: &Path,
+    _ttl: Duration,
+    _printer: &Printer,
+) -> Result<(), String> {
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+pub fn send(_socket: &Path, _request: &Value) -> Result<Value, String> {
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+fn unsupported() -> String {
+    "The daemon listens on a Unix domain socket, which this platform doesn't have".to_string()
+}
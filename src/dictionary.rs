@@ -0,0 +1,32 @@
+// Training and loading zstd dictionaries for `train-dict` and `--dict`.
+// A dictionary is built from fragments of the sample files, so it is written encrypted with the same password
+// as the files it is used for, and decrypted again when it is loaded.
+use encryptor::{Dictionary, EncryptError, Encryptor};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Default maximum dictionary size, the same as the zstd command line tool
+pub const DEFAULT_SIZE: usize = 110 * 1024;
+
+// Train a dictionary on the sample files and write it, encrypted, to `dict_path`. Returns the dictionary id.
+pub fn train(
+    encryptor: &Encryptor,
+    dict_path: &Path,
+    samples: &[PathBuf],
+    max_size: usize,
+) -> Result<u32, EncryptError> {
+    let samples = samples
+        .iter()
+        .map(fs::read)
+        .collect::<Result<Vec<_>, _>>()?;
+    let dictionary =
+        Dictionary::train(&samples, max_size).map_err(EncryptError::CompressionError)?;
+    fs::write(dict_path, encryptor.encrypt_bytes(dictionary.as_bytes())?)?;
+    Ok(dictionary.id())
+}
+
+// Read and decrypt a dictionary written by `train`
+pub fn load(encryptor: &Encryptor, dict_path: &str) -> Result<Dictionary, EncryptError> {
+    let bytes = encryptor.decrypt_bytes(&fs::read(dict_path)?)?;
+    Ok(Dictionary::from_bytes(bytes))
+}
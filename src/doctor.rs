@@ -0,0 +1,591 @@
+// `encryptor doctor <file>`: a guided diagnosis of an encrypted file that won't decrypt. It goes through what can go
+// wrong in the order decrypting would run into it, printing each finding as it goes, and ends with the commands
+// that fix what it found:
+//
+//   Diagnosing backup.tar.enc
+//     ok    format: native, binary
+//     ok    header: format version 3, 97 bytes (this build writes version 5)
+//     fail  header backup: missing or damaged
+//     fail  length: 1048576 bytes are missing, so the file was cut off
+//     info  opened by: a password (Argon2id, memory 19456 KiB, iterations 2)
+//     fail  file:pass.txt: doesn't open it; it is the wrong password or key, or the ciphertext is damaged
+//     fail  file:old-pass.txt: doesn't open it; it is the wrong password or key, or the ciphertext is damaged
+//
+// The secrets to try are given with --try, like those of `rekey`; without any, it asks for passwords on the
+// terminal until one opens the file. Trying a secret verifies the whole file like `verify`, writing nothing.
+// The suggestions are `recover` for a damaged header, `rekey` to rewrite an old format version, or renaming a file
+// back to the name its header records; what no command can repair (a cut-off or damaged payload) is said so.
+use crate::cli::{DoctorArgs, SecretSource, TriedSecret};
+use crate::password::{self, Source};
+use crate::{age_file, cms_file, header_json, jwe_file, keyfile, openssl_file};
+use encryptor::header::{self, Header, HeaderError, Kdf, StanzaKind};
+use encryptor::recipient::Identity;
+use encryptor::{encoding, names, CancellationToken, EncryptError, Encryptor};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// The findings so far, printed as they are made
+struct Diagnosis<'a> {
+    print: &'a dyn Fn(String),
+    problems: usize,
+    // The secrets tried that didn't open the file
+    misses: usize,
+    suggestions: Vec<String>,
+}
+
+impl Diagnosis<'_> {
+    fn ok(&mut self, what: &str, finding: &str) {
+        (self.print)(format!("  ok    {}: {}", what, finding));
+    }
+
+    fn info(&mut self, what: &str, finding: &str) {
+        (self.print)(format!("  info  {}: {}", what, finding));
+    }
+
+    fn fail(&mut self, what: &str, finding: &str) {
+        self.problems += 1;
+        (self.print)(format!("  fail  {}: {}", what, finding));
+    }
+
+    // A secret that doesn't open the file, only a problem when none does
+    fn miss(&mut self, what: &str, finding: &str) {
+        self.misses += 1;
+        (self.print)(format!("  fail  {}: {}", what, finding));
+    }
+
+    fn suggest(&mut self, suggestion: String) {
+        self.suggestions.push(suggestion);
+    }
+
+    // Print the suggestions, failing when anything was wrong
+    fn finish(self, file_path: &Path) -> Result<(), String> {
+        if !self.suggestions.is_empty() {
+            (self.print)(String::new());
+            (self.print)("Suggested:".to_string());
+            for suggestion in &self.suggestions {
+                (self.print)(format!("  {}", suggestion));
+            }
+        }
+        match self.problems {
+            0 => Ok(()),
+            1 => Err(format!("Found 1 problem with {}", file_path.display())),
+            problems => Err(format!(
+                "Found {} problems with {}",
+                problems,
+                file_path.display()
+            )),
+        }
+    }
+}
+
+// The bytes of the encrypted file, read where they're needed from a binary file, or all at once from one that was
+// encoded as text
+enum Contents {
+    Binary(File, u64),
+    Decoded(Vec<u8>),
+}
+
+impl Contents {
+    fn len(&self) -> u64 {
+        match self {
+            Contents::Binary(_, len) => *len,
+            Contents::Decoded(bytes) => bytes.len() as u64,
+        }
+    }
+
+    // Up to `len` bytes from `offset` on, fewer at the end of the file
+    fn read(&mut self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        match self {
+            Contents::Binary(file, _) => {
+                let mut bytes = Vec::with_capacity(len);
+                file.seek(SeekFrom::Start(offset))?;
+                file.take(len as u64).read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+            Contents::Decoded(bytes) => {
+                let start = (offset as usize).min(bytes.len());
+                Ok(bytes[start..(start + len).min(bytes.len())].to_vec())
+            }
+        }
+    }
+
+    // The header at the start, reading as much as it turns out to need
+    fn header(&mut self) -> io::Result<Result<(Header, usize), HeaderError>> {
+        let mut len = 4096;
+        loop {
+            let head = self.read(0, len)?;
+            match Header::parse(&head) {
+                Err(HeaderError::Truncated) if (head.len() as u64) < self.len() => len *= 2,
+                parsed => return Ok(parsed),
+            }
+        }
+    }
+
+    // The header copy at the end, with its bytes
+    fn backup(&mut self) -> io::Result<Option<(Header, Vec<u8>)>> {
+        let Some(len_at) = self.len().checked_sub(4) else {
+            return Ok(None);
+        };
+        let len_bytes = self.read(len_at, 4)?;
+        let len = u32::from_le_bytes(len_bytes[..].try_into().unwrap_or_default()) as u64;
+        let Some(start) = len_at.checked_sub(len) else {
+            return Ok(None);
+        };
+        let tail = self.read(start, len as usize + 4)?;
+        Ok(Header::parse_backup(&tail).map(|(header, _)| (header, tail[..len as usize].to_vec())))
+    }
+}
+
+pub fn run(args: DoctorArgs, print: &dyn Fn(String)) -> Result<(), String> {
+    let file_path = args.file.as_path();
+    let mut diagnosis = Diagnosis {
+        print,
+        problems: 0,
+        misses: 0,
+        suggestions: Vec::new(),
+    };
+    print(format!("Diagnosing {}", file_path.display()));
+
+    // Files in other formats are decrypted by `decrypt` too, but have nothing of ours to check
+    let foreign = [
+        (age_file::is_age(file_path), "an age file"),
+        (jwe_file::is_jwe(file_path), "a JWE file"),
+        (openssl_file::is_openssl(file_path), "an OpenSSL enc file"),
+        (cms_file::is_cms(file_path), "a CMS file"),
+    ];
+    if let Some((_, format)) = foreign.iter().find(|(is, _)| *is) {
+        diagnosis.info(
+            "format",
+            &format!("{}, which doctor can't look into", format),
+        );
+        diagnosis.suggest(format!(
+            "Decrypt it with `encryptor decrypt {}`, which reports what is wrong with it",
+            file_path.display()
+        ));
+        return diagnosis.finish(file_path);
+    }
+
+    let read_error = |err: io::Error| format!("{}: {}", file_path.display(), err);
+    let Some(mut contents) = open(file_path, &mut diagnosis).map_err(read_error)? else {
+        return diagnosis.finish(file_path);
+    };
+    let parsed = contents.header().map_err(read_error)?;
+    let backup = contents.backup().map_err(read_error)?;
+
+    // A header that can't be read is rebuilt first; everything else needs it
+    let (header, header_len) = match parsed {
+        Ok(parsed) => parsed,
+        Err(HeaderError::UnsupportedVersion(version)) if version > header::VERSION => {
+            diagnosis.fail(
+                "header",
+                &format!(
+                    "format version {} is newer than this build reads (versions {} to {})",
+                    version,
+                    header::MIN_VERSION,
+                    header::VERSION
+                ),
+            );
+            diagnosis.suggest(format!(
+                "Upgrade encryptor to a release that reads format version {}",
+                version
+            ));
+            return diagnosis.finish(file_path);
+        }
+        Err(err) => {
+            diagnosis.fail("header", &format!("can't be read: {}", err));
+            let recovered = recovered_path(file_path);
+            match backup {
+                Some(_) => {
+                    diagnosis.ok("header backup", "intact");
+                    diagnosis.suggest(format!(
+                        "Put the backup back in front: `encryptor recover {} --out {}`, then run doctor on {}",
+                        file_path.display(), recovered, recovered
+                    ));
+                }
+                None => {
+                    diagnosis.fail("header backup", "missing or damaged too");
+                    diagnosis.suggest(format!(
+                        "Rebuild the header from what you know about the file: `encryptor recover {} --assume \
+                         cipher=aes256gcm,kdf=argon2id:19456:2,... --out {}` (see `encryptor recover --help`)",
+                        file_path.display(), recovered
+                    ));
+                }
+            }
+            return diagnosis.finish(file_path);
+        }
+    };
+    let mut version = format!("format version {}, {} bytes", header.version, header_len);
+    if header.version < header::VERSION {
+        version += &format!(" (this build writes version {})", header::VERSION);
+    }
+    diagnosis.ok("header", &version);
+
+    match (header.header_backup, backup) {
+        (false, _) => diagnosis.info(
+            "header backup",
+            "none, it was encrypted with --no-header-backup",
+        ),
+        (true, Some((_, backup_bytes))) => {
+            let header_bytes = contents.read(0, header_len).map_err(read_error)?;
+            match backup_bytes == header_bytes {
+                true => diagnosis.ok("header backup", "intact, the same as the header"),
+                false => {
+                    diagnosis.fail(
+                        "header backup",
+                        "differs from the header, so one of them was altered",
+                    );
+                    diagnosis.suggest(format!(
+                        "If no secret opens it, try the backup instead: `encryptor recover {} --out {}`",
+                        file_path.display(),
+                        recovered_path(file_path)
+                    ));
+                }
+            }
+        }
+        (true, None) => diagnosis.fail("header backup", "missing or damaged"),
+    }
+
+    check_length(file_path, &contents, &header, header_len, &mut diagnosis);
+
+    let mut opened_by = header_json::unlocked_by(&header);
+    if let Some(key_id) = &header.key_id {
+        opened_by.push(format!("recorded as key ID {}", key_id));
+    }
+    diagnosis.info("opened by", &opened_by.join(", "));
+
+    // Decrypting checks the name the header records against the name it writes to
+    let in_place = !header.sealed_name && check_name(file_path, &header, &mut diagnosis);
+
+    let opened = try_secrets(file_path, &header, in_place, args.tries, &mut diagnosis)?;
+    match opened {
+        Some(spec) if header.version < header::VERSION && diagnosis.problems == 0 => diagnosis
+            .suggest(format!(
+                "Rewrite it in format version {}: `encryptor rekey {} {} {}`",
+                header::VERSION,
+                spec,
+                spec,
+                file_path.display()
+            )),
+        Some(_) => {}
+        None if diagnosis.misses > 0 => {
+            diagnosis.problems += 1;
+            diagnosis.suggest(format!(
+            "Try the other passwords or keys it may be under: `encryptor doctor {} --try file:<path> --try \
+             keyfile:<path>`",
+                file_path.display()
+            ))
+        }
+        None => {}
+    }
+    diagnosis.finish(file_path)
+}
+
+// Open the file, decoding it if it was written as text. None if it is empty, since there's nothing more to check.
+fn open(file_path: &Path, diagnosis: &mut Diagnosis) -> io::Result<Option<Contents>> {
+    let mut file = File::open(file_path)?;
+    let len = file.metadata()?.len();
+    if len == 0 {
+        diagnosis.fail("format", "the file is empty");
+        diagnosis.suggest(format!("Restore {} from another copy", file_path.display()));
+        return Ok(None);
+    }
+    let mut magic = Vec::new();
+    (&mut file)
+        .take(header::MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    if magic == header::MAGIC {
+        diagnosis.ok("format", "native, binary");
+        return Ok(Some(Contents::Binary(file, len)));
+    }
+
+    let mut data = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut data)?;
+    match encoding::decode(data) {
+        Ok(decoded) if decoded.starts_with(header::MAGIC) => {
+            diagnosis.ok(
+                "format",
+                &format!("native, encoded as text ({} bytes decoded)", decoded.len()),
+            );
+            Ok(Some(Contents::Decoded(decoded)))
+        }
+        // Whatever the header turns out to be, such as not encrypted at all, is found next
+        Ok(decoded) => Ok(Some(Contents::Decoded(decoded))),
+        Err(err) => {
+            diagnosis.fail("format", &format!("encoded as text, but {}", err));
+            diagnosis.suggest(format!(
+                "Make sure {} is complete, from its BEGIN line to its END line (or the whole JSON envelope)",
+                file_path.display()
+            ));
+            Ok(None)
+        }
+    }
+}
+
+// Whether the ciphertext is as long as the header says
+fn check_length(
+    file_path: &Path,
+    contents: &Contents,
+    header: &Header,
+    header_len: usize,
+    diagnosis: &mut Diagnosis,
+) {
+    let Some(expected) = header.ciphertext_len() else {
+        diagnosis.info(
+            "length",
+            "not recorded (it was encrypted with --no-bind-metadata), so only decrypting tells if it was cut off",
+        );
+        return;
+    };
+    let trailer_len = match header.header_backup {
+        true => header_len as u64 + 4,
+        false => 0,
+    };
+    let expected_len = header_len as u64 + expected + trailer_len;
+    let len = contents.len();
+    match len.cmp(&expected_len) {
+        std::cmp::Ordering::Equal => diagnosis.ok(
+            "length",
+            &format!("{} bytes of ciphertext, as recorded", expected),
+        ),
+        std::cmp::Ordering::Less => {
+            diagnosis.fail(
+                "length",
+                &format!(
+                    "{} bytes are missing, so the file was cut off",
+                    expected_len - len
+                ),
+            );
+            diagnosis.suggest(
+                "The missing end can't be rebuilt: restore the file from another copy, or finish the copy that was \
+                 interrupted"
+                    .to_string(),
+            );
+        }
+        std::cmp::Ordering::Greater => {
+            diagnosis.fail(
+                "length",
+                &format!(
+                    "{} bytes more than recorded, so something was appended",
+                    len - expected_len
+                ),
+            );
+            if let Contents::Binary(..) = contents {
+                diagnosis.suggest(format!(
+                    "Cut off what was appended: `truncate -s {} {}`",
+                    expected_len,
+                    file_path.display()
+                ));
+            }
+        }
+    }
+}
+
+// Whether the name the header records is the one decrypting the file at `file_path` would write, or its own for a
+// file encrypted in place. Returns whether it was encrypted in place.
+fn check_name(file_path: &Path, header: &Header, diagnosis: &mut Diagnosis) -> bool {
+    let recorded: OsString = match (header.file_name.as_deref(), header.escaped_name) {
+        (None, _) => return false,
+        (Some(name), true) => names::unescape(name).unwrap_or_else(|| name.into()),
+        (Some(name), false) => name.into(),
+    };
+    if header.hash_name {
+        diagnosis.ok(
+            "file name",
+            &format!("decrypts to {}, named by its hash", recorded.display()),
+        );
+        return false;
+    }
+    let own_name = names::file_name(file_path);
+    if names::split_extension(own_name).0 == recorded {
+        diagnosis.ok(
+            "file name",
+            &format!("decrypts to {}, as recorded", recorded.display()),
+        );
+        return false;
+    }
+    if own_name == recorded {
+        diagnosis.info(
+            "file name",
+            "its own, since it was encrypted in place: decrypt it with --in-place",
+        );
+        return true;
+    }
+    diagnosis.fail(
+        "file name",
+        &format!(
+            "the header records {}, so it was renamed from {}.enc",
+            recorded.display(),
+            recorded.display()
+        ),
+    );
+    let original = file_path.with_file_name(names::with_suffix(Path::new(&recorded), ".enc"));
+    diagnosis.suggest(format!(
+        "Rename it back before decrypting: `mv {} {}`",
+        file_path.display(),
+        original.display()
+    ));
+    false
+}
+
+// What trying a secret found
+enum Tried {
+    Opened,
+    NotOpened,
+    // Something already found keeps any secret from being checked
+    Blocked,
+}
+
+// Verify the file with each secret in turn, or with passwords asked for on the terminal, until one opens it.
+// Returns how the one that did was given, or None when none did or none could be checked.
+fn try_secrets(
+    file_path: &Path,
+    header: &Header,
+    in_place: bool,
+    tries: Vec<TriedSecret>,
+    diagnosis: &mut Diagnosis,
+) -> Result<Option<String>, String> {
+    if tries.is_empty() {
+        if !io::stdin().is_terminal() {
+            diagnosis.info(
+                "secrets",
+                "none given to try; name some with --try, e.g. --try file:pass.txt",
+            );
+            return Ok(None);
+        }
+        loop {
+            let password = rpassword::prompt_password("Password to try (nothing to stop): ")
+                .map_err(|err| format!("Cannot read the password: {}", err))?;
+            if password.is_empty() {
+                return Ok(None);
+            }
+            match try_secret(
+                file_path,
+                "the password",
+                Encryptor::new(&password),
+                in_place,
+                diagnosis,
+            ) {
+                Tried::Opened => return Ok(Some("prompt".to_string())),
+                Tried::NotOpened => {}
+                Tried::Blocked => return Ok(None),
+            }
+        }
+    }
+
+    // A key file opens an X25519 file as the identity it holds
+    let x25519 = header.kdf == Kdf::Recipients
+        && header
+            .recipients
+            .iter()
+            .any(|stanza| stanza.kind == StanzaKind::X25519);
+    for tried in tries {
+        let encryptor = match tried.source {
+            SecretSource::Password(source) => {
+                let password = match source {
+                    Source::Prompt => {
+                        password::read_as("Password to try", Some(Source::Prompt), false)
+                    }
+                    source => password::read(Some(source), false),
+                }?;
+                Encryptor::new(&password)
+            }
+            SecretSource::Keyfile(path) => {
+                let key = match keyfile::load(&path) {
+                    Ok(key) => key,
+                    Err(err) => {
+                        diagnosis.miss(&tried.spec, &err);
+                        continue;
+                    }
+                };
+                match x25519 {
+                    true => Encryptor::from_identity(Identity::from_bytes(*key)),
+                    false => Encryptor::from_key(*key),
+                }
+            }
+        };
+        match try_secret(file_path, &tried.spec, encryptor, in_place, diagnosis) {
+            Tried::Opened => return Ok(Some(tried.spec)),
+            Tried::NotOpened => {}
+            Tried::Blocked => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+// Verify the file with one secret, described as `what`, reporting whether it opens the file
+fn try_secret(
+    file_path: &Path,
+    what: &str,
+    encryptor: Encryptor,
+    in_place: bool,
+    diagnosis: &mut Diagnosis,
+) -> Tried {
+    let verified = encryptor.with_in_place(in_place).verify_file_with(
+        file_path,
+        |_| {},
+        &CancellationToken::new(),
+    );
+    let finding = match verified {
+        Ok(()) => {
+            diagnosis.ok(what, "opens it, and all of it authenticates");
+            return Tried::Opened;
+        }
+        // A wrong key fails on the first chunk, like damage there
+        Err(EncryptError::AeadError(_)) | Err(EncryptError::CorruptChunk { index: 0, .. }) => {
+            "doesn't open it; it is the wrong password or key, or the ciphertext is damaged"
+                .to_string()
+        }
+        Err(EncryptError::CorruptChunk { index, offset }) => {
+            diagnosis.fail(
+                what,
+                &format!(
+                    "opens it, but chunk {} at byte {} is damaged (the chunks before it are intact)",
+                    index, offset
+                ),
+            );
+            diagnosis.suggest(
+                "A damaged chunk can't be repaired: restore the file from another copy".to_string(),
+            );
+            return Tried::Opened;
+        }
+        Err(EncryptError::DamagedChunks { ranges, .. }) => {
+            let ranges: Vec<String> = ranges.iter().map(|range| range.to_string()).collect();
+            diagnosis.fail(
+                what,
+                &format!(
+                    "opens it, but {} failed authentication (the other chunks are intact)",
+                    ranges.join(", ")
+                ),
+            );
+            diagnosis.suggest(format!(
+                "A damaged chunk can't be repaired: restore {} from another copy, or salvage the intact chunks with `encryptor decrypt --keep-going {}`",
+                file_path.display(), file_path.display()
+            ));
+            return Tried::Opened;
+        }
+        Err(EncryptError::WrongPassword) => {
+            "fails its password check, or isn't one of the passwords its key is wrapped for"
+                .to_string()
+        }
+        // The name or the header backup, already reported, stop decrypting before the secret is known to be right
+        Err(EncryptError::MetadataMismatch(_)) => {
+            diagnosis.info(what, "can't be checked until the problems above are fixed");
+            return Tried::Blocked;
+        }
+        Err(err) => err.to_string(),
+    };
+    diagnosis.miss(what, &finding);
+    Tried::NotOpened
+}
+
+// Where `recover` is suggested to write the repaired file
+fn recovered_path(file_path: &Path) -> String {
+    let (stem, extension) = names::split_extension(file_path.as_os_str());
+    match extension == ".enc" {
+        true => format!("{}.recovered.enc", Path::new(stem).display()),
+        false => format!("{}.recovered", file_path.display()),
+    }
+}
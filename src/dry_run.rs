@@ -0,0 +1,451 @@
+// `encrypt --dry-run` and `decrypt --dry-run`: what a run would do, printed instead of done.
+//
+//   Would encrypt notes.txt -> notes.txt.enc (12.1 KiB)
+//   Would encrypt photo.jpg -> photo.jpg.enc (2.3 MiB, replacing the existing file)
+//   failed Would fail: report.pdf: report.pdf.enc already exists, and only --force replaces it
+//   Needs about 2.3 MiB of the 41.2 GiB free on the filesystem of .
+//
+// Every file is checked (that it exists, isn't a directory and, to decrypt, is an encrypted file), its output is
+// worked out the way the run would, and outputs that clash are caught: two files written to the same output, an
+// output that is one of the inputs, a missing output directory, and an existing file, which is only replaced with
+// --force (or written next to with --auto-rename). The space the outputs need is estimated from the sizes of the inputs and compared with what is free on
+// their filesystems. Only headers are read; no password is asked for and nothing is written.
+//
+// Every real `encrypt` and `decrypt` run makes the same estimate first, as its preflight, and stops before anything
+// is written if an output filesystem is short, rather than running out of space halfway; --no-preflight skips it.
+use crate::output::Printer;
+use crate::streamed::Existing;
+use crate::{
+    age_file, cms_file, http, jwe_file, openssl_file, report, s3, streamed, units, volumes, Format,
+    Options,
+};
+use encryptor::{names, Codec};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Room for a header and its backup, rounded up, on top of the payload of every encrypted file
+const HEADER_ALLOWANCE: u64 = 4096;
+// The 16-byte tag of every 1 MiB chunk, as a fraction of the payload
+const TAG_FRACTION: u64 = (1 << 20) / 16;
+
+// One file of the run
+struct Step {
+    input: PathBuf,
+    output: PathBuf,
+    bytes: u64,
+    needed: u64, // The space its output takes, about
+    named: bool, // Whether the output's name is known before the run, unlike a random or sealed one
+    notes: Vec<String>,
+    problem: Option<String>,
+}
+
+// Print what `command` would do to `files` with `options`, returning whether all of it would work
+pub fn run(command: &str, files: &[PathBuf], options: &Options, printer: &Printer) -> bool {
+    let steps = plan(command, files, options);
+
+    let verb = match command {
+        "encrypt" => "encrypt",
+        _ => "decrypt",
+    };
+    for step in &steps {
+        match &step.problem {
+            Some(problem) => printer.failed(&format!(
+                "Would fail: {}: {}",
+                step.input.display(),
+                problem
+            )),
+            None => {
+                let mut details = vec![units::size(step.bytes)];
+                details.extend(step.notes.iter().cloned());
+                printer.note(&format!(
+                    "Would {} {} -> {} ({})",
+                    verb,
+                    step.input.display(),
+                    step.output.display(),
+                    details.join(", ")
+                ));
+            }
+        }
+    }
+    let mut enough_space = true;
+    for (dir, needed, free) in space(&steps) {
+        let message = format!(
+            "Needs about {} of the {} free on the filesystem of {}",
+            units::size(needed),
+            units::size(free),
+            dir.display()
+        );
+        match needed > free {
+            true => {
+                printer.failed(&message);
+                enough_space = false;
+            }
+            false => printer.note(&message),
+        }
+    }
+
+    let failing = steps.iter().filter(|step| step.problem.is_some()).count();
+    if failing > 0 || !enough_space {
+        printer.failed(&format!(
+            "Dry run: {} of {} files would fail{}; nothing was written",
+            failing,
+            steps.len(),
+            match enough_space {
+                true => "",
+                false => ", and there isn't enough free space",
+            }
+        ));
+        return false;
+    }
+    printer.ok(&format!(
+        "Dry run: {} files would be {}ed; nothing was written",
+        steps.len(),
+        verb
+    ));
+    true
+}
+
+// Check that the outputs `command` writes for `files` fit on their filesystems before any of them is written. Files
+// that would fail anyway are left for the run to report.
+pub fn preflight(command: &str, files: &[PathBuf], options: &Options) -> Result<(), String> {
+    let steps = plan(command, files, options);
+    match space(&steps).into_iter().find(|(_, needed, free)| needed > free) {
+        Some((dir, needed, free)) => Err(format!(
+            "Not enough free space: the outputs need about {}, and the filesystem of {} has {} free \
+             (--dry-run shows the estimate, --no-preflight skips this check)",
+            units::size(needed),
+            dir.display(),
+            units::size(free)
+        )),
+        None => Ok(()),
+    }
+}
+
+// What `command` would do to each of `files`
+fn plan(command: &str, files: &[PathBuf], options: &Options) -> Vec<Step> {
+    let several = files.len() > 1;
+    let mut steps: Vec<Step> = files
+        .iter()
+        .map(|file_path| match command {
+            "encrypt" => encrypt_step(file_path, options, several),
+            _ => decrypt_step(file_path, options, several),
+        })
+        .collect();
+    check_clashes(&mut steps, options);
+    steps
+}
+
+fn encrypt_step(file_path: &Path, options: &Options, several: bool) -> Step {
+    let name = names::file_name(file_path);
+    let default = match options.format {
+        Format::Age => names::with_suffix(file_path, ".age"),
+        Format::Jwe | Format::JweJson => names::with_suffix(file_path, ".jwe"),
+        Format::Cms => names::with_suffix(file_path, ".p7m"),
+        Format::Openssl => names::with_suffix(file_path, ".enc"),
+        Format::Native if options.in_place => file_path.to_path_buf(),
+        Format::Native if options.encrypt_names => file_path.with_file_name("<random name>.enc"),
+        Format::Native => names::with_suffix(file_path, ".enc"),
+    };
+    // An object key is `/`-separated text, whatever the platform
+    let object = |uri: &Path, separator: &str| {
+        let mut key = OsString::from(uri);
+        key.push(separator);
+        key.push(name);
+        key.push(".enc");
+        PathBuf::from(key)
+    };
+    let mut unsendable = None;
+    let output = match options.out.as_deref() {
+        Some(uri) if s3::is_uri(uri) => {
+            match (uri.as_os_str().to_string_lossy().ends_with('/'), several) {
+                (false, false) => uri.to_path_buf(),
+                (true, _) => object(uri, ""),
+                (false, true) => object(uri, "/"),
+            }
+        }
+        Some(url) if http::is_url(url) => {
+            match http::target(&url.to_string_lossy(), file_path, several) {
+                Ok(target) => PathBuf::from(target),
+                Err(err) => {
+                    unsendable = Some(err.to_string());
+                    url.to_path_buf()
+                }
+            }
+        }
+        Some(out_path) => into_output(out_path, &default, several),
+        None => default,
+    };
+    let mut step = new_step(file_path, output);
+    step.named = !options.encrypt_names;
+    // Compression only makes the output smaller, padding up to the length it rounds to, and armor a third bigger
+    let padded = options
+        .padding
+        .map_or(step.bytes, |padding| padding.padded_len(step.bytes));
+    step.needed = padded + padded / TAG_FRACTION + HEADER_ALLOWANCE;
+    if options.encoder.name() != "raw" {
+        step.needed += step.needed / 3 + step.needed / 64;
+    }
+    if let Some(split_size) = options.split_size {
+        let count = step.needed.div_ceil(split_size).max(1);
+        step.notes.push(format!(
+            "in {} volumes of at most {}",
+            count,
+            units::size(split_size)
+        ));
+        step.output = names::with_suffix(&step.output, ".001");
+    }
+    if options.in_place {
+        step.notes.push("replacing it".to_string());
+    }
+    if options.shred {
+        step.notes.push("then shredding the original".to_string());
+    }
+    if options.remove_to_trash {
+        step.notes
+            .push("then moving the original to the trash".to_string());
+    }
+    step.problem = input_problem(file_path).or(unsendable);
+    step
+}
+
+fn decrypt_step(file_path: &Path, options: &Options, several: bool) -> Step {
+    let out_path = options.out.as_deref();
+    // s3:// objects, URLs, split volumes, ranges and legacy files are written like a stream, the others next to themselves
+    let streamed = |name: &OsStr, out_path: Option<&Path>, several: bool| {
+        streamed::output_path(name, out_path, several, Existing::Replace)
+            .unwrap_or_else(|_| PathBuf::from(name))
+    };
+    if s3::is_uri(file_path) {
+        let uri = file_path.to_string_lossy();
+        let name = uri.rsplit('/').next().unwrap_or(&uri);
+        let mut step = new_step(file_path, streamed(OsStr::new(name), out_path, several));
+        step.notes.push("downloaded".to_string());
+        return step;
+    }
+    if http::is_url(file_path) {
+        let name = http::file_name(&file_path.to_string_lossy()).unwrap_or_default();
+        let mut step = new_step(file_path, streamed(OsStr::new(&name), out_path, several));
+        step.notes.push("downloaded".to_string());
+        return step;
+    }
+    if let Some(path) = volumes::split_path(file_path) {
+        let mut step = new_step(path, streamed(names::file_name(path), out_path, several));
+        step.notes.push("from split volumes".to_string());
+        return step;
+    }
+    let output = match out_path {
+        // A legacy file is decrypted next to itself, and a range into the current directory
+        None if options.legacy_nonce.is_some() => {
+            streamed(names::file_name(file_path), file_path.parent(), true)
+        }
+        _ if options.legacy_nonce.is_some() || options.range.is_some() => {
+            streamed(names::file_name(file_path), out_path, several)
+        }
+        _ if options.in_place => file_path.to_path_buf(),
+        Some(out_path) => into_output(out_path, names::strip_extension(file_path), several),
+        None => names::strip_extension(file_path).to_path_buf(),
+    };
+    let mut step = new_step(file_path, output);
+    step.needed = step.bytes;
+    step.problem = input_problem(file_path);
+    if step.problem.is_some()
+        || options.legacy_nonce.is_some()
+        || cms_file::is_cms(file_path)
+        || age_file::is_age(file_path)
+        || jwe_file::is_jwe(file_path)
+        || openssl_file::is_openssl(file_path)
+    {
+        return step;
+    }
+    match report::native_header(file_path) {
+        None => step.problem = Some("not an encrypted file".to_string()),
+        Some(header) => {
+            if header.sealed_name && !options.in_place {
+                step.output = step.output.with_file_name("<name sealed in the file>");
+                step.named = false;
+            }
+            // Decompressed, the output may well need more than the file
+            if header.compression != Codec::None {
+                step.notes
+                    .push(format!("compressed with {}", header.compression.name()));
+            }
+            if let Some(len) = header.payload_len {
+                step.needed = len;
+            }
+        }
+    }
+    if options.in_place {
+        step.notes.push("replacing it".to_string());
+    }
+    step
+}
+
+// A step writing `output`, with the size of `input`
+fn new_step(input: &Path, output: PathBuf) -> Step {
+    let metadata = fs::metadata(input);
+    Step {
+        input: input.to_path_buf(),
+        output,
+        bytes: metadata.as_ref().map_or(0, |metadata| metadata.len()),
+        needed: 0,
+        named: true,
+        notes: Vec::new(),
+        problem: None,
+    }
+}
+
+// Why the input can't be read, if it can't
+fn input_problem(file_path: &Path) -> Option<String> {
+    match fs::metadata(file_path) {
+        Err(err) => Some(err.to_string()),
+        Ok(metadata) if metadata.is_dir() => {
+            Some("a directory (encrypt --archive packs directory trees)".to_string())
+        }
+        Ok(_) => None,
+    }
+}
+
+// Outputs that would be written twice, over an input, into a missing directory or over a file that is kept
+fn check_clashes(steps: &mut [Step], options: &Options) {
+    let inputs: Vec<PathBuf> = steps.iter().map(|step| normalize(&step.input)).collect();
+    let creates_dir = creates_out_dir(options, steps.len() > 1);
+    let mut outputs: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for step in steps.iter_mut() {
+        if step.problem.is_some() || !step.named || streamed::is_remote(&step.output) {
+            continue;
+        }
+        let output = normalize(&step.output);
+        let replaces = !options.in_place && output.exists();
+        let missing_dir = output
+            .parent()
+            .is_some_and(|parent| !parent.as_os_str().is_empty() && !parent.is_dir());
+        step.problem = if let Some(first) = outputs.get(&output) {
+            Some(format!(
+                "{} is also the output of {}",
+                step.output.display(),
+                first.display()
+            ))
+        } else if !options.in_place && inputs.contains(&output) {
+            Some(format!(
+                "{} is one of the files given",
+                step.output.display()
+            ))
+        } else if missing_dir && !creates_dir {
+            Some(format!(
+                "the directory of {} doesn't exist",
+                step.output.display()
+            ))
+        } else if replaces && options.auto_rename {
+            let renamed = encryptor::unclaimed_path(&step.output);
+            step.notes
+                .push(format!("renamed, since {} exists", step.output.display()));
+            step.output = renamed;
+            None
+        } else if replaces && !options.force {
+            Some(format!(
+                "{} already exists, and only --force replaces it",
+                step.output.display()
+            ))
+        } else {
+            if replaces {
+                step.notes.push("replacing the existing file".to_string());
+            }
+            None
+        };
+        outputs.entry(output).or_insert_with(|| step.input.clone());
+    }
+}
+
+// Whether the run creates --out as the directory its outputs go into
+fn creates_out_dir(options: &Options, several: bool) -> bool {
+    options
+        .out
+        .as_deref()
+        .is_some_and(|out_path| several || streamed::ends_with_separator(out_path))
+}
+
+// The space the outputs need on each filesystem they go to, and what is free there: the first directory written to
+// on it, the bytes needed and those free
+fn space(steps: &[Step]) -> Vec<(PathBuf, u64, u64)> {
+    // By filesystem: the first directory written to on it, the bytes needed there and those free
+    let mut needs: BTreeMap<String, (PathBuf, u64, u64)> = BTreeMap::new();
+    for step in steps {
+        if step.problem.is_some() || streamed::is_remote(&step.output) {
+            continue;
+        }
+        let dir = existing_dir(&step.output);
+        if let Some((filesystem, free)) = free_space(&dir) {
+            needs.entry(filesystem).or_insert((dir, 0, free)).1 += step.needed;
+        }
+    }
+    needs.into_values().collect()
+}
+
+// The closest directory of `path` that exists, where its output would be created
+fn existing_dir(path: &Path) -> PathBuf {
+    let mut dir = path.parent().unwrap_or(Path::new("."));
+    loop {
+        if dir.as_os_str().is_empty() {
+            return PathBuf::from(".");
+        }
+        if dir.is_dir() {
+            return dir.to_path_buf();
+        }
+        match dir.parent() {
+            Some(parent) => dir = parent,
+            None => return PathBuf::from("."),
+        }
+    }
+}
+
+// An ID of the filesystem `dir` is on, and the bytes free on it for this user
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<(String, u64)> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    let device = fs::metadata(dir).ok()?.dev();
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // Safety: the path is a valid C string and statvfs only writes into `stats`, which is read once it succeeded
+    let stats = unsafe {
+        if libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stats.assume_init()
+    };
+    Some((
+        device.to_string(),
+        encryptor::fault::free_space((stats.f_bavail as u64).saturating_mul(stats.f_frsize as u64)),
+    ))
+}
+
+#[cfg(not(unix))]
+fn free_space(_dir: &Path) -> Option<(String, u64)> {
+    None
+}
+
+// Where an output asked for with --out goes, like Encryptor::encrypt_file_to puts it: into it when it is (or the run
+// makes it) a directory, otherwise there
+fn into_output(out_path: &Path, default: &Path, several: bool) -> PathBuf {
+    match several || streamed::ends_with_separator(out_path) || out_path.is_dir() {
+        true => out_path.join(names::file_name(default)),
+        false => out_path.to_path_buf(),
+    }
+}
+
+// A path compared with others, whether they spell it the same way or not
+fn normalize(path: &Path) -> PathBuf {
+    let (parent, name) = (path.parent().unwrap_or(Path::new("")), path.file_name());
+    let parent = match parent.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => parent,
+    };
+    match (fs::canonicalize(parent), name) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
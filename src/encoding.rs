@@ -0,0 +1,348 @@
+//! How encrypted files are written out: raw binary, or wrapped for channels that aren't binary-safe.
+//!
+//! Every encoding wraps the complete binary file (header, ciphertext and header backup), so it changes nothing
+//! about what is authenticated. Choose one with [`crate::Encryptor::with_encoder`]; decryption detects every
+//! built-in encoding on its own, and tries the encryptor's own encoder first.
+//!
+//! - [`Raw`]: the binary file as is (the default)
+//! - [`Armor`]: base64 text between BEGIN and END lines, see the [`crate::armor`] module
+//! - [`ArmoredSplit`]: armor cut into numbered parts of a fixed number of lines, for channels that limit the
+//!   length of a message; the parts can be decoded in any order
+//! - [`JsonEnvelope`]: a JSON object with the header metadata next to the base64 file, for APIs and databases
+//!
+//! Library users can add their own by implementing [`Encoder`].
+#[cfg(feature = "armor")]
+use crate::armor;
+#[cfg(feature = "json")]
+use crate::header::Header;
+use crate::header::{HeaderError, MAGIC};
+use std::sync::Arc;
+
+/// One way of writing out an encrypted file
+pub trait Encoder: Send + Sync + std::fmt::Debug {
+    /// The name used for this encoding on the command line (--encoding)
+    fn name(&self) -> &str;
+
+    /// Encode a complete encrypted file
+    fn encode(&self, encrypted: Vec<u8>) -> Vec<u8>;
+
+    /// Whether `data` looks like the output of [`Encoder::encode`]
+    fn detect(&self, data: &[u8]) -> bool;
+
+    /// The encrypted file inside the output of [`Encoder::encode`]
+    fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>, HeaderError>;
+
+    /// Whether [`Encoder::encode`] returns the encrypted file unchanged, so it can be written as it is produced
+    fn is_identity(&self) -> bool {
+        false
+    }
+}
+
+/// The binary file as is
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Raw;
+
+impl Encoder for Raw {
+    fn name(&self) -> &str {
+        "raw"
+    }
+
+    fn encode(&self, encrypted: Vec<u8>) -> Vec<u8> {
+        encrypted
+    }
+
+    fn is_identity(&self) -> bool {
+        true
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>, HeaderError> {
+        Ok(data)
+    }
+}
+
+/// ASCII armor, see the [`crate::armor`] module
+#[cfg(feature = "armor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Armor;
+
+#[cfg(feature = "armor")]
+impl Encoder for Armor {
+    fn name(&self) -> &str {
+        "armor"
+    }
+
+    fn encode(&self, encrypted: Vec<u8>) -> Vec<u8> {
+        armor::encode(&encrypted)
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        armor::is_armored(data)
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>, HeaderError> {
+        armor::decode(&data)
+    }
+}
+
+/// Armor cut into numbered parts of at most `lines_per_part` base64 lines each, every part between its own
+/// `-----BEGIN ENCRYPTOR FILE PART 1/3-----` and `-----END ENCRYPTOR FILE PART 1/3-----` lines, so each
+/// fits in one message of a line-limited channel. Text around the parts is ignored when decoding.
+#[cfg(feature = "armor")]
+#[derive(Debug, Clone, Copy)]
+pub struct ArmoredSplit {
+    pub lines_per_part: usize,
+}
+
+#[cfg(feature = "armor")]
+impl Default for ArmoredSplit {
+    /// 100 lines of 64 characters, about 4.7 KiB of the binary file per part
+    fn default() -> Self {
+        ArmoredSplit {
+            lines_per_part: 100,
+        }
+    }
+}
+
+#[cfg(feature = "armor")]
+const PART_BEGIN: &str = "-----BEGIN ENCRYPTOR FILE PART ";
+#[cfg(feature = "armor")]
+const PART_END: &str = "-----END ENCRYPTOR FILE PART ";
+
+#[cfg(feature = "armor")]
+impl Encoder for ArmoredSplit {
+    fn name(&self) -> &str {
+        "armored-split"
+    }
+
+    fn encode(&self, encrypted: Vec<u8>) -> Vec<u8> {
+        use base64::Engine;
+
+        let encoded = base64::engine::general_purpose::STANDARD.encode(encrypted);
+        let lines: Vec<&str> = encoded
+            .as_bytes()
+            .chunks(armor::LINE_LEN)
+            // Base64 is ASCII, so every chunk is valid UTF-8
+            .map(|line| std::str::from_utf8(line).unwrap_or_default())
+            .collect();
+        let parts: Vec<&[&str]> = lines.chunks(self.lines_per_part.max(1)).collect();
+        let mut text = String::with_capacity(
+            encoded.len() + encoded.len() / armor::LINE_LEN + parts.len() * 80,
+        );
+        for (index, part) in parts.iter().enumerate() {
+            let number = format!("{}/{}-----", index + 1, parts.len());
+            if index > 0 {
+                text.push('\n');
+            }
+            text.push_str(PART_BEGIN);
+            text.push_str(&number);
+            text.push('\n');
+            for line in *part {
+                text.push_str(line);
+                text.push('\n');
+            }
+            text.push_str(PART_END);
+            text.push_str(&number);
+            text.push('\n');
+        }
+        text.into_bytes()
+    }
+
+    // The parts may be pasted with other text around them
+    fn detect(&self, data: &[u8]) -> bool {
+        data.windows(PART_BEGIN.len())
+            .any(|window| window == PART_BEGIN.as_bytes())
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>, HeaderError> {
+        use base64::Engine;
+
+        let damaged =
+            |reason: &str| HeaderError::InvalidEncoding(format!("armored parts: {}", reason));
+        let text = std::str::from_utf8(&data).map_err(|_| damaged("not text"))?;
+        let mut parts: Vec<Option<String>> = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find(PART_BEGIN) {
+            let after = &rest[start + PART_BEGIN.len()..];
+            let (number, body) = after
+                .split_once("-----")
+                .ok_or_else(|| damaged("a BEGIN line is damaged"))?;
+            let (index, total) = number
+                .split_once('/')
+                .and_then(|(index, total)| {
+                    Some((index.parse::<usize>().ok()?, total.parse::<usize>().ok()?))
+                })
+                .filter(|(index, total)| (1..=*total).contains(index))
+                .ok_or_else(|| damaged("a part number is damaged"))?;
+            let end = format!("{}{}-----", PART_END, number);
+            let (body, after_end) = body
+                .split_once(&end)
+                .ok_or_else(|| damaged(&format!("the END line of part {} is missing", number)))?;
+            if parts.is_empty() {
+                parts.resize(total, None);
+            } else if parts.len() != total {
+                return Err(damaged("the parts disagree on how many there are"));
+            }
+            // The same part pasted twice is harmless
+            parts[index - 1] = Some(body.split_whitespace().collect());
+            rest = after_end;
+        }
+
+        if parts.is_empty() {
+            return Err(damaged("no parts found"));
+        }
+        let missing: Vec<String> = (1..=parts.len())
+            .filter(|index| parts[index - 1].is_none())
+            .map(|index| index.to_string())
+            .collect();
+        if !missing.is_empty() {
+            return Err(damaged(&format!(
+                "part {} of {} is missing",
+                missing.join(", "),
+                parts.len()
+            )));
+        }
+        let encoded: String = parts.into_iter().flatten().collect();
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|_| damaged("invalid base64"))
+    }
+}
+
+/// A JSON object with the header metadata next to the base64 of the binary file:
+///
+/// ```text
+/// {"algorithm":"aes256gcm","compression":"none","data":"RU5DUgMBAQ...","file_name":"test.txt",
+///  "format":"encryptor","kdf":"argon2id","size":120,"version":3}
+/// ```
+///
+/// The metadata is informational, copied from the header; `data` alone is decrypted, and its header is
+/// authenticated as usual.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonEnvelope;
+
+#[cfg(feature = "json")]
+impl Encoder for JsonEnvelope {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn encode(&self, encrypted: Vec<u8>) -> Vec<u8> {
+        use base64::Engine;
+
+        let mut envelope = serde_json::Map::new();
+        envelope.insert("format".into(), "encryptor".into());
+        if let Ok((header, _)) = Header::parse(&encrypted) {
+            envelope.insert("version".into(), header.version.into());
+            envelope.insert("algorithm".into(), header.algorithm.name().into());
+            envelope.insert("kdf".into(), header.kdf.name().into());
+            envelope.insert("compression".into(), header.compression.name().into());
+            envelope.insert("file_name".into(), header.file_name.into());
+        }
+        envelope.insert("size".into(), encrypted.len().into());
+        envelope.insert(
+            "data".into(),
+            base64::engine::general_purpose::STANDARD
+                .encode(&encrypted)
+                .into(),
+        );
+        let mut json = serde_json::Value::Object(envelope).to_string().into_bytes();
+        json.push(b'\n');
+        json
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        data.trim_ascii_start().starts_with(b"{")
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>, HeaderError> {
+        use base64::Engine;
+
+        let damaged =
+            |reason: &str| HeaderError::InvalidEncoding(format!("JSON envelope: {}", reason));
+        let envelope: serde_json::Value =
+            serde_json::from_slice(&data).map_err(|_| damaged("invalid JSON"))?;
+        if envelope["format"] != "encryptor" {
+            return Err(damaged("not an encryptor envelope"));
+        }
+        let encoded = envelope["data"]
+            .as_str()
+            .ok_or_else(|| damaged("'data' is missing"))?;
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|_| damaged("'data' is not base64"))
+    }
+}
+
+/// Look up a built-in encoding by its command line name: `raw`, `armor`, `armored-split[:<lines per part>]`
+/// or `json`
+pub fn from_name(spec: &str) -> Result<Arc<dyn Encoder>, String> {
+    let (name, lines) = match spec.split_once(':') {
+        Some((name, lines)) => (name, Some(lines)),
+        None => (spec, None),
+    };
+    match (name, lines) {
+        ("raw", None) => Ok(Arc::new(Raw)),
+        #[cfg(feature = "armor")]
+        ("armor", None) => Ok(Arc::new(Armor)),
+        #[cfg(feature = "armor")]
+        ("armored-split", lines) => {
+            let mut split = ArmoredSplit::default();
+            if let Some(lines) = lines {
+                split.lines_per_part = lines
+                    .parse()
+                    .ok()
+                    .filter(|lines| *lines > 0)
+                    .ok_or_else(|| format!("invalid number of lines per part '{}'", lines))?;
+            }
+            Ok(Arc::new(split))
+        }
+        #[cfg(feature = "json")]
+        ("json", None) => Ok(Arc::new(JsonEnvelope)),
+        _ => Err(format!(
+            "unknown encoding '{}' (expected one of: {})",
+            spec,
+            NAMES.join(", ")
+        )),
+    }
+}
+
+// The encodings built into this build, as accepted by from_name
+const NAMES: &[&str] = &[
+    "raw",
+    #[cfg(feature = "armor")]
+    "armor",
+    #[cfg(feature = "armor")]
+    "armored-split[:<lines>]",
+    #[cfg(feature = "json")]
+    "json",
+];
+
+/// The encrypted file inside `data`, written with any built-in encoding. Data no built-in encoding recognizes
+/// is returned as is, so [`Header::parse`] reports what's wrong with it.
+pub fn decode(data: Vec<u8>) -> Result<Vec<u8>, HeaderError> {
+    detect(&data).decode(data)
+}
+
+// The built-in encoding `data` was written with, or Raw for a binary file and data no encoding recognizes
+pub(crate) fn detect(data: &[u8]) -> Arc<dyn Encoder> {
+    let builtins: Vec<Arc<dyn Encoder>> = vec![
+        #[cfg(feature = "armor")]
+        Arc::new(Armor),
+        #[cfg(feature = "armor")]
+        Arc::new(ArmoredSplit::default()),
+        #[cfg(feature = "json")]
+        Arc::new(JsonEnvelope),
+    ];
+    if data.starts_with(MAGIC) {
+        return Arc::new(Raw);
+    }
+    builtins
+        .into_iter()
+        .find(|encoder| encoder.detect(data))
+        .unwrap_or_else(|| Arc::new(Raw))
+}
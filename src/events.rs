@@ -0,0 +1,106 @@
+// Machine-readable progress events for GUI wrappers and other frontends.
+// When --progress-fd is given, every operation writes newline-delimited JSON events to that file descriptor,
+// separate from the human-readable status lines on stdout:
+//
+//   {"event":"started","operation":"encrypt","file":"test.txt"}
+//   {"event":"progress","file":"test.txt","percent":40}
+//   {"event":"finished","file":"test.txt","output":"test.txt.enc"}
+//   {"event":"error","file":"test.txt","code":"ENC_AUTH_FAIL","exit_code":4,"retryable":false,"message":"..."}
+//
+// Paths are given as they are shown, with any bytes that aren't UTF-8 replaced.
+use encryptor::EncryptError;
+use serde_json::{json, Value}; // Used to build the JSON events
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Where progress events go. Events are dropped when no descriptor was requested.
+pub struct ProgressSink {
+    file: Option<File>,
+    last_percent: HashMap<PathBuf, u8>, // Last percentage reported for each file, since several can run at once
+}
+
+impl ProgressSink {
+    // A sink that discards every event
+    pub fn disabled() -> Self {
+        ProgressSink {
+            file: None,
+            last_percent: HashMap::new(),
+        }
+    }
+
+    // Write events to an already open file descriptor inherited from the parent process
+    #[cfg(unix)]
+    pub fn from_fd(fd: i32) -> io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+
+        // Safety: the descriptor was handed to us explicitly on the command line for this purpose,
+        // and nothing else in the program uses it. Checking its metadata catches descriptors that are not open.
+        let file = unsafe { File::from_raw_fd(fd) };
+        if let Err(err) = file.metadata() {
+            // Don't let File close a descriptor we don't own
+            std::mem::forget(file);
+            return Err(err);
+        }
+        Ok(ProgressSink {
+            file: Some(file),
+            last_percent: HashMap::new(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_fd(_fd: i32) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--progress-fd is only supported on Unix",
+        ))
+    }
+
+    pub fn started(&mut self, operation: &str, file: &Path) {
+        self.last_percent.remove(file);
+        self.emit(
+            json!({"event": "started", "operation": operation, "file": file.to_string_lossy()}),
+        );
+    }
+
+    // Only emit when the percentage changes, since the library reports progress for every chunk
+    pub fn percent(&mut self, file: &Path, percent: u8) {
+        if self.last_percent.get(file) == Some(&percent) {
+            return;
+        }
+        self.last_percent.insert(file.to_path_buf(), percent);
+        self.emit(json!({"event": "progress", "file": file.to_string_lossy(), "percent": percent}));
+    }
+
+    pub fn finished(&mut self, file: &Path, output: &Path) {
+        self.last_percent.remove(file);
+        self.emit(json!({
+            "event": "finished",
+            "file": file.to_string_lossy(),
+            "output": output.to_string_lossy(),
+        }));
+    }
+
+    pub fn error(&mut self, file: &Path, err: &EncryptError) {
+        self.last_percent.remove(file);
+        let code = err.code();
+        self.emit(json!({
+            "event": "error",
+            "file": file.to_string_lossy(),
+            "code": code.as_str(),
+            "exit_code": code.number(),
+            "retryable": err.is_retryable(),
+            "message": err.to_string(),
+        }));
+    }
+
+    // Write one event per line. Progress reporting is best-effort: a frontend that closed
+    // its end of the pipe must not make the encryption itself fail.
+    fn emit(&mut self, mut event: Value) {
+        crate::redact::value(&mut event);
+        if let Some(file) = self.file.as_mut() {
+            let _ = writeln!(file, "{}", event).and_then(|_| file.flush());
+        }
+    }
+}
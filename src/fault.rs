@@ -0,0 +1,140 @@
+//! Fault injection for testing how files survive real failures. Only active with the test-only
+//! `fault-injection` feature; without it every hook does nothing and compiles away.
+//!
+//! Faults are read once from the `ENCRYPTOR_FAULTS` environment variable, as comma-separated rules:
+//!
+//! - `short-read`: every read returns at most a few bytes, as a slow network filesystem might
+//! - `enospc@<n>`: writing an output fails with "no space left on device" once `n` bytes of it are written
+//! - `free@<n>`: every filesystem has only `n` bytes free as far as [`free_space`] is concerned
+//! - `flip@<n>`: the lowest bit of byte `n` of every output is flipped on its way to the disk
+//! - `abort@<n>`: the process aborts once `n` bytes of an output are written, leaving a partial temporary file behind
+//! - `abort:<point>`: the process aborts when it reaches the named point, see [`point`]
+//! - `repeat-nonce`: every nonce is the same, as with a broken random number generator
+//!
+//! Since faults are configured per process, tests run the `encryptor` binary with the variable set,
+//! see `tests/fault_injection.rs`.
+use std::borrow::Cow;
+use std::io;
+
+/// Abort the process here if `abort:<name>` was requested. The library stops at `written` once an output file
+/// is complete, and the command line tool at `journal-<step>` once a journal step is recorded and at `shred`
+/// once an original is overwritten but not yet removed.
+pub fn point(name: &str) {
+    #[cfg(feature = "fault-injection")]
+    if plan().abort_points.iter().any(|point| point == name) {
+        std::process::abort();
+    }
+    #[cfg(not(feature = "fault-injection"))]
+    let _ = name;
+}
+
+// How many of `len` bytes the next read may return
+pub(crate) fn read_len(len: usize) -> usize {
+    #[cfg(feature = "fault-injection")]
+    if plan().short_read {
+        return len.min(7);
+    }
+    len
+}
+
+// The bytes to write for `chunk`, which starts `offset` bytes into the output, and whether the write then fails
+pub(crate) fn write(chunk: &[u8], offset: u64) -> (Cow<'_, [u8]>, io::Result<()>) {
+    #[cfg(feature = "fault-injection")]
+    {
+        let plan = plan();
+        let end = offset + chunk.len() as u64;
+        if let Some(limit) = plan.no_space_at.filter(|limit| *limit < end) {
+            let kept = limit.saturating_sub(offset) as usize;
+            let error = io::Error::new(
+                io::ErrorKind::StorageFull,
+                "no space left on device (injected)",
+            );
+            return (Cow::Borrowed(&chunk[..kept]), Err(error));
+        }
+        // Only the bytes before the abort point reach the file, then `written` aborts
+        if let Some(limit) = plan.abort_at.filter(|limit| *limit < end) {
+            let kept = limit.saturating_sub(offset) as usize;
+            return (Cow::Borrowed(&chunk[..kept]), Ok(()));
+        }
+        if let Some(flip) = plan.flip_at.filter(|flip| (offset..end).contains(flip)) {
+            let mut flipped = chunk.to_vec();
+            flipped[(flip - offset) as usize] ^= 1;
+            return (Cow::Owned(flipped), Ok(()));
+        }
+    }
+    #[cfg(not(feature = "fault-injection"))]
+    let _ = offset;
+    (Cow::Borrowed(chunk), Ok(()))
+}
+
+/// The bytes free on a filesystem that has `free` of them, or fewer if `free@<n>` was requested. The command line
+/// tool's preflight and `--dry-run` ask it what they measured.
+pub fn free_space(free: u64) -> u64 {
+    #[cfg(feature = "fault-injection")]
+    if let Some(limit) = plan().free {
+        return free.min(limit);
+    }
+    free
+}
+
+// Replace a freshly generated nonce with the same bytes every time if `repeat-nonce` was requested
+pub(crate) fn nonce(nonce: &mut [u8]) {
+    #[cfg(feature = "fault-injection")]
+    if plan().repeat_nonce {
+        nonce.fill(0x42);
+    }
+    #[cfg(not(feature = "fault-injection"))]
+    let _ = nonce;
+}
+
+// Called once `written` bytes of an output are on their way to the disk
+pub(crate) fn written(written: u64) {
+    #[cfg(feature = "fault-injection")]
+    if plan().abort_at.is_some_and(|limit| limit <= written) {
+        std::process::abort();
+    }
+    #[cfg(not(feature = "fault-injection"))]
+    let _ = written;
+}
+
+#[cfg(feature = "fault-injection")]
+#[derive(Default)]
+struct Plan {
+    short_read: bool,
+    repeat_nonce: bool,
+    no_space_at: Option<u64>,
+    free: Option<u64>,
+    flip_at: Option<u64>,
+    abort_at: Option<u64>,
+    abort_points: Vec<String>,
+}
+
+// The faults from ENCRYPTOR_FAULTS. A rule that can't be parsed panics, so a typo can't make a test pass.
+#[cfg(feature = "fault-injection")]
+fn plan() -> &'static Plan {
+    static PLAN: std::sync::OnceLock<Plan> = std::sync::OnceLock::new();
+    PLAN.get_or_init(|| {
+        let mut plan = Plan::default();
+        let rules = std::env::var("ENCRYPTOR_FAULTS").unwrap_or_default();
+        for rule in rules.split(',').filter(|rule| !rule.is_empty()) {
+            let offset = |value: &str| {
+                value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("ENCRYPTOR_FAULTS: invalid offset in '{}'", rule))
+            };
+            match rule.split_once(['@', ':']) {
+                None if rule == "short-read" => plan.short_read = true,
+                None if rule == "repeat-nonce" => plan.repeat_nonce = true,
+                Some(("enospc", value)) => plan.no_space_at = Some(offset(value)),
+                Some(("free", value)) => plan.free = Some(offset(value)),
+                Some(("flip", value)) => plan.flip_at = Some(offset(value)),
+                Some(("abort", value)) if rule.starts_with("abort@") => {
+                    plan.abort_at = Some(offset(value))
+                }
+                Some(("abort", point)) => plan.abort_points.push(point.to_string()),
+                _ => panic!("ENCRYPTOR_FAULTS: unknown fault '{}'", rule),
+            }
+        }
+        plan
+    })
+}
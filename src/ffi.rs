@@ -0,0 +1,166 @@
+//! A C interface, for embedding the library in C, C++ or Swift applications.
+//!
+//! The functions here are built into the `cdylib` and `staticlib` of the crate with the `ffi` feature, and declared
+//! in `include/encryptor.h`, which the build regenerates from this file with cbindgen. They produce and consume the
+//! same format as [`Encryptor::encrypt_bytes`], so files written by a C application decrypt with the command line
+//! tool, and the other way around:
+//!
+//! ```c
+//! #include "encryptor.h"
+//!
+//! uint8_t *sealed;
+//! size_t sealed_len;
+//! if (encryptor_encrypt_buf("correct horse", plaintext, plaintext_len, &sealed, &sealed_len) != ENCRYPTOR_OK) {
+//!     fprintf(stderr, "%s\n", encryptor_last_error());
+//!     return 1;
+//! }
+//! write_file(sealed, sealed_len);
+//! encryptor_free_buf(sealed, sealed_len);
+//! ```
+//!
+//! Every function returns `ENCRYPTOR_OK` (0) on success, `ENCRYPTOR_USAGE` (2) for invalid arguments, such as a
+//! null pointer or a password that isn't UTF-8, and otherwise the number of the [`ErrorCode`](crate::ErrorCode) of
+//! the failure (the same numbers as the exit codes of the command line tool). `encryptor_last_error` describes the
+//! last failure on the calling thread. A panic is caught and returned as `ENCRYPTOR_FAILED` (1), never unwound
+//! into C.
+use crate::{EncryptError, Encryptor};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+/// Returned on success
+pub const ENCRYPTOR_OK: c_int = 0;
+/// Returned when the library panicked
+pub const ENCRYPTOR_FAILED: c_int = 1;
+/// Returned for invalid arguments
+pub const ENCRYPTOR_USAGE: c_int = 2;
+
+thread_local! {
+    // The message of the last failure on this thread, for encryptor_last_error
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Encrypt the `input_len` bytes at `input` with a key derived from the NUL-terminated UTF-8 `password`, storing
+/// a newly allocated buffer of the output in `*output` and its length in `*output_len`. Free the buffer with
+/// [`encryptor_free_buf`].
+///
+/// # Safety
+///
+/// `password` must be a NUL-terminated string, `input` must point to `input_len` readable bytes (or may be null if
+/// `input_len` is 0), and `output` and `output_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn encryptor_encrypt_buf(
+    password: *const c_char,
+    input: *const u8,
+    input_len: usize,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> c_int {
+    run(output, output_len, || {
+        let encryptor = Encryptor::new(password_arg(password)?);
+        Ok(encryptor.encrypt_bytes(bytes_arg(input, input_len)?)?)
+    })
+}
+
+/// Decrypt the `input_len` bytes at `input` with a key derived from the NUL-terminated UTF-8 `password`, storing
+/// a newly allocated buffer of the plaintext in `*output` and its length in `*output_len`. Free the buffer with
+/// [`encryptor_free_buf`]. Nothing is stored if the input doesn't authenticate.
+///
+/// # Safety
+///
+/// The same as for [`encryptor_encrypt_buf`].
+#[no_mangle]
+pub unsafe extern "C" fn encryptor_decrypt_buf(
+    password: *const c_char,
+    input: *const u8,
+    input_len: usize,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> c_int {
+    run(output, output_len, || {
+        let encryptor = Encryptor::new(password_arg(password)?);
+        Ok(encryptor.decrypt_bytes(bytes_arg(input, input_len)?)?)
+    })
+}
+
+/// Free a buffer returned by [`encryptor_encrypt_buf`] or [`encryptor_decrypt_buf`]. Null is ignored.
+///
+/// # Safety
+///
+/// `buf` and `len` must be a buffer and its length as returned by this library, not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn encryptor_free_buf(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)));
+    }
+}
+
+/// A description of the last failure on the calling thread, starting with its error code (e.g. `ENC_AUTH_FAIL`),
+/// or null if nothing failed yet. The string stays valid until the next call into the library on the same thread.
+#[no_mangle]
+pub extern "C" fn encryptor_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |error| error.as_ptr())
+    })
+}
+
+// Why a call failed
+enum Failure {
+    Usage(&'static str),
+    Encrypt(EncryptError),
+}
+
+impl From<EncryptError> for Failure {
+    fn from(error: EncryptError) -> Self {
+        Failure::Encrypt(error)
+    }
+}
+
+// Run `work` and store its output for the caller, returning the status code and recording the error of a failure
+unsafe fn run(
+    output: *mut *mut u8,
+    output_len: *mut usize,
+    work: impl FnOnce() -> Result<Vec<u8>, Failure>,
+) -> c_int {
+    let result = match output.is_null() || output_len.is_null() {
+        true => Ok(Err(Failure::Usage("the output pointers must not be null"))),
+        false => panic::catch_unwind(AssertUnwindSafe(work)),
+    };
+    let (status, message) = match result {
+        Ok(Ok(bytes)) => {
+            *output_len = bytes.len();
+            *output = Box::into_raw(bytes.into_boxed_slice()).cast();
+            return ENCRYPTOR_OK;
+        }
+        Ok(Err(Failure::Usage(message))) => (ENCRYPTOR_USAGE, message.to_string()),
+        Ok(Err(Failure::Encrypt(error))) => (error.code().number(), error.to_string()),
+        Err(_) => (ENCRYPTOR_FAILED, "the library panicked".to_string()),
+    };
+    // A message with a NUL in it is cut off there
+    let message = CString::new(message).unwrap_or_else(|err| {
+        let end = err.nul_position();
+        CString::new(&err.into_vec()[..end]).unwrap_or_default()
+    });
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    status
+}
+
+unsafe fn password_arg<'a>(password: *const c_char) -> Result<&'a str, Failure> {
+    if password.is_null() {
+        return Err(Failure::Usage("the password must not be null"));
+    }
+    CStr::from_ptr(password)
+        .to_str()
+        .map_err(|_| Failure::Usage("the password must be UTF-8"))
+}
+
+unsafe fn bytes_arg<'a>(bytes: *const u8, len: usize) -> Result<&'a [u8], Failure> {
+    match bytes.is_null() {
+        true if len == 0 => Ok(&[]),
+        true => Err(Failure::Usage("the input must not be null")),
+        false => Ok(slice::from_raw_parts(bytes, len)),
+    }
+}
@@ -0,0 +1,177 @@
+//! Files whose key is held by a FIDO2 security key (a YubiKey, SoloKey...), through its hmac-secret extension.
+//!
+//! A credential made with the hmac-secret extension turns any 32-byte salt into a 32-byte secret that only the
+//! security key holding it can compute, and only once the user has touched it. Every file gets a random file key,
+//! sealed with the secret for a random salt; the header carries one [`Stanza`] with the salt, the credential's ID
+//! and the sealed key, so decrypting needs the same credential on the same security key. An Encryptor uses one salt
+//! for all the files it encrypts and remembers the secret of every salt it has seen, so a batch asks for one touch.
+//!
+//! Talking to the security key is left to an [`Authenticator`]: the command line speaks CTAP2 to it over USB
+//! (`encryptor keygen --fido2`, `--fido2`), and other applications can bring their own, e.g. over NFC.
+//!
+//! ```no_run
+//! use encryptor::fido2::Authenticator;
+//! use encryptor::{EncryptError, Encryptor};
+//! use std::sync::Arc;
+//! use zeroize::Zeroizing;
+//!
+//! struct SecurityKey;
+//!
+//! impl Authenticator for SecurityKey {
+//!     fn hmac_secret(&self, _credential: &[u8], _salt: &[u8; 32]) -> Result<Zeroizing<[u8; 32]>, EncryptError> {
+//!         // A getAssertion with the hmac-secret extension, on a real security key
+//! #       unimplemented!()
+//!     }
+//! }
+//!
+//! let credential = std::fs::read("credential.id")?;
+//! let encryptor = Encryptor::from_fido2(credential, Arc::new(SecurityKey));
+//! let sealed = encryptor.encrypt_bytes(b"hello")?;
+//! assert_eq!(encryptor.decrypt_bytes(&sealed)?, b"hello");
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use crate::header::{Stanza, StanzaKind};
+use crate::rng::RandomSource;
+use crate::{
+    cipher, fault, Algorithm, CryptoError, EncryptError, KeyBytes, NonceLog, KEY_LEN, NONCE_LEN,
+    TAG_LEN,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use zeroize::Zeroizing;
+
+/// Length in bytes of the salts given to the hmac-secret extension
+pub const SALT_LEN: usize = 32;
+
+// Keeps the wrapping key apart from any other use of the same hmac-secret
+const CONTEXT: &[u8] = b"encryptor fido2 stanza";
+// A FIDO2 stanza body is the salt, a nonce and the sealed file key, followed by the credential's ID
+const FIXED_LEN: usize = SALT_LEN + NONCE_LEN + KEY_LEN + TAG_LEN;
+
+/// A FIDO2 authenticator able to evaluate the hmac-secret extension of its credentials
+pub trait Authenticator: Send + Sync {
+    /// The hmac-secret of the credential with the ID `credential` for `salt`. This is where the user touches the
+    /// security key (and enters its PIN, if the credential needs one); the secret must be the same every time.
+    fn hmac_secret(
+        &self,
+        credential: &[u8],
+        salt: &[u8; SALT_LEN],
+    ) -> Result<Zeroizing<[u8; KEY_LEN]>, EncryptError>;
+}
+
+// A credential on a security key, and the secrets it has computed so far, shared by the clones of an Encryptor
+pub(crate) struct Fido2Key {
+    credential: Vec<u8>,
+    authenticator: Arc<dyn Authenticator>,
+    session_salt: OnceLock<[u8; SALT_LEN]>,
+    secrets: Mutex<HashMap<[u8; SALT_LEN], KeyBytes>>,
+}
+
+impl Fido2Key {
+    pub(crate) fn new(credential: Vec<u8>, authenticator: Arc<dyn Authenticator>) -> Self {
+        Fido2Key {
+            credential,
+            authenticator,
+            session_salt: OnceLock::new(),
+            secrets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // The hmac-secret for `salt`, asking the security key only the first time. The lock is held meanwhile, so
+    // workers decrypting files of the same batch wait for that one touch instead of asking for theirs.
+    fn secret(&self, salt: &[u8; SALT_LEN]) -> Result<KeyBytes, EncryptError> {
+        let mut secrets = self.secrets.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(secret) = secrets.get(salt) {
+            return Ok(secret.clone());
+        }
+        let secret = self.authenticator.hmac_secret(&self.credential, salt)?;
+        crate::protect(&secret[..]);
+        secrets.insert(*salt, secret.clone());
+        Ok(secret)
+    }
+
+    // The salt of every file this Encryptor wraps a key for, drawn the first time
+    fn session_salt(&self, rng: &dyn RandomSource) -> Result<[u8; SALT_LEN], EncryptError> {
+        if let Some(salt) = self.session_salt.get() {
+            return Ok(*salt);
+        }
+        let mut salt = [0u8; SALT_LEN];
+        rng.fill(&mut salt)?;
+        Ok(*self.session_salt.get_or_init(|| salt))
+    }
+}
+
+// Wrap the file key for the credential of `key`, sealing it with `algorithm`. The wrapping key is reused for every
+// file, so its nonces go into `nonces` like those of the files.
+pub(crate) fn wrap(
+    file_key: &[u8; KEY_LEN],
+    key: &Fido2Key,
+    algorithm: Algorithm,
+    nonces: Option<&NonceLog>,
+    rng: &dyn RandomSource,
+) -> Result<Stanza, EncryptError> {
+    let salt = key.session_salt(rng)?;
+    let wrap_key = key.secret(&salt)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut nonce)?;
+    fault::nonce(&mut nonce);
+    if let Some(nonces) = nonces {
+        nonces.record(&wrap_key, &nonce, false)?;
+    }
+
+    let mut sealed = file_key.to_vec();
+    cipher::seal_in_place(
+        algorithm,
+        &wrap_key,
+        &nonce,
+        &context(algorithm, &key.credential),
+        &mut sealed,
+    )?;
+
+    let mut body = Vec::with_capacity(FIXED_LEN + key.credential.len());
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&nonce);
+    body.extend_from_slice(&sealed);
+    body.extend_from_slice(&key.credential);
+    Ok(Stanza {
+        kind: StanzaKind::Fido2,
+        body,
+    })
+}
+
+// Open the stanza wrapped for the credential of `key`, asking the security key for its secret, and return the
+// file key from it. Returns None when no stanza was wrapped for this credential, and an authentication failure
+// when the stanza for it doesn't open (it was damaged, or the credential was made again on another security key).
+pub(crate) fn unwrap(
+    stanzas: &[Stanza],
+    key: &Fido2Key,
+    algorithm: Algorithm,
+) -> Result<Option<KeyBytes>, EncryptError> {
+    for stanza in stanzas {
+        if stanza.kind != StanzaKind::Fido2
+            || stanza.body.len() <= FIXED_LEN
+            || stanza.body[FIXED_LEN..] != key.credential[..]
+        {
+            continue;
+        }
+        let (salt, rest) = stanza.body.split_at(SALT_LEN);
+        let (nonce, rest) = rest.split_at(NONCE_LEN);
+        let sealed = &rest[..KEY_LEN + TAG_LEN];
+        let wrap_key = key.secret(salt.try_into().expect("a salt is SALT_LEN bytes"))?;
+
+        let mut file_key = Zeroizing::new(sealed.to_vec());
+        let context = context(algorithm, &key.credential);
+        cipher::open_in_place(algorithm, &wrap_key, nonce, &context, &mut file_key)?;
+        let file_key = file_key[..].try_into().map_err(|_| CryptoError)?;
+        return Ok(Some(Zeroizing::new(file_key)));
+    }
+    Ok(None)
+}
+
+// The wrapping key is bound to the algorithm it seals with and to the credential it came from
+fn context(algorithm: Algorithm, credential: &[u8]) -> Vec<u8> {
+    let mut context = CONTEXT.to_vec();
+    context.push(algorithm.id());
+    context.extend_from_slice(credential);
+    context
+}
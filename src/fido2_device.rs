@@ -0,0 +1,313 @@
+// FIDO2 security keys for --fido2 and `keygen --fido2`: a credential made with the hmac-secret extension on a
+// security key (a YubiKey, SoloKey...), whose secret wraps the key of every file (see the `encryptor::fido2` module).
+//
+// CTAP2 is spoken to the security key over USB HID by the ctap-hid-fido2 crate: MakeCredential for `keygen --fido2`,
+// and GetAssertion with the hmac-secret extension once per run, whose key agreement keeps the salt and the secret
+// off the wire, with the PIN of a security key that has one. The credential file `keygen --fido2` writes holds the
+// credential's ID and whether it was made with a PIN, as JSON. It isn't secret: the credential is useless without
+// the security key. With the `fido2` cargo feature; on Linux, building it needs libudev's headers, and the hidraw
+// devices are usually only open to their user once the udev rules of libfido2 or the security key's vendor are
+// installed.
+use encryptor::Encryptor;
+
+#[cfg(feature = "fido2")]
+use crate::{keyfile, password};
+#[cfg(feature = "fido2")]
+use base64::Engine;
+#[cfg(feature = "fido2")]
+use ctap_hid_fido2::fidokey::{
+    AssertionExtension, CredentialExtension, GetAssertionArgsBuilder, MakeCredentialArgsBuilder,
+};
+#[cfg(feature = "fido2")]
+use ctap_hid_fido2::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
+#[cfg(feature = "fido2")]
+use ctap_hid_fido2::{FidoKeyHid, HidParam, LibCfg};
+#[cfg(feature = "fido2")]
+use encryptor::fido2::{Authenticator, SALT_LEN};
+#[cfg(feature = "fido2")]
+use encryptor::EncryptError;
+#[cfg(feature = "fido2")]
+use std::fs;
+#[cfg(feature = "fido2")]
+use std::io;
+#[cfg(feature = "fido2")]
+use std::sync::Arc;
+#[cfg(feature = "fido2")]
+use zeroize::Zeroizing;
+
+// The relying party credentials are made for, so they can't be used to sign in anywhere
+#[cfg(feature = "fido2")]
+const RP_ID: &str = "encryptor";
+
+// Make a credential with the hmac-secret extension on the security key plugged in, and write its credential file
+// to `path`, returning what to print
+#[cfg(feature = "fido2")]
+pub fn keygen(path: &str) -> Result<String, String> {
+    let Some(device) = devices().into_iter().next() else {
+        return Err("No FIDO2 security key is plugged in".to_string());
+    };
+    let (credential, pin) = open(&device)
+        .and_then(|device| make_credential(&device))
+        .map_err(|err| format!("Cannot make a credential: {}", err))?;
+    let contents = serde_json::json!({
+        "fido2": 1,
+        "rp": RP_ID,
+        "credential": base64::engine::general_purpose::STANDARD.encode(&credential),
+        "pin": pin,
+    });
+    keyfile::create_private(path, format!("{}\n", contents).as_bytes())
+        .map_err(|err| format!("Cannot write the credential file {}: {}", path, err))?;
+    Ok(format!(
+        "Wrote a new FIDO2 credential to {}{}; the security key it was made on is needed to decrypt",
+        path,
+        if pin { ", used with the security key's PIN" } else { "" }
+    ))
+}
+
+#[cfg(not(feature = "fido2"))]
+pub fn keygen(_path: &str) -> Result<String, String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+// The Encryptor for the credential file at `path`, reaching the security key through USB when it is used
+#[cfg(feature = "fido2")]
+pub fn load(path: &str) -> Result<Encryptor, String> {
+    let invalid = || format!("{} isn't a credential file from `keygen --fido2`", path);
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Cannot read the credential file {}: {}", path, err))?;
+    let value: serde_json::Value = serde_json::from_str(&contents).map_err(|_| invalid())?;
+    let credential = value["credential"]
+        .as_str()
+        .and_then(|credential| {
+            base64::engine::general_purpose::STANDARD
+                .decode(credential)
+                .ok()
+        })
+        .filter(|credential| !credential.is_empty())
+        .ok_or_else(invalid)?;
+    let security_key = SecurityKey {
+        rp: value["rp"].as_str().ok_or_else(invalid)?.to_string(),
+        pin: value["pin"].as_bool().ok_or_else(invalid)?,
+    };
+    Ok(Encryptor::from_fido2(credential, Arc::new(security_key)))
+}
+
+#[cfg(not(feature = "fido2"))]
+pub fn load(_path: &str) -> Result<Encryptor, String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+#[cfg(not(feature = "fido2"))]
+const UNSUPPORTED: &str =
+    "FIDO2 security keys are not supported by this build (enable the 'fido2' feature)";
+
+// The security keys plugged in, asked in turn for the hmac-secret of a credential until one holds it
+#[cfg(feature = "fido2")]
+struct SecurityKey {
+    rp: String,
+    pin: bool, // The credential was made with the PIN, so its secret is asked for with it too
+}
+
+#[cfg(feature = "fido2")]
+impl Authenticator for SecurityKey {
+    fn hmac_secret(
+        &self,
+        credential: &[u8],
+        salt: &[u8; SALT_LEN],
+    ) -> Result<Zeroizing<[u8; 32]>, EncryptError> {
+        let failed = |err: Failure| {
+            EncryptError::IoError(io::Error::other(format!(
+                "the security key failed: {}",
+                err
+            )))
+        };
+        let devices = devices();
+        if devices.is_empty() {
+            return Err(EncryptError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no FIDO2 security key is plugged in",
+            )));
+        }
+        for device in devices {
+            let secret = open(&device)
+                .and_then(|device| hmac_secret(&device, &self.rp, credential, salt, self.pin));
+            match secret {
+                Ok(secret) => return Ok(secret),
+                Err(Failure::Ctap(err)) if err.contains("CTAP2_ERR_NO_CREDENTIALS") => continue,
+                Err(err) => return Err(failed(err)),
+            }
+        }
+        Err(EncryptError::KeyModeMismatch(
+            "no security key plugged in holds the credential (plug in the one it was made on)"
+                .to_string(),
+        ))
+    }
+}
+
+// Why talking to a security key failed
+#[cfg(feature = "fido2")]
+enum Failure {
+    Open,         // The device couldn't be opened
+    Ctap(String), // What ctap-hid-fido2 reported, which names the CTAP2 status when there is one
+    Protocol(String),
+}
+
+#[cfg(feature = "fido2")]
+impl Failure {
+    fn ctap(err: impl std::fmt::Display) -> Self {
+        Failure::Ctap(err.to_string())
+    }
+}
+
+#[cfg(feature = "fido2")]
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The CTAP2 statuses a user can do something about
+        const STATUSES: [(&str, &str); 8] = [
+            ("CTAP2_ERR_OPERATION_DENIED", "the request was denied"),
+            (
+                "CTAP2_ERR_UNSUPPORTED_EXTENSION",
+                "it doesn't support the hmac-secret extension",
+            ),
+            ("CTAP2_ERR_USER_ACTION_TIMEOUT", "it wasn't touched in time"),
+            ("CTAP2_ERR_PIN_INVALID", "wrong PIN"),
+            (
+                "CTAP2_ERR_PIN_BLOCKED",
+                "its PIN is blocked, after too many wrong ones",
+            ),
+            (
+                "CTAP2_ERR_PIN_AUTH_BLOCKED",
+                "too many wrong PINs, unplug it and plug it in again",
+            ),
+            ("CTAP2_ERR_PIN_NOT_SET", "it has no PIN set"),
+            (
+                "CTAP2_ERR_PIN_REQUIRED",
+                "it needs a PIN, but the credential was made without one",
+            ),
+        ];
+        match self {
+            Failure::Open if cfg!(target_os = "linux") => write!(
+                f,
+                "it can't be opened (install the udev rules of libfido2 or of the security key's vendor)"
+            ),
+            Failure::Open => write!(f, "it can't be opened"),
+            Failure::Ctap(err) => match STATUSES.iter().find(|(status, _)| err.contains(status)) {
+                Some((_, message)) => write!(f, "{}", message),
+                None => write!(f, "{}", err),
+            },
+            Failure::Protocol(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+// The HID devices of the FIDO usage page (0xF1D0)
+#[cfg(feature = "fido2")]
+fn devices() -> Vec<HidParam> {
+    ctap_hid_fido2::get_fidokey_devices()
+        .into_iter()
+        .map(|device| device.param)
+        .collect()
+}
+
+// Open a security key, telling the user on stderr when it waits to be touched
+#[cfg(feature = "fido2")]
+fn open(device: &HidParam) -> Result<FidoKeyHid, Failure> {
+    let mut cfg = LibCfg::init();
+    cfg.keep_alive_msg = "Touch your security key".to_string();
+    cfg.keep_alive_msg_to_stderr = true;
+    FidoKeyHid::new(std::slice::from_ref(device), &cfg).map_err(|_| Failure::Open)
+}
+
+// Make a credential with the hmac-secret extension, returning its ID and whether the PIN was used
+#[cfg(feature = "fido2")]
+fn make_credential(device: &FidoKeyHid) -> Result<(Vec<u8>, bool), Failure> {
+    let info = device.get_info().map_err(Failure::ctap)?;
+    if !info
+        .extensions
+        .iter()
+        .any(|extension| extension == "hmac-secret")
+    {
+        return Err(Failure::Protocol(
+            "it doesn't support the hmac-secret extension".to_string(),
+        ));
+    }
+    let pin = info
+        .options
+        .iter()
+        .any(|(option, set)| option == "clientPin" && *set);
+
+    let user = PublicKeyCredentialUserEntity::new(
+        Some(&random::<16>()?),
+        Some("encryptor"),
+        Some("encryptor"),
+    );
+    let builder = MakeCredentialArgsBuilder::new(RP_ID, &random::<32>()?)
+        .user_entity(&user)
+        .extensions(&[CredentialExtension::HmacSecret(Some(true))]);
+    let pin_code = if pin { Some(read_pin()?) } else { None };
+    let args = match &pin_code {
+        Some(pin_code) => builder.pin(pin_code),
+        None => builder.without_pin_and_uv(),
+    }
+    .build();
+    let credential = device
+        .make_credential_with_args(&args)
+        .map_err(Failure::ctap)?
+        .credential_descriptor
+        .id;
+    if credential.is_empty() {
+        return Err(Failure::Protocol("it returned no credential".to_string()));
+    }
+    Ok((credential, pin))
+}
+
+// The hmac-secret of `credential` for `salt`, which the security key computes once it is touched
+#[cfg(feature = "fido2")]
+fn hmac_secret(
+    device: &FidoKeyHid,
+    rp: &str,
+    credential: &[u8],
+    salt: &[u8; SALT_LEN],
+    pin: bool,
+) -> Result<Zeroizing<[u8; 32]>, Failure> {
+    let builder = GetAssertionArgsBuilder::new(rp, &random::<32>()?)
+        .credential_id(credential)
+        .extensions(&[AssertionExtension::HmacSecret(Some(*salt))]);
+    let pin_code = if pin { Some(read_pin()?) } else { None };
+    let args = match &pin_code {
+        Some(pin_code) => builder.pin(pin_code),
+        None => builder.without_pin_and_uv(),
+    }
+    .build();
+    let assertions = device
+        .get_assertion_with_args(&args)
+        .map_err(Failure::ctap)?;
+    assertions
+        .iter()
+        .flat_map(|assertion| &assertion.extensions)
+        .find_map(|extension| match extension {
+            AssertionExtension::HmacSecret(Some(secret)) => Some(Zeroizing::new(*secret)),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            Failure::Protocol(
+                "it returned no hmac-secret (was the credential made by `keygen --fido2`?)"
+                    .to_string(),
+            )
+        })
+}
+
+// Ask for the PIN at the terminal
+#[cfg(feature = "fido2")]
+fn read_pin() -> Result<Zeroizing<String>, Failure> {
+    password::read_as("Security key PIN", Some(password::Source::Prompt), false)
+        .map_err(Failure::Protocol)
+}
+
+#[cfg(feature = "fido2")]
+fn random<const N: usize>() -> Result<[u8; N], Failure> {
+    let key = Zeroizing::new(
+        encryptor::generate_key().map_err(|err| Failure::Protocol(err.to_string()))?,
+    );
+    Ok(key[..N].try_into().expect("N is at most 32"))
+}
@@ -0,0 +1,44 @@
+// The `git-filter` command: encryptor as the clean and smudge filter of a git attribute, so that the files it is
+// set on are committed encrypted and checked out as plaintext. Git runs `clean` on a file's contents when it is
+// added and `smudge` on the blob when it is checked out, each time through stdin and stdout.
+//
+// Git compares the cleaned contents of a file with the blob in the index to tell whether it was modified, so clean
+// has to seal the same plaintext into the same bytes every time: see Encryptor::with_deterministic. The salt is
+// derived from the path git gives (%f), and the nonce from the contents, under AES-256-GCM-SIV.
+//
+// Contents that already carry an encryptor header are cleaned as they are, so a file left encrypted by a smudge
+// that couldn't run isn't encrypted twice; a blob without one (committed before the filter was set) is smudged as
+// it is.
+use encryptor::header::Header;
+use encryptor::{names, EncryptError, Encryptor};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+// Which way the contents go
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Mode {
+    Clean,  // Encrypt, from the working tree into the repository
+    Smudge, // Decrypt, from the repository into the working tree
+}
+
+// Run `mode` on stdin into stdout, for the file at `path` in the repository
+pub fn run(encryptor: Encryptor, mode: Mode, path: &Path) -> Result<(), EncryptError> {
+    let mut input = Vec::new();
+    io::stdin().lock().read_to_end(&mut input)?;
+    let encrypted = Header::parse(&input).is_ok();
+    let output = match mode {
+        // The path as a header would record it, so a UTF-8 path seals as it always did
+        Mode::Clean if !encrypted => encryptor
+            .with_deterministic(&names::record(path.as_os_str()).0)
+            .encrypt_bytes(&input)?,
+        Mode::Smudge if encrypted => encryptor.decrypt_bytes(&input)?,
+        _ => {
+            log::debug!("Passing {} through unchanged", path.display());
+            input
+        }
+    };
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&output)?;
+    stdout.flush()?;
+    Ok(())
+}
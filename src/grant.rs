@@ -0,0 +1,362 @@
+//! Short-lived, signed decryption grants, for delegated and auditable access without sharing long-term keys.
+//!
+//! An administrator who can decrypt some files mints a grant for an operator's X25519 [`Recipient`]: for each file
+//! it holds the file's ID and the file's key wrapped to the operator, next to an expiry time and a purpose, all
+//! signed with the administrator's Ed25519 [`IssuerKey`]. The operator redeems it with [`Encryptor::from_grant`]
+//! and their own [`Identity`]. That decrypts only the files it lists, only until it expires, and only when the
+//! signature comes from an issuer the operator trusts; the administrator's password or identity never leaves
+//! their hands.
+//!
+//! ```no_run
+//! use encryptor::grant::{self, Grant, IssuerKey};
+//! use encryptor::recipient::Identity;
+//! use encryptor::Encryptor;
+//! use std::time::Duration;
+//!
+//! let (issuer, operator) = (IssuerKey::generate()?, Identity::generate()?);
+//! let file = std::fs::read("report.pdf.enc")?;
+//! let token = grant::mint(&Encryptor::new("admin password"), &[&file], &operator.recipient(), &issuer,
+//!     "incident 42", Duration::from_secs(3600))?;
+//! let grant = Grant::verify(&token, &[issuer.issuer()])?;
+//! let plaintext = Encryptor::from_grant(grant, operator).decrypt_bytes(&file)?;
+//! # Ok::<(), encryptor::EncryptError>(())
+//! ```
+//!
+//! The token is the grant as JSON and its Ed25519 signature, each base64url-encoded and joined by a dot. Expiry is
+//! enforced by this library rather than by cryptography: an operator who unwraps a file's key can keep it, so keep
+//! grants short. What the signature and the wrapping do guarantee is that a grant only ever opens the files it lists,
+//! for the operator it was minted for, with the expiry and purpose the issuer chose.
+use crate::header::{Header, Stanza, StanzaKind};
+use crate::recipient::{self, Identity, Recipient};
+use crate::rng::SystemRandom;
+use crate::{cipher, encoding, Algorithm, EncryptError, Encryptor, KeyBytes};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const TYPE: &str = "encryptor-grant";
+const VERSION: u64 = 1;
+
+/// The longest a grant can be valid for. Longer-lived access should be a real recipient of the files instead.
+pub const MAX_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+// Clocks of the administrator's and the operator's machines may disagree by this much
+const CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+
+/// The Ed25519 private key an administrator signs grants with
+#[derive(Clone)]
+pub struct IssuerKey(SigningKey);
+
+impl IssuerKey {
+    /// Generate a new issuer key with the crypto backend's secure random generator
+    pub fn generate() -> Result<Self, EncryptError> {
+        let mut bytes = [0u8; 32];
+        cipher::fill_random(&mut bytes)?;
+        Ok(IssuerKey::from_bytes(bytes))
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        IssuerKey(SigningKey::from_bytes(&bytes))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    /// The public key operators check this issuer's grants with
+    pub fn issuer(&self) -> Issuer {
+        Issuer(self.0.verifying_key())
+    }
+
+    /// Sign other documents than grants with this key, such as key bundles
+    pub fn sign(&self, message: &[u8]) -> [u8; 64] {
+        self.0.sign(message).to_bytes()
+    }
+}
+
+// The private key must never end up in logs or panic messages
+impl std::fmt::Debug for IssuerKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IssuerKey")
+            .field("issuer", &self.issuer())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The Ed25519 public key of an issuer, trusted by operators to sign grants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Issuer(VerifyingKey);
+
+impl Issuer {
+    /// Fails for bytes that aren't a valid Ed25519 public key
+    pub fn from_bytes(bytes: [u8; 32]) -> Result<Self, EncryptError> {
+        VerifyingKey::from_bytes(&bytes)
+            .map(Issuer)
+            .map_err(|_| rejected("not an Ed25519 public key"))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    /// Whether `signature` is this issuer's signature of `message`, see [`IssuerKey::sign`]
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        Signature::from_slice(signature)
+            .is_ok_and(|signature| self.0.verify(message, &signature).is_ok())
+    }
+}
+
+/// A verified grant, see [`Grant::verify`]
+#[derive(Debug, Clone)]
+pub struct Grant {
+    id: String,
+    issuer: Issuer,
+    recipient: Recipient,
+    purpose: String,
+    issued_at: SystemTime,
+    expires_at: SystemTime,
+    files: Vec<(String, Vec<u8>)>, // Each file's ID and its key wrapped to the operator, as a stanza body
+}
+
+impl Grant {
+    /// Check a token's signature against the `trusted` issuers and its validity period against the clock,
+    /// and read the grant in it
+    pub fn verify(token: &[u8], trusted: &[Issuer]) -> Result<Grant, EncryptError> {
+        let token = std::str::from_utf8(token)
+            .map_err(|_| rejected("the token is not text"))?
+            .trim();
+        let (payload, signature) = token
+            .split_once('.')
+            .ok_or_else(|| rejected("the token is damaged"))?;
+        let decode = |part: &str| {
+            URL_SAFE_NO_PAD
+                .decode(part)
+                .map_err(|_| rejected("the token is damaged"))
+        };
+        let payload_bytes = decode(payload)?;
+        let signature = Signature::from_slice(&decode(signature)?)
+            .map_err(|_| rejected("the token's signature is damaged"))?;
+        let grant: Value =
+            serde_json::from_slice(&payload_bytes).map_err(|_| rejected("the token is damaged"))?;
+        if grant["type"] != TYPE || grant["version"] != VERSION {
+            return Err(rejected("not a decryption grant, or from a newer version"));
+        }
+
+        // The signature is checked before anything else in the grant is trusted
+        let issuer = key_field(&grant, "issuer")
+            .and_then(|bytes| Issuer::from_bytes(bytes).ok())
+            .ok_or_else(|| rejected("the token's issuer is damaged"))?;
+        if !trusted.contains(&issuer) {
+            return Err(rejected("the grant was issued by an untrusted key"));
+        }
+        issuer
+            .0
+            .verify(payload.as_bytes(), &signature)
+            .map_err(|_| rejected("the grant's signature is invalid"))?;
+
+        let time = |name: &str| {
+            grant[name]
+                .as_u64()
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+                .ok_or_else(|| rejected(&format!("the grant's {} is damaged", name)))
+        };
+        let files = grant["files"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|file| {
+                let id = file["id"].as_str()?.to_string();
+                let key = URL_SAFE_NO_PAD.decode(file["key"].as_str()?).ok()?;
+                Some((id, key))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| rejected("the grant's files are damaged"))?;
+        let grant = Grant {
+            id: grant["id"].as_str().unwrap_or_default().to_string(),
+            issuer,
+            recipient: key_field(&grant, "recipient")
+                .map(Recipient::from_bytes)
+                .ok_or_else(|| rejected("the grant's recipient is damaged"))?,
+            purpose: grant["purpose"].as_str().unwrap_or_default().to_string(),
+            issued_at: time("issued_at")?,
+            expires_at: time("expires_at")?,
+            files,
+        };
+        if grant
+            .expires_at
+            .duration_since(grant.issued_at)
+            .unwrap_or_default()
+            > MAX_LIFETIME
+        {
+            return Err(rejected("the grant is valid for longer than allowed"));
+        }
+        grant.check_time()?;
+        Ok(grant)
+    }
+
+    /// The random ID of this grant, for audit logs
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn issuer(&self) -> Issuer {
+        self.issuer
+    }
+
+    /// The operator the grant was minted for
+    pub fn recipient(&self) -> Recipient {
+        self.recipient
+    }
+
+    /// Why the grant was issued, as given by the issuer
+    pub fn purpose(&self) -> &str {
+        &self.purpose
+    }
+
+    pub fn issued_at(&self) -> SystemTime {
+        self.issued_at
+    }
+
+    pub fn expires_at(&self) -> SystemTime {
+        self.expires_at
+    }
+
+    /// The IDs of the files this grant decrypts, see [`file_id`]
+    pub fn file_ids(&self) -> impl Iterator<Item = &str> {
+        self.files.iter().map(|(id, _)| id.as_str())
+    }
+
+    // Whether the grant is valid now
+    fn check_time(&self) -> Result<(), EncryptError> {
+        let now = SystemTime::now();
+        if now + CLOCK_SKEW < self.issued_at {
+            return Err(rejected("the grant was issued in the future"));
+        }
+        if now >= self.expires_at {
+            return Err(rejected(&format!(
+                "the grant expired {} seconds ago",
+                now.duration_since(self.expires_at)
+                    .unwrap_or_default()
+                    .as_secs()
+            )));
+        }
+        Ok(())
+    }
+
+    // The key of the file described by `header`, unwrapped with the operator's identity
+    pub(crate) fn key(
+        &self,
+        header: &Header,
+        identity: &Identity,
+    ) -> Result<KeyBytes, EncryptError> {
+        self.check_time()?;
+        if identity.recipient() != self.recipient {
+            return Err(rejected("the grant was minted for another operator"));
+        }
+        let id = header_id(header);
+        let (_, wrapped) = self
+            .files
+            .iter()
+            .find(|(file, _)| *file == id)
+            .ok_or_else(|| rejected(&format!("the grant doesn't cover this file ({})", id)))?;
+        let stanza = Stanza {
+            kind: StanzaKind::X25519,
+            body: wrapped.clone(),
+        };
+        recipient::unwrap(&[stanza], identity, Algorithm::Aes256Gcm)
+            .ok_or_else(|| rejected("the grant is damaged"))
+    }
+}
+
+/// Mint a grant for `operator` to decrypt `files` (complete encrypted files, in any encoding) for `lifetime`,
+/// signed by `issuer`. `encryptor` must be able to decrypt every one of them; each is decrypted once to make sure.
+pub fn mint(
+    encryptor: &Encryptor,
+    files: &[&[u8]],
+    operator: &Recipient,
+    issuer: &IssuerKey,
+    purpose: &str,
+    lifetime: Duration,
+) -> Result<Vec<u8>, EncryptError> {
+    if lifetime.is_zero() || lifetime > MAX_LIFETIME {
+        return Err(rejected(&format!(
+            "a grant can be valid for up to {} days",
+            MAX_LIFETIME.as_secs() / 86400
+        )));
+    }
+    let mut granted = Vec::with_capacity(files.len());
+    for data in files {
+        encryptor.decrypt_bytes(data)?;
+        let header = parse_header(data)?;
+        let key = encryptor.key(&header)?;
+        let stanza = recipient::wrap(&key, operator, Algorithm::Aes256Gcm, &SystemRandom)?;
+        granted.push(json!({
+            "id": header_id(&header),
+            "key": URL_SAFE_NO_PAD.encode(stanza.body),
+        }));
+    }
+
+    let mut id = [0u8; 16];
+    cipher::fill_random(&mut id)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut grant = Map::new();
+    grant.insert("type".into(), TYPE.into());
+    grant.insert("version".into(), VERSION.into());
+    grant.insert("id".into(), hex(&id).into());
+    grant.insert(
+        "issuer".into(),
+        URL_SAFE_NO_PAD.encode(issuer.issuer().to_bytes()).into(),
+    );
+    grant.insert(
+        "recipient".into(),
+        URL_SAFE_NO_PAD.encode(operator.to_bytes()).into(),
+    );
+    grant.insert("purpose".into(), purpose.into());
+    grant.insert("issued_at".into(), now.as_secs().into());
+    grant.insert("expires_at".into(), (now + lifetime).as_secs().into());
+    grant.insert("files".into(), granted.into());
+
+    let payload = URL_SAFE_NO_PAD.encode(Value::Object(grant).to_string());
+    let signature = issuer.0.sign(payload.as_bytes());
+    Ok(format!(
+        "{}.{}\n",
+        payload,
+        URL_SAFE_NO_PAD.encode(signature.to_bytes())
+    )
+    .into_bytes())
+}
+
+/// The ID grants know an encrypted file by: a hash of its header, which has a random nonce (and salt or file key),
+/// so no two files share one. `data` is the complete encrypted file, in any encoding.
+pub fn file_id(data: &[u8]) -> Result<String, EncryptError> {
+    Ok(header_id(&parse_header(data)?))
+}
+
+fn parse_header(data: &[u8]) -> Result<Header, EncryptError> {
+    let data = encoding::decode(data.to_vec())?;
+    Ok(Header::parse(&data)?.0)
+}
+
+fn header_id(header: &Header) -> String {
+    hex(&Sha256::digest(header.to_bytes())[..16])
+}
+
+fn key_field(grant: &Value, name: &str) -> Option<[u8; 32]> {
+    URL_SAFE_NO_PAD
+        .decode(grant[name].as_str()?)
+        .ok()?
+        .try_into()
+        .ok()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn rejected(reason: &str) -> EncryptError {
+    EncryptError::GrantRejected(reason.to_string())
+}
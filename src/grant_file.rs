@@ -0,0 +1,241 @@
+// Decryption grants on the command line: `keygen --signing` for the issuer's key, the `grant` command that mints
+// a token, and `decrypt --grant` that redeems one (see the `encryptor::grant` module). A --grant directory is a
+// ticket cache: every *.tok file in it is tried, and the one covering each file is used. Every redemption is
+// appended to the usage log as a JSON line. Needs the `grant` cargo feature; without it all of these fail
+// with an explanation.
+#[cfg(feature = "grant")]
+use crate::units;
+use crate::Secret;
+use encryptor::{EncryptError, Encryptor};
+use std::path::{Path, PathBuf};
+
+// The verified grants from --grant, the operator's identity and the open usage log
+#[cfg(feature = "grant")]
+pub struct Grants {
+    grants: Vec<(String, encryptor::grant::Grant)>, // Each with the token file it came from
+    ignored: Vec<String>, // Tokens in a ticket cache that were rejected, and why
+    identity: encryptor::recipient::Identity,
+    log: std::sync::Mutex<std::fs::File>,
+}
+
+#[cfg(not(feature = "grant"))]
+pub struct Grants;
+
+// Write a new Ed25519 issuer key to `path` and its public key to `<path>.pub`, returning what was written
+#[cfg(feature = "grant")]
+pub fn keygen(path: &str, format: crate::keyfile::Format) -> Result<String, String> {
+    use crate::keyfile;
+    use encryptor::grant::IssuerKey;
+
+    let key = IssuerKey::generate().map_err(|err| err.to_string())?;
+    let public_key = key.issuer().to_bytes();
+    let public_path = format!("{}.pub", path);
+    keyfile::write(path, &key.to_bytes(), format)?;
+    keyfile::write(&public_path, &public_key, keyfile::Format::Base64)?;
+    Ok(format!(
+        "Wrote a new Ed25519 signing key to {} and its public key to {}: {}",
+        path,
+        public_path,
+        keyfile::encode(&public_key)
+    ))
+}
+
+// Mint a grant for `operator` to decrypt `files` for `lifetime`, signed with the key at `signing_key`,
+// and write the token to `out_path`
+#[cfg(feature = "grant")]
+pub fn mint(
+    encryptor: &Encryptor,
+    files: &[PathBuf],
+    operator: [u8; 32],
+    signing_key: [u8; 32],
+    purpose: &str,
+    lifetime: std::time::Duration,
+    out_path: &Path,
+) -> Result<String, EncryptError> {
+    use encryptor::grant::{self, IssuerKey};
+    use encryptor::recipient::Recipient;
+
+    let contents = files
+        .iter()
+        .map(std::fs::read)
+        .collect::<Result<Vec<_>, _>>()?;
+    let contents: Vec<&[u8]> = contents.iter().map(Vec::as_slice).collect();
+    let token = grant::mint(
+        encryptor,
+        &contents,
+        &Recipient::from_bytes(operator),
+        &IssuerKey::from_bytes(signing_key),
+        purpose,
+        lifetime,
+    )?;
+    std::fs::write(out_path, token)?;
+    Ok(format!(
+        "Granted {} files for {} -> {}",
+        files.len(),
+        units::duration(lifetime),
+        out_path.display()
+    ))
+}
+
+// Verify the token at `path`, or every *.tok file in the directory at `path`, against the trusted issuers,
+// and open the usage log: `log`, or `<path>.log` (`grants.log` inside a directory)
+#[cfg(feature = "grant")]
+pub fn load(
+    path: &str,
+    issuers: &[[u8; 32]],
+    identity: encryptor::recipient::Identity,
+    log: Option<&str>,
+) -> Result<Grants, String> {
+    use encryptor::grant::{Grant, Issuer};
+    use std::fs;
+
+    let issuers = issuers
+        .iter()
+        .map(|bytes| Issuer::from_bytes(*bytes))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("--grant-issuer: {}", err))?;
+    let read = |path: &str| fs::read(path).map_err(|err| format!("Cannot read {}: {}", path, err));
+
+    let (mut grants, mut ignored) = (Vec::new(), Vec::new());
+    let default_log = if Path::new(path).is_dir() {
+        let mut tokens = fs::read_dir(path)
+            .map_err(|err| format!("Cannot read {}: {}", path, err))?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|token| token.extension().is_some_and(|ext| ext == "tok"))
+            .map(|token| token.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        tokens.sort();
+        // Expired tickets are expected in a cache, so rejected ones are only reported if no grant covers a file
+        for token in tokens {
+            match Grant::verify(&read(&token)?, &issuers) {
+                Ok(grant) => grants.push((token, grant)),
+                Err(err) => ignored.push(format!("{}: {}", token, err)),
+            }
+        }
+        Path::new(path)
+            .join("grants.log")
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        let grant = Grant::verify(&read(path)?, &issuers)
+            .map_err(|err| format!("Cannot use the grant {}: {}", path, err))?;
+        grants.push((path.to_string(), grant));
+        format!("{}.log", path)
+    };
+
+    let log = log.unwrap_or(&default_log);
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)
+        .map_err(|err| format!("Cannot open the grant log {}: {}", log, err))?;
+    Ok(Grants {
+        grants,
+        ignored,
+        identity,
+        log: std::sync::Mutex::new(log),
+    })
+}
+
+// Decrypt the file at `file_path` with the grant that covers it, through `open` (which applies the
+// command line's settings), and log the attempt. Files no grant covers are refused and logged as well.
+#[cfg(feature = "grant")]
+pub fn decrypt(
+    file_path: &Path,
+    secret: &Secret,
+    open: impl FnOnce(Encryptor) -> Result<PathBuf, EncryptError>,
+) -> Result<PathBuf, EncryptError> {
+    use encryptor::grant;
+
+    let Secret::Grants(grants) = secret else {
+        return Err(EncryptError::KeyModeMismatch(
+            "decrypting with a grant needs --grant".to_string(),
+        ));
+    };
+    let file_id = grant::file_id(&std::fs::read(file_path)?)?;
+    let covering = grants
+        .grants
+        .iter()
+        .find(|(_, grant)| grant.file_ids().any(|id| id == file_id));
+    let result = match covering {
+        Some((_, grant)) => open(Encryptor::from_grant(
+            grant.clone(),
+            grants.identity.clone(),
+        )),
+        None if grants.ignored.is_empty() => Err(EncryptError::GrantRejected(format!(
+            "no grant covers this file ({})",
+            file_id
+        ))),
+        None => Err(EncryptError::GrantRejected(format!(
+            "no valid grant covers this file ({}); ignored {}",
+            file_id,
+            grants.ignored.join(", ")
+        ))),
+    };
+
+    // A redemption that can't be logged fails, even if the file was already decrypted
+    let mut entry = serde_json::json!({
+        "time": std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        "grant": covering.map(|(_, grant)| grant.id()),
+        "token": covering.map(|(token, _)| token),
+        "purpose": covering.map(|(_, grant)| grant.purpose()),
+        "file": file_path.to_string_lossy(),
+        "file_id": file_id,
+        "result": match &result {
+            Ok(_) => "ok",
+            Err(err) => err.code().as_str(),
+        },
+    });
+    crate::redact::value(&mut entry);
+    let mut log = grants.log.lock().unwrap_or_else(|e| e.into_inner());
+    std::io::Write::write_all(&mut *log, format!("{}\n", entry).as_bytes())?;
+    result
+}
+
+#[cfg(not(feature = "grant"))]
+pub fn keygen(_path: &str, _format: crate::keyfile::Format) -> Result<String, String> {
+    Err(unavailable().to_string())
+}
+
+#[cfg(not(feature = "grant"))]
+pub fn mint(
+    _encryptor: &Encryptor,
+    _files: &[PathBuf],
+    _operator: [u8; 32],
+    _signing_key: [u8; 32],
+    _purpose: &str,
+    _lifetime: std::time::Duration,
+    _out_path: &Path,
+) -> Result<String, EncryptError> {
+    Err(EncryptError::IoError(unavailable()))
+}
+
+#[cfg(not(feature = "grant"))]
+pub fn load(
+    _path: &str,
+    _issuers: &[[u8; 32]],
+    _identity: encryptor::recipient::Identity,
+    _log: Option<&str>,
+) -> Result<Grants, String> {
+    Err(unavailable().to_string())
+}
+
+#[cfg(not(feature = "grant"))]
+pub fn decrypt(
+    _file_path: &Path,
+    _secret: &Secret,
+    _open: impl FnOnce(Encryptor) -> Result<PathBuf, EncryptError>,
+) -> Result<PathBuf, EncryptError> {
+    Err(EncryptError::IoError(unavailable()))
+}
+
+#[cfg(not(feature = "grant"))]
+fn unavailable() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Grant support is not built in (enable the 'grant' feature)",
+    )
+}
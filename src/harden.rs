@@ -0,0 +1,115 @@
+//! Hardening for hostile multi-tenant hosts: keeping secrets out of swap and core dumps.
+//!
+//! Passwords and keys are already wiped from memory when they are dropped, but while they are in use the operating
+//! system may still copy them to disk: into swap when memory runs low, or into a core dump when the process
+//! crashes. After [`enable`], every password and key an [`Encryptor`](crate::Encryptor) holds, and every key it
+//! derives, is locked into RAM with `mlock`, core dumps are turned off (`RLIMIT_CORE` 0), and on Linux the process
+//! is made undumpable (`PR_SET_DUMPABLE` 0), which also stops other processes of the same user from attaching a
+//! debugger to it or reading its memory.
+//!
+//! ```no_run
+//! use encryptor::{harden, Encryptor};
+//!
+//! let hardening = harden::enable();
+//! if !hardening.memory_locked {
+//!     eprintln!("warning: secrets may be swapped to disk");
+//! }
+//! let encryptor = Encryptor::new("correct horse battery staple");
+//! ```
+//!
+//! Enabling it is process-wide and can't be undone. Whatever a platform doesn't support is skipped rather than
+//! failing, and the returned [`Hardening`] tells what is in effect: none of it is available on Windows or in a
+//! browser, and `mlock` fails where `RLIMIT_MEMLOCK` is 0, as in some containers. Locked pages stay locked until
+//! the process exits, so a long-running service should enable it only with a bounded number of Encryptors.
+//!
+//! Available with the `harden` feature, part of the command line tool's `--harden`.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Whether enable() was called, so that secrets are locked from then on
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The protections [`enable`] put in place on this platform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hardening {
+    /// Secrets can be locked into RAM, so they are never written to swap
+    pub memory_locked: bool,
+    /// Core dumps are turned off, so a crash doesn't write secrets to disk
+    pub core_dumps_disabled: bool,
+    /// The process is undumpable, so other processes of the same user can't read its memory (Linux only)
+    pub undumpable: bool,
+}
+
+/// Turn hardening on for the rest of the process, returning what this platform supports.
+/// Secrets created before the call aren't locked, so call it first thing.
+pub fn enable() -> Hardening {
+    ENABLED.store(true, Ordering::SeqCst);
+    let hardening = Hardening {
+        memory_locked: can_lock(),
+        core_dumps_disabled: disable_core_dumps(),
+        undumpable: make_undumpable(),
+    };
+    log::debug!("Hardening enabled: {:?}", hardening);
+    hardening
+}
+
+/// Whether [`enable`] was called
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Lock the memory pages holding `bytes` into RAM if hardening is enabled, for secrets kept outside the library
+/// (e.g. a password read by the application). Does nothing otherwise, or where locking isn't supported.
+pub fn protect(bytes: &[u8]) {
+    if is_enabled() && !bytes.is_empty() && !lock(bytes) {
+        log::debug!("Cannot lock {} bytes of secrets into memory", bytes.len());
+    }
+}
+
+// Lock the pages spanning `bytes`, which mlock wants aligned to the page size on some platforms
+#[cfg(unix)]
+fn lock(bytes: &[u8]) -> bool {
+    // SAFETY: sysconf has no preconditions, and mlock only changes how the pages of a live buffer are paged
+    unsafe {
+        let page = usize::try_from(libc::sysconf(libc::_SC_PAGESIZE)).unwrap_or(4096);
+        let start = bytes.as_ptr() as usize & !(page - 1);
+        let end = bytes.as_ptr() as usize + bytes.len();
+        libc::mlock(start as *const libc::c_void, end - start) == 0
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_bytes: &[u8]) -> bool {
+    false
+}
+
+// Whether a buffer can be locked at all, found out by locking one
+fn can_lock() -> bool {
+    let probe = Box::new([0u8; 1]);
+    lock(&probe[..])
+}
+
+#[cfg(unix)]
+fn disable_core_dumps() -> bool {
+    let limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: setrlimit only reads the limit it is given
+    unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) == 0 }
+}
+
+#[cfg(not(unix))]
+fn disable_core_dumps() -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn make_undumpable() -> bool {
+    // SAFETY: PR_SET_DUMPABLE takes no pointers
+    unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn make_undumpable() -> bool {
+    false
+}
@@ -0,0 +1,991 @@
+//! The self-describing header written at the start of every encrypted file.
+//!
+//! Layout (all integers little endian):
+//!
+//!   magic        4 bytes   "ENCR"
+//!   version      u8        format version, currently 5
+//!   algorithm    u8        AEAD algorithm id (see Algorithm)
+//!   kdf          u8        key derivation function id (see Kdf), 0 when a raw key was used instead of a password
+//!                          and 2 when a random file key was wrapped for each recipient (passwords or X25519 keys),
+//!                          3 when the key was derived from a key shared by a batch of files (see below)
+//!   kdf params   8 bytes   Argon2id memory (KiB, u32) and iterations (u32), zero for a raw key
+//!   salt         u8 length followed by the salt bytes (empty for a raw key, 32 bytes with KDF 3)
+//!   nonce        u8 length followed by the nonce bytes
+//!   flags        u8        which of the optional fields below are present (version 2 and later)
+//!   file name    u16 length followed by the original file name in UTF-8, escaped if FLAG_ESCAPED_NAME is set
+//!                          (if FLAG_FILE_NAME is set)
+//!   length       u64       length of the sealed payload in bytes (if FLAG_LENGTH is set)
+//!   compression  u8        compression codec id, see Codec (if FLAG_COMPRESSION is set)
+//!   dictionary   u32       id of the zstd dictionary the payload was compressed with (if FLAG_DICTIONARY is set)
+//!   recipients   u8 count, then per stanza: kind u8, u16 length and the stanza body (if FLAG_RECIPIENTS is set)
+//!   chunk size   u32       plaintext bytes per chunk, if the payload is sealed in chunks (if FLAG_CHUNKED is set)
+//!   more flags   u8        which of the optional fields below are present (version 4 and later)
+//!   created      u64       when the file was encrypted, in seconds since the Unix epoch (if FLAG_CREATED is set)
+//!   key id       u8 length followed by the ID of the key or password in UTF-8 (if FLAG_KEY_ID is set)
+//!   checksum     32 bytes  BLAKE3 hash of the plaintext, masked (if FLAG_CHECKSUM is set)
+//!   padding      u8 scheme id (see Padding), u32 block size (0 for PADMÉ) and u64 number of padding bytes,
+//!                          masked (if FLAG_PADDED is set)
+//!   password     4 bytes   password check, the start of a MAC under the key derived from the password
+//!     check                (if FLAG_PASSWORD_CHECK is set)
+//!   third flags  u8        which of the optional fields below are present (version 5 and later)
+//!   password set u64       when the password was set, masked (if FLAG_PASSWORD_SET is set)
+//!
+//! If FLAG_REWRAPPABLE is set (version 4 and later, with recipients), the recipients and key id fields are left out
+//! of the associated data, so the file key can be wrapped again for another password or key, and labeled with its
+//! ID, without touching the payload. Each stanza authenticates the file key it wraps on its own, and a stanza
+//! wrapping any other key fails to open the payload.
+//!
+//! With KDF 3 (`argon2id-hkdf`), Argon2id runs once for a whole batch of files: the first 16 bytes of the salt are
+//! the Argon2id salt shared by the batch, and the last 16 are random for this file. The Argon2id output, derived
+//! with the key context like KDF 1, is only a master key: the file key is HKDF-SHA256 with the master key as input
+//! key material, the file's 16 bytes as salt, and "encryptor batch file key" followed by the key context as info.
+//! Every file still gets its own key, and a guess at the password still costs one Argon2id run per batch.
+//!
+//! The checksum is the BLAKE3 hash of the file's contents as decrypting gives them back (before any name is sealed
+//! in front of them and before compression), XORed with the BLAKE3 key derived with the context
+//! "encryptor 2026-10-16 plaintext checksum mask" from the file's key followed by the nonce, so it can only be read
+//! with the key. See the `checksum` module.
+//!
+//! If FLAG_SEALED_NAME is set, the original file name is not in the header but sealed with the contents: the
+//! plaintext starts with the name's u16 length and the name in UTF-8, followed by the file contents.
+//!
+//! A name that isn't valid UTF-8, recorded or sealed, is written with each backslash as `\\`, each byte that isn't
+//! part of valid UTF-8 as `\xNN` and each unpaired UTF-16 surrogate (of a Windows name) as `\uNNNN`, with
+//! FLAG_ESCAPED_NAME set (version 4 and later). Valid UTF-8 names are written as they are.
+//!
+//! If FLAG_PADDED is set, the payload (after compression) ends with zero bytes up to the length the padding scheme
+//! rounds it up to, so files of close sizes can't be told apart. How many there are is XORed with the first 8 bytes
+//! (little endian) of the BLAKE2s MAC under the file's key of "encryptor padding length mask", a zero byte and the
+//! nonce: the scheme is public, but the exact size of the plaintext isn't. See the `padding` module.
+//!
+//! If FLAG_PASSWORD_CHECK is set (version 4 and later, with KDF 1 or 3), the header records the first 4 bytes of the
+//! BLAKE2s MAC under the file's key of "encryptor password check", a zero byte and the nonce. A password whose key
+//! doesn't give them back is wrong, and one that does but fails to open the payload means the file is damaged. The
+//! check tells a guesser nothing the payload's tag doesn't, and costs the same Argon2id run to test.
+//!
+//! If FLAG_PASSWORD_SET is set (version 5 and later, with KDF 1 or 3), the header ends with when the password was
+//! set, in seconds since the Unix epoch, XORed with the first 8 bytes (little endian) of the BLAKE2s MAC under the
+//! file's key of "encryptor password set mask", a zero byte and the nonce, so only a holder of the password learns
+//! how old it is. It is only recorded when the encryptor was told; a header without it doesn't know.
+//!
+//! If FLAG_HASH_NAME is set (version 4 and later), the encrypted file is named after the BLAKE3 hash of its own bytes
+//! instead of after the recorded name, which it decrypts to.
+//!
+//! The ciphertext and authentication tag follow immediately after the header. A chunked payload is instead split into
+//! chunks of `chunk size` bytes (the last one shorter, and never empty unless the whole payload is), each sealed on its
+//! own and followed by its tag, so files far larger than memory can be encrypted and decrypted as a stream. Every
+//! chunk is bound to the header and to its position: its nonce is the header's nonce with the chunk's index XORed
+//! into bytes 7 to 10 (big endian) and, for the last chunk, 1 XORed into byte 11. See the `stream` module.
+//! If FLAG_BACKUP is set, a copy of
+//! the header follows the ciphertext, then the length of that copy as a u32, so `encryptor recover` can restore
+//! a file whose leading bytes were damaged.
+//! From version 2 on, the whole header is passed to the AEAD as associated data, so the version,
+//! the file name and the length can't be changed without decryption failing.
+//! From version 3 on, the version, algorithm and KDF parameters are also fed into the key derivation
+//! (see [`Header::key_context`]). Rewriting them to weaker values doesn't spare a guesser anything: Argon2id still
+//! runs with whatever parameters the header now says, but the key it derives differs from the one the file was
+//! sealed with, so the password check or the payload's authentication fails.
+//! Version 4 adds a second flags byte, since the first one is full, and the time the file was encrypted.
+//! Version 5 adds a third flags byte, since the second one is full, and when the password was set.
+//! Keeping the algorithm and KDF as ids lets future versions add new ones while still reading old files.
+use crate::compression::Codec;
+use crate::KdfParams;
+use std::borrow::Cow;
+
+/// Magic bytes identifying a file produced by this tool
+pub const MAGIC: &[u8; 4] = b"ENCR";
+/// The format version written by this build
+pub const VERSION: u8 = 5;
+/// The oldest format version this build can still read
+pub const MIN_VERSION: u8 = 1;
+
+/// Flag set when the header records the original file name
+pub const FLAG_FILE_NAME: u8 = 0b01;
+/// Flag set when the header records the payload length
+pub const FLAG_LENGTH: u8 = 0b10;
+/// Flag set when the payload was compressed before encryption
+pub const FLAG_COMPRESSION: u8 = 0b100;
+/// Flag set when the payload was compressed with a zstd dictionary
+pub const FLAG_DICTIONARY: u8 = 0b1000;
+/// Flag set when the header carries stanzas wrapping the file key for recipients
+pub const FLAG_RECIPIENTS: u8 = 0b10000;
+/// Flag set when a copy of the header is stored after the ciphertext
+pub const FLAG_BACKUP: u8 = 0b100000;
+/// Flag set when the original file name is encrypted at the start of the payload instead of recorded in the header
+pub const FLAG_SEALED_NAME: u8 = 0b1000000;
+/// Flag set when the payload is sealed in chunks instead of in one piece
+pub const FLAG_CHUNKED: u8 = 0b10000000;
+/// Flag in the second flags byte set when the header records when the file was encrypted
+pub const FLAG_CREATED: u8 = 0b01;
+/// Flag in the second flags byte set when the stanzas are left out of the associated data
+pub const FLAG_REWRAPPABLE: u8 = 0b10;
+/// Flag in the second flags byte set when the header records the ID of the key the file was encrypted under
+pub const FLAG_KEY_ID: u8 = 0b100;
+/// Flag in the second flags byte set when the header records a checksum of the plaintext
+pub const FLAG_CHECKSUM: u8 = 0b1000;
+/// Flag in the second flags byte set when the file name, recorded or sealed, is escaped since it isn't valid UTF-8
+pub const FLAG_ESCAPED_NAME: u8 = 0b10000;
+/// Flag in the second flags byte set when the encrypted file is named after its hash instead of its recorded name
+pub const FLAG_HASH_NAME: u8 = 0b100000;
+/// Flag in the second flags byte set when the payload is padded to hide its exact length
+pub const FLAG_PADDED: u8 = 0b1000000;
+/// Flag in the second flags byte set when the header records a check of the password the key was derived from
+pub const FLAG_PASSWORD_CHECK: u8 = 0b10000000;
+/// The length of the password check
+pub const PASSWORD_CHECK_LEN: usize = 4;
+/// Flag in the third flags byte set when the header records when the password the key was derived from was set
+pub const FLAG_PASSWORD_SET: u8 = 0b01;
+
+/// The AEAD algorithm used to seal the file contents.
+///
+/// Every file records the [`id`](Algorithm::id) of its algorithm in the header, and is opened with the algorithm
+/// that id names, whatever the encryptor was set up to seal with. Ids are never reused or renumbered, so a cipher
+/// added later gets the next one and every existing file keeps opening as it always did; a build that doesn't know
+/// an id refuses the file with [`HeaderError::UnknownAlgorithm`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Aes256Gcm,
+    ChaCha20Poly1305,
+    Aes256GcmSiv, // Nonce-misuse resistant: a repeated nonce only reveals whether two plaintexts are equal
+    Aes128Gcm, // For environments that mandate AES-128; keyed with the first half of the 256-bit key
+}
+
+impl Algorithm {
+    /// Every algorithm, in the order of their ids
+    pub const ALL: [Algorithm; 4] = [
+        Algorithm::Aes256Gcm,
+        Algorithm::ChaCha20Poly1305,
+        Algorithm::Aes256GcmSiv,
+        Algorithm::Aes128Gcm,
+    ];
+
+    /// The id stored in the header for this algorithm
+    pub fn id(self) -> u8 {
+        match self {
+            Algorithm::Aes256Gcm => 1,
+            Algorithm::ChaCha20Poly1305 => 2,
+            Algorithm::Aes256GcmSiv => 3,
+            Algorithm::Aes128Gcm => 4,
+        }
+    }
+
+    /// Look up an algorithm from its header id
+    pub fn from_id(id: u8) -> Option<Self> {
+        Algorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.id() == id)
+    }
+
+    /// The name used for this algorithm on the command line (--cipher)
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Aes256Gcm => "aes256gcm",
+            Algorithm::ChaCha20Poly1305 => "chacha20poly1305",
+            Algorithm::Aes256GcmSiv => "aes256gcmsiv",
+            Algorithm::Aes128Gcm => "aes128gcm",
+        }
+    }
+
+    /// How many bytes of key the algorithm takes. Keys are always derived and stored at 256 bits; an algorithm
+    /// with a shorter key uses their first bytes.
+    pub fn key_len(self) -> usize {
+        match self {
+            Algorithm::Aes128Gcm => 16,
+            _ => crate::KEY_LEN,
+        }
+    }
+
+    /// The largest payload that can be sealed under a single nonce, in bytes. Past it the cipher's internal
+    /// block counter would wrap around and reuse keystream.
+    pub fn max_payload_len(self) -> u64 {
+        match self {
+            Algorithm::Aes256Gcm | Algorithm::Aes128Gcm => (1 << 36) - 32, // 2^32 - 2 blocks of 16 bytes (NIST SP 800-38D)
+            Algorithm::ChaCha20Poly1305 => (1 << 38) - 64, // 2^32 - 1 blocks of 64 bytes (RFC 8439)
+            Algorithm::Aes256GcmSiv => 1 << 36,            // RFC 8452
+        }
+    }
+
+    /// How many payloads may be sealed under one key with random 96-bit nonces before the chance
+    /// of two of them sharing a nonce stops being negligible
+    pub fn max_messages_per_key(self) -> u64 {
+        match self {
+            Algorithm::Aes256Gcm | Algorithm::Aes128Gcm | Algorithm::ChaCha20Poly1305 => 1 << 32, // NIST SP 800-38D, section 8.3
+            Algorithm::Aes256GcmSiv => 1 << 48, // A repeated nonce only reveals equal plaintexts (RFC 8452)
+        }
+    }
+
+    /// Look up an algorithm from its command line name, also spelled with dashes: `aes128-gcm` is `aes128gcm`
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.replace('-', "");
+        Algorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == name)
+    }
+
+    /// The command line names of every algorithm, for error messages: `aes256gcm, chacha20poly1305, ...`
+    pub fn names() -> String {
+        Algorithm::ALL.map(Algorithm::name).join(", ")
+    }
+}
+
+/// How the payload is padded before it is sealed, so the size of a file gives less of its contents away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    Padme, // Up to the next PADMÉ length: at most 12% more, and only O(log log n) bits of the length are left
+    Block(u32), // Up to the next multiple of this many bytes (and at least one block, so empty files aren't told apart)
+}
+
+impl Padding {
+    /// The id stored in the header for this scheme
+    pub fn id(self) -> u8 {
+        match self {
+            Padding::Padme => 1,
+            Padding::Block(_) => 2,
+        }
+    }
+
+    /// The block size stored in the header after the id, 0 for PADMÉ
+    pub fn block_size(self) -> u32 {
+        match self {
+            Padding::Padme => 0,
+            Padding::Block(size) => size,
+        }
+    }
+
+    /// Look up a scheme from its header id and block size
+    pub fn from_id(id: u8, block_size: u32) -> Option<Self> {
+        match (id, block_size) {
+            (1, 0) => Some(Padding::Padme),
+            (2, 1..) => Some(Padding::Block(block_size)),
+            _ => None,
+        }
+    }
+
+    /// How long a payload of `len` bytes is once padded with this scheme
+    pub fn padded_len(self, len: u64) -> u64 {
+        crate::padding::padded_len(self, len)
+    }
+
+    /// The name used for this scheme on the command line (--pad)
+    pub fn name(self) -> String {
+        match self {
+            Padding::Padme => "padme".to_string(),
+            Padding::Block(size) => format!("block:{}", size),
+        }
+    }
+
+    /// Look up a scheme from its command line name, `padme` or `block:<bytes>`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.split_once(':') {
+            None if name == "padme" => Some(Padding::Padme),
+            Some(("block", size)) => match size.parse() {
+                Ok(0) | Err(_) => None,
+                Ok(size) => Some(Padding::Block(size)),
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The key derivation function used to turn the password into a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    RawKey, // No derivation: the file was encrypted with a raw 256-bit key rather than a password
+    Argon2id,
+    Recipients, // A random file key, wrapped for each password or public key in the header's stanzas
+    Argon2idHkdf, // Argon2id once for a batch of files, then HKDF-SHA256 with a salt of the file's own
+}
+
+impl Kdf {
+    /// The id stored in the header for this KDF
+    pub fn id(self) -> u8 {
+        match self {
+            Kdf::RawKey => 0,
+            Kdf::Argon2id => 1,
+            Kdf::Recipients => 2,
+            Kdf::Argon2idHkdf => 3,
+        }
+    }
+
+    /// Look up a KDF from its header id
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Kdf::RawKey),
+            1 => Some(Kdf::Argon2id),
+            2 => Some(Kdf::Recipients),
+            3 => Some(Kdf::Argon2idHkdf),
+            _ => None,
+        }
+    }
+
+    /// A short name for this KDF, e.g. for exported headers
+    pub fn name(self) -> &'static str {
+        match self {
+            Kdf::RawKey => "raw",
+            Kdf::Argon2id => "argon2id",
+            Kdf::Recipients => "recipients",
+            Kdf::Argon2idHkdf => "argon2id-hkdf",
+        }
+    }
+
+    /// Look up a KDF from its name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(Kdf::RawKey),
+            "argon2id" => Some(Kdf::Argon2id),
+            "recipients" => Some(Kdf::Recipients),
+            "argon2id-hkdf" => Some(Kdf::Argon2idHkdf),
+            _ => None,
+        }
+    }
+}
+
+/// The kind of recipient a stanza wraps the file key for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StanzaKind {
+    X25519, // An ephemeral X25519 public key followed by the wrapped file key, see the recipient module
+    Password, // The salt, Argon2id cost and nonce the file key was wrapped with, followed by the wrapped key
+    Key,   // The nonce the file key was wrapped with under a raw key, followed by the wrapped key
+    Fido2, // The hmac-secret salt and nonce the file key was wrapped with, the wrapped key and the credential ID
+}
+
+impl StanzaKind {
+    /// The id stored in the header for this kind of stanza
+    pub fn id(self) -> u8 {
+        match self {
+            StanzaKind::X25519 => 1,
+            StanzaKind::Password => 2,
+            StanzaKind::Key => 3,
+            StanzaKind::Fido2 => 4,
+        }
+    }
+
+    /// Look up a stanza kind from its header id
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(StanzaKind::X25519),
+            2 => Some(StanzaKind::Password),
+            3 => Some(StanzaKind::Key),
+            4 => Some(StanzaKind::Fido2),
+            _ => None,
+        }
+    }
+
+    /// A short name for this kind, e.g. for exported headers
+    pub fn name(self) -> &'static str {
+        match self {
+            StanzaKind::X25519 => "x25519",
+            StanzaKind::Password => "password",
+            StanzaKind::Key => "key",
+            StanzaKind::Fido2 => "fido2",
+        }
+    }
+
+    /// Look up a stanza kind from its name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "x25519" => Some(StanzaKind::X25519),
+            "password" => Some(StanzaKind::Password),
+            "key" => Some(StanzaKind::Key),
+            "fido2" => Some(StanzaKind::Fido2),
+            _ => None,
+        }
+    }
+}
+
+/// A copy of the file key, wrapped for one recipient
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stanza {
+    pub kind: StanzaKind,
+    pub body: Vec<u8>,
+}
+
+/// Everything decrypt needs to know about a file, apart from the password or key
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub version: u8,
+    pub algorithm: Algorithm,
+    pub kdf: Kdf,
+    pub kdf_params: KdfParams,
+    pub salt: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub file_name: Option<String>, // The name of the file that was encrypted, without any directories
+    pub escaped_name: bool,        // The recorded or sealed name is escaped, see FLAG_ESCAPED_NAME
+    pub hash_name: bool, // The encrypted file is named after its hash, and decrypts to the recorded name
+    pub payload_len: Option<u64>, // The length of the sealed payload (after compression), to detect truncated files before decrypting
+    pub compression: Codec,       // The codec to decompress the payload with after decrypting
+    pub dictionary_id: Option<u32>, // The zstd dictionary needed to decompress the payload
+    pub recipients: Vec<Stanza>, // The file key wrapped for each recipient (only with Kdf::Recipients)
+    pub header_backup: bool,     // A copy of the header is stored after the ciphertext
+    pub sealed_name: bool,       // The payload starts with the original file name
+    pub chunk_size: Option<u32>, // The payload is sealed in chunks of this many bytes
+    pub created: Option<u64>,    // When the file was encrypted, in seconds since the Unix epoch
+    pub rewrappable: bool, // The stanzas aren't authenticated with the payload, so they can be replaced
+    pub key_id: Option<String>, // Which key or password the file was encrypted under, for tracking rotation
+    pub checksum: Option<[u8; 32]>, // The masked BLAKE3 hash of the plaintext, checked after decrypting
+    pub padding: Option<(Padding, u64)>, // How the payload is padded, and the masked number of padding bytes
+    pub password_check: Option<[u8; PASSWORD_CHECK_LEN]>, // Tells a wrong password from a damaged file
+    pub password_set: Option<u64>, // The masked time the password was set, in seconds since the Unix epoch
+}
+
+/// Reasons a header can fail to parse
+#[derive(Debug)]
+pub enum HeaderError {
+    Empty,        // There are no bytes at all, e.g. a file left behind by an interrupted copy
+    NotEncrypted, // The magic bytes are missing, so this is not one of our files
+    UnsupportedVersion(u8), // The file was written by a newer (or unknown) format version
+    UnknownAlgorithm(u8), // The algorithm id is not one this build knows about
+    UnknownKdf(u8), // The KDF id is not one this build knows about
+    Truncated,    // The file ends before the header does
+    UnknownFlags(u8), // The flags byte has bits set that this build doesn't understand
+    InvalidFileName, // The recorded file name is not valid UTF-8
+    InvalidKeyId, // The recorded key ID is not valid UTF-8
+    UnknownCodec(u8), // The compression codec id is not one this build knows about
+    UnknownStanza(u8), // A recipient stanza kind is not one this build knows about
+    InvalidArmor, // The file is armored, but the END line is missing or the text isn't base64
+    InvalidEncoding(String), // The file is in another encoding (see the encoding module) and can't be decoded
+    InvalidChunkSize,        // The payload is chunked, but the chunk size is zero
+    InvalidSalt,             // The salt of a key derived for a batch of files isn't 32 bytes
+    UnknownPadding(u8), // The padding scheme id (or its block size) is not one this build knows about
+}
+
+impl std::fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeaderError::Empty => write!(f, "the file is empty (it holds no header or ciphertext)"),
+            HeaderError::NotEncrypted => write!(f, "not an encrypted file (missing ENCR header)"),
+            HeaderError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported format version {} (this build supports versions {} to {})",
+                version, MIN_VERSION, VERSION
+            ),
+            HeaderError::UnknownAlgorithm(id) => write!(
+                f,
+                "unknown algorithm id {} (this build knows {}; the file may come from a newer version)",
+                id,
+                Algorithm::names()
+            ),
+            HeaderError::UnknownKdf(id) => write!(f, "unknown KDF id {}", id),
+            HeaderError::Truncated => write!(f, "file is too short to contain a complete header"),
+            HeaderError::UnknownFlags(flags) => write!(f, "unknown header flags {:#04x}", flags),
+            HeaderError::InvalidFileName => write!(f, "recorded file name is not valid UTF-8"),
+            HeaderError::InvalidKeyId => write!(f, "recorded key ID is not valid UTF-8"),
+            HeaderError::UnknownCodec(id) => write!(f, "unknown compression codec id {}", id),
+            HeaderError::UnknownStanza(id) => write!(f, "unknown recipient stanza kind {}", id),
+            HeaderError::UnknownPadding(id) => write!(f, "unknown padding scheme id {}", id),
+            HeaderError::InvalidEncoding(reason) => {
+                write!(f, "encoded file is damaged ({})", reason)
+            }
+            HeaderError::InvalidChunkSize => {
+                write!(f, "the payload is chunked, but the chunk size is zero")
+            }
+            HeaderError::InvalidSalt => {
+                write!(
+                    f,
+                    "the salt of a key derived for a batch of files isn't 32 bytes"
+                )
+            }
+            HeaderError::InvalidArmor => {
+                write!(
+                    f,
+                    "armored file is damaged (missing END line or invalid base64)"
+                )
+            }
+        }
+    }
+}
+
+impl Header {
+    /// Serialize the header into the bytes written in front of the ciphertext
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(self.version);
+        bytes.push(self.algorithm.id());
+        bytes.push(self.kdf.id());
+        bytes.extend_from_slice(&self.kdf_params.memory.to_le_bytes());
+        bytes.extend_from_slice(&self.kdf_params.iterations.to_le_bytes());
+        bytes.push(self.salt.len() as u8);
+        bytes.extend_from_slice(&self.salt);
+        bytes.push(self.nonce.len() as u8);
+        bytes.extend_from_slice(&self.nonce);
+        if self.version < 2 {
+            return bytes;
+        }
+
+        let mut flags = 0;
+        if self.file_name.is_some() {
+            flags |= FLAG_FILE_NAME;
+        }
+        if self.payload_len.is_some() {
+            flags |= FLAG_LENGTH;
+        }
+        if self.compression != Codec::None {
+            flags |= FLAG_COMPRESSION;
+        }
+        if self.dictionary_id.is_some() {
+            flags |= FLAG_DICTIONARY;
+        }
+        if !self.recipients.is_empty() {
+            flags |= FLAG_RECIPIENTS;
+        }
+        if self.header_backup {
+            flags |= FLAG_BACKUP;
+        }
+        if self.sealed_name {
+            flags |= FLAG_SEALED_NAME;
+        }
+        if self.chunk_size.is_some() {
+            flags |= FLAG_CHUNKED;
+        }
+        bytes.push(flags);
+        if let Some(name) = &self.file_name {
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        if let Some(len) = self.payload_len {
+            bytes.extend_from_slice(&len.to_le_bytes());
+        }
+        if self.compression != Codec::None {
+            bytes.push(self.compression.id());
+        }
+        if let Some(id) = self.dictionary_id {
+            bytes.extend_from_slice(&id.to_le_bytes());
+        }
+        if !self.recipients.is_empty() {
+            bytes.push(self.recipients.len() as u8);
+            for stanza in &self.recipients {
+                bytes.push(stanza.kind.id());
+                bytes.extend_from_slice(&(stanza.body.len() as u16).to_le_bytes());
+                bytes.extend_from_slice(&stanza.body);
+            }
+        }
+        if let Some(chunk_size) = self.chunk_size {
+            bytes.extend_from_slice(&chunk_size.to_le_bytes());
+        }
+        if self.version < 4 {
+            return bytes;
+        }
+
+        let mut flags = 0;
+        if self.created.is_some() {
+            flags |= FLAG_CREATED;
+        }
+        if self.rewrappable {
+            flags |= FLAG_REWRAPPABLE;
+        }
+        if self.key_id.is_some() {
+            flags |= FLAG_KEY_ID;
+        }
+        if self.checksum.is_some() {
+            flags |= FLAG_CHECKSUM;
+        }
+        if self.escaped_name {
+            flags |= FLAG_ESCAPED_NAME;
+        }
+        if self.hash_name {
+            flags |= FLAG_HASH_NAME;
+        }
+        if self.padding.is_some() {
+            flags |= FLAG_PADDED;
+        }
+        if self.password_check.is_some() {
+            flags |= FLAG_PASSWORD_CHECK;
+        }
+        bytes.push(flags);
+        if let Some(created) = self.created {
+            bytes.extend_from_slice(&created.to_le_bytes());
+        }
+        if let Some(key_id) = &self.key_id {
+            bytes.push(key_id.len() as u8);
+            bytes.extend_from_slice(key_id.as_bytes());
+        }
+        if let Some(checksum) = &self.checksum {
+            bytes.extend_from_slice(checksum);
+        }
+        if let Some((padding, masked_len)) = self.padding {
+            bytes.push(padding.id());
+            bytes.extend_from_slice(&padding.block_size().to_le_bytes());
+            bytes.extend_from_slice(&masked_len.to_le_bytes());
+        }
+        if let Some(check) = &self.password_check {
+            bytes.extend_from_slice(check);
+        }
+        if self.version < 5 {
+            return bytes;
+        }
+
+        let mut flags = 0;
+        if self.password_set.is_some() {
+            flags |= FLAG_PASSWORD_SET;
+        }
+        bytes.push(flags);
+        if let Some(masked_set) = self.password_set {
+            bytes.extend_from_slice(&masked_set.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// How long the ciphertext of the recorded payload length is, with the tag of every chunk.
+    /// None if the length isn't recorded.
+    pub fn ciphertext_len(&self) -> Option<u64> {
+        let chunks = match self.chunk_size {
+            Some(chunk_size) => crate::stream::chunk_count(self.payload_len?, chunk_size),
+            None => 1,
+        };
+        let tags = chunks.saturating_mul(crate::TAG_LEN as u64);
+        Some(self.payload_len?.saturating_add(tags))
+    }
+
+    /// The associated data the ciphertext is bound to: the serialized header itself from version 2 on,
+    /// and nothing for version 1 files, which didn't use associated data. A rewrappable header is serialized
+    /// without its stanzas and key ID instead.
+    pub fn associated_data<'a>(&self, header_bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.version {
+            1 => Cow::Borrowed(&[]),
+            _ if self.rewrappable => Cow::Owned(
+                Header {
+                    recipients: Vec::new(),
+                    key_id: None,
+                    ..self.clone()
+                }
+                .to_bytes(),
+            ),
+            _ => Cow::Borrowed(header_bytes),
+        }
+    }
+
+    /// The parameters the key is bound to, passed to Argon2id as associated data from version 3 on, so a header
+    /// with any of them rewritten derives another key, with the rewritten cost, that fails to authenticate.
+    /// Empty for older versions, whose keys depended only on the password, salt and KDF cost.
+    pub fn key_context(&self) -> Vec<u8> {
+        if self.version < 3 {
+            return Vec::new();
+        }
+        let mut context = Vec::with_capacity(MAGIC.len() + 11);
+        context.extend_from_slice(MAGIC);
+        context.push(self.version);
+        context.push(self.algorithm.id());
+        context.push(self.kdf.id());
+        context.extend_from_slice(&self.kdf_params.memory.to_le_bytes());
+        context.extend_from_slice(&self.kdf_params.iterations.to_le_bytes());
+        context
+    }
+
+    /// Parse a header from the start of the file contents.
+    /// Returns the header and the number of bytes it occupied, so the caller can find the ciphertext.
+    pub fn parse(bytes: &[u8]) -> Result<(Header, usize), HeaderError> {
+        let mut reader = Reader { bytes, pos: 0 };
+
+        // Check the magic first so that random files get a clear "not encrypted" error
+        if bytes.is_empty() {
+            return Err(HeaderError::Empty);
+        }
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(HeaderError::NotEncrypted);
+        }
+        reader.take(MAGIC.len())?;
+
+        let version = reader.u8()?;
+        if !(MIN_VERSION..=VERSION).contains(&version) {
+            return Err(HeaderError::UnsupportedVersion(version));
+        }
+
+        let algorithm_id = reader.u8()?;
+        let algorithm =
+            Algorithm::from_id(algorithm_id).ok_or(HeaderError::UnknownAlgorithm(algorithm_id))?;
+        let kdf_id = reader.u8()?;
+        let kdf = Kdf::from_id(kdf_id).ok_or(HeaderError::UnknownKdf(kdf_id))?;
+        let kdf_params = KdfParams {
+            memory: reader.u32()?,
+            iterations: reader.u32()?,
+        };
+
+        let salt_len = reader.u8()? as usize;
+        let salt = reader.take(salt_len)?.to_vec();
+        if kdf == Kdf::Argon2idHkdf && salt.len() != 2 * crate::SALT_LEN {
+            return Err(HeaderError::InvalidSalt);
+        }
+        let nonce_len = reader.u8()? as usize;
+        let nonce = reader.take(nonce_len)?.to_vec();
+
+        let mut file_name = None;
+        let mut payload_len = None;
+        let mut compression = Codec::None;
+        let mut dictionary_id = None;
+        let mut recipients = Vec::new();
+        let mut header_backup = false;
+        let mut sealed_name = false;
+        let mut chunk_size = None;
+        let mut created = None;
+        let mut rewrappable = false;
+        let mut key_id = None;
+        let mut checksum = None;
+        let mut escaped_name = false;
+        let mut hash_name = false;
+        let mut padding = None;
+        let mut password_check = None;
+        let mut password_set = None;
+        if version >= 2 {
+            let flags = reader.u8()?;
+            let known = FLAG_FILE_NAME
+                | FLAG_LENGTH
+                | FLAG_COMPRESSION
+                | FLAG_DICTIONARY
+                | FLAG_RECIPIENTS
+                | FLAG_BACKUP
+                | FLAG_SEALED_NAME
+                | FLAG_CHUNKED;
+            if flags & !known != 0 {
+                return Err(HeaderError::UnknownFlags(flags));
+            }
+            if flags & FLAG_FILE_NAME != 0 {
+                let name_len = reader.u16()? as usize;
+                let name = reader.take(name_len)?.to_vec();
+                file_name =
+                    Some(String::from_utf8(name).map_err(|_| HeaderError::InvalidFileName)?);
+            }
+            if flags & FLAG_LENGTH != 0 {
+                payload_len = Some(reader.u64()?);
+            }
+            if flags & FLAG_COMPRESSION != 0 {
+                let codec_id = reader.u8()?;
+                compression =
+                    Codec::from_id(codec_id).ok_or(HeaderError::UnknownCodec(codec_id))?;
+            }
+            if flags & FLAG_DICTIONARY != 0 {
+                dictionary_id = Some(reader.u32()?);
+            }
+            if flags & FLAG_RECIPIENTS != 0 {
+                for _ in 0..reader.u8()? {
+                    let kind_id = reader.u8()?;
+                    let kind =
+                        StanzaKind::from_id(kind_id).ok_or(HeaderError::UnknownStanza(kind_id))?;
+                    let body_len = reader.u16()? as usize;
+                    let body = reader.take(body_len)?.to_vec();
+                    recipients.push(Stanza { kind, body });
+                }
+            }
+            if flags & FLAG_CHUNKED != 0 {
+                match reader.u32()? {
+                    0 => return Err(HeaderError::InvalidChunkSize),
+                    size => chunk_size = Some(size),
+                }
+            }
+            header_backup = flags & FLAG_BACKUP != 0;
+            sealed_name = flags & FLAG_SEALED_NAME != 0;
+        }
+        if version >= 4 {
+            let flags = reader.u8()?;
+            let known = FLAG_CREATED
+                | FLAG_REWRAPPABLE
+                | FLAG_KEY_ID
+                | FLAG_CHECKSUM
+                | FLAG_ESCAPED_NAME
+                | FLAG_HASH_NAME
+                | FLAG_PADDED
+                | FLAG_PASSWORD_CHECK;
+            if flags & !known != 0 {
+                return Err(HeaderError::UnknownFlags(flags));
+            }
+            if flags & FLAG_CREATED != 0 {
+                created = Some(reader.u64()?);
+            }
+            if flags & FLAG_KEY_ID != 0 {
+                let id_len = reader.u8()? as usize;
+                let id = reader.take(id_len)?.to_vec();
+                key_id = Some(String::from_utf8(id).map_err(|_| HeaderError::InvalidKeyId)?);
+            }
+            if flags & FLAG_CHECKSUM != 0 {
+                let mut bytes = [0u8; 32];
+                bytes.copy_from_slice(reader.take(32)?);
+                checksum = Some(bytes);
+            }
+            if flags & FLAG_PADDED != 0 {
+                let scheme_id = reader.u8()?;
+                let scheme = Padding::from_id(scheme_id, reader.u32()?)
+                    .ok_or(HeaderError::UnknownPadding(scheme_id))?;
+                padding = Some((scheme, reader.u64()?));
+            }
+            if flags & FLAG_PASSWORD_CHECK != 0 {
+                let mut check = [0u8; PASSWORD_CHECK_LEN];
+                check.copy_from_slice(reader.take(PASSWORD_CHECK_LEN)?);
+                password_check = Some(check);
+            }
+            rewrappable = flags & FLAG_REWRAPPABLE != 0;
+            escaped_name = flags & FLAG_ESCAPED_NAME != 0;
+            hash_name = flags & FLAG_HASH_NAME != 0;
+        }
+        if version >= 5 {
+            let flags = reader.u8()?;
+            if flags & !FLAG_PASSWORD_SET != 0 {
+                return Err(HeaderError::UnknownFlags(flags));
+            }
+            if flags & FLAG_PASSWORD_SET != 0 {
+                password_set = Some(reader.u64()?);
+            }
+        }
+
+        let header = Header {
+            version,
+            algorithm,
+            kdf,
+            kdf_params,
+            salt,
+            nonce,
+            file_name,
+            escaped_name,
+            hash_name,
+            payload_len,
+            compression,
+            dictionary_id,
+            recipients,
+            header_backup,
+            sealed_name,
+            chunk_size,
+            created,
+            rewrappable,
+            key_id,
+            checksum,
+            padding,
+            password_check,
+            password_set,
+        };
+        Ok((header, reader.pos))
+    }
+
+    /// The bytes stored after the ciphertext of a file with a header backup: the header copy and its length
+    pub fn backup_trailer(header_bytes: &[u8]) -> Vec<u8> {
+        let mut trailer = header_bytes.to_vec();
+        trailer.extend_from_slice(&(header_bytes.len() as u32).to_le_bytes());
+        trailer
+    }
+
+    /// Find the header copy at the end of `contents`, returning it and the offset it starts at.
+    /// Returns None when the file has no intact backup.
+    pub fn parse_backup(contents: &[u8]) -> Option<(Header, usize)> {
+        let len_at = contents.len().checked_sub(4)?;
+        let len = u32::from_le_bytes(contents[len_at..].try_into().ok()?) as usize;
+        let start = len_at.checked_sub(len)?;
+        match Header::parse(&contents[start..len_at]) {
+            Ok((header, parsed_len)) if parsed_len == len && header.header_backup => {
+                Some((header, start))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The longest header [`write_raw_key`] writes
+pub(crate) const RAW_KEY_MAX_LEN: usize =
+    MAGIC.len() + 3 + 8 + 1 + 1 + crate::NONCE_LEN + 1 + 8 + 1 + 8 + 1;
+
+// Write the header of a raw-key payload without file name, compression or backup (see
+// Encryptor::encrypt_in_place) into `out`, returning its length. Byte for byte what Header::to_bytes writes for
+// the same fields, but without allocating.
+pub(crate) fn write_raw_key(
+    algorithm: Algorithm,
+    nonce: &[u8; crate::NONCE_LEN],
+    payload_len: Option<u64>,
+    created: Option<u64>,
+    out: &mut [u8; RAW_KEY_MAX_LEN],
+) -> usize {
+    let mut len = 0;
+    let mut put = |bytes: &[u8]| {
+        out[len..len + bytes.len()].copy_from_slice(bytes);
+        len += bytes.len();
+    };
+    put(MAGIC);
+    put(&[VERSION, algorithm.id(), Kdf::RawKey.id()]);
+    put(&[0; 8]); // No KDF parameters
+    put(&[0, nonce.len() as u8]); // No salt
+    put(nonce);
+    match payload_len {
+        Some(payload_len) => {
+            put(&[FLAG_LENGTH]);
+            put(&payload_len.to_le_bytes());
+        }
+        None => put(&[0]),
+    }
+    match created {
+        Some(created) => {
+            put(&[FLAG_CREATED]);
+            put(&created.to_le_bytes());
+        }
+        None => put(&[0]),
+    }
+    put(&[0]); // No third flags
+    len
+}
+
+// Read a header written by write_raw_key, returning the algorithm, nonce, payload length and header length.
+// None for any other header, which Header::parse handles.
+pub(crate) fn parse_raw_key(
+    bytes: &[u8],
+) -> Option<(Algorithm, [u8; crate::NONCE_LEN], Option<u64>, usize)> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let fixed = reader.take(MAGIC.len() + 3 + 8 + 2).ok()?;
+    let (magic, fixed) = fixed.split_at(MAGIC.len());
+    if magic != MAGIC || fixed[0] != VERSION || fixed[2] != Kdf::RawKey.id() {
+        return None;
+    }
+    if fixed[3..] != [0, 0, 0, 0, 0, 0, 0, 0, 0, crate::NONCE_LEN as u8] {
+        return None;
+    }
+    let algorithm = Algorithm::from_id(fixed[1])?;
+    let nonce = reader.take(crate::NONCE_LEN).ok()?.try_into().ok()?;
+    let payload_len = match reader.u8().ok()? {
+        0 => None,
+        FLAG_LENGTH => Some(reader.u64().ok()?),
+        _ => return None,
+    };
+    match reader.u8().ok()? {
+        0 => {}
+        FLAG_CREATED => {
+            reader.u64().ok()?;
+        }
+        _ => return None,
+    }
+    if reader.u8().ok()? != 0 {
+        return None;
+    }
+    Some((algorithm, nonce, payload_len, reader.pos))
+}
+
+// A small cursor over the header bytes that reports Truncated instead of panicking
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], HeaderError> {
+        let end = self.pos.checked_add(len).ok_or(HeaderError::Truncated)?;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(HeaderError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, HeaderError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, HeaderError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, HeaderError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, HeaderError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
@@ -0,0 +1,420 @@
+// `encryptor header export|graft`: the file header as standalone JSON, for indexing and recovery tools,
+// and `encryptor info`, the same header summarized for people.
+//
+// `export` prints the parsed header, so other tools can read the metadata without parsing the binary format:
+//
+//   {"version":5,"algorithm":"aes256gcm","kdf":"argon2id","kdf_params":{"memory":19456,"iterations":2},
+//    "salt":"<hex>","nonce":"<hex>","file_name":"test.txt","payload_len":6,"compression":"none",
+//    "dictionary_id":null,"recipients":[],"header_backup":false,"sealed_name":false,"escaped_name":false,
+//    "hash_name":false,"chunk_size":null,"created":1760000000,"rewrappable":false,"key_id":null,"checksum":null,
+//    "padding":null,"password_check":"<hex>","password_set":null,"header_len":75}
+//
+// A padded payload has "padding":{"scheme":"block:4096","masked_len":<number>}, the number of padding bytes as
+// masked in the header. "password_check" is null for a file that wasn't encrypted with a password alone, and
+// "password_set" is null unless the time its password was set was recorded, as masked in the header.
+//
+// `info` needs no password: it shows the format version, the cipher, what unlocks the file (with the Argon2id cost),
+// the recorded name, size and time of encryption, the compression, the padding and how the payload is laid out.
+//
+// `graft` rebuilds the binary header from that JSON and puts it in front of the ciphertext of another file,
+// replacing the header that file has. The rebuilt header is byte-for-byte the original, so the AEAD still
+// authenticates it.
+use crate::{age_file, cms_file, jwe_file, openssl_file, redact, units};
+use encryptor::compression::Codec;
+use encryptor::encoding;
+use encryptor::header::{Header, Kdf, Padding, Stanza, StanzaKind, PASSWORD_CHECK_LEN};
+use encryptor::{Algorithm, KdfParams};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+pub fn export(file_path: &Path) -> Result<String, String> {
+    let (header, header_len) = read_header(file_path)?;
+    // The recorded name is as sensitive as the path with --redact-paths
+    if let Some(name) = &header.file_name {
+        redact::path(name);
+    }
+    let value = json!({
+        "version": header.version,
+        "algorithm": header.algorithm.name(),
+        "kdf": header.kdf.name(),
+        "kdf_params": {
+            "memory": header.kdf_params.memory,
+            "iterations": header.kdf_params.iterations,
+        },
+        "salt": hex::encode(&header.salt),
+        "nonce": hex::encode(&header.nonce),
+        "file_name": header.file_name,
+        "payload_len": header.payload_len,
+        "compression": header.compression.name(),
+        "dictionary_id": header.dictionary_id,
+        "recipients": header.recipients.iter().map(|stanza| json!({
+            "kind": stanza.kind.name(),
+            "body": hex::encode(&stanza.body),
+        })).collect::<Vec<_>>(),
+        "header_backup": header.header_backup,
+        "sealed_name": header.sealed_name,
+        "escaped_name": header.escaped_name,
+        "hash_name": header.hash_name,
+        "chunk_size": header.chunk_size,
+        "created": header.created,
+        "rewrappable": header.rewrappable,
+        "key_id": header.key_id,
+        "checksum": header.checksum.map(hex::encode),
+        "padding": header.padding.map(|(scheme, masked_len)| json!({
+            "scheme": scheme.name(),
+            "masked_len": masked_len,
+        })),
+        "password_check": header.password_check.map(hex::encode),
+        "password_set": header.password_set,
+        "header_len": header_len,
+    });
+    serde_json::to_string_pretty(&value).map_err(|err| err.to_string())
+}
+
+// What the header of the file at `file_path` records: the cipher, what unlocks it and the metadata
+pub fn info(file_path: &Path) -> Result<String, String> {
+    let foreign = [
+        (age_file::is_age(file_path), "an age file"),
+        (jwe_file::is_jwe(file_path), "a JWE file"),
+        (openssl_file::is_openssl(file_path), "an OpenSSL enc file"),
+        (cms_file::is_cms(file_path), "a CMS file"),
+    ];
+    if let Some((_, format)) = foreign.iter().find(|(is, _)| *is) {
+        return Ok(format!(
+            "{}: {}, decrypted with `decrypt`",
+            file_path.display(),
+            format
+        ));
+    }
+    let (header, header_len) = read_header(file_path)?;
+    let unlocked_by = unlocked_by(&header);
+    let file_name = match (&header.file_name, header.sealed_name) {
+        (_, true) => "encrypted with the contents".to_string(),
+        (Some(name), false) => {
+            redact::path(name);
+            let name = match header.escaped_name {
+                true => format!("{} (not valid UTF-8, so escaped)", name),
+                false => name.clone(),
+            };
+            match header.hash_name {
+                true => format!(
+                    "{}, decrypted to that name (the file is named by its hash)",
+                    name
+                ),
+                false => name,
+            }
+        }
+        (None, false) => "not recorded".to_string(),
+    };
+    // The payload is the file itself unless it was compressed, padded or starts with the sealed name
+    let payload = match header.payload_len {
+        None => "not recorded".to_string(),
+        Some(len)
+            if header.compression == Codec::None
+                && !header.sealed_name
+                && header.padding.is_none() =>
+        {
+            format!("{}, the size of the original file", units::size(len))
+        }
+        Some(len) => units::size(len),
+    };
+    let created = header
+        .created
+        .map_or("not recorded".to_string(), units::time);
+    let compression = match header.dictionary_id {
+        Some(id) => format!("{} with dictionary {}", header.compression.name(), id),
+        None => header.compression.name().to_string(),
+    };
+
+    let lines = [
+        format!("{}: format version {}", file_path.display(), header.version),
+        format!("  cipher:        {}", header.algorithm.name()),
+        format!("  unlocked by:   {}", unlocked_by.join(", ")),
+        format!("  file name:     {}", file_name),
+        format!("  payload:       {}", payload),
+        format!("  created:       {}", created),
+        format!(
+            "  key ID:        {}",
+            header.key_id.as_deref().unwrap_or("not recorded")
+        ),
+        format!(
+            "  checksum:      {}",
+            match header.checksum {
+                Some(_) => "BLAKE3 of the plaintext, checked after decrypting",
+                None => "not recorded",
+            }
+        ),
+        format!(
+            "  password:      {}",
+            match (header.password_check, header.password_set) {
+                (Some(_), Some(_)) => "checked before decrypting, so a wrong one isn't taken for damage; when it was set is only known with it",
+                (Some(_), None) => "checked before decrypting, so a wrong one isn't taken for damage; when it was set isn't recorded",
+                (None, _) => "not checked",
+            }
+        ),
+        format!("  compression:   {}", compression),
+        format!(
+            "  padding:       {}",
+            header
+                .padding
+                .map_or("none".to_string(), |(scheme, _)| format!(
+                    "{}, how much of the payload is padding is only known with the key",
+                    scheme.name()
+                ))
+        ),
+        format!(
+            "  layout:        {}",
+            header
+                .chunk_size
+                .map_or("one piece".to_string(), |size| format!(
+                    "chunks of {}",
+                    units::size(u64::from(size))
+                ))
+        ),
+        format!(
+            "  header:        {}, {}",
+            units::size(header_len as u64),
+            if header.header_backup {
+                "with a backup at the end"
+            } else {
+                "no backup"
+            }
+        ),
+    ];
+    Ok(lines.join("\n"))
+}
+
+// Parse the header of the file at `file_path`, returning it and its length
+// What can open a file with this header: its password (with the Argon2id cost) or key file, or the kinds of
+// stanzas its key is wrapped in
+pub fn unlocked_by(header: &Header) -> Vec<String> {
+    let mut unlocked_by = match header.kdf {
+        Kdf::Argon2id | Kdf::Argon2idHkdf => vec![format!(
+            "a password (Argon2id, memory {} KiB, iterations {})",
+            header.kdf_params.memory, header.kdf_params.iterations
+        )],
+        Kdf::RawKey => vec!["a key file".to_string()],
+        Kdf::Recipients => Vec::new(),
+    };
+    for kind in [
+        StanzaKind::X25519,
+        StanzaKind::Password,
+        StanzaKind::Key,
+        StanzaKind::Fido2,
+    ] {
+        let count = header
+            .recipients
+            .iter()
+            .filter(|stanza| stanza.kind == kind)
+            .count();
+        if count > 0 {
+            unlocked_by.push(format!("{} {} stanza(s)", count, kind.name()));
+        }
+    }
+    if header.rewrappable {
+        unlocked_by.push("rewrappable by `rekey`".to_string());
+    }
+    unlocked_by
+}
+
+fn read_header(file_path: &Path) -> Result<(Header, usize), String> {
+    // Armored and other encoded files are read like the binary file inside them
+    let contents = fs::read(file_path)
+        .map_err(|err| format!("{}: {}", file_path.display(), err))
+        .and_then(|contents| {
+            encoding::decode(contents).map_err(|err| format!("{}: {}", file_path.display(), err))
+        })?;
+    Header::parse(&contents).map_err(|err| format!("{}: {}", file_path.display(), err))
+}
+
+// Write the header described by `json_path` followed by the ciphertext of `file_path` to `out_path`
+pub fn graft(json_path: &Path, file_path: &Path, out_path: &Path) -> Result<(), String> {
+    let json =
+        fs::read_to_string(json_path).map_err(|err| format!("{}: {}", json_path.display(), err))?;
+    let value: Value =
+        serde_json::from_str(&json).map_err(|err| format!("{}: {}", json_path.display(), err))?;
+    let header = from_json(&value).map_err(|err| format!("{}: {}", json_path.display(), err))?;
+
+    let mut output = header.to_bytes();
+    // Armored and other encoded files are read like the binary file inside them
+    let contents = fs::read(file_path)
+        .map_err(|err| format!("{}: {}", file_path.display(), err))
+        .and_then(|contents| {
+            encoding::decode(contents).map_err(|err| format!("{}: {}", file_path.display(), err))
+        })?;
+    let ciphertext = &contents[header_len(&contents, &header, output.len())..];
+
+    output.extend_from_slice(ciphertext);
+    fs::write(out_path, output).map_err(|err| format!("{}: {}", out_path.display(), err))
+}
+
+// Where the ciphertext starts in `contents`: after its own header if that still parses; nowhere if the file is
+// exactly as long as the ciphertext the header describes (bare ciphertext); otherwise after a damaged header
+// the same size as the rebuilt one.
+fn header_len(contents: &[u8], header: &Header, rebuilt_len: usize) -> usize {
+    if let Ok((_, len)) = Header::parse(contents) {
+        return len;
+    }
+    match header.ciphertext_len() {
+        Some(ciphertext_len) if contents.len() as u64 == ciphertext_len => 0,
+        _ => rebuilt_len.min(contents.len()),
+    }
+}
+
+fn from_json(value: &Value) -> Result<Header, String> {
+    let field = |name: &str| value.get(name).filter(|field| !field.is_null());
+    let number = |name: &str| {
+        field(name)
+            .and_then(Value::as_u64)
+            .ok_or_else(|| format!("'{}' must be a number", name))
+    };
+    let text = |name: &str| {
+        field(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("'{}' must be a string", name))
+    };
+    let bytes =
+        |name: &str| hex::decode(text(name)?).map_err(|_| format!("'{}' must be hex", name));
+    let small = |name: &str, max: u64| {
+        number(name).and_then(|n| {
+            (n <= max)
+                .then_some(n)
+                .ok_or_else(|| format!("'{}' is out of range", name))
+        })
+    };
+
+    let kdf_params = field("kdf_params").ok_or("'kdf_params' is missing")?;
+    let kdf_number = |name: &str| {
+        kdf_params
+            .get(name)
+            .and_then(Value::as_u64)
+            .and_then(|n| u32::try_from(n).ok())
+            .ok_or_else(|| format!("'kdf_params.{}' must be a 32-bit number", name))
+    };
+
+    let header = Header {
+        version: small("version", u8::MAX as u64)? as u8,
+        algorithm: Algorithm::from_name(text("algorithm")?).ok_or("unknown 'algorithm'")?,
+        kdf: Kdf::from_name(text("kdf")?).ok_or("unknown 'kdf'")?,
+        kdf_params: KdfParams {
+            memory: kdf_number("memory")?,
+            iterations: kdf_number("iterations")?,
+        },
+        salt: bytes("salt")?,
+        nonce: bytes("nonce")?,
+        file_name: field("file_name")
+            .map(|_| text("file_name").map(str::to_string))
+            .transpose()?,
+        payload_len: field("payload_len")
+            .map(|_| number("payload_len"))
+            .transpose()?,
+        compression: Codec::from_name(text("compression")?).ok_or("unknown 'compression'")?,
+        dictionary_id: field("dictionary_id")
+            .map(|_| small("dictionary_id", u32::MAX as u64).map(|id| id as u32))
+            .transpose()?,
+        recipients: match field("recipients") {
+            Some(stanzas) => stanzas
+                .as_array()
+                .ok_or("'recipients' must be an array")?
+                .iter()
+                .map(stanza_from_json)
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        },
+        header_backup: field("header_backup")
+            .map(|flag| {
+                flag.as_bool()
+                    .ok_or("'header_backup' must be true or false")
+            })
+            .transpose()?
+            .unwrap_or(false),
+        sealed_name: field("sealed_name")
+            .map(|flag| flag.as_bool().ok_or("'sealed_name' must be true or false"))
+            .transpose()?
+            .unwrap_or(false),
+        escaped_name: field("escaped_name")
+            .map(|flag| flag.as_bool().ok_or("'escaped_name' must be true or false"))
+            .transpose()?
+            .unwrap_or(false),
+        hash_name: field("hash_name")
+            .map(|flag| flag.as_bool().ok_or("'hash_name' must be true or false"))
+            .transpose()?
+            .unwrap_or(false),
+        chunk_size: field("chunk_size")
+            .map(|_| small("chunk_size", u32::MAX as u64).map(|size| size as u32))
+            .transpose()?,
+        created: field("created").map(|_| number("created")).transpose()?,
+        rewrappable: field("rewrappable")
+            .map(|flag| flag.as_bool().ok_or("'rewrappable' must be true or false"))
+            .transpose()?
+            .unwrap_or(false),
+        key_id: field("key_id")
+            .map(|_| text("key_id").map(str::to_string))
+            .transpose()?,
+        checksum: field("checksum")
+            .map(|_| {
+                bytes("checksum")?
+                    .try_into()
+                    .map_err(|_| "'checksum' must be 32 bytes".to_string())
+            })
+            .transpose()?,
+        padding: field("padding").map(padding_from_json).transpose()?,
+        password_check: field("password_check")
+            .map(|_| {
+                bytes("password_check")?
+                    .try_into()
+                    .map_err(|_| format!("'password_check' must be {} bytes", PASSWORD_CHECK_LEN))
+            })
+            .transpose()?,
+        password_set: field("password_set")
+            .map(|_| number("password_set"))
+            .transpose()?,
+    };
+
+    // The binary format stores these lengths in a u8 (salt, nonce) or u16 (file name)
+    if header.salt.len() > u8::MAX as usize || header.nonce.len() > u8::MAX as usize {
+        return Err("'salt' and 'nonce' can be at most 255 bytes".to_string());
+    }
+    if header.file_name.as_ref().map_or(0, String::len) > u16::MAX as usize {
+        return Err("'file_name' is too long".to_string());
+    }
+    if header.key_id.as_ref().map_or(0, String::len) > u8::MAX as usize {
+        return Err("'key_id' can be at most 255 bytes".to_string());
+    }
+    if header.chunk_size == Some(0) {
+        return Err("'chunk_size' can't be 0".to_string());
+    }
+    if header.recipients.len() > u8::MAX as usize {
+        return Err("'recipients' can have at most 255 entries".to_string());
+    }
+    Ok(header)
+}
+
+fn padding_from_json(value: &Value) -> Result<(Padding, u64), String> {
+    let scheme = value
+        .get("scheme")
+        .and_then(Value::as_str)
+        .and_then(Padding::from_name)
+        .ok_or("unknown 'padding.scheme'")?;
+    let masked_len = value
+        .get("masked_len")
+        .and_then(Value::as_u64)
+        .ok_or("'padding.masked_len' must be a number")?;
+    Ok((scheme, masked_len))
+}
+
+fn stanza_from_json(value: &Value) -> Result<Stanza, String> {
+    let kind = value
+        .get("kind")
+        .and_then(Value::as_str)
+        .and_then(StanzaKind::from_name)
+        .ok_or("unknown recipient 'kind'")?;
+    let body = value
+        .get("body")
+        .and_then(Value::as_str)
+        .and_then(|body| hex::decode(body).ok())
+        .filter(|body| body.len() <= u16::MAX as usize)
+        .ok_or("recipient 'body' must be hex")?;
+    Ok(Stanza { kind, body })
+}
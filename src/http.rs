@@ -0,0 +1,366 @@
+// http:// and https:// URLs: files encrypted straight to a web server with `encrypt --out https://...`, and
+// decrypted or verified straight from one with `decrypt https://...` and `verify https://...`, for WebDAV shares and
+// the presigned URLs of object stores. Nothing is staged on local disk: the ciphertext is sent as the body of one
+// request (PUT, or POST with --http-method post) as it is encrypted, and a download is decrypted chunk by chunk as it
+// comes in, through the library's streaming adapters, like s3:// objects are.
+//
+// The body is sent with a Content-Length, which EncryptingWriter knows from the size of the file before anything is
+// encrypted, since presigned PUT URLs refuse chunked uploads. A file that fails midway, or changes size while it is
+// read, ends the connection before the body is complete, so the server never stores a cut-off ciphertext as if it
+// were whole. Each --http-header goes with every request, e.g. the Authorization of a WebDAV share.
+//
+// Needs the `http` cargo feature; without it http(s):// paths fail with an explanation.
+#[cfg(feature = "http")]
+use crate::interrupt;
+use crate::streamed::Existing;
+#[cfg(feature = "http")]
+use crate::streamed::{self, from_io};
+#[cfg(feature = "http")]
+use encryptor::EncryptingWriter;
+use encryptor::{EncryptError, Encryptor};
+use std::io;
+#[cfg(feature = "http")]
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "http")]
+use std::sync::mpsc;
+
+// How many pieces of the ciphertext wait for the connection at most, so a slow server holds back the encryption
+#[cfg(feature = "http")]
+const PIECES: usize = 16;
+
+// Whether `path` is an http:// or https:// URL rather than a local file
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+// The URL the file at `file_path` is sent to: `url`, or `<url>/<file name>.enc` when it ends in / or `several`
+// files go to it
+pub fn target(url: &str, file_path: &Path, several: bool) -> io::Result<String> {
+    match (url.ends_with('/'), several) {
+        (false, false) => Ok(url.to_string()),
+        // A query belongs to the one resource it signs
+        _ if url.contains('?') => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} has a query, so it names one file; give it a single file",
+                redacted(url)
+            ),
+        )),
+        (prefix, _) => {
+            let name = encryptor::names::file_name(file_path).to_string_lossy();
+            let separator = if prefix { "" } else { "/" };
+            Ok(format!("{}{}{}.enc", url, separator, percent_encode(&name)))
+        }
+    }
+}
+
+// Encrypt the file at `file_path` to `url` with `method` (PUT or POST), or to `<url>/<file name>.enc` when it ends
+// in / or `several` files go to it, sending `headers` with it. Returns the URL the file was sent to, without the
+// query.
+#[cfg(feature = "http")]
+pub fn encrypt(
+    encryptor: &Encryptor,
+    file_path: &Path,
+    url: &Path,
+    several: bool,
+    method: &str,
+    headers: &[(String, String)],
+) -> Result<PathBuf, EncryptError> {
+    let url = target(&url.to_string_lossy(), file_path, several)?;
+    let input = std::fs::File::open(file_path)?;
+    let len = input.metadata()?.len();
+    let (sender, receiver) = mpsc::sync_channel(PIECES);
+    let mut writer = EncryptingWriter::new(Pipe(sender), encryptor)?;
+    let output_len = writer.output_len(len);
+
+    std::thread::scope(|scope| {
+        // The file is encrypted on another thread while this one sends the ciphertext
+        let encrypting = scope.spawn(move || -> Result<(), EncryptError> {
+            let copied =
+                io::copy(&mut interrupt::Reader(input).take(len), &mut writer).map_err(from_io)?;
+            if copied < len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the file got shorter while it was encrypted",
+                )
+                .into());
+            }
+            let pipe = writer.finish()?;
+            // Only a stream that was finished is ended, anything else breaks the body off
+            pipe.0.send(Piece::End).map_err(|_| hung_up())?;
+            Ok(())
+        });
+        let mut body = Body {
+            receiver,
+            piece: Vec::new(),
+            position: 0,
+        };
+        let request = match method {
+            "POST" => agent().post(&url),
+            _ => agent().put(&url),
+        }
+        .header("content-length", output_len.to_string())
+        .header("content-type", "application/octet-stream");
+        let sent = with_headers(request, headers)
+            .send(ureq::SendBody::from_reader(&mut body))
+            .map_err(|err| failed(method, &url, err));
+        drop(body);
+        // The error of the encryption says more than the connection that broke off because of it
+        encrypting
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+        check(sent?, method, &url)
+    })?;
+    Ok(PathBuf::from(redacted(&url)))
+}
+
+// Decrypt what `url` serves to `out_path`, or into it when it is a directory, ends in a separator or `several`
+// URLs go to it; without it, to the last segment of the URL without its extension in the current directory.
+// Returns the path written.
+#[cfg(feature = "http")]
+pub fn decrypt(
+    encryptor: &Encryptor,
+    url: &Path,
+    out_path: Option<&Path>,
+    several: bool,
+    existing: Existing,
+    headers: &[(String, String)],
+) -> Result<PathBuf, EncryptError> {
+    let url = url.to_string_lossy();
+    let name = file_name(&url).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} doesn't end with a file name; give --out", url),
+        )
+    })?;
+    let output_path = streamed::output_path(name.as_ref(), out_path, several, existing)?;
+    streamed::decrypt(get(&url, headers)?, encryptor, &output_path)
+}
+
+// Check that what `url` serves decrypts, without writing the plaintext anywhere, returning the URL
+#[cfg(feature = "http")]
+pub fn verify(
+    encryptor: &Encryptor,
+    url: &Path,
+    headers: &[(String, String)],
+) -> Result<PathBuf, EncryptError> {
+    let url = url.to_string_lossy();
+    streamed::verify(get(&url, headers)?, encryptor)?;
+    Ok(PathBuf::from(redacted(&url)))
+}
+
+// A piece of the ciphertext, or the end of a stream that was finished
+#[cfg(feature = "http")]
+enum Piece {
+    Data(Vec<u8>),
+    End,
+}
+
+// The writing end of the ciphertext, handing each write to the thread that sends it
+#[cfg(feature = "http")]
+struct Pipe(mpsc::SyncSender<Piece>);
+
+#[cfg(feature = "http")]
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(Piece::Data(buf.to_vec()))
+            .map_err(|_| hung_up())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The body of the request, read from the pipe. It fails instead of ending when the pipe closes before the end.
+#[cfg(feature = "http")]
+struct Body {
+    receiver: mpsc::Receiver<Piece>,
+    piece: Vec<u8>,
+    position: usize,
+}
+
+#[cfg(feature = "http")]
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.piece.len() {
+            match self.receiver.recv() {
+                Ok(Piece::Data(piece)) => {
+                    self.piece = piece;
+                    self.position = 0;
+                }
+                Ok(Piece::End) => return Ok(0),
+                Err(_) => return Err(io::Error::other("the encryption stopped before the end")),
+            }
+        }
+        let len = buf.len().min(self.piece.len() - self.position);
+        buf[..len].copy_from_slice(&self.piece[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+// The response body of a GET of `url`, failing unless the server answers with success
+#[cfg(feature = "http")]
+fn get(url: &str, headers: &[(String, String)]) -> Result<impl Read, EncryptError> {
+    let response = with_headers(agent().get(url), headers)
+        .call()
+        .map_err(|err| failed("GET", url, err))?;
+    Ok(check(response, "GET", url)?.into_body().into_reader())
+}
+
+#[cfg(feature = "http")]
+fn agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+#[cfg(feature = "http")]
+fn with_headers<B>(
+    mut request: ureq::RequestBuilder<B>,
+    headers: &[(String, String)],
+) -> ureq::RequestBuilder<B> {
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    request
+}
+
+// `response`, unless it is a failure, which is explained with the start of its body
+#[cfg(feature = "http")]
+fn check(
+    mut response: ureq::http::Response<ureq::Body>,
+    method: &str,
+    url: &str,
+) -> Result<ureq::http::Response<ureq::Body>, EncryptError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let text = response.body_mut().read_to_string().unwrap_or_default();
+    let explanation: String = text.trim().chars().take(200).collect();
+    let kind = match status.as_u16() {
+        401 | 403 => io::ErrorKind::PermissionDenied,
+        404 => io::ErrorKind::NotFound,
+        _ => io::ErrorKind::Other,
+    };
+    Err(io::Error::new(
+        kind,
+        format!(
+            "{} {}: the server answered {} {}",
+            method,
+            redacted(url),
+            status,
+            explanation
+        ),
+    )
+    .into())
+}
+
+#[cfg(feature = "http")]
+fn failed(method: &str, url: &str, error: ureq::Error) -> EncryptError {
+    io::Error::other(format!("{} {}: {}", method, redacted(url), error)).into()
+}
+
+#[cfg(feature = "http")]
+fn hung_up() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "the connection to the server closed before the end",
+    )
+}
+
+// `url` without its query, which holds the signature of a presigned URL
+fn redacted(url: &str) -> &str {
+    url.split_once('?').map_or(url, |(url, _)| url)
+}
+
+// The last segment of the path of `url`, percent-decoded, or None when it ends with a slash
+pub fn file_name(url: &str) -> Option<String> {
+    let path = redacted(url)
+        .split_once('#')
+        .map_or(redacted(url), |(path, _)| path);
+    let segment = path
+        .rsplit('/')
+        .next()
+        .filter(|segment| !segment.is_empty())?;
+    let mut bytes = Vec::new();
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| after.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &after[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// Percent-encode everything but the unreserved characters
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "http"))]
+pub fn encrypt(
+    _encryptor: &Encryptor,
+    _file_path: &Path,
+    _url: &Path,
+    _several: bool,
+    _method: &str,
+    _headers: &[(String, String)],
+) -> Result<PathBuf, EncryptError> {
+    Err(EncryptError::IoError(unavailable()))
+}
+
+#[cfg(not(feature = "http"))]
+pub fn decrypt(
+    _encryptor: &Encryptor,
+    _url: &Path,
+    _out_path: Option<&Path>,
+    _several: bool,
+    _existing: Existing,
+    _headers: &[(String, String)],
+) -> Result<PathBuf, EncryptError> {
+    Err(EncryptError::IoError(unavailable()))
+}
+
+#[cfg(not(feature = "http"))]
+pub fn verify(
+    _encryptor: &Encryptor,
+    _url: &Path,
+    _headers: &[(String, String)],
+) -> Result<PathBuf, EncryptError> {
+    Err(EncryptError::IoError(unavailable()))
+}
+
+#[cfg(not(feature = "http"))]
+fn unavailable() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "http(s):// paths are not supported by this build (enable the 'http' feature)",
+    )
+}
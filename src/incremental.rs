@@ -0,0 +1,354 @@
+// `encrypt --incremental --state <path> <dir>... --out <dir>`: recurring backups of directory trees that only
+// encrypt what changed since the last run. The state records every file that was encrypted, with the size,
+// modification time and BLAKE3 hash of its plaintext and where its output went. A file whose size and time haven't
+// changed is skipped without being read; one whose time changed is hashed, and only encrypted again if its contents
+// did. A file whose output is missing or elsewhere is encrypted again too. With --delete, the outputs of files that
+// no longer exist are removed. The state is JSON encrypted with the same password or key as the files, since its
+// hashes would tell what a file contains:
+//
+//   {"state": 1, "version": "...", "updated_at": 1760000000,
+//    "files": [{"path": "photos/a.jpg", "size": 1024, "mtime": 1760000000000000000, "blake3": "...",
+//               "output": "backup/a.jpg.enc"}, ...]}
+//
+// Paths are those walked from the directories as given, and outputs mirror the trees under --out like `watch` does
+// (`photos/2026/a.jpg` to `backup/2026/a.jpg.enc`). A path that isn't valid UTF-8 is recorded escaped like a file
+// name in a header (see `encryptor::names`), with "escaped": true, or "output_escaped": true for the output.
+use crate::manifest;
+use crate::path_filter::PathFilter;
+use crate::rotate;
+use encryptor::{names, EncryptError, Encryptor};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const FORMAT_VERSION: u64 = 1;
+
+// What the state records about a file that was encrypted
+struct Entry {
+    size: u64,
+    mtime: u64, // In nanoseconds since the Unix epoch, 0 when the file system has none
+    hash: String,
+    output: PathBuf,
+}
+
+// A file this run encrypts: where its output goes, and what to record once it is there (unless it couldn't be read)
+struct Planned {
+    output: PathBuf,
+    entry: Option<Entry>,
+}
+
+pub struct State {
+    path: PathBuf,
+    existed: bool,
+    entries: BTreeMap<PathBuf, Entry>,
+    planned: HashMap<PathBuf, Planned>,
+}
+
+impl State {
+    // The state at `path`, or an empty one on the first run, when there's none yet
+    pub fn load(encryptor: &Encryptor, path: &Path) -> Result<Self, EncryptError> {
+        let mut state = State {
+            path: path.to_path_buf(),
+            existed: false,
+            entries: BTreeMap::new(),
+            planned: HashMap::new(),
+        };
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(state),
+            Err(err) => return Err(err.into()),
+        };
+        state.existed = true;
+        let document: Value = serde_json::from_slice(&encryptor.decrypt_bytes(&bytes)?)
+            .map_err(|_| invalid(format!("{} is not a backup state", path.display())))?;
+        if document["state"] != FORMAT_VERSION {
+            return Err(invalid(format!(
+                "{} is not a backup state, or is from a newer version",
+                path.display()
+            )));
+        }
+        let damaged = || invalid(format!("the state {} is damaged", path.display()));
+        for file in document["files"].as_array().ok_or_else(damaged)? {
+            let (Some(file_path), Some(size), Some(mtime), Some(hash), Some(output)) = (
+                recorded(file, "path", "escaped"),
+                file["size"].as_u64(),
+                file["mtime"].as_u64(),
+                file["blake3"].as_str(),
+                recorded(file, "output", "output_escaped"),
+            ) else {
+                return Err(damaged());
+            };
+            let entry = Entry {
+                size,
+                mtime,
+                hash: hash.to_string(),
+                output,
+            };
+            state.entries.insert(file_path, entry);
+        }
+        Ok(state)
+    }
+
+    // The files under `roots` that `filter` takes and that are new or changed since the last run, in a stable order,
+    // with a line saying how many that is. The directories of their outputs under `out` are created. Neither the
+    // outputs nor the state are backed up themselves when they are inside a tree.
+    pub fn plan(
+        &mut self,
+        roots: &[PathBuf],
+        out: &Path,
+        filter: &PathFilter,
+    ) -> Result<(Vec<PathBuf>, String), String> {
+        fs::create_dir_all(out).map_err(|err| {
+            format!(
+                "Cannot create the output directory {}: {}",
+                out.display(),
+                err
+            )
+        })?;
+        let out_dir = fs::canonicalize(out).ok();
+        let state_path = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map_or_else(|| fs::canonicalize("."), fs::canonicalize)
+            .ok()
+            .map(|parent| parent.join(names::file_name(&self.path)));
+        let mut sources: HashMap<PathBuf, PathBuf> = HashMap::new();
+        let mut changed = Vec::new();
+        for root in roots {
+            if !root.exists() {
+                return Err(format!("{} doesn't exist", root.display()));
+            }
+            let mut files = Vec::new();
+            rotate::walk(root, "", &mut filter.walk(), &mut files);
+            for file_path in files {
+                let canonical = fs::canonicalize(&file_path).ok();
+                let inside = |dir: &Option<PathBuf>| {
+                    canonical
+                        .as_ref()
+                        .zip(dir.as_ref())
+                        .is_some_and(|(canonical, dir)| canonical.starts_with(dir))
+                };
+                if inside(&out_dir) || inside(&state_path) {
+                    continue;
+                }
+                let output = output_path(root, &file_path, out);
+                if let Some(other) = sources.insert(output.clone(), file_path.clone()) {
+                    return Err(format!(
+                        "{} and {} would both be encrypted to {}",
+                        other.display(),
+                        file_path.display(),
+                        output.display()
+                    ));
+                }
+                let entry = match self.check(&file_path, &output) {
+                    Ok(None) => continue,
+                    Ok(Some(entry)) => Some(entry),
+                    // Encrypting it fails too, and says why
+                    Err(err) => {
+                        log::warn!("Cannot read {}: {}", file_path.display(), err);
+                        None
+                    }
+                };
+                if let Some(parent) = output.parent() {
+                    fs::create_dir_all(parent).map_err(|err| {
+                        format!(
+                            "Cannot create the output directory {}: {}",
+                            parent.display(),
+                            err
+                        )
+                    })?;
+                }
+                self.planned
+                    .insert(file_path.clone(), Planned { output, entry });
+                changed.push(file_path);
+            }
+        }
+        let summary = match self.existed {
+            true => format!(
+                "{} of {} files changed since the last run recorded in {}",
+                changed.len(),
+                sources.len(),
+                self.path.display()
+            ),
+            false => format!(
+                "No state at {} yet: encrypting all {} files",
+                self.path.display(),
+                sources.len()
+            ),
+        };
+        Ok((changed, summary))
+    }
+
+    // Where the output of a file `plan` returned goes
+    pub fn output(&self, file_path: &Path) -> Option<&Path> {
+        self.planned
+            .get(file_path)
+            .map(|planned| planned.output.as_path())
+    }
+
+    // Record the files that were encrypted, given the results of the files `plan` returned, in the same order
+    pub fn record(&mut self, files: &[PathBuf], results: &[Result<PathBuf, EncryptError>]) {
+        for (file_path, result) in files.iter().zip(results) {
+            if let (
+                Ok(_),
+                Some(Planned {
+                    entry: Some(entry), ..
+                }),
+            ) = (result, self.planned.remove(file_path))
+            {
+                self.entries.insert(file_path.clone(), entry);
+            }
+        }
+    }
+
+    // How many of the files recorded no longer exist
+    pub fn deleted(&self) -> usize {
+        self.entries.keys().filter(|path| gone(path)).count()
+    }
+
+    // Remove the outputs of the files that no longer exist, and forget them. Returns how many were removed, and why
+    // any others couldn't be; those are kept, to be tried again on the next run.
+    pub fn prune(&mut self) -> (usize, Vec<String>) {
+        let mut removed = 0;
+        let mut failures = Vec::new();
+        self.entries.retain(|file_path, entry| {
+            if !gone(file_path) {
+                return true;
+            }
+            match fs::remove_file(&entry.output) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    failures.push(format!(
+                        "Cannot remove {}, the output of the deleted {}: {}",
+                        entry.output.display(),
+                        file_path.display(),
+                        err
+                    ));
+                    true
+                }
+                _ => {
+                    log::debug!(
+                        "Removed {}: {} was deleted",
+                        entry.output.display(),
+                        file_path.display()
+                    );
+                    removed += 1;
+                    false
+                }
+            }
+        });
+        (removed, failures)
+    }
+
+    // Encrypt the state into its path. It is written next to it first and renamed into place, so a run that dies
+    // meanwhile leaves the last one whole.
+    pub fn save(&self, encryptor: &Encryptor) -> Result<String, EncryptError> {
+        let files: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|(file_path, entry)| {
+                let mut file = json!({
+                    "size": entry.size,
+                    "mtime": entry.mtime,
+                    "blake3": entry.hash,
+                });
+                record(&mut file, "path", "escaped", file_path);
+                record(&mut file, "output", "output_escaped", &entry.output);
+                file
+            })
+            .collect();
+        let document = json!({
+            "state": FORMAT_VERSION,
+            "version": env!("CARGO_PKG_VERSION"),
+            "updated_at": SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            "files": files,
+        });
+        let temporary = names::with_suffix(&self.path, ".tmp");
+        fs::write(
+            &temporary,
+            encryptor.encrypt_bytes(document.to_string().as_bytes())?,
+        )?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(format!(
+            "Recorded {} files in the state {}",
+            self.entries.len(),
+            self.path.display()
+        ))
+    }
+
+    // What to record about `file_path` once it is encrypted to `output`, or None when its last output is current
+    fn check(&mut self, file_path: &Path, output: &Path) -> io::Result<Option<Entry>> {
+        let metadata = fs::metadata(file_path)?;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos() as u64);
+        let current = self.entries.get_mut(file_path).filter(|entry| {
+            entry.output == output && entry.size == metadata.len() && output.exists()
+        });
+        if current
+            .as_ref()
+            .is_some_and(|entry| entry.mtime == mtime && mtime != 0)
+        {
+            return Ok(None);
+        }
+        let (hash, size) = manifest::hash_file(file_path)?;
+        let hash = hash.to_hex().to_string();
+        match current {
+            // Touched, but with the same contents: only its new time is recorded
+            Some(entry) if entry.hash == hash => {
+                entry.mtime = mtime;
+                Ok(None)
+            }
+            _ => Ok(Some(Entry {
+                size,
+                mtime,
+                hash,
+                output: output.to_path_buf(),
+            })),
+        }
+    }
+}
+
+// Where the output of `file_path`, found under `root`, goes in `out`: at the same place under it as the file is under
+// the root, or named after the file when the root is the file itself
+fn output_path(root: &Path, file_path: &Path, out: &Path) -> PathBuf {
+    let relative = match file_path.strip_prefix(root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+        _ => Path::new(names::file_name(file_path)),
+    };
+    names::with_suffix(&out.join(relative), ".enc")
+}
+
+// Whether `path` no longer exists
+fn gone(path: &Path) -> bool {
+    matches!(fs::symlink_metadata(path), Err(err) if err.kind() == io::ErrorKind::NotFound)
+}
+
+// The path under `key` of a file in the state, unescaped when `escaped` says so
+fn recorded(file: &Value, key: &str, escaped: &str) -> Option<PathBuf> {
+    let path = file[key].as_str()?;
+    match file[escaped] == true {
+        true => names::unescape(path).map(PathBuf::from),
+        false => Some(PathBuf::from(path)),
+    }
+}
+
+// Put `path` under `key` of a file in the state, escaped with `escaped` set if it has to be
+fn record(file: &mut Value, key: &str, escaped: &str, path: &Path) {
+    let (text, was_escaped) = names::record(path.as_os_str());
+    file[key] = json!(text);
+    if was_escaped {
+        file[escaped] = json!(true);
+    }
+}
+
+fn invalid(message: String) -> EncryptError {
+    EncryptError::IoError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
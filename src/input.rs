@@ -0,0 +1,71 @@
+// Input files, read with read calls through a buffer (see Encryptor::with_io_buffer) or, with Encryptor::with_mmap,
+// through a memory map. A mapped file is copied out of the page cache as it is read, without a system call per
+// piece, and its pages can be dropped again as soon as they were read, so streaming a huge file doesn't grow the
+// process's memory.
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// An open input file
+pub(crate) enum Input {
+    File(BufReader<File>),
+    #[cfg(feature = "mmap")]
+    Mapped(io::Cursor<memmap2::Mmap>),
+}
+
+impl Input {
+    // Open the file at `file_path`, mapped into memory if `mmap` is set and otherwise read `buffer_len` bytes at a
+    // time (0 reads what each caller asks for)
+    pub(crate) fn open(file_path: &Path, mmap: bool, buffer_len: usize) -> io::Result<Input> {
+        Input::new(File::open(file_path)?, mmap, buffer_len)
+    }
+
+    // Read `file` from its start, like `open`. An empty file has nothing to map, and no buffer is larger than the
+    // file it holds, so small files don't cost a whole one.
+    pub(crate) fn new(mut file: File, mmap: bool, buffer_len: usize) -> io::Result<Input> {
+        #[cfg(feature = "mmap")]
+        if mmap && file.metadata()?.len() > 0 {
+            // SAFETY: the map is only read, through the Cursor. Another process truncating the file meanwhile would
+            // make reading the pages past its new end fault, which is why mapping is opt-in and meant for local
+            // files that nothing else writes to (see Encryptor::with_mmap).
+            let map = unsafe { memmap2::Mmap::map(&file)? };
+            #[cfg(unix)]
+            let _ = map.advise(memmap2::Advice::Sequential); // Only a hint to read ahead and drop behind
+            return Ok(Input::Mapped(io::Cursor::new(map)));
+        }
+        #[cfg(not(feature = "mmap"))]
+        let _ = mmap;
+        let file_len = file.metadata()?.len();
+        let buffer_len = buffer_len.min(file_len.try_into().unwrap_or(usize::MAX));
+        file.seek(SeekFrom::Start(0))?;
+        Ok(Input::File(BufReader::with_capacity(buffer_len, file)))
+    }
+
+    pub(crate) fn len(&self) -> io::Result<u64> {
+        match self {
+            Input::File(file) => Ok(file.get_ref().metadata()?.len()),
+            #[cfg(feature = "mmap")]
+            Input::Mapped(map) => Ok(map.get_ref().len() as u64),
+        }
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::File(file) => file.read(buf),
+            #[cfg(feature = "mmap")]
+            Input::Mapped(map) => map.read(buf),
+        }
+    }
+}
+
+impl Seek for Input {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        match self {
+            Input::File(file) => file.seek(position),
+            #[cfg(feature = "mmap")]
+            Input::Mapped(map) => map.seek(position),
+        }
+    }
+}
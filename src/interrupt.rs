@@ -0,0 +1,93 @@
+// Ctrl-C, and the SIGTERM and SIGHUP a service manager or a closed terminal stops a run with. The first one doesn't
+// end the process: it cancels the files being worked on through their CancellationToken, so each stops at its next
+// chunk and removes its unfinished output (with --resume a streamed one is kept as <output>.partial to continue),
+// the files not started yet are skipped, and the command exits with ENC_CANCELLED. A second one ends the process
+// at once, which may leave a temporary `<output>.<random>.tmp` behind, but never a partial file under the output's
+// own name. Unix only; elsewhere Ctrl-C ends the process as it always did.
+use encryptor::{CancellationToken, EncryptError};
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set by the first signal
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// The end of a pipe the handler wakes the thread that cancels through, since a handler can't do much itself
+#[cfg(unix)]
+static WAKE: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(-1);
+
+// Have the signals cancel `cancel` instead of ending the process
+#[cfg(unix)]
+pub fn cancel_on_signals(cancel: &CancellationToken) {
+    use std::fs::File;
+    use std::os::fd::FromRawFd;
+
+    extern "C" fn interrupt(signal: libc::c_int) {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            // SAFETY: signal and raise are async-signal-safe
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+            return;
+        }
+        let byte = 1u8;
+        // SAFETY: write is async-signal-safe, and the pipe stays open as long as the process runs
+        unsafe {
+            libc::write(
+                WAKE.load(Ordering::SeqCst),
+                &byte as *const u8 as *const libc::c_void,
+                1,
+            );
+        }
+    }
+
+    let mut fds = [0; 2];
+    // SAFETY: pipe fills in the two descriptors of the array it is given
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        log::debug!(
+            "Cannot handle interruptions: {}",
+            io::Error::last_os_error()
+        );
+        return;
+    }
+    WAKE.store(fds[1], Ordering::SeqCst);
+    // SAFETY: the read end was just opened, and nothing else owns it
+    let mut wake = unsafe { File::from_raw_fd(fds[0]) };
+    let cancel = cancel.clone();
+    std::thread::spawn(move || {
+        let mut byte = [0u8];
+        if wake.read_exact(&mut byte).is_ok() {
+            log::warn!("Interrupted: stopping and removing the unfinished outputs (interrupt again to quit at once)");
+            cancel.cancel();
+        }
+    });
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        // SAFETY: the handler only touches an atomic and calls async-signal-safe functions
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn cancel_on_signals(_cancel: &CancellationToken) {}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// A Read that fails with EncryptError::Cancelled once interrupted, for the copies the library doesn't run itself
+pub struct Reader<R>(pub R);
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if interrupted() {
+            return Err(io::Error::other(EncryptError::Cancelled));
+        }
+        self.0.read(buf)
+    }
+}
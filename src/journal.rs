@@ -0,0 +1,267 @@
+// The transaction journal of `encrypt --shred` batches, and the `resume-journal` command.
+//
+// Before any file is touched, every file of the batch is recorded as planned. Each step of a file is then appended
+// as a line of JSON and synced to disk before the next step starts:
+//
+//   {"step":"planned","file":"a.txt"}    the original is intact; a.txt.enc may be missing or partial
+//   {"step":"written","file":"a.txt"}    a.txt.enc is complete and on disk
+//   {"step":"verified","file":"a.txt"}   a.txt.enc decrypts
+//   {"step":"shredding","file":"a.txt"}  the original is being overwritten
+//   {"step":"shredded","file":"a.txt"}   the original is gone
+//
+// A path that isn't valid UTF-8 is recorded escaped like a file name in a header (see `encryptor::names`), with
+// "escaped":true.
+//
+// The journal is removed once every file of the batch is shredded. If the process dies before that, the journal
+// stays behind, and `resume-journal` reads the last step of every file to either finish the batch or, with
+// --rollback, undo it: outputs are removed, and originals that were already shredded are first decrypted back
+// from their verified outputs.
+use crate::shred;
+use encryptor::{names, CancellationToken, EncryptError, Encryptor, Progress};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Where the journal goes without --journal
+pub const DEFAULT_PATH: &str = "encryptor.journal";
+
+// How far a file got, in order
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Step {
+    Planned,
+    Written,
+    Verified,
+    Shredding,
+    Shredded,
+}
+
+impl Step {
+    fn name(self) -> &'static str {
+        match self {
+            Step::Planned => "planned",
+            Step::Written => "written",
+            Step::Verified => "verified",
+            Step::Shredding => "shredding",
+            Step::Shredded => "shredded",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "planned" => Some(Step::Planned),
+            "written" => Some(Step::Written),
+            "verified" => Some(Step::Verified),
+            "shredding" => Some(Step::Shredding),
+            "shredded" => Some(Step::Shredded),
+            _ => None,
+        }
+    }
+}
+
+// An open journal. Worker threads append to it concurrently, one whole line at a time.
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl Journal {
+    // Start the journal of a new batch, recording every file as planned. An existing journal is never replaced,
+    // since it belongs to a batch that was neither finished nor rolled back.
+    pub fn begin(path: &Path, files: &[PathBuf]) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::AlreadyExists => format!(
+                    "{} records an unfinished batch; finish it or roll it back with `encryptor resume-journal {}` first",
+                    path.display(),
+                    path.display()
+                ),
+                _ => format!("Cannot create the journal {}: {}", path.display(), err),
+            })?;
+        let journal = Journal {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        };
+        for file_path in files {
+            journal
+                .record(file_path, Step::Planned)
+                .map_err(|err| format!("Cannot write the journal {}: {}", path.display(), err))?;
+        }
+        Ok(journal)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Remove the journal once every file of the batch is done
+    pub fn finish(self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+
+    // Encrypt `file_path`, verify the output and shred the original, starting after `step`.
+    // Returns the path of the output.
+    pub fn encrypt_and_shred(
+        &self,
+        encryptor: &Encryptor,
+        file_path: &Path,
+        step: Step,
+        progress: impl FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf, EncryptError> {
+        let output_path = output_path(file_path);
+        if step < Step::Written {
+            // The output is synced to disk before encrypt_file_with returns
+            encryptor.encrypt_file_with(file_path, progress, cancel)?;
+            self.record(file_path, Step::Written)?;
+        }
+        if step < Step::Verified {
+            encryptor.verify_file(&output_path)?;
+            self.record(file_path, Step::Verified)?;
+        }
+        if step < Step::Shredding {
+            self.record(file_path, Step::Shredding)?;
+        }
+        if step < Step::Shredded {
+            shred::shred(file_path)?;
+            self.record(file_path, Step::Shredded)?;
+        }
+        Ok(output_path)
+    }
+
+    // Undo whatever was done to `file_path`, leaving the original in place and no output
+    fn roll_back(
+        &self,
+        encryptor: &Encryptor,
+        file_path: &Path,
+        step: Step,
+    ) -> Result<(), EncryptError> {
+        let output_path = output_path(file_path);
+        // Shredding may have started, so the original is restored from the output, which was verified first
+        if step >= Step::Shredding {
+            encryptor.decrypt_file(&output_path)?;
+            File::open(file_path)?.sync_all()?;
+        }
+        match fs::remove_file(&output_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        self.record(file_path, Step::Planned)?;
+        Ok(())
+    }
+
+    // Append a step, synced to disk before returning
+    fn record(&self, file_path: &Path, step: Step) -> io::Result<()> {
+        let mut entry = json!({"step": step.name()});
+        match names::record(file_path.as_os_str()) {
+            (path, false) => entry["file"] = json!(path),
+            (path, true) => {
+                entry["file"] = json!(path);
+                entry["escaped"] = json!(true);
+            }
+        }
+        let mut line = entry.to_string();
+        line.push('\n');
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        encryptor::fault::point(&format!("journal-{}", step.name()));
+        Ok(())
+    }
+}
+
+// A file of the batch, with its output or the error that stopped it
+pub type FileResult = (PathBuf, Result<PathBuf, EncryptError>);
+
+// Finish the batch recorded in the journal at `path`, or undo it with `rollback`, using a fresh Encryptor from
+// `encryptor` for every file. Returns every file with its output (or, when rolling back, the restored original)
+// or its error. The journal is removed when every file succeeded.
+pub fn resume(
+    path: &Path,
+    rollback: bool,
+    encryptor: impl Fn() -> Encryptor,
+) -> Result<Vec<FileResult>, String> {
+    let files = read(path)?;
+    let file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .map_err(|err| format!("Cannot open the journal {}: {}", path.display(), err))?;
+    let journal = Journal {
+        path: path.to_path_buf(),
+        file: Mutex::new(file),
+    };
+
+    let results: Vec<_> = files
+        .into_iter()
+        .map(|(file_path, step)| {
+            let result = if rollback {
+                journal
+                    .roll_back(&encryptor(), &file_path, step)
+                    .map(|()| file_path.clone())
+            } else {
+                let cancel = CancellationToken::new();
+                journal.encrypt_and_shred(&encryptor(), &file_path, step, |_| {}, &cancel)
+            };
+            (file_path, result)
+        })
+        .collect();
+    if results.iter().all(|(_, result)| result.is_ok()) {
+        journal
+            .finish()
+            .map_err(|err| format!("Cannot remove the journal {}: {}", path.display(), err))?;
+    }
+    Ok(results)
+}
+
+// The last step recorded for each file, in the order the files were planned. A torn last line, left by a crash
+// in the middle of writing it, is ignored: its step was never reached.
+fn read(path: &Path) -> Result<Vec<(PathBuf, Step)>, String> {
+    let file = File::open(path)
+        .map_err(|err| format!("Cannot open the journal {}: {}", path.display(), err))?;
+    let lines = BufReader::new(file)
+        .lines()
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(|err| format!("Cannot read the journal {}: {}", path.display(), err))?;
+
+    let mut files: Vec<(PathBuf, Step)> = Vec::new();
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
+    for (number, line) in lines.iter().enumerate() {
+        let entry = serde_json::from_str::<Value>(line).ok().and_then(|entry| {
+            let step = Step::from_name(entry.get("step")?.as_str()?)?;
+            let file_path = entry.get("file")?.as_str()?;
+            let file_path = match entry.get("escaped").and_then(Value::as_bool) {
+                Some(true) => names::unescape(file_path)?,
+                _ => file_path.into(),
+            };
+            Some((PathBuf::from(file_path), step))
+        });
+        let Some((file_path, step)) = entry else {
+            if number + 1 == lines.len() {
+                break;
+            }
+            return Err(format!(
+                "{}:{}: not a journal entry",
+                path.display(),
+                number + 1
+            ));
+        };
+        match index.get(&file_path) {
+            Some(&at) => files[at].1 = step,
+            None => {
+                index.insert(file_path.clone(), files.len());
+                files.push((file_path, step));
+            }
+        }
+    }
+    Ok(files)
+}
+
+// Where `encrypt` writes the output of `file_path`
+fn output_path(file_path: &Path) -> PathBuf {
+    names::with_suffix(file_path, ".enc")
+}
@@ -0,0 +1,407 @@
+//! JSON Web Encryption (RFC 7516), so encrypted payloads can be read by existing JOSE libraries.
+//!
+//! The content is always encrypted with `A256GCM`. The content key comes from one of:
+//!
+//! - a password, with `PBES2-HS512+A256KW` (RFC 7518 section 4.8): PBKDF2-HMAC-SHA512 over a random `p2s` salt
+//!   derives a key that wraps a random content key with AES key wrap
+//! - an X25519 public key, with `ECDH-ES` (RFC 7518 section 4.6 and RFC 8037): the content key is derived with the
+//!   Concat KDF from a key agreement with a fresh ephemeral key, which is sent in the `epk` header
+//!
+//! Both the compact serialization (`header.key.iv.ciphertext.tag`) and the flattened JSON serialization are
+//! written; decrypting also accepts the general JSON serialization, trying every recipient in it.
+//! JWE has no file name, compression or header backup, so none of the other options of the native format apply.
+use crate::header::HeaderError;
+use crate::recipient::{Identity, Recipient};
+use crate::{cipher, Algorithm, CryptoError, EncryptError, KeyBytes, KEY_LEN, NONCE_LEN, TAG_LEN};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::Zeroizing;
+
+const ENC: &str = "A256GCM";
+const PBES2: &str = "PBES2-HS512+A256KW";
+const ECDH_ES: &str = "ECDH-ES";
+
+/// PBKDF2 iterations for new files, the OWASP recommendation for PBKDF2-HMAC-SHA512
+pub const PBES2_ITERATIONS: u32 = 210_000;
+// Files asking for more iterations than this are refused rather than keeping the CPU busy for minutes;
+// fewer than RFC 7518's minimum of 1000 are refused as too weak
+const MAX_ITERATIONS: u64 = 10_000_000;
+const MIN_ITERATIONS: u64 = 1000;
+
+/// How the JWE is written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Serialization {
+    Compact, // header.encrypted_key.iv.ciphertext.tag, each part base64url
+    Json,    // The flattened JSON serialization, a single JSON object
+}
+
+/// What a JWE is encrypted to or decrypted with
+#[derive(Debug, Clone, Copy)]
+pub enum Key<'a> {
+    Password(&'a str),
+    Recipient(&'a Recipient), // Encrypts only
+    Identity(&'a Identity),   // Decrypts, and encrypts to its own public key
+}
+
+/// Encrypt `plaintext` into a JWE for `key`
+pub fn encrypt(
+    plaintext: &[u8],
+    key: Key,
+    serialization: Serialization,
+) -> Result<Vec<u8>, EncryptError> {
+    let mut header = Map::new();
+    let (content_key, encrypted_key) = match key {
+        Key::Password(password) => {
+            let mut salt = [0u8; 16];
+            cipher::fill_random(&mut salt)?;
+            header.insert("alg".into(), PBES2.into());
+            header.insert("p2s".into(), URL_SAFE_NO_PAD.encode(salt).into());
+            header.insert("p2c".into(), PBES2_ITERATIONS.into());
+            let content_key = Zeroizing::new(crate::generate_key()?);
+            let kek = pbes2_key(password, &salt, PBES2_ITERATIONS);
+            let mut wrapped = [0u8; KEY_LEN + 8];
+            aes_kw::KekAes256::new(&(*kek).into())
+                .wrap(&*content_key, &mut wrapped)
+                .map_err(|_| EncryptError::AeadError(CryptoError))?;
+            (content_key, wrapped.to_vec())
+        }
+        Key::Recipient(recipient) => ecdh_es(recipient, &mut header)?,
+        Key::Identity(identity) => ecdh_es(&identity.recipient(), &mut header)?,
+    };
+    header.insert("enc".into(), ENC.into());
+
+    let protected = URL_SAFE_NO_PAD.encode(Value::Object(header).to_string());
+    let mut nonce = [0u8; NONCE_LEN];
+    cipher::fill_random(&mut nonce)?;
+    let mut sealed = plaintext.to_vec();
+    cipher::seal_in_place(
+        Algorithm::Aes256Gcm,
+        &content_key,
+        &nonce,
+        protected.as_bytes(),
+        &mut sealed,
+    )?;
+    let tag = sealed.split_off(sealed.len() - TAG_LEN);
+
+    let output = match serialization {
+        Serialization::Compact => [
+            protected,
+            URL_SAFE_NO_PAD.encode(&encrypted_key),
+            URL_SAFE_NO_PAD.encode(nonce),
+            URL_SAFE_NO_PAD.encode(&sealed),
+            URL_SAFE_NO_PAD.encode(&tag),
+        ]
+        .join("."),
+        Serialization::Json => {
+            let mut object = Map::new();
+            object.insert("protected".into(), protected.into());
+            if !encrypted_key.is_empty() {
+                object.insert(
+                    "encrypted_key".into(),
+                    URL_SAFE_NO_PAD.encode(&encrypted_key).into(),
+                );
+            }
+            object.insert("iv".into(), URL_SAFE_NO_PAD.encode(nonce).into());
+            object.insert("ciphertext".into(), URL_SAFE_NO_PAD.encode(&sealed).into());
+            object.insert("tag".into(), URL_SAFE_NO_PAD.encode(&tag).into());
+            Value::Object(object).to_string() + "\n"
+        }
+    };
+    Ok(output.into_bytes())
+}
+
+/// Decrypt a JWE in any serialization with `key`. A wrong password or identity fails like one for the
+/// native format, with [`EncryptError::AeadError`].
+pub fn decrypt(data: &[u8], key: Key) -> Result<Vec<u8>, EncryptError> {
+    let jwe = parse(data)?;
+    let protected_header = match &jwe.protected {
+        Some(protected) => header_object(&decode(protected, "protected")?)?,
+        None => Map::new(),
+    };
+    // The AAD is the protected header as written, followed by the JSON "aad" member if there is one
+    let aad = match &jwe.aad {
+        Some(aad) => format!("{}.{}", jwe.protected.as_deref().unwrap_or_default(), aad),
+        None => jwe.protected.clone().unwrap_or_default(),
+    };
+    let nonce = decode(&jwe.iv, "iv")?;
+    let mut sealed = decode(&jwe.ciphertext, "ciphertext")?;
+    sealed.extend_from_slice(&decode(&jwe.tag, "tag")?);
+
+    let mut last_error = EncryptError::AeadError(CryptoError);
+    for recipient in &jwe.recipients {
+        // Header parameters may be split between the protected, shared and per-recipient headers
+        let mut header = protected_header.clone();
+        for unprotected in [&jwe.unprotected, &recipient.header].into_iter().flatten() {
+            for (name, value) in unprotected {
+                header.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        let content_key = match content_key(&header, recipient.encrypted_key.as_deref(), key) {
+            Ok(content_key) => content_key,
+            Err(err) => {
+                last_error = err;
+                continue;
+            }
+        };
+        let mut plaintext = sealed.clone();
+        match cipher::open_in_place(
+            Algorithm::Aes256Gcm,
+            &content_key,
+            &nonce,
+            aad.as_bytes(),
+            &mut plaintext,
+        ) {
+            Ok(()) => return Ok(plaintext),
+            Err(err) => last_error = err.into(),
+        }
+    }
+    Err(last_error)
+}
+
+/// Whether `data` is a JWE: five base64url parts separated by dots, or a JSON object with a ciphertext and an iv
+pub fn is_jwe(data: &[u8]) -> bool {
+    let data = data.trim_ascii();
+    if data.starts_with(b"eyJ") {
+        let parts = data.split(|&byte| byte == b'.').count();
+        return parts == 5
+            && data
+                .iter()
+                .all(|byte| byte.is_ascii_alphanumeric() || b"-_.".contains(byte));
+    }
+    data.starts_with(b"{")
+        && serde_json::from_slice::<Value>(data)
+            .is_ok_and(|value| value.get("ciphertext").is_some() && value.get("iv").is_some())
+}
+
+// A JWE in any serialization, with its members still base64url
+struct Parsed {
+    protected: Option<String>,
+    unprotected: Option<Map<String, Value>>,
+    aad: Option<String>,
+    iv: String,
+    ciphertext: String,
+    tag: String,
+    recipients: Vec<ParsedRecipient>,
+}
+
+struct ParsedRecipient {
+    header: Option<Map<String, Value>>,
+    encrypted_key: Option<String>,
+}
+
+fn parse(data: &[u8]) -> Result<Parsed, EncryptError> {
+    let text = std::str::from_utf8(data)
+        .map_err(|_| invalid("not text"))?
+        .trim();
+    if !text.starts_with('{') {
+        let parts: Vec<&str> = text.split('.').collect();
+        let [protected, encrypted_key, iv, ciphertext, tag] = parts[..] else {
+            return Err(invalid("the compact serialization has five parts"));
+        };
+        return Ok(Parsed {
+            protected: Some(protected.to_string()),
+            unprotected: None,
+            aad: None,
+            iv: iv.to_string(),
+            ciphertext: ciphertext.to_string(),
+            tag: tag.to_string(),
+            recipients: vec![ParsedRecipient {
+                header: None,
+                encrypted_key: Some(encrypted_key.to_string()),
+            }],
+        });
+    }
+
+    let value: Value = serde_json::from_str(text).map_err(|_| invalid("invalid JSON"))?;
+    let text_member = |value: &Value, name: &str| -> Result<Option<String>, EncryptError> {
+        match value.get(name) {
+            None => Ok(None),
+            Some(Value::String(text)) => Ok(Some(text.clone())),
+            Some(_) => Err(invalid(&format!("'{}' must be a string", name))),
+        }
+    };
+    let object_member =
+        |value: &Value, name: &str| -> Result<Option<Map<String, Value>>, EncryptError> {
+            match value.get(name) {
+                None => Ok(None),
+                Some(Value::Object(object)) => Ok(Some(object.clone())),
+                Some(_) => Err(invalid(&format!("'{}' must be an object", name))),
+            }
+        };
+    let required = |name: &str| -> Result<String, EncryptError> {
+        text_member(&value, name)?.ok_or_else(|| invalid(&format!("'{}' is missing", name)))
+    };
+    // The general serialization lists the recipients, the flattened one has a single recipient inline
+    let recipients = match value.get("recipients") {
+        Some(Value::Array(recipients)) => recipients
+            .iter()
+            .map(|recipient| {
+                Ok(ParsedRecipient {
+                    header: object_member(recipient, "header")?,
+                    encrypted_key: text_member(recipient, "encrypted_key")?,
+                })
+            })
+            .collect::<Result<_, EncryptError>>()?,
+        Some(_) => return Err(invalid("'recipients' must be an array")),
+        None => vec![ParsedRecipient {
+            header: object_member(&value, "header")?,
+            encrypted_key: text_member(&value, "encrypted_key")?,
+        }],
+    };
+    Ok(Parsed {
+        protected: text_member(&value, "protected")?,
+        unprotected: object_member(&value, "unprotected")?,
+        aad: text_member(&value, "aad")?,
+        iv: required("iv")?,
+        ciphertext: required("ciphertext")?,
+        tag: required("tag")?,
+        recipients,
+    })
+}
+
+// The content key for one recipient, from the header parameters it was encrypted with
+fn content_key(
+    header: &Map<String, Value>,
+    encrypted_key: Option<&str>,
+    key: Key,
+) -> Result<KeyBytes, EncryptError> {
+    let parameter = |name: &str| header.get(name).and_then(Value::as_str);
+    if parameter("enc") != Some(ENC) {
+        return Err(invalid(&format!(
+            "unsupported content encryption {} (only {} is supported)",
+            parameter("enc").unwrap_or("(none)"),
+            ENC
+        )));
+    }
+    // Compression and critical extensions would change how the content is read, and neither is supported
+    for unsupported in ["zip", "crit"] {
+        if header.contains_key(unsupported) {
+            return Err(invalid(&format!(
+                "the '{}' header is not supported",
+                unsupported
+            )));
+        }
+    }
+
+    match (parameter("alg"), key) {
+        (Some(PBES2), Key::Password(password)) => {
+            let salt = decode(
+                parameter("p2s").ok_or_else(|| invalid("'p2s' is missing"))?,
+                "p2s",
+            )?;
+            let iterations = header
+                .get("p2c")
+                .and_then(Value::as_u64)
+                .filter(|iterations| (MIN_ITERATIONS..=MAX_ITERATIONS).contains(iterations))
+                .ok_or_else(|| {
+                    invalid(&format!(
+                        "'p2c' must be between {} and {}",
+                        MIN_ITERATIONS, MAX_ITERATIONS
+                    ))
+                })?;
+            let wrapped = decode(encrypted_key.unwrap_or_default(), "encrypted_key")?;
+            let kek = pbes2_key(password, &salt, iterations as u32);
+            let mut content_key = Zeroizing::new([0u8; KEY_LEN]);
+            // A wrong password fails the key wrap's integrity check
+            aes_kw::KekAes256::new(&(*kek).into())
+                .unwrap(&wrapped, &mut *content_key)
+                .map_err(|_| EncryptError::AeadError(CryptoError))?;
+            Ok(content_key)
+        }
+        (Some(ECDH_ES), Key::Identity(identity)) => {
+            let epk = header
+                .get("epk")
+                .filter(|epk| epk["kty"] == "OKP" && epk["crv"] == "X25519")
+                .and_then(|epk| epk["x"].as_str())
+                .ok_or_else(|| invalid("'epk' must be an X25519 key"))?;
+            let ephemeral: [u8; 32] = decode(epk, "epk")?
+                .try_into()
+                .map_err(|_| invalid("'epk' must be 32 bytes"))?;
+            let shared = identity
+                .shared_secret(&Recipient::from_bytes(ephemeral))
+                .ok_or(EncryptError::AeadError(CryptoError))?;
+            let party = |name: &str| match parameter(name) {
+                Some(encoded) => decode(encoded, name),
+                None => Ok(Vec::new()),
+            };
+            Ok(concat_kdf(&shared, &party("apu")?, &party("apv")?))
+        }
+        (Some(PBES2), _) => Err(mismatch("a password")),
+        (Some(ECDH_ES), _) => Err(mismatch("an X25519 identity")),
+        (alg, _) => Err(invalid(&format!(
+            "unsupported key management algorithm {} (only {} and {} are supported)",
+            alg.unwrap_or("(none)"),
+            PBES2,
+            ECDH_ES
+        ))),
+    }
+}
+
+// PBES2: PBKDF2-HMAC-SHA512 over the algorithm name, a zero byte and the p2s salt
+fn pbes2_key(password: &str, salt: &[u8], iterations: u32) -> KeyBytes {
+    let mut full_salt = PBES2.as_bytes().to_vec();
+    full_salt.push(0);
+    full_salt.extend_from_slice(salt);
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    pbkdf2::pbkdf2_hmac::<Sha512>(password.as_bytes(), &full_salt, iterations, &mut *key);
+    key
+}
+
+// The Concat KDF of NIST SP 800-56A as used by ECDH-ES in direct key agreement mode: one SHA-256 round over the
+// shared secret, the content encryption algorithm, the party infos and the key length in bits
+fn concat_kdf(shared: &[u8; 32], apu: &[u8], apv: &[u8]) -> KeyBytes {
+    let mut hash = Sha256::new();
+    hash.update(1u32.to_be_bytes());
+    hash.update(shared);
+    for field in [ENC.as_bytes(), apu, apv] {
+        hash.update((field.len() as u32).to_be_bytes());
+        hash.update(field);
+    }
+    hash.update(((KEY_LEN * 8) as u32).to_be_bytes());
+    Zeroizing::new(hash.finalize().into())
+}
+
+// ECDH-ES with a fresh ephemeral key: returns the content key and the empty encrypted key, and puts the
+// ephemeral public key in the header
+fn ecdh_es(
+    recipient: &Recipient,
+    header: &mut Map<String, Value>,
+) -> Result<(KeyBytes, Vec<u8>), EncryptError> {
+    let ephemeral = Identity::generate()?;
+    header.insert("alg".into(), ECDH_ES.into());
+    header.insert(
+        "epk".into(),
+        json!({
+            "kty": "OKP",
+            "crv": "X25519",
+            "x": URL_SAFE_NO_PAD.encode(ephemeral.recipient().to_bytes()),
+        }),
+    );
+    let shared = ephemeral
+        .shared_secret(recipient)
+        .ok_or(EncryptError::AeadError(CryptoError))?;
+    Ok((concat_kdf(&shared, &[], &[]), Vec::new()))
+}
+
+fn header_object(bytes: &[u8]) -> Result<Map<String, Value>, EncryptError> {
+    match serde_json::from_slice(bytes) {
+        Ok(Value::Object(object)) => Ok(object),
+        _ => Err(invalid("the protected header is not a JSON object")),
+    }
+}
+
+fn decode(encoded: &str, name: &str) -> Result<Vec<u8>, EncryptError> {
+    URL_SAFE_NO_PAD
+        .decode(encoded)
+        .map_err(|_| invalid(&format!("'{}' is not base64url", name)))
+}
+
+fn invalid(reason: &str) -> EncryptError {
+    HeaderError::InvalidEncoding(format!("JWE: {}", reason)).into()
+}
+
+fn mismatch(needed: &str) -> EncryptError {
+    EncryptError::KeyModeMismatch(format!("the JWE is decrypted with {}", needed))
+}
@@ -0,0 +1,146 @@
+// Files in JSON Web Encryption form (RFC 7516), for `encrypt --format jwe|jwe-json` and decrypting JWE files.
+// Passwords use PBES2-HS512+A256KW and X25519 keys use ECDH-ES, both with A256GCM content encryption, so JOSE
+// libraries can decrypt the output (see the `encryptor::jwe` module). Needs the `jwe` cargo feature; without it
+// compact JWE files are still detected, but encrypting or decrypting them fails with an explanation.
+#[cfg(feature = "jwe")]
+use crate::streamed;
+use crate::streamed::Existing;
+use crate::Secret;
+use encryptor::EncryptError;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// Whether the file at `path` is a JWE rather than one of ours: compact ones start with the base64url of `{"`,
+// JSON ones are recognized by their members
+pub fn is_jwe(path: &Path) -> bool {
+    let mut start = [0u8; 3];
+    let read = fs::File::open(path).and_then(|mut file| file.read_exact(&mut start));
+    if read.is_err() {
+        return false;
+    }
+    #[cfg(feature = "jwe")]
+    if start.starts_with(b"eyJ") || start.starts_with(b"{") {
+        return fs::read(path).is_ok_and(|data| encryptor::jwe::is_jwe(&data));
+    }
+    start.starts_with(b"eyJ")
+}
+
+// Encrypt the file at `file_path` into `<file_path>.jwe`, in the JSON serialization with `json`, returning the
+// path written
+#[cfg(feature = "jwe")]
+pub fn encrypt(
+    file_path: &Path,
+    secret: &Secret,
+    json: bool,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    use encryptor::jwe::{self, Serialization};
+
+    let serialization = match json {
+        true => Serialization::Json,
+        false => Serialization::Compact,
+    };
+    let plaintext = fs::read(file_path)?;
+    let output = jwe::encrypt(&plaintext, encrypt_key(secret)?, serialization)?;
+    let output_path = streamed::claim(encryptor::names::with_suffix(file_path, ".jwe"), existing)?;
+    streamed::write(&output[..], &output_path)?;
+    Ok(output_path)
+}
+
+// Decrypt the JWE file at `file_path` into the same path without its last extension, returning the path written
+#[cfg(feature = "jwe")]
+pub fn decrypt(
+    file_path: &Path,
+    secret: &Secret,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    use encryptor::jwe::{self, Key};
+
+    let key = match secret {
+        Secret::Password(password, _) => Key::Password(password),
+        Secret::Identity(identity) => Key::Identity(identity),
+        Secret::Key(_) => return Err(unsupported("JWE files can't be decrypted with a raw key")),
+        Secret::Recipients(_) => {
+            return Err(unsupported(
+                "JWE files are decrypted with an identity, not public keys",
+            ))
+        }
+        Secret::Certificates(_) => {
+            return Err(unsupported(
+                "JWE files can't be decrypted with certificates",
+            ))
+        }
+        Secret::Grants(_) => return Err(unsupported("JWE files can't be decrypted with a grant")),
+        Secret::Fido2(_) => {
+            return Err(unsupported(
+                "JWE files can't be decrypted with a security key",
+            ))
+        }
+    };
+    let plaintext = jwe::decrypt(&fs::read(file_path)?, key)?;
+
+    let output_path = encryptor::names::strip_extension(file_path).to_path_buf();
+    let output_path = streamed::claim(output_path, existing)?;
+    streamed::write(&plaintext[..], &output_path)?;
+    Ok(output_path)
+}
+
+// A JWE has one key management algorithm per recipient and the compact form allows only one recipient,
+// and JOSE has no raw key mode compatible with ours
+#[cfg(feature = "jwe")]
+fn encrypt_key(secret: &Secret) -> Result<encryptor::jwe::Key<'_>, EncryptError> {
+    use encryptor::jwe::Key;
+
+    match secret {
+        Secret::Password(password, extra) if extra.is_empty() => Ok(Key::Password(password)),
+        Secret::Password(..) => Err(unsupported(
+            "JWE files can't be encrypted to a password and public keys at once",
+        )),
+        Secret::Key(_) => Err(unsupported("JWE files can't be encrypted with a raw key")),
+        Secret::Identity(identity) => Ok(Key::Identity(identity)),
+        Secret::Recipients(recipients) => match &recipients[..] {
+            [recipient] => Ok(Key::Recipient(recipient)),
+            _ => Err(unsupported(
+                "JWE files can only be encrypted to a single public key",
+            )),
+        },
+        Secret::Certificates(_) => Err(unsupported("JWE files can't be encrypted to certificates")),
+        Secret::Grants(_) => Err(unsupported("JWE files can't be encrypted with a grant")),
+        Secret::Fido2(_) => Err(unsupported(
+            "JWE files can't be encrypted with a security key",
+        )),
+    }
+}
+
+#[cfg(feature = "jwe")]
+fn unsupported(message: &str) -> EncryptError {
+    EncryptError::KeyModeMismatch(message.to_string())
+}
+
+#[cfg(not(feature = "jwe"))]
+pub fn encrypt(
+    _file_path: &Path,
+    _secret: &Secret,
+    _json: bool,
+    _existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "jwe"))]
+pub fn decrypt(
+    _file_path: &Path,
+    _secret: &Secret,
+    _existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "jwe"))]
+fn unavailable() -> EncryptError {
+    EncryptError::IoError(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "JWE support is not built in (enable the 'jwe' feature)",
+    ))
+}
@@ -0,0 +1,111 @@
+// A cache of keys derived from the Encryptor's password.
+//
+// Argon2id is deliberately slow, so re-deriving the key for every call would make a shared Encryptor
+// expensive to use from a server. The cache is guarded by a Mutex and shared (through an Arc) by every
+// clone of the Encryptor, so it can be used from any number of threads at once.
+use crate::rng::RandomSource;
+use crate::{derive_key, protect, EncryptError, KdfParams, KeyBytes, SALT_LEN};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use zeroize::Zeroizing;
+
+// Upper bound on cached keys, so decrypting files from many different sources can't grow the cache forever
+const CAPACITY: usize = 64;
+
+#[derive(Default)]
+pub(crate) struct KeyCache {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    // Salts used for everything this Encryptor encrypts, generated on first use. There is one per password it
+    // encrypts to (see Encryptor::with_extra_password), so a guess can't be tested against all of them at once.
+    sessions: HashMap<usize, Session>,
+    // Derived keys by salt, KDF parameters and key context, wiped when evicted or when the cache is dropped. Boxed
+    // so that they stay where they were locked into RAM (see crate::protect) when the map grows.
+    keys: HashMap<(Vec<u8>, KdfParams, Vec<u8>), Box<KeyBytes>>,
+}
+
+struct Session {
+    salt: [u8; SALT_LEN],
+    // How many outputs have been sealed with the key derived from the salt
+    uses: u64,
+}
+
+impl KeyCache {
+    // The salt for the next encryption with password number `slot` (0 for the Encryptor's own password).
+    // It is random but generated once per Encryptor, so every encryption reuses the same derived key and only
+    // the nonce changes between outputs. The encryption counts as `uses` outputs (one per chunk of a chunked
+    // payload), and once the salt would pass `max_uses` a new one is picked, rolling over to a fresh key before
+    // random nonces could collide.
+    pub(crate) fn session_salt(
+        &self,
+        slot: usize,
+        uses: u64,
+        max_uses: u64,
+        rng: &dyn RandomSource,
+    ) -> Result<[u8; SALT_LEN], EncryptError> {
+        let mut inner = self.lock();
+        if let Some(session) = inner.sessions.get_mut(&slot) {
+            if session.uses.saturating_add(uses) <= max_uses {
+                session.uses += uses;
+                return Ok(session.salt);
+            }
+        }
+        if let Some(session) = inner.sessions.get(&slot) {
+            log::debug!(
+                "Picking a new salt for password {} after {} outputs under its key",
+                slot,
+                session.uses
+            );
+        }
+        let mut salt = [0u8; SALT_LEN];
+        rng.fill(&mut salt)?;
+        inner.sessions.insert(slot, Session { salt, uses });
+        Ok(salt)
+    }
+
+    // Return the key for this salt, parameters and context (see Header::key_context), deriving and remembering it on a miss.
+    // The lock is not held while Argon2id runs, so a slow derivation doesn't block other threads.
+    pub(crate) fn key(
+        &self,
+        password: &str,
+        salt: &[u8],
+        params: KdfParams,
+        context: &[u8],
+    ) -> Result<KeyBytes, EncryptError> {
+        let id = (salt.to_vec(), params, context.to_vec());
+        if let Some(key) = self.lock().keys.get(&id) {
+            return Ok(KeyBytes::clone(key));
+        }
+
+        // Instant has no clock to read in a browser, so derivations aren't timed there
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        let start = std::time::Instant::now();
+        let key = Zeroizing::new(derive_key(password, salt, params, context)?);
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        log::debug!(
+            "Derived a key with Argon2id (memory {} KiB, iterations {}) in {} ms",
+            params.memory,
+            params.iterations,
+            start.elapsed().as_millis()
+        );
+        let mut inner = self.lock();
+        if inner.keys.len() >= CAPACITY {
+            inner.keys.clear();
+        }
+        let cached = Box::new(key.clone());
+        protect(&cached[..]);
+        inner.keys.insert(id, cached);
+        Ok(key)
+    }
+
+    // A panic in another thread while holding the lock can't leave the cache half-updated
+    // in a harmful way (worst case a key is missing and gets derived again), so recover from poisoning.
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,68 @@
+// Wrapping a file's random key for a raw key (a master key), for files whose key can be rewrapped later.
+//
+// A key stanza body is a nonce (12 bytes) followed by the file key sealed with the raw key (32 bytes and a 16-byte
+// tag). The raw key may also seal whole payloads, so the stanza is sealed with its own associated data, which no
+// payload header can equal, and its random nonce goes into the nonce log like theirs.
+use crate::header::{Stanza, StanzaKind};
+use crate::rng::RandomSource;
+use crate::{
+    cipher, fault, Algorithm, EncryptError, KeyBytes, NonceLog, KEY_LEN, NONCE_LEN, TAG_LEN,
+};
+use zeroize::Zeroizing;
+
+// Keeps a sealed file key apart from a payload sealed under the same key
+const CONTEXT: &[u8] = b"encryptor key stanza";
+const BODY_LEN: usize = NONCE_LEN + KEY_LEN + TAG_LEN;
+
+// Wrap the file key for the raw key `key`, sealing it with `algorithm`
+pub(crate) fn wrap(
+    file_key: &[u8; KEY_LEN],
+    key: &[u8; KEY_LEN],
+    algorithm: Algorithm,
+    nonces: Option<&NonceLog>,
+    rng: &dyn RandomSource,
+) -> Result<Stanza, EncryptError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut nonce)?;
+    fault::nonce(&mut nonce);
+    if let Some(nonces) = nonces {
+        nonces.record(key, &nonce, false)?;
+    }
+
+    let mut sealed = file_key.to_vec();
+    cipher::seal_in_place(algorithm, key, &nonce, &context(algorithm), &mut sealed)?;
+
+    let mut body = Vec::with_capacity(BODY_LEN);
+    body.extend_from_slice(&nonce);
+    body.extend_from_slice(&sealed);
+    Ok(Stanza {
+        kind: StanzaKind::Key,
+        body,
+    })
+}
+
+// Try every key stanza until one opens with `key`, and return the file key from it.
+// Returns None when none of them were wrapped for this key.
+pub(crate) fn unwrap(
+    stanzas: &[Stanza],
+    key: &[u8; KEY_LEN],
+    algorithm: Algorithm,
+) -> Option<KeyBytes> {
+    stanzas
+        .iter()
+        .filter(|stanza| stanza.kind == StanzaKind::Key && stanza.body.len() == BODY_LEN)
+        .find_map(|stanza| {
+            let (nonce, sealed) = stanza.body.split_at(NONCE_LEN);
+            let mut file_key = Zeroizing::new(sealed.to_vec());
+            cipher::open_in_place(algorithm, key, nonce, &context(algorithm), &mut file_key)
+                .ok()?;
+            Some(Zeroizing::new(file_key[..].try_into().ok()?))
+        })
+}
+
+// Bound to the algorithm it seals with, like the password stanza's wrapping key
+fn context(algorithm: Algorithm) -> Vec<u8> {
+    let mut context = CONTEXT.to_vec();
+    context.push(algorithm.id());
+    context
+}
@@ -0,0 +1,62 @@
+// Passwords stored in the platform keychain (Secret Service on Linux, Keychain on macOS, Credential Manager
+// on Windows), for --use-keyring and the `keyring` command. Entries are stored under the "encryptor" service,
+// one per name. Needs the `keyring` cargo feature; without it every call fails with an explanation.
+
+#[cfg(feature = "keyring")]
+const SERVICE: &str = "encryptor";
+
+// The password stored under `name`, or None if there isn't one
+#[cfg(feature = "keyring")]
+pub fn get(name: &str) -> Result<Option<String>, String> {
+    match entry(name)?.get_password() {
+        Ok(password) => Ok(Some(password)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(failed(name, err)),
+    }
+}
+
+// Store `password` under `name`, replacing any password already stored there
+#[cfg(feature = "keyring")]
+pub fn set(name: &str, password: &str) -> Result<(), String> {
+    entry(name)?
+        .set_password(password)
+        .map_err(|err| failed(name, err))
+}
+
+// Remove the password stored under `name`
+#[cfg(feature = "keyring")]
+pub fn delete(name: &str) -> Result<(), String> {
+    entry(name)?
+        .delete_credential()
+        .map_err(|err| failed(name, err))
+}
+
+#[cfg(feature = "keyring")]
+fn entry(name: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(SERVICE, name).map_err(|err| failed(name, err))
+}
+
+#[cfg(feature = "keyring")]
+fn failed(name: &str, err: keyring::Error) -> String {
+    format!("Keyring entry '{}': {}", name, err)
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn get(_name: &str) -> Result<Option<String>, String> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn set(_name: &str, _password: &str) -> Result<(), String> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn delete(_name: &str) -> Result<(), String> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn unavailable() -> String {
+    "Keyring support is not built in (enable the 'keyring' feature)".to_string()
+}
@@ -0,0 +1,120 @@
+// Reading and writing 256-bit key files, for --keyfile, --identity, --recipient and the `keygen` command.
+// A key file holds either the 32 key bytes themselves, or the key written as hex (64 digits) or base64 text,
+// optionally followed by a newline. Keys in age's encoding (from age-keygen) are accepted too, and OpenSSH Ed25519
+// private keys as identities (see ssh_file). Loaded keys, and
+// the file contents they were decoded from, are wiped from memory once they are dropped.
+use base64::Engine;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const KEY_LEN: usize = 32;
+
+// How `keygen` writes the key. `load` accepts all of them.
+#[derive(Clone, Copy)]
+pub enum Format {
+    Raw,
+    Hex,
+    Base64,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(Format::Raw),
+            "hex" => Some(Format::Hex),
+            "base64" => Some(Format::Base64),
+            _ => None,
+        }
+    }
+}
+
+pub fn load(path: &str) -> Result<Zeroizing<[u8; KEY_LEN]>, String> {
+    decode_file(path, &read(path)?)
+}
+
+// The X25519 identity in the file at `path`: a key file, or an SSH private key converted to X25519
+pub fn load_identity(path: &str) -> Result<Zeroizing<[u8; KEY_LEN]>, String> {
+    let bytes = read(path)?;
+    match crate::ssh_file::is_private_key(&bytes) {
+        true => crate::ssh_file::identity(path, &bytes),
+        false => decode_file(path, &bytes),
+    }
+}
+
+fn read(path: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    fs::read(path)
+        .map(Zeroizing::new)
+        .map_err(|err| format!("Cannot read the key file {}: {}", path, err))
+}
+
+fn decode_file(path: &str, bytes: &[u8]) -> Result<Zeroizing<[u8; KEY_LEN]>, String> {
+    decode(bytes).ok_or_else(|| {
+        format!(
+            "The key file {} must contain a {}-byte key, as raw bytes, hex or base64",
+            path, KEY_LEN
+        )
+    })
+}
+
+// A --recipient public key, given either as hex or base64 text or as the path of a file holding it
+pub fn load_recipient(value: &str) -> Result<[u8; KEY_LEN], String> {
+    if std::path::Path::new(value).is_file() {
+        return load(value).map(|key| *key);
+    }
+    decode(value.as_bytes()).map(|key| *key).ok_or_else(|| {
+        format!(
+            "--recipient expects a public key (hex or base64) or a file holding one: {}",
+            value
+        )
+    })
+}
+
+// The base64 text form of a key, as printed for public keys
+pub fn encode(key: &[u8; KEY_LEN]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(key)
+}
+
+fn decode(bytes: &[u8]) -> Option<Zeroizing<[u8; KEY_LEN]>> {
+    if let Ok(key) = <[u8; KEY_LEN]>::try_from(bytes) {
+        return Some(Zeroizing::new(key));
+    }
+    let text = std::str::from_utf8(bytes).ok()?.trim();
+    let decoded = if text.len() == KEY_LEN * 2 {
+        hex::decode(text).ok()
+    } else {
+        base64::engine::general_purpose::STANDARD.decode(text).ok()
+    };
+    let decoded = decoded.map(Zeroizing::new);
+    decoded
+        .and_then(|key| <[u8; KEY_LEN]>::try_from(&key[..]).ok())
+        .or_else(|| crate::age_file::decode_key(text))
+        .map(Zeroizing::new)
+}
+
+// Write a new key file that only its owner can read. An existing file is never overwritten,
+// since replacing a key would make everything encrypted with it undecryptable.
+pub fn write(path: &str, key: &[u8; KEY_LEN], format: Format) -> Result<(), String> {
+    let contents = Zeroizing::new(match format {
+        Format::Raw => key.to_vec(),
+        Format::Hex => format!("{}\n", hex::encode(key)).into_bytes(),
+        Format::Base64 => format!("{}\n", encode(key)).into_bytes(),
+    });
+    create_private(path, &contents)
+        .map_err(|err| format!("Cannot write the key file {}: {}", path, err))
+}
+
+// Write `contents` to a new file at `path` that only its owner can read, never overwriting an existing one
+pub fn create_private(path: impl AsRef<Path>, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+}
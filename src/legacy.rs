@@ -0,0 +1,48 @@
+//! Reading the files of the first releases, which had no header.
+//!
+//! Those releases sealed the whole file with AES-256-GCM under the password itself, used as the key (so it had to be
+//! exactly 32 bytes), and a 12-byte nonce the user chose and had to give again to decrypt. Nothing else was
+//! authenticated, and the output was the ciphertext followed by the tag. Since the nonce isn't stored anywhere and
+//! was often reused across files, these files should be decrypted once and sealed again in the current format:
+//! see `encryptor migrate`.
+//!
+//! ```no_run
+//! let contents = std::fs::read("notes.txt.enc")?;
+//! let nonce = [246, 231, 118, 136, 232, 16, 173, 214, 11, 241, 220, 114];
+//! let plaintext = encryptor::legacy::decrypt(b"12345678901234567890123456789012", &nonce, contents)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use crate::header::Header;
+use crate::{cipher, Algorithm, EncryptError, KEY_LEN, NONCE_LEN, TAG_LEN};
+
+/// Decrypt `contents`, written by the first releases with `password` and `nonce`, returning the plaintext.
+/// Fails with [`EncryptError::KeyModeMismatch`] for a password that can't have been a key, or for a file in the
+/// current format, and with [`EncryptError::AeadError`] for a wrong password or nonce, or a damaged file.
+pub fn decrypt(
+    password: &[u8],
+    nonce: &[u8; NONCE_LEN],
+    mut contents: Vec<u8>,
+) -> Result<Vec<u8>, EncryptError> {
+    let key: &[u8; KEY_LEN] = password.try_into().map_err(|_| {
+        EncryptError::KeyModeMismatch(format!(
+            "the first releases used the password itself as the key, so it was {} bytes long, not {}",
+            KEY_LEN,
+            password.len()
+        ))
+    })?;
+    if Header::parse(&contents).is_ok() {
+        return Err(EncryptError::KeyModeMismatch(
+            "the file has a header, so it is in the current format and not a legacy one"
+                .to_string(),
+        ));
+    }
+    if contents.len() < TAG_LEN {
+        return Err(EncryptError::MetadataMismatch(format!(
+            "{} bytes can't hold the {}-byte authentication tag (the file was truncated)",
+            contents.len(),
+            TAG_LEN
+        )));
+    }
+    cipher::open_in_place(Algorithm::Aes256Gcm, key, nonce, &[], &mut contents)?;
+    Ok(contents)
+}
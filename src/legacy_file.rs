@@ -0,0 +1,76 @@
+// Files written by the first releases, which had no header: `decrypt --legacy --nonce <json>` decrypts them as
+// those releases did, and `migrate --nonce <json>` seals them again in the current format, under the same password.
+// See the `encryptor::legacy` module for the format.
+use crate::streamed::{self, Existing};
+use crate::Secret;
+use encryptor::{legacy, names, EncryptError, Encryptor};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// The password these files were sealed with, which was used as the key
+fn password(secret: &Secret) -> Result<&[u8], EncryptError> {
+    match secret {
+        Secret::Password(password, _) => Ok(password.as_bytes()),
+        _ => Err(EncryptError::KeyModeMismatch(
+            "files of the first releases are decrypted with the password they were encrypted with"
+                .to_string(),
+        )),
+    }
+}
+
+// Decrypt the legacy file at `file_path` into `out_path` (see streamed::output_path), or by default next to it
+// without its last extension, returning the path written
+pub fn decrypt(
+    file_path: &Path,
+    secret: &Secret,
+    nonce: &[u8; 12],
+    out_path: Option<&Path>,
+    several: bool,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    let plaintext = legacy::decrypt(password(secret)?, nonce, fs::read(file_path)?)?;
+    let name = names::file_name(file_path);
+    let output_path = match out_path {
+        Some(_) => streamed::output_path(name, out_path, several, existing)?,
+        None => {
+            let dir = file_path.parent().unwrap_or(Path::new(""));
+            streamed::output_path(name, Some(dir), true, existing)?
+        }
+    };
+    streamed::write(&plaintext[..], &output_path)
+}
+
+// Replace the legacy file at `file_path` with the same contents sealed by `encryptor`, returning its path. The new
+// file is written next to it and renamed over it once it is complete, so a failed migration leaves the old one.
+pub fn migrate(
+    encryptor: &Encryptor,
+    file_path: &Path,
+    secret: &Secret,
+    nonce: &[u8; 12],
+) -> Result<PathBuf, EncryptError> {
+    let plaintext = legacy::decrypt(password(secret)?, nonce, fs::read(file_path)?)?;
+    let sealed = encryptor.encrypt_bytes(&plaintext)?;
+    let temp_path = names::with_suffix(file_path, &format!(".{}.tmp", std::process::id()));
+    let written = (|| -> io::Result<()> {
+        let mut file = File::create_new(&temp_path)?;
+        file.write_all(&sealed)?;
+        file.set_permissions(fs::metadata(file_path)?.permissions())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, file_path)
+    })();
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err.into());
+    }
+    // The rename only lasts a crash once the directory is synced too
+    if let Some(dir) = file_path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        File::open(dir).and_then(|dir| dir.sync_all()).ok();
+    }
+    Ok(file_path.to_path_buf())
+}
@@ -0,0 +1,3528 @@
+//! Encryptor: a small library for encrypting and decrypting files and byte buffers.
+//!
+//! The contents are sealed with AES-256-GCM (or ChaCha20-Poly1305 or AES-256-GCM-SIV) under a key derived from the password with Argon2id,
+//! and every output starts with a self-describing [`header::Header`]. Machine keys can skip the password and use a raw
+//! 256-bit key instead, see [`Encryptor::from_key`].
+//!
+//! ```no_run
+//! use encryptor::Encryptor;
+//!
+//! let encryptor = Encryptor::new("correct horse battery staple");
+//! let sealed = encryptor.encrypt_bytes(b"hello")?;
+//! assert_eq!(encryptor.decrypt_bytes(&sealed)?, b"hello");
+//!
+//! let output_path = encryptor.encrypt_file("test.txt")?; // writes test.txt.enc
+//! # Ok::<(), encryptor::EncryptError>(())
+//! ```
+
+// A browser has no clock or random numbers but JavaScript's, which the wasm feature wires up
+#[cfg(all(target_arch = "wasm32", target_os = "unknown", not(feature = "wasm")))]
+compile_error!("building for wasm32-unknown-unknown needs the `wasm` feature");
+
+// Import the necessary modules and packages
+pub mod adapters; // EncryptingWriter and DecryptingReader, std::io adapters over any stream
+#[cfg(feature = "armor")]
+pub mod armor; // Encrypted files as base64 text between BEGIN and END lines
+#[cfg(feature = "async")]
+pub mod async_io; // Encrypting and decrypting tokio streams, for async servers
+mod backend; // The crypto backend selected at compile time (ring or RustCrypto)
+mod checksum; // The BLAKE3 checksum of the plaintext recorded in the header, masked with the file key
+mod cipher; // The AEAD operations behind each Algorithm
+#[cfg(feature = "cms")]
+pub mod cms; // CMS EnvelopedData (S/MIME-style) output for X.509 certificates
+pub mod compression; // Optional compression of the plaintext before encryption
+pub mod encoding; // Writing encrypted files as raw binary, armor, armored parts or a JSON envelope
+pub mod fault; // Fault injection for tests, active only with the fault-injection feature
+#[cfg(feature = "ffi")]
+pub mod ffi; // A C interface for embedding the library in other languages
+#[cfg(feature = "fido2")]
+pub mod fido2; // File keys wrapped with the hmac-secret of a FIDO2 security key
+#[cfg(feature = "grant")]
+pub mod grant; // Short-lived decryption grants signed by an administrator for an operator
+#[cfg(feature = "harden")]
+pub mod harden; // Secrets locked into RAM and no core dumps, for hostile hosts
+pub mod header; // The versioned file header (magic bytes, algorithm, KDF parameters, salt and nonce)
+mod input; // Reading input files with read calls or through a memory map
+#[cfg(feature = "jwe")]
+pub mod jwe; // JSON Web Encryption (RFC 7516) output, for interoperability with JOSE libraries
+mod key_cache; // Thread-safe cache of keys derived from the password
+mod key_stanza; // Wrapping the file key for a raw key, for files whose key can be rewrapped
+pub mod legacy; // Reading the headerless files of the first releases, to migrate them
+pub mod names; // File names as headers record them, valid UTF-8 or not, and the paths derived from inputs
+pub mod nonce_log; // Refusing to reuse a nonce under the same key within a run
+#[cfg(feature = "openssl-enc")]
+pub mod openssl_enc; // `openssl enc -aes-256-cbc -pbkdf2` files, a non-AEAD compatibility mode
+mod padding; // Padding the payload to hide its exact length, with the count masked with the file key
+mod password_stanza; // Wrapping the file key for a password, for files with several recipients
+pub mod progress; // Progress reports and cancellation for file operations
+pub mod ranges; // Decrypting single chunks, for serving byte ranges of encrypted blobs
+#[cfg(feature = "x25519")]
+pub mod recipient; // Encryption to X25519 public keys instead of a password
+pub mod retry; // Retrying transient I/O failures with exponential backoff
+pub mod rng; // Where the random salts, nonces and keys come from, seeded for test vectors with fixed-test-vectors
+pub mod secret; // Plaintext kept in memory as a secret: wiped when dropped, hidden from Debug, locked with harden
+pub mod selftest; // Known-answer tests of the ciphers, the KDF and the file format, to validate a build
+#[cfg(feature = "shamir")]
+pub mod shamir; // Splitting a key into shares, any threshold of which rebuild it
+pub mod stream; // Chunked payloads, sealed and opened one chunk at a time for large files
+#[cfg(feature = "wasm")]
+pub mod wasm; // encryptBytes and decryptBytes for JavaScript, in browsers
+
+pub use adapters::{DecryptingReader, EncryptingWriter, SeekableReader};
+pub use backend::CryptoError;
+use blake2::digest::Mac;
+use blake2::{Blake2s256, Blake2sMac256, Digest};
+use checksum::Checksum;
+pub use compression::{Codec, Compression, Dictionary};
+pub use encoding::Encoder;
+pub use header::{Algorithm, Padding};
+use header::{Header, HeaderError, Kdf, Stanza};
+use input::Input;
+use key_cache::KeyCache;
+pub use nonce_log::NonceLog;
+use padding::Padded;
+use progress::OutputFile;
+pub use progress::{CancellationToken, Progress, Stage};
+pub use ranges::ChunkIndex;
+#[cfg(feature = "x25519")]
+use recipient::{Identity, Recipient};
+pub use retry::RetryPolicy;
+use rng::{RandomSource, SystemRandom};
+pub use secret::SecretVec;
+use std::borrow::Cow; // Names recorded as they are or escaped
+use std::cell::Cell; // Lets a sink skip chunks of a stream it already has
+use std::ffi::{OsStr, OsString}; // File names that needn't be valid UTF-8
+use std::fs::File; // Chunked files are read and written as streams
+use std::io::{self, Read, Seek}; // This module provides a way to perform input/output operations
+use std::path::{Path, PathBuf}; // Input and output paths, whatever their names are
+use std::sync::Arc; // Shared ownership of the key cache between clones of an Encryptor
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH}; // Timing key derivations, and the time of encryption in headers
+use zeroize::Zeroizing; // Wiping passwords and keys from memory once they are dropped
+
+// Length in bytes of the random salt stored in the header of every encrypted file
+pub(crate) const SALT_LEN: usize = 16;
+// Length in bytes of the key derived from the password (all supported algorithms use 256-bit keys)
+pub(crate) const KEY_LEN: usize = 32;
+// A key in memory, overwritten when it is dropped
+pub(crate) type KeyBytes = Zeroizing<[u8; KEY_LEN]>;
+// Length in bytes of the random 96-bit nonce generated for every encryption
+pub(crate) const NONCE_LEN: usize = 12;
+// Length in bytes of the authentication tag appended to the ciphertext
+pub(crate) const TAG_LEN: usize = 16;
+
+/// Cost parameters for the Argon2id key derivation.
+/// `memory` is in KiB and `iterations` is the number of passes over that memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KdfParams {
+    pub memory: u32,
+    pub iterations: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            memory: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    // Recorded in the header when the key isn't derived from a password
+    pub(crate) const NONE: KdfParams = KdfParams {
+        memory: 0,
+        iterations: 0,
+    };
+
+    /// Keep the memory cost and pick the number of iterations that makes one key derivation take about `target`
+    /// on this machine, so the cost keeps up with faster hardware over the years. At least one iteration is used.
+    pub fn calibrate(self, target: Duration) -> Result<Self, EncryptError> {
+        // Every iteration is another full pass over the memory, so time one pass (best of two, to smooth out noise)
+        // and scale it up
+        let single_pass = KdfParams {
+            iterations: 1,
+            ..self
+        };
+        let mut fastest = Duration::MAX;
+        for _ in 0..2 {
+            let start = Instant::now();
+            derive_key("calibration", &[0u8; SALT_LEN], single_pass, &[])?;
+            fastest = fastest.min(start.elapsed());
+        }
+        let iterations = target.as_secs_f64() / fastest.as_secs_f64().max(f64::EPSILON);
+        Ok(KdfParams {
+            iterations: iterations.round().clamp(1.0, u32::MAX as f64) as u32,
+            ..self
+        })
+    }
+}
+
+/// Errors returned by the library
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum EncryptError {
+    IoError(io::Error),          // An I/O error
+    AeadError(CryptoError), // An error from the AEAD (Authenticated Encryption with Associated Data) operation
+    KdfError(argon2::Error), // An error from the Argon2 key derivation (e.g. invalid cost parameters)
+    HeaderError(HeaderError), // The file header is missing, truncated or damaged
+    UnsupportedVersion(u8),  // The file was written by a newer (or unknown) format version
+    Cancelled,               // The operation was stopped through its CancellationToken
+    MetadataMismatch(String), // The file name or length recorded in the header doesn't match the file being decrypted
+    CompressionError(io::Error), // Compressing or decompressing failed, or the codec isn't built in
+    KeyModeMismatch(String), // The file was encrypted with a password but a raw key was given, or the other way around
+    TooLarge(String), // The payload is larger than the algorithm can safely seal under one nonce
+    GrantRejected(String), // A decryption grant is expired, untrusted, damaged or doesn't cover the file
+    WrongPassword, // The file's password check, or none of its password stanzas, matches the password
+    NonceReuse(String), // A nonce was about to be used twice under the same key (see the nonce_log module)
+    // A chunk of a chunked payload failed authentication, at this byte of the file
+    CorruptChunk {
+        index: u64,
+        offset: u64,
+    },
+    // Some chunks of a chunked file failed authentication, but not all, and the output they were salvaged into if
+    // they were (see Encryptor::with_keep_going)
+    DamagedChunks {
+        ranges: Vec<DamagedRange>,
+        salvaged: Option<PathBuf>,
+    },
+}
+
+/// A run of consecutive chunks of a chunked file that failed authentication (see [`EncryptError::DamagedChunks`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DamagedRange {
+    pub first_chunk: u64, // The index of the first damaged chunk
+    pub last_chunk: u64,  // The index of the last one, the same for a single chunk
+    pub offset: u64,      // The byte of the file the first chunk starts at
+    pub len: u64,         // How many bytes of the file the chunks take up, tags included
+}
+
+impl std::fmt::Display for DamagedRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.first_chunk == self.last_chunk {
+            true => write!(f, "chunk {}", self.first_chunk)?,
+            false => write!(f, "chunks {}-{}", self.first_chunk, self.last_chunk)?,
+        }
+        write!(
+            f,
+            " at bytes {}-{}",
+            self.offset,
+            self.offset + self.len - 1
+        )
+    }
+}
+
+// Implement the From trait for io::Error to allow for easy conversion to EncryptError
+impl From<io::Error> for EncryptError {
+    fn from(error: io::Error) -> Self {
+        EncryptError::IoError(error)
+    }
+}
+
+// Implement the From trait for CryptoError to allow for easy conversion to EncryptError
+impl From<CryptoError> for EncryptError {
+    fn from(error: CryptoError) -> Self {
+        EncryptError::AeadError(error)
+    }
+}
+
+// Implement the From trait for argon2::Error to allow for easy conversion to EncryptError
+impl From<argon2::Error> for EncryptError {
+    fn from(error: argon2::Error) -> Self {
+        EncryptError::KdfError(error)
+    }
+}
+
+// Implement the From trait for HeaderError to allow for easy conversion to EncryptError
+impl From<HeaderError> for EncryptError {
+    fn from(error: HeaderError) -> Self {
+        match error {
+            HeaderError::UnsupportedVersion(version) => EncryptError::UnsupportedVersion(version),
+            error => EncryptError::HeaderError(error),
+        }
+    }
+}
+
+// Implement the Display trait for EncryptError to allow for easy printing of the error.
+// The stable code comes first so that logs can be searched for it.
+impl std::fmt::Display for EncryptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.code())?;
+        match self {
+            EncryptError::IoError(err) if err.kind() == io::ErrorKind::StorageFull => write!(
+                f,
+                "IO error: {} (the output's filesystem is full; no incomplete output was put in its place)",
+                err
+            ),
+            EncryptError::IoError(err) => write!(f, "IO error: {}", err),
+            EncryptError::AeadError(_) => write!(
+                f,
+                "authentication failed (wrong password, or the file is corrupted or was tampered with)"
+            ),
+            EncryptError::KdfError(err) => write!(f, "KDF error: {}", err),
+            EncryptError::HeaderError(err) => write!(f, "Header error: {}", err),
+            EncryptError::UnsupportedVersion(version) => write!(
+                f,
+                "Header error: {}",
+                HeaderError::UnsupportedVersion(*version)
+            ),
+            EncryptError::Cancelled => write!(f, "Operation cancelled"),
+            EncryptError::MetadataMismatch(message) => write!(f, "Metadata mismatch: {}", message),
+            EncryptError::CompressionError(err) => write!(f, "Compression error: {}", err),
+            EncryptError::KeyModeMismatch(message) => write!(f, "Key mode mismatch: {}", message),
+            EncryptError::TooLarge(message) => write!(f, "Too large: {}", message),
+            EncryptError::GrantRejected(message) => write!(f, "Grant rejected: {}", message),
+            EncryptError::WrongPassword => write!(
+                f,
+                "wrong password (it doesn't match the file's password check or open any of its recipients)"
+            ),
+            EncryptError::NonceReuse(message) => write!(f, "Nonce reuse: {}", message),
+            // Once the first chunk opened, the key is right and only the file can be at fault
+            EncryptError::CorruptChunk { index: 0, offset } => write!(
+                f,
+                "authentication failed at the first chunk, at byte {} (wrong password, or the file is corrupted or was tampered with there)",
+                offset
+            ),
+            EncryptError::CorruptChunk { index, offset } => write!(
+                f,
+                "chunk {} at byte {} failed authentication: the file is corrupted or was tampered with there, the chunks before it are intact",
+                index, offset
+            ),
+            EncryptError::DamagedChunks { ranges, salvaged } => {
+                let chunks: u64 = ranges
+                    .iter()
+                    .map(|range| range.last_chunk - range.first_chunk + 1)
+                    .sum();
+                let ranges: Vec<String> = ranges.iter().map(|range| range.to_string()).collect();
+                write!(
+                    f,
+                    "{} {} failed authentication, the file is corrupted or was tampered with there ({}); the other chunks are intact",
+                    chunks,
+                    if chunks == 1 { "chunk" } else { "chunks" },
+                    ranges.join(", ")
+                )?;
+                match salvaged {
+                    Some(path) => write!(
+                        f,
+                        ", and were salvaged into {} with the damaged ones zero-filled",
+                        path.display()
+                    ),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl EncryptError {
+    /// The stable code identifying this kind of failure
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            EncryptError::IoError(_) => ErrorCode::Io,
+            EncryptError::AeadError(_) => ErrorCode::AuthFail,
+            EncryptError::KdfError(_) => ErrorCode::Kdf,
+            EncryptError::HeaderError(_) => ErrorCode::BadHeader,
+            EncryptError::UnsupportedVersion(_) => ErrorCode::UnsupportedVersion,
+            EncryptError::Cancelled => ErrorCode::Cancelled,
+            EncryptError::MetadataMismatch(_) => ErrorCode::MetadataMismatch,
+            EncryptError::CompressionError(_) => ErrorCode::Compression,
+            EncryptError::KeyModeMismatch(_) => ErrorCode::KeyMode,
+            EncryptError::TooLarge(_) => ErrorCode::TooLarge,
+            EncryptError::GrantRejected(_) => ErrorCode::Grant,
+            EncryptError::WrongPassword => ErrorCode::WrongPassword,
+            EncryptError::NonceReuse(_) => ErrorCode::NonceReuse,
+            EncryptError::CorruptChunk { .. } => ErrorCode::AuthFail,
+            EncryptError::DamagedChunks { .. } => ErrorCode::AuthFail,
+        }
+    }
+
+    /// Whether the failure may go away if the same operation is tried again: an I/O error such as a timeout,
+    /// an interrupted call or a dropped network connection. Everything else, including a missing file or
+    /// a wrong password, is permanent. [`RetryPolicy`] only retries these.
+    pub fn is_retryable(&self) -> bool {
+        let EncryptError::IoError(err) = self else {
+            return false;
+        };
+        matches!(
+            err.kind(),
+            io::ErrorKind::Interrupted
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::ResourceBusy
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::NetworkDown
+                | io::ErrorKind::NetworkUnreachable
+                | io::ErrorKind::HostUnreachable
+                | io::ErrorKind::StaleNetworkFileHandle
+        )
+    }
+}
+
+/// Stable identifiers for each kind of [`EncryptError`], so callers can branch on failures
+/// without matching on message text. The names and numbers never change once released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    Io,                 // ENC_IO (3): reading or writing a file failed
+    AuthFail,           // ENC_AUTH_FAIL (4): the ciphertext could not be authenticated
+    Kdf,                // ENC_KDF (5): the key could not be derived, e.g. invalid cost parameters
+    BadHeader,          // ENC_BAD_HEADER (6): the header is missing, truncated or damaged
+    Cancelled,          // ENC_CANCELLED (7): the operation was cancelled
+    MetadataMismatch,   // ENC_METADATA_MISMATCH (8): the file was renamed, truncated or extended
+    Compression,        // ENC_COMPRESSION (9): compressing or decompressing failed
+    KeyMode, // ENC_KEY_MODE (10): a password was given for a raw-key file, or the other way around
+    TooLarge, // ENC_TOO_LARGE (11): the payload exceeds the algorithm's per-nonce limit
+    Grant,   // ENC_GRANT (12): a decryption grant was rejected
+    WrongPassword, // ENC_WRONG_PASSWORD (13): the password fails the file's password check or opens none of its recipients
+    UnsupportedVersion, // ENC_UNSUPPORTED_VERSION (14): the file is from a newer format version
+    NonceReuse,    // ENC_NONCE_REUSE (15): a nonce would have been used twice under the same key
+}
+
+impl ErrorCode {
+    /// The string form, e.g. `ENC_AUTH_FAIL`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::Io => "ENC_IO",
+            ErrorCode::AuthFail => "ENC_AUTH_FAIL",
+            ErrorCode::Kdf => "ENC_KDF",
+            ErrorCode::BadHeader => "ENC_BAD_HEADER",
+            ErrorCode::Cancelled => "ENC_CANCELLED",
+            ErrorCode::MetadataMismatch => "ENC_METADATA_MISMATCH",
+            ErrorCode::Compression => "ENC_COMPRESSION",
+            ErrorCode::KeyMode => "ENC_KEY_MODE",
+            ErrorCode::TooLarge => "ENC_TOO_LARGE",
+            ErrorCode::Grant => "ENC_GRANT",
+            ErrorCode::WrongPassword => "ENC_WRONG_PASSWORD",
+            ErrorCode::UnsupportedVersion => "ENC_UNSUPPORTED_VERSION",
+            ErrorCode::NonceReuse => "ENC_NONCE_REUSE",
+        }
+    }
+
+    /// The numeric form, also used as the command line tool's exit code.
+    /// 1 and 2 are left for generic failures and usage errors.
+    pub fn number(self) -> i32 {
+        match self {
+            ErrorCode::Io => 3,
+            ErrorCode::AuthFail => 4,
+            ErrorCode::Kdf => 5,
+            ErrorCode::BadHeader => 6,
+            ErrorCode::Cancelled => 7,
+            ErrorCode::MetadataMismatch => 8,
+            ErrorCode::Compression => 9,
+            ErrorCode::KeyMode => 10,
+            ErrorCode::TooLarge => 11,
+            ErrorCode::Grant => 12,
+            ErrorCode::WrongPassword => 13,
+            ErrorCode::UnsupportedVersion => 14,
+            ErrorCode::NonceReuse => 15,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Implement the Error trait for EncryptError to allow for easy error handling
+impl std::error::Error for EncryptError {}
+
+/// Encrypts and decrypts files and byte buffers with a password (or a raw key).
+///
+/// An `Encryptor` is `Send + Sync` and caches the keys it derives, so a server can share one
+/// (or cheap clones of it, which share the cache) across threads without paying for Argon2id on every call.
+/// Everything it encrypts uses one random salt chosen on first use, and a fresh random nonce per output.
+/// Once an algorithm's safe number of outputs per key is reached, a new salt (and so a new key) is picked.
+#[derive(Clone)]
+pub struct Encryptor {
+    secret: Secret,
+    kdf_params: KdfParams,
+    algorithm: Algorithm,
+    bind_metadata: bool,
+    compression: Compression,
+    dictionary: Option<Dictionary>,
+    header_backup: bool,
+    encoder: Arc<dyn Encoder>,
+    encrypt_names: bool,
+    name_by_hash: bool, // Name outputs after the BLAKE3 hash of their bytes, recording the original name
+    in_place: bool,
+    overwrite: bool,
+    auto_rename: bool, // Write next to an existing output instead of over it
+    resume: bool,      // Keep and continue partly written chunked outputs
+    keep_going: bool,  // Salvage the intact chunks of a damaged chunked file
+    mmap: bool,        // Read inputs through a memory map
+    io_buffer: usize,  // Bytes files are read and written in at once
+    retry: RetryPolicy,
+    stream_threshold: u64, // Payloads at least this long are sealed in chunks
+    chunk_size: u32,
+    stream_workers: usize, // Threads sealing the chunks of one streamed file
+    extra_recipients: Vec<Secret>, // Passwords and public keys that can also decrypt the outputs
+    wrap_keys: bool,       // Seal every output with a random key wrapped in rewrappable stanzas
+    batch_key: bool,       // Derive each output's key with HKDF from one Argon2id key
+    key_id: Option<String>, // Recorded in every header, to track which outputs still need rotating
+    password_set: Option<u64>, // When the password was set, recorded masked instead of the time of encryption
+    checksum: bool,            // Record a checksum of the plaintext in every header
+    padding: Option<Padding>,  // Pad every payload to hide its exact length
+    deterministic: Option<String>, // The context outputs are sealed deterministically for, if they are
+    keys: Arc<KeyCache>,
+    nonces: Option<NonceLog>, // Every nonce sealed so far, when reuse is checked
+    rng: Arc<dyn RandomSource>, // Salts, nonces and keys come from here
+    fixed_time: Option<u64>, // Recorded as the creation time instead of the clock's, for test vectors
+}
+
+// What the key comes from: a password stretched with Argon2id, a raw key used as is,
+// or a random file key wrapped for each password and X25519 recipient. Passwords and keys are shared by the clones
+// of an Encryptor, locked into RAM when hardening is enabled, and wiped from memory when the last one is dropped.
+#[derive(Clone)]
+enum Secret {
+    Password(Arc<Zeroizing<String>>),
+    Key(Arc<KeyBytes>),
+    #[cfg(feature = "x25519")]
+    Recipients(Vec<Recipient>), // Can only encrypt
+    #[cfg(feature = "x25519")]
+    Identity(Identity), // Decrypts, and encrypts to its own recipient
+    #[cfg(feature = "grant")]
+    Grant(Arc<grant::Grant>, Identity), // Can only decrypt, the files the grant covers
+    #[cfg(feature = "fido2")]
+    Fido2(Arc<fido2::Fido2Key>), // A credential on a security key, which computes the key of each file
+}
+
+impl Secret {
+    fn password(password: &str) -> Self {
+        let password = Arc::new(Zeroizing::new(password.to_string()));
+        protect(password.as_bytes());
+        Secret::Password(password)
+    }
+
+    fn key(key: [u8; KEY_LEN]) -> Self {
+        let key = Arc::new(Zeroizing::new(key));
+        protect(&key[..]);
+        Secret::Key(key)
+    }
+
+    // How this secret is described in KeyModeMismatch errors
+    fn describe(&self) -> &'static str {
+        match self {
+            Secret::Password(_) => "a password",
+            Secret::Key(_) => "a raw key",
+            #[cfg(feature = "x25519")]
+            Secret::Recipients(_) => "public keys (decrypting needs an identity)",
+            #[cfg(feature = "x25519")]
+            Secret::Identity(_) => "an X25519 identity",
+            #[cfg(feature = "grant")]
+            Secret::Grant(..) => "a decryption grant",
+            #[cfg(feature = "fido2")]
+            Secret::Fido2(_) => "a FIDO2 security key",
+        }
+    }
+}
+
+// Compile-time check that Encryptor can be shared between threads
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Encryptor>();
+};
+
+// Debug is implemented by hand so that the password or key never ends up in logs or panic messages
+impl std::fmt::Debug for Encryptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encryptor")
+            .field("kdf_params", &self.kdf_params)
+            .field("algorithm", &self.algorithm)
+            .field("bind_metadata", &self.bind_metadata)
+            .field("compression", &self.compression)
+            .field("dictionary", &self.dictionary)
+            .field("header_backup", &self.header_backup)
+            .field("encoder", &self.encoder)
+            .field("encrypt_names", &self.encrypt_names)
+            .field("name_by_hash", &self.name_by_hash)
+            .field("in_place", &self.in_place)
+            .field("overwrite", &self.overwrite)
+            .field("auto_rename", &self.auto_rename)
+            .field("resume", &self.resume)
+            .field("keep_going", &self.keep_going)
+            .field("mmap", &self.mmap)
+            .field("io_buffer", &self.io_buffer)
+            .field("retry", &self.retry)
+            .field("stream_threshold", &self.stream_threshold)
+            .field("chunk_size", &self.chunk_size)
+            .field("stream_workers", &self.stream_workers)
+            .field("wrap_keys", &self.wrap_keys)
+            .field("batch_key", &self.batch_key)
+            .field("key_id", &self.key_id)
+            .field("checksum", &self.checksum)
+            .field("padding", &self.padding)
+            .field("deterministic", &self.deterministic)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Encryptor {
+    /// Create an encryptor for the given password, using AES-256-GCM and the default Argon2id parameters
+    pub fn new(password: &str) -> Self {
+        Encryptor::with_secret(Secret::password(password))
+    }
+
+    /// Create an encryptor for a raw 256-bit key, using AES-256-GCM. The key is used directly, without Argon2id,
+    /// so it must be uniformly random (e.g. generated by a key management system), never a password.
+    /// The header records that a raw key was used, and files encrypted this way can't be decrypted with a password.
+    pub fn from_key(key: [u8; 32]) -> Self {
+        Encryptor::with_secret(Secret::key(key))
+    }
+
+    /// Create an encryptor that encrypts to X25519 public keys, using AES-256-GCM. Every output gets a random
+    /// file key, wrapped separately for each recipient, so any one of them can decrypt it with their [`Identity`].
+    /// It can't decrypt anything itself. See the [`recipient`] module.
+    #[cfg(feature = "x25519")]
+    pub fn for_recipients(recipients: Vec<Recipient>) -> Self {
+        Encryptor::with_secret(Secret::Recipients(recipients))
+    }
+
+    /// Create an encryptor that decrypts files encrypted to this identity's public key,
+    /// and encrypts to that public key itself
+    #[cfg(feature = "x25519")]
+    pub fn from_identity(identity: Identity) -> Self {
+        Encryptor::with_secret(Secret::Identity(identity))
+    }
+
+    /// Create an encryptor that decrypts the files a verified [`grant::Grant`] covers, with the identity of the
+    /// operator it was minted for, until it expires. It can't encrypt anything.
+    #[cfg(feature = "grant")]
+    pub fn from_grant(grant: grant::Grant, identity: Identity) -> Self {
+        Encryptor::with_secret(Secret::Grant(Arc::new(grant), identity))
+    }
+
+    /// Create an encryptor for the credential with the ID `credential` on a FIDO2 security key, reached through
+    /// `authenticator`: the file key of every output is wrapped with the credential's hmac-secret, so encrypting and
+    /// decrypting need the security key. See the [`fido2`] module.
+    #[cfg(feature = "fido2")]
+    pub fn from_fido2(credential: Vec<u8>, authenticator: Arc<dyn fido2::Authenticator>) -> Self {
+        Encryptor::with_secret(Secret::Fido2(Arc::new(fido2::Fido2Key::new(
+            credential,
+            authenticator,
+        ))))
+    }
+
+    fn with_secret(secret: Secret) -> Self {
+        Encryptor {
+            secret,
+            kdf_params: KdfParams::default(),
+            algorithm: Algorithm::Aes256Gcm,
+            bind_metadata: true,
+            compression: Compression::none(),
+            dictionary: None,
+            header_backup: true,
+            encoder: Arc::new(encoding::Raw),
+            encrypt_names: false,
+            name_by_hash: false,
+            in_place: false,
+            overwrite: true,
+            auto_rename: false,
+            resume: false,
+            keep_going: false,
+            mmap: false,
+            io_buffer: progress::DEFAULT_IO_BUFFER,
+            retry: RetryPolicy::default(),
+            stream_threshold: stream::DEFAULT_THRESHOLD,
+            chunk_size: stream::DEFAULT_CHUNK_SIZE,
+            stream_workers: 1,
+            extra_recipients: Vec::new(),
+            wrap_keys: false,
+            batch_key: false,
+            key_id: None,
+            password_set: None,
+            checksum: false,
+            padding: None,
+            deterministic: None,
+            keys: Arc::new(KeyCache::default()),
+            nonces: None,
+            rng: Arc::new(SystemRandom),
+            fixed_time: None,
+        }
+    }
+
+    /// Use a different AEAD algorithm when encrypting, e.g. ChaCha20-Poly1305 on machines without AES acceleration.
+    /// Decryption always uses the algorithm recorded in the file header.
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Use different Argon2id cost parameters when encrypting.
+    /// Decryption always uses the parameters stored in the file header.
+    pub fn with_kdf_params(mut self, kdf_params: KdfParams) -> Self {
+        self.kdf_params = kdf_params;
+        self
+    }
+
+    /// Choose whether to record the original file name, the plaintext length and the time of encryption in the
+    /// header (on by default). They are authenticated together with the rest of the header, so decryption reports a
+    /// renamed, truncated or extended file as [`EncryptError::MetadataMismatch`]. Turn this off if the file name
+    /// or the time itself is sensitive, since the header is not encrypted.
+    pub fn with_metadata_binding(mut self, bind_metadata: bool) -> Self {
+        self.bind_metadata = bind_metadata;
+        self
+    }
+
+    /// Compress the plaintext before encrypting it (off by default).
+    /// Decryption always decompresses with the codec recorded in the file header.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Use a trained zstd dictionary when compressing with zstd, and to decompress files that were compressed with it.
+    /// Files compressed with a dictionary can't be decrypted without it.
+    pub fn with_dictionary(mut self, dictionary: Dictionary) -> Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    /// Store a copy of the header after the ciphertext (on by default), so a file whose first bytes get
+    /// damaged can still be restored with `encryptor recover`. The copy adds the header's size to every output.
+    /// Both copies are authenticated: the copy is the same bytes the ciphertext is bound to, and its flag in
+    /// the header says it must be there.
+    pub fn with_header_backup(mut self, header_backup: bool) -> Self {
+        self.header_backup = header_backup;
+        self
+    }
+
+    /// Write outputs as ASCII armor, base64 text between BEGIN and END lines that survives email and
+    /// copy-paste (off by default). Decryption detects armored inputs either way. See the [`armor`] module.
+    #[cfg(feature = "armor")]
+    pub fn with_armor(self, armor: bool) -> Self {
+        match armor {
+            true => self.with_encoder(Arc::new(encoding::Armor)),
+            false => self.with_encoder(Arc::new(encoding::Raw)),
+        }
+    }
+
+    /// Write outputs with `encoder` (raw binary by default), e.g. one of the built-in encodings or your own.
+    /// Decryption tries this encoder first, then detects the built-in ones. See the [`encoding`] module.
+    pub fn with_encoder(mut self, encoder: Arc<dyn Encoder>) -> Self {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Hide the names of encrypted files (off by default). The original name is encrypted together with the
+    /// contents instead of recorded in the header, and [`Encryptor::encrypt_file`] writes to a random name such as
+    /// `3f9c0a...e1.enc` in the same directory; [`Encryptor::decrypt_file`] restores the original name.
+    /// Decryption handles such files either way.
+    pub fn with_name_encryption(mut self, encrypt_names: bool) -> Self {
+        self.encrypt_names = encrypt_names;
+        self
+    }
+
+    /// Name each output after its contents (off by default): [`Encryptor::encrypt_file`] writes `report.pdf` to
+    /// `<BLAKE3 hash of the output in hex>.enc` in the same directory, or in the directory given to
+    /// [`Encryptor::encrypt_file_to_with`], so outputs can be stored by content and never collide. The original name
+    /// is recorded in the header, which [`Encryptor::decrypt_file`] restores instead of checking it against the
+    /// encrypted file's own name. The output is read once more after it is written, to hash it. Ignored with
+    /// [`Encryptor::with_name_encryption`] and [`Encryptor::with_in_place`], and without
+    /// [`Encryptor::with_metadata_binding`] nothing restores the name.
+    #[cfg(feature = "checksum")]
+    pub fn with_name_by_hash(mut self, name_by_hash: bool) -> Self {
+        self.name_by_hash = name_by_hash;
+        self
+    }
+
+    /// Replace the file itself instead of writing a new one next to it (off by default): [`Encryptor::encrypt_file`]
+    /// turns `report.pdf` into an encrypted `report.pdf`, and [`Encryptor::decrypt_file`] turns it back, so no
+    /// plaintext copy or `.enc` file is left behind. The new contents are written to a temporary file, synced and
+    /// renamed over the original, so a crash leaves either the old file or the new one. The file keeps its name,
+    /// also when [`Encryptor::with_name_encryption`] is on. The old contents' blocks are freed, not overwritten.
+    pub fn with_in_place(mut self, in_place: bool) -> Self {
+        self.in_place = in_place;
+        self
+    }
+
+    /// Whether writing a file may replace one that already exists (on by default). When off, the file operations
+    /// fail with an [`io::ErrorKind::AlreadyExists`] error instead, before anything is written.
+    pub fn with_overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Leave a file already at an output's path alone and write to the first free name like `report (1).pdf`
+    /// instead (off by default; see [`unclaimed_path`]), whatever [`Encryptor::with_overwrite`] says. An encrypted
+    /// file written to another name records that name, so it decrypts to `report.pdf (1)` for `report.pdf (1).enc`.
+    /// Files replaced with [`Encryptor::with_in_place`] are still replaced.
+    pub fn with_auto_rename(mut self, auto_rename: bool) -> Self {
+        self.auto_rename = auto_rename;
+        self
+    }
+
+    /// Continue chunked outputs that an earlier attempt didn't finish, instead of starting over (off by default),
+    /// for huge files on storage that may go away midway. A chunked output is written to `<output>.partial` until
+    /// it is complete, and kept there when writing fails or is cancelled. The next attempt checks the chunks in it
+    /// (that the header opens with this encryptor's secret and has the same settings and payload length, and that
+    /// every complete chunk holds what the input has there), cuts off any incomplete chunk and carries on from the
+    /// chunk after it, sealing that chunk again only if what was written of it is what sealing it gives again. A
+    /// partial output that doesn't match is started over under a new nonce when encrypting, so no nonce ever seals
+    /// two contents, and reported when decrypting. Outputs sealed in one piece always start over.
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Salvage what can be salvaged from a damaged chunked file (off by default). Decrypting a file whose chunks
+    /// fail authentication always goes on to check the rest of them, and fails with
+    /// [`EncryptError::DamagedChunks`] listing every damaged one. With this on, the output is written anyway, with
+    /// the plaintext of the intact chunks in place and each damaged chunk zero-filled, and the error names it.
+    /// A file none of whose chunks open (a wrong password, say) is never written, nor one that hides its name in
+    /// a damaged first chunk. Files sealed in one piece have nothing to salvage.
+    pub fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Read input files through a memory map instead of with read calls (off by default), which cuts the system
+    /// calls spent on multi-gigabyte local files. Mapped pages are read once, in order, so the kernel can drop them
+    /// as soon as they were used, and a streamed file still only holds one chunk in memory. Only for local files that nothing else writes to meanwhile: a mapped file
+    /// truncated by another process makes reading it crash the process instead of failing with an error.
+    #[cfg(feature = "mmap")]
+    pub fn with_mmap(mut self, mmap: bool) -> Self {
+        self.mmap = mmap;
+        self
+    }
+
+    /// Read input files and write outputs `bytes` at a time (4 MiB by default), so that spinning disks and network
+    /// filesystems see a few large requests instead of many small ones: what is read or written is collected in
+    /// buffers of this size, one for the input and one for the output of each file being worked on. Outputs are
+    /// still synced before they are put in place, so a larger buffer doesn't make them any less all-or-nothing.
+    /// 0 turns the buffers off, so every piece (64 KiB, or a chunk of a streamed file) is read and written as it
+    /// comes.
+    pub fn with_io_buffer(mut self, bytes: usize) -> Self {
+        self.io_buffer = bytes;
+        self
+    }
+
+    /// Retry reading inputs and writing outputs after transient I/O failures, such as a timed out or reset
+    /// network filesystem (off by default). See the [`retry`] module.
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Record every nonce this encryptor seals with in `log`, and fail with [`EncryptError::NonceReuse`] instead of
+    /// using one twice under the same key. Share one log between the encryptors of a batch to check all of it.
+    /// See the [`nonce_log`] module.
+    pub fn with_nonce_log(mut self, log: NonceLog) -> Self {
+        self.nonces = Some(log);
+        self
+    }
+
+    /// Seal payloads of at least `threshold` bytes (8 MiB by default) in chunks instead of in one piece. Files with a
+    /// chunked payload are encrypted and decrypted as a stream, one chunk in memory at a time, while smaller files are
+    /// read whole, which costs less per file. `u64::MAX` never chunks and 0 chunks everything. Compressed payloads are
+    /// always sealed in one piece. Decryption reads either layout. See the [`stream`] module.
+    pub fn with_stream_threshold(mut self, threshold: u64) -> Self {
+        self.stream_threshold = threshold;
+        self
+    }
+
+    /// The number of plaintext bytes in each chunk of a chunked payload (1 MiB by default, at least 1).
+    /// Every chunk adds a 16-byte tag, and streaming holds one chunk in memory.
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Seal the chunks of a file streamed by [`Encryptor::encrypt_file`] on this many threads (1 by default, at
+    /// least 1), so that a single large file can be encrypted as fast as the disk reads it on a multi-core
+    /// machine: one thread reads the file, the workers seal its chunks, and this one writes them in order. Up to
+    /// two chunks per worker are held in memory. The output is the same as with one thread.
+    pub fn with_stream_workers(mut self, workers: usize) -> Self {
+        self.stream_workers = workers.max(1);
+        self
+    }
+
+    /// Seal every output with its own random data key, wrapped for this encryptor's password, raw key or public
+    /// keys, instead of the key they give directly (off by default). The wrapped keys are left out of what the
+    /// payload is authenticated with, so [`Encryptor::rekey_file_with`] changes the password of such a file by
+    /// wrapping its data key again, rewriting only the header instead of re-encrypting the payload.
+    pub fn with_key_wrapping(mut self, wrap_keys: bool) -> Self {
+        self.wrap_keys = wrap_keys;
+        self
+    }
+
+    /// Give every output its own key derived with HKDF-SHA256 from the password's key, under a random salt of its
+    /// own, instead of using the password's key directly (off by default). The password's key is derived with
+    /// Argon2id once and shared by the clones of this encryptor, so encrypting many files costs one Argon2id run
+    /// while no two files share a key; decrypting them with one encryptor also runs it once. Files written this way
+    /// need the `batch-key` feature to be decrypted. It only applies to a password alone, without
+    /// [`Encryptor::with_key_wrapping`], recipients or [`Encryptor::with_deterministic`]. See the [`header`] module.
+    #[cfg(feature = "batch-key")]
+    pub fn with_batch_key(mut self, batch_key: bool) -> Self {
+        self.batch_key = batch_key;
+        self
+    }
+
+    /// Record `key_id`, a name for this encryptor's password or key such as `2026-q3`, in the header of every
+    /// output, so that files still encrypted under an old key can be found and rotated (see
+    /// [`header::Header::key_id`]). It is a label, not checked against the key. An ID longer than 255 bytes
+    /// isn't recorded.
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string()).filter(|id| id.len() <= u8::MAX as usize);
+        self
+    }
+
+    /// Record `secs` since the Unix epoch as when this encryptor's password was set, in the header of every output
+    /// sealed with a key derived from it. Without it the header records that the time is unknown, rather than
+    /// guessing. It is masked with the file's key (see the `header` module), and read back with
+    /// [`Encryptor::password_set`] to find passwords due for a change.
+    pub fn with_password_set(mut self, secs: u64) -> Self {
+        self.password_set = Some(secs);
+        self
+    }
+
+    /// Record a BLAKE3 checksum of the plaintext in the header of every output (off by default), which decrypting and
+    /// verifying check once the whole file is decrypted, on top of the tag of every chunk: a file whose chunks all
+    /// authenticate but don't add up to what was encrypted fails with [`EncryptError::MetadataMismatch`]. The
+    /// checksum is masked with the file key, so the header doesn't give away whether a guessed plaintext is the one
+    /// inside (see the [`header`] module), and [`Encryptor::verify_checksum_with`] checks a decrypted file against it.
+    ///
+    /// A file streamed in chunks is read twice: once to hash it, since the header in front of the chunks holds the
+    /// checksum, then to seal it. [`EncryptingWriter`], the async writer and [`Encryptor::encrypt_in_place`] don't
+    /// record one, and decrypting that resumes a partial output (see [`Encryptor::with_resume`]) can't check it, as the
+    /// chunks it already wrote aren't decrypted again.
+    #[cfg(feature = "checksum")]
+    pub fn with_checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Pad every payload with `padding` before sealing it (none by default), so the size of an output gives less of
+    /// the size of its plaintext away: [`Padding::Block`] rounds it up to a multiple of the block size, and
+    /// [`Padding::Padme`] to the next PADMÉ length, at most 12% more. The padding is added after compression and
+    /// stripped again on decryption; its length is recorded masked with the file key (see the [`header`] module).
+    /// [`EncryptingWriter`], the async writer and [`Encryptor::encrypt_in_place`] don't pad, as they don't know the
+    /// length of the plaintext before they seal it.
+    pub fn with_padding(mut self, padding: Padding) -> Self {
+        self.padding = Some(padding);
+        self
+    }
+
+    /// Seal the same plaintext into the same bytes every time, for version control filters that must not see an
+    /// unchanged file as modified: the salt is derived from `context` (e.g. the file's path in the repository)
+    /// instead of being random, the nonce is a keyed hash of the payload, and no creation time is recorded.
+    /// Outputs then reveal which ones have identical contents. It needs [`Algorithm::Aes256GcmSiv`], which stays
+    /// secure when a nonce repeats: the nonce only depends on the key and the payload, so the same payload sealed
+    /// under another header would repeat a nonce under the same key, which breaks the other ciphers. It also needs
+    /// a password or raw key alone and the whole payload at once ([`Encryptor::encrypt_bytes`], or a file below the
+    /// stream threshold); anything else fails.
+    pub fn with_deterministic(mut self, context: &str) -> Self {
+        self.deterministic = Some(context.to_string());
+        self
+    }
+
+    /// Draw the salts, nonces, file keys and ephemeral keys of every output from `rng` instead of the crypto
+    /// backend's secure generator, e.g. a [`rng::SeededRandom`] for outputs that are the same bytes on every run.
+    /// Test-only, with the `fixed-test-vectors` feature; see the [`rng`] module.
+    #[cfg(feature = "fixed-test-vectors")]
+    pub fn with_rng(mut self, rng: Arc<dyn RandomSource>) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Record `secs` since the Unix epoch as the creation time of every output instead of the time it is sealed.
+    /// Test-only, with the `fixed-test-vectors` feature, for byte-identical outputs with [`Encryptor::with_rng`].
+    #[cfg(feature = "fixed-test-vectors")]
+    pub fn with_fixed_time(mut self, secs: u64) -> Self {
+        self.fixed_time = Some(secs);
+        self
+    }
+
+    /// Also let `password` decrypt everything this encryptor encrypts. Every output then gets a random file key,
+    /// wrapped separately for this encryptor's own password, raw key or public keys and for each extra password
+    /// and recipient, so any one of them can decrypt it.
+    pub fn with_extra_password(mut self, password: &str) -> Self {
+        self.extra_recipients.push(Secret::password(password));
+        self
+    }
+
+    /// Also let the identities of these X25519 public keys decrypt everything this encryptor encrypts,
+    /// like [`Encryptor::with_extra_password`]
+    #[cfg(feature = "x25519")]
+    pub fn with_extra_recipients(mut self, recipients: Vec<Recipient>) -> Self {
+        self.extra_recipients.push(Secret::Recipients(recipients));
+        self
+    }
+
+    /// Encrypt a buffer, returning the header followed by the ciphertext and tag (or by the chunks, for a buffer of at
+    /// least [`Encryptor::with_stream_threshold`] bytes). A buffer has no name to hide, so
+    /// [`Encryptor::with_name_encryption`] doesn't apply.
+    pub fn encrypt_bytes(&self, plaintext: &[u8]) -> Result<Vec<u8>, EncryptError> {
+        let mut contents = plaintext.to_vec();
+        let mut output = self.seal(&mut contents, None)?;
+        output.extend_from_slice(&contents);
+        Ok(self.encoder.encode(output))
+    }
+
+    /// Decrypt a buffer produced by [`Encryptor::encrypt_bytes`] or read from an encrypted file.
+    /// A file name recorded in the header is not checked, since a buffer has no name, and a sealed one is dropped.
+    pub fn decrypt_bytes(&self, data: &[u8]) -> Result<Vec<u8>, EncryptError> {
+        self.open(data.to_vec(), None)
+            .map(|(plaintext, _)| plaintext)
+    }
+
+    /// Encrypt the plaintext in `buffer` in place, for services sealing many small records with a raw key (see
+    /// [`Encryptor::from_key`]): it is replaced by the header, ciphertext and tag, in the binary format of
+    /// [`Encryptor::encrypt_bytes`], so [`Encryptor::decrypt_bytes`] can read it too.
+    ///
+    /// Nothing is allocated as long as `buffer` has room for [`Encryptor::in_place_overhead`] more bytes, with the
+    /// ring and rustcrypto backends (OpenSSL's AEAD always writes to a new buffer). Compression, the header backup,
+    /// names and the encoder don't apply. Any secret other than a raw key fails with [`EncryptError::KeyModeMismatch`].
+    pub fn encrypt_in_place(&self, buffer: &mut Vec<u8>) -> Result<(), EncryptError> {
+        let alone = self.extra_recipients.is_empty() && !self.wrap_keys;
+        let (Secret::Key(key), true) = (&self.secret, alone) else {
+            return Err(EncryptError::KeyModeMismatch(format!(
+                "in-place encryption needs a raw key alone, but was given {}",
+                self.secret.describe()
+            )));
+        };
+        let max_len = self.algorithm.max_payload_len();
+        if buffer.len() as u64 > max_len {
+            return Err(EncryptError::TooLarge(format!(
+                "{} can seal at most {} bytes at once, but the payload is {} bytes",
+                self.algorithm.name(),
+                max_len,
+                buffer.len()
+            )));
+        }
+
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce)?;
+        fault::nonce(&mut nonce);
+        self.record_nonce(key, &nonce, false)?;
+        let mut header = [0u8; header::RAW_KEY_MAX_LEN];
+        let payload_len = Some(buffer.len() as u64).filter(|_| self.bind_metadata);
+        let header_len = header::write_raw_key(
+            self.algorithm,
+            &nonce,
+            payload_len,
+            self.created(),
+            &mut header,
+        );
+        let header = &header[..header_len];
+        cipher::seal_in_place(self.algorithm, key, &nonce, header, buffer)?;
+
+        // Move the ciphertext up to make room for the header in front of it
+        let sealed_len = buffer.len();
+        buffer.resize(sealed_len + header_len, 0);
+        buffer.copy_within(..sealed_len, header_len);
+        buffer[..header_len].copy_from_slice(header);
+        Ok(())
+    }
+
+    /// Decrypt the header, ciphertext and tag in `buffer` in place, leaving only the plaintext in it.
+    /// Output of [`Encryptor::encrypt_in_place`] is opened without allocating (with the same backends); anything
+    /// else [`Encryptor::decrypt_bytes`] reads works too, but allocates like it. On failure the buffer is left
+    /// empty: ciphertext that fails authentication is never handed back as plaintext.
+    pub fn decrypt_in_place(&self, buffer: &mut Vec<u8>) -> Result<(), EncryptError> {
+        let raw_key = match (&self.secret, header::parse_raw_key(buffer)) {
+            (Secret::Key(key), Some(parsed)) => Some((key, parsed)),
+            _ => None,
+        };
+        let Some((key, (algorithm, nonce, payload_len, header_len))) = raw_key else {
+            let (plaintext, _) = self.open(std::mem::take(buffer), None)?;
+            *buffer = plaintext;
+            return Ok(());
+        };
+        let expected = payload_len.map(|len| len.saturating_add(TAG_LEN as u64));
+        check_ciphertext_len(expected, (buffer.len() - header_len) as u64)?;
+
+        // The header is the associated data, so keep a copy of it before moving the ciphertext down over it
+        let mut header = [0u8; header::RAW_KEY_MAX_LEN];
+        header[..header_len].copy_from_slice(&buffer[..header_len]);
+        buffer.copy_within(header_len.., 0);
+        buffer.truncate(buffer.len() - header_len);
+        cipher::open_in_place(algorithm, key, &nonce, &header[..header_len], buffer)?;
+        Ok(())
+    }
+
+    /// How many bytes [`Encryptor::encrypt_in_place`] adds to the plaintext: the header and the tag
+    pub fn in_place_overhead(&self) -> usize {
+        // The length and the time of encryption are only recorded when metadata is bound
+        let metadata_fields = if self.bind_metadata { 16 } else { 0 };
+        header::RAW_KEY_MAX_LEN - 16 + metadata_fields + TAG_LEN
+    }
+
+    /// Encrypt the file at `file_path` into `<file_path>.enc` (or a random name, see
+    /// [`Encryptor::with_name_encryption`]), returning the path written
+    pub fn encrypt_file(&self, file_path: impl AsRef<Path>) -> Result<PathBuf, EncryptError> {
+        self.encrypt_file_with(file_path, |_| {}, &CancellationToken::new())
+    }
+
+    /// Decrypt the file at `file_path` into the same path without its last extension (or the original name sealed in
+    /// the file, see [`Encryptor::with_name_encryption`]), returning the path written
+    pub fn decrypt_file(&self, file_path: impl AsRef<Path>) -> Result<PathBuf, EncryptError> {
+        self.decrypt_file_with(file_path, |_| {}, &CancellationToken::new())
+    }
+
+    /// Like [`Encryptor::encrypt_file`], calling `progress` as the file is read, encrypted and written,
+    /// and stopping with [`EncryptError::Cancelled`] once `cancel` is triggered.
+    /// The output is written to a temporary file and renamed into place once it is complete and synced to disk,
+    /// and a failed or cancelled operation removes the temporary file. See the [`progress`] module.
+    // @dev: Efe
+    // &str is a borrowed string slice also called a string slice. It's a reference to a string.
+    // str is a string. It's an owned string.
+    // &[u8] is a slice of bytes. It's a reference to a byte array.
+    // In the CLI these params are borrowed from the args variable in the main function. ie. the args variable owns the arguments while the main function owns the args variable.
+    pub fn encrypt_file_with(
+        &self,
+        file_path: impl AsRef<Path>,
+        progress: impl FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf, EncryptError> {
+        self.encrypt_file_into(file_path.as_ref(), None, progress, cancel)
+    }
+
+    /// Like [`Encryptor::encrypt_file_with`], writing to `output_path` instead of `<file_path>.enc`.
+    /// When `output_path` is an existing directory, the output gets its usual name inside it.
+    pub fn encrypt_file_to_with(
+        &self,
+        file_path: impl AsRef<Path>,
+        output_path: impl AsRef<Path>,
+        progress: impl FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf, EncryptError> {
+        self.encrypt_file_into(
+            file_path.as_ref(),
+            Some(output_path.as_ref()),
+            progress,
+            cancel,
+        )
+    }
+
+    fn encrypt_file_into(
+        &self,
+        file_path: &Path,
+        output_path: Option<&Path>,
+        mut progress: impl FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf, EncryptError> {
+        // Paths too long for Windows' usual limit are taken in their \\?\ form, and so is everything derived from them
+        let file_path = &*names::long_path(file_path);
+        let output_path = output_path.map(names::long_path);
+        let output_path = output_path.as_deref();
+
+        // read_file(...)?: This reads the entire contents of a file into a byte vector (Vec<u8>), in chunks so progress can be reported.
+        // This is useful when you’re working with binary data or when you need the raw bytes from the file.
+        // fs::read_to_string(file_path): This function reads the entire contents of a file into a String.
+        // This is useful when you’re working with text data, as it allows you to easily work with the contents as a String.
+        // The concept of working with raw bytes is particularly relevant to encryption and decryption because these operations often deal with binary data.
+
+        // When you’re encrypting or decrypting data, you’re usually working at a low level where you need to manipulate the raw bytes of the data.
+        // This is because encryption algorithms operate on binary data, transforming the input bytes into a different set of output bytes. Similarly,
+        // decryption algorithms reverse this process, converting the encrypted bytes back into their original form.
+
+        // On the other hand, when you’re reading the number of lines in a text file, you’re typically working with higher-level text data, not raw binary data.
+        // Each line of text in a file is represented as a sequence of characters, and you can count the number of lines by counting the number of newline characters.
+        // This operation doesn’t require dealing with the raw bytes of the file, so the concept of working with byte streams or raw bytes is less applicable in this context.
+
+        // In summary, whether you need to work with raw bytes or higher-level data structures depends on the nature of the task at hand. For low-level tasks
+        // like encryption and decryption, working with raw bytes is often necessary. For higher-level tasks like counting lines in a text file, working with
+        // text data is usually more appropriate.
+
+        // Where the output goes. A file written to another name records the name decrypting it will check:
+        // its own, less the extension.
+        // One named by its hash is written under a random name first, in the directory it goes in.
+        let by_hash = self.hashes_names();
+        let encrypted_file_path = match (self.in_place, self.encrypt_names || by_hash) {
+            (true, _) => file_path.to_path_buf(),
+            (false, true) => random_file_path(file_path, &*self.rng)?,
+            (false, false) => names::with_suffix(file_path, ".enc"),
+        };
+        let (mut encrypted_file_path, mut renamed) = match output_path {
+            Some(output_path) if by_hash => (
+                output_path.join(names::file_name(&encrypted_file_path)),
+                false,
+            ),
+            Some(output_path) => (into_output(output_path, &encrypted_file_path), true),
+            None => (encrypted_file_path, false),
+        };
+        // An output moved to a free name by with_auto_rename records that name like one asked for
+        if self.auto_rename && !self.in_place {
+            let unclaimed = unclaimed_path(&encrypted_file_path);
+            renamed |= unclaimed != encrypted_file_path;
+            encrypted_file_path = unclaimed;
+        }
+        let name = match renamed && !self.encrypt_names {
+            true => names::file_name(names::strip_extension(&encrypted_file_path)).to_os_string(),
+            false => names::file_name(file_path).to_os_string(),
+        };
+
+        // Large files are sealed in chunks as they're read, unless an encoder needs the whole output at once
+        if self.encoder.is_identity() {
+            let payload_len = self.retry.run(cancel, || {
+                Ok(std::fs::metadata(file_path)?.len() + self.sealed_name_len(&name))
+            })?;
+            if let Some(chunk_size) = self.chunk_size_for(payload_len) {
+                self.retry.run(cancel, || {
+                    self.encrypt_stream(
+                        file_path,
+                        &encrypted_file_path,
+                        &name,
+                        chunk_size,
+                        &mut progress,
+                        cancel,
+                    )
+                })?;
+                return match by_hash {
+                    true => rename_to_hash(&encrypted_file_path),
+                    false => Ok(encrypted_file_path),
+                };
+            }
+        }
+
+        // Creating a buffer to hold the encrypted contents, with room for the authentication tag
+        let mut contents = self.retry.run(cancel, || {
+            progress::read_file(
+                file_path,
+                TAG_LEN,
+                self.mmap,
+                self.io_buffer,
+                &mut progress,
+                cancel,
+            )
+        })?;
+
+        // Encrypt the contents in place
+        let total = contents.len() as u64;
+        report_processing(&mut progress, 0, total);
+        cancel.check()?;
+        let header_bytes = self.seal(&mut contents, Some(&name))?;
+        report_processing(&mut progress, total, total);
+
+        // Write the header (algorithm, KDF parameters, salt, nonce and metadata) followed by the encrypted contents to a new file.
+        // Decryption reads the header back to derive the same key and open the ciphertext.
+        contents.splice(0..0, header_bytes);
+        let output = self.encoder.encode(contents);
+        self.retry.run(cancel, || {
+            progress::write_file(
+                &encrypted_file_path,
+                &[&output],
+                self.overwrite,
+                self.io_buffer,
+                &mut progress,
+                cancel,
+            )
+        })?;
+
+        match by_hash {
+            true => rename_to_hash(&encrypted_file_path),
+            false => Ok(encrypted_file_path),
+        }
+    }
+
+    /// Like [`Encryptor::decrypt_file`], calling `progress` as the file is read, decrypted and written,
+    /// and stopping with [`EncryptError::Cancelled`] once `cancel` is triggered.
+    /// Nothing is written unless the ciphertext authenticates, and a failed or cancelled write is removed.
+    pub fn decrypt_file_with(
+        &self,
+        file_path: impl AsRef<Path>,
+        progress: impl FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf, EncryptError> {
+        self.decrypt_file_into(file_path.as_ref(), None, progress, cancel)
+    }
+
+    /// Like [`Encryptor::decrypt_file_with`], writing to `output_path` instead of the file path without its
+    /// extension. When `output_path` is an existing directory, the output gets its usual name inside it.
+    /// The name recorded in the header is still checked against the encrypted file's own name.
+    pub fn decrypt_file_to_with(
+        &self,
+        file_path: impl AsRef<Path>,
+        output_path: impl AsRef<Path>,
+        progress: impl FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf, EncryptError> {
+        self.decrypt_file_into(
+            file_path.as_ref(),
+            Some(output_path.as_ref()),
+            progress,
+            cancel,
+        )
+    }
+
+    fn decrypt_file_into(
+        &self,
+        file_path: &Path,
+        output_path: Option<&Path>,
+        mut progress: impl FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf, EncryptError> {
+        let file_path = &*names::long_path(file_path);
+        let output_path = output_path.map(names::long_path);
+        let output_path = output_path.as_deref();
+
+        // A chunked file is decrypted as a stream
+        let streamed = self.retry.run(cancel, || match chunked_input(file_path)? {
+            Some(input) => self
+                .decrypt_stream(input, file_path, output_path, &mut progress, cancel)
+                .map(Some),
+            None => Ok(None),
+        })?;
+        if let Some(decrypted_file_path) = streamed {
+            return Ok(decrypted_file_path);
+        }
+
+        let (plaintext, sealed_name, decrypted_file_path) =
+            self.open_file(file_path, false, &mut progress, cancel)?;
+        let decrypted_file_path =
+            self.output_path(file_path, decrypted_file_path, sealed_name, output_path);
+
+        // Write the decrypted contents to a new file
+        self.retry.run(cancel, || {
+            progress::write_file(
+                &decrypted_file_path,
+                &[&plaintext],
+                self.overwrite,
+                self.io_buffer,
+                &mut progress,
+                cancel,
+            )
+        })?;
+
+        Ok(decrypted_file_path)
+    }
+
+    // Read the file at `file_path` into memory and decrypt it in one piece, as decrypt_file_into and
+    // decrypt_to_secret do with files that aren't chunked. Returns the plaintext, the name sealed with it and the
+    // path it decrypts to by default. With `lock`, the contents are locked into RAM before they are decrypted,
+    // which happens in the same buffer.
+    fn open_file(
+        &self,
+        file_path: &Path,
+        lock: bool,
+        progress: &mut dyn FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<(Vec<u8>, Option<OsString>, PathBuf), EncryptError> {
+        // Open the file and read its contents into a vector
+        let contents = self.retry.run(cancel, || {
+            progress::read_file(file_path, 0, self.mmap, self.io_buffer, progress, cancel)
+        })?;
+
+        // Determine the file path for the decrypted file
+        let decrypted_file_path = self.decrypted_file_path(file_path);
+        // @explanation:
+        // `if let Some(index) = file_path.rfind('.')`: This line is using the `rfind` method to search for the last occurrence of the period character (`.`) in `file_path`,
+        // which usually indicates the start of the file extension. If a period is found, its index in the string is returned as `Some(index)`.
+        // If no period is found, `rfind` returns `None`.
+
+        // `let (name_without_extension, _) = file_path.split_at(index);`: If a period was found, this line splits the `file_path` string into two at the index of the period.
+        // The part before the period (the file name without the extension) is assigned to `name_without_extension`,
+        // and the part after the period (the file extension) is ignored (`_` is a placeholder for ignored values in Rust).
+
+        // `name_without_extension.to_string()`: This line converts `name_without_extension` from a string slice (`&str`) to a `String` and returns it.
+        // This will be the value of `decrypted_file_path`.
+
+        // `file_path.to_string()`: If no period was found in `file_path` (i.e., the file has no extension), this line is executed.
+        // It converts `file_path` from a string slice (`&str`) to a `String` and returns it. This will be the value of `decrypted_file_path`.
+
+        // So, in summary, this code snippet is determining the path for the decrypted file. If the encrypted file has an extension,
+        // it removes the extension to get the original file name. If the encrypted file has no extension, it uses the encrypted file's name as is.
+
+        // @explanation: Why need to use `to_string()` for `name_without_extension` and `file_path`?
+        // In this program, the conversion from a string slice (&str) to an owned String is necessary because of the way the decrypted_file_path is used below.
+        // The decrypted_file_path is determined within the decrypt function and is then used to create a new file with File::create(decrypted_file_path)?.
+        // The File::create function requires its argument to be an owned String or something that can be converted into an owned String. A string slice (&str)
+        // would not suffice here because it’s just a borrowed reference, and File::create needs ownership of its argument.
+        // Moreover, the decrypted_file_path is created based on the file_path argument to the decrypt function. If I were to use a string slice that points
+        // into file_path, it would be tied to the lifetime of file_path. If file_path is modified or goes out of scope, the string slice would no longer be valid.
+        // By creating an owned String, I ensure that decrypted_file_path is valid for as long as it needs to be.
+
+        // Parse the header and decrypt the ciphertext behind it, in place
+        let contents = self.decoded(contents)?;
+        if lock {
+            protect(&contents);
+        }
+        let total = contents.len() as u64;
+        report_processing(progress, 0, total);
+        cancel.check()?;
+        // The name the file will be decrypted to must match the name recorded when it was encrypted.
+        // A name sealed with the contents is restored instead.
+        let (plaintext, sealed_name) =
+            self.open(contents, Some(names::file_name(&decrypted_file_path)))?;
+        report_processing(progress, total, total);
+        Ok((plaintext, sealed_name, decrypted_file_path))
+    }
+
+    /// Check that the file at `file_path` decrypts, without writing the plaintext anywhere.
+    /// Also checks that the header copy at the end of the file (see [`Encryptor::with_header_backup`])
+    /// is identical to the header at the start, reporting [`EncryptError::MetadataMismatch`] otherwise.
+    pub fn verify_file_with(
+        &self,
+        file_path: impl AsRef<Path>,
+        mut progress: impl FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<(), EncryptError> {
+        let file_path = &*names::long_path(file_path.as_ref());
+        // The file name is checked against the name decrypting would write to
+        let decrypted_file_path = match self.in_place {
+            true => file_path,
+            false => names::strip_extension(file_path),
+        };
+
+        // A chunked file is verified as a stream
+        let streamed = self.retry.run(cancel, || match chunked_input(file_path)? {
+            Some(mut input) => {
+                input.check_header_backup()?;
+                self.open_stream(
+                    input,
+                    Some(names::file_name(decrypted_file_path)),
+                    &mut |_| Ok(()),
+                    &Cell::new(0),
+                    &mut progress,
+                    cancel,
+                )
+                .map(Some)
+            }
+            None => Ok(None),
+        })?;
+        if streamed.is_some() {
+            return Ok(());
+        }
+
+        let contents = self.retry.run(cancel, || {
+            progress::read_file(
+                file_path,
+                0,
+                self.mmap,
+                self.io_buffer,
+                &mut progress,
+                cancel,
+            )
+        })?;
+        let contents = self.decoded(contents)?;
+        check_header_backup(&contents)?;
+
+        let total = contents.len() as u64;
+        report_processing(&mut progress, 0, total);
+        cancel.check()?;
+        self.open(contents, Some(names::file_name(decrypted_file_path)))?;
+        report_processing(&mut progress, total, total);
+        Ok(())
+    }
+
+    /// Decrypt the file at `file_path` into memory only, for the configuration and credentials an application loads
+    /// at startup: nothing is written to disk, and the plaintext comes back in a [`SecretVec`], wiped when it is
+    /// dropped and locked into RAM once hardening is enabled (see the `harden` module). The name recorded in the
+    /// header is checked against the file's own name less its extension, as [`Encryptor::decrypt_file`] does, and
+    /// errors are the same too. The plaintext is decrypted in a buffer locked before it is written to, and the
+    /// buffers it passes through on the way are wiped. Those a compressed file is decompressed through aren't, so
+    /// secrets are best encrypted uncompressed.
+    pub fn decrypt_to_secret(
+        &self,
+        file_path: impl AsRef<Path>,
+    ) -> Result<SecretVec, EncryptError> {
+        let file_path = &*names::long_path(file_path.as_ref());
+        let cancel = CancellationToken::new();
+
+        // A chunked file is decrypted chunk by chunk straight into the secret, which is locked before the first one
+        let streamed = self
+            .retry
+            .run(&cancel, || match chunked_input(file_path)? {
+                Some(input) => {
+                    let decrypted_file_path = self.decrypted_file_path(file_path);
+                    let mut secret = SecretVec::with_capacity(input.ciphertext_len as usize);
+                    self.open_stream(
+                        input,
+                        Some(names::file_name(&decrypted_file_path)),
+                        &mut |plaintext| {
+                            if let Plaintext::Bytes(bytes) = plaintext {
+                                secret.extend_from_slice(bytes);
+                            }
+                            Ok(())
+                        },
+                        &Cell::new(0),
+                        &mut |_| {},
+                        &cancel,
+                    )?;
+                    Ok(Some(secret))
+                }
+                None => Ok(None),
+            })?;
+        if let Some(secret) = streamed {
+            return Ok(secret);
+        }
+
+        let (plaintext, _, _) = self.open_file(file_path, true, &mut |_| {}, &cancel)?;
+        Ok(SecretVec::from(plaintext))
+    }
+
+    /// Like [`Encryptor::verify_file_with`], without progress reports or cancellation
+    pub fn verify_file(&self, file_path: impl AsRef<Path>) -> Result<(), EncryptError> {
+        self.verify_file_with(file_path, |_| {}, &CancellationToken::new())
+    }
+
+    /// Check this Encryptor's secret against the encrypted file at `file_path` without decrypting its payload: the key
+    /// is only derived, or the file key unwrapped. A password that fails the file's password check (see the `header`
+    /// module) is reported as [`EncryptError::WrongPassword`]; a file without one, from before version 4 or sealed
+    /// with a raw key or for recipients, passes any password its key derives from.
+    pub fn check_password(&self, file_path: impl AsRef<Path>) -> Result<(), EncryptError> {
+        let file_path = &*names::long_path(file_path.as_ref());
+        let header = self.file_header(file_path, &mut |_| {}, &CancellationToken::new())?;
+        self.key(&header).map(drop)
+    }
+
+    /// When the password of the encrypted file at `file_path` was set, in seconds since the Unix epoch (see
+    /// [`Encryptor::with_password_set`]), unmasked with the key derived from this Encryptor's password. None for a
+    /// file that doesn't record it: one from before version 5, sealed with a raw key or for recipients, or whose
+    /// encryptor wasn't told the time. Fails like [`Encryptor::check_password`] for a wrong password.
+    pub fn password_set(&self, file_path: impl AsRef<Path>) -> Result<Option<u64>, EncryptError> {
+        let file_path = &*names::long_path(file_path.as_ref());
+        let header = self.file_header(file_path, &mut |_| {}, &CancellationToken::new())?;
+        let key = self.key(&header)?;
+        Ok(header
+            .password_set
+            .map(|masked| mask_password_set(masked, &key, &header.nonce)))
+    }
+
+    // The header of the encrypted file at `file_path`. Only a chunked file's header is read by itself; any other
+    // file is read whole to decode it.
+    fn file_header(
+        &self,
+        file_path: &Path,
+        progress: &mut dyn FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<Header, EncryptError> {
+        match self.retry.run(cancel, || chunked_input(file_path))? {
+            Some(input) => Ok(input.header),
+            None => {
+                let contents = self.retry.run(cancel, || {
+                    progress::read_file(file_path, 0, self.mmap, self.io_buffer, progress, cancel)
+                })?;
+                Ok(Header::parse(&self.decoded(contents)?)?.0)
+            }
+        }
+    }
+
+    /// Check the decrypted file at `plaintext_path` against the checksum recorded in the encrypted file at
+    /// `file_path` (see [`Encryptor::with_checksum`]), without decrypting the payload again: the key is only derived,
+    /// or the file key unwrapped, to unmask the checksum. A plaintext that differs is reported as
+    /// [`EncryptError::MetadataMismatch`], and a file that records no checksum fails as well.
+    #[cfg(feature = "checksum")]
+    pub fn verify_checksum_with(
+        &self,
+        file_path: impl AsRef<Path>,
+        plaintext_path: impl AsRef<Path>,
+        mut progress: impl FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<(), EncryptError> {
+        let (file_path, plaintext_path) = (
+            &*names::long_path(file_path.as_ref()),
+            &*names::long_path(plaintext_path.as_ref()),
+        );
+        let header = self.file_header(file_path, &mut progress, cancel)?;
+        let Some(checksum) = checksum::recorded(&header, &*self.key(&header)?) else {
+            return Err(EncryptError::IoError(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} records no checksum of its plaintext",
+                    file_path.display()
+                ),
+            )));
+        };
+        let hashed = self.retry.run(cancel, || {
+            self.hash_file(plaintext_path, &mut progress, cancel)
+        })?;
+        checksum::check(&checksum, hashed)
+    }
+
+    /// Like [`Encryptor::verify_checksum_with`], without progress reports or cancellation
+    #[cfg(feature = "checksum")]
+    pub fn verify_checksum(
+        &self,
+        file_path: impl AsRef<Path>,
+        plaintext_path: impl AsRef<Path>,
+    ) -> Result<(), EncryptError> {
+        self.verify_checksum_with(file_path, plaintext_path, |_| {}, &CancellationToken::new())
+    }
+
+    /// Re-encrypt the file at `file_path`, which this Encryptor decrypts, under the secret of `new` (a new password,
+    /// say), and replace it once the new file is complete. A chunked file is opened and sealed again one chunk at
+    /// a time, so its plaintext is never written to disk and never held in memory whole.
+    ///
+    /// The file keeps its cipher, compression, chunk size, encoding, recorded name and header backup. The salt,
+    /// nonce, KDF cost and time of encryption are the ones `new` picks for any new output, and the header is
+    /// written in the current version.
+    ///
+    /// A file written with [`Encryptor::with_key_wrapping`] isn't re-encrypted at all: its data key is unwrapped
+    /// with this Encryptor's secret and wrapped again for `new`'s, and only the header is rewritten. The payload is
+    /// copied as it is, without being decrypted.
+    pub fn rekey_file_with(
+        &self,
+        file_path: impl AsRef<Path>,
+        new: &Encryptor,
+        mut progress: impl FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<(), EncryptError> {
+        let file_path = file_path.as_ref();
+        // A chunked file is rekeyed as a stream
+        let streamed = self.retry.run(cancel, || match chunked_input(file_path)? {
+            Some(input) if input.header.rewrappable => self
+                .rewrap_stream(input, file_path, new, &mut progress, cancel)
+                .map(Some),
+            Some(input) => self
+                .rekey_stream(input, file_path, new, &mut progress, cancel)
+                .map(Some),
+            None => Ok(None),
+        })?;
+        if streamed.is_some() {
+            return Ok(());
+        }
+
+        let contents = self.retry.run(cancel, || {
+            progress::read_file(
+                file_path,
+                0,
+                self.mmap,
+                self.io_buffer,
+                &mut progress,
+                cancel,
+            )
+        })?;
+        let encoder = match self.encoder.detect(&contents) {
+            true => self.encoder.clone(),
+            false => encoding::detect(&contents),
+        };
+
+        let contents = self.decoded(contents)?;
+
+        let total = contents.len() as u64;
+        report_processing(&mut progress, 0, total);
+        cancel.check()?;
+        let (header, header_len) = Header::parse(&contents)?;
+        let rekeyed = match header.rewrappable {
+            // Only the header changes, the payload is copied as it is
+            true => {
+                let trailer_len = match header.header_backup {
+                    true => header_len + 4,
+                    false => 0,
+                };
+                let end = contents
+                    .len()
+                    .checked_sub(trailer_len)
+                    .filter(|end| *end >= header_len)
+                    .ok_or(HeaderError::Truncated)?;
+                let header_bytes = self.rewrapped_header(&header, new)?;
+                let mut rekeyed = header_bytes.clone();
+                rekeyed.extend_from_slice(&contents[header_len..end]);
+                if header.header_backup {
+                    rekeyed.extend_from_slice(&Header::backup_trailer(&header_bytes));
+                }
+                rekeyed
+            }
+            // The payload is sealed again as it was, without decompressing it or taking out a hidden name, and
+            // padded again to the same length
+            false => {
+                let (header, checksum, mut payload) = self.open_payload(contents, None)?;
+                let header_bytes = new.sealing_like(&header).seal_payload(
+                    &mut payload,
+                    (
+                        header.file_name.as_deref(),
+                        header.sealed_name,
+                        header.escaped_name,
+                    ),
+                    header.dictionary_id,
+                    header.chunk_size,
+                    (checksum, header.padding.map(|(scheme, _)| scheme)),
+                )?;
+                payload.splice(0..0, header_bytes);
+                payload
+            }
+        };
+        report_processing(&mut progress, total, total);
+
+        let output = encoder.encode(rekeyed);
+        self.retry.run(cancel, || {
+            progress::write_file(
+                file_path,
+                &[&output],
+                true,
+                self.io_buffer,
+                &mut progress,
+                cancel,
+            )
+        })
+    }
+
+    /// Like [`Encryptor::rekey_file_with`], without progress reports or cancellation
+    pub fn rekey_file(
+        &self,
+        file_path: impl AsRef<Path>,
+        new: &Encryptor,
+    ) -> Result<(), EncryptError> {
+        self.rekey_file_with(file_path, new, |_| {}, &CancellationToken::new())
+    }
+
+    // The key for the file described by `header`: derived from the password with the header's salt and KDF,
+    // this Encryptor's raw key, or the file key unwrapped with this Encryptor's identity.
+    // A file written in another mode gets a clear error instead of an authentication failure.
+    fn key(&self, header: &Header) -> Result<KeyBytes, EncryptError> {
+        let key = self.unchecked_key(header)?;
+        // A key that fails the password check can't open the payload either, and says so before trying
+        match &header.password_check {
+            Some(check) if !password_check_matches(&key, &header.nonce, check) => {
+                Err(EncryptError::WrongPassword)
+            }
+            _ => Ok(key),
+        }
+    }
+
+    // The key of the file with `header`, whether or not it passes the header's password check
+    fn unchecked_key(&self, header: &Header) -> Result<KeyBytes, EncryptError> {
+        match (header.kdf, &self.secret) {
+            (Kdf::Argon2id, Secret::Password(password)) => self.keys.key(
+                password,
+                &header.salt,
+                header.kdf_params,
+                &header.key_context(),
+            ),
+            // The master key is cached like any password key, and each file's key derived from it
+            (Kdf::Argon2idHkdf, Secret::Password(password)) => {
+                let (batch_salt, file_salt) = header.salt.split_at(SALT_LEN);
+                let context = header.key_context();
+                let master = self
+                    .keys
+                    .key(password, batch_salt, header.kdf_params, &context)?;
+                batch_file_key(&master[..], file_salt, &context)
+            }
+            (Kdf::RawKey, Secret::Key(key)) => Ok(KeyBytes::clone(key)),
+            (Kdf::Recipients, Secret::Key(key)) => {
+                key_stanza::unwrap(&header.recipients, key, header.algorithm)
+                    .ok_or(EncryptError::AeadError(CryptoError))
+            }
+            // Unlike a password-only file, where a failed authentication may also be damage, a stanza opening
+            // tells a wrong password apart. An identity no stanza opens for stays an authentication failure.
+            (Kdf::Recipients, Secret::Password(password)) => {
+                password_stanza::unwrap(&header.recipients, password, &self.keys, header.algorithm)?
+                    .ok_or(EncryptError::WrongPassword)
+            }
+            #[cfg(feature = "x25519")]
+            (Kdf::Recipients, Secret::Identity(identity)) => {
+                recipient::unwrap(&header.recipients, identity, header.algorithm)
+                    .ok_or(EncryptError::AeadError(CryptoError))
+            }
+            // Like a password stanza, the security key's secret tells a file encrypted for another credential apart
+            #[cfg(feature = "fido2")]
+            (Kdf::Recipients, Secret::Fido2(key)) => {
+                fido2::unwrap(&header.recipients, key, header.algorithm)?.ok_or_else(|| {
+                    EncryptError::KeyModeMismatch(
+                        "the file wasn't encrypted for this security key credential".to_string(),
+                    )
+                })
+            }
+            // A grant holds the key of each file it covers, whatever the file was encrypted with
+            #[cfg(feature = "grant")]
+            (_, Secret::Grant(grant, identity)) => grant.key(header, identity),
+            (kdf, secret) => {
+                let encrypted_with = match kdf {
+                    Kdf::Argon2id | Kdf::Argon2idHkdf => "a password",
+                    Kdf::RawKey => "a raw key",
+                    Kdf::Recipients => "passwords or public keys",
+                };
+                Err(EncryptError::KeyModeMismatch(format!(
+                    "the file was encrypted with {}, but is being decrypted with {}",
+                    encrypted_with,
+                    secret.describe()
+                )))
+            }
+        }
+    }
+
+    // Function to encrypt the contents in place with a fresh nonce, returning the serialized header that describes them.
+    // The header is built first because it is also the associated data the ciphertext is bound to.
+    fn seal(
+        &self,
+        contents: &mut Vec<u8>,
+        file_name: Option<&OsStr>,
+    ) -> Result<Vec<u8>, EncryptError> {
+        // The checksum is of the contents as decrypting gives them back
+        let checksum = match self.checksum {
+            true => checksum::hash(contents),
+            false => None,
+        };
+
+        // A hidden name is sealed at the start of the payload instead of recorded in the header
+        let (file_name, sealed_name, escaped_name) = self.names(file_name);
+        if let Some(name) = &sealed_name {
+            seal_name(contents, name);
+        }
+
+        // Compress first, since ciphertext doesn't compress. Only zstd uses the dictionary.
+        let dictionary = self
+            .dictionary
+            .as_ref()
+            .filter(|_| self.compression.codec == Codec::Zstd);
+        if self.compression.codec != Codec::None {
+            *contents = compression::compress(contents, self.compression, dictionary)
+                .map_err(EncryptError::CompressionError)?;
+        }
+
+        let chunk_size = self.chunk_size_for(contents.len() as u64);
+        self.seal_payload(
+            contents,
+            (file_name.as_deref(), sealed_name.is_some(), escaped_name),
+            dictionary.map(Dictionary::id),
+            chunk_size,
+            (checksum, self.padding),
+        )
+    }
+
+    // Seal a payload that is ready to be sealed (compressed, with any hidden name in front of it) in place, in one
+    // piece or in chunks of `chunk_size` bytes, padded with `padding` first if it is given, returning the serialized
+    // header. The other arguments after `contents` are recorded in the header like in `new_header`.
+    fn seal_payload(
+        &self,
+        contents: &mut Vec<u8>,
+        names: (Option<&str>, bool, bool),
+        dictionary_id: Option<u32>,
+        chunk_size: Option<u32>,
+        (checksum, padding): (Option<Checksum>, Option<Padding>),
+    ) -> Result<Vec<u8>, EncryptError> {
+        let padding = padding.map(|scheme| (scheme, padding::pad(contents, scheme)));
+
+        // Sealing more than this under one nonce would make the cipher reuse keystream, unless it is chunked
+        let payload_len = contents.len() as u64;
+        let max_len = self.algorithm.max_payload_len();
+        if chunk_size.is_none() && payload_len > max_len {
+            return Err(EncryptError::TooLarge(format!(
+                "{} can seal at most {} bytes at once, but the payload is {} bytes",
+                self.algorithm.name(),
+                max_len,
+                contents.len()
+            )));
+        }
+
+        let (header, header_bytes, key_bytes) = self.new_header(
+            Some(payload_len),
+            names,
+            dictionary_id,
+            chunk_size,
+            (Some(contents), checksum, padding),
+        )?;
+
+        // Encrypt the contents in place with the chosen algorithm (AES-256-GCM by default) and append the authentication
+        // tag, or seal them chunk by chunk, each chunk followed by its tag
+        let aad = header.associated_data(&header_bytes);
+        match chunk_size {
+            None => {
+                cipher::seal_in_place(self.algorithm, &key_bytes, &header.nonce, &aad, contents)?
+            }
+            Some(chunk_size) => {
+                let count = stream::chunk_count(payload_len, chunk_size);
+                let mut chunks =
+                    stream::Chunks::new(self.algorithm, &key_bytes, &header.nonce, &aad);
+                let mut sealed = Vec::with_capacity(contents.len() + count as usize * TAG_LEN);
+                let mut chunk = Vec::with_capacity(chunk_size as usize + TAG_LEN);
+                for index in 0..count {
+                    let start = (index * chunk_size as u64) as usize;
+                    let end = (start + chunk_size as usize).min(contents.len());
+                    chunk.clear();
+                    chunk.extend_from_slice(&contents[start..end]);
+                    chunks.seal(&mut chunk, index == count - 1)?;
+                    sealed.extend_from_slice(&chunk);
+                }
+                *contents = sealed;
+            }
+        }
+        if self.header_backup {
+            contents.extend_from_slice(&Header::backup_trailer(&header_bytes));
+        }
+        Ok(header_bytes)
+    }
+
+    // The name to record in the header and the name to seal with the contents, for a file named `file_name`, as
+    // text, and whether that text is escaped (see the names module). Names too long for the u16 length field can't
+    // be recorded; real file systems never get close.
+    fn names<'a>(
+        &self,
+        file_name: Option<&'a OsStr>,
+    ) -> (Option<Cow<'a, str>>, Option<Cow<'a, str>>, bool) {
+        let Some((name, escaped)) = file_name
+            .map(names::record)
+            .filter(|(name, _)| name.len() <= u16::MAX as usize)
+        else {
+            return (None, None, false);
+        };
+        match (self.encrypt_names, self.bind_metadata) {
+            (true, _) => (None, Some(name), escaped),
+            (false, true) => (Some(name), None, escaped),
+            (false, false) => (None, None, false),
+        }
+    }
+
+    // Whether outputs are named by their hash, which hiding the name or replacing the file in place overrides
+    fn hashes_names(&self) -> bool {
+        self.name_by_hash && !self.encrypt_names && !self.in_place
+    }
+
+    // How many bytes sealing the name `name` adds to the start of the payload
+    fn sealed_name_len(&self, name: &OsStr) -> u64 {
+        match self.names(Some(name)) {
+            (_, Some(name), _) => 2 + name.len() as u64,
+            _ => 0,
+        }
+    }
+
+    // Record that `nonce` seals an output (or every chunk of one) under `key`, when reuse is checked
+    fn record_nonce(
+        &self,
+        key: &[u8; KEY_LEN],
+        nonce: &[u8],
+        chunked: bool,
+    ) -> Result<(), EncryptError> {
+        match &self.nonces {
+            Some(log) => log.record(key, nonce, chunked),
+            None => Ok(()),
+        }
+    }
+
+    // The time to record as when an output was encrypted, in seconds since the Unix epoch, if metadata is bound
+    fn created(&self) -> Option<u64> {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).ok();
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        let since_epoch = wasm::since_epoch();
+        self.fixed_time
+            .or(since_epoch.map(|since| since.as_secs()))
+            .filter(|_| self.bind_metadata && self.deterministic.is_none())
+    }
+
+    // Fail unless an output can be sealed deterministically for `context`: with AES-256-GCM-SIV and a password or raw
+    // key alone, its whole payload at hand
+    fn check_deterministic(
+        &self,
+        context: &str,
+        wraps_key: bool,
+        whole: bool,
+    ) -> Result<(), EncryptError> {
+        if wraps_key || !matches!(self.secret, Secret::Password(_) | Secret::Key(_)) {
+            return Err(EncryptError::KeyModeMismatch(format!(
+                "deterministic encryption (for {}) needs a password or raw key alone, but was given {}",
+                context,
+                match wraps_key {
+                    true => "wrapped or extra keys",
+                    false => self.secret.describe(),
+                }
+            )));
+        }
+        if self.algorithm != Algorithm::Aes256GcmSiv {
+            return Err(EncryptError::IoError(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "deterministic encryption (for {}) needs {}, which stays secure when a nonce repeats, not {}",
+                    context,
+                    Algorithm::Aes256GcmSiv.name(),
+                    self.algorithm.name()
+                ),
+            )));
+        }
+        if !whole {
+            return Err(EncryptError::IoError(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "deterministic encryption (for {}) needs the whole payload at once, not a stream",
+                    context
+                ),
+            )));
+        }
+        Ok(())
+    }
+
+    // The chunk size to seal a payload of `payload_len` bytes with, or None to seal it in one piece
+    fn chunk_size_for(&self, payload_len: u64) -> Option<u32> {
+        let chunked = self.compression.codec == Codec::None && payload_len >= self.stream_threshold;
+        chunked.then_some(self.chunk_size)
+    }
+
+    // The header of a new output with a payload of `payload_len` bytes, serialized, and the key to seal it with.
+    // The length of a chunked payload written as it comes (see EncryptingWriter) isn't known, and isn't recorded.
+    // The payload itself is only given when it is at hand, and `checksum` is the plaintext's, recorded masked, like
+    // the number of bytes `padding` added to the end of the payload. `names` are the name to record, whether one is sealed and whether either is escaped, like `names` gives them.
+    fn new_header(
+        &self,
+        payload_len: Option<u64>,
+        (file_name, sealed_name, escaped_name): (Option<&str>, bool, bool),
+        dictionary_id: Option<u32>,
+        chunk_size: Option<u32>,
+        (payload, checksum, padding): (Option<&[u8]>, Option<Checksum>, Option<Padded>),
+    ) -> Result<(Header, Vec<u8>, KeyBytes), EncryptError> {
+        // Every chunk is sealed with its own nonce, so each counts as an output of the key; a payload of unknown
+        // length as many as a payload can have
+        let outputs = match (chunk_size, payload_len) {
+            (Some(chunk_size), Some(payload_len)) => {
+                stream::checked_chunk_count(payload_len, chunk_size)?
+            }
+            (Some(_), None) => u32::MAX as u64 + 1,
+            (None, _) => 1,
+        };
+
+        // Use this Encryptor's random salt for every output. A raw key isn't derived, so it has no salt or KDF cost,
+        // and recipients get a fresh random file key for every output, wrapped for each of them.
+        let wraps_key = self.wrap_keys || !self.extra_recipients.is_empty();
+        if let Some(context) = &self.deterministic {
+            self.check_deterministic(context, wraps_key, payload.is_some())?;
+        }
+        let (kdf, kdf_params, salt, recipients, file_key) = match &self.secret {
+            // The batch's salt never rolls over, since the key it derives seals nothing itself
+            Secret::Password(_) if !wraps_key && self.batch_key && self.deterministic.is_none() => {
+                let mut salt = self.keys.session_salt(0, 0, u64::MAX, &*self.rng)?.to_vec();
+                salt.resize(2 * SALT_LEN, 0);
+                self.rng.fill(&mut salt[SALT_LEN..])?;
+                (Kdf::Argon2idHkdf, self.kdf_params, salt, Vec::new(), None)
+            }
+            Secret::Password(_) if !wraps_key => (
+                Kdf::Argon2id,
+                self.kdf_params,
+                match &self.deterministic {
+                    Some(context) => deterministic_salt(context).to_vec(),
+                    None => self
+                        .keys
+                        .session_salt(
+                            0,
+                            outputs,
+                            self.algorithm.max_messages_per_key(),
+                            &*self.rng,
+                        )?
+                        .to_vec(),
+                },
+                Vec::new(),
+                None,
+            ),
+            Secret::Key(_) if !wraps_key => {
+                (Kdf::RawKey, KdfParams::NONE, Vec::new(), Vec::new(), None)
+            }
+            _ => {
+                let (key, stanzas) = self.wrap_new_key()?;
+                (
+                    Kdf::Recipients,
+                    KdfParams::NONE,
+                    Vec::new(),
+                    stanzas,
+                    Some(key),
+                )
+            }
+        };
+
+        // Generate a fresh random nonce for this encryption. A nonce must never be reused with the same key;
+        // with 96 random bits the chance of a collision stays negligible for billions of outputs under one key.
+        // A deterministic output gets its nonce once the key is known.
+        let mut nonce = [0u8; NONCE_LEN];
+        if self.deterministic.is_none() {
+            self.rng.fill(&mut nonce)?;
+            fault::nonce(&mut nonce);
+        }
+
+        let mut header = Header {
+            version: header::VERSION,
+            algorithm: self.algorithm,
+            kdf,
+            kdf_params,
+            salt,
+            nonce: nonce.to_vec(),
+            file_name: file_name.map(str::to_string),
+            escaped_name,
+            hash_name: self.name_by_hash && file_name.is_some() && !sealed_name,
+            payload_len: payload_len.filter(|_| self.bind_metadata),
+            compression: self.compression.codec,
+            dictionary_id,
+            recipients,
+            header_backup: self.header_backup,
+            sealed_name,
+            chunk_size,
+            created: self.created(),
+            rewrappable: self.wrap_keys,
+            key_id: self.key_id.clone(),
+            checksum: None,
+            padding: None,
+            password_check: None,
+            password_set: None,
+        };
+
+        // Derive the key from the password and salt, bound to the algorithm and KDF cost in the header
+        // (cached after the first call), unless a file key was just generated
+        let key_bytes = match file_key {
+            Some(key) => key,
+            None => self.key(&header)?,
+        };
+        // The same payload under the same key is meant to get the same nonce, so only random ones are recorded
+        match (&self.deterministic, payload) {
+            (Some(_), Some(payload)) => {
+                header.nonce = deterministic_nonce(&key_bytes, payload).to_vec()
+            }
+            _ => self.record_nonce(&key_bytes, &nonce, chunk_size.is_some())?,
+        }
+        // Masked with the key and the final nonce, both known only now
+        header.checksum =
+            checksum.and_then(|checksum| checksum::mask(&checksum, &key_bytes, &header.nonce));
+        header.padding =
+            padding.map(|(scheme, len)| (scheme, padding::mask(len, &key_bytes, &header.nonce)));
+        // Only a key derived from a password can be wrong in a way the payload can't tell from damage, or get old
+        if matches!(kdf, Kdf::Argon2id | Kdf::Argon2idHkdf) {
+            header.password_check = Some(password_check(&key_bytes, &header.nonce));
+            header.password_set = self
+                .password_set
+                .map(|secs| mask_password_set(secs, &key_bytes, &header.nonce));
+        }
+        let header_bytes = header.to_bytes();
+        log_layout("Sealing", &header);
+        Ok((header, header_bytes, key_bytes))
+    }
+
+    // Encrypt the file at `file_path` into `output_path` one chunk at a time, with the payload chunked in pieces of
+    // `chunk_size` bytes. `name` is recorded or sealed like in `seal`.
+    fn encrypt_stream(
+        &self,
+        file_path: &Path,
+        output_path: &Path,
+        name: &OsStr,
+        chunk_size: u32,
+        progress: &mut dyn FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<(), EncryptError> {
+        let mut input = Input::open(file_path, self.mmap, self.io_buffer)?;
+        let (file_name, sealed_name, escaped_name) = self.names(Some(name));
+        let mut prefix = Vec::new();
+        if let Some(name) = &sealed_name {
+            seal_name(&mut prefix, name);
+        }
+        let names = (file_name.as_deref(), sealed_name.is_some(), escaped_name);
+        let unpadded_len = prefix.len() as u64 + input.len()?;
+        let padding = self.padding.map(|scheme| {
+            (
+                scheme,
+                padding::padded_len(scheme, unpadded_len) - unpadded_len,
+            )
+        });
+        let pad = padding.map_or(0, |(_, pad)| pad);
+        let payload_len = unpadded_len + pad;
+        let count = stream::chunk_count(payload_len, chunk_size);
+        // The checksum goes into the header in front of the chunks, so the file is read through for it first
+        let checksum = match self.checksum {
+            true => self.hash_file(file_path, progress, cancel)?,
+            false => None,
+        };
+
+        // Continue a partial output of this payload, or start a new one
+        let mut output = self.stream_output(output_path, cancel)?;
+        let resumed = match self.resume {
+            true => self.resumable_chunks(
+                output_path,
+                names,
+                (payload_len, chunk_size),
+                &prefix,
+                &mut input,
+                (checksum, padding),
+            )?,
+            false => None,
+        };
+        let (header, header_bytes, key_bytes, first) = match resumed {
+            Some(PartialOutput {
+                header,
+                header_bytes,
+                key_bytes,
+                chunks: first,
+            }) => {
+                log::info!(
+                    "Resuming {} after {} of its {} chunks",
+                    output_path.display(),
+                    first,
+                    count
+                );
+                let sealed_chunk_len = chunk_size as u64 + TAG_LEN as u64;
+                output.truncate(header_bytes.len() as u64 + first * sealed_chunk_len)?;
+                (header, header_bytes, key_bytes, first)
+            }
+            None => {
+                let (header, header_bytes, key_bytes) = self.new_header(
+                    Some(payload_len),
+                    names,
+                    None,
+                    Some(chunk_size),
+                    (None, checksum, padding),
+                )?;
+                output.truncate(0)?;
+                output.write(&header_bytes, cancel)?;
+                (header, header_bytes, key_bytes, 0)
+            }
+        };
+
+        let aad = header.associated_data(&header_bytes);
+        let mut chunks = stream::Chunks::new(self.algorithm, &key_bytes, &header.nonce, &aad);
+        chunks.seek(first);
+        let mut reader = payload_reader(&prefix, &mut input, pad, first * chunk_size as u64)?;
+        // The payload length is in the header in front of the chunks, so the file must not grow or shrink meanwhile
+        let changed = || {
+            EncryptError::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} changed while it was being encrypted",
+                    file_path.display()
+                ),
+            ))
+        };
+        let len = |index: u64| (payload_len - index * chunk_size as u64).min(chunk_size as u64);
+        let mut write = |index: u64, chunk: &[u8]| {
+            output.write(chunk, cancel)?;
+            progress(Progress {
+                stage: Stage::Streaming,
+                bytes_done: index * chunk_size as u64 + len(index),
+                bytes_total: payload_len,
+            });
+            Ok(())
+        };
+        if self.stream_workers > 1 && count - first > 1 {
+            stream::seal_pipelined(
+                &chunks,
+                self.stream_workers,
+                (first, count),
+                |index| len(index) as usize,
+                &mut reader,
+                changed,
+                &mut write,
+            )?;
+        } else {
+            let mut chunk = Vec::with_capacity(chunk_size as usize + TAG_LEN);
+            for index in first..count {
+                chunk.clear();
+                chunk.resize(len(index) as usize, 0);
+                if progress::read_full(&mut reader, &mut chunk)? != chunk.len() {
+                    return Err(changed());
+                }
+                chunks.seal(&mut chunk, index == count - 1)?;
+                write(index, &chunk)?;
+            }
+            if progress::read_full(&mut reader, &mut [0])? != 0 {
+                return Err(changed());
+            }
+        }
+        if self.header_backup {
+            output.write(&Header::backup_trailer(&header_bytes), cancel)?;
+        }
+        // Closed before the output is renamed, which may replace it (see with_in_place)
+        drop(reader);
+        drop(input);
+        output.commit()
+    }
+
+    // The checksum of the file at `file_path`, read through once, or None when this build has no BLAKE3
+    fn hash_file(
+        &self,
+        file_path: &Path,
+        progress: &mut dyn FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<Option<Checksum>, EncryptError> {
+        let Some(mut hasher) = checksum::Hasher::new() else {
+            return Ok(None);
+        };
+        let mut input = Input::open(file_path, self.mmap, self.io_buffer)?;
+        let bytes_total = input.len()?;
+        let mut buffer = vec![0; progress::IO_CHUNK];
+        let mut bytes_done = 0;
+        loop {
+            cancel.check()?;
+            let read = progress::read_full(&mut input, &mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            bytes_done += read as u64;
+            progress(Progress {
+                stage: Stage::Reading,
+                bytes_done,
+                bytes_total,
+            });
+        }
+        Ok(Some(hasher.finish()))
+    }
+
+    // How far the partial output of `output_path` (see with_resume) got with the payload of `prefix` followed by
+    // `input`. None when there is nothing to continue, or the partial output is of another input, secret or
+    // settings, so it's started over under a new nonce. The names (like `new_header` takes them), and the payload's
+    // length and chunk size, are the header's, and so is the checksum.
+    fn resumable_chunks(
+        &self,
+        output_path: &Path,
+        (file_name, sealed_name, escaped_name): (Option<&str>, bool, bool),
+        (payload_len, chunk_size): (u64, u32),
+        prefix: &[u8],
+        input: &mut Input,
+        (checksum, padding): (Option<Checksum>, Option<Padded>),
+    ) -> Result<Option<PartialOutput>, EncryptError> {
+        let partial_path = progress::partial_path(output_path);
+        let start_over = |reason: &str| {
+            log::info!("Starting {} over: {}", partial_path.display(), reason);
+            Ok(None)
+        };
+        let partial = match chunked_input(&partial_path) {
+            Ok(Some(partial)) => partial,
+            Err(EncryptError::IoError(err)) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(None)
+            }
+            _ if std::fs::metadata(&partial_path).is_ok_and(|metadata| metadata.len() == 0) => {
+                return Ok(None)
+            }
+            _ => return start_over("its header is incomplete or damaged"),
+        };
+        let ChunkedInput {
+            mut file,
+            header,
+            header_bytes,
+            ..
+        } = partial;
+        let same_settings = header.algorithm == self.algorithm
+            && header.chunk_size == Some(chunk_size)
+            && header.compression == Codec::None
+            && header.header_backup == self.header_backup
+            && header.sealed_name == sealed_name
+            && header.file_name.as_deref() == file_name
+            && header.escaped_name == escaped_name
+            && header.payload_len.is_none_or(|len| len == payload_len)
+            && header.padding.map(|(scheme, _)| scheme) == self.padding
+            && header.key_id == self.key_id;
+        if !same_settings {
+            return start_over("it was written with other settings or from another input");
+        }
+        let Ok(key_bytes) = self.key(&header) else {
+            return start_over("it was written with another secret");
+        };
+        let pad = padding.map_or(0, |(_, pad)| pad);
+        if checksum::recorded(&header, &key_bytes) != checksum
+            || padding::recorded(&header, &key_bytes) != pad
+        {
+            return start_over("it was written from other contents");
+        }
+
+        // The complete chunks, but always the last one again, which also ends the output
+        let sealed_chunk_len = chunk_size as u64 + TAG_LEN as u64;
+        let written = file.metadata()?.len() - header_bytes.len() as u64;
+        let complete =
+            (written / sealed_chunk_len).min(stream::chunk_count(payload_len, chunk_size) - 1);
+        if complete == 0 {
+            return Ok(None);
+        }
+
+        // Every kept chunk must hold what the input has there: continuing after a chunk of other contents would seal
+        // the rest with nonces that already sealed those
+        let aad = header.associated_data(&header_bytes);
+        let mut chunks = stream::Chunks::new(header.algorithm, &key_bytes, &header.nonce, &aad);
+        let mut sealed = Zeroizing::new(Vec::with_capacity(sealed_chunk_len as usize));
+        let mut expected = Zeroizing::new(vec![0; chunk_size as usize]);
+        let mut reader = payload_reader(prefix, input, pad, 0)?;
+        file.seek(io::SeekFrom::Start(header_bytes.len() as u64))?;
+        for _ in 0..complete {
+            sealed.resize(sealed_chunk_len as usize, 0);
+            file.read_exact(&mut sealed)?;
+            if chunks.open(&mut sealed, false).is_err() {
+                return start_over("one of its chunks is damaged");
+            }
+            if progress::read_full(&mut reader, &mut expected)? != expected.len()
+                || *sealed != *expected
+            {
+                return start_over("it was written from other contents");
+            }
+        }
+        // The chunk after them is sealed again under the nonce that sealed whatever was written of it, so that must
+        // be what sealing the input's chunk gives, or the nonce would seal two contents
+        let count = stream::chunk_count(payload_len, chunk_size);
+        let next_len = (payload_len - complete * chunk_size as u64).min(chunk_size as u64) as usize;
+        let mut written = Vec::with_capacity(next_len + TAG_LEN);
+        (&mut file)
+            .take((next_len + TAG_LEN) as u64)
+            .read_to_end(&mut written)?;
+        if !written.is_empty() {
+            let mut resealed = Zeroizing::new(vec![0; next_len]);
+            if progress::read_full(&mut reader, &mut resealed)? != next_len {
+                return start_over("it was written from other contents");
+            }
+            chunks.seal(&mut resealed, complete == count - 1)?;
+            if !resealed.starts_with(&written) {
+                return start_over("its last chunk was written from other contents");
+            }
+        }
+        Ok(Some(PartialOutput {
+            header,
+            header_bytes,
+            key_bytes,
+            chunks: complete,
+        }))
+    }
+
+    // Generate a random file key and wrap it for this Encryptor's own secret and every extra recipient
+    fn wrap_new_key(&self) -> Result<(KeyBytes, Vec<Stanza>), EncryptError> {
+        let mut file_key = Zeroizing::new([0u8; KEY_LEN]);
+        self.rng.fill(&mut file_key[..])?;
+        let stanzas = self.wrap_key(&file_key)?;
+        Ok((file_key, stanzas))
+    }
+
+    // Wrap `file_key` for this Encryptor's own secret and every extra recipient.
+    // An identity encrypts to its own public key.
+    fn wrap_key(&self, file_key: &[u8; KEY_LEN]) -> Result<Vec<Stanza>, EncryptError> {
+        let mut stanzas = Vec::new();
+        let secrets = std::iter::once(&self.secret).chain(&self.extra_recipients);
+        for (slot, secret) in secrets.enumerate() {
+            match secret {
+                Secret::Password(password) => stanzas.push(password_stanza::wrap(
+                    file_key,
+                    password,
+                    slot,
+                    &self.keys,
+                    self.kdf_params,
+                    self.algorithm,
+                    self.nonces.as_ref(),
+                    &*self.rng,
+                )?),
+                Secret::Key(key) => stanzas.push(key_stanza::wrap(
+                    file_key,
+                    key,
+                    self.algorithm,
+                    self.nonces.as_ref(),
+                    &*self.rng,
+                )?),
+                #[cfg(feature = "x25519")]
+                Secret::Recipients(targets) => {
+                    for target in targets {
+                        stanzas.push(recipient::wrap(
+                            file_key,
+                            target,
+                            self.algorithm,
+                            &*self.rng,
+                        )?);
+                    }
+                }
+                #[cfg(feature = "x25519")]
+                Secret::Identity(identity) => stanzas.push(recipient::wrap(
+                    file_key,
+                    &identity.recipient(),
+                    self.algorithm,
+                    &*self.rng,
+                )?),
+                #[cfg(feature = "fido2")]
+                Secret::Fido2(key) => stanzas.push(fido2::wrap(
+                    file_key,
+                    key,
+                    self.algorithm,
+                    self.nonces.as_ref(),
+                    &*self.rng,
+                )?),
+                #[cfg(feature = "grant")]
+                Secret::Grant(..) => {
+                    return Err(EncryptError::KeyModeMismatch(
+                        "a decryption grant can't encrypt".to_string(),
+                    ))
+                }
+            }
+        }
+
+        // The header stores the number of stanzas in a u8, and with none nobody could decrypt the file
+        if stanzas.is_empty() || stanzas.len() > u8::MAX as usize {
+            return Err(EncryptError::KeyModeMismatch(
+                "a file can be encrypted to between 1 and 255 passwords and public keys"
+                    .to_string(),
+            ));
+        }
+        Ok(stanzas)
+    }
+
+    // The binary file inside an input written with this encryptor's encoder or any built-in encoding
+    fn decoded(&self, contents: Vec<u8>) -> Result<Vec<u8>, HeaderError> {
+        match self.encoder.detect(&contents) {
+            true => self.encoder.decode(contents),
+            false => encoding::decode(contents),
+        }
+    }
+
+    // Function to parse the header at the start of the contents and decrypt the ciphertext behind it.
+    // `file_name` is the name the plaintext is being decrypted to, checked against the name recorded in the header.
+    // Returns the plaintext and the original file name, if it was sealed with the contents or the file is named by
+    // its hash.
+    fn open(
+        &self,
+        contents: Vec<u8>,
+        file_name: Option<&OsStr>,
+    ) -> Result<(Vec<u8>, Option<OsString>), EncryptError> {
+        let (header, checksum, payload) = self.open_payload(contents, file_name)?;
+
+        // Undo the compression applied before encryption, if any, with the dictionary it was compressed with
+        let plaintext = match header.compression {
+            Codec::None => payload,
+            codec => {
+                let payload = Zeroizing::new(payload);
+                compression::decompress(&payload, codec, self.dictionary_for(&header)?)
+                    .map_err(EncryptError::CompressionError)?
+            }
+        };
+        let (plaintext, sealed_name) = match header.sealed_name {
+            true => unseal_name(plaintext, header.escaped_name)
+                .map(|(plaintext, name)| (plaintext, Some(name)))?,
+            false => (plaintext, hashed_file_name(&header)?),
+        };
+        if let Some(checksum) = checksum {
+            checksum::check(&checksum, checksum::hash(&plaintext))?;
+        }
+        Ok((plaintext, sealed_name))
+    }
+
+    // The first half of `open`: parse the header and decrypt the ciphertext behind it, returning the header, the
+    // checksum it records (unmasked) and the payload as it was sealed without its padding, still compressed and with
+    // any hidden name in front of it
+    fn open_payload(
+        &self,
+        contents: Vec<u8>,
+        file_name: Option<&OsStr>,
+    ) -> Result<(Header, Option<Checksum>, Vec<u8>), EncryptError> {
+        // Parse and validate the header written in front of the ciphertext
+        let mut contents = self.decoded(contents)?;
+        let (header, header_len) = Header::parse(&contents)?;
+        log_layout("Opening", &header);
+        // The ciphertext stays in the buffer it was read into, to be decrypted there (see open_file)
+        let header_bytes = contents[..header_len].to_vec();
+        contents.drain(..header_len);
+        let mut ciphertext = contents;
+
+        // The header copy at the end is only needed by `encryptor recover`
+        if header.header_backup {
+            let trailer_len = header_len + 4;
+            if ciphertext.len() < trailer_len {
+                return Err(HeaderError::Truncated.into());
+            }
+            ciphertext.truncate(ciphertext.len() - trailer_len);
+        }
+
+        check_metadata(&header, file_name, ciphertext.len() as u64)?;
+        let count = match header.chunk_size {
+            Some(chunk_size) => {
+                stream::ciphertext_chunk_count(ciphertext.len() as u64, chunk_size)?
+            }
+            None => 1,
+        };
+
+        // Derive the same key from the password, salt and stored parameters. From version 3 on the key also
+        // depends on the algorithm and KDF cost, so a header rewritten to weaker values yields the wrong key.
+        let key_bytes = self.key(&header)?;
+
+        // Decrypt the contents in place, leaving only the plaintext in the buffer, or chunk by chunk
+        let aad = header.associated_data(&header_bytes);
+        match header.chunk_size {
+            None => cipher::open_in_place(
+                header.algorithm,
+                &key_bytes,
+                &header.nonce,
+                &aad,
+                &mut ciphertext,
+            )?,
+            Some(chunk_size) => {
+                let mut chunks =
+                    stream::Chunks::new(header.algorithm, &key_bytes, &header.nonce, &aad);
+                // Each chunk's plaintext is moved up over the tags in front of it, in the same buffer. The chunks
+                // that opened before one fails are wiped along with it, like a one-piece payload.
+                let mut opened = Zeroizing::new(std::mem::take(&mut ciphertext));
+                let mut chunk = Zeroizing::new(Vec::with_capacity(chunk_size as usize + TAG_LEN));
+                let sealed_chunk_len = chunk_size as usize + TAG_LEN;
+                let mut plaintext_len = 0;
+                for index in 0..count as usize {
+                    let start = index * sealed_chunk_len;
+                    let end = (start + sealed_chunk_len).min(opened.len());
+                    chunk.clear();
+                    chunk.extend_from_slice(&opened[start..end]);
+                    chunks.open(&mut chunk, index as u64 == count - 1)?;
+                    opened[plaintext_len..plaintext_len + chunk.len()].copy_from_slice(&chunk);
+                    plaintext_len += chunk.len();
+                }
+                opened.truncate(plaintext_len);
+                ciphertext = std::mem::take(&mut *opened);
+            }
+        }
+        let unpadded_len = padding::unpadded_len(&header, &key_bytes, ciphertext.len() as u64)?;
+        ciphertext.truncate(unpadded_len as usize);
+        let checksum = checksum::recorded(&header, &key_bytes);
+        Ok((header, checksum, ciphertext))
+    }
+
+    // Decrypt a chunked file one chunk at a time, passing each chunk's plaintext to `sink` as soon as it authenticates,
+    // preceded by the original name if it was sealed with the contents. `file_name` is checked like in `open`, and
+    // `skip_to` like in `open_chunks`. The checksum, if the file records one, is checked at the end, unless chunks
+    // were skipped.
+    fn open_stream(
+        &self,
+        input: ChunkedInput,
+        file_name: Option<&OsStr>,
+        sink: &mut dyn FnMut(Plaintext<'_>) -> Result<(), EncryptError>,
+        skip_to: &Cell<u64>,
+        progress: &mut dyn FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<(), EncryptError> {
+        // The start of the payload, until the sealed name in front of it is complete
+        let mut name_prefix = input.header.sealed_name.then(|| Zeroizing::new(Vec::new()));
+        let escaped_name = input.header.escaped_name;
+        let mut hasher = input.header.checksum.and_then(|_| checksum::Hasher::new());
+        let mut sink = |plaintext: Plaintext<'_>| {
+            if let (Some(hasher), Plaintext::Bytes(bytes)) = (&mut hasher, &plaintext) {
+                hasher.update(bytes);
+            }
+            sink(plaintext)
+        };
+        let checksum = self.open_chunks(
+            input,
+            file_name,
+            &mut |chunk| match &mut name_prefix {
+                Some(prefix) => {
+                    prefix.extend_from_slice(chunk);
+                    if sealed_name_complete(prefix) {
+                        let sealed_len = prefix.len();
+                        let (rest, name) =
+                            unseal_name(std::mem::take(&mut **prefix), escaped_name)?;
+                        let rest = Zeroizing::new(rest);
+                        sink(Plaintext::Name(name, sealed_len - rest.len()))?;
+                        sink(Plaintext::Bytes(&rest))?;
+                        name_prefix = None;
+                    }
+                    Ok(())
+                }
+                None => sink(Plaintext::Bytes(chunk)),
+            },
+            skip_to,
+            progress,
+            cancel,
+        )?;
+        // A payload too short for its sealed name
+        if let Some(mut prefix) = name_prefix {
+            unseal_name(std::mem::take(&mut *prefix), escaped_name)?;
+        }
+        match (checksum, hasher) {
+            (Some(checksum), Some(hasher)) if skip_to.get() == 0 => {
+                checksum::check(&checksum, Some(hasher.finish()))
+            }
+            (Some(_), _) => {
+                log::debug!(
+                    "Not checking the checksum, as the chunks already decrypted were skipped"
+                );
+                Ok(())
+            }
+            (None, _) => Ok(()),
+        }
+    }
+
+    // Decrypt a chunked file one chunk at a time, passing each chunk's plaintext to `sink` as soon as it
+    // authenticates, as it was sealed (with any hidden name still in front of the first one). A sink that already
+    // has the plaintext of some chunks (see decrypt_stream) can raise `skip_to` to the index of the next chunk it needs,
+    // and those before it are skipped without being opened.
+    //
+    // A chunk that fails authentication doesn't stop the rest from being checked, so that DamagedChunks can list
+    // every damaged one, but nothing more goes to `sink` unless the intact chunks are salvaged (see
+    // with_keep_going), when it gets zeros in place of each damaged chunk. The padding at the end of the payload
+    // isn't passed on. Returns the checksum the header records, unmasked, for the caller to check against the
+    // plaintext.
+    fn open_chunks(
+        &self,
+        input: ChunkedInput,
+        file_name: Option<&OsStr>,
+        sink: &mut dyn FnMut(&[u8]) -> Result<(), EncryptError>,
+        skip_to: &Cell<u64>,
+        progress: &mut dyn FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<Option<Checksum>, EncryptError> {
+        let ChunkedInput {
+            file,
+            header,
+            header_bytes,
+            chunk_size,
+            ciphertext_len,
+        } = input;
+        log_layout("Opening", &header);
+        check_metadata(&header, file_name, ciphertext_len)?;
+        let count = stream::ciphertext_chunk_count(ciphertext_len, chunk_size)?;
+        let key_bytes = self.key(&header)?;
+        let unpadded_len =
+            padding::unpadded_len(&header, &key_bytes, ciphertext_len - count * TAG_LEN as u64)?;
+        // What is left of the chunk at `index` once the padding is cut off
+        let unpadded = |index: u64, chunk: &[u8]| {
+            let end = unpadded_len.saturating_sub(index * chunk_size as u64);
+            chunk.len().min(end.try_into().unwrap_or(usize::MAX))
+        };
+
+        let aad = header.associated_data(&header_bytes);
+        let mut chunks = stream::Chunks::new(header.algorithm, &key_bytes, &header.nonce, &aad);
+        let sealed_chunk_len = chunk_size as u64 + TAG_LEN as u64;
+        let mut input = Input::new(file, self.mmap, self.io_buffer)?;
+        input.seek(io::SeekFrom::Start(header_bytes.len() as u64))?;
+        let mut reader = input.take(ciphertext_len);
+        let mut chunk = Zeroizing::new(Vec::with_capacity(sealed_chunk_len as usize));
+        let mut index = 0;
+        let mut damaged: Vec<DamagedRange> = Vec::new();
+        let mut salvage = self.keep_going;
+        while index < count {
+            if skip_to.get() > index {
+                index = skip_to.get().min(count - 1);
+                chunks.seek(index);
+                let offset = index * sealed_chunk_len;
+                reader
+                    .get_mut()
+                    .seek(io::SeekFrom::Start(header_bytes.len() as u64 + offset))?;
+                reader.set_limit(ciphertext_len - offset);
+            }
+            cancel.check()?;
+            let offset = index * sealed_chunk_len;
+            let len = (ciphertext_len - offset).min(sealed_chunk_len) as usize;
+            chunk.resize(len, 0);
+            if progress::read_full(&mut reader, &mut chunk)? != len {
+                return Err(HeaderError::Truncated.into());
+            }
+            match chunks.open(&mut chunk, index == count - 1) {
+                Ok(()) if damaged.is_empty() || salvage => sink(&chunk[..unpadded(index, &chunk)])?,
+                Ok(()) => {}
+                Err(EncryptError::AeadError(_)) => {
+                    let file_offset = header_bytes.len() as u64 + offset;
+                    // A wrong key fails every chunk, and so does the last one then: stop at the first, like before
+                    // chunks could be told apart
+                    if index == 0 {
+                        let last_offset = (count - 1) * sealed_chunk_len;
+                        let mut last =
+                            Zeroizing::new(vec![0; (ciphertext_len - last_offset) as usize]);
+                        reader
+                            .get_mut()
+                            .seek(io::SeekFrom::Start(header_bytes.len() as u64 + last_offset))?;
+                        progress::read_full(reader.get_mut(), &mut last)?;
+                        chunks.seek(count - 1);
+                        if count == 1 || chunks.open(&mut last, true).is_err() {
+                            return Err(EncryptError::CorruptChunk {
+                                index,
+                                offset: file_offset,
+                            });
+                        }
+                        reader
+                            .get_mut()
+                            .seek(io::SeekFrom::Start(header_bytes.len() as u64 + len as u64))?;
+                        reader.set_limit(ciphertext_len - len as u64);
+                        // The hidden name is in the first chunk, and there is nowhere to write without it
+                        salvage &= !header.sealed_name;
+                    }
+                    chunks.seek(index + 1);
+                    match damaged.last_mut() {
+                        Some(range) if range.last_chunk + 1 == index => {
+                            range.last_chunk = index;
+                            range.len += len as u64;
+                        }
+                        _ => damaged.push(DamagedRange {
+                            first_chunk: index,
+                            last_chunk: index,
+                            offset: file_offset,
+                            len: len as u64,
+                        }),
+                    }
+                    if salvage {
+                        chunk.clear();
+                        chunk.resize(len - TAG_LEN, 0);
+                        sink(&chunk[..unpadded(index, &chunk)])?;
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+            progress(Progress {
+                stage: Stage::Streaming,
+                bytes_done: offset + len as u64,
+                bytes_total: ciphertext_len,
+            });
+            index += 1;
+        }
+        if !damaged.is_empty() {
+            return Err(EncryptError::DamagedChunks {
+                ranges: damaged,
+                salvaged: None,
+            });
+        }
+        Ok(checksum::recorded(&header, &key_bytes))
+    }
+
+    // The output of a stream at `path`: a new one, or the partial one to continue (see with_resume), buffered (see
+    // with_io_buffer)
+    fn stream_output(
+        &self,
+        path: &Path,
+        cancel: &CancellationToken,
+    ) -> Result<OutputFile, EncryptError> {
+        let output = match self.resume {
+            true => OutputFile::resume(path, self.overwrite, cancel)?,
+            false => OutputFile::create(path, self.overwrite, cancel)?,
+        };
+        Ok(output.buffered(self.io_buffer))
+    }
+
+    // Decrypt the chunked file at `file_path` as a stream, returning the path written (see decrypt_file_into)
+    fn decrypt_stream(
+        &self,
+        input: ChunkedInput,
+        file_path: &Path,
+        output_path: Option<&Path>,
+        progress: &mut dyn FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf, EncryptError> {
+        let default_path = self.decrypted_file_path(file_path);
+        let expected_name = names::file_name(&default_path).to_os_string();
+        let recorded_name = hashed_file_name(&input.header)?;
+        let resolve = |sealed_name: Option<OsString>| {
+            self.output_path(
+                file_path,
+                default_path.clone(),
+                sealed_name.or_else(|| recorded_name.clone()),
+                output_path,
+            )
+        };
+        let create = |path: &Path| self.stream_output(path, cancel);
+        let chunk_size = input.chunk_size as u64;
+        let count = stream::ciphertext_chunk_count(input.ciphertext_len, input.chunk_size)?;
+
+        // The output is created once the first plaintext is ready, since a sealed name decides where it goes
+        let mut decrypted_file_path = None;
+        let mut output = None;
+        // Where the next plaintext goes in the output, and how many bytes the sealed name takes in front of the
+        // payload. A resumed output keeps the plaintext of its complete chunks, which is checked instead of written:
+        // that of the first chunk and of the last one kept, skipping those between.
+        let mut position = 0;
+        let mut name_len = 0;
+        let mut kept = 0;
+        let mut last_kept = 0;
+        let skip_to = Cell::new(0);
+        let opened = self.open_stream(
+            input,
+            Some(&expected_name),
+            &mut |plaintext| match plaintext {
+                Plaintext::Name(name, sealed_len) => {
+                    name_len = sealed_len as u64;
+                    decrypted_file_path = Some(resolve(Some(name)));
+                    Ok(())
+                }
+                Plaintext::Bytes(bytes) => {
+                    let output = match &mut output {
+                        Some(output) => output,
+                        None => {
+                            let path = decrypted_file_path.get_or_insert_with(|| resolve(None));
+                            let output = output.insert(create(path)?);
+                            // The complete chunks, but always the last one again, which also ends the output
+                            let complete = ((output.len() + name_len) / chunk_size).min(count - 1);
+                            kept = (complete * chunk_size).saturating_sub(name_len);
+                            last_kept = complete.saturating_sub(1);
+                            if kept > 0 {
+                                log::info!(
+                                    "Resuming {} after {} of its {} chunks",
+                                    path.display(),
+                                    complete,
+                                    count
+                                );
+                            }
+                            output
+                        }
+                    };
+                    let checked = kept.saturating_sub(position).min(bytes.len() as u64) as usize;
+                    if checked > 0 {
+                        let mut written = vec![0; checked];
+                        output.read_at(position, &mut written)?;
+                        if written != bytes[..checked] {
+                            return Err(EncryptError::IoError(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "{} holds other contents than {} decrypts to; delete it to start over",
+                                    progress::partial_path(decrypted_file_path.as_deref().unwrap_or(Path::new(""))).display(),
+                                    file_path.display()
+                                ),
+                            )));
+                        }
+                    }
+                    position += checked as u64;
+                    if checked < bytes.len() {
+                        if output.len() != position {
+                            output.truncate(position)?;
+                        }
+                        output.write(&bytes[checked..], cancel)?;
+                        position += (bytes.len() - checked) as u64;
+                    }
+                    // Every piece of plaintext ends a chunk
+                    if last_kept > (position + name_len) / chunk_size {
+                        skip_to.set(last_kept);
+                        position = last_kept * chunk_size - name_len;
+                    }
+                    Ok(())
+                }
+            },
+            &skip_to,
+            progress,
+            cancel,
+        );
+        // Salvaged chunks are written out like a complete file, and reported once they are
+        let salvaged = match opened {
+            Ok(()) => None,
+            Err(EncryptError::DamagedChunks { ranges, .. })
+                if self.keep_going && output.is_some() =>
+            {
+                Some(ranges)
+            }
+            Err(err) => return Err(err),
+        };
+
+        let decrypted_file_path = decrypted_file_path.unwrap_or_else(|| resolve(None));
+        let mut output = match output {
+            Some(output) => output,
+            None => create(&decrypted_file_path)?,
+        };
+        if output.len() != position {
+            output.truncate(position)?;
+        }
+        output.commit()?;
+        match salvaged {
+            Some(ranges) => Err(EncryptError::DamagedChunks {
+                ranges,
+                salvaged: Some(decrypted_file_path),
+            }),
+            None => Ok(decrypted_file_path),
+        }
+    }
+
+    // Rekey the chunked file at `file_path` as a stream (see rekey_file_with), each chunk sealed under the new key
+    // as soon as it authenticates under the old one
+    fn rekey_stream(
+        &self,
+        mut input: ChunkedInput,
+        file_path: &Path,
+        new: &Encryptor,
+        progress: &mut dyn FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<(), EncryptError> {
+        // A wrong secret fails here, before the new key is derived and the output created. The checksum and the
+        // padding are carried over under the new key, and the padding is sealed again with the rest of the chunks
+        // instead of being cut off.
+        let key_bytes = self.key(&input.header)?;
+        let checksum = checksum::recorded(&input.header, &key_bytes);
+        let padding = input
+            .header
+            .padding
+            .map(|(scheme, _)| (scheme, padding::recorded(&input.header, &key_bytes)));
+        input.header.padding = None;
+        let chunk_size = input.chunk_size;
+        let count = stream::ciphertext_chunk_count(input.ciphertext_len, chunk_size)?;
+        let payload_len = input.ciphertext_len - count * TAG_LEN as u64;
+        let (header, header_bytes, key_bytes) = new.sealing_like(&input.header).new_header(
+            Some(payload_len),
+            (
+                input.header.file_name.as_deref(),
+                input.header.sealed_name,
+                input.header.escaped_name,
+            ),
+            None,
+            Some(chunk_size),
+            (None, checksum, padding),
+        )?;
+
+        let mut output = OutputFile::create(file_path, true, cancel)?.buffered(self.io_buffer);
+        output.write(&header_bytes, cancel)?;
+        let aad = header.associated_data(&header_bytes);
+        let mut chunks = stream::Chunks::new(header.algorithm, &key_bytes, &header.nonce, &aad);
+        let mut sealed = Vec::with_capacity(chunk_size as usize + TAG_LEN);
+        let mut index = 0;
+        // The input is closed when this returns, before the output replaces it
+        self.open_chunks(
+            input,
+            None,
+            &mut |chunk| {
+                index += 1;
+                sealed.clear();
+                sealed.extend_from_slice(chunk);
+                chunks.seal(&mut sealed, index == count)?;
+                output.write(&sealed, cancel)
+            },
+            &Cell::new(0),
+            progress,
+            cancel,
+        )?;
+        if header.header_backup {
+            output.write(&Header::backup_trailer(&header_bytes), cancel)?;
+        }
+        output.commit()
+    }
+
+    // Rekey the chunked, rewrappable file at `file_path` (see rekey_file_with): a new header with the file key
+    // wrapped for `new`, followed by the chunks copied as they are
+    fn rewrap_stream(
+        &self,
+        input: ChunkedInput,
+        file_path: &Path,
+        new: &Encryptor,
+        progress: &mut dyn FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<(), EncryptError> {
+        let ChunkedInput {
+            file,
+            header,
+            ciphertext_len,
+            ..
+        } = input;
+        let header_bytes = self.rewrapped_header(&header, new)?;
+
+        let mut output = OutputFile::create(file_path, true, cancel)?.buffered(self.io_buffer);
+        output.write(&header_bytes, cancel)?;
+        let mut reader = io::BufReader::with_capacity(self.io_buffer, file).take(ciphertext_len);
+        let mut buffer = vec![0; progress::IO_CHUNK];
+        let mut copied = 0;
+        loop {
+            let read = progress::read_full(&mut reader, &mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            output.write(&buffer[..read], cancel)?;
+            copied += read as u64;
+            progress(Progress {
+                stage: Stage::Streaming,
+                bytes_done: copied,
+                bytes_total: ciphertext_len,
+            });
+        }
+        if copied != ciphertext_len {
+            return Err(HeaderError::Truncated.into());
+        }
+        if header.header_backup {
+            output.write(&Header::backup_trailer(&header_bytes), cancel)?;
+        }
+        // Closed before the output replaces it
+        drop(reader);
+        output.commit()
+    }
+
+    // The header of a rewrappable file, with the file key unwrapped with this Encryptor's secret and wrapped again
+    // for `new`'s
+    fn rewrapped_header(&self, header: &Header, new: &Encryptor) -> Result<Vec<u8>, EncryptError> {
+        let file_key = self.key(header)?;
+        log::debug!(
+            "Rewrapping the file key of a version {} header, leaving the payload as it is",
+            header.version
+        );
+        let new = new.sealing_like(header);
+        Ok(Header {
+            recipients: new.wrap_key(&file_key)?,
+            key_id: new.key_id,
+            ..header.clone()
+        }
+        .to_bytes())
+    }
+
+    // This Encryptor, set to seal a payload like the one `header` describes: with the same cipher and compression,
+    // the metadata and header backup only if it had them, its key wrapped if it was (or this Encryptor wraps) and
+    // its key ID unless this Encryptor has one of its own
+    fn sealing_like(&self, header: &Header) -> Encryptor {
+        let mut encryptor = self.clone();
+        encryptor.algorithm = header.algorithm;
+        encryptor.compression.codec = header.compression;
+        encryptor.bind_metadata = header.payload_len.is_some();
+        encryptor.header_backup = header.header_backup;
+        encryptor.wrap_keys |= header.rewrappable;
+        encryptor.name_by_hash = header.hash_name;
+        if encryptor.key_id.is_none() {
+            encryptor.key_id = header.key_id.clone();
+        }
+        encryptor
+    }
+
+    // Where decrypting the file at `file_path` writes by default: the same path without its extension
+    // (if there's no extension, the encrypted file's name as is), or the path itself when replacing it in place
+    fn decrypted_file_path(&self, file_path: &Path) -> PathBuf {
+        match self.in_place {
+            true => file_path.to_path_buf(),
+            false => names::strip_extension(file_path).to_path_buf(),
+        }
+    }
+
+    // Where the plaintext of `file_path` goes: next to it under the name sealed with the contents (or recorded for a
+    // file named by its hash) if there was one,
+    // otherwise `decrypted_file_path`, or `output_path` if it was asked for; with with_auto_rename, the first free
+    // name like it
+    fn output_path(
+        &self,
+        file_path: &Path,
+        decrypted_file_path: PathBuf,
+        sealed_name: Option<OsString>,
+        output_path: Option<&Path>,
+    ) -> PathBuf {
+        let decrypted_file_path = match sealed_name {
+            Some(name) if !self.in_place => file_path.with_file_name(name),
+            _ => decrypted_file_path,
+        };
+        let path = match output_path {
+            Some(output_path) => into_output(output_path, &decrypted_file_path),
+            None => decrypted_file_path,
+        };
+        match self.auto_rename && !self.in_place {
+            true => unclaimed_path(&path),
+            false => path,
+        }
+    }
+
+    // The dictionary to decompress the payload described by `header` with, which must be the one it was compressed with
+    fn dictionary_for(&self, header: &Header) -> Result<Option<&Dictionary>, EncryptError> {
+        match (header.dictionary_id, &self.dictionary) {
+            (None, _) => Ok(None),
+            (Some(needed), Some(dictionary)) if dictionary.id() == needed => Ok(Some(dictionary)),
+            (Some(needed), provided) => {
+                let provided = match provided {
+                    Some(dictionary) => format!("dictionary {} was provided", dictionary.id()),
+                    None => "none was provided".to_string(),
+                };
+                Err(EncryptError::CompressionError(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "the file was compressed with zstd dictionary {}, but {}",
+                        needed, provided
+                    ),
+                )))
+            }
+        }
+    }
+}
+
+// Put the original file name in front of the contents, for with_name_encryption, as the text `names` gives
+fn seal_name(contents: &mut Vec<u8>, name: &str) {
+    let mut prefix = (name.len() as u16).to_le_bytes().to_vec();
+    prefix.extend_from_slice(name.as_bytes());
+    contents.splice(0..0, prefix);
+}
+
+// Split the sealed file name off the front of the plaintext. It was authenticated with the contents, but is still
+// checked to be a plain name, so a crafted file can't make decrypt_file write outside the encrypted file's directory.
+// An escaped name (see the header's escaped_name) is unescaped once it is.
+// The plaintext is wiped if that fails.
+fn unseal_name(plaintext: Vec<u8>, escaped: bool) -> Result<(Vec<u8>, OsString), EncryptError> {
+    let mut plaintext = Zeroizing::new(plaintext);
+    let damaged = || EncryptError::MetadataMismatch("the sealed file name is damaged".to_string());
+    let name_len = match plaintext.get(..2) {
+        Some(len) => u16::from_le_bytes([len[0], len[1]]) as usize,
+        None => return Err(damaged()),
+    };
+    let name = plaintext.get(2..2 + name_len).ok_or_else(damaged)?;
+    let name = String::from_utf8(name.to_vec()).map_err(|_| damaged())?;
+    let name = match escaped {
+        true => names::unescape(&name).ok_or_else(damaged)?,
+        false => OsString::from(name),
+    };
+    let text = name.to_string_lossy();
+    if text.contains(['/', '\\']) || matches!(&*text, "" | "." | "..") {
+        return Err(EncryptError::MetadataMismatch(format!(
+            "the sealed file name '{}' is not a plain file name",
+            text
+        )));
+    }
+    plaintext.drain(..2 + name_len);
+    Ok((std::mem::take(&mut *plaintext), name))
+}
+
+// Whether `prefix` holds the whole sealed name at the start of a payload
+fn sealed_name_complete(prefix: &[u8]) -> bool {
+    match prefix.get(..2) {
+        Some(len) => prefix.len() >= 2 + u16::from_le_bytes([len[0], len[1]]) as usize,
+        None => false,
+    }
+}
+
+// The salt of every deterministic output for `context`, so they all get the same key (see with_deterministic)
+fn deterministic_salt(context: &str) -> [u8; SALT_LEN] {
+    let digest = Blake2s256::new()
+        .chain_update(b"encryptor deterministic salt\0")
+        .chain_update(context.as_bytes())
+        .finalize();
+    digest[..SALT_LEN]
+        .try_into()
+        .expect("a digest is longer than a salt")
+}
+
+// The nonce of a deterministic output, a hash of its payload keyed with its key: the same only for the same payload
+fn deterministic_nonce(key: &[u8; KEY_LEN], payload: &[u8]) -> [u8; NONCE_LEN] {
+    let mut mac = <Blake2sMac256 as Mac>::new_from_slice(key).expect("a 32-byte key fits BLAKE2s");
+    Mac::update(&mut mac, b"encryptor deterministic nonce\0");
+    Mac::update(&mut mac, payload);
+    mac.finalize().into_bytes()[..NONCE_LEN]
+        .try_into()
+        .expect("a digest is longer than a nonce")
+}
+
+// The password check of a file sealed with `key` under `nonce` (see the header module)
+fn password_check(key: &[u8; KEY_LEN], nonce: &[u8]) -> [u8; header::PASSWORD_CHECK_LEN] {
+    let mut mac = <Blake2sMac256 as Mac>::new_from_slice(key).expect("a 32-byte key fits BLAKE2s");
+    Mac::update(&mut mac, b"encryptor password check\0");
+    Mac::update(&mut mac, nonce);
+    mac.finalize().into_bytes()[..header::PASSWORD_CHECK_LEN]
+        .try_into()
+        .expect("a digest is longer than a password check")
+}
+
+// Whether `check` is the password check of `key` and `nonce`, compared in constant time
+fn password_check_matches(key: &[u8; KEY_LEN], nonce: &[u8], check: &[u8]) -> bool {
+    let mut mac = <Blake2sMac256 as Mac>::new_from_slice(key).expect("a 32-byte key fits BLAKE2s");
+    Mac::update(&mut mac, b"encryptor password check\0");
+    Mac::update(&mut mac, nonce);
+    mac.verify_truncated_left(check).is_ok()
+}
+
+// `secs`, when the password of a file sealed with `key` under `nonce` was set, masked or unmasked (see the header
+// module)
+fn mask_password_set(secs: u64, key: &[u8; KEY_LEN], nonce: &[u8]) -> u64 {
+    let mut mac = <Blake2sMac256 as Mac>::new_from_slice(key).expect("a 32-byte key fits BLAKE2s");
+    Mac::update(&mut mac, b"encryptor password set mask\0");
+    Mac::update(&mut mac, nonce);
+    let mask = mac.finalize().into_bytes();
+    secs ^ u64::from_le_bytes(
+        mask[..8]
+            .try_into()
+            .expect("a digest is longer than 8 bytes"),
+    )
+}
+
+// What a chunked file decrypts to, piece by piece
+enum Plaintext<'a> {
+    Name(OsString, usize), // The original name, if it was sealed with the contents, and the bytes it took sealed
+    Bytes(&'a [u8]),       // The next part of the contents
+}
+
+// A file with a chunked payload, positioned at the start of its chunks
+struct ChunkedInput {
+    file: File,
+    header: Header,
+    header_bytes: Vec<u8>,
+    chunk_size: u32,
+    ciphertext_len: u64, // The chunks, without the header backup after them
+}
+
+// The start of a partial output that encrypting continues, see resumable_chunks
+struct PartialOutput {
+    header: Header,
+    header_bytes: Vec<u8>,
+    key_bytes: KeyBytes,
+    chunks: u64, // The complete chunks after the header, which are kept
+}
+
+impl ChunkedInput {
+    // The streaming counterpart of check_header_backup
+    fn check_header_backup(&mut self) -> Result<(), EncryptError> {
+        if !self.header.header_backup {
+            return Ok(());
+        }
+        let trailer = Header::backup_trailer(&self.header_bytes);
+        let start = self.header_bytes.len() as u64 + self.ciphertext_len;
+        self.file.seek(io::SeekFrom::Start(start))?;
+        let mut stored = vec![0; trailer.len()];
+        let read = progress::read_full(&mut self.file, &mut stored)?;
+        self.file
+            .seek(io::SeekFrom::Start(self.header_bytes.len() as u64))?;
+        if read != trailer.len() || stored != trailer {
+            return Err(EncryptError::MetadataMismatch(
+                "the header copy at the end of the file doesn't match the header at the start"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Open the file at `file_path` for decrypting as a stream, if it is a binary file with an uncompressed chunked
+// payload. Anything else, including a header that doesn't parse, is left to be read whole.
+fn chunked_input(file_path: &Path) -> Result<Option<ChunkedInput>, EncryptError> {
+    let mut file = File::open(file_path)?;
+    let file_len = file.metadata()?.len();
+    // Read as much of the start of the file as the header turns out to need
+    let mut start = vec![0; 4096];
+    let mut filled = progress::read_full(&mut file, &mut start)?;
+    let (header, header_len) = loop {
+        match Header::parse(&start[..filled]) {
+            Err(HeaderError::Truncated) if filled == start.len() => {
+                start.resize(start.len() * 2, 0);
+                filled += progress::read_full(&mut file, &mut start[filled..])?;
+            }
+            Ok(parsed) => break parsed,
+            Err(_) => return Ok(None),
+        }
+    };
+    let chunk_size = match header.chunk_size {
+        Some(chunk_size) if header.compression == Codec::None => chunk_size,
+        _ => return Ok(None),
+    };
+
+    let trailer_len = match header.header_backup {
+        true => header_len as u64 + 4,
+        false => 0,
+    };
+    let ciphertext_len = file_len
+        .checked_sub(header_len as u64 + trailer_len)
+        .ok_or(HeaderError::Truncated)?;
+    file.seek(io::SeekFrom::Start(header_len as u64))?;
+    start.truncate(header_len);
+    Ok(Some(ChunkedInput {
+        file,
+        header,
+        header_bytes: start,
+        chunk_size,
+        ciphertext_len,
+    }))
+}
+
+// The payload of `prefix` (a sealed name) followed by `input` and `pad` zeros of padding, read from `offset` on
+fn payload_reader<'a>(
+    prefix: &'a [u8],
+    input: &'a mut Input,
+    pad: u64,
+    offset: u64,
+) -> io::Result<impl Read + 'a> {
+    let skipped = offset.min(prefix.len() as u64);
+    let input_offset = (offset - skipped).min(input.len()?);
+    input.seek(io::SeekFrom::Start(input_offset))?;
+    let padded = offset - skipped - input_offset;
+    Ok(prefix[skipped as usize..]
+        .chain(input)
+        .chain(io::repeat(0).take(pad.saturating_sub(padded))))
+}
+
+// A random name for the output of `file_path` in the same directory, for with_name_encryption, and for
+// with_name_by_hash until the output is complete
+fn random_file_path(file_path: &Path, rng: &dyn RandomSource) -> Result<PathBuf, EncryptError> {
+    let mut random = [0u8; 16];
+    rng.fill(&mut random)?;
+    let name: String = random.iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(file_path.with_file_name(format!("{}.enc", name)))
+}
+
+// Rename the complete output at `path` to the BLAKE3 hash of its bytes, for with_name_by_hash. An output already
+// there has the same bytes, so it is kept and this one removed.
+fn rename_to_hash(path: &Path) -> Result<PathBuf, EncryptError> {
+    let unsupported = || io::Error::new(io::ErrorKind::Unsupported, "this build has no BLAKE3");
+    let mut hasher = checksum::Hasher::new().ok_or_else(unsupported)?;
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; progress::IO_CHUNK];
+    loop {
+        let read = progress::read_full(&mut file, &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    drop(file);
+    let name: String = hasher
+        .finish()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let hashed_path = path.with_file_name(format!("{}.enc", name));
+    match std::fs::symlink_metadata(&hashed_path) {
+        Ok(_) => std::fs::remove_file(path)?,
+        Err(_) => std::fs::rename(path, &hashed_path)?,
+    }
+    Ok(hashed_path)
+}
+
+// The name a file named by its hash (see with_name_by_hash) decrypts to: the one its header records. It was
+// authenticated with the contents, but is still checked to be a plain name like a sealed one.
+fn hashed_file_name(header: &Header) -> Result<Option<OsString>, EncryptError> {
+    let Some(name) = header.file_name.as_deref().filter(|_| header.hash_name) else {
+        return Ok(None);
+    };
+    let damaged =
+        || EncryptError::MetadataMismatch("the recorded file name is damaged".to_string());
+    let name = match header.escaped_name {
+        true => names::unescape(name).ok_or_else(damaged)?,
+        false => OsString::from(name),
+    };
+    let text = name.to_string_lossy();
+    if text.contains(['/', '\\']) || matches!(&*text, "" | "." | "..") {
+        return Err(EncryptError::MetadataMismatch(format!(
+            "the recorded file name '{}' is not a plain file name",
+            text
+        )));
+    }
+    Ok(Some(name))
+}
+
+// Where an output asked for with `output_path` goes: that path, or the usual name `default_path` would get
+// inside it when it is a directory
+fn into_output(output_path: &Path, default_path: &Path) -> PathBuf {
+    match output_path.is_dir() {
+        true => output_path.join(names::file_name(default_path)),
+        false => output_path.to_path_buf(),
+    }
+}
+
+// Log at debug level how the payload behind `header` is sealed, as it is about to be sealed or opened
+fn log_layout(action: &str, header: &Header) {
+    if !log::log_enabled!(log::Level::Debug) {
+        return;
+    }
+    let layout = match header.chunk_size {
+        Some(chunk_size) => format!("in chunks of {} bytes", chunk_size),
+        None => "in one piece".to_string(),
+    };
+    log::debug!(
+        "{} a version {} payload with {} (key: {}), {}",
+        action,
+        header.version,
+        header.algorithm.name(),
+        header.kdf.name(),
+        layout
+    );
+}
+
+// Check the metadata recorded in `header` before decrypting, so a renamed or truncated file gets a clear error
+// instead of a generic authentication failure. The AEAD still catches anyone who edits the header to match.
+// `file_name` is the name the plaintext is being decrypted to, compared as the header would record it.
+fn check_metadata(
+    header: &Header,
+    file_name: Option<&OsStr>,
+    ciphertext_len: u64,
+) -> Result<(), EncryptError> {
+    // A file named by its hash decrypts to the name it records instead
+    if let (Some(recorded), Some(actual), false) = (&header.file_name, file_name, header.hash_name)
+    {
+        let (actual, escaped) = names::record(actual);
+        if *recorded != actual || header.escaped_name != escaped {
+            return Err(EncryptError::MetadataMismatch(format!(
+                "the file was encrypted as '{}' but is being decrypted as '{}' (was it renamed?)",
+                recorded, actual
+            )));
+        }
+    }
+    check_ciphertext_len(header.ciphertext_len(), ciphertext_len)?;
+    // Without a recorded length, a payload sealed in one piece still ends with its tag. Catching a shorter one here
+    // reports the truncation before the key is derived, instead of as a failed authentication after it.
+    if header.chunk_size.is_none() && ciphertext_len < TAG_LEN as u64 {
+        return Err(EncryptError::MetadataMismatch(format!(
+            "{} bytes of ciphertext can't hold the {}-byte authentication tag (the file was truncated)",
+            ciphertext_len, TAG_LEN
+        )));
+    }
+    Ok(())
+}
+
+// The ciphertext must be exactly as long as the payload length recorded in the header says, if it has one
+fn check_ciphertext_len(expected: Option<u64>, ciphertext_len: u64) -> Result<(), EncryptError> {
+    if let Some(expected) = expected {
+        if ciphertext_len != expected {
+            return Err(EncryptError::MetadataMismatch(format!(
+                "expected {} bytes of ciphertext but found {} (the file was truncated or extended)",
+                expected, ciphertext_len
+            )));
+        }
+    }
+    Ok(())
+}
+
+// Function to report the processing stage, which happens in one step between reading and writing
+fn report_processing(
+    progress: &mut (impl FnMut(Progress) + ?Sized),
+    bytes_done: u64,
+    bytes_total: u64,
+) {
+    progress(Progress {
+        stage: Stage::Processing,
+        bytes_done,
+        bytes_total,
+    });
+}
+
+// The header copy at the end of a file with a header backup must be byte-for-byte the header at the start.
+// Decryption itself only needs the first one, so this is checked by verify_file_with alone.
+fn check_header_backup(contents: &[u8]) -> Result<(), EncryptError> {
+    let (header, header_len) = Header::parse(contents)?;
+    if !header.header_backup {
+        return Ok(());
+    }
+    let trailer = Header::backup_trailer(&contents[..header_len]);
+    if contents.len() < header_len + trailer.len() || !contents.ends_with(&trailer) {
+        return Err(EncryptError::MetadataMismatch(
+            "the header copy at the end of the file doesn't match the header at the start"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The first of `path`, `name (1).ext`, `name (2).ext` and on in the same directory that nothing is at yet, where
+/// [`Encryptor::with_auto_rename`] writes an output. The number goes before the last extension of the file name:
+/// `report.pdf.enc` becomes `report.pdf (1).enc`.
+pub fn unclaimed_path(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    if std::fs::symlink_metadata(path).is_err() {
+        return path.to_path_buf();
+    }
+    let (stem, extension) = names::split_extension(names::file_name(path));
+    (1u64..)
+        .map(|number| {
+            let mut name = stem.to_os_string();
+            name.push(format!(" ({})", number));
+            name.push(extension);
+            path.with_file_name(name)
+        })
+        .find(|candidate| std::fs::symlink_metadata(candidate).is_err())
+        .expect("a number is free")
+}
+
+/// Fill `dest` with random bytes from the crypto backend's secure random generator
+pub fn fill_random(dest: &mut [u8]) -> Result<(), EncryptError> {
+    Ok(cipher::fill_random(dest)?)
+}
+
+/// Generate a random 256-bit key for [`Encryptor::from_key`], using the crypto backend's secure random generator
+pub fn generate_key() -> Result<[u8; 32], EncryptError> {
+    let mut key = [0u8; KEY_LEN];
+    cipher::fill_random(&mut key)?;
+    Ok(key)
+}
+
+// Lock a password or key into RAM when hardening is enabled (see the harden module)
+#[cfg_attr(not(feature = "harden"), allow(unused_variables))]
+pub(crate) fn protect(secret: &[u8]) {
+    #[cfg(feature = "harden")]
+    harden::protect(secret);
+}
+
+// The key of one file of a batch (see Kdf::Argon2idHkdf): HKDF-SHA256 of the batch's master key under the file's
+// own salt, bound to the header's key context like the master key
+#[cfg(feature = "batch-key")]
+fn batch_file_key(master: &[u8], salt: &[u8], context: &[u8]) -> Result<KeyBytes, EncryptError> {
+    let mut info = b"encryptor batch file key".to_vec();
+    info.extend_from_slice(context);
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    hkdf::Hkdf::<sha2::Sha256>::new(Some(salt), master)
+        .expand(&info, &mut *key)
+        .map_err(|_| EncryptError::AeadError(CryptoError))?;
+    Ok(key)
+}
+
+#[cfg(not(feature = "batch-key"))]
+fn batch_file_key(_: &[u8], _: &[u8], _: &[u8]) -> Result<KeyBytes, EncryptError> {
+    Err(EncryptError::KeyModeMismatch(
+        "the file's key was derived for a batch of files, which this build can't do (enable the 'batch-key' feature)"
+            .to_string(),
+    ))
+}
+
+// Function to derive an AES-256 key from a password of any length using Argon2id.
+// The salt makes the same password produce a different key for every file, and the memory and
+// iteration costs make each guess expensive for anyone trying to brute-force the password offline.
+pub(crate) fn derive_key(
+    password: &str,
+    salt: &[u8],
+    params: KdfParams,
+    context: &[u8],
+) -> Result<[u8; KEY_LEN], EncryptError> {
+    let params = argon2::ParamsBuilder::new()
+        .m_cost(params.memory)
+        .t_cost(params.iterations)
+        .p_cost(1)
+        .output_len(KEY_LEN)
+        .data(argon2::AssociatedData::new(context)?)
+        .build()?;
+    let argon2 = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+
+    let mut key = [0u8; KEY_LEN];
+    argon2.hash_password_into(password.as_bytes(), salt, &mut key)?;
+    Ok(key)
+}
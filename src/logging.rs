@@ -0,0 +1,76 @@
+// Log messages for -v, -vv and --quiet, written to stderr next to the status lines:
+//
+//   info   Encryption of big.iso started
+//   debug  Sealing a version 3 payload with aes256gcm (key: argon2id), in chunks of 1048576 bytes
+//   debug  big.iso: 40%
+//
+// Without a flag only warnings are logged, -v adds what happens to each file, -vv the KDF parameters, progress and
+// timing of each file, and -vvv everything. --quiet logs nothing. Only the messages of this crate are shown,
+// not those of its dependencies. A --debug-transcript gets every message up to debug level, whatever is shown.
+use crate::{progress_bar, redact, run_report, transcript};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::sync::OnceLock;
+
+struct StderrLogger;
+
+static LOGGER: StderrLogger = StderrLogger;
+
+// The most detailed level shown on stderr
+static STDERR_LEVEL: OnceLock<LevelFilter> = OnceLock::new();
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level() && metadata.target().starts_with("encryptor")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if record.level() <= Level::Debug {
+            transcript::log(record);
+        }
+        if record.level() <= Level::Warn {
+            run_report::warning(&record.args().to_string());
+        }
+        if STDERR_LEVEL
+            .get()
+            .is_none_or(|level| record.level() > *level)
+        {
+            return;
+        }
+        // Labels as wide as the status lines' "failed"
+        let label = match record.level() {
+            Level::Error => "error ",
+            Level::Warn => "warn  ",
+            Level::Info => "info  ",
+            Level::Debug => "debug ",
+            Level::Trace => "trace ",
+        };
+        let message = record.args().to_string();
+        let message = redact::text(&message);
+        progress_bar::suspend(|| eprintln!("{} {}", label, message));
+    }
+
+    fn flush(&self) {}
+}
+
+// Log at the level picked by the number of -v flags, or nothing at all with --quiet. The transcript must already
+// be started, if there is one.
+pub fn init(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Off,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    let recorded = match transcript::active() {
+        true => LevelFilter::Debug,
+        false => LevelFilter::Off,
+    };
+    // Only fails when a logger is already set, and main sets it once
+    if log::set_logger(&LOGGER).is_ok() && STDERR_LEVEL.set(level).is_ok() {
+        log::set_max_level(level.max(recorded));
+    }
+}
@@ -1,106 +1,603 @@
 // Import the necessary modules and packages
-use ring::aead; // The 'ring' crate provides cryptographic operations
-use ring::error::Unspecified; // This is a type for unspecified errors from the 'ring' crate
-use serde_json; // This crate is used for serializing and deserializing JSON data
-use std::env; // This module provides access to the process's environment
-use std::fs::File; // This module provides a way to work with the file system
-use std::io::{self, Read, Write}; // This module provides a way to perform input/output operations
-
-// Define an enumeration for possible encryption errors
-#[derive(Debug)]
-enum EncryptError {
-    IoError(io::Error),     // An I/O error
-    AeadError(Unspecified), // An error from the AEAD (Authenticated Encryption with Associated Data) operation
-}
+mod ads; // NTFS alternate data streams, packed by `encrypt --archive --ads` and restored by --extract
+mod age_file; // Files in the age format, for --format age and decrypting age files
+mod archive; // Whole directory trees as one encrypted file, for --archive and --extract
+mod audit; // The tamper-evident --audit-log of what was done to each file, and the `audit verify` command
+mod batch; // Runs an operation over many files on a pool of worker threads
+mod bench; // The `bench` command: performance baselines and compression comparisons
+mod bundle_file; // Team key bundles: the `bundle create|import` commands and --team
+mod cli; // The command line definition: subcommands, typed flags and the generated help
+mod cms_file; // Files in CMS EnvelopedData form, for --format cms and decrypting CMS files
+mod config; // Default settings from config.toml and the environment, below the flags given
+mod daemon; // The `daemon` that holds a key for a while, and the `client` that has it encrypt and decrypt files
+mod dictionary; // Training and loading encrypted zstd dictionaries
+mod doctor; // The `doctor` command, a guided diagnosis of files that won't decrypt
+mod dry_run; // What `encrypt --dry-run` and `decrypt --dry-run` would do, printed instead of done
+mod events; // Newline-delimited JSON progress events for --progress-fd
+mod fido2_device; // FIDO2 security keys over USB for --fido2 and `keygen --fido2` (with the fido2 feature)
+mod git_filter; // The `git-filter` command, encrypting files committed to git through a clean/smudge filter
+mod grant_file; // Decryption grants: the `grant` command, `keygen --signing` and `decrypt --grant`
+mod header_json; // The `header export|graft` commands
+mod http; // http:// and https:// URLs for `encrypt --out`, `decrypt` and `verify` (with the http feature)
+mod incremental; // The state of `encrypt --incremental`, which backs up only the files changed since the last run
+mod interrupt; // Ctrl-C and termination signals, which cancel the files being worked on instead of killing them
+mod journal; // The transaction journal of `encrypt --shred` batches, and the `resume-journal` command
+mod jwe_file; // Files in JSON Web Encryption form, for --format jwe|jwe-json and decrypting JWE files
+mod keychain; // Passwords stored in the platform keychain for --use-keyring
+mod keyfile; // Raw keys and X25519 keys for --keyfile, --identity, --recipient and the `keygen` command
+mod legacy_file; // Headerless files of the first releases, for `decrypt --legacy` and the `migrate` command
+mod logging; // Log messages on stderr for -v, -vv and --quiet
+mod manifest; // The --manifest of what a batch or archive encrypted, and checking a restored tree against it
+mod mnemonic; // Keys as 24 words for paper backups, for `keygen --mnemonic` and the `key` command
+mod mount; // The `mount` command, browsing an archive as a read-only filesystem (with the fuse feature)
+mod openssl_file; // Files in the format of `openssl enc`, for --format openssl and decrypting such files
+mod output; // Terminal-aware status output (colors, NO_COLOR support)
+mod password; // Password sources: hidden prompt, file, environment variable or file descriptor
+mod path_filter; // The --exclude, --include and --respect-gitignore filters of the directory trees walked
+mod progress_bar; // Progress bars with the speed and time left on a terminal
+mod qr; // QR codes of a short encrypted text for `encrypt-text --qr` (with the qr feature)
+mod recover; // The `recover` command for files with damaged headers
+mod recovery_code; // Recovery codes that decrypt files when their password is forgotten, for --recovery-code
+mod redact; // Paths shown as salted hashes for --redact-paths
+mod report; // One JSON object per file on stdout for --json
+mod rotate; // Finding the files `rotate` rekeys, by the key ID in their headers
+mod run_report; // The --report summary of a batch run, as HTML or JSON
+mod s3; // s3://bucket/key paths for `encrypt --out`, `decrypt` and `verify` (with the s3 feature)
+mod share_file; // Key shares for `encrypt --shares` and `decrypt --share`
+mod shred; // Overwriting and removing originals for `encrypt --shred`
+mod ssh_file; // SSH Ed25519 keys converted to X25519, for --recipient-ssh and --identity ~/.ssh/id_ed25519
+mod stale_keys; // The `report stale-keys` command, and the warnings about passwords set too long ago
+mod stats; // Opt-in usage statistics kept on this machine, and the `stats` command that summarizes them
+mod streamed; // Decrypting streams that aren't one local file, for s3:// objects, http(s):// URLs and split volumes
+mod strength; // Password strength estimates for new passwords, with --min-strength
+mod text; // The `encrypt-text` and `decrypt-text` commands, for short secrets printed or on the clipboard
+mod transcript; // The --debug-transcript record of a run, with secrets scrubbed
+mod undo; // Moving originals to the trash for `encrypt --remove-to-trash`, and putting them back with `undo`
+mod units; // Sizes, durations and times shown for people (--human) or for scripts (--raw)
+mod vault; // The `vault` commands, named secrets kept together in one encrypted file
+mod volumes; // Outputs split into numbered volumes for --split-size, and decrypting them as one file
+mod watch; // The `watch` command, encrypting the files that appear or change in a drop folder
+
+use clap::{CommandFactory, FromArgMatches};
+use cli::{
+    AuditCommand, BundleCommand, Cli, ClientCommand, Command, FileCommand, HeaderCommand,
+    KeyCommand, KeyringCommand, StatsCommand,
+};
+use encryptor::harden;
+use encryptor::recipient::{Identity, Recipient};
+use encryptor::{
+    encoding, Algorithm, CancellationToken, Codec, Compression, Encoder, EncryptError, Encryptor,
+    ErrorCode, KdfParams, NonceLog, Padding, RetryPolicy,
+}; // The library this binary is a thin wrapper around
+use events::ProgressSink;
+use journal::Step;
+use manifest::Manifest;
+use output::{ColorChoice, Printer};
+use password::Source;
+use path_filter::PathFilter;
+use std::cell::Cell; // How far the progress of a file was logged
+use std::io::{self, IsTerminal}; // Progress bars are only drawn when stdout is a terminal
+use std::path::{Path, PathBuf}; // The files given, and where the local config is
+use std::process; // Used to exit with a code describing the failure
+use std::sync::{Arc, Mutex}; // Lets the worker threads share the progress sink and the encoder
+use std::time::{Duration, Instant}; // The --kdf-target unlock time, and how long each file took
+use zeroize::Zeroizing; // Wipes the password and key from memory when they are dropped
+
+// Exit code for failures that happen before the library is called. Usage errors exit with 2 (from clap),
+// and library failures with their ErrorCode number (3 and up).
+const EXIT_FAILURE: i32 = 1;
 
-// Implement the From trait for io::Error to allow for easy conversion to EncryptError
-impl From<io::Error> for EncryptError {
-    fn from(error: io::Error) -> Self {
-        EncryptError::IoError(error)
+// The main function where the program starts execution
+fn main() {
+    // Parse the command line. Usage errors, --help and --version end the process right here.
+    let matches = Cli::command().get_matches_from(cli::legacy_shim(std::env::args_os().collect()));
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    // What the command line leaves out comes from the environment, then the config file
+    let defaults = config::load(cli.config.as_deref());
+    // ENCRYPTOR_REDACT_PATHS or redact-paths in the config make it the default, for machines where file names must
+    // never be shown. Even a config that doesn't load leaves the variable in effect.
+    let redact_paths = cli.redact_paths
+        || match &defaults {
+            Ok(defaults) => defaults.redact_paths(),
+            Err(_) => env_flag("ENCRYPTOR_REDACT_PATHS"),
+        };
+    if redact_paths {
+        if let Err(message) = redact::enable() {
+            eprintln!("{}", message);
+            process::exit(EXIT_FAILURE);
+        }
+    }
+    if let Some(path) = &cli.debug_transcript {
+        transcript::start(path, matches.subcommand_name().unwrap_or_default());
+    }
+    logging::init(cli.verbose, cli.quiet);
+    units::set_raw(cli.raw);
+    // ENCRYPTOR_HARDEN likewise, for shared hosts, and the daemon always runs hardened since it holds its key for long.
+    // Whatever the platform can't do is skipped with a warning.
+    if cli.harden || env_flag("ENCRYPTOR_HARDEN") || matches!(cli.command, Command::Daemon(_)) {
+        let hardening = harden::enable();
+        let missing = [
+            (hardening.memory_locked, "secrets can't be locked into RAM"),
+            (
+                hardening.core_dumps_disabled,
+                "core dumps can't be turned off",
+            ),
+        ];
+        for (_, message) in missing.iter().filter(|(done, _)| !done) {
+            log::warn!("--harden: {}", message);
+        }
     }
+    run(cli, &matches, defaults);
+    stats::finish(0);
+    run_report::finish(0);
+    transcript::finish(0);
 }
 
-// Implement the From trait for Unspecified to allow for easy conversion to EncryptError
-impl From<Unspecified> for EncryptError {
-    fn from(error: Unspecified) -> Self {
-        EncryptError::AeadError(error)
-    }
+// Whether the environment variable `name` is set to something other than "" or "0"
+fn env_flag(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|value| !matches!(value.as_str(), "" | "0"))
 }
 
-// Implement the Display trait for EncryptError to allow for easy printing of the error
-impl std::fmt::Display for EncryptError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            EncryptError::IoError(err) => write!(f, "IO error: {}", err),
-            EncryptError::AeadError(err) => write!(f, "AEAD error: {}", err),
+// End the process with `code`, writing the usage history, the report and the debug transcript first
+fn exit(code: i32) -> ! {
+    stats::finish(code);
+    run_report::finish(code);
+    transcript::finish(code);
+    process::exit(code)
+}
+
+// Run the command, exiting with the code of the failure if it fails
+fn run(cli: Cli, matches: &clap::ArgMatches, defaults: Result<config::Defaults, String>) {
+    // The commands that don't take a password or work through files in batches are run on their own
+    let FileCommand {
+        name: command,
+        files,
+        mut options,
+    } = match cli.command.into_file_command() {
+        Ok(file_command) => file_command,
+        // `client` reports each file like the file commands do, and exits with the code of the first that failed
+        Err(command) if matches!(*command, Command::Client { .. }) => {
+            let Command::Client { command } = *command else {
+                unreachable!("matched above");
+            };
+            let mut printer = Printer::new(cli.color);
+            if cli.quiet {
+                printer = printer.quiet();
+            }
+            run_client(command, &printer);
+            return;
+        }
+        Err(command) => {
+            if let Err(message) = run_standalone(*command) {
+                transcript::record("failure", serde_json::json!({ "message": message }));
+                eprintln!("{}", redact::text(&message));
+                exit(EXIT_FAILURE);
+            }
+            return;
+        }
+    };
+    options.color = cli.color;
+    options.quiet = cli.quiet;
+    // A bad config only fails the commands that take its settings
+    match defaults {
+        Ok(defaults) => {
+            let (_, mut command_matches) = matches.subcommand().expect("a subcommand is required");
+            // `vault` takes its flags after its own subcommand
+            while let Some((_, nested)) = command_matches.subcommand() {
+                command_matches = nested;
+            }
+            defaults.apply(command_matches, &mut options);
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            exit(EXIT_FAILURE);
         }
     }
-}
+    // A glob such as `backup.enc.*` names every volume of a split file, which is decrypted once from the first
+    let files = match command {
+        "decrypt" | "verify" => volumes::without_later_volumes(files),
+        _ => files,
+    };
+    let files = &files[..];
+    // Whatever is shown about these paths is redacted with --redact-paths
+    files.iter().chain(&options.out).for_each(redact::path);
+    [
+        &options.manifest,
+        &options.journal,
+        &options.grant,
+        &options.dict_path,
+    ]
+    .into_iter()
+    .flatten()
+    .for_each(redact::path);
+    options.incremental.iter().for_each(redact::path);
 
-// Implement the Error trait for EncryptError to allow for easy error handling
-impl std::error::Error for EncryptError {}
+    // Certificates only encrypt and decrypt CMS files, and CMS files are only encrypted to certificates.
+    // Checked before the password prompt, which a CMS command never needs.
+    let certificates = !options.certificates.is_empty() || options.private_key.is_some();
+    let cms_encrypt = command == "encrypt" && options.format == Format::Cms;
+    if certificates
+        && (!(cms_encrypt || command == "decrypt") || options.archive || options.extract)
+    {
+        cli::usage_error(
+            command,
+            "--recipient-cert and --private-key only work with `encrypt --format cms` and `decrypt`",
+        );
+    }
+    if cms_encrypt && (options.certificates.is_empty() || options.private_key.is_some()) {
+        cli::usage_error(
+            command,
+            "--format cms encrypts to the certificates given with --recipient-cert (and no --private-key)",
+        );
+    }
 
-// The main function where the program starts execution
-fn main() {
-    // Collect the command line arguments into a vector
-    let args: Vec<String> = env::args().collect();
-    // Check if the correct number of arguments are provided
-    if args.len() < 4 {
-        println!("Usage: encryptor <encrypt|decrypt> <password> <file>");
+    // OpenSSL enc files are the only ones with PBKDF2 iterations to choose
+    if command == "encrypt"
+        && options.pbkdf2_iterations.is_some()
+        && options.format != Format::Openssl
+    {
+        cli::usage_error(command, "--pbkdf2-iter only works with --format openssl");
+    }
+
+    // Only the directory trees --archive packs and --incremental backs up (and rotate searches) are filtered
+    if options.filter.is_active()
+        && command == "encrypt"
+        && !options.archive
+        && options.incremental.is_none()
+    {
+        cli::usage_error(
+            command,
+            "--exclude, --include and --respect-gitignore filter the directory trees of --archive and --incremental",
+        );
+    }
+
+    // A grant only decrypts native files, with the identity of the operator it was minted for (checked by clap)
+    let granted = options.grant.is_some();
+
+    // Only our own format keeps its name when replaced in place, hides names, records its output's name,
+    // is verified before shredding and packs archives. How these flags combine otherwise is checked by clap.
+    if options.format != Format::Native {
+        let native_only = [
+            ("--in-place", options.in_place),
+            ("--out", options.out.is_some()),
+            ("--shred", options.shred),
+            ("--encrypt-names", options.encrypt_names),
+            ("--name-by-hash", options.name_by_hash),
+            ("--wrap-key", options.wrap_keys),
+            ("--key-id", options.key_id.is_some()),
+            ("--pad", options.padding.is_some()),
+            ("--archive", options.archive),
+            ("--split-size", options.split_size.is_some()),
+            ("--recovery-code", options.recovery_code),
+            ("--incremental", options.incremental.is_some()),
+        ];
+        if let Some((flag, _)) = native_only.iter().find(|(_, given)| *given) {
+            cli::usage_error(
+                command,
+                &format!(
+                    "{} can't be combined with --format {}",
+                    flag,
+                    options.format.name()
+                ),
+            );
+        }
+    }
+
+    // S3 objects and URLs are uploaded and downloaded through the streaming adapters, which write chunks and nothing
+    // else
+    let remote_out = options.out.as_deref().is_some_and(streamed::is_remote);
+    let remote_files = files.iter().any(|file_path| streamed::is_remote(file_path));
+    if remote_out && command != "encrypt" {
+        cli::usage_error(
+            command,
+            "an s3:// or http(s):// --out only works with `encrypt`; objects are decrypted to local paths",
+        );
+    }
+    if remote_files && !matches!(command, "decrypt" | "verify") {
+        cli::usage_error(
+            command,
+            "s3:// and http(s):// files only work with `decrypt` and `verify`",
+        );
+    }
+    if remote_out || remote_files {
+        let local_only = [
+            ("--in-place", options.in_place),
+            ("--archive", options.archive),
+            ("--extract", options.extract),
+            ("--shred", options.shred),
+            ("--remove-to-trash", options.remove_to_trash),
+            ("--resume", options.resume),
+            ("--encrypt-names", options.encrypt_names),
+            ("--name-by-hash", options.name_by_hash),
+            ("--pad", options.padding.is_some()),
+            (
+                "compression (--compress or --dict)",
+                options.compression.codec != Codec::None || options.dict_path.is_some(),
+            ),
+            ("--armor or --encoding", options.encoder.name() != "raw"),
+            ("--manifest", options.manifest.is_some()),
+            ("--grant", options.grant.is_some()),
+            ("--split-size", options.split_size.is_some()),
+            ("--range", options.range.is_some()),
+            ("--keep-going", options.keep_going),
+            ("--legacy", options.legacy_nonce.is_some()),
+            ("--incremental", options.incremental.is_some()),
+        ];
+        if let Some((flag, _)) = local_only.iter().find(|(_, given)| *given) {
+            cli::usage_error(
+                command,
+                &format!("{} can't be combined with s3:// or http(s):// paths", flag),
+            );
+        }
+    }
+    if options.storage_class.is_some() && !options.out.as_deref().is_some_and(s3::is_uri) {
+        cli::usage_error(command, "--storage-class only applies to an s3:// --out");
+    }
+    // So are split volumes, one after the other
+    let split_files = command == "decrypt"
+        && files
+            .iter()
+            .any(|file_path| volumes::split_path(file_path).is_some());
+    if options.split_size.is_some() || split_files {
+        let whole_only = [
+            ("--in-place", options.in_place),
+            ("--extract", options.extract),
+            ("--resume", options.resume),
+            ("--grant", options.grant.is_some()),
+            (
+                "compression (--compress or --dict)",
+                options.compression.codec != Codec::None || options.dict_path.is_some(),
+            ),
+            ("--pad", options.padding.is_some()),
+            ("--armor or --encoding", options.encoder.name() != "raw"),
+            ("--range", options.range.is_some()),
+            ("--keep-going", options.keep_going),
+            ("--legacy", options.legacy_nonce.is_some()),
+        ];
+        if let Some((flag, _)) = whole_only.iter().find(|(_, given)| *given) {
+            cli::usage_error(
+                command,
+                &format!(
+                    "{} can't be combined with volumes written with --split-size",
+                    flag
+                ),
+            );
+        }
+    }
+
+    // `rotate` rekeys only the encrypted files under its paths that aren't recorded under the new key ID yet,
+    // and doesn't ask for a password when there are none
+    let selected;
+    let files = match (command, &options.key_id) {
+        ("rotate", Some(key_id)) => {
+            selected = rotate::select(
+                files,
+                key_id,
+                options.rotate_from.as_deref(),
+                &options.filter,
+            );
+            if selected.is_empty() {
+                if !options.quiet {
+                    Printer::new(options.color).ok(&format!(
+                        "Nothing to rotate: no encrypted file under another key ID than {}",
+                        key_id
+                    ));
+                }
+                return;
+            }
+            &selected[..]
+        }
+        _ => files,
+    };
+
+    // --dry-run prints what the run would do and stops before a password is asked for or anything is written
+    if options.dry_run {
+        if options.archive || options.extract {
+            cli::usage_error(
+                command,
+                "--dry-run plans files one by one, and can't be combined with --archive or --extract",
+            );
+        }
+        let mut printer = Printer::new(options.color);
+        if options.quiet {
+            printer = printer.quiet();
+        }
+        if !dry_run::run(command, files, &options, &printer) {
+            exit(EXIT_FAILURE);
+        }
         return;
     }
 
-    // @function: Extract the command, password, file path, and nonce from the arguments
-    // Note the these variables below are being assigned borrowed references to the strings or slices of strings in the vector which is returned from
-    // env::args().collect() which in turn is owned by the args variable. We can therefore call the vector ie. the Vec<String> the args vector.
-    // But it's good to know how it becomes that anyway.
+    // The preflight: a run whose outputs can't fit stops here, before a password is asked for, rather than when the
+    // disk fills up halfway through it
+    if matches!(command, "encrypt" | "decrypt")
+        && !options.archive
+        && !options.extract
+        && !options.no_preflight
+    {
+        if let Err(message) = dry_run::preflight(command, files, &options) {
+            Printer::new(options.color).failed(&message);
+            exit(ErrorCode::Io.number());
+        }
+    }
+
+    // Load the keys, or read the password from the chosen source. When it's typed at the hidden prompt,
+    // commands that create encrypted output ask twice, since a typo would make the output impossible to decrypt.
+    // Recipients given alongside a password can decrypt the output as well as the password.
+    let confirm = matches!(
+        command,
+        "encrypt" | "encrypt-text" | "vault init" | "daemon" | "train-dict" | "watch"
+    ) || (command == "resume-journal" && !options.rollback);
+    let asking_for = match command {
+        "rekey" | "rotate" => "Current password",
+        "decrypt" if options.recovery_code => "Recovery code",
+        _ => "Password",
+    };
+    // A team installed with `bundle import` gives the key, unlocked with --identity, and the policy for new files
+    let team = options.team.as_ref().map(|name| {
+        let team = options
+            .identity
+            .as_deref()
+            .ok_or_else(|| "--team needs --identity".to_string())
+            .and_then(keyfile::load_identity)
+            .inspect(|identity| transcript::secret_key(identity))
+            .and_then(|identity| bundle_file::load_team(name, Identity::from_bytes(*identity)));
+        team.unwrap_or_else(|message| {
+            eprintln!("{}", message);
+            exit(EXIT_FAILURE);
+        })
+    });
+    if let Some(team) = &team {
+        transcript::secret_key(&team.key);
+        team.policy.apply(&mut options);
+    }
+    let recipients = options
+        .recipients
+        .iter()
+        .map(|value| keyfile::load_recipient(value).map(Recipient::from_bytes))
+        .chain(
+            options
+                .ssh_recipients
+                .iter()
+                .map(|value| ssh_file::recipient(value)),
+        )
+        .collect::<Result<Vec<_>, _>>();
+    let read_password = |source| {
+        password::read_as(asking_for, source, confirm)
+            .and_then(
+                |password| match options.recovery_code && command == "decrypt" {
+                    true => recovery_code::normalize(&password),
+                    false => Ok(password),
+                },
+            )
+            .inspect(|password| {
+                transcript::secret(password);
+                harden::protect(password.as_bytes());
+            })
+    };
+    let password_source = options.password.take();
+    let prompted = password::prompts(password_source.as_ref());
+    // `encrypt --shares` writes the shares of a new key before anything is encrypted with it
+    let mut share_paths = Vec::new();
+    let secret = recipients.and_then(|recipients| {
+        if !options.certificates.is_empty() || options.private_key.is_some() {
+            cms_file::load(&options.certificates, options.private_key.as_deref())
+                .map(|keys| Secret::Certificates(Box::new(keys)))
+        } else if let (Some(path), Some(identity)) = (&options.grant, &options.identity) {
+            let issuers = options
+                .grant_issuers
+                .iter()
+                .map(|value| keyfile::load_recipient(value))
+                .collect::<Result<Vec<_>, _>>()?;
+            let identity = keyfile::load_identity(identity)?;
+            transcript::secret_key(&identity);
+            let identity = Identity::from_bytes(*identity);
+            grant_file::load(path, &issuers, identity, options.grant_log.as_deref())
+                .map(|grants| Secret::Grants(Box::new(grants)))
+        } else if let Some(path) = &options.keyfile {
+            keyfile::load(path)
+                .inspect(|key| transcript::secret_key(key))
+                .map(Secret::Key)
+        } else if let Some(team) = &team {
+            Ok(Secret::Key(team.key.clone()))
+        } else if let Some(path) = &options.fido2 {
+            fido2_device::load(path).map(|encryptor| Secret::Fido2(Box::new(encryptor)))
+        } else if let Some((count, threshold)) = options.shares {
+            let prefix = options.share_out.as_deref().map_or(&*files[0], Path::new);
+            share_file::split(prefix, threshold, count).map(|(key, paths)| {
+                transcript::secret_key(&key);
+                share_paths = paths;
+                Secret::Key(key)
+            })
+        } else if !options.share_files.is_empty() {
+            share_file::combine(&options.share_files)
+                .inspect(|key| transcript::secret_key(key))
+                .map(Secret::Key)
+        } else if let Some(path) = &options.identity {
+            keyfile::load_identity(path)
+                .inspect(|key| transcript::secret_key(key))
+                .map(|key| Secret::Identity(Identity::from_bytes(*key)))
+        } else if password_source.is_none() && !recipients.is_empty() {
+            Ok(Secret::Recipients(recipients))
+        } else {
+            read_password(password_source).map(|password| Secret::Password(password, recipients))
+        }
+    });
+    let mut secret = match secret {
+        Ok(secret) => secret,
+        Err(message) => {
+            eprintln!("{}", message);
+            exit(EXIT_FAILURE);
+        }
+    };
 
-    let command = &args[1];
-    let password = &args[2];
-    let file_path = &args[3];
-    let nonce_str = &args[4];
+    // A password typed at the prompt is checked against the first file before anything is decrypted, and asked for
+    // again while it fails the file's password check, up to --password-attempts times in all. The Encryptor that
+    // passed keeps the key it derived for the files decrypted with it. With --try-typos, each wrong password is tried
+    // with its common typos before asking again, each costing a key derivation like any other attempt.
+    let mut checked = None;
+    if command == "decrypt" && prompted {
+        let first_file = files.iter().find(|file| file.is_file());
+        for attempt in 1..=options.password_attempts {
+            let Secret::Password(password, recipients) = &secret else {
+                break;
+            };
+            let Some(file) = first_file else {
+                break;
+            };
+            let encryptor = secret.encryptor();
+            let result = encryptor.check_password(file);
+            if matches!(result, Err(EncryptError::WrongPassword)) && options.try_typos {
+                if let Some((typo, found, encryptor)) = typo_of(password, recipients, file) {
+                    eprintln!(
+                        "The password as typed doesn't open {}, but it does {}; decrypting with that",
+                        file.display(),
+                        typo
+                    );
+                    secret = found;
+                    checked = Some(encryptor);
+                    break;
+                }
+            }
+            match result {
+                Err(err @ EncryptError::WrongPassword) if attempt < options.password_attempts => {
+                    eprintln!("{}, try again", err);
+                    let recipients = recipients.clone();
+                    secret = match read_password(Some(password::Source::Prompt)) {
+                        Ok(password) => Secret::Password(password, recipients),
+                        Err(message) => {
+                            eprintln!("{}", message);
+                            exit(EXIT_FAILURE);
+                        }
+                    };
+                }
+                // Anything else is reported by the file's own decryption
+                _ => {
+                    checked = Some(encryptor);
+                    break;
+                }
+            }
+        }
+    }
 
-    // @dev: Efe
-    // Parse the nonce string into a vector of bytes.
-    // This is done to allow for easy deserialization of the nonce which is a vector of bytes sent in as a string
-    // passed by the user as the fourth command line argument.
-    // So, we go from a string of JSON text representing 12 bytes visually and then back to a vector of bytes again
-    let nonce: Vec<u8> = serde_json::from_str(nonce_str)
-        .map_err(|e| {
-            println!("Error parsing nonce: {}", e);
-            EncryptError::AeadError(Unspecified)
+    // `rekey` seals every file again under a second password or key, asked for twice at the prompt
+    let new_secret = matches!(command, "rekey" | "rotate").then(|| {
+        let new_secret = match &options.new_keyfile {
+            Some(path) => keyfile::load(path)
+                .inspect(|key| transcript::secret_key(key))
+                .map(Secret::Key),
+            None => password::read_as("New password", options.new_password.take(), true)
+                .inspect(|password| {
+                    transcript::secret(password);
+                    harden::protect(password.as_bytes());
+                })
+                .map(|password| Secret::Password(password, Vec::new())),
+        };
+        new_secret.unwrap_or_else(|message| {
+            eprintln!("{}", message);
+            exit(EXIT_FAILURE);
         })
-        .unwrap();
-
-    /*
-        @dev: Efe
-        So are we just type-casting with the serde_json::from_str() method?
-        Not exactly. The serde_json::from_str() function in Rust is not just type-casting, it’s actually performing deserialization.
-        In Rust, type casting is a way to convert a value from one data type to another. For example, converting an integer to a float.
-        This is a simple conversion and doesn’t involve any complex processing.
-        On the other hand, serde_json::from_str() is a function provided by the serde_json crate that deserializes a JSON string into a Rust data structure.
-        Deserialization is a more complex process than type-casting. It involves parsing the JSON string, understanding its structure, and then creating the
-        corresponding Rust data structure.
-        In the case of serde_json::from_str(nonce_str), the function is trying to parse the nonce_str (which is a JSON string) and convert it into a Vec<u8>,
-        which is a vector of bytes. If the nonce_str is not a valid JSON string, or if it doesn’t match the structure of a Vec<u8>, the function will return an error.
-        So, while both type-casting and deserialization involve some form of conversion, they are used for different purposes and involve different levels of
-        complexity.
-    */
-
-    /*
-        @dev: Efe
-        Let break unwrap() down into its parts:
-        serde_json::from_str(nonce_str) is a function that tries to deserialize a JSON string (nonce_str) into a Rust data structure (Vec<u8> in this case).
-        This function returns a Result type. If the deserialization is successful, it returns Ok(value) where value is the deserialized value (Vec<u8>).
-        If the deserialization fails (for example, if nonce_str is not a valid JSON string), it returns Err(e) where e is the error that occurred.
-        The map_err() function is then called on this Result. If the Result is Err(e), map_err() transforms the Err(e) into a new error
-        EncryptError::AeadError(Unspecified). If the Result is Ok(value), map_err() does nothing and the Ok(value) is passed through.
-        Finally, unwrap() is called on the Result. If the Result is Ok(value), unwrap() returns the value. If the Result is Err(e), unwrap() causes the program
-        to panic and print a debug message. So, in this code, unwrap() is being used to get the deserialized value (Vec<u8>) if the deserialization was successful,
-        or to cause the program to panic if the deserialization failed. It’s a way of saying “give me the value if it’s there, but stop the program if there was
-        an error”. However, using unwrap() in this way can lead to program crashes and is generally not recommended for production code. Instead, it’s better to
-        handle potential errors explicitly. For example, you could use a match statement to handle both the Ok and Err cases.
-    */
+    });
 
     // A 'stream' is a sequence or flow of data from one place to another in a continuous manner.
     // Streams are used in programming for input/output operations, where data is read from or written to a storage medium
@@ -114,178 +611,1701 @@ fn main() {
     // For example, a byte stream could be interpreted as text (in various encodings), as integer numbers (in big or little endian),
     // or even as a file (like a zip file).
 
-    // In your Rust code, 'serde_json::from_str(nonce_str).unwrap()' is deserializing a JSON string into a byte stream (a 'Vec<u8>'),
-    // where each byte is a unit of binary data.
-
     // Perform the encryption or decryption based on the command
     //
-    match command.as_str() {
-        "encrypt" => {
-            if let Err(err) = encrypt(password, file_path, &nonce) {
-                println!("Encryption error: {}", err);
+    let mut printer = Printer::new(options.color);
+    // stdout carries the contents of the file for git-filter, the text for the text commands and what the vault holds
+    // for `vault get` and `vault list`
+    let prints_text = options.text.as_ref().is_some_and(text::Text::prints)
+        || matches!(command, "vault get" | "vault list");
+    if options.json || command == "git-filter" || prints_text {
+        printer = printer.without_successes();
+    }
+    if options.quiet {
+        printer = printer.quiet();
+    }
+    // Whoever writes OpenSSL enc files should know that nothing will notice if they are changed
+    if command == "encrypt" && options.format == Format::Openssl {
+        printer.warn(
+            "--format openssl writes AES-256-CBC without authentication, like `openssl enc`: changes to the \
+             files won't be detected. Use it only to exchange files with systems that have nothing but OpenSSL.",
+        );
+    }
+    let progress = match options.progress_fd {
+        Some(fd) => match ProgressSink::from_fd(fd) {
+            Ok(sink) => sink,
+            Err(err) => {
+                printer.failed(&format!("Cannot use progress fd {}: {}", fd, err));
+                exit(EXIT_FAILURE);
+            }
+        },
+        None => ProgressSink::disabled(),
+    };
+    if let Some((count, threshold)) = options.shares {
+        printer.note(&format!(
+            "Wrote {} key shares, any {} of which decrypt: {}",
+            count,
+            threshold,
+            share_paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    // --kdf-target replaces the iteration count with one measured on this machine. Only new files need it,
+    // since decryption reads the parameters from the header, and a raw key isn't derived at all.
+    let encrypts = matches!(
+        command,
+        "encrypt" | "encrypt-text" | "vault init" | "daemon" | "train-dict" | "watch"
+    );
+    if let (Some(target), Secret::Password(..), true) = (options.kdf_target, &secret, encrypts) {
+        match options.kdf_params.calibrate(target) {
+            Ok(params) => {
+                options.kdf_params = params;
+                printer.note(&format!(
+                    "Calibrated Argon2id to {} iterations over {} KiB for about {}",
+                    params.iterations,
+                    params.memory,
+                    units::duration(target)
+                ));
+            }
+            Err(err) => {
+                printer.failed(&format!("Cannot calibrate the KDF: {}", err));
+                exit(err.code().number());
+            }
+        }
+    }
+
+    // A new password is rated before anything is encrypted with it: a guessable one gets a warning with the time
+    // it would take to crack, and one rated below --min-strength is refused
+    if let (
+        Secret::Password(password, _),
+        "encrypt" | "encrypt-text" | "vault init" | "daemon" | "watch",
+    ) = (&secret, command)
+    {
+        let names: Vec<String> = files
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let estimate = strength::estimate(password, &names);
+        let crack_time = match options.format {
+            Format::Native => format!(
+                "; it could be cracked in {} at this Argon2id cost",
+                estimate.crack_time(options.kdf_params)
+            ),
+            _ => String::new(),
+        };
+        let message = format!(
+            "Weak password (strength {}/4): {}{}",
+            estimate.score,
+            estimate.pattern.describe(),
+            crack_time
+        );
+        match options.min_strength {
+            Some(min) if estimate.score < min => {
+                printer.failed(&format!("{} (--min-strength is {})", message, min));
+                exit(EXIT_FAILURE);
+            }
+            _ if estimate.score < strength::WEAK => printer.warn(&message),
+            _ => log::debug!("Password strength {}/4", estimate.score),
+        }
+    }
+
+    if encrypts && options.format == Format::Native {
+        match &secret {
+            Secret::Password(..) => log::debug!(
+                "Encrypting with {}, keys derived with Argon2id over {} KiB and {} iterations",
+                options.algorithm.name(),
+                options.kdf_params.memory,
+                options.kdf_params.iterations
+            ),
+            _ => log::debug!("Encrypting with {}", options.algorithm.name()),
+        }
+    }
+    // `encrypt --incremental` walks its trees and only encrypts the files that changed since the run its state
+    // records. The state is encrypted, so it needs a secret that can read it back.
+    let mut backup = None;
+    let planned;
+    let files = match (&options.incremental, &options.out) {
+        (Some(state_path), Some(out_dir)) => {
+            if matches!(secret, Secret::Recipients(_)) {
+                cli::usage_error(
+                    command,
+                    "--incremental needs a password, key or identity that can read its --state back",
+                );
+            }
+            let mut state = incremental::State::load(&secret.encryptor(), state_path)
+                .unwrap_or_else(|err| {
+                    printer.failed(&format!("State error: {}: {}", state_path.display(), err));
+                    exit(err.code().number());
+                });
+            let (changed, summary) =
+                state
+                    .plan(files, out_dir, &options.filter)
+                    .unwrap_or_else(|message| {
+                        printer.failed(&message);
+                        exit(EXIT_FAILURE);
+                    });
+            changed.iter().for_each(redact::path);
+            printer.note(&summary);
+            backup = Some(state);
+            planned = changed;
+            &planned[..]
+        }
+        _ => files,
+    };
+
+    transcript::record(
+        "settings",
+        transcript::settings(&options, &secret, files.len()),
+    );
+    if let Some(path) = &options.report {
+        run_report::start(
+            path,
+            command,
+            files,
+            transcript::settings(&options, &secret, files.len()),
+        );
+    }
+
+    // Nothing is done to a file that can't be audited
+    if let Some(path) = &options.audit_log {
+        if let Err(message) = audit::start(path, options.audit_key.as_deref()) {
+            printer.failed(&message);
+            exit(EXIT_FAILURE);
+        }
+    }
+
+    // The encryption settings shared by every file. Each file still gets its own Encryptor below.
+    let base = secret
+        .encryptor()
+        .with_algorithm(options.algorithm)
+        .with_kdf_params(options.kdf_params);
+
+    // `encryptor train-dict <dict-file> <sample>...` writes an encrypted zstd dictionary
+    if command == "train-dict" {
+        let (dict_path, samples) = files
+            .split_first()
+            .expect("clap requires the dictionary path");
+        let max_size = options.dict_size.unwrap_or(dictionary::DEFAULT_SIZE);
+        match dictionary::train(&base, dict_path, samples, max_size) {
+            Ok(id) => printer.ok(&format!(
+                "Trained dictionary {} on {} files -> {}",
+                id,
+                samples.len(),
+                dict_path.display()
+            )),
+            Err(err) => {
+                printer.failed(&format!("Training error: {}", err));
+                exit(err.code().number());
+            }
+        }
+        return;
+    }
+
+    // `encryptor grant <file>... --grant-to <pubkey> --signing-key <path> --expires <time> --purpose <text> --out <token>`
+    // lets the operator with that public key decrypt the files until the grant expires
+    if command == "grant" {
+        let (Some(operator), Some(signing_key), Some(lifetime), Some(purpose), Some(out_path)) = (
+            &options.grant_to,
+            &options.signing_key,
+            options.expires,
+            &options.purpose,
+            &options.out,
+        ) else {
+            unreachable!("clap requires every option of `grant`");
+        };
+        let keys = keyfile::load_recipient(operator)
+            .and_then(|operator| Ok((operator, keyfile::load(signing_key)?)));
+        let (operator, signing_key) = keys.unwrap_or_else(|message| {
+            printer.failed(&message);
+            exit(EXIT_FAILURE);
+        });
+        match grant_file::mint(
+            &base,
+            files,
+            operator,
+            *signing_key,
+            purpose,
+            lifetime,
+            out_path,
+        ) {
+            Ok(summary) => printer.ok(&summary),
+            Err(err) => {
+                printer.failed(&format!("Grant error: {}", err));
+                exit(err.code().number());
+            }
+        }
+        return;
+    }
+
+    // --out names the output of a single file, or the directory every output of a batch (or named by its hash) goes
+    // into.
+    let archives = options.archive || options.extract;
+    if let Some(out_path) = options
+        .out
+        .as_deref()
+        .filter(|out_path| !archives && backup.is_none() && !streamed::is_remote(out_path))
+    {
+        if files.len() > 1 || options.name_by_hash || streamed::ends_with_separator(out_path) {
+            if let Err(err) = std::fs::create_dir_all(out_path) {
+                printer.failed(&format!(
+                    "Cannot create the output directory {}: {}",
+                    out_path.display(),
+                    err
+                ));
+                exit(EXIT_FAILURE);
+            }
+        }
+    }
+
+    // --shred verifies every output before destroying its original, so it needs a secret that can decrypt.
+    // With --in-place it overwrites the replaced file's old blocks instead.
+    if options.shred && !options.in_place && matches!(secret, Secret::Recipients(_)) {
+        cli::usage_error(
+            command,
+            "--shred needs a password, key or identity that can decrypt the outputs to verify them",
+        );
+    }
+
+    // age and CMS have their own armor (PEM for CMS), and OpenSSL enc files base64 like `openssl enc -a`, with no other
+    // encodings. JWE is text already, and has
+    // no encodings at all.
+    let armor = match (options.format, options.encoder.name()) {
+        (Format::Native, _) | (_, "raw") => false,
+        (Format::Age | Format::Cms | Format::Openssl, "armor") => true,
+        (format, other) => cli::usage_error(
+            command,
+            &format!("--format {} can't be written as {}", format.name(), other),
+        ),
+    };
+    // Outputs that already exist fail the file, unless --force or --auto-rename says otherwise
+    let existing = match (options.force, options.auto_rename) {
+        (true, _) => streamed::Existing::Replace,
+        (_, true) => streamed::Existing::Rename,
+        _ => streamed::Existing::Refuse,
+    };
+
+    // A dictionary given with --dict is used for zstd, which it implies when no other codec was chosen
+    let mut compression = options.compression;
+    let dictionary =
+        options
+            .dict_path
+            .as_ref()
+            .map(|dict_path| match dictionary::load(&base, dict_path) {
+                Ok(dictionary) => dictionary,
+                Err(err) => {
+                    printer.failed(&format!("Cannot load dictionary {}: {}", dict_path, err));
+                    exit(err.code().number());
+                }
+            });
+    if dictionary.is_some() && compression.codec == Codec::None {
+        compression = Compression::new(Codec::Zstd);
+    }
+
+    // The recovery code every output is also wrapped for. It is printed even with --quiet, since nobody could use
+    // a code they never saw.
+    let recovery = (options.recovery_code && command == "encrypt").then(|| {
+        let code = recovery_code::generate().unwrap_or_else(|message| {
+            printer.failed(&message);
+            exit(EXIT_FAILURE);
+        });
+        transcript::secret(&code);
+        eprintln!(
+            "Recovery code: {}\nKeep it apart from the password: it decrypts these files without it (decrypt --recovery-code)",
+            *code
+        );
+        code
+    });
+
+    // A fresh Encryptor for every file, so every file gets its own random salt (and key) as well as its own nonce.
+    // They share one nonce log, so a nonce repeated under a key shared across files (a raw key) is caught too.
+    // Several files encrypted with a password alone instead share one Argon2id run, each getting its own key from
+    // it with HKDF, and files decrypted together share the keys they derive, so a batch from one run is fast too.
+    let nonces = NonceLog::new();
+    let batch_key = command == "encrypt"
+        && files.len() > 1
+        && matches!(&secret, Secret::Password(_, recipients) if recipients.is_empty())
+        && !options.wrap_keys
+        && recovery.is_none();
+    let shared = (batch_key || command == "decrypt").then(|| {
+        checked
+            .take()
+            .unwrap_or_else(|| secret.encryptor())
+            .with_batch_key(batch_key)
+    });
+    // --jobs threads go to the files first, and those left over seal the chunks of each file
+    let jobs = options.jobs.unwrap_or_else(batch::default_jobs);
+    let stream_workers = jobs / files.len().clamp(1, jobs.max(1));
+    let file_encryptor = || {
+        let encryptor = match &shared {
+            Some(shared) => shared.clone(),
+            None => secret.encryptor(),
+        };
+        let encryptor = encryptor
+            .with_algorithm(options.algorithm)
+            .with_kdf_params(options.kdf_params)
+            .with_metadata_binding(options.bind_metadata)
+            .with_header_backup(options.header_backup)
+            .with_checksum(options.checksum)
+            .with_key_wrapping(options.wrap_keys)
+            .with_stream_threshold(options.stream_threshold)
+            .with_chunk_size(options.chunk_size)
+            .with_stream_workers(stream_workers)
+            .with_encoder(options.encoder.clone())
+            .with_name_encryption(options.encrypt_names)
+            .with_name_by_hash(options.name_by_hash)
+            .with_in_place(options.in_place)
+            // A changed file replaces the output of its last --incremental run
+            .with_overwrite(options.force || options.in_place || options.incremental.is_some())
+            .with_auto_rename(options.auto_rename)
+            .with_resume(options.resume)
+            .with_keep_going(options.keep_going)
+            .with_mmap(options.mmap)
+            .with_io_buffer(options.io_buffer)
+            .with_retry_policy(options.retry)
+            .with_nonce_log(nonces.clone())
+            .with_compression(compression);
+        let encryptor = match options.padding {
+            Some(padding) => encryptor.with_padding(padding),
+            None => encryptor,
+        };
+        let encryptor = match &options.key_id {
+            Some(key_id) => encryptor.with_key_id(key_id),
+            None => encryptor,
+        };
+        let encryptor = match options.password_set {
+            Some(secs) => encryptor.with_password_set(secs),
+            None => encryptor,
+        };
+        let encryptor = match &recovery {
+            Some(code) => encryptor.with_extra_password(code),
+            None => encryptor,
+        };
+        match &dictionary {
+            Some(dictionary) => encryptor.with_dictionary(dictionary.clone()),
+            None => encryptor,
+        }
+    };
+
+    // `encrypt --archive <path>... [--out <file>]` packs files and directory trees into one encrypted file,
+    // and `decrypt --extract <file> [--out <dir>]` restores them
+    if options.archive || options.extract {
+        if options.json {
+            cli::usage_error(
+                command,
+                "--json reports files one by one, and can't be combined with --archive or --extract",
+            );
+        }
+        if options.report.is_some() {
+            cli::usage_error(
+                command,
+                "--report summarizes files one by one, and can't be combined with --archive or --extract",
+            );
+        }
+        if options.audit_log.is_some() {
+            cli::usage_error(
+                command,
+                "--audit-log records files one by one, and can't be combined with --archive or --extract",
+            );
+        }
+        // An archive is encrypted as it is packed, like s3:// objects, in chunks and nothing else
+        let unstreamed = [
+            (
+                "compression (--compress or --dict)",
+                options.compression.codec != Codec::None || options.dict_path.is_some(),
+            ),
+            ("--pad", options.padding.is_some()),
+            ("--armor or --encoding", options.encoder.name() != "raw"),
+        ];
+        if let Some((flag, _)) = unstreamed
+            .iter()
+            .find(|(_, given)| *given && options.archive)
+        {
+            cli::usage_error(
+                command,
+                &format!(
+                    "{} can't be combined with --archive, which is encrypted as it is packed",
+                    flag
+                ),
+            );
+        }
+        let mut manifest = options.manifest.as_ref().map(|_| Manifest::default());
+        let result = match (command, files) {
+            ("encrypt", roots) => archive::encrypt(
+                &file_encryptor(),
+                roots,
+                options.out.as_deref(),
+                manifest.as_mut(),
+                &options.filter,
+                options.symlinks,
+                options.ads,
+            ),
+            ("decrypt", [file_path]) => {
+                archive::extract(&file_encryptor(), file_path, options.out.as_deref())
+            }
+            _ => cli::usage_error(command, "--extract unpacks one archive at a time"),
+        };
+        match result {
+            Ok(summary) => printer.ok(&summary),
+            Err(err) => {
+                printer.failed(&format!("Archive error: {}", err));
+                exit(err.code().number());
+            }
+        }
+        if let (Some(manifest), Some(manifest_path)) = (manifest, &options.manifest) {
+            write_manifest(manifest, &file_encryptor(), manifest_path, &printer);
+        }
+        return;
+    }
+
+    // `verify --manifest <manifest> <dir>...` checks restored trees against what `encrypt --manifest` recorded
+    if let ("verify", Some(manifest_path)) = (command, &options.manifest) {
+        let mut mismatched = false;
+        for dir in files {
+            match manifest::check(&file_encryptor(), manifest_path, dir) {
+                Ok((listed, differences)) if differences.is_empty() => printer.ok(&format!(
+                    "{} matches the manifest {} ({} files)",
+                    dir.display(),
+                    manifest_path,
+                    listed
+                )),
+                Ok((_, differences)) => {
+                    for difference in &differences {
+                        printer.failed(&format!(
+                            "{}: {}",
+                            ErrorCode::MetadataMismatch.as_str(),
+                            difference
+                        ));
+                    }
+                    printer.failed(&format!(
+                        "{} doesn't match the manifest {}: {} differences",
+                        dir.display(),
+                        manifest_path,
+                        differences.len()
+                    ));
+                    mismatched = true;
+                }
+                Err(err) => {
+                    printer.failed(&format!("Manifest error: {}: {}", manifest_path, err));
+                    exit(err.code().number());
+                }
+            }
+        }
+        if mismatched {
+            exit(ErrorCode::MetadataMismatch.number());
+        }
+        return;
+    }
+
+    // `encryptor resume-journal <journal> [--rollback]` finishes or undoes an interrupted `encrypt --shred` batch
+    if command == "resume-journal" {
+        let [journal_path] = files else {
+            unreachable!("resume-journal takes one journal");
+        };
+        let results = match journal::resume(journal_path, options.rollback, file_encryptor) {
+            Ok(results) => results,
+            Err(message) => {
+                printer.failed(&message);
+                exit(EXIT_FAILURE);
+            }
+        };
+        for (file_path, result) in &results {
+            match result {
+                Ok(_) if options.rollback => {
+                    printer.ok(&format!("Rolled back {}", file_path.display()))
+                }
+                Ok(output_path) => printer.ok(&format!(
+                    "Finished {} -> {}",
+                    file_path.display(),
+                    output_path.display()
+                )),
+                Err(err) => {
+                    printer.failed(&format!("Resume error: {}: {}", file_path.display(), err))
+                }
+            }
+        }
+        if let Some(err) = results.iter().find_map(|(_, result)| result.as_ref().err()) {
+            printer.failed(&format!(
+                "{} still records the unfinished files",
+                journal_path.display()
+            ));
+            exit(err.code().number());
+        }
+        return;
+    }
+
+    // `encryptor watch <dir> --out <dir>` encrypts the files that appear or change in a directory until stopped
+    if command == "watch" {
+        let ([dir], Some(out_dir)) = (files, &options.out) else {
+            unreachable!("clap requires the directory and --out");
+        };
+        let cancel = CancellationToken::new();
+        let result = watch::run(
+            dir,
+            out_dir,
+            options.debounce,
+            &options.excludes,
+            &printer,
+            |file_path, out_path| {
+                file_encryptor().encrypt_file_to_with(file_path, out_path, |_| {}, &cancel)
+            },
+        );
+        if let Err(message) = result {
+            printer.failed(&message);
+            exit(EXIT_FAILURE);
+        }
+        return;
+    }
+
+    // `encryptor git-filter clean|smudge <path>` encrypts or decrypts one file from stdin to stdout for git
+    if let (Some(mode), [path]) = (options.git_filter, files) {
+        if let Err(err) = git_filter::run(file_encryptor(), mode, path) {
+            printer.failed(&format!("git-filter error: {}: {}", path.display(), err));
+            exit(err.code().number());
+        }
+        return;
+    }
+
+    // `encryptor encrypt-text|decrypt-text` seals or opens a short text, printed or put onto the clipboard
+    if let Some(text) = &options.text {
+        let decrypt = command == "decrypt-text";
+        match text::run(file_encryptor(), decrypt, text) {
+            Ok(Some(message)) => printer.ok(&message),
+            Ok(None) => {}
+            Err(err) => {
+                let operation = if decrypt { "Decryption" } else { "Encryption" };
+                printer.failed(&format!("{} error: {}", operation, err));
+                exit(err.code().number());
             }
         }
-        "decrypt" => {
-            if let Err(err) = decrypt(password, file_path, &nonce) {
-                println!("Decryption error: {}", err);
+        return;
+    }
+
+    // `encryptor report stale-keys <path>...` lists the files whose password was set too long ago
+    if command == "report stale-keys" {
+        let max_age = options
+            .max_password_age
+            .unwrap_or(stale_keys::DEFAULT_MAX_AGE);
+        let stale = stale_keys::run(&file_encryptor(), files, max_age, &options.filter, &printer);
+        if stale > 0 {
+            exit(EXIT_FAILURE);
+        }
+        return;
+    }
+
+    // `encryptor vault init|set|get|list|rm` works on the named secrets of one encrypted file
+    if let Some(vault) = &options.vault {
+        let path = match &vault.path {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => config_dir().map(|dir| dir.join("vault.enc")),
+        };
+        let path = path.unwrap_or_else(|message| {
+            printer.failed(&message);
+            exit(EXIT_FAILURE);
+        });
+        match vault::run(file_encryptor(), &path, &vault.action) {
+            Ok(Some(message)) => printer.ok(&message),
+            Ok(None) => {}
+            Err(err) => {
+                printer.failed(&format!("Vault error: {}", err));
+                exit(err.code().number());
             }
         }
-        _ => println!("Invalid command"),
+        return;
+    }
+
+    // `encryptor daemon` holds the key and serves `encryptor client` until its TTL runs out or it is stopped
+    if command == "daemon" {
+        let served = daemon::socket_path(options.socket.as_deref())
+            .and_then(|socket| daemon::serve(file_encryptor(), &socket, options.ttl, &printer));
+        if let Err(message) = served {
+            printer.failed(&message);
+            exit(EXIT_FAILURE);
+        }
+        return;
+    }
+
+    // `encryptor mount <archive> <dir>` serves the archive as a read-only filesystem until it is unmounted
+    if command == "mount" {
+        let ([file_path], Some(mountpoint)) = (files, &options.out) else {
+            unreachable!("clap requires the archive and the mount point");
+        };
+        if let Err(err) = mount::run(&file_encryptor(), file_path, mountpoint, &printer) {
+            printer.failed(&format!("Mount error: {}: {}", file_path.display(), err));
+            exit(err.code().number());
+        }
+        return;
+    }
+
+    let (done, operation) = match command {
+        "encrypt" => ("Encrypted", "Encryption"),
+        "decrypt" => ("Decrypted", "Decryption"),
+        "verify" => ("Verified", "Verification"),
+        "rekey" => ("Rekeyed", "Rekeying"),
+        "rotate" => ("Rotated", "Rotation"),
+        "migrate" => ("Migrated", "Migration"),
+        _ => unreachable!("{} is handled above", command),
+    };
+    let progress = Mutex::new(progress);
+    let cancel = CancellationToken::new();
+    interrupt::cancel_on_signals(&cancel);
+    let bars = progress_bar::Bars::new(
+        io::stdout().is_terminal() && !options.quiet && !options.json,
+        files,
+    );
+
+    // With --shred, every step is journaled first, so a crash midway can be finished or undone with resume-journal
+    let journal = (options.shred && !options.in_place).then(|| {
+        let path = options.journal.as_deref().unwrap_or(journal::DEFAULT_PATH);
+        journal::Journal::begin(Path::new(path), files).unwrap_or_else(|message| {
+            printer.failed(&message);
+            exit(EXIT_FAILURE);
+        })
+    });
+
+    // Overwriting only destroys the old blocks where the filesystem writes in place, so say where it may not
+    if options.shred {
+        let caveats: std::collections::BTreeSet<String> = files
+            .iter()
+            .filter_map(|file_path| shred::caveat(file_path))
+            .collect();
+        for caveat in caveats {
+            printer.warn(&format!(
+                "Some files are on {}, where old copies of their blocks may survive shredding; only full-disk encryption protects them there",
+                caveat
+            ));
+        }
+    }
+
+    // With --remove-to-trash, the originals moved to the trash are recorded for `encryptor undo`
+    let trashed = options.remove_to_trash.then(|| {
+        undo::Record::start().unwrap_or_else(|message| {
+            printer.failed(&message);
+            exit(EXIT_FAILURE);
+        })
+    });
+
+    // Process the files on up to --jobs worker threads, reporting each one as it finishes
+    let manifest = options
+        .manifest
+        .as_ref()
+        .map(|_| Mutex::new(Manifest::default()));
+    stats::start(command);
+    let results = batch::run(files, jobs, |file_path| {
+        // Once interrupted, the files not started yet are skipped
+        if cancel.is_cancelled() {
+            return Err(EncryptError::Cancelled);
+        }
+        let encryptor = file_encryptor();
+        let start = Instant::now();
+        let bar = bars.start(file_path);
+        // Progress is logged in steps of 10%, since the library reports it for every chunk
+        let logged_step = Cell::new(0);
+        let report = |update: encryptor::Progress| {
+            let percent = update.percent();
+            bar.update(percent);
+            lock(&progress).percent(file_path, percent);
+            if percent / 10 > logged_step.get() {
+                logged_step.set(percent / 10);
+                log::debug!("{}: {}%", file_path.display(), percent);
+            }
+        };
+        log::info!("{} of {} started", operation, file_path.display());
+
+        lock(&progress).started(command, file_path);
+        // The cipher of a native output is chosen here, and that of a native input recorded in its header
+        let json_report = (options.json
+            || transcript::active()
+            || run_report::active()
+            || stats::active()
+            || audit::active())
+        .then(|| {
+            let foreign = options.format != Format::Native
+                || (command != "encrypt"
+                    && (certificates
+                        || cms_file::is_cms(file_path)
+                        || age_file::is_age(file_path)
+                        || jwe_file::is_jwe(file_path)
+                        || openssl_file::is_openssl(file_path)));
+            let algorithm = match command {
+                _ if foreign => None,
+                "encrypt" => Some(options.algorithm.name()),
+                _ => report::native_algorithm(file_path),
+            };
+            report::Operation::start(command, file_path, algorithm)
+        });
+        let audited_header = (audit::active() && command != "encrypt")
+            .then(|| report::native_header(file_path))
+            .flatten();
+        // A password set longer ago than --max-password-age is pointed out before the file is opened, while the
+        // header is still there to read it from (--in-place replaces it)
+        if let (Some(max_age), "decrypt" | "verify") = (options.max_password_age, command) {
+            if let Some(age) = stale_keys::stale(&encryptor, file_path, max_age) {
+                printer.warn(&format!(
+                    "{}: {}; change it with `encryptor rekey`",
+                    file_path.display(),
+                    age
+                ));
+            }
+        }
+        // The volumes of a file written with --split-size are read as one
+        let split = match command {
+            "decrypt" | "verify" => volumes::split_path(file_path),
+            _ => None,
+        };
+        // Native files go to --out when it was given
+        let decrypt_native = |encryptor: Encryptor| match &options.out {
+            Some(out_path) => encryptor.decrypt_file_to_with(file_path, out_path, report, &cancel),
+            None => encryptor.decrypt_file_with(file_path, report, &cancel),
+        };
+        let operate = || match command {
+            "encrypt" if options.format == Format::Age => {
+                age_file::encrypt(file_path, &secret, armor, existing)
+            }
+            "encrypt" if options.format == Format::Jwe => {
+                jwe_file::encrypt(file_path, &secret, false, existing)
+            }
+            "encrypt" if options.format == Format::JweJson => {
+                jwe_file::encrypt(file_path, &secret, true, existing)
+            }
+            "encrypt" if options.format == Format::Cms => {
+                cms_file::encrypt(file_path, &secret, armor, existing)
+            }
+            "encrypt" if options.format == Format::Openssl => openssl_file::encrypt(
+                file_path,
+                &secret,
+                options.pbkdf2_iterations,
+                armor,
+                existing,
+            ),
+            // --incremental writes each file to its place in the mirror of its tree
+            "encrypt" if backup.is_some() => {
+                let out_path = backup
+                    .as_ref()
+                    .and_then(|state| state.output(file_path))
+                    .expect("every file of an incremental run is planned");
+                encryptor.encrypt_file_to_with(file_path, out_path, report, &cancel)
+            }
+            "encrypt" => match &journal {
+                Some(journal) => {
+                    journal.encrypt_and_shred(&encryptor, file_path, Step::Planned, report, &cancel)
+                }
+                None => match (&options.out, options.split_size) {
+                    (out_path, Some(split_size)) => volumes::encrypt(
+                        &encryptor,
+                        file_path,
+                        out_path.as_deref(),
+                        split_size,
+                        existing,
+                    ),
+                    (Some(out_path), None) if s3::is_uri(out_path) => s3::encrypt(
+                        &encryptor,
+                        file_path,
+                        out_path,
+                        files.len() > 1,
+                        options.storage_class.as_deref(),
+                    ),
+                    (Some(out_path), None) if http::is_url(out_path) => http::encrypt(
+                        &encryptor,
+                        file_path,
+                        out_path,
+                        files.len() > 1,
+                        &options.http_method,
+                        &options.http_headers,
+                    ),
+                    (Some(out_path), None) => {
+                        encryptor.encrypt_file_to_with(file_path, out_path, report, &cancel)
+                    }
+                    (None, None) => encryptor.encrypt_file_with(file_path, report, &cancel),
+                },
+            },
+            // Rekeying keeps the cipher and layout of the file and takes a fresh salt, nonce and KDF cost from the
+            // new secret's own Encryptor. The file is replaced, so it is reported as the output.
+            "rekey" | "rotate" => {
+                let new = new_secret
+                    .as_ref()
+                    .expect("rekey reads a new secret")
+                    .encryptor()
+                    .with_kdf_params(options.kdf_params)
+                    .with_key_wrapping(options.wrap_keys)
+                    .with_nonce_log(nonces.clone());
+                let new = match &options.key_id {
+                    Some(key_id) => new.with_key_id(key_id),
+                    None => new,
+                };
+                encryptor
+                    .rekey_file_with(file_path, &new, report, &cancel)
+                    .map(|()| file_path.to_path_buf())
+            }
+            // Files of the first releases have no header, so the nonce comes from the command line
+            "migrate" => legacy_file::migrate(
+                &encryptor,
+                file_path,
+                &secret,
+                &options.legacy_nonce.unwrap_or_default(),
+            ),
+            _ if options.legacy_nonce.is_some() => legacy_file::decrypt(
+                file_path,
+                &secret,
+                &options.legacy_nonce.unwrap_or_default(),
+                options.out.as_deref(),
+                files.len() > 1,
+                existing,
+            ),
+            // Verifying writes nothing, so the file itself is reported as the output
+            // The plaintext is where decrypting writes it by default, the path without its extension
+            "verify" if options.checksum_only => {
+                let plaintext_path = encryptor::names::strip_extension(file_path);
+                encryptor
+                    .verify_checksum_with(file_path, plaintext_path, report, &cancel)
+                    .map(|()| file_path.to_path_buf())
+            }
+            "verify" if s3::is_uri(file_path) => s3::verify(&encryptor, file_path),
+            "verify" if http::is_url(file_path) => {
+                http::verify(&encryptor, file_path, &options.http_headers)
+            }
+            "verify" => match split {
+                Some(path) => volumes::verify(&encryptor, path),
+                None => encryptor.verify_file_with(file_path, report, &cancel),
+            }
+            .map(|()| file_path.to_path_buf()),
+            // A grant only decrypts the native files it covers
+            _ if granted => grant_file::decrypt(file_path, &secret, |encryptor| {
+                decrypt_native(
+                    encryptor
+                        .with_encoder(options.encoder.clone())
+                        .with_retry_policy(options.retry)
+                        .with_in_place(options.in_place)
+                        .with_overwrite(options.force || options.in_place)
+                        .with_auto_rename(options.auto_rename)
+                        .with_resume(options.resume)
+                        .with_keep_going(options.keep_going)
+                        .with_mmap(options.mmap)
+                        .with_io_buffer(options.io_buffer),
+                )
+            }),
+            _ if s3::is_uri(file_path) => s3::decrypt(
+                &encryptor,
+                file_path,
+                options.out.as_deref(),
+                files.len() > 1,
+                existing,
+            ),
+            _ if http::is_url(file_path) => http::decrypt(
+                &encryptor,
+                file_path,
+                options.out.as_deref(),
+                files.len() > 1,
+                existing,
+                &options.http_headers,
+            ),
+            _ if options.range.is_some() => streamed::decrypt_range(
+                &encryptor,
+                file_path,
+                options.range.unwrap_or_default(),
+                options.out.as_deref(),
+                files.len() > 1,
+                existing,
+            ),
+            _ if split.is_some() => volumes::decrypt(
+                &encryptor,
+                split.unwrap_or(file_path),
+                options.out.as_deref(),
+                files.len() > 1,
+                existing,
+            ),
+            // Only native files are decrypted in place or to --out
+            _ if options.in_place => encryptor.decrypt_file_with(file_path, report, &cancel),
+            _ if options.out.is_some()
+                && (cms_file::is_cms(file_path)
+                    || age_file::is_age(file_path)
+                    || jwe_file::is_jwe(file_path)
+                    || openssl_file::is_openssl(file_path)) =>
+            {
+                Err(EncryptError::KeyModeMismatch(
+                    "--out only works with files in the native format".to_string(),
+                ))
+            }
+            // Certificates only decrypt CMS files. Files written by age or rage, JWE, CMS and OpenSSL enc files are
+            // recognized by how they start.
+            _ if certificates || cms_file::is_cms(file_path) => {
+                cms_file::decrypt(file_path, &secret, existing)
+            }
+            _ if age_file::is_age(file_path) => age_file::decrypt(file_path, &secret, existing),
+            _ if jwe_file::is_jwe(file_path) => jwe_file::decrypt(file_path, &secret, existing),
+            _ if openssl_file::is_openssl(file_path) => {
+                openssl_file::decrypt(file_path, &secret, options.pbkdf2_iterations, existing)
+            }
+            _ => decrypt_native(encryptor.clone()),
+        };
+        // The manifest hashes the plaintext before it is encrypted, and maybe replaced or shredded
+        let (hashed, result) = match manifest
+            .as_ref()
+            .map(|_| manifest::hash_file(file_path))
+            .transpose()
+        {
+            Err(err) => (None, Err(err.into())),
+            // With --in-place --shred the replaced file's old blocks are overwritten once the new file is in place
+            Ok(hashed) if options.in_place && options.shred => {
+                (hashed, shred::replacing(file_path, operate))
+            }
+            Ok(hashed) => (hashed, operate()),
+        };
+        // The original only goes to the trash once its output is complete
+        let result = match (&trashed, result) {
+            (Some(trashed), Ok(output_path)) => {
+                trashed.trash(file_path, &output_path).map(|()| output_path)
+            }
+            (_, result) => result,
+        };
+        if let (Some(manifest), Some((hash, size)), Ok(output_path)) = (&manifest, hashed, &result)
+        {
+            lock(manifest).add_file(file_path, hash, size, output_path);
+        }
+        log::debug!(
+            "{} of {} took {}",
+            operation,
+            file_path.display(),
+            units::duration(start.elapsed())
+        );
+        if let Ok(output_path) = &result {
+            redact::path(output_path);
+        }
+        bar.finish();
+        match &result {
+            Ok(output_path) if matches!(command, "verify" | "rekey" | "rotate" | "migrate") => {
+                lock(&progress).finished(file_path, output_path);
+                printer.ok(&format!("{} {}", done, file_path.display()));
+            }
+            Ok(output_path) => {
+                lock(&progress).finished(file_path, output_path);
+                printer.ok(&format!(
+                    "{} {} -> {}",
+                    done,
+                    file_path.display(),
+                    output_path.display()
+                ));
+            }
+            Err(err) => {
+                lock(&progress).error(file_path, err);
+                printer.failed(&format!(
+                    "{} error: {}: {}",
+                    operation,
+                    file_path.display(),
+                    err
+                ));
+            }
+        }
+        if let Some(json_report) = json_report {
+            let mut json_report = json_report.finish(&result);
+            redact::value(&mut json_report);
+            if options.json {
+                println!("{}", json_report);
+            }
+            run_report::file(&json_report);
+            stats::file(&json_report);
+            if audit::active() {
+                // The key an output was encrypted under, or an input was, read before decrypting replaced it
+                let key_id = match (command, &result) {
+                    ("encrypt", Ok(output)) => report::native_header(output),
+                    ("encrypt", Err(_)) => None,
+                    _ => audited_header,
+                };
+                audit::file(&json_report, key_id.and_then(|header| header.key_id));
+            }
+            transcript::record("file", json_report);
+        }
+        result
+    });
+
+    bars.finish();
+    // Summarize batches, and exit with the code of the first failed file (in command line order)
+    let failures: Vec<_> = results
+        .iter()
+        .filter_map(|result| result.as_ref().err())
+        .collect();
+    if interrupt::interrupted() {
+        let unfinished = failures
+            .iter()
+            .filter(|err| matches!(err, EncryptError::Cancelled))
+            .count();
+        printer.failed(&format!(
+            "Interrupted: {} of {} files weren't finished, and their unfinished outputs were removed{}",
+            unfinished,
+            files.len(),
+            match options.resume {
+                true => " (or kept as <output>.partial to continue with --resume)",
+                false => "",
+            }
+        ));
+    } else if files.len() > 1 {
+        if failures.is_empty() {
+            printer.ok(&format!("{} {} files", done, files.len()));
+        } else {
+            printer.failed(&format!(
+                "{} of {} files failed",
+                failures.len(),
+                files.len()
+            ));
+        }
+    }
+    if let Some(journal) = journal {
+        if failures.is_empty() {
+            if let Err(err) = journal.finish() {
+                printer.failed(&format!("Cannot remove the journal: {}", err));
+            }
+        } else {
+            printer.failed(&format!(
+                "{} records how far every file got; finish the batch with `encryptor resume-journal {}`, or undo it with --rollback",
+                journal.path().display(),
+                journal.path().display()
+            ));
+        }
+    }
+    // The state records the files that were encrypted, even when others failed, so only those are skipped next time
+    if let Some(mut state) = backup {
+        state.record(files, &results);
+        if options.delete && !interrupt::interrupted() {
+            let (removed, failed) = state.prune();
+            for message in &failed {
+                printer.failed(message);
+            }
+            if removed > 0 {
+                printer.ok(&format!("Removed the outputs of {} deleted files", removed));
+            }
+        } else if state.deleted() > 0 {
+            printer.note(&format!(
+                "{} files were deleted since they were backed up; --delete removes their outputs",
+                state.deleted()
+            ));
+        }
+        match state.save(&base) {
+            Ok(summary) => printer.ok(&summary),
+            Err(err) => {
+                printer.failed(&format!(
+                    "State error: {}: {}",
+                    options
+                        .incremental
+                        .as_deref()
+                        .unwrap_or(Path::new(""))
+                        .display(),
+                    err
+                ));
+                exit(err.code().number());
+            }
+        }
+    }
+    // The manifest lists the files that were encrypted, even when others failed
+    if let (Some(manifest), Some(manifest_path)) = (manifest, &options.manifest) {
+        let manifest = manifest.into_inner().unwrap_or_else(|e| e.into_inner());
+        write_manifest(manifest, &file_encryptor(), manifest_path, &printer);
+    }
+    if let Some(err) = failures.first() {
+        exit(err.code().number());
     }
     // @dev: Efe
     // Explanation:
-    // The above code is checking the value of `command`. If the value is "encrypt", the code will call the `encrypt` function.
-    // If the value is "decrypt", the code will call the `decrypt` function.
+    // The above code is checking the value of `command`. If the value is "encrypt", the code will call the library's `encrypt_file_with` method
+    // for every file. If the value is "decrypt", the code will call the library's `decrypt_file_with` method.
     // If the value is anything else, the code will print "Invalid command".
     // It is the equivalent of a switch statement in other languages like Javascipt.
     //
-    // 1. `match command.as_str() { ... }`: This is a match expression, similar to a switch statement in other languages.
+    // 1. `match command { ... }`: This is a match expression, similar to a switch statement in other languages.
     //    It's checking the string value of `command`.
-    // 2. `"encrypt" => { ... }` and `"decrypt" => { ... }`: These are match arms. If `command.as_str()` equals "encrypt" or "decrypt",
-    //    the code inside the curly braces `{}` will be executed.
-    // 3. `if let Err(err) = encrypt(password, file_path, &nonce) { ... }`: This is an "if let" statement. It's used for pattern matching.
-    //    Here, it's trying to match the result of `encrypt(password, file_path, &nonce)` with `Err(err)`. If the `encrypt` function
-    //    returns an `Err`, it will be matched and the error will be bound to `err`, and the code inside the curly braces `{}` will be executed.
-    // 4. `println!("Encryption error: {}", err);`: This line will be executed if the `encrypt` function returns an `Err`.
-    //    It prints the error message to the console.
-    // 5. `_ => println!("Invalid command"),`: The underscore `_` is a catch-all pattern that matches anything. If `command.as_str()`
-    //    is neither "encrypt" nor "decrypt", this match arm will be executed and it will print "Invalid command" to the console.
+    // 2. `"encrypt" => ...` and `"decrypt" => ...`: These are match arms. If `command.as_str()` equals "encrypt" or "decrypt",
+    //    the expression after the arrow is evaluated; the first match picks the words for the status lines, the second one the operation to run.
+    // 3. `match &result { ... }`: This is a match on the `Result` returned by the encrypt or decrypt call for one file.
+    //    `Ok(output_path)` is matched when the operation succeeded and binds the path of the written file to `output_path`,
+    //    while `Err(err)` is matched when it failed and binds the error to `err`. Both are also reported as progress events,
+    //    A failing file doesn't stop the others; once all are done, the process exits with the first failure's code so scripts can tell failures apart.
+    // 4. `printer.ok(...)` and `printer.failed(...)`: These print a green "ok" or red "failed" status line (plain text when piped
+    //    or when colors are turned off) followed by the message.
+    // 5. `_ => unreachable!(...)`: The underscore `_` is a catch-all pattern that matches anything. clap only accepts the commands
+    //    it knows, and the others were run above, so this arm can never be reached; `unreachable!` panics if it ever were.
+}
 
-    // @dev: Efe
-    // @topic: Understanding the `if let` statement
-    // The `if let` statement in Rust is used for both calling the function and handling the potential error that might be returned by the function.
-    // - The `encrypt(password, file_path, &nonce)` or `decrypt(password, file_path, &nonce)` function is called within the `if let` statement.
-    // - These functions return a `Result` type. If the operation was successful, they return `Ok(value)`. If there was an error, they return `Err(err)`.
-    // - The `if let Err(err) = ...` syntax is used to check if the function returned an `Err(err)`. If it did, the `err` inside `Err(err)` is bound to the `err` variable in the `if let` statement, and the code inside the curly braces `{}` is executed.
-    // - If the function returned `Ok(value)`, the `if let` statement does nothing, and the program continues to the next line of code.
-    // The `match` statement, on the other hand, is used to determine which function (`encrypt` or `decrypt`) should be called based on the `command` string.
-    // It's not directly involved in error handling. That's the job of the `if let` statement inside each `match` arm.
+// Run one of the commands that neither take a password nor work through files in batches, printing what it did
+fn run_standalone(command: Command) -> Result<(), String> {
+    let print = |message: String| println!("{}", redact::text(&message));
+    match command {
+        // `encryptor bench` prints its own tables
+        Command::Bench(args) if args.compression => bench::compression(args.file.as_deref())
+            .map_err(|err| format!("Benchmark failed: {}", err)),
+        Command::Bench(args) => bench::performance(
+            args.save.as_deref(),
+            args.compare.as_deref(),
+            args.threshold,
+        ),
+        Command::Keygen(args) => keygen(&args).map(print),
+        // `encryptor key export|import --mnemonic` backs a key up on paper and restores it
+        Command::Key {
+            command: KeyCommand::Export(args),
+        } => keyfile::load(&args.key).map(|key| {
+            print(format!(
+                "The key in {}, as words to write down:\n\n{}",
+                args.key,
+                *mnemonic::encode(&key)
+            ))
+        }),
+        Command::Key {
+            command: KeyCommand::Import(args),
+        } => mnemonic::read()
+            .and_then(|words| mnemonic::decode(&words))
+            .and_then(|key| {
+                write_key(
+                    &args.out,
+                    &key,
+                    args.asymmetric,
+                    args.format,
+                    "the restored",
+                )
+            })
+            .map(print),
+        // `encryptor bundle create|import` signs a team's key bundle, or installs one for --team
+        Command::Bundle {
+            command: BundleCommand::Create(args),
+        } => bundle_file::create(&args).map(print),
+        Command::Bundle {
+            command: BundleCommand::Import(args),
+        } => bundle_file::import(&args).map(print),
+        // `encryptor info <file>...` and the `header` commands read the plaintext header, and `recover`
+        // restores a damaged one
+        Command::Info { files } => {
+            files.iter().for_each(redact::path);
+            let failed = files
+                .iter()
+                .filter(|file_path| match header_json::info(file_path) {
+                    Ok(info) => {
+                        print(info);
+                        false
+                    }
+                    Err(message) => {
+                        eprintln!("{}", redact::text(&message));
+                        true
+                    }
+                })
+                .count();
+            match failed {
+                0 => Ok(()),
+                _ => Err(format!(
+                    "{} of {} files couldn't be read",
+                    failed,
+                    files.len()
+                )),
+            }
+        }
+        Command::Header {
+            command: HeaderCommand::Export { file },
+        } => {
+            redact::path(&file);
+            header_json::export(&file).map(print)
+        }
+        Command::Header {
+            command: HeaderCommand::Graft { json, file, out },
+        } => {
+            [&json, &file, &out].into_iter().for_each(redact::path);
+            header_json::graft(&json, &file, &out).map(|()| {
+                print(format!(
+                    "Wrote {} with the header from {}",
+                    out.display(),
+                    json.display()
+                ))
+            })
+        }
+        Command::Recover(args) => {
+            redact::path(&args.file);
+            redact::path(&args.out);
+            recover::recover(&args.file, args.assume.as_deref(), &args.out).map(print)
+        }
+        // `encryptor doctor <file>` walks through what keeps a file from decrypting
+        Command::Doctor(args) => {
+            redact::path(&args.file);
+            doctor::run(args, &print)
+        }
+        // `encryptor selftest` validates the build before it is trusted with data
+        Command::Selftest => selftest(&print),
+        // `encryptor undo` puts back what the last `encrypt --remove-to-trash` moved to the trash
+        Command::Undo => undo::undo().map(print),
+        // `encryptor stats enable|disable|local` turns the usage history on or off, or summarizes it
+        Command::Stats {
+            command: StatsCommand::Enable,
+        } => stats::enable().map(print),
+        Command::Stats {
+            command: StatsCommand::Disable,
+        } => stats::disable().map(print),
+        Command::Stats {
+            command: StatsCommand::Local { days, json },
+        } => stats::local(days, json).map(print),
+        // `encryptor audit verify <log>` checks the chain of an --audit-log
+        Command::Audit {
+            command: AuditCommand::Verify { log, audit_key },
+        } => {
+            redact::path(&log);
+            audit::verify(&log, audit_key.as_deref()).map(print)
+        }
+        // `encryptor keyring <set|delete> <name>` manages the passwords used by --use-keyring
+        Command::Keyring {
+            command: KeyringCommand::Set { name },
+        } => password::read(None, true)
+            .and_then(|password| keychain::set(&name, &password))
+            .map(|()| print(format!("Stored the password for '{}' in the keyring", name))),
+        Command::Keyring {
+            command: KeyringCommand::Delete { name },
+        } => keychain::delete(&name).map(|()| {
+            print(format!(
+                "Deleted the password for '{}' from the keyring",
+                name
+            ))
+        }),
+        Command::Encrypt(_)
+        | Command::Decrypt(_)
+        | Command::Verify(_)
+        | Command::Rekey(_)
+        | Command::Rotate(_)
+        | Command::Migrate(_)
+        | Command::Grant(_)
+        | Command::TrainDict(_)
+        | Command::ResumeJournal(_)
+        | Command::Watch(_)
+        | Command::Mount(_)
+        | Command::GitFilter(_)
+        | Command::EncryptText(_)
+        | Command::DecryptText(_)
+        | Command::Vault { .. }
+        | Command::Report { .. }
+        | Command::Daemon(_)
+        | Command::Client { .. } => unreachable!("file commands are run by main"),
+    }
+}
+
+// `encryptor client encrypt|decrypt <file>...` has the daemon encrypt or decrypt files, and `client status|stop`
+// asks how long it keeps its key or stops it. Exits with the code of the first file that failed.
+fn run_client(command: ClientCommand, printer: &Printer) {
+    let (operation, socket) = match &command {
+        ClientCommand::Encrypt(args) => ("encrypt", &args.client.socket),
+        ClientCommand::Decrypt(args) => ("decrypt", &args.client.socket),
+        ClientCommand::Status(args) => ("status", &args.socket),
+        ClientCommand::Stop(args) => ("stop", &args.socket),
+    };
+    let socket = daemon::socket_path(socket.as_deref()).unwrap_or_else(|message| {
+        printer.failed(&message);
+        exit(EXIT_FAILURE);
+    });
+    let code = match command {
+        ClientCommand::Encrypt(args) | ClientCommand::Decrypt(args) => daemon::client(
+            &socket,
+            operation,
+            &args.files,
+            args.out.as_deref(),
+            args.force,
+            printer,
+        ),
+        ClientCommand::Status(_) | ClientCommand::Stop(_) => {
+            match daemon::control(&socket, operation) {
+                Ok(message) => {
+                    printer.ok(&message);
+                    0
+                }
+                Err(message) => {
+                    printer.failed(&message);
+                    EXIT_FAILURE
+                }
+            }
+        }
+    };
+    if code != 0 {
+        exit(code);
+    }
+}
+
+// `encryptor keygen --out <path>` creates a key for --keyfile. With --asymmetric it creates an X25519 identity
+// for --identity instead, and its public key in <path>.pub, and with --signing an Ed25519 key for signing grants.
+// --mnemonic also prints the key as words to write down.
+fn keygen(args: &cli::KeygenArgs) -> Result<String, String> {
+    let path = &args.out;
+    if args.signing {
+        return grant_file::keygen(path, args.format);
+    } else if args.fido2 {
+        return fido2_device::keygen(path);
+    }
+    let key = match args.asymmetric {
+        true => Identity::generate().map(|identity| identity.to_bytes()),
+        false => encryptor::generate_key(),
+    };
+    let key = Zeroizing::new(key.map_err(|err| err.to_string())?);
+    let written = write_key(path, &key, args.asymmetric, args.format, "a new")?;
+    match args.mnemonic {
+        true => Ok(format!(
+            "{}. Write down these words to restore it with `key import --mnemonic`:\n\n{}",
+            written,
+            *mnemonic::encode(&key)
+        )),
+        false => Ok(written),
+    }
 }
 
-// Function to encrypt a file
-// @dev: Efe
-// &str is a borrowed string slice also called a string slice. It's a reference to a string.
-// str is a string. It's an owned string.
-// &[u8] is a slice of bytes. It's a reference to a byte array.
-// In this case these params are borrowed from the args variable in the main function. ie. the args variable owns the arguments while the main function owns the args variable.
-fn encrypt(password: &str, file_path: &str, nonce: &[u8]) -> Result<(), EncryptError> {
-    // Open the file and read its contents into a vector
-    let mut file = File::open(file_path)?;
-    let mut contents = Vec::new();
-
-    // file.read_to_end(&mut contents)?: This method reads the entire contents of a file into a byte vector (Vec<u8>).
-    // This is useful when you’re working with binary data or when you need the raw bytes from the file.
-    // fs::read_to_string(file_path): This function reads the entire contents of a file into a String.
-    // This is useful when you’re working with text data, as it allows you to easily work with the contents as a String.
-    // The concept of working with raw bytes is particularly relevant to encryption and decryption because these operations often deal with binary data.
-
-    // When you’re encrypting or decrypting data, you’re usually working at a low level where you need to manipulate the raw bytes of the data.
-    // This is because encryption algorithms operate on binary data, transforming the input bytes into a different set of output bytes. Similarly,
-    // decryption algorithms reverse this process, converting the encrypted bytes back into their original form.
-
-    // On the other hand, when you’re reading the number of lines in a text file, you’re typically working with higher-level text data, not raw binary data.
-    // Each line of text in a file is represented as a sequence of characters, and you can count the number of lines by counting the number of newline characters.
-    // This operation doesn’t require dealing with the raw bytes of the file, so the concept of working with byte streams or raw bytes is less applicable in this context.
-
-    // In summary, whether you need to work with raw bytes or higher-level data structures depends on the nature of the task at hand. For low-level tasks
-    // like encryption and decryption, working with raw bytes is often necessary. For higher-level tasks like counting lines in a text file, working with
-    // text data is usually more appropriate.
-
-    // Creating a buffer to hold the encrypted contents
-    file.read_to_end(&mut contents)?;
-
-    // Create a new instance of an unbound key using the AES_256_GCM algorithm and the password bytes.
-    // The `new` function returns a `Result` type, so the `?` operator is used to propagate any potential error.
-    let key = aead::UnboundKey::new(&aead::AES_256_GCM, password.as_bytes())?;
-
-    // Create a new instance of a less safe key from the unbound key.
-    // The `LessSafeKey` is a wrapper around `UnboundKey` that can be used for encryption and decryption operations.
-    // In programming, a wrapper is a class, function, or data structure that contains (or “wraps”) another item to provide a
-    // simpler or more compatible interface.
-    let key = aead::LessSafeKey::new(key);
-
-    // @terminology: In place” is a term used in programming to describe an operation that modifies data directly in the memory where it already resides,
-    // instead of creating a copy of the data and performing the operation on the copy.
-
-    // When an operation is performed “in place”, it means that the original data is modified. This can be more efficient because it avoids the need to
-    // allocate additional memory for a copy of the data. However, it also means that the original data is lost, because it has been overwritten by the
-    // result of the operation.
-
-    // In this Rust code, the seal_in_place_append_tag and open_in_place methods from the ring crate are examples of in-place operations. They encrypt
-    // and decrypt data directly in the buffer where the data already resides, instead of creating a new buffer for the encrypted or decrypted data.
-    // This can make the code more efficient, especially when working with large amounts of data. I hope this helps!
-
-    // Encrypt the contents in place and append the authentication tag
-    key.seal_in_place_append_tag(
-        aead::Nonce::try_assume_unique_for_key(nonce)?,
-        aead::Aad::empty(),
-        &mut contents,
-    )?;
-
-    // Write the encrypted contents to a new file
-    let mut encrypted_file = File::create(format!("{}.enc", file_path))?;
-    encrypted_file.write_all(&contents)?;
+// Write `key` to `path`, or the X25519 identity it is and its public key to <path>.pub, saying what was written
+fn write_key(
+    path: &str,
+    key: &[u8; 32],
+    asymmetric: bool,
+    format: keyfile::Format,
+    what: &str,
+) -> Result<String, String> {
+    if !asymmetric {
+        keyfile::write(path, key, format)?;
+        return Ok(format!("Wrote {} 256-bit key to {}", what, path));
+    }
+    let public_key = Identity::from_bytes(*key).recipient().to_bytes();
+    let public_path = format!("{}.pub", path);
+    keyfile::write(path, key, format)?;
+    keyfile::write(&public_path, &public_key, keyfile::Format::Base64)?;
+    Ok(format!(
+        "Wrote {} X25519 identity to {} and its public key to {}: {}",
+        what,
+        path,
+        public_path,
+        keyfile::encode(&public_key)
+    ))
+}
 
+// Run the known-answer tests of the library, printing every check, and fail if any of them did
+fn selftest(print: &dyn Fn(String)) -> Result<(), String> {
+    print(format!(
+        "encryptor {} with the {} backend",
+        env!("CARGO_PKG_VERSION"),
+        encryptor::selftest::backend()
+    ));
+    let checks = encryptor::selftest::run();
+    for check in &checks {
+        match &check.result {
+            Ok(()) => print(format!("ok     {}", check.name)),
+            Err(reason) => print(format!("failed {}: {}", check.name, reason)),
+        }
+    }
+    let failed = checks.iter().filter(|check| check.result.is_err()).count();
+    if failed > 0 {
+        return Err(format!(
+            "Self-test failed: {} of {} checks; don't use this build",
+            failed,
+            checks.len()
+        ));
+    }
+    print(format!("All {} checks passed", checks.len()));
     Ok(())
 }
 
-// Function to decrypt a file
-fn decrypt(password: &str, file_path: &str, nonce: &[u8]) -> Result<(), EncryptError> {
-    // Open the file and read its contents into a vector
-    let mut file = File::open(file_path)?;
-    let mut contents = Vec::new();
-    file.read_to_end(&mut contents)?;
-
-    // Create a new AES-256-GCM key from the password
-    let key = aead::UnboundKey::new(&aead::AES_256_GCM, password.as_bytes())?;
-    let key = aead::LessSafeKey::new(key);
-
-    // Decrypt the contents in place
-    key.open_in_place(
-        aead::Nonce::try_assume_unique_for_key(nonce)?,
-        aead::Aad::empty(),
-        &mut contents,
-    )?;
-
-    // Determine the file path for the decrypted file
-    let decrypted_file_path = if let Some(index) = file_path.rfind('.') {
-        // Remove the extension from the file name
-        let (name_without_extension, _) = file_path.split_at(index);
-        name_without_extension.to_string()
-    } else {
-        // If there's no extension, use the original name
-        file_path.to_string()
-    };
-    // @explanation:
-    // `if let Some(index) = file_path.rfind('.')`: This line is using the `rfind` method to search for the last occurrence of the period character (`.`) in `file_path`,
-    // which usually indicates the start of the file extension. If a period is found, its index in the string is returned as `Some(index)`.
-    // If no period is found, `rfind` returns `None`.
+// The settings of a file command, collected from its flags by the cli module
+struct Options {
+    algorithm: Algorithm,
+    kdf_params: KdfParams,
+    color: ColorChoice,
+    quiet: bool,
+    dry_run: bool,
+    no_preflight: bool, // `encrypt` and `decrypt` don't check first that their outputs fit
+    progress_fd: Option<i32>,
+    bind_metadata: bool,
+    jobs: Option<usize>,
+    compression: Compression,
+    dict_path: Option<String>,
+    dict_size: Option<usize>,
+    password: Option<Source>,
+    keyfile: Option<String>,
+    fido2: Option<String>,        // The credential file of a FIDO2 security key
+    new_password: Option<Source>, // What `rekey` seals the files under
+    new_keyfile: Option<String>,
+    kdf_target: Option<Duration>,
+    out: Option<PathBuf>,
+    identity: Option<String>,
+    team: Option<String>,
+    recipients: Vec<String>,
+    ssh_recipients: Vec<String>, // --recipient-ssh public keys, converted to X25519
+    header_backup: bool,
+    checksum: bool,           // Record a checksum of the plaintext in each header
+    padding: Option<Padding>, // Pad each payload to hide its exact length
+    checksum_only: bool, // `verify` checks the decrypted files against the checksums instead of decrypting
+    wrap_keys: bool,
+    key_id: Option<String>,
+    rotate_from: Option<String>, // Which key ID `rotate` replaces, if not every other one
+    stream_threshold: u64,
+    chunk_size: u32,
+    format: Format,
+    pbkdf2_iterations: Option<u32>, // --pbkdf2-iter, for --format openssl and decrypting OpenSSL enc files
+    encoder: Arc<dyn Encoder>,
+    shred: bool,
+    remove_to_trash: bool, // `encrypt` moves each original to the trash once its output is written
+    http_headers: Vec<(String, String)>, // Sent with each request of an http(s):// path
+    http_method: String,   // PUT or POST, how `encrypt` uploads to an http(s):// --out
+    storage_class: Option<String>, // The storage class of the objects `encrypt` uploads to an s3:// --out
+    json: bool,
+    report: Option<String>,
+    audit_log: Option<String>,
+    audit_key: Option<String>,
+    manifest: Option<String>,
+    journal: Option<String>,
+    rollback: bool,
+    retry: RetryPolicy,
+    mmap: bool,
+    io_buffer: usize,
+    archive: bool,
+    extract: bool,
+    encrypt_names: bool,
+    name_by_hash: bool,
+    in_place: bool,
+    force: bool,
+    auto_rename: bool,
+    resume: bool,
+    certificates: Vec<String>,
+    private_key: Option<String>,
+    grant_to: Option<String>,
+    signing_key: Option<String>,
+    expires: Option<Duration>,
+    purpose: Option<String>,
+    min_strength: Option<u8>, // Passwords rated below it are refused by `encrypt`
+    grant: Option<String>,
+    grant_issuers: Vec<String>,
+    grant_log: Option<String>,
+    debounce: Duration,      // How long `watch` waits for a file to stop changing
+    split_size: Option<u64>, // The largest volume `encrypt` writes an output as
+    range: Option<(u64, Option<u64>)>, // The offset and length `decrypt` restores, if not the whole file
+    keep_going: bool, // `decrypt` salvages the intact chunks of damaged chunked files
+    legacy_nonce: Option<[u8; 12]>, // The nonce of the headerless files `decrypt --legacy` and `migrate` read
+    git_filter: Option<git_filter::Mode>, // Which way `git-filter` goes
+    text: Option<text::Text>, // What `encrypt-text` and `decrypt-text` read, and where they write
+    vault: Option<vault::Vault>, // Which vault the `vault` commands work on, and what they do with it
+    socket: Option<String>,      // The socket `daemon` listens on
+    ttl: Duration,               // How long `daemon` keeps its key
+    shares: Option<(u8, u8)>, // How many key shares `encrypt` writes, and how many of them decrypt
+    share_out: Option<String>,
+    share_files: Vec<String>, // The key shares `decrypt` rebuilds the key from
+    recovery_code: bool, // `encrypt` adds a recovery code, `decrypt` asks for one instead of the password
+    password_attempts: u32, // How many times `decrypt` asks again for a typed password that fails the password check
+    try_typos: bool, // `decrypt` tries common typos of a typed password that fails the password check
+    password_set: Option<u64>, // When the password `encrypt` seals with was set, if not at the time of encryption
+    max_password_age: Option<Duration>, // The age of a password `decrypt`, `verify` and `report stale-keys` flag
+    incremental: Option<PathBuf>, // The state of `encrypt --incremental`, which only encrypts the files that changed
+    delete: bool,                 // `encrypt --incremental` removes the outputs of deleted files
+    excludes: Vec<glob::Pattern>,
+    filter: PathFilter, // What the directory trees of `encrypt --archive` and `rotate` leave out
+    symlinks: archive::Symlinks, // What `encrypt --archive` does with symbolic links
+    ads: bool,          // `encrypt --archive` packs alternate data streams too
+}
 
-    // `let (name_without_extension, _) = file_path.split_at(index);`: If a period was found, this line splits the `file_path` string into two at the index of the period.
-    // The part before the period (the file name without the extension) is assigned to `name_without_extension`,
-    // and the part after the period (the file extension) is ignored (`_` is a placeholder for ignored values in Rust).
+// The format `encrypt` writes, chosen with --format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    Native,
+    Age,     // age v1, see age_file
+    Jwe,     // The compact JWE serialization, see jwe_file
+    JweJson, // The flattened JSON JWE serialization
+    Cms,     // CMS EnvelopedData, see cms_file
+    Openssl, // `openssl enc -aes-256-cbc -pbkdf2 -salt`, not authenticated, see openssl_file
+}
 
-    // `name_without_extension.to_string()`: This line converts `name_without_extension` from a string slice (`&str`) to a `String` and returns it.
-    // This will be the value of `decrypted_file_path`.
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::Native => "native",
+            Format::Age => "age",
+            Format::Jwe => "jwe",
+            Format::JweJson => "jwe-json",
+            Format::Cms => "cms",
+            Format::Openssl => "openssl",
+        }
+    }
+}
+
+// The password, raw key or X25519 keys every file is encrypted or decrypted with
+enum Secret {
+    Password(Zeroizing<String>, Vec<Recipient>), // Public keys that can decrypt the output as well as the password
+    Key(Zeroizing<[u8; 32]>),
+    Identity(Identity),
+    Recipients(Vec<Recipient>),
+    #[cfg_attr(not(feature = "cms"), allow(dead_code))] // Never loaded without the cms feature
+    Certificates(Box<cms_file::Keys>), // For CMS files only
+    #[cfg_attr(not(feature = "grant"), allow(dead_code))] // Never loaded without the grant feature
+    Grants(Box<grant_file::Grants>), // For decrypting the files the grants cover only
+    #[cfg_attr(not(feature = "fido2"), allow(dead_code))] // Never loaded without the fido2 feature
+    Fido2(Box<Encryptor>), // A credential on a security key; its clones share the secrets it computed
+}
+
+// The first common typo of `password` (see password::typos) that passes the password check of `file`, with how it
+// differs from what was typed, the secret it makes and the Encryptor that checked it
+fn typo_of(
+    password: &str,
+    recipients: &[Recipient],
+    file: &Path,
+) -> Option<(&'static str, Secret, Encryptor)> {
+    password::typos(password)
+        .into_iter()
+        .find_map(|(typo, variant)| {
+            let secret = Secret::Password(variant, recipients.to_vec());
+            let encryptor = secret.encryptor();
+            encryptor
+                .check_password(file)
+                .is_ok()
+                .then_some((typo, secret, encryptor))
+        })
+        .inspect(|(_, secret, _)| {
+            if let Secret::Password(password, _) = secret {
+                transcript::secret(password);
+                harden::protect(password.as_bytes());
+            }
+        })
+}
 
-    // `file_path.to_string()`: If no period was found in `file_path` (i.e., the file has no extension), this line is executed.
-    // It converts `file_path` from a string slice (`&str`) to a `String` and returns it. This will be the value of `decrypted_file_path`.
+impl Secret {
+    fn encryptor(&self) -> Encryptor {
+        match self {
+            Secret::Password(password, recipients) if recipients.is_empty() => {
+                Encryptor::new(password)
+            }
+            Secret::Password(password, recipients) => {
+                Encryptor::new(password).with_extra_recipients(recipients.clone())
+            }
+            Secret::Key(key) => Encryptor::from_key(**key),
+            Secret::Identity(identity) => Encryptor::from_identity(identity.clone()),
+            Secret::Recipients(recipients) => Encryptor::for_recipients(recipients.clone()),
+            // Certificates only work with CMS files (checked in main), so this one never touches a file
+            Secret::Certificates(_) => Encryptor::for_recipients(Vec::new()),
+            // Neither do grants, each file gets the Encryptor of the grant covering it
+            Secret::Grants(_) => Encryptor::for_recipients(Vec::new()),
+            Secret::Fido2(encryptor) => (**encryptor).clone(),
+        }
+    }
+}
 
-    // So, in summary, this code snippet is determining the path for the decrypted file. If the encrypted file has an extension,
-    // it removes the extension to get the original file name. If the encrypted file has no extension, it uses the encrypted file's name as is.
+// The settings of a file command before any flags are applied
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            algorithm: Algorithm::Aes256Gcm,
+            kdf_params: KdfParams::default(),
+            color: ColorChoice::Auto,
+            quiet: false,
+            dry_run: false,
+            no_preflight: false,
+            progress_fd: None,
+            bind_metadata: true,
+            jobs: None,
+            compression: Compression::none(),
+            dict_path: None,
+            dict_size: None,
+            password: None,
+            keyfile: None,
+            fido2: None,
+            new_password: None,
+            new_keyfile: None,
+            kdf_target: None,
+            out: None,
+            identity: None,
+            team: None,
+            recipients: Vec::new(),
+            ssh_recipients: Vec::new(),
+            header_backup: true,
+            checksum: false,
+            padding: None,
+            checksum_only: false,
+            wrap_keys: false,
+            key_id: None,
+            rotate_from: None,
+            stream_threshold: encryptor::stream::DEFAULT_THRESHOLD,
+            chunk_size: encryptor::stream::DEFAULT_CHUNK_SIZE,
+            format: Format::Native,
+            pbkdf2_iterations: None,
+            encoder: Arc::new(encoding::Raw),
+            shred: false,
+            remove_to_trash: false,
+            http_headers: Vec::new(),
+            http_method: "PUT".to_string(),
+            storage_class: None,
+            json: false,
+            report: None,
+            audit_log: None,
+            audit_key: None,
+            manifest: None,
+            journal: None,
+            rollback: false,
+            retry: RetryPolicy::default(),
+            mmap: false,
+            io_buffer: encryptor::progress::DEFAULT_IO_BUFFER,
+            archive: false,
+            extract: false,
+            encrypt_names: false,
+            name_by_hash: false,
+            in_place: false,
+            force: false,
+            auto_rename: false,
+            resume: false,
+            certificates: Vec::new(),
+            private_key: None,
+            grant_to: None,
+            signing_key: None,
+            expires: None,
+            purpose: None,
+            min_strength: None,
+            grant: None,
+            grant_issuers: Vec::new(),
+            grant_log: None,
+            debounce: Duration::ZERO,
+            excludes: Vec::new(),
+            filter: PathFilter::default(),
+            symlinks: archive::Symlinks::Skip,
+            ads: false,
+            split_size: None,
+            range: None,
+            keep_going: false,
+            legacy_nonce: None,
+            git_filter: None,
+            text: None,
+            vault: None,
+            socket: None,
+            ttl: daemon::DEFAULT_TTL,
+            shares: None,
+            share_out: None,
+            share_files: Vec::new(),
+            recovery_code: false,
+            password_attempts: 1,
+            try_typos: false,
+            password_set: None,
+            max_password_age: None,
+            incremental: None,
+            delete: false,
+        }
+    }
+}
 
-    // @explanation: Why need to use `to_string()` for `name_without_extension` and `file_path`?
-    // In this program, the conversion from a string slice (&str) to an owned String is necessary because of the way the decrypted_file_path is used below.
-    // The decrypted_file_path is determined within the decrypt function and is then used to create a new file with File::create(decrypted_file_path)?.
-    // The File::create function requires its argument to be an owned String or something that can be converted into an owned String. A string slice (&str)
-    // would not suffice here because it’s just a borrowed reference, and File::create needs ownership of its argument.
-    // Moreover, the decrypted_file_path is created based on the file_path argument to the decrypt function. If I were to use a string slice that points
-    // into file_path, it would be tied to the lifetime of file_path. If file_path is modified or goes out of scope, the string slice would no longer be valid.
-    // By creating an owned String, I ensure that decrypted_file_path is valid for as long as it needs to be.
+// Function to lock the shared progress sink or manifest. A worker that panicked while holding the lock
+// can't leave either in a harmful state, so a poisoned lock is still used.
+fn lock<T>(shared: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
 
-    // Write the decrypted contents to a new file
-    let mut decrypted_file = File::create(decrypted_file_path)?;
-    decrypted_file.write_all(&contents)?;
+// The directory of the local config (installed teams, the usage history): $ENCRYPTOR_CONFIG_DIR, or `encryptor`
+// in $XDG_CONFIG_HOME, ~/.config or %APPDATA%
+fn config_dir() -> Result<PathBuf, String> {
+    let env = |name: &str| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    env("ENCRYPTOR_CONFIG_DIR")
+        .or_else(|| env("XDG_CONFIG_HOME").map(|dir| dir.join("encryptor")))
+        .or_else(|| env("HOME").map(|dir| dir.join(".config").join("encryptor")))
+        .or_else(|| env("APPDATA").map(|dir| dir.join("encryptor")))
+        .ok_or_else(|| "Cannot find the config directory: set ENCRYPTOR_CONFIG_DIR".to_string())
+}
 
-    Ok(())
+// Encrypt the manifest of an `encrypt --manifest` run, exiting if it can't be written
+fn write_manifest(manifest: Manifest, encryptor: &Encryptor, path: &str, printer: &Printer) {
+    match manifest.write(encryptor, path) {
+        Ok(summary) => printer.ok(&summary),
+        Err(err) => {
+            printer.failed(&format!("Manifest error: {}: {}", path, err));
+            exit(err.code().number());
+        }
+    }
 }
@@ -0,0 +1,169 @@
+// `encrypt --manifest <path>` and `verify --manifest <path> <dir>`: a record of every file a batch or an archive
+// encrypted, to confirm later that a restored tree matches exactly what was backed up. The manifest is JSON,
+// encrypted like any other file with the same password or key, so it can't be altered unnoticed:
+//
+//   {"manifest": 1, "version": "...", "created_at": 1760000000, "roots": ["projects"],
+//    "files": [{"path": "projects/a.txt", "blake3": "...", "size": 1024, "output": "backup.enc"}, ...]}
+//
+// Paths are those inside an archive, or the inputs of a batch as given; an input whose path isn't valid UTF-8 is
+// recorded escaped like a file name in a header (see `encryptor::names`), with "escaped": true. The hash and size are of the plaintext,
+// read just before it was encrypted. `roots` are the directories an archive was made of: verify also reports any
+// file under them that the manifest doesn't list. A batch has none, since it encrypts only the files it's given.
+use encryptor::{names, EncryptError, Encryptor};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const FORMAT_VERSION: u64 = 1;
+
+#[derive(Default)]
+pub struct Manifest {
+    roots: Vec<String>,
+    files: Vec<Value>,
+}
+
+impl Manifest {
+    // A directory of an archive, stored under `name`
+    pub fn add_root(&mut self, name: &str) {
+        self.roots.push(name.to_string());
+    }
+
+    // A file encrypted into `output`, with the hash and size of its plaintext
+    pub fn add_file(&mut self, path: &Path, hash: blake3::Hash, size: u64, output: &Path) {
+        let (path, escaped) = names::record(path.as_os_str());
+        let mut file = json!({
+            "path": path,
+            "blake3": hash.to_hex().as_str(),
+            "size": size,
+            "output": output.to_string_lossy(),
+        });
+        if escaped {
+            file["escaped"] = json!(true);
+        }
+        self.files.push(file);
+    }
+
+    // Encrypt the manifest into `path`, with the files sorted by path
+    pub fn write(mut self, encryptor: &Encryptor, path: &str) -> Result<String, EncryptError> {
+        self.files
+            .sort_by(|a, b| a["path"].as_str().cmp(&b["path"].as_str()));
+        let files = self.files.len();
+        let document = json!({
+            "manifest": FORMAT_VERSION,
+            "version": env!("CARGO_PKG_VERSION"),
+            "created_at": SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            "roots": self.roots,
+            "files": self.files,
+        });
+        fs::write(
+            path,
+            encryptor.encrypt_bytes(document.to_string().as_bytes())?,
+        )?;
+        Ok(format!("Recorded {} files in the manifest {}", files, path))
+    }
+}
+
+// The BLAKE3 hash and the size of the file at `path`
+pub fn hash_file(path: &Path) -> io::Result<(blake3::Hash, u64)> {
+    let mut hasher = blake3::Hasher::new();
+    let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((hasher.finalize(), size))
+}
+
+// Compare the tree restored under `dir` with the manifest at `manifest_path`. Returns how many files the manifest
+// lists, and what differs: files that are missing or changed, and files under its roots that it doesn't list.
+pub fn check(
+    encryptor: &Encryptor,
+    manifest_path: &str,
+    dir: &Path,
+) -> Result<(usize, Vec<String>), EncryptError> {
+    let document: Value =
+        serde_json::from_slice(&encryptor.decrypt_bytes(&fs::read(manifest_path)?)?)
+            .map_err(|_| invalid(format!("{} is not a manifest", manifest_path)))?;
+    if document["manifest"] != FORMAT_VERSION {
+        return Err(invalid(format!(
+            "{} is not a manifest, or is from a newer version",
+            manifest_path
+        )));
+    }
+    let damaged = || invalid(format!("the manifest {} is damaged", manifest_path));
+    let files = document["files"].as_array().ok_or_else(damaged)?;
+
+    let mut listed = BTreeSet::new();
+    let mut differences = Vec::new();
+    for file in files {
+        let (Some(path), Some(hash), Some(size)) = (
+            file["path"].as_str(),
+            file["blake3"].as_str(),
+            file["size"].as_u64(),
+        ) else {
+            return Err(damaged());
+        };
+        listed.insert(path);
+        let path = match file["escaped"] == true {
+            true => PathBuf::from(names::unescape(path).ok_or_else(damaged)?),
+            false => PathBuf::from(path),
+        };
+        let restored = dir.join(path);
+        let difference = match hash_file(&restored) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => "missing".to_string(),
+            Err(err) => format!("cannot be read: {}", err),
+            Ok((_, actual)) if actual != size => {
+                format!("{} bytes instead of {}", actual, size)
+            }
+            Ok((actual, _)) if actual.to_hex().as_str() != hash => "contents differ".to_string(),
+            Ok(_) => continue,
+        };
+        differences.push(format!("{}: {}", restored.display(), difference));
+    }
+
+    for root in document["roots"].as_array().ok_or_else(damaged)? {
+        let root = root.as_str().ok_or_else(damaged)?;
+        let mut found = Vec::new();
+        walk(&dir.join(root), root, &mut found);
+        for path in found {
+            if !listed.contains(path.as_str()) {
+                differences.push(format!(
+                    "{}: not in the manifest",
+                    dir.join(&path).display()
+                ));
+            }
+        }
+    }
+    Ok((files.len(), differences))
+}
+
+// The files under `path` (itself stored as `name`), named like the archive names them. Symlinks are skipped, as
+// archives skip them.
+fn walk(path: &Path, name: &str, files: &mut Vec<String>) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return;
+    };
+    if metadata.is_file() {
+        files.push(name.to_string());
+    }
+    if !metadata.is_dir() {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(path) else {
+        log::warn!("Cannot read the directory {}", path.display());
+        return;
+    };
+    let mut entries = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect::<Vec<_>>();
+    entries.sort();
+    for entry in entries {
+        walk(&path.join(&entry), &format!("{}/{}", name, entry), files);
+    }
+}
+
+fn invalid(message: String) -> EncryptError {
+    EncryptError::IoError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
@@ -0,0 +1,129 @@
+// 256-bit keys as 24 words of the BIP39 English word list, for paper backups: `keygen --mnemonic`, `key export
+// --mnemonic` and `key import --mnemonic`. Like BIP39, the key is followed by the first byte of its SHA-256 hash
+// as a checksum and every 11 bits pick a word, so a mistyped, missing or swapped word is caught instead of
+// restoring a wrong key. Unlike BIP39 the words are the key itself; no seed is derived from them. A word may be
+// given by its first four letters, which are unique in the list, and the numbers printed with the words are
+// skipped when they are typed back.
+use sha2::{Digest, Sha256};
+use std::io::{self, IsTerminal, Read};
+use std::sync::OnceLock;
+use zeroize::Zeroizing;
+
+const KEY_LEN: usize = 32;
+const WORD_COUNT: usize = 24; // 256 key bits and 8 checksum bits, 11 bits a word
+
+// The 2048 words of the BIP39 English word list, in order
+fn words() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| {
+        include_str!("../assets/bip39-english.txt")
+            .lines()
+            .collect()
+    })
+}
+
+// The 24 words of `key`, numbered, six to a line for writing down
+pub fn encode(key: &[u8; KEY_LEN]) -> Zeroizing<String> {
+    let mut bytes = Zeroizing::new([0u8; KEY_LEN + 1]);
+    bytes[..KEY_LEN].copy_from_slice(key);
+    bytes[KEY_LEN] = Sha256::digest(key)[0];
+
+    let mut text = Zeroizing::new(String::new());
+    for word in 0..WORD_COUNT {
+        let index = (0..11).fold(0usize, |index, bit| {
+            let bit = word * 11 + bit;
+            index << 1 | (bytes[bit / 8] >> (7 - bit % 8) & 1) as usize
+        });
+        let separator = match word % 6 {
+            0 if word > 0 => "\n",
+            0 => "",
+            _ => "  ",
+        };
+        text.push_str(&format!(
+            "{}{:>2}. {:<8}",
+            separator,
+            word + 1,
+            words()[index]
+        ));
+    }
+    Zeroizing::new(
+        text.lines()
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+// The key spelled by the words in `text`, checking their number and checksum
+pub fn decode(text: &str) -> Result<Zeroizing<[u8; KEY_LEN]>, String> {
+    let given: Vec<String> = text
+        .split_whitespace()
+        .filter(|token| {
+            !token
+                .trim_end_matches('.')
+                .chars()
+                .all(|c| c.is_ascii_digit())
+        })
+        .map(str::to_lowercase)
+        .collect();
+    let given = Zeroizing::new(given);
+    if given.len() != WORD_COUNT {
+        return Err(format!(
+            "A key is {} words, but {} were given",
+            WORD_COUNT,
+            given.len()
+        ));
+    }
+
+    let mut bytes = Zeroizing::new([0u8; KEY_LEN + 1]);
+    for (word, typed) in given.iter().enumerate() {
+        let index = lookup(typed).ok_or_else(|| {
+            format!(
+                "Word {}, '{}', isn't in the word list; check its spelling",
+                word + 1,
+                typed
+            )
+        })?;
+        for bit in 0..11 {
+            if index >> (10 - bit) & 1 == 1 {
+                let bit = word * 11 + bit;
+                bytes[bit / 8] |= 0x80 >> (bit % 8);
+            }
+        }
+    }
+    let key: [u8; KEY_LEN] = bytes[..KEY_LEN].try_into().expect("33 bytes hold the key");
+    let key = Zeroizing::new(key);
+    if Sha256::digest(&key[..])[0] != bytes[KEY_LEN] {
+        return Err(
+            "The words don't match their checksum: one of them is wrong, or two are swapped"
+                .to_string(),
+        );
+    }
+    Ok(key)
+}
+
+// The index of a word, given in full or by its first four letters
+fn lookup(typed: &str) -> Option<usize> {
+    let words = words();
+    if let Ok(index) = words.binary_search(&typed) {
+        return Some(index);
+    }
+    match typed.len() >= 4 {
+        true => words.iter().position(|word| word.starts_with(typed)),
+        false => None,
+    }
+}
+
+// The words for `key import --mnemonic`: a line typed at the prompt, or everything piped to stdin
+pub fn read() -> Result<Zeroizing<String>, String> {
+    let mut words = Zeroizing::new(String::new());
+    let read = match io::stdin().is_terminal() {
+        true => {
+            eprintln!("Type the {} words, then press Enter:", WORD_COUNT);
+            io::stdin().read_line(&mut words)
+        }
+        false => io::stdin().read_to_string(&mut words),
+    };
+    read.map_err(|err| format!("Cannot read the words: {}", err))?;
+    Ok(words)
+}
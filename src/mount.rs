@@ -0,0 +1,449 @@
+// The `mount` command: an archive made with `encrypt --archive` shown as a read-only filesystem until it is
+// unmounted, so a backup can be browsed and single files copied out of it without extracting everything. The
+// entries of the archive are listed when it is mounted; the contents of a file are only decrypted when it is read,
+// chunk by chunk through SeekableReader. An archive SeekableReader can't open (one written by an earlier build,
+// armored, compressed or sealed in one piece) is decrypted into memory instead.
+//
+// The FUSE protocol is spoken by the fuser crate, which mounts with mount(2) when run as root and otherwise through
+// the fusermount3 (or fusermount) helper of the fuse package. Linux only, with the `fuse` cargo feature. It is
+// unmounted on Ctrl-C, or from elsewhere with `umount` or `fusermount3 -u`.
+use crate::output::Printer;
+use encryptor::{EncryptError, Encryptor};
+use std::io;
+use std::path::Path;
+
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use crate::archive::{self, Entry};
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use crate::streamed::from_io;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use encryptor::SeekableReader;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use fuser::{
+    AccessFlags, Config, Errno, FileAttr, FileHandle, FileType, FopenFlags, Generation, INodeNo,
+    MountOption, OpenAccMode, OpenFlags, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty,
+    ReplyEntry, ReplyOpen, ReplyStatfs, Request,
+};
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use std::collections::HashMap;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use std::ffi::OsStr;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use std::fs::{self, File};
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use std::io::{Cursor, Read, Seek, SeekFrom};
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use std::sync::Mutex;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+use std::time::{Duration, UNIX_EPOCH};
+
+// How long the kernel may cache names and attributes, which never change while mounted
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+const VALID: Duration = Duration::from_secs(3600);
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+const ROOT: u64 = 1;
+
+// Set by SIGINT and SIGTERM, to unmount and stop
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+static STOP: AtomicBool = AtomicBool::new(false);
+
+// Mount the archive at `file_path` on the directory `mountpoint` and serve it until it is unmounted or the process
+// is stopped, when it is unmounted too.
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+pub fn run(
+    encryptor: &Encryptor,
+    file_path: &Path,
+    mountpoint: &Path,
+    printer: &Printer,
+) -> Result<(), EncryptError> {
+    if !mountpoint.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "the mount point {} is not a directory",
+                mountpoint.display()
+            ),
+        )
+        .into());
+    }
+    let mut contents: Box<dyn Contents> =
+        match SeekableReader::new(File::open(file_path)?, encryptor) {
+            Ok(reader) => Box::new(reader),
+            Err(err) => {
+                log::debug!("Decrypting {} into memory: {}", file_path.display(), err);
+                Box::new(Cursor::new(encryptor.decrypt_bytes(&fs::read(file_path)?)?))
+            }
+        };
+    let entries = archive::entries(&mut contents).map_err(|err| match err {
+        EncryptError::IoError(err) => from_io(err),
+        err => err,
+    })?;
+    let mtime = fs::metadata(file_path)?
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs() as i64);
+    let filesystem = Filesystem::new(entries, mtime, contents, printer.clone());
+
+    let mountpoint = fs::canonicalize(mountpoint)?;
+    let mut config = Config::default();
+    config.mount_options = vec![
+        MountOption::RO,
+        MountOption::NoSuid,
+        MountOption::NoDev,
+        MountOption::DefaultPermissions,
+        MountOption::FSName("encryptor".to_string()),
+        MountOption::Subtype("encryptor".to_string()),
+    ];
+    let session = fuser::spawn_mount(filesystem, &mountpoint, &config)
+        .map_err(|err| mount_error(err, &mountpoint))?;
+    stop_on_signals();
+    printer.note(&format!(
+        "Mounted {} read-only on {}; unmount with Ctrl-C or `umount {}`",
+        file_path.display(),
+        mountpoint.display(),
+        mountpoint.display()
+    ));
+    // Requests are answered on fuser's thread until the filesystem is unmounted from elsewhere, or the process is
+    // stopped and unmounts it
+    while !STOP.load(Ordering::SeqCst) && !session.guard.is_finished() {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    match session.guard.is_finished() {
+        true => session.join()?,
+        false => session.umount_and_join()?,
+    }
+    printer.ok(&format!("Unmounted {}", file_path.display()));
+    Ok(())
+}
+
+#[cfg(not(all(feature = "fuse", target_os = "linux")))]
+pub fn run(
+    _encryptor: &Encryptor,
+    _file_path: &Path,
+    _mountpoint: &Path,
+    _printer: &Printer,
+) -> Result<(), EncryptError> {
+    Err(EncryptError::IoError(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "mounting archives is not supported by this build (enable the 'fuse' feature, on Linux)",
+    )))
+}
+
+// Where the container of the archive is read from
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+trait Contents: Read + Seek + Send {}
+
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+impl<T: Read + Seek + Send> Contents for T {}
+
+// A file or directory of the mounted tree; its inode number is its index in Filesystem::nodes plus one
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+struct Node {
+    path: String,
+    directory: bool,
+    mode: u32,
+    mtime: i64,
+    offset: u64,
+    len: u64,
+    parent: u64,
+    children: Vec<u64>,
+}
+
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+struct Filesystem {
+    nodes: Vec<Node>,
+    // The inode of each name in each directory
+    names: HashMap<(u64, String), u64>,
+    contents: Mutex<Box<dyn Contents>>,
+    uid: u32,
+    gid: u32,
+    printer: Printer, // For the reads that fail
+}
+
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+impl Filesystem {
+    // The tree of `entries`, under a root directory dated `mtime`. A directory an entry is in but that isn't
+    // archived itself is added; an entry archived twice is the later one.
+    fn new(entries: Vec<Entry>, mtime: i64, contents: Box<dyn Contents>, printer: Printer) -> Self {
+        let mut filesystem = Filesystem {
+            nodes: vec![Node {
+                path: String::new(),
+                directory: true,
+                mode: 0o555,
+                mtime,
+                offset: 0,
+                len: 0,
+                parent: ROOT,
+                children: Vec::new(),
+            }],
+            names: HashMap::new(),
+            contents: Mutex::new(contents),
+            // SAFETY: getuid and getgid can't fail
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            printer,
+        };
+        for entry in entries {
+            let mut parent = ROOT;
+            let mut names = entry.path.split('/').peekable();
+            while let Some(name) = names.next() {
+                let last = names.peek().is_none();
+                let path = match &filesystem.nodes[parent as usize - 1].path {
+                    path if path.is_empty() => name.to_string(),
+                    path => format!("{}/{}", path, name),
+                };
+                let node = Node {
+                    path,
+                    directory: !last || entry.directory,
+                    mode: if last { entry.mode } else { 0o755 },
+                    mtime: if last { entry.mtime } else { mtime },
+                    offset: entry.offset,
+                    len: if last { entry.len } else { 0 },
+                    parent,
+                    children: Vec::new(),
+                };
+                parent = match filesystem.names.get(&(parent, name.to_string())) {
+                    Some(&ino) if last => {
+                        let children =
+                            std::mem::take(&mut filesystem.nodes[ino as usize - 1].children);
+                        filesystem.nodes[ino as usize - 1] = Node { children, ..node };
+                        ino
+                    }
+                    Some(&ino) => ino,
+                    None => {
+                        filesystem.nodes.push(node);
+                        let ino = filesystem.nodes.len() as u64;
+                        filesystem.nodes[parent as usize - 1].children.push(ino);
+                        filesystem.names.insert((parent, name.to_string()), ino);
+                        ino
+                    }
+                };
+            }
+        }
+        filesystem
+    }
+
+    fn node(&self, ino: u64) -> Option<&Node> {
+        ino.checked_sub(1)
+            .and_then(|index| self.nodes.get(index as usize))
+    }
+
+    // The inode of `name` in the directory `parent`
+    fn child(&self, parent: u64, name: &OsStr) -> Result<u64, Errno> {
+        let name = name.to_str().ok_or(Errno::ENOENT)?;
+        match name {
+            "." => Ok(parent),
+            ".." => Ok(self.node(parent).ok_or(Errno::ENOENT)?.parent),
+            name => self
+                .names
+                .get(&(parent, name.to_string()))
+                .copied()
+                .ok_or(Errno::ENOENT),
+        }
+    }
+
+    // The attributes of a node, with the write permissions taken away
+    fn attr(&self, ino: u64) -> Result<FileAttr, Errno> {
+        let node = self.node(ino).ok_or(Errno::ENOENT)?;
+        let (kind, nlink, default) = match node.directory {
+            true => (FileType::Directory, 2, 0o555),
+            false => (FileType::RegularFile, 1, 0o444),
+        };
+        // Archives made on Windows record no permissions
+        let perm = match node.mode & 0o7777 {
+            0 => default,
+            mode => mode & !0o222,
+        };
+        let mtime = match u64::try_from(node.mtime) {
+            Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+            Err(_) => UNIX_EPOCH
+                .checked_sub(Duration::from_secs(node.mtime.unsigned_abs()))
+                .unwrap_or(UNIX_EPOCH),
+        };
+        Ok(FileAttr {
+            ino: INodeNo(ino),
+            size: node.len,
+            blocks: node.len.div_ceil(512),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: mtime,
+            kind,
+            perm: perm as u16,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        })
+    }
+
+    // `size` bytes of the file `ino` from `offset`, fewer at its end
+    fn read_at(&self, ino: u64, offset: u64, size: u32) -> Result<Vec<u8>, Errno> {
+        let node = self.node(ino).ok_or(Errno::ENOENT)?;
+        if node.directory {
+            return Err(Errno::EISDIR);
+        }
+        let mut data = vec![0; node.len.saturating_sub(offset).min(size as u64) as usize];
+        let mut contents = self.contents.lock().unwrap_or_else(|e| e.into_inner());
+        let read = contents
+            .seek(SeekFrom::Start(node.offset + offset))
+            .and_then(|_| contents.read_exact(&mut data));
+        match read {
+            Ok(()) => Ok(data),
+            Err(err) => {
+                let message = format!("Read error: {}: {}", node.path, from_io(err));
+                self.printer.warn(&message);
+                Err(Errno::EIO)
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+impl fuser::Filesystem for Filesystem {
+    fn lookup(&self, _req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        match self.child(parent.0, name).and_then(|ino| self.attr(ino)) {
+            Ok(attr) => reply.entry(&VALID, &attr, Generation(0)),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn getattr(&self, _req: &Request, ino: INodeNo, _fh: Option<FileHandle>, reply: ReplyAttr) {
+        match self.attr(ino.0) {
+            Ok(attr) => reply.attr(&VALID, &attr),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn open(&self, _req: &Request, ino: INodeNo, flags: OpenFlags, reply: ReplyOpen) {
+        match self.node(ino.0) {
+            None => reply.error(Errno::ENOENT),
+            Some(node) if node.directory => reply.error(Errno::EISDIR),
+            Some(_) if flags.acc_mode() != OpenAccMode::O_RDONLY => reply.error(Errno::EROFS),
+            // The page cache is kept from one open to the next, since nothing changes
+            Some(_) => reply.opened(FileHandle(0), FopenFlags::FOPEN_KEEP_CACHE),
+        }
+    }
+
+    fn read(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        size: u32,
+        _flags: OpenFlags,
+        _lock_owner: Option<fuser::LockOwner>,
+        reply: ReplyData,
+    ) {
+        match self.read_at(ino.0, offset, size) {
+            Ok(data) => reply.data(&data),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn opendir(&self, _req: &Request, ino: INodeNo, _flags: OpenFlags, reply: ReplyOpen) {
+        match self.node(ino.0) {
+            Some(node) if node.directory => reply.opened(FileHandle(0), FopenFlags::empty()),
+            Some(_) => reply.error(Errno::ENOTDIR),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    // ".", ".." and the children of the directory, from the offset asked for until the reply is full
+    fn readdir(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        mut reply: ReplyDirectory,
+    ) {
+        let node = match self.node(ino.0) {
+            Some(node) if node.directory => node,
+            Some(_) => return reply.error(Errno::ENOTDIR),
+            None => return reply.error(Errno::ENOENT),
+        };
+        let entries =
+            [(ino.0, "."), (node.parent, "..")]
+                .into_iter()
+                .chain(node.children.iter().map(|&child| {
+                    (
+                        child,
+                        self.nodes[child as usize - 1]
+                            .path
+                            .rsplit('/')
+                            .next()
+                            .unwrap_or_default(),
+                    )
+                }));
+        for (index, (child, name)) in entries.enumerate().skip(offset as usize) {
+            let kind = match self.nodes[child as usize - 1].directory {
+                true => FileType::Directory,
+                false => FileType::RegularFile,
+            };
+            if reply.add(INodeNo(child), index as u64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    // A full filesystem as large as the files in it
+    fn statfs(&self, _req: &Request, _ino: INodeNo, reply: ReplyStatfs) {
+        let blocks: u64 = self.nodes.iter().map(|node| node.len.div_ceil(4096)).sum();
+        reply.statfs(blocks, 0, 0, self.nodes.len() as u64, 0, 4096, 255, 4096);
+    }
+
+    fn access(&self, _req: &Request, _ino: INodeNo, mask: AccessFlags, reply: ReplyEmpty) {
+        match mask.contains(AccessFlags::W_OK) {
+            true => reply.error(Errno::EROFS),
+            false => reply.ok(),
+        }
+    }
+}
+
+// Have SIGINT and SIGTERM set STOP, rather than kill the process with the filesystem still mounted
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+fn stop_on_signals() {
+    extern "C" fn stop(_signal: libc::c_int) {
+        STOP.store(true, Ordering::SeqCst);
+    }
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler only stores to an atomic
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+// Why mounting failed, in words for the usual reasons
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+fn mount_error(err: io::Error, mountpoint: &Path) -> EncryptError {
+    let reason = match err.raw_os_error() {
+        Some(libc::ENODEV) => "the kernel has no FUSE support (try `modprobe fuse`)".to_string(),
+        Some(libc::EPERM) => {
+            "not allowed to mount here (in a container, it needs CAP_SYS_ADMIN)".to_string()
+        }
+        Some(libc::EBUSY) => "something is already mounted there".to_string(),
+        // fuser runs fusermount3 or fusermount when mount(2) isn't allowed
+        None if err.kind() == io::ErrorKind::NotFound => {
+            "mounting as another user than root needs fusermount3 or fusermount (from the fuse package)"
+                .to_string()
+        }
+        _ => err.to_string(),
+    };
+    io::Error::new(
+        err.kind(),
+        format!("cannot mount on {}: {}", mountpoint.display(), reason),
+    )
+    .into()
+}
@@ -0,0 +1,216 @@
+//! File names as the header records them, and the output paths derived from input paths, for any name the file
+//! system allows, valid UTF-8 or not.
+//!
+//! A name is recorded as text. A valid UTF-8 name is recorded as it is, as it always was. In any other name each
+//! backslash becomes `\\`, each byte that isn't part of valid UTF-8 `\xNN` (Unix) and each unpaired surrogate
+//! `\uNNNN` (Windows), and the header says so with [`FLAG_ESCAPED_NAME`](crate::header::FLAG_ESCAPED_NAME). The
+//! escaped text is the same on every platform, so a name is checked the same way wherever it is decrypted, but it
+//! only turns back into the same name on the kind of system it came from; elsewhere an escape becomes U+FFFD.
+//!
+//! ```
+//! use std::ffi::OsStr;
+//! use std::path::Path;
+//!
+//! assert_eq!(encryptor::names::record(OsStr::new("notes.txt")), ("notes.txt".into(), false));
+//! assert_eq!(encryptor::names::strip_extension(Path::new("dir/notes.txt.enc")), Path::new("dir/notes.txt"));
+//! assert_eq!(encryptor::names::long_path(Path::new("dir/notes.txt")), Path::new("dir/notes.txt"));
+//! ```
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+#[cfg(any(unix, windows))]
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// The name of `path` without any directories, or the whole path if it has no name (such as `..`)
+pub fn file_name(path: &Path) -> &OsStr {
+    path.file_name().unwrap_or(path.as_os_str())
+}
+
+/// `name` as the header records it, and whether it had to be escaped
+pub fn record(name: &OsStr) -> (Cow<'_, str>, bool) {
+    match name.to_str() {
+        Some(name) => (Cow::Borrowed(name), false),
+        None => (Cow::Owned(escape(name)), true),
+    }
+}
+
+/// The name whose escaped text is `name`, or None if it isn't escaped text
+pub fn unescape(name: &str) -> Option<OsString> {
+    let mut units = Units::default();
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            units.char(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => units.char('\\'),
+            'x' => units.byte(u8::from_str_radix(&take(&mut chars, 2)?, 16).ok()?),
+            'u' => units.surrogate(u16::from_str_radix(&take(&mut chars, 4)?, 16).ok()?),
+            _ => return None,
+        }
+    }
+    Some(units.finish())
+}
+
+/// The path with `suffix` added to its last component: `notes.txt` and `.enc` give `notes.txt.enc`
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// The path without its last extension, which is where decrypting a file writes by default. A path without a dot
+/// is returned as it is.
+pub fn strip_extension(path: &Path) -> &Path {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    match bytes.iter().rposition(|byte| *byte == b'.') {
+        // SAFETY: the bytes come from as_encoded_bytes and are split right before an ASCII '.'
+        Some(index) => Path::new(unsafe { OsStr::from_encoded_bytes_unchecked(&bytes[..index]) }),
+        None => path,
+    }
+}
+
+/// The stem and the last extension of `name`, a file name: `report.pdf.enc` gives `report.pdf` and `.enc`. A name
+/// starting with its only dot has no extension.
+pub fn split_extension(name: &OsStr) -> (&OsStr, &OsStr) {
+    let bytes = name.as_encoded_bytes();
+    match bytes.iter().rposition(|byte| *byte == b'.') {
+        // SAFETY: the bytes come from as_encoded_bytes and are split right before an ASCII '.'
+        Some(index) if index > 0 => unsafe {
+            (
+                OsStr::from_encoded_bytes_unchecked(&bytes[..index]),
+                OsStr::from_encoded_bytes_unchecked(&bytes[index..]),
+            )
+        },
+        _ => (name, OsStr::new("")),
+    }
+}
+
+/// `path` in the `\\?\` form that lifts the 260-character limit of Windows paths, if it is that long once
+/// absolute. Shorter paths, and every path on other systems, are returned as they are, so they print the same.
+/// Windows takes the components of such a path literally, so it is made absolute and normalized first.
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    const MAX_PATH: usize = 260;
+    let bytes = path.as_os_str().as_encoded_bytes();
+    // Already verbatim, or a device
+    if bytes.starts_with(br"\\?\") || bytes.starts_with(br"\\.\") {
+        return Cow::Borrowed(path);
+    }
+    let absolute = match std::path::absolute(path) {
+        Ok(absolute) if absolute.as_os_str().len() >= MAX_PATH => absolute.into_os_string(),
+        _ => return Cow::Borrowed(path),
+    };
+    let mut verbatim = OsString::new();
+    match absolute.as_encoded_bytes().strip_prefix(br"\\") {
+        // \\server\share\... becomes \\?\UNC\server\share\...
+        Some(share) => {
+            verbatim.push(r"\\?\UNC\");
+            // SAFETY: the bytes come from as_encoded_bytes and are split right after an ASCII '\'
+            verbatim.push(unsafe { OsStr::from_encoded_bytes_unchecked(share) });
+        }
+        None => {
+            verbatim.push(r"\\?\");
+            verbatim.push(&absolute);
+        }
+    }
+    Cow::Owned(PathBuf::from(verbatim))
+}
+
+/// `path` in the `\\?\` form that lifts the 260-character limit of Windows paths, if it is that long once
+/// absolute. Shorter paths, and every path on other systems, are returned as they are, so they print the same.
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+// The next `count` characters of an escape
+fn take(chars: &mut std::str::Chars, count: usize) -> Option<String> {
+    let digits: String = chars.take(count).collect();
+    (digits.len() == count).then_some(digits)
+}
+
+#[cfg(unix)]
+fn escape(name: &OsStr) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut escaped = String::new();
+    for chunk in name.as_bytes().utf8_chunks() {
+        escaped.push_str(&chunk.valid().replace('\\', "\\\\"));
+        for byte in chunk.invalid() {
+            let _ = write!(escaped, "\\x{:02x}", byte);
+        }
+    }
+    escaped
+}
+
+#[cfg(windows)]
+fn escape(name: &OsStr) -> String {
+    use std::os::windows::ffi::OsStrExt;
+
+    let mut escaped = String::new();
+    for unit in char::decode_utf16(name.encode_wide()) {
+        match unit {
+            Ok('\\') => escaped.push_str("\\\\"),
+            Ok(c) => escaped.push(c),
+            Err(err) => {
+                let _ = write!(escaped, "\\u{:04x}", err.unpaired_surrogate());
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(not(any(unix, windows)))]
+fn escape(name: &OsStr) -> String {
+    name.to_string_lossy().replace('\\', "\\\\")
+}
+
+// A name being unescaped, in the units of this platform's names
+#[derive(Default)]
+struct Units {
+    #[cfg(unix)]
+    bytes: Vec<u8>,
+    #[cfg(not(unix))]
+    wide: Vec<u16>,
+}
+
+impl Units {
+    fn char(&mut self, c: char) {
+        #[cfg(unix)]
+        self.bytes
+            .extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        #[cfg(not(unix))]
+        self.wide.extend_from_slice(c.encode_utf16(&mut [0; 2]));
+    }
+
+    // A byte of a Unix name, which a name here can only have if it is one
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn byte(&mut self, byte: u8) {
+        #[cfg(unix)]
+        self.bytes.push(byte);
+        #[cfg(not(unix))]
+        self.char(char::REPLACEMENT_CHARACTER);
+    }
+
+    // A surrogate of a Windows name, which a name here can only have if it is one
+    fn surrogate(&mut self, unit: u16) {
+        #[cfg(windows)]
+        self.wide.push(unit);
+        #[cfg(not(windows))]
+        {
+            let _ = unit;
+            self.char(char::REPLACEMENT_CHARACTER);
+        }
+    }
+
+    fn finish(self) -> OsString {
+        #[cfg(unix)]
+        return std::os::unix::ffi::OsStringExt::from_vec(self.bytes);
+        #[cfg(windows)]
+        return std::os::windows::ffi::OsStringExt::from_wide(&self.wide);
+        #[cfg(not(any(unix, windows)))]
+        return OsString::from(String::from_utf16_lossy(&self.wide));
+    }
+}
@@ -0,0 +1,129 @@
+//! Catching nonce reuse across the outputs of a run.
+//!
+//! Every nonce is random, so two outputs under the same key only share one when the random number generator is
+//! broken, or was made to repeat itself, e.g. by restoring the same VM snapshot twice. Reusing a nonce with
+//! AES-256-GCM or ChaCha20-Poly1305 leaks the XOR of the two plaintexts and lets anyone forge messages, so a
+//! [`NonceLog`] given to [`Encryptor::with_nonce_log`](crate::Encryptor::with_nonce_log) remembers every nonce sealed
+//! under each key and turns a repeat into [`EncryptError::NonceReuse`] before anything is sealed with it.
+//!
+//! The chunks of a chunked payload all share the first 7 bytes of the header's nonce (see the [`stream`](crate::stream)
+//! module), so for those the prefix is what is recorded: a chunked payload is refused when any other output under
+//! the key used the same prefix, and a payload sealed in one piece when its nonce starts with the prefix of a chunked
+//! one. Within one payload, the chunk counter refuses to wrap around instead.
+//!
+//! ```no_run
+//! use encryptor::{Encryptor, NonceLog};
+//!
+//! // One log for the whole batch, shared by every Encryptor in it
+//! let nonces = NonceLog::new();
+//! let encryptor = Encryptor::from_key(encryptor::generate_key()?).with_nonce_log(nonces.clone());
+//! encryptor.encrypt_file("a.txt")?;
+//! encryptor.encrypt_file("b.txt")?;
+//! # Ok::<(), encryptor::EncryptError>(())
+//! ```
+//!
+//! The log grows with every output, so it is meant for a run of bounded length, like one invocation of the command
+//! line tool. A long-running service should start a new log from time to time.
+use crate::{EncryptError, KEY_LEN, NONCE_LEN};
+use blake2::{Blake2s256, Digest};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// The part of the nonce that every chunk of a chunked payload shares
+const PREFIX_LEN: usize = 7;
+
+/// Every nonce sealed under each key so far. Clones share the same log.
+#[derive(Clone, Default)]
+pub struct NonceLog {
+    // By a fingerprint of each key, so that the log doesn't keep a copy of every key it has seen for the whole run
+    inner: Arc<Mutex<HashMap<[u8; 32], Used>>>,
+}
+
+// The nonces used under one key, each with the number of the output that used it
+#[derive(Default)]
+struct Used {
+    outputs: u64,
+    whole: HashMap<[u8; NONCE_LEN], u64>, // Payloads sealed in one piece, and wrapped file keys
+    whole_prefixes: HashMap<[u8; PREFIX_LEN], u64>, // The prefixes of those nonces
+    chunked: HashMap<[u8; PREFIX_LEN], u64>, // Chunked payloads
+}
+
+impl NonceLog {
+    /// An empty log
+    pub fn new() -> Self {
+        NonceLog::default()
+    }
+
+    // Record that `nonce` is about to seal something under `key`, or every chunk of a payload when `chunked`,
+    // failing if an earlier output already used it
+    pub(crate) fn record(
+        &self,
+        key: &[u8; KEY_LEN],
+        nonce: &[u8],
+        chunked: bool,
+    ) -> Result<(), EncryptError> {
+        let (Ok(whole), Some(Ok(prefix))) = (
+            <[u8; NONCE_LEN]>::try_from(nonce),
+            nonce.get(..PREFIX_LEN).map(<[u8; PREFIX_LEN]>::try_from),
+        ) else {
+            return Ok(());
+        };
+
+        // A poisoned lock only means another thread panicked while recording; the maps are still consistent
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let used = inner.entry(fingerprint(key)).or_default();
+        let earlier = match chunked {
+            true => used
+                .chunked
+                .get(&prefix)
+                .or_else(|| used.whole_prefixes.get(&prefix)),
+            false => used.whole.get(&whole).or_else(|| used.chunked.get(&prefix)),
+        };
+        if let Some(earlier) = earlier {
+            let reused = match chunked {
+                true => format!("nonce prefix {}", hex(&prefix)),
+                false => format!("nonce {}", hex(&whole)),
+            };
+            return Err(EncryptError::NonceReuse(format!(
+                "output {} under this key would reuse the {} of output {}, so the random number generator \
+                 can't be trusted; nothing was sealed with it",
+                used.outputs + 1,
+                reused,
+                earlier
+            )));
+        }
+
+        used.outputs += 1;
+        let output = used.outputs;
+        if chunked {
+            used.chunked.insert(prefix, output);
+        } else {
+            used.whole.insert(whole, output);
+            used.whole_prefixes.entry(prefix).or_insert(output);
+        }
+        Ok(())
+    }
+}
+
+// Only fingerprints of the keys are kept, and only how many there are is shown
+impl std::fmt::Debug for NonceLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys = self.inner.lock().map_or(0, |inner| inner.len());
+        f.debug_struct("NonceLog").field("keys", &keys).finish()
+    }
+}
+
+// A one-way fingerprint of `key`, which tells keys apart without revealing them
+fn fingerprint(key: &[u8; KEY_LEN]) -> [u8; 32] {
+    Blake2s256::new_with_prefix(b"encryptor nonce log")
+        .chain_update(key)
+        .finalize()
+        .into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
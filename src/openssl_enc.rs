@@ -0,0 +1,113 @@
+//! Files in the format of `openssl enc -aes-256-cbc -pbkdf2 -salt`, to exchange data with systems where only the
+//! OpenSSL command line is available.
+//!
+//! This is a compatibility mode and **not authenticated encryption**: the content is AES-256-CBC with PKCS#7 padding
+//! and no MAC. Changes to a file go unnoticed, turning into changed plaintext, and a wrong password is only caught
+//! when the padding it decrypts to happens to be invalid, which it isn't once in about 256 tries. Use the native
+//! format whenever both ends run encryptor.
+//!
+//! A file is `Salted__`, an 8-byte random salt and the ciphertext. The 32-byte key and 16-byte IV are the 48 bytes
+//! PBKDF2-HMAC-SHA256 derives from the password and salt. The iteration count isn't recorded, so both ends must use
+//! the same one: [`DEFAULT_ITERATIONS`] unless OpenSSL was given `-iter`. `openssl enc -a` writes the same bytes in
+//! base64, 64 characters a line, which [`decrypt`] reads as well.
+use crate::header::HeaderError;
+use crate::{cipher, CryptoError, EncryptError};
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+/// How every file starts, before its salt
+pub const MAGIC: &[u8; 8] = b"Salted__";
+/// How a file written with `openssl enc -a` starts: the base64 of [`MAGIC`] and the first bits of the salt
+pub const BASE64_MAGIC: &[u8; 10] = b"U2FsdGVkX1";
+/// The PBKDF2 iterations of `openssl enc -pbkdf2` without `-iter`
+pub const DEFAULT_ITERATIONS: u32 = 10_000;
+
+const SALT_LEN: usize = 8;
+const KEY_LEN: usize = 32;
+const IV_LEN: usize = 16;
+const LINE_LEN: usize = 64;
+
+type CbcEncryptor = cbc::Encryptor<aes::Aes256>;
+type CbcDecryptor = cbc::Decryptor<aes::Aes256>;
+
+/// Encrypt `plaintext` under `password` with a fresh random salt, as `openssl enc -aes-256-cbc -pbkdf2 -salt -iter
+/// <iterations>` would
+pub fn encrypt(plaintext: &[u8], password: &str, iterations: u32) -> Result<Vec<u8>, EncryptError> {
+    let mut salt = [0u8; SALT_LEN];
+    cipher::fill_random(&mut salt)?;
+    let key = derive(password, &salt, iterations)?;
+    let ciphertext = CbcEncryptor::new_from_slices(&key[..KEY_LEN], &key[KEY_LEN..])
+        .map_err(|_| EncryptError::AeadError(CryptoError))?
+        .encrypt_padded_vec_mut::<Pkcs7>(plaintext);
+    Ok([&MAGIC[..], &salt, &ciphertext].concat())
+}
+
+/// Decrypt a file written by `openssl enc -aes-256-cbc -pbkdf2 -salt`, raw or in base64. Without a MAC, a wrong
+/// password or a changed file usually fails with [`EncryptError::AeadError`], but not always.
+pub fn decrypt(data: &[u8], password: &str, iterations: u32) -> Result<Vec<u8>, EncryptError> {
+    let decoded;
+    let data = match data.starts_with(BASE64_MAGIC) {
+        true => {
+            let text: Vec<u8> = data
+                .iter()
+                .copied()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .collect();
+            decoded = STANDARD
+                .decode(text)
+                .map_err(|_| invalid("damaged base64"))?;
+            &decoded[..]
+        }
+        false => data,
+    };
+    let rest = data.strip_prefix(&MAGIC[..]).ok_or_else(|| {
+        invalid("no Salted__ header; files written without -salt aren't supported")
+    })?;
+    if rest.len() < SALT_LEN + IV_LEN || !(rest.len() - SALT_LEN).is_multiple_of(IV_LEN) {
+        return Err(invalid("the file is truncated"));
+    }
+    let (salt, ciphertext) = rest.split_at(SALT_LEN);
+    let key = derive(password, salt, iterations)?;
+    CbcDecryptor::new_from_slices(&key[..KEY_LEN], &key[KEY_LEN..])
+        .map_err(|_| EncryptError::AeadError(CryptoError))?
+        .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        .map_err(|_| EncryptError::AeadError(CryptoError))
+}
+
+/// `data` in base64, 64 characters a line with a newline after the last, as `openssl enc -a` writes it
+pub fn to_base64(data: &[u8]) -> Vec<u8> {
+    let encoded = STANDARD.encode(data);
+    let mut text = Vec::with_capacity(encoded.len() + encoded.len() / LINE_LEN + 1);
+    for line in encoded.as_bytes().chunks(LINE_LEN) {
+        text.extend_from_slice(line);
+        text.push(b'\n');
+    }
+    text
+}
+
+/// Whether `data` looks like the output of `openssl enc -salt`, raw or in base64
+pub fn is_openssl(data: &[u8]) -> bool {
+    data.starts_with(MAGIC) || data.starts_with(BASE64_MAGIC)
+}
+
+// The key and IV, one after the other
+fn derive(
+    password: &str,
+    salt: &[u8],
+    iterations: u32,
+) -> Result<Zeroizing<[u8; KEY_LEN + IV_LEN]>, EncryptError> {
+    if iterations == 0 {
+        return Err(invalid("the PBKDF2 iteration count must be at least 1"));
+    }
+    let mut key = Zeroizing::new([0u8; KEY_LEN + IV_LEN]);
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut *key);
+    Ok(key)
+}
+
+fn invalid(reason: &str) -> EncryptError {
+    HeaderError::InvalidEncoding(format!("OpenSSL enc: {}", reason)).into()
+}
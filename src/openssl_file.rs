@@ -0,0 +1,104 @@
+// Files in the format of `openssl enc -aes-256-cbc -pbkdf2 -salt`, for `encrypt --format openssl` and decrypting
+// such files, with a password only. A compatibility mode without authentication (see the `encryptor::openssl_enc`
+// module), for systems where only OpenSSL is available. Needs the `openssl-enc` cargo feature; without it these files
+// are still detected, but encrypting or decrypting them fails with an explanation.
+#[cfg(feature = "openssl-enc")]
+use crate::streamed;
+use crate::streamed::Existing;
+use crate::Secret;
+use encryptor::EncryptError;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// Whether the file at `path` was written by `openssl enc -salt` (with -a or without) rather than one of ours
+pub fn is_openssl(path: &Path) -> bool {
+    let mut start = [0u8; 10];
+    let read = fs::File::open(path).and_then(|mut file| file.read_exact(&mut start));
+    read.is_ok() && (start.starts_with(b"Salted__") || start.starts_with(b"U2FsdGVkX1"))
+}
+
+// Encrypt the file at `file_path` into `<file_path>.enc` with PBKDF2 `iterations` (OpenSSL's default without), in
+// base64 with `base64` like `openssl enc -a`, returning the path written
+#[cfg(feature = "openssl-enc")]
+pub fn encrypt(
+    file_path: &Path,
+    secret: &Secret,
+    iterations: Option<u32>,
+    base64: bool,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    use encryptor::openssl_enc;
+
+    let iterations = iterations.unwrap_or(openssl_enc::DEFAULT_ITERATIONS);
+    let password = password(secret, "encrypted")?;
+    let plaintext = fs::read(file_path)?;
+    let mut output = openssl_enc::encrypt(&plaintext, password, iterations)?;
+    if base64 {
+        output = openssl_enc::to_base64(&output);
+    }
+    let output_path = streamed::claim(encryptor::names::with_suffix(file_path, ".enc"), existing)?;
+    streamed::write(&output[..], &output_path)?;
+    Ok(output_path)
+}
+
+// Decrypt the file at `file_path` into the same path without its last extension, returning the path written
+#[cfg(feature = "openssl-enc")]
+pub fn decrypt(
+    file_path: &Path,
+    secret: &Secret,
+    iterations: Option<u32>,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    use encryptor::openssl_enc;
+
+    let iterations = iterations.unwrap_or(openssl_enc::DEFAULT_ITERATIONS);
+    let password = password(secret, "decrypted")?;
+    let plaintext = openssl_enc::decrypt(&fs::read(file_path)?, password, iterations)?;
+
+    let output_path = encryptor::names::strip_extension(file_path).to_path_buf();
+    let output_path = streamed::claim(output_path, existing)?;
+    streamed::write(&plaintext[..], &output_path)?;
+    Ok(output_path)
+}
+
+// `openssl enc` derives its key from a password and nothing else
+#[cfg(feature = "openssl-enc")]
+fn password<'a>(secret: &'a Secret, done: &str) -> Result<&'a str, EncryptError> {
+    match secret {
+        Secret::Password(password, extra) if extra.is_empty() => Ok(password),
+        _ => Err(EncryptError::KeyModeMismatch(format!(
+            "OpenSSL enc files can only be {} with a password",
+            done
+        ))),
+    }
+}
+
+#[cfg(not(feature = "openssl-enc"))]
+pub fn encrypt(
+    _file_path: &Path,
+    _secret: &Secret,
+    _iterations: Option<u32>,
+    _base64: bool,
+    _existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "openssl-enc"))]
+pub fn decrypt(
+    _file_path: &Path,
+    _secret: &Secret,
+    _iterations: Option<u32>,
+    _existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    Err(unavailable())
+}
+
+#[cfg(not(feature = "openssl-enc"))]
+fn unavailable() -> EncryptError {
+    EncryptError::IoError(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "OpenSSL enc support is not built in (enable the 'openssl-enc' feature)",
+    ))
+}
@@ -0,0 +1,139 @@
+// Terminal-aware output for the CLI.
+// Status lines are colored (green for success, yellow for warnings, red for failure) when their stream is a terminal,
+// and fall back to plain text when the output is piped, when NO_COLOR is set or when --color=never is passed.
+// Successes go to stdout, and warnings and failures to stderr, so scripts can capture them separately.
+// With --json, stdout is left to the JSON reports, so successes aren't printed and notes go to stderr.
+// With --quiet, only failures are printed. Lines printed while progress bars are drawn go above them.
+use crate::{progress_bar, redact, run_report, transcript}; // Warnings and failures are recorded in the --debug-transcript and --report
+use serde_json::json;
+use std::env; // Used to read the NO_COLOR environment variable
+use std::io::{self, IsTerminal}; // IsTerminal tells us whether stdout or stderr is attached to a terminal
+
+// ANSI escape codes for the colors we use
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+// When to use colors, as chosen with the --color flag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,   // Color only when the stream is a terminal and NO_COLOR is not set
+    Always, // Always color, even when piped
+    Never,  // Never color
+}
+
+impl ColorChoice {
+    // Parse the value of the --color flag
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    // Decide whether colors should actually be used on a stream, given whether it is a terminal.
+    // Following https://no-color.org, a NO_COLOR variable with any non-empty value disables colors in auto mode.
+    fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                !no_color && terminal
+            }
+        }
+    }
+}
+
+// Prints status lines, colored or plain depending on the resolved ColorChoice
+#[derive(Clone)]
+pub struct Printer {
+    color: bool,       // On stdout
+    error_color: bool, // On stderr
+    successes: bool,   // Whether stdout is ours
+    quiet: bool,       // Only print failures
+}
+
+impl Printer {
+    pub fn new(choice: ColorChoice) -> Self {
+        Printer {
+            color: choice.enabled(io::stdout().is_terminal()),
+            error_color: choice.enabled(io::stderr().is_terminal()),
+            successes: true,
+            quiet: false,
+        }
+    }
+
+    // Leave stdout to something else, like the reports of --json
+    pub fn without_successes(mut self) -> Self {
+        self.successes = false;
+        self
+    }
+
+    // Print nothing but failures, for --quiet
+    pub fn quiet(mut self) -> Self {
+        self.quiet = true;
+        self
+    }
+
+    // Print a successful status line, e.g. "ok     Encrypted test.txt -> test.txt.enc"
+    pub fn ok(&self, message: &str) {
+        if self.successes && !self.quiet {
+            progress_bar::suspend(|| {
+                println!(
+                    "{} {}",
+                    paint(self.color, GREEN, "ok    "),
+                    redact::text(message)
+                )
+            });
+        }
+    }
+
+    // Print an informational line without a status, e.g. the result of --kdf-target
+    pub fn note(&self, message: &str) {
+        match (self.quiet, self.successes) {
+            (true, _) => {}
+            (false, true) => println!("{}", redact::text(message)),
+            (false, false) => eprintln!("{}", redact::text(message)),
+        }
+    }
+
+    // Print a warning that doesn't stop the command, e.g. "warn   Shredding ..."
+    pub fn warn(&self, message: &str) {
+        transcript::record("warning", json!({ "message": message }));
+        run_report::warning(message);
+        if !self.quiet {
+            progress_bar::suspend(|| {
+                eprintln!(
+                    "{} {}",
+                    paint(self.error_color, YELLOW, "warn  "),
+                    redact::text(message)
+                )
+            });
+        }
+    }
+
+    // Print a failed status line, e.g. "failed Decryption error: ..."
+    pub fn failed(&self, message: &str) {
+        transcript::record("failure", json!({ "message": message }));
+        progress_bar::suspend(|| {
+            eprintln!(
+                "{} {}",
+                paint(self.error_color, RED, "failed"),
+                redact::text(message)
+            )
+        });
+    }
+}
+
+// Wrap the text in the given color when colors are enabled
+fn paint(enabled: bool, color: &str, text: &str) -> String {
+    if enabled {
+        format!("{}{}{}", color, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
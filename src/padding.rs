@@ -0,0 +1,80 @@
+// Padding the payload before it is sealed, set with `Encryptor::with_padding`. An AEAD hides the contents but not
+// their length, and the length alone is often enough to tell which of a few known files (a document, a release, a
+// photo) a ciphertext holds. Rounding the payload up blunts that: to a multiple of a fixed block, which hides
+// everything below the block size, or to the next PADMÉ length (Nikitin et al., "Reducing Metadata Leakage from
+// Encrypted Files and Communication with PURBs"), which costs at most 12% and leaves only O(log log n) bits of the
+// length to tell files apart.
+//
+// The padding is zero bytes after the payload, added after compression so it isn't compressed away. The
+// header records the scheme, which is no secret, and how many bytes were added, masked with the file key like the
+// checksum, since the two together would give the exact length back. The header is authenticated with the payload,
+// so the count can't be changed to cut the plaintext short.
+use crate::header::{Header, Padding};
+use crate::{EncryptError, KEY_LEN};
+use blake2::digest::Mac;
+use blake2::Blake2sMac256;
+
+// A padding scheme and how many bytes it added to a payload
+pub(crate) type Padded = (Padding, u64);
+
+// How long a payload of `len` bytes is once padded
+pub(crate) fn padded_len(padding: Padding, len: u64) -> u64 {
+    match padding {
+        Padding::Padme if len < 2 => len,
+        Padding::Padme => {
+            // Keep the top floor(log2 E) + 1 bits of the length, where E is its own floor(log2), and round the rest up
+            let exponent = len.ilog2();
+            let kept = exponent.ilog2() + 1;
+            let mask = (1u64 << (exponent - kept.min(exponent))) - 1;
+            len.checked_add(mask).map_or(len, |len| len & !mask)
+        }
+        Padding::Block(size) => {
+            let size = size as u64;
+            len.div_ceil(size).max(1).saturating_mul(size)
+        }
+    }
+}
+
+// Mask the number of padding bytes for the output sealed with `key` and `nonce`, or unmask it again: XORing twice
+// with the same mask gives it back
+pub(crate) fn mask(len: u64, key: &[u8; KEY_LEN], nonce: &[u8]) -> u64 {
+    let mut mac = <Blake2sMac256 as Mac>::new_from_slice(key).expect("a 32-byte key fits BLAKE2s");
+    Mac::update(&mut mac, b"encryptor padding length mask\0");
+    Mac::update(&mut mac, nonce);
+    let mask = mac.finalize().into_bytes();
+    len ^ u64::from_le_bytes(
+        mask[..8]
+            .try_into()
+            .expect("a digest is longer than 8 bytes"),
+    )
+}
+
+// How many padding bytes end the payload of the file with `header`, unmasked with its `key`. 0 when it isn't padded.
+pub(crate) fn recorded(header: &Header, key: &[u8; KEY_LEN]) -> u64 {
+    header
+        .padding
+        .map_or(0, |(_, masked)| mask(masked, key, &header.nonce))
+}
+
+// Pad `contents` with zeros to the length `padding` rounds it up to, returning how many were added
+pub(crate) fn pad(contents: &mut Vec<u8>, padding: Padding) -> u64 {
+    let len = contents.len() as u64;
+    let padded_len = padded_len(padding, len);
+    contents.resize(padded_len as usize, 0);
+    padded_len - len
+}
+
+// How much of a payload of `payload_len` bytes, sealed with `header` under `key`, is left once its padding is cut off
+pub(crate) fn unpadded_len(
+    header: &Header,
+    key: &[u8; KEY_LEN],
+    payload_len: u64,
+) -> Result<u64, EncryptError> {
+    payload_len
+        .checked_sub(recorded(header, key))
+        .ok_or_else(|| {
+            EncryptError::MetadataMismatch(
+                "the header records more padding than the payload holds".to_string(),
+            )
+        })
+}
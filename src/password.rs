@@ -0,0 +1,277 @@
+// Where the password comes from: a hidden prompt on the terminal (the default), a file, an environment variable,
+// an inherited file descriptor, the platform keychain, a password manager's command line tool, or (deprecated)
+// the command line itself. Passwords, and whatever else was read along with them, are wiped from memory once
+// they are dropped.
+use crate::keychain;
+use rpassword::prompt_password;
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process::{Command, Stdio};
+use zeroize::Zeroizing;
+
+// The password command run when no source is given, so a shell profile can pick the password manager once
+const COMMAND_VAR: &str = "ENCRYPTOR_PASSWORD_CMD";
+
+// A password source chosen with the command line flags
+#[derive(Clone)]
+pub enum Source {
+    Argv(String), // --password: visible to other users through `ps` and kept in the shell history
+    File(String), // --passfile <path>
+    Env(String),  // --pass-env <VAR>
+    Fd(i32),      // --pass-fd <n>
+    Keyring(String), // --use-keyring <name>
+    Command(String), // --password-cmd <command>
+    Prompt,       // `rekey ... prompt`: the terminal, even when $ENCRYPTOR_PASSWORD_CMD is set
+}
+
+// Read the password from `source`, or when no source was given from the command in $ENCRYPTOR_PASSWORD_CMD or
+// else the terminal. With `confirm`, the terminal prompt asks a second time and makes sure both entries match.
+pub fn read(source: Option<Source>, confirm: bool) -> Result<Zeroizing<String>, String> {
+    read_as("Password", source, confirm)
+}
+
+// Whether `read` with `source` asks at the terminal, where a mistyped password can be typed again
+pub fn prompts(source: Option<&Source>) -> bool {
+    match source {
+        None => env::var(COMMAND_VAR).map_or(true, |command| command.is_empty()),
+        Some(source) => matches!(source, Source::Prompt),
+    }
+}
+
+// Like `read`, with the prompt asking for `what`, e.g. "New password"
+pub fn read_as(
+    what: &str,
+    source: Option<Source>,
+    confirm: bool,
+) -> Result<Zeroizing<String>, String> {
+    let source = source.or_else(|| {
+        env::var(COMMAND_VAR)
+            .ok()
+            .filter(|command| !command.is_empty())
+            .map(Source::Command)
+    });
+    let password = match source {
+        None | Some(Source::Prompt) => return prompt(what, confirm),
+        Some(Source::Argv(password)) => {
+            eprintln!("warning: --password is deprecated, since other users can see it through `ps`; use --passfile, --pass-env, --pass-fd or the prompt instead");
+            Zeroizing::new(password)
+        }
+        Some(Source::File(path)) => fs::read_to_string(&path)
+            .map(|password| trim_newline(Zeroizing::new(password)))
+            .map_err(|err| format!("Cannot read the password file {}: {}", path, err))?,
+        Some(Source::Env(name)) => env::var(&name)
+            .map(Zeroizing::new)
+            .map_err(|err| format!("Cannot read the password from ${}: {}", name, err))?,
+        Some(Source::Fd(fd)) => read_fd(fd)
+            .map(trim_newline)
+            .map_err(|err| format!("Cannot read the password from fd {}: {}", fd, err))?,
+        // The first use of a name asks for the password (twice, since it will be reused) and stores it
+        Some(Source::Keyring(name)) => match keychain::get(&name)? {
+            Some(password) => Zeroizing::new(password),
+            None => {
+                let password = prompt(what, true)?;
+                keychain::set(&name, &password)?;
+                password
+            }
+        },
+        Some(Source::Command(command)) => run(&command)?,
+    };
+    if password.is_empty() {
+        return Err("The password must not be empty".to_string());
+    }
+    Ok(password)
+}
+
+// Ask for the password on the terminal without echoing it, or with no terminal to ask on (a service, or a session
+// without one) read it as the next line piped to stdin
+fn prompt(what: &str, confirm: bool) -> Result<Zeroizing<String>, String> {
+    let terminal = has_terminal();
+    let read = |prompt: &str| {
+        let password = match terminal {
+            true => prompt_password(prompt).map(Zeroizing::new),
+            false => {
+                eprint!("{}", prompt);
+                read_line()
+            }
+        };
+        password.map_err(|err| format!("Cannot read the password: {}", err))
+    };
+
+    let password = read(&format!("{}: ", what))?;
+    if password.is_empty() {
+        return Err("The password must not be empty".to_string());
+    }
+    if confirm && read(&format!("Confirm {}: ", what.to_lowercase()))? != password {
+        return Err("The passwords do not match".to_string());
+    }
+    Ok(password)
+}
+
+// Whether there is a terminal for the hidden prompt: the controlling terminal rpassword opens on Unix, or the
+// console on Windows
+#[cfg(unix)]
+fn has_terminal() -> bool {
+    fs::File::open("/dev/tty").is_ok()
+}
+
+#[cfg(not(unix))]
+fn has_terminal() -> bool {
+    use std::io::IsTerminal;
+    io::stdin().is_terminal()
+}
+
+// The next line of stdin, without its newline
+fn read_line() -> io::Result<Zeroizing<String>> {
+    let mut line = Zeroizing::new(String::new());
+    io::stdin().read_line(&mut line)?;
+    Ok(trim_newline(line))
+}
+
+// Run a password manager's command, e.g. `op read op://vault/item/password`, `bw get password item` or
+// `pass show item`, through the shell and take the first line it prints: `pass` keeps other fields on the lines
+// after the password. It can still ask for its own master password, since it shares our terminal.
+fn run(command: &str) -> Result<Zeroizing<String>, String> {
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    };
+    let output = shell
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|err| format!("Cannot run the password command `{}`: {}", command, err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "The password command `{}` failed ({})",
+            command, output.status
+        ));
+    }
+    // The other lines can hold secrets too, like `pass` entries do
+    let stdout = Zeroizing::new(output.stdout);
+    let stdout = std::str::from_utf8(&stdout)
+        .map_err(|_| format!("The password command `{}` printed invalid UTF-8", command))?;
+    Ok(Zeroizing::new(
+        stdout.lines().next().unwrap_or_default().to_string(),
+    ))
+}
+
+// The common ways a typed password differs from the one a file was encrypted with, for `decrypt --try-typos`:
+// each variant of `password`, with how it was typed differently. Letters are swapped on the keys where QWERTZ and
+// AZERTY keyboards differ from QWERTY, both ways, so a password set on either layout is found from the other. Each
+// variant is built in place in a buffer that is wiped when dropped, sized up front so it never reallocates: case
+// mapping at most triples the length of a character, and the other variants keep the length or add one byte.
+pub fn typos(password: &str) -> Vec<(&'static str, Zeroizing<String>)> {
+    let buffer = |capacity: usize| Zeroizing::new(String::with_capacity(capacity));
+    let swapped = |pairs: &[(char, char)]| {
+        let mut variant = buffer(password.len());
+        for c in password.chars() {
+            variant.push(
+                pairs
+                    .iter()
+                    .find_map(|&(a, b)| match c {
+                        _ if c == a => Some(b),
+                        _ if c == b => Some(a),
+                        _ => None,
+                    })
+                    .unwrap_or(c),
+            );
+        }
+        variant
+    };
+    let mut inverted = buffer(password.len() * 3);
+    for c in password.chars() {
+        match c.is_lowercase() {
+            true => c.to_uppercase().for_each(|c| inverted.push(c)),
+            false => c.to_lowercase().for_each(|c| inverted.push(c)),
+        }
+    }
+    let mut trimmed = buffer(password.len());
+    trimmed.push_str(password.trim());
+    let mut spaced = buffer(password.len() + 1);
+    spaced.push_str(password);
+    spaced.push(' ');
+    let mut newline = buffer(password.len() + 1);
+    newline.push_str(password);
+    newline.push('\n');
+    let mut variants = vec![
+        ("with caps lock inverted", inverted),
+        ("without the spaces around it", trimmed),
+        ("with a trailing space", spaced),
+        ("with a trailing newline", newline),
+        (
+            "as typed on a QWERTZ keyboard (Y and Z swapped)",
+            swapped(&[('y', 'z'), ('Y', 'Z')]),
+        ),
+        (
+            "as typed on an AZERTY keyboard (A and Q, W and Z swapped)",
+            swapped(&[('a', 'q'), ('w', 'z'), ('A', 'Q'), ('W', 'Z')]),
+        ),
+    ];
+    // Two characters typed in the wrong order, at each place in the password
+    let mut chars = password.char_indices().peekable();
+    while let Some((at, first)) = chars.next() {
+        let Some(&(next_at, second)) = chars.peek() else {
+            break;
+        };
+        let mut variant = buffer(password.len());
+        variant.push_str(&password[..at]);
+        variant.push(second);
+        variant.push(first);
+        variant.push_str(&password[next_at + second.len_utf8()..]);
+        variants.push(("with two neighbouring characters swapped", variant));
+    }
+
+    let mut typos: Vec<(&str, Zeroizing<String>)> = Vec::new();
+    for (typo, variant) in variants {
+        if !variant.is_empty()
+            && *variant != password
+            && typos.iter().all(|(_, seen)| *seen != variant)
+        {
+            typos.push((typo, variant));
+        }
+    }
+    typos
+}
+
+// Files written with `echo` or an editor end with a newline that isn't part of the password
+fn trim_newline(mut password: Zeroizing<String>) -> Zeroizing<String> {
+    if password.ends_with('\n') {
+        password.pop();
+        if password.ends_with('\r') {
+            password.pop();
+        }
+    }
+    password
+}
+
+// Read everything from a file descriptor inherited from the parent process
+#[cfg(unix)]
+fn read_fd(fd: i32) -> io::Result<Zeroizing<String>> {
+    use std::fs::File;
+    use std::os::unix::io::FromRawFd;
+
+    // Safety: the descriptor was handed to us explicitly on the command line for this purpose,
+    // and nothing else in the program uses it. It is closed once the password has been read.
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    let mut password = Zeroizing::new(String::new());
+    file.read_to_string(&mut password)?;
+    Ok(password)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> io::Result<Zeroizing<String>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--pass-fd is only supported on Unix",
+    ))
+}
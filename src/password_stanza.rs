@@ -0,0 +1,94 @@
+// Wrapping a file's random key for a password, when a file is encrypted to several recipients at once.
+//
+// A password stanza body is the Argon2id salt (16 bytes), memory cost and iterations (u32 LE each) and a nonce
+// (12 bytes), followed by the file key sealed with the key derived from the password (32 bytes and a 16-byte tag).
+// The salt is the Encryptor's session salt for that password, so the derived key is cached like in password mode,
+// and every stanza gets its own random nonce instead.
+use crate::header::{Stanza, StanzaKind};
+use crate::key_cache::KeyCache;
+use crate::rng::RandomSource;
+use crate::{
+    cipher, fault, Algorithm, EncryptError, KdfParams, KeyBytes, NonceLog, KEY_LEN, NONCE_LEN,
+    SALT_LEN, TAG_LEN,
+};
+use zeroize::Zeroizing;
+
+// Keeps the wrapping key apart from the key password mode derives from the same password and salt
+const CONTEXT: &[u8] = b"encryptor password stanza";
+const BODY_LEN: usize = SALT_LEN + 8 + NONCE_LEN + KEY_LEN + TAG_LEN;
+
+// Wrap the file key for `password`, the Encryptor's password number `slot`, sealing it with `algorithm`.
+// The wrapping key is reused for every file, so its nonces go into `nonces` like those of the files.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn wrap(
+    file_key: &[u8; KEY_LEN],
+    password: &str,
+    slot: usize,
+    keys: &KeyCache,
+    params: KdfParams,
+    algorithm: Algorithm,
+    nonces: Option<&NonceLog>,
+    rng: &dyn RandomSource,
+) -> Result<Stanza, EncryptError> {
+    let salt = keys.session_salt(slot, 1, algorithm.max_messages_per_key(), rng)?;
+    let wrap_key = keys.key(password, &salt, params, &context(algorithm))?;
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut nonce)?;
+    fault::nonce(&mut nonce);
+    if let Some(nonces) = nonces {
+        nonces.record(&wrap_key, &nonce, false)?;
+    }
+
+    let mut sealed = file_key.to_vec();
+    cipher::seal_in_place(algorithm, &wrap_key, &nonce, &[], &mut sealed)?;
+
+    let mut body = Vec::with_capacity(BODY_LEN);
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&params.memory.to_le_bytes());
+    body.extend_from_slice(&params.iterations.to_le_bytes());
+    body.extend_from_slice(&nonce);
+    body.extend_from_slice(&sealed);
+    Ok(Stanza {
+        kind: StanzaKind::Password,
+        body,
+    })
+}
+
+// Try every password stanza until one opens with `password`, and return the file key from it.
+// Returns None when none of them were wrapped for this password.
+pub(crate) fn unwrap(
+    stanzas: &[Stanza],
+    password: &str,
+    keys: &KeyCache,
+    algorithm: Algorithm,
+) -> Result<Option<KeyBytes>, EncryptError> {
+    for stanza in stanzas {
+        if stanza.kind != StanzaKind::Password || stanza.body.len() != BODY_LEN {
+            continue;
+        }
+        let (salt, rest) = stanza.body.split_at(SALT_LEN);
+        let (params, rest) = rest.split_at(8);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        let params = KdfParams {
+            memory: u32::from_le_bytes([params[0], params[1], params[2], params[3]]),
+            iterations: u32::from_le_bytes([params[4], params[5], params[6], params[7]]),
+        };
+        let wrap_key = keys.key(password, salt, params, &context(algorithm))?;
+
+        // A stanza for another password simply fails to authenticate
+        let mut file_key = Zeroizing::new(sealed.to_vec());
+        if cipher::open_in_place(algorithm, &wrap_key, nonce, &[], &mut file_key).is_ok() {
+            if let Ok(file_key) = file_key[..].try_into() {
+                return Ok(Some(Zeroizing::new(file_key)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// The wrapping key is bound to the algorithm it seals with, like the key in password mode
+fn context(algorithm: Algorithm) -> Vec<u8> {
+    let mut context = CONTEXT.to_vec();
+    context.push(algorithm.id());
+    context
+}
@@ -0,0 +1,205 @@
+// Which files of a directory tree `encrypt --archive` and `rotate` take: --exclude and --include glob patterns, and
+// with --respect-gitignore the .gitignore files found along the way. Patterns follow gitignore: one without a slash
+// matches a name at any depth (`*.o`), one with a slash matches the path under the directory given on the command
+// line, or under the directory of its .gitignore (`target/**`, `/build`), and a trailing slash matches directories
+// only (`cache/`). `*` and `?` don't cross a slash, while `**` spans any number of directories. An excluded
+// directory isn't entered at all. --include patterns only apply to files: once one is given, a file must match one
+// of them, and directories are still searched. A .gitignore line starting with `!` takes a file back in, and later
+// lines win over earlier ones and deeper .gitignore files over those above them, like git. The paths given on the
+// command line are always taken, even when a pattern matches them.
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::Path;
+
+const OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Clone, Default)]
+pub struct PathFilter {
+    pub excludes: Vec<Rule>,
+    pub includes: Vec<Rule>,
+    pub gitignore: bool,
+}
+
+// One pattern, of a flag or a .gitignore line
+#[derive(Clone)]
+pub struct Rule {
+    pattern: Pattern,
+    anchored: bool, // Matched against the whole path instead of the name
+    dir_only: bool,
+    negated: bool,
+}
+
+impl Rule {
+    pub fn parse(text: &str) -> Result<Rule, String> {
+        let (negated, text) = match text.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (dir_only, text) = match text.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let anchored = text.contains('/');
+        // `dir/**` is everything inside dir, which is the same as leaving out dir itself
+        let (dir_only, text) = match text.strip_suffix("/**") {
+            Some(rest) if !rest.is_empty() => (true, rest),
+            _ => (dir_only, text),
+        };
+        let text = text.strip_prefix('/').unwrap_or(text);
+        if text.is_empty() {
+            return Err("expected a glob pattern such as '*.o' or 'target/'".to_string());
+        }
+        Ok(Rule {
+            pattern: Pattern::new(text).map_err(|err| err.to_string())?,
+            anchored,
+            dir_only,
+            negated,
+        })
+    }
+
+    // Whether the rule matches `path`, '/'-separated and relative to the directory the rule applies under
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        match self.anchored {
+            true => self.pattern.matches_with(path, OPTIONS),
+            false => {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                self.pattern.matches_with(name, OPTIONS)
+            }
+        }
+    }
+}
+
+impl PathFilter {
+    // Whether anything is filtered at all
+    pub fn is_active(&self) -> bool {
+        !self.excludes.is_empty() || !self.includes.is_empty() || self.gitignore
+    }
+
+    // A walk down the tree under one path given on the command line
+    pub fn walk(&self) -> Walk<'_> {
+        Walk {
+            filter: self,
+            gitignores: Vec::new(),
+        }
+    }
+}
+
+// The .gitignore files of the directories a walk is in, outermost first
+pub struct Walk<'a> {
+    filter: &'a PathFilter,
+    gitignores: Vec<GitIgnore>,
+}
+
+struct GitIgnore {
+    base: String, // The directory it is in, relative to the root of the walk ("" for the root itself)
+    rules: Vec<Rule>,
+}
+
+impl Walk<'_> {
+    // Whether anything is left out, so a directory left empty by the filters can be left out too
+    pub fn is_active(&self) -> bool {
+        self.filter.is_active()
+    }
+
+    // Called before listing the directory `dir`, at `relative` under the root of the walk, to read its .gitignore
+    pub fn enter(&mut self, dir: &Path, relative: &str) {
+        if !self.filter.gitignore {
+            return;
+        }
+        let Ok(text) = fs::read_to_string(dir.join(".gitignore")) else {
+            return;
+        };
+        let mut rules = Vec::new();
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match Rule::parse(line) {
+                Ok(rule) => rules.push(rule),
+                Err(err) => log::warn!(
+                    "Ignoring '{}' in {}: {}",
+                    line,
+                    dir.join(".gitignore").display(),
+                    err
+                ),
+            }
+        }
+        self.gitignores.push(GitIgnore {
+            base: relative.to_string(),
+            rules,
+        });
+    }
+
+    // Called once the directory at `relative` has been listed
+    pub fn leave(&mut self, relative: &str) {
+        if self
+            .gitignores
+            .last()
+            .is_some_and(|gitignore| gitignore.base == relative)
+        {
+            self.gitignores.pop();
+        }
+    }
+
+    // Whether the entry at `relative` (non-empty, '/'-separated) under the root of the walk is taken
+    pub fn keeps(&self, relative: &str, is_dir: bool) -> bool {
+        let filter = self.filter;
+        if filter
+            .excludes
+            .iter()
+            .any(|rule| rule.matches(relative, is_dir))
+        {
+            log::debug!("Leaving out {}: excluded", relative);
+            return false;
+        }
+        if filter.gitignore && self.ignored(relative, is_dir) {
+            log::debug!("Leaving out {}: ignored by git", relative);
+            return false;
+        }
+        if !is_dir
+            && !filter.includes.is_empty()
+            && !filter
+                .includes
+                .iter()
+                .any(|rule| rule.matches(relative, false))
+        {
+            log::debug!("Leaving out {}: not included", relative);
+            return false;
+        }
+        true
+    }
+
+    // What the last matching line of the .gitignore files says, git's own directory always being ignored
+    fn ignored(&self, relative: &str, is_dir: bool) -> bool {
+        if is_dir && relative.rsplit('/').next() == Some(".git") {
+            return true;
+        }
+        let mut ignored = false;
+        for gitignore in &self.gitignores {
+            let path = match gitignore.base.as_str() {
+                "" => relative,
+                base => match relative
+                    .strip_prefix(base)
+                    .and_then(|rest| rest.strip_prefix('/'))
+                {
+                    Some(path) => path,
+                    None => continue,
+                },
+            };
+            for rule in &gitignore.rules {
+                if rule.matches(path, is_dir) {
+                    ignored = !rule.negated;
+                }
+            }
+        }
+        ignored
+    }
+}
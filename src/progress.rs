@@ -0,0 +1,413 @@
+//! Progress reporting and cancellation for file operations.
+//!
+//! [`Encryptor::encrypt_file_with`](crate::Encryptor::encrypt_file_with) and
+//! [`Encryptor::decrypt_file_with`](crate::Encryptor::decrypt_file_with) report a [`Progress`] value
+//! as they read, process and write the file, and check a [`CancellationToken`] between chunks.
+//! A cancelled or failed operation never leaves a partial output file behind: outputs are written to a temporary
+//! file next to their final path, synced to disk and only then renamed into place, so even a crash midway leaves
+//! at worst a stray `*.tmp` file, never a truncated output under the final name. The exception is
+//! [`Encryptor::with_resume`](crate::Encryptor::with_resume), which keeps a partly written chunked output as
+//! `*.partial` to continue it later.
+use crate::input::Input;
+use crate::{cipher, fault, names, EncryptError};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Size of the pieces files are read and written in, between progress reports and cancellation checks
+pub(crate) const IO_CHUNK: usize = 64 * 1024;
+/// How many bytes of a file are read or written at once by default (4 MiB), see
+/// [`Encryptor::with_io_buffer`](crate::Encryptor::with_io_buffer)
+pub const DEFAULT_IO_BUFFER: usize = 4 << 20;
+
+/// The stage an operation is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Reading,    // Reading the input file
+    Processing, // Deriving the key and encrypting or decrypting
+    Writing,    // Writing the output file
+    Streaming,  // Reading, encrypting or decrypting and writing a chunked file one chunk at a time
+}
+
+/// A snapshot of how far an operation has got.
+/// `bytes_done` and `bytes_total` count the bytes of the current stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub stage: Stage,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+impl Progress {
+    /// Overall completion of the operation from 0 to 100.
+    /// Reading counts for the first 40%, processing for the next 20% and writing for the rest.
+    /// A chunked file is read, processed and written together, so streaming spans all of it.
+    pub fn percent(&self) -> u8 {
+        let (start, span) = match self.stage {
+            Stage::Reading => (0, 40),
+            Stage::Processing => (40, 20),
+            Stage::Writing => (60, 40),
+            Stage::Streaming => (0, 100),
+        };
+        let fraction = if self.bytes_total == 0 {
+            1.0
+        } else {
+            self.bytes_done as f64 / self.bytes_total as f64
+        };
+        (start as f64 + span as f64 * fraction.min(1.0)) as u8
+    }
+}
+
+/// A handle that lets another thread (or a signal handler) abort an operation.
+/// Clones share the same flag, so cancelling any clone cancels them all.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every operation watching this token to stop at its next check
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    // Return an error if cancellation was requested, so callers can use `?`
+    pub(crate) fn check(&self) -> Result<(), EncryptError> {
+        if self.is_cancelled() {
+            Err(EncryptError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// Function to read a whole file in chunks, reporting progress and checking for cancellation between chunks.
+// `spare` extra bytes of capacity are reserved so the authentication tag can be appended without reallocating.
+// With `mmap`, the chunks are copied out of a memory map instead of read (see Encryptor::with_mmap), and otherwise
+// the file is read `buffer_len` bytes at a time.
+pub(crate) fn read_file(
+    file_path: &Path,
+    spare: usize,
+    mmap: bool,
+    buffer_len: usize,
+    progress: &mut dyn FnMut(Progress),
+    cancel: &CancellationToken,
+) -> Result<Vec<u8>, EncryptError> {
+    let mut file = Input::open(file_path, mmap, buffer_len)?;
+    let bytes_total = file.len()?;
+    let mut contents = Vec::with_capacity(bytes_total as usize + spare);
+
+    let mut chunk = vec![0u8; IO_CHUNK];
+    loop {
+        cancel.check()?;
+        let read = file.read(&mut chunk[..fault::read_len(IO_CHUNK)])?;
+        if read == 0 {
+            break;
+        }
+        contents.extend_from_slice(&chunk[..read]);
+        progress(Progress {
+            stage: Stage::Reading,
+            bytes_done: contents.len() as u64,
+            bytes_total,
+        });
+    }
+    Ok(contents)
+}
+
+// Read from `reader` until `buffer` is full or the input ends, returning how many bytes were read
+pub(crate) fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let len = fault::read_len(buffer.len() - filled);
+        match reader.read(&mut buffer[filled..filled + len]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+// Function to write the given parts to a new file in chunks, reporting progress and checking for cancellation.
+// The chunks are written out `buffer_len` bytes at a time (see OutputFile::buffered), and OutputFile says how the
+// file is put in place.
+pub(crate) fn write_file(
+    file_path: &Path,
+    parts: &[&[u8]],
+    overwrite: bool,
+    buffer_len: usize,
+    progress: &mut dyn FnMut(Progress),
+    cancel: &CancellationToken,
+) -> Result<(), EncryptError> {
+    let mut output = OutputFile::create(file_path, overwrite, cancel)?.buffered(buffer_len);
+    let bytes_total: u64 = parts.iter().map(|part| part.len() as u64).sum();
+    for part in parts {
+        for chunk in part.chunks(IO_CHUNK) {
+            output.write(chunk, cancel)?;
+            progress(Progress {
+                stage: Stage::Writing,
+                bytes_done: output.len(),
+                bytes_total,
+            });
+        }
+    }
+    output.commit()
+}
+
+// A new file being written. The bytes go to a temporary file in the same directory, which `commit` syncs and then
+// atomically renames to the final path, replacing any file there unless `overwrite` was off. If anything goes wrong
+// before that (including cancellation), dropping it removes the temporary file and the final path is left untouched.
+pub(crate) struct OutputFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    file: Option<File>,
+    len: u64,        // The bytes in the file, not counting those still in `buffer`
+    keep: bool,      // The temporary file is a partial output kept for resuming, see `resume`
+    overwrite: bool, // Whether committing may replace a file that appeared at `path` since it was created
+    buffer: Vec<u8>,
+    buffer_len: usize, // How much `buffer` collects before it is written out, or 0 when writes go straight out
+}
+
+// Where the partial output of `file_path` is kept, see Encryptor::with_resume
+pub(crate) fn partial_path(file_path: &Path) -> PathBuf {
+    names::with_suffix(file_path, ".partial")
+}
+
+impl OutputFile {
+    pub(crate) fn create(
+        file_path: &Path,
+        overwrite: bool,
+        cancel: &CancellationToken,
+    ) -> Result<OutputFile, EncryptError> {
+        cancel.check()?;
+        if !overwrite && fs::symlink_metadata(file_path).is_ok() {
+            return Err(already_exists(file_path));
+        }
+        // A random suffix, so concurrent runs writing the same output don't share a temporary file
+        let mut suffix = [0u8; 4];
+        cipher::fill_random(&mut suffix)?;
+        let temp_path = names::with_suffix(
+            file_path,
+            &format!(".{:08x}.tmp", u32::from_le_bytes(suffix)),
+        );
+        let file = private_options().create_new(true).open(&temp_path)?;
+        Ok(OutputFile {
+            path: file_path.to_path_buf(),
+            temp_path,
+            file: Some(file),
+            len: 0,
+            keep: false,
+            overwrite,
+            buffer: Vec::new(),
+            buffer_len: 0,
+        })
+    }
+
+    // Like `create`, but written to the partial output of `file_path`, which is kept if anything goes wrong so a
+    // later attempt can continue it (see Encryptor::with_resume). A partial output already there is opened as it
+    // is, for the caller to check with `read_at` and cut down with `truncate` before writing on.
+    pub(crate) fn resume(
+        file_path: &Path,
+        overwrite: bool,
+        cancel: &CancellationToken,
+    ) -> Result<OutputFile, EncryptError> {
+        cancel.check()?;
+        if !overwrite && fs::symlink_metadata(file_path).is_ok() {
+            return Err(already_exists(file_path));
+        }
+        let temp_path = partial_path(file_path);
+        let mut file = private_options()
+            .read(true)
+            .create(true)
+            .truncate(false)
+            .open(&temp_path)?;
+        let len = file.seek(SeekFrom::End(0))?;
+        Ok(OutputFile {
+            path: file_path.to_path_buf(),
+            temp_path,
+            file: Some(file),
+            len,
+            keep: true,
+            overwrite,
+            buffer: Vec::new(),
+            buffer_len: 0,
+        })
+    }
+
+    // Collect what is written in memory and write it out `buffer_len` bytes at a time, for fewer and larger writes
+    // (0 writes every piece as it comes). The buffer only grows as far as what is written fills it, so a small
+    // output never costs a whole one.
+    pub(crate) fn buffered(mut self, buffer_len: usize) -> OutputFile {
+        self.buffer_len = buffer_len;
+        self
+    }
+
+    // Append `bytes`, in pieces with a cancellation check before each
+    pub(crate) fn write(
+        &mut self,
+        bytes: &[u8],
+        cancel: &CancellationToken,
+    ) -> Result<(), EncryptError> {
+        for chunk in bytes.chunks(IO_CHUNK) {
+            cancel.check()?;
+            if self.buffer_len == 0 {
+                self.write_out(chunk)?;
+                continue;
+            }
+            // Grow by doubling, like a Vec would, but never past the size the buffer is written out at
+            let needed = self.buffer.len() + chunk.len();
+            if needed > self.buffer.capacity() {
+                let grown = (self.buffer.capacity() * 2).clamp(needed, self.buffer_len.max(needed));
+                self.buffer.reserve_exact(grown - self.buffer.len());
+            }
+            self.buffer.extend_from_slice(chunk);
+            if self.buffer.len() >= self.buffer_len {
+                self.flush_buffer()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_out(&mut self, bytes: &[u8]) -> Result<(), EncryptError> {
+        let file = self
+            .file
+            .as_mut()
+            .expect("an output file is written until it is committed");
+        let (bytes, result) = fault::write(bytes, self.len);
+        file.write_all(&bytes)?;
+        result?;
+        self.len += bytes.len() as u64;
+        fault::written(self.len);
+        Ok(())
+    }
+
+    // Write out what the buffer collected so far
+    fn flush_buffer(&mut self) -> Result<(), EncryptError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let buffer = std::mem::take(&mut self.buffer);
+        self.write_out(&buffer)?;
+        self.buffer = buffer;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    // How many bytes were written so far
+    pub(crate) fn len(&self) -> u64 {
+        self.len + self.buffer.len() as u64
+    }
+
+    // Read back the bytes written at `offset`, filling `buf`
+    pub(crate) fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), EncryptError> {
+        self.flush_buffer()?;
+        let file = self
+            .file
+            .as_mut()
+            .expect("an output file is read until it is committed");
+        file.seek(SeekFrom::Start(offset))?;
+        let read = file.read_exact(buf);
+        file.seek(SeekFrom::Start(self.len))?;
+        Ok(read?)
+    }
+
+    // Cut what was written so far down to its first `len` bytes, to write on from there
+    pub(crate) fn truncate(&mut self, len: u64) -> Result<(), EncryptError> {
+        self.flush_buffer()?;
+        let file = self
+            .file
+            .as_mut()
+            .expect("an output file is truncated until it is committed");
+        file.set_len(len)?;
+        file.seek(SeekFrom::Start(len))?;
+        self.len = len;
+        Ok(())
+    }
+
+    // Sync the temporary file and move it into place. Unless the output may be overwritten, it is linked under the
+    // final name, which fails if a file got there since `create` checked, and only then is the temporary name removed.
+    pub(crate) fn commit(mut self) -> Result<(), EncryptError> {
+        self.flush_buffer()?;
+        let mut file = self.file.take().expect("an output file is committed once");
+        file.flush()?;
+        file.sync_all()?;
+        drop(file);
+
+        match self.overwrite {
+            true => fs::rename(&self.temp_path, &self.path)?,
+            false => match fs::hard_link(&self.temp_path, &self.path) {
+                Ok(()) => fs::remove_file(&self.temp_path)?,
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                    return Err(already_exists(&self.path));
+                }
+                // A filesystem without hard links, like FAT, only gets the check `create` made
+                Err(_) if fs::symlink_metadata(&self.path).is_err() => {
+                    fs::rename(&self.temp_path, &self.path)?
+                }
+                Err(_) => return Err(already_exists(&self.path)),
+            },
+        }
+        self.temp_path = PathBuf::new();
+        sync_parent(&self.path)?;
+        fault::point("written");
+        Ok(())
+    }
+}
+
+// The error for an output that is already there and mustn't be overwritten
+fn already_exists(file_path: &Path) -> EncryptError {
+    EncryptError::IoError(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        format!("{} already exists", file_path.display()),
+    ))
+}
+
+// Options to write a new temporary output that only its owner can read until it is in place
+fn private_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+// Sync the directory holding `file_path`, so the renamed directory entry is on disk too.
+// Directories can't be opened for syncing on Windows, where the rename itself is all there is.
+fn sync_parent(file_path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match file_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = file_path;
+    Ok(())
+}
+
+// The temporary file is removed when the output is dropped before it was renamed into place, unless it is a
+// partial output to resume. Using Drop means every early return (`?`) cleans up without extra code at each call site.
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        if !self.temp_path.as_os_str().is_empty() && !self.keep {
+            drop(self.file.take());
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
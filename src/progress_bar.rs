@@ -0,0 +1,166 @@
+// Progress bars on the terminal for the commands that work through files: one bar a file with the bytes done, the
+// speed and the time left, and in a batch a bar over all of them that also counts the files done, each file's
+// status line appearing above it as the file finishes:
+//
+//   ok     Encrypted a.iso -> a.iso.enc
+//   b.iso  [##########>---------]  1.2 GiB/2.4 GiB  310 MiB/s  ETA 4s
+//   2/5 files  [####>---------------]  3.5 GiB/12.0 GiB  305 MiB/s  ETA 28s
+//
+// With --raw the sizes are in bytes, the speed in bytes a second and the time left in seconds. The bars are drawn on
+// stderr, and only when stdout is a terminal and neither --quiet nor --json is given, so scripts and pipes see the
+// status lines alone. Status lines and log messages printed while the bars are shown go through `suspend`, which
+// clears the bars and draws them again underneath.
+use crate::units;
+use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+// The bars being drawn, once a command has shown any
+static BARS: OnceLock<MultiProgress> = OnceLock::new();
+
+const FILE_TEMPLATE: &str = "{prefix}  [{bar:20}]";
+const TOTAL_TEMPLATE: &str = "{msg}  [{bar:20}]";
+// What follows the bar: the bytes done, the speed and the time left, for people or in bytes and seconds with --raw
+const HUMAN_UNITS: &str = "  {bytes}/{total_bytes}  {bytes_per_sec}  ETA {eta}";
+const RAW_UNITS: &str = "  {pos}/{len} bytes  {raw_per_sec} bytes/s  ETA {raw_eta} s";
+
+// Print something without tearing the bars, e.g. a status line
+pub fn suspend<R>(print: impl FnOnce() -> R) -> R {
+    match BARS.get() {
+        Some(bars) => bars.suspend(print),
+        None => print(),
+    }
+}
+
+// The bars of one batch
+pub struct Bars {
+    total: Option<ProgressBar>, // Over all the files of a batch
+    files: usize,
+    done: AtomicUsize,
+}
+
+impl Bars {
+    // Bars for `files`, or nothing drawn at all when not `shown`
+    pub fn new(shown: bool, files: &[PathBuf]) -> Self {
+        if shown {
+            multi();
+        }
+        let total = (shown && files.len() > 1).then(|| {
+            let size = files.iter().map(|file_path| size(file_path)).sum();
+            let bar = multi().add(ProgressBar::new(size));
+            bar.set_style(style(TOTAL_TEMPLATE));
+            bar.set_message(format!("0/{} files", files.len()));
+            bar
+        });
+        Bars {
+            total,
+            files: files.len(),
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    // A bar for one file of the batch, as it starts
+    pub fn start(&self, file_path: &Path) -> FileBar<'_> {
+        let size = size(file_path);
+        let bar = BARS.get().map(|bars| {
+            let bar = ProgressBar::new(size);
+            let bar = match &self.total {
+                Some(total) => bars.insert_before(total, bar),
+                None => bars.add(bar),
+            };
+            bar.set_style(style(FILE_TEMPLATE));
+            bar.set_prefix(crate::redact::text(&file_path.to_string_lossy()).into_owned());
+            bar
+        });
+        FileBar {
+            bars: self,
+            bar,
+            size,
+            shown: Cell::new(0),
+        }
+    }
+
+    // Take the bars off the terminal once the batch is done
+    pub fn finish(&self) {
+        if let Some(total) = &self.total {
+            total.finish_and_clear();
+        }
+    }
+}
+
+// The bar of a file being worked on
+pub struct FileBar<'a> {
+    bars: &'a Bars,
+    bar: Option<ProgressBar>,
+    size: u64,
+    shown: Cell<u64>, // How much of the file the bars show as done
+}
+
+impl FileBar<'_> {
+    // Move the bars to `percent` of the file, as the library reports it
+    pub fn update(&self, percent: u8) {
+        let done = self.size * u64::from(percent.min(100)) / 100;
+        self.advance(done);
+    }
+
+    // Take the file's bar away before its status line is printed, counting the file as done
+    pub fn finish(&self) {
+        self.advance(self.size);
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+        let done = self.bars.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(total) = &self.bars.total {
+            total.set_message(format!("{}/{} files", done, self.bars.files));
+        }
+    }
+
+    fn advance(&self, done: u64) {
+        let shown = self.shown.get();
+        if done <= shown {
+            return;
+        }
+        self.shown.set(done);
+        if let Some(bar) = &self.bar {
+            bar.set_position(done);
+        }
+        if let Some(total) = &self.bars.total {
+            total.inc(done - shown);
+        }
+    }
+}
+
+fn multi() -> &'static MultiProgress {
+    BARS.get_or_init(MultiProgress::new)
+}
+
+fn style(template: &str) -> ProgressStyle {
+    let units = match units::raw() {
+        true => RAW_UNITS,
+        false => HUMAN_UNITS,
+    };
+    ProgressStyle::with_template(&format!("{}{}", template, units))
+        .expect("the templates are valid")
+        .with_key(
+            "raw_per_sec",
+            |state: &ProgressState, out: &mut dyn fmt::Write| {
+                let _ = write!(out, "{}", state.per_sec() as u64);
+            },
+        )
+        .with_key(
+            "raw_eta",
+            |state: &ProgressState, out: &mut dyn fmt::Write| {
+                let _ = write!(out, "{}", state.eta().as_secs());
+            },
+        )
+        .progress_chars("#>-")
+}
+
+// The size of a file, or 0 when it can't be known, like that of an s3:// object
+fn size(file_path: &Path) -> u64 {
+    fs::metadata(file_path).map_or(0, |metadata| metadata.len())
+}
@@ -0,0 +1,103 @@
+// QR codes of a short encrypted text, for `encrypt-text --qr` and `--qr-out`, to carry it to an air-gapped machine
+// by camera. The code holds the encrypted file as one line of base64, without the BEGIN and END lines of the armor,
+// and `decrypt-text` takes that line as it was scanned. At the medium error correction level a code holds 2331
+// bytes, which is about 1.5 KiB of text once the header is counted. On a terminal the code is drawn with half
+// blocks, two rows of modules to a line, in black on white whatever the terminal's colors; a PNG image gets 8 pixels
+// to a module. Both keep the 4-module quiet zone scanners need around it. Needs the `qr` cargo feature.
+#[cfg(feature = "qr")]
+use crate::streamed;
+use encryptor::EncryptError;
+#[cfg(feature = "qr")]
+use qrcode::{EcLevel, QrCode};
+use std::io;
+
+// Where the code goes
+pub enum Target {
+    Terminal,
+    Png(String),
+}
+
+#[cfg(feature = "qr")]
+const PIXELS_PER_MODULE: usize = 8;
+#[cfg(feature = "qr")]
+const QUIET_ZONE: usize = 4;
+
+// Draw `payload` as a QR code for the terminal, colored when `ansi`, a newline ending every line
+#[cfg(feature = "qr")]
+pub fn terminal(payload: &str, ansi: bool) -> Result<String, EncryptError> {
+    use qrcode::render::unicode::Dense1x2;
+
+    let drawn = code(payload)?
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Dark)
+        .light_color(Dense1x2::Light)
+        .build();
+    let lines = drawn.lines().map(|line| match ansi {
+        true => format!("\x1b[30;107m{}\x1b[0m\n", line),
+        false => format!("{}\n", line),
+    });
+    Ok(lines.collect())
+}
+
+// Write `payload` as a QR code to the PNG image at `path`
+#[cfg(feature = "qr")]
+pub fn png(payload: &str, path: &str) -> Result<(), EncryptError> {
+    let code = code(payload)?;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let side = (modules + 2 * QUIET_ZONE) * PIXELS_PER_MODULE;
+    let mut pixels = vec![255u8; side * side];
+    for (index, color) in colors.iter().enumerate() {
+        if *color != qrcode::Color::Dark {
+            continue;
+        }
+        let (x, y) = (index % modules + QUIET_ZONE, index / modules + QUIET_ZONE);
+        for row in y * PIXELS_PER_MODULE..(y + 1) * PIXELS_PER_MODULE {
+            let start = row * side + x * PIXELS_PER_MODULE;
+            pixels[start..start + PIXELS_PER_MODULE].fill(0);
+        }
+    }
+
+    let mut image = Vec::new();
+    let mut encoder = png::Encoder::new(&mut image, side as u32, side as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(io::Error::other)?;
+    streamed::write(&image[..], path)?;
+    Ok(())
+}
+
+#[cfg(feature = "qr")]
+fn code(payload: &str) -> Result<QrCode, EncryptError> {
+    QrCode::with_error_correction_level(payload, EcLevel::M).map_err(|err| {
+        let message = match err {
+            qrcode::types::QrError::DataTooLong => format!(
+                "The encrypted text is too long for a QR code ({} bytes of base64, at most 2331)",
+                payload.len()
+            ),
+            err => format!("Cannot make a QR code: {}", err),
+        };
+        EncryptError::IoError(io::Error::new(io::ErrorKind::InvalidInput, message))
+    })
+}
+
+#[cfg(not(feature = "qr"))]
+pub fn terminal(_payload: &str, _ansi: bool) -> Result<String, EncryptError> {
+    Err(unavailable().into())
+}
+
+#[cfg(not(feature = "qr"))]
+pub fn png(_payload: &str, _path: &str) -> Result<(), EncryptError> {
+    Err(unavailable().into())
+}
+
+#[cfg(not(feature = "qr"))]
+fn unavailable() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "QR codes are not supported by this build (enable the 'qr' feature)",
+    )
+}
@@ -0,0 +1,260 @@
+//! Decrypting single chunks of a file, and finding which chunks a byte range of its plaintext needs, for serving
+//! HTTP Range requests straight from encrypted blobs.
+//!
+//! Every chunk of a chunked payload is sealed on its own and has the same size but the last, so where the bytes of
+//! any range are follows from the header, without an index. A [`ChunkIndex`] is built from the start of a file and
+//! its length, and tells which bytes of the file to fetch for a range of its plaintext; only those are fetched from
+//! the blob store, and [`ChunkIndex::decrypt_range`] (or [`ChunkIndex::decrypt_chunk`], one chunk at a time)
+//! authenticates them and hands back the plaintext:
+//!
+//! ```no_run
+//! use encryptor::{ChunkIndex, Encryptor};
+//! # fn fetch(_: std::ops::Range<u64>) -> Vec<u8> { Vec::new() }
+//!
+//! # fn main() -> Result<(), encryptor::EncryptError> {
+//! let encryptor = Encryptor::new("correct horse battery staple");
+//! // The header and the first chunk, more than enough for the header alone
+//! let blob_len = 10 << 30;
+//! let index = ChunkIndex::new(&fetch(0..(1 << 20) + 4096), blob_len, &encryptor)?;
+//!
+//! // `Range: bytes=1000000-1999999`
+//! let range = 1_000_000..2_000_000;
+//! let sealed = fetch(index.ciphertext_range(range.clone()));
+//! let body = index.decrypt_range(range, &sealed)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Ranges are half-open, `start..end`, where an HTTP Range header gives the last byte: `bytes=0-99` is `0..100`.
+//! Every chunk is authenticated before any of its plaintext is returned, so a response never includes plaintext
+//! that was tampered with. The sealed name of a file encrypted with
+//! [`Encryptor::with_name_encryption`](crate::Encryptor::with_name_encryption) and the padding of
+//! [`Encryptor::with_padding`](crate::Encryptor::with_padding) are left out, so plaintext offsets are offsets into
+//! the file as it was encrypted. The payload must be chunked and uncompressed, as files of at least
+//! [`Encryptor::with_stream_threshold`](crate::Encryptor::with_stream_threshold) bytes and the output of
+//! [`EncryptingWriter`](crate::EncryptingWriter) are, and written raw rather than encoded.
+use crate::header::{Header, HeaderError};
+use crate::{
+    check_ciphertext_len, log_layout, padding, sealed_name_complete, stream, unseal_name, Codec,
+    EncryptError, Encryptor, TAG_LEN,
+};
+use std::io;
+use std::ops::Range;
+
+/// Where the chunks of an encrypted file are, and the key to open them, for decrypting any of them on its own.
+pub struct ChunkIndex {
+    header: Header,
+    header_len: u64,
+    chunk_size: u64,
+    count: u64,
+    ciphertext_len: u64,
+    name_len: u64,     // The sealed name in front of the contents, if there is one
+    unpadded_len: u64, // The payload without its padding, name included
+    chunks: stream::Chunks,
+}
+
+impl ChunkIndex {
+    /// Parse the header at the start of `head` and derive the file's key with the settings of `encryptor`.
+    /// `file_len` is the length of the whole file, which says how many chunks there are.
+    ///
+    /// `head` is the start of the file: its header, and when the name is sealed with the contents the chunks
+    /// holding it, which is the first chunk for any name shorter than a chunk. Fetching the first 4096 bytes plus a
+    /// chunk covers both; a `head` too short fails with [`HeaderError::Truncated`], and can be fetched again
+    /// longer. The header copy at the end of the file isn't checked.
+    pub fn new(head: &[u8], file_len: u64, encryptor: &Encryptor) -> Result<Self, EncryptError> {
+        let (header, header_len) = Header::parse(head)?;
+        let chunk_size = match header.chunk_size {
+            Some(chunk_size) if header.compression == Codec::None => chunk_size,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "only a chunked, uncompressed payload can be read at an offset, and this one is sealed in one piece or compressed",
+                )
+                .into())
+            }
+        };
+        let trailer_len = match header.header_backup {
+            true => header_len as u64 + 4,
+            false => 0,
+        };
+        let ciphertext_len = file_len
+            .checked_sub(header_len as u64 + trailer_len)
+            .ok_or(HeaderError::Truncated)?;
+        check_ciphertext_len(header.ciphertext_len(), ciphertext_len)?;
+        let count = stream::ciphertext_chunk_count(ciphertext_len, chunk_size)?;
+
+        log_layout("Opening", &header);
+        let key_bytes = encryptor.key(&header)?;
+        let aad = header.associated_data(&head[..header_len]);
+        let unpadded_len =
+            padding::unpadded_len(&header, &key_bytes, ciphertext_len - count * TAG_LEN as u64)?;
+        let mut index = ChunkIndex {
+            chunks: stream::Chunks::new(header.algorithm, &key_bytes, &header.nonce, &aad),
+            header,
+            header_len: header_len as u64,
+            chunk_size: chunk_size as u64,
+            count,
+            ciphertext_len,
+            name_len: 0,
+            unpadded_len,
+        };
+        // The sealed name is opened and checked like decrypting does, to know where the contents start
+        if index.header.sealed_name {
+            let mut prefix = Vec::new();
+            let mut chunk = 0;
+            while !sealed_name_complete(&prefix) && chunk < count {
+                let range = index.sealed_chunk(chunk);
+                let sealed = head
+                    .get(range.start as usize..range.end as usize)
+                    .ok_or(HeaderError::Truncated)?;
+                prefix.extend_from_slice(&index.open(chunk, sealed)?);
+                chunk += 1;
+            }
+            let rest = unseal_name(prefix.clone(), index.header.escaped_name)?.0;
+            index.name_len = (prefix.len() - rest.len()) as u64;
+        }
+        Ok(index)
+    }
+
+    /// The header of the file.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The length of the plaintext, the Content-Length of the whole file.
+    pub fn len(&self) -> u64 {
+        self.unpadded_len - self.name_len
+    }
+
+    /// Whether the plaintext is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many chunks the payload is sealed in.
+    pub fn chunk_count(&self) -> u64 {
+        self.count
+    }
+
+    /// The number of plaintext bytes in each chunk but the last.
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size as u32
+    }
+
+    /// The index of the chunk holding the plaintext byte at `offset`. Offsets at or past the end of the plaintext
+    /// are in the last chunk.
+    pub fn chunk_at(&self, offset: u64) -> u64 {
+        ((self.name_len + offset) / self.chunk_size).min(self.count - 1)
+    }
+
+    /// Where the chunk at `index` is in the file: its ciphertext and tag, as [`ChunkIndex::decrypt_chunk`] takes
+    /// them.
+    pub fn sealed_chunk(&self, index: u64) -> Range<u64> {
+        let sealed_chunk_len = self.chunk_size + TAG_LEN as u64;
+        let offset = (index * sealed_chunk_len).min(self.ciphertext_len);
+        let len = (self.ciphertext_len - offset).min(sealed_chunk_len);
+        self.header_len + offset..self.header_len + offset + len
+    }
+
+    /// Which bytes of the plaintext the chunk at `index` holds. Empty for a chunk holding nothing but the sealed
+    /// name or padding.
+    pub fn plaintext_of(&self, index: u64) -> Range<u64> {
+        let clamp =
+            |position: u64| position.clamp(self.name_len, self.unpadded_len) - self.name_len;
+        clamp(index * self.chunk_size)..clamp((index + 1) * self.chunk_size)
+    }
+
+    /// The indexes of the chunks holding `range` of the plaintext, cut to its length. Empty for an empty range.
+    pub fn chunks_for(&self, range: Range<u64>) -> Range<u64> {
+        let range = self.clamp(range);
+        match range.is_empty() {
+            true => self.chunk_at(range.start)..self.chunk_at(range.start),
+            false => self.chunk_at(range.start)..self.chunk_at(range.end - 1) + 1,
+        }
+    }
+
+    /// The bytes of the file to fetch for `range` of the plaintext: the chunks holding it, one after the other.
+    pub fn ciphertext_range(&self, range: Range<u64>) -> Range<u64> {
+        let chunks = self.chunks_for(range);
+        match chunks.is_empty() {
+            true => self.header_len..self.header_len,
+            false => self.sealed_chunk(chunks.start).start..self.sealed_chunk(chunks.end - 1).end,
+        }
+    }
+
+    /// Authenticate and decrypt the chunk at `index`, its ciphertext and tag being `sealed`, returning the plaintext
+    /// it holds ([`ChunkIndex::plaintext_of`]). A chunk that fails fails with [`EncryptError::CorruptChunk`].
+    pub fn decrypt_chunk(&self, index: u64, sealed: &[u8]) -> Result<Vec<u8>, EncryptError> {
+        if index >= self.count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "chunk {} is past the end of a payload of {} chunks",
+                    index, self.count
+                ),
+            )
+            .into());
+        }
+        let mut plaintext = self.open(index, sealed)?;
+        let start = index * self.chunk_size;
+        let contents = self.plaintext_of(index);
+        // A chunk past the end of the contents holds nothing but padding
+        let from =
+            ((self.name_len + contents.start).saturating_sub(start) as usize).min(plaintext.len());
+        plaintext.truncate(from + (contents.end - contents.start) as usize);
+        plaintext.drain(..from);
+        Ok(plaintext)
+    }
+
+    /// Authenticate and decrypt `range` of the plaintext, cut to its length, `sealed` being the bytes of
+    /// [`ChunkIndex::ciphertext_range`] for it.
+    pub fn decrypt_range(&self, range: Range<u64>, sealed: &[u8]) -> Result<Vec<u8>, EncryptError> {
+        let range = self.clamp(range);
+        let fetched = self.ciphertext_range(range.clone());
+        if sealed.len() as u64 != fetched.end - fetched.start {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the range needs the {} bytes of the file from {}, but was given {}",
+                    fetched.end - fetched.start,
+                    fetched.start,
+                    sealed.len()
+                ),
+            )
+            .into());
+        }
+        let mut plaintext = Vec::with_capacity((range.end - range.start) as usize);
+        for index in self.chunks_for(range.clone()) {
+            let chunk = self.sealed_chunk(index);
+            let sealed = &sealed
+                [(chunk.start - fetched.start) as usize..(chunk.end - fetched.start) as usize];
+            let contents = self.plaintext_of(index);
+            let chunk_plaintext = self.decrypt_chunk(index, sealed)?;
+            let from = range.start.max(contents.start) - contents.start;
+            let to = range.end.min(contents.end) - contents.start;
+            plaintext.extend_from_slice(&chunk_plaintext[from as usize..to as usize]);
+        }
+        Ok(plaintext)
+    }
+
+    // `range` cut to the length of the plaintext
+    fn clamp(&self, range: Range<u64>) -> Range<u64> {
+        let end = range.end.min(self.len());
+        range.start.min(end)..end
+    }
+
+    // Open the chunk at `index`, returning all of its plaintext: any sealed name and padding included
+    fn open(&self, index: u64, sealed: &[u8]) -> Result<Vec<u8>, EncryptError> {
+        let mut chunk = sealed.to_vec();
+        self.chunks
+            .open_at(index, &mut chunk, index == self.count - 1)
+            .map_err(|err| match err {
+                EncryptError::AeadError(_) => EncryptError::CorruptChunk {
+                    index,
+                    offset: self.sealed_chunk(index).start,
+                },
+                err => err,
+            })?;
+        Ok(chunk)
+    }
+}
@@ -0,0 +1,187 @@
+//! Encryption to X25519 public keys, so the sender never needs a shared password.
+//!
+//! Every file gets a random file key, and the header carries one [`Stanza`] per recipient with a copy of it:
+//! an ephemeral X25519 key agreement with the recipient's public key, HKDF-SHA256 over the shared secret to get
+//! a single-use wrapping key, and the file's AEAD to seal the file key with it. Only the holder of the matching
+//! [`Identity`] can unwrap a copy and decrypt the file. Passwords can be recipients of the same file too, see
+//! [`Encryptor::with_extra_password`](crate::Encryptor::with_extra_password).
+//!
+//! ```no_run
+//! use encryptor::recipient::Identity;
+//! use encryptor::Encryptor;
+//!
+//! let identity = Identity::generate()?;
+//! let sealed = Encryptor::for_recipients(vec![identity.recipient()]).encrypt_bytes(b"hello")?;
+//! assert_eq!(Encryptor::from_identity(identity).decrypt_bytes(&sealed)?, b"hello");
+//! # Ok::<(), encryptor::EncryptError>(())
+//! ```
+use crate::header::{Stanza, StanzaKind};
+use crate::rng::RandomSource;
+use crate::{cipher, Algorithm, CryptoError, EncryptError, KeyBytes, KEY_LEN, NONCE_LEN, TAG_LEN};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+use zeroize::Zeroizing;
+
+// Domain separation for the wrapping key, so it can never collide with a key derived for another purpose
+const WRAP_INFO: &[u8] = b"encryptor x25519 file key";
+// An X25519 stanza body: the ephemeral public key followed by the sealed file key and its tag
+const BODY_LEN: usize = 32 + KEY_LEN + TAG_LEN;
+
+/// An X25519 private key, able to decrypt files encrypted to its [`Recipient`]
+#[derive(Clone)]
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    /// Generate a new identity with the crypto backend's secure random generator
+    pub fn generate() -> Result<Self, EncryptError> {
+        let mut bytes = [0u8; 32];
+        cipher::fill_random(&mut bytes)?;
+        Ok(Identity::from_bytes(bytes))
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Identity(StaticSecret::from(bytes))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    /// The identity of an Ed25519 signing key (such as an SSH key) given by its 32-byte seed, converted for X25519
+    /// like age does: the clamped scalar the Ed25519 key signs with. It decrypts the files encrypted to
+    /// [`Recipient::from_ed25519`] of the key's public half.
+    #[cfg(feature = "ssh")]
+    pub fn from_ed25519(seed: &[u8; 32]) -> Self {
+        let scalar = Zeroizing::new(ed25519_dalek::SigningKey::from_bytes(seed).to_scalar_bytes());
+        Identity::from_bytes(*scalar)
+    }
+
+    /// The public key files for this identity are encrypted to
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+
+    // The raw X25519 shared secret with `public`, or None when a low-order public key made it predictable.
+    // For formats that derive their own keys from it, like JWE's ECDH-ES.
+    #[cfg(feature = "jwe")]
+    pub(crate) fn shared_secret(&self, public: &Recipient) -> Option<Zeroizing<[u8; 32]>> {
+        let shared = self.0.diffie_hellman(&public.0);
+        shared
+            .was_contributory()
+            .then(|| Zeroizing::new(shared.to_bytes()))
+    }
+}
+
+// The private key must never end up in logs or panic messages
+impl std::fmt::Debug for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Identity")
+            .field("recipient", &self.recipient())
+            .finish_non_exhaustive()
+    }
+}
+
+/// An X25519 public key that files can be encrypted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recipient(PublicKey);
+
+impl Recipient {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Recipient(PublicKey::from(bytes))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    /// The recipient of an Ed25519 public key (such as an SSH key), converted for X25519 like age does: the
+    /// Montgomery form of the Edwards point. None when the bytes aren't a valid Ed25519 public key.
+    #[cfg(feature = "ssh")]
+    pub fn from_ed25519(public_key: &[u8; 32]) -> Option<Self> {
+        let public_key = ed25519_dalek::VerifyingKey::from_bytes(public_key).ok()?;
+        Some(Recipient::from_bytes(public_key.to_montgomery().to_bytes()))
+    }
+}
+
+// Wrap the file key for one recipient, sealing it with `algorithm`
+pub(crate) fn wrap(
+    file_key: &[u8; KEY_LEN],
+    recipient: &Recipient,
+    algorithm: Algorithm,
+    rng: &dyn RandomSource,
+) -> Result<Stanza, EncryptError> {
+    let mut bytes = Zeroizing::new([0u8; 32]);
+    rng.fill(&mut bytes[..])?;
+    let ephemeral = Identity::from_bytes(*bytes);
+    let ephemeral_public = ephemeral.recipient();
+    let shared = ephemeral.0.diffie_hellman(&recipient.0);
+    let wrap_key = wrap_key(shared, &ephemeral_public, recipient)?;
+
+    // The wrapping key is used exactly once, so a fixed nonce is safe
+    let mut sealed = file_key.to_vec();
+    cipher::seal_in_place(algorithm, &wrap_key, &[0u8; NONCE_LEN], &[], &mut sealed)?;
+
+    let mut body = ephemeral_public.to_bytes().to_vec();
+    body.extend_from_slice(&sealed);
+    Ok(Stanza {
+        kind: StanzaKind::X25519,
+        body,
+    })
+}
+
+// Find the stanza addressed to `identity` and unwrap the file key from it.
+// Returns None when none of the stanzas are for this identity.
+pub(crate) fn unwrap(
+    stanzas: &[Stanza],
+    identity: &Identity,
+    algorithm: Algorithm,
+) -> Option<KeyBytes> {
+    let ours = identity.recipient();
+    for stanza in stanzas {
+        if stanza.kind != StanzaKind::X25519 || stanza.body.len() != BODY_LEN {
+            continue;
+        }
+        let (ephemeral_public, sealed) = stanza.body.split_at(32);
+        let Ok(ephemeral_public) = <[u8; 32]>::try_from(ephemeral_public) else {
+            continue;
+        };
+        let ephemeral_public = Recipient::from_bytes(ephemeral_public);
+        let shared = identity.0.diffie_hellman(&ephemeral_public.0);
+        let Ok(wrap_key) = wrap_key(shared, &ephemeral_public, &ours) else {
+            continue;
+        };
+
+        // A stanza for someone else simply fails to authenticate
+        let mut file_key = Zeroizing::new(sealed.to_vec());
+        if cipher::open_in_place(algorithm, &wrap_key, &[0u8; NONCE_LEN], &[], &mut file_key)
+            .is_ok()
+        {
+            if let Ok(file_key) = file_key[..].try_into() {
+                return Some(Zeroizing::new(file_key));
+            }
+        }
+    }
+    None
+}
+
+// Derive the single-use wrapping key from the shared secret of the key agreement.
+// Both public keys go into the HKDF salt, so the key is bound to this exact pair.
+fn wrap_key(
+    shared: SharedSecret,
+    ephemeral_public: &Recipient,
+    recipient: &Recipient,
+) -> Result<KeyBytes, EncryptError> {
+    // A low-order public key would make the shared secret predictable
+    if !shared.was_contributory() {
+        return Err(EncryptError::AeadError(CryptoError));
+    }
+
+    let mut salt = ephemeral_public.to_bytes().to_vec();
+    salt.extend_from_slice(&recipient.to_bytes());
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(WRAP_INFO, &mut *key)
+        .map_err(|_| EncryptError::AeadError(CryptoError))?;
+    Ok(key)
+}
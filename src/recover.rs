@@ -0,0 +1,282 @@
+// `encryptor recover`: salvaging an encrypted file whose leading bytes were damaged.
+//
+// Without --assume, the header copy stored at the end of the file by `encrypt --header-backup` is put back in front
+// of the ciphertext. With --assume, an expert supplies the header fields they know as comma-separated key=value pairs:
+//
+//   version=4  cipher=aes256gcm  kdf=argon2id:<memory KiB>:<iterations> (or raw, or recipients)
+//   salt=<hex>  nonce=<hex>  name=<file name>  len=<payload bytes>  compression=zstd  dict=<id>
+//   sealed-name=true (the file was encrypted with --encrypt-names)  chunk-size=<bytes> (the payload is chunked)
+//   created=<seconds since the Unix epoch>, key-id=<ID> and checksum=<hex> (version 4 on)
+//   header-len=<bytes the damaged header occupies>
+//
+// The version, cipher and KDF sit at fixed offsets, so they are patched into the damaged bytes and the rest of the
+// header is read from where it was; the other fields then replace what was read. If the header can't be read at all,
+// it is built from the assumptions alone. The header is authenticated, so decrypting the output only succeeds when
+// every field matches the original.
+use encryptor::compression::Codec;
+use encryptor::encoding;
+use encryptor::header::{self, Header, Kdf};
+use encryptor::{Algorithm, KdfParams};
+use std::fs;
+use std::path::Path;
+
+// Where the version, algorithm and KDF fields end, after the magic bytes
+const FIXED_LEN: usize = 15;
+
+// Header fields given with --assume
+#[derive(Default)]
+struct Assumptions {
+    version: Option<u8>,
+    algorithm: Option<Algorithm>,
+    kdf: Option<(Kdf, KdfParams)>,
+    salt: Option<Vec<u8>>,
+    nonce: Option<Vec<u8>>,
+    file_name: Option<String>,
+    payload_len: Option<u64>,
+    compression: Option<Codec>,
+    dictionary_id: Option<u32>,
+    sealed_name: Option<bool>,
+    chunk_size: Option<u32>,
+    created: Option<u64>,
+    key_id: Option<String>,
+    checksum: Option<[u8; 32]>,
+    header_len: Option<usize>,
+}
+
+// Write `file_path` with a restored header to `out_path`, returning a description of what was done
+pub fn recover(file_path: &Path, assume: Option<&str>, out_path: &Path) -> Result<String, String> {
+    // Armored and other encoded files are read like the binary file inside them
+    let contents = fs::read(file_path)
+        .map_err(|err| format!("{}: {}", file_path.display(), err))
+        .and_then(|contents| {
+            encoding::decode(contents).map_err(|err| format!("{}: {}", file_path.display(), err))
+        })?;
+    let (header, ciphertext_start, source) = match assume {
+        None => {
+            let (header, start) = from_backup(&contents).ok_or_else(|| {
+                format!(
+                    "{} has no intact header backup; give the header fields with --assume",
+                    file_path.display()
+                )
+            })?;
+            (header, start, "its header backup")
+        }
+        Some(assume) => {
+            let assumptions = parse_assumptions(assume)?;
+            let (header, start) = from_assumptions(&contents, &assumptions)
+                .map_err(|err| format!("{}: {}", file_path.display(), err))?;
+            (header, start, "the assumed header fields")
+        }
+    };
+
+    let mut output = header.to_bytes();
+    output.extend_from_slice(&contents[ciphertext_start..]);
+    fs::write(out_path, output).map_err(|err| format!("{}: {}", out_path.display(), err))?;
+    Ok(format!(
+        "Wrote {} with the header rebuilt from {}; decrypt it to check that it authenticates",
+        out_path.display(),
+        source
+    ))
+}
+
+// The header copy at the end of the file, and where the ciphertext in front of it starts
+fn from_backup(contents: &[u8]) -> Option<(Header, usize)> {
+    let (header, backup_start) = Header::parse_backup(contents)?;
+    let start = match header.ciphertext_len() {
+        Some(ciphertext_len) => backup_start.checked_sub(usize::try_from(ciphertext_len).ok()?)?,
+        // The damaged header was as long as its copy
+        None => contents.len() - 4 - backup_start,
+    };
+    Some((header, start))
+}
+
+fn from_assumptions(contents: &[u8], assumptions: &Assumptions) -> Result<(Header, usize), String> {
+    let mut patched = contents.to_vec();
+    if patched.len() >= FIXED_LEN {
+        patched[..header::MAGIC.len()].copy_from_slice(header::MAGIC);
+        if let Some(version) = assumptions.version {
+            patched[4] = version;
+        }
+        if let Some(algorithm) = assumptions.algorithm {
+            patched[5] = algorithm.id();
+        }
+        if let Some((kdf, params)) = assumptions.kdf {
+            patched[6] = kdf.id();
+            patched[7..11].copy_from_slice(&params.memory.to_le_bytes());
+            patched[11..15].copy_from_slice(&params.iterations.to_le_bytes());
+        }
+    }
+
+    let (mut header, mut header_len) = match Header::parse(&patched) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            let header = build(assumptions).map_err(|missing| {
+                format!(
+                    "the damaged header can't be read ({}), and {}",
+                    err, missing
+                )
+            })?;
+            let header_len = match header.ciphertext_len() {
+                Some(ciphertext_len) => (contents.len() as u64)
+                    .checked_sub(ciphertext_len)
+                    .ok_or("the file is shorter than the assumed payload length")?
+                    as usize,
+                None => header.to_bytes().len(),
+            };
+            (header, header_len)
+        }
+    };
+
+    if let Some(salt) = &assumptions.salt {
+        header.salt = salt.clone();
+    }
+    if let Some(nonce) = &assumptions.nonce {
+        header.nonce = nonce.clone();
+    }
+    if let Some(file_name) = &assumptions.file_name {
+        header.file_name = Some(file_name.clone());
+    }
+    if let Some(payload_len) = assumptions.payload_len {
+        header.payload_len = Some(payload_len);
+    }
+    if let Some(compression) = assumptions.compression {
+        header.compression = compression;
+    }
+    if let Some(dictionary_id) = assumptions.dictionary_id {
+        header.dictionary_id = Some(dictionary_id);
+    }
+    if let Some(sealed_name) = assumptions.sealed_name {
+        header.sealed_name = sealed_name;
+    }
+    if let Some(chunk_size) = assumptions.chunk_size {
+        header.chunk_size = Some(chunk_size);
+    }
+    if let Some(created) = assumptions.created {
+        header.created = Some(created);
+    }
+    if let Some(key_id) = &assumptions.key_id {
+        header.key_id = Some(key_id.clone());
+    }
+    if let Some(checksum) = assumptions.checksum {
+        header.checksum = Some(checksum);
+    }
+    if let Some(len) = assumptions.header_len {
+        header_len = len;
+    }
+    if header_len > contents.len() {
+        return Err(format!(
+            "the header can't be {} bytes long, the file only has {}",
+            header_len,
+            contents.len()
+        ));
+    }
+    Ok((header, header_len))
+}
+
+// A header made of nothing but the assumptions, for when the damaged one can't be read at all
+fn build(assumptions: &Assumptions) -> Result<Header, &'static str> {
+    let algorithm = assumptions.algorithm.ok_or("no cipher was assumed")?;
+    let (kdf, kdf_params) = assumptions.kdf.ok_or("no kdf was assumed")?;
+    let nonce = assumptions.nonce.clone().ok_or("no nonce was assumed")?;
+    let salt = match (kdf, &assumptions.salt) {
+        (Kdf::Argon2id | Kdf::Argon2idHkdf, None) => return Err("no salt was assumed"),
+        (_, salt) => salt.clone().unwrap_or_default(),
+    };
+    if kdf == Kdf::Recipients {
+        return Err("the recipients of a file can't be assumed");
+    }
+    Ok(Header {
+        version: assumptions.version.unwrap_or(header::VERSION),
+        algorithm,
+        kdf,
+        kdf_params,
+        salt,
+        nonce,
+        file_name: assumptions.file_name.clone(),
+        payload_len: assumptions.payload_len,
+        compression: assumptions.compression.unwrap_or(Codec::None),
+        dictionary_id: assumptions.dictionary_id,
+        recipients: Vec::new(),
+        header_backup: false,
+        sealed_name: assumptions.sealed_name.unwrap_or(false),
+        escaped_name: false,
+        hash_name: false,
+        chunk_size: assumptions.chunk_size,
+        created: assumptions.created,
+        rewrappable: false,
+        key_id: assumptions.key_id.clone(),
+        checksum: assumptions.checksum,
+        padding: None,
+        password_check: None,
+        password_set: None,
+    })
+}
+
+// Parse `cipher=aes256gcm,kdf=argon2id:19456:2,...`
+fn parse_assumptions(assume: &str) -> Result<Assumptions, String> {
+    let mut assumptions = Assumptions::default();
+    for pair in assume.split(',').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').ok_or_else(|| {
+            format!(
+                "--assume expects key=value pairs, e.g. cipher=aes256gcm,kdf=argon2id:19456:2, not '{}'",
+                pair
+            )
+        })?;
+        let invalid = || format!("--assume: invalid value for {}: '{}'", key, value);
+        let number = |value: &str| value.parse::<u64>().map_err(|_| invalid());
+        let bytes = |value: &str| {
+            hex::decode(value)
+                .ok()
+                .filter(|bytes| bytes.len() <= u8::MAX as usize)
+                .ok_or_else(invalid)
+        };
+        match key {
+            "version" => assumptions.version = Some(value.parse().map_err(|_| invalid())?),
+            "cipher" => {
+                assumptions.algorithm = Some(Algorithm::from_name(value).ok_or_else(invalid)?)
+            }
+            "kdf" => assumptions.kdf = Some(parse_kdf(value).ok_or_else(invalid)?),
+            "salt" => assumptions.salt = Some(bytes(value)?),
+            "nonce" => assumptions.nonce = Some(bytes(value)?),
+            "name" => assumptions.file_name = Some(value.to_string()),
+            "len" => assumptions.payload_len = Some(number(value)?),
+            "compression" => {
+                assumptions.compression = Some(Codec::from_name(value).ok_or_else(invalid)?)
+            }
+            "dict" => assumptions.dictionary_id = Some(value.parse().map_err(|_| invalid())?),
+            "sealed-name" => assumptions.sealed_name = Some(value.parse().map_err(|_| invalid())?),
+            "chunk-size" => {
+                let size = value.parse().ok().filter(|&size: &u32| size > 0);
+                assumptions.chunk_size = Some(size.ok_or_else(invalid)?)
+            }
+            "created" => assumptions.created = Some(number(value)?),
+            "key-id" if value.len() <= u8::MAX as usize => {
+                assumptions.key_id = Some(value.to_string())
+            }
+            "checksum" => {
+                let checksum = bytes(value)?.try_into().map_err(|_| invalid())?;
+                assumptions.checksum = Some(checksum)
+            }
+            "header-len" => assumptions.header_len = Some(value.parse().map_err(|_| invalid())?),
+            _ => return Err(format!("--assume: unknown header field '{}'", key)),
+        }
+    }
+    Ok(assumptions)
+}
+
+// `argon2id:<memory>:<iterations>`, `argon2id-hkdf:<memory>:<iterations>`, `raw` or `recipients`
+fn parse_kdf(value: &str) -> Option<(Kdf, KdfParams)> {
+    let mut parts = value.split(':');
+    let kdf = Kdf::from_name(parts.next()?)?;
+    let params = match kdf {
+        Kdf::Argon2id | Kdf::Argon2idHkdf => KdfParams {
+            memory: parts.next()?.parse().ok()?,
+            iterations: parts.next()?.parse().ok()?,
+        },
+        _ => KdfParams {
+            memory: 0,
+            iterations: 0,
+        },
+    };
+    parts.next().is_none().then_some((kdf, params))
+}
@@ -0,0 +1,54 @@
+// Recovery codes for `encrypt --recovery-code`: a random code of 25 Crockford base32 symbols (125 bits), printed
+// as five groups like 7K3QD-M0XZ4-... for the user to keep apart from the password. The file key of every output
+// is also wrapped under the code, in a second password stanza, so the code decrypts the files when the password
+// is forgotten. `decrypt --recovery-code` asks for it instead of the password and reads it leniently: case,
+// dashes and spaces don't matter, and O, I and L are taken for 0, 1 and 1.
+use encryptor::fill_random;
+use zeroize::Zeroizing;
+
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const GROUPS: usize = 5;
+const GROUP_LEN: usize = 5;
+
+// A new recovery code, in its printed form
+pub fn generate() -> Result<Zeroizing<String>, String> {
+    let mut random = Zeroizing::new([0u8; GROUPS * GROUP_LEN]);
+    fill_random(&mut random[..])
+        .map_err(|err| format!("Cannot generate a recovery code: {}", err))?;
+    // 256 is a multiple of 32, so every symbol is equally likely
+    let symbols: Zeroizing<Vec<u8>> = Zeroizing::new(
+        random
+            .iter()
+            .map(|byte| ALPHABET[(byte % 32) as usize])
+            .collect(),
+    );
+    Ok(group(&symbols))
+}
+
+// The printed form of a recovery code as typed, which is what its stanza was wrapped for
+pub fn normalize(typed: &str) -> Result<Zeroizing<String>, String> {
+    let symbols: Option<Vec<u8>> = typed
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => Some(b'0'),
+            'I' | 'L' => Some(b'1'),
+            c => ALPHABET.iter().copied().find(|&symbol| symbol as char == c),
+        })
+        .collect();
+    match symbols.map(Zeroizing::new) {
+        Some(symbols) if symbols.len() == GROUPS * GROUP_LEN => Ok(group(&symbols)),
+        _ => Err(format!(
+            "That isn't a recovery code: they are {} letters and digits, like XXXXX-XXXXX-XXXXX-XXXXX-XXXXX",
+            GROUPS * GROUP_LEN
+        )),
+    }
+}
+
+fn group(symbols: &[u8]) -> Zeroizing<String> {
+    let groups: Vec<&str> = symbols
+        .chunks(GROUP_LEN)
+        .map(|chunk| std::str::from_utf8(chunk).expect("the alphabet is ASCII"))
+        .collect();
+    Zeroizing::new(groups.join("-"))
+}
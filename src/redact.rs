@@ -0,0 +1,198 @@
+// `--redact-paths` (or ENCRYPTOR_REDACT_PATHS=1): for environments where even file names are sensitive, every path
+// the command works on is shown as stable salted hashes of its components in the status lines, log messages,
+// errors, --json and --progress-fd events, the --report, the --debug-transcript and the grant usage log:
+//
+//   ok     Encrypted [4f0c2a9d1e]/[b81e9c07aa] -> [4f0c2a9d1e]/[0d5e37c2f1]
+//
+// A component is hashed with BLAKE3 keyed by a random salt kept in <config>/redact-salt, so the same name always
+// gets the same hash on this machine (to follow a file across runs and logs), but can't be guessed from its hash
+// elsewhere. `.`, `..` and the separators are kept.
+//
+// Like the transcript's secrets, the paths are remembered as they are registered (the files on the command line,
+// --out and the outputs written), and any text shown has them replaced: first the whole paths, then their
+// components where they stand as names of their own.
+use serde_json::Value;
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+const SALT_FILE: &str = "redact-salt";
+
+struct Redactor {
+    salt: [u8; 32],
+    // The registered paths and their components with their redacted forms, longest first
+    paths: Mutex<Vec<(String, String)>>,
+    components: Mutex<Vec<(String, String)>>,
+}
+
+static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+
+fn lock<T>(shared: &Mutex<T>) -> MutexGuard<'_, T> {
+    shared
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Turn redaction on, with the salt of this machine (created on first use)
+pub fn enable() -> Result<(), String> {
+    let path = crate::config_dir()?.join(SALT_FILE);
+    let salt = match fs::read_to_string(&path) {
+        Ok(text) => hex::decode(text.trim())
+            .ok()
+            .and_then(|salt| salt.try_into().ok())
+            .ok_or_else(|| {
+                format!(
+                    "The salt of --redact-paths in {} is damaged",
+                    path.display()
+                )
+            })?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let salt = encryptor::generate_key().map_err(|err| err.to_string())?;
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::write(&path, hex::encode(salt) + "\n"))
+                .map_err(|err| {
+                    format!(
+                        "Cannot keep the salt of --redact-paths in {}: {}",
+                        path.display(),
+                        err
+                    )
+                })?;
+            salt
+        }
+        Err(err) => {
+            return Err(format!(
+                "Cannot read the salt of --redact-paths in {}: {}",
+                path.display(),
+                err
+            ))
+        }
+    };
+    let _ = REDACTOR.set(Redactor {
+        salt,
+        paths: Mutex::new(Vec::new()),
+        components: Mutex::new(Vec::new()),
+    });
+    Ok(())
+}
+
+// Remember `path`, so that it and its components are redacted wherever they would appear
+pub fn path(path: impl AsRef<Path>) {
+    let Some(redactor) = REDACTOR.get() else {
+        return;
+    };
+    // As it is shown, with any bytes that aren't UTF-8 replaced
+    let path = path.as_ref().to_string_lossy();
+    let path = path.as_ref();
+    if path.is_empty() {
+        return;
+    }
+    let mut paths = lock(&redactor.paths);
+    if paths.iter().any(|(known, _)| known == path) {
+        return;
+    }
+    paths.push((path.to_string(), redactor.redact_path(path)));
+    paths.sort_by_key(|(known, _)| std::cmp::Reverse(known.len()));
+
+    let mut components = lock(&redactor.components);
+    for component in path.split(is_separator).filter(|name| is_name(name)) {
+        if !components.iter().any(|(known, _)| known == component) {
+            components.push((component.to_string(), redactor.hash(component)));
+        }
+    }
+    components.sort_by_key(|(known, _)| std::cmp::Reverse(known.len()));
+}
+
+// `text` with every registered path and component replaced by its redacted form
+pub fn text(text: &str) -> Cow<'_, str> {
+    let Some(redactor) = REDACTOR.get() else {
+        return Cow::Borrowed(text);
+    };
+    let mut redacted = text.to_string();
+    let paths = lock(&redactor.paths);
+    let components = lock(&redactor.components);
+    for (name, replacement) in paths.iter().chain(components.iter()) {
+        if redacted.contains(name.as_str()) {
+            redacted = replace_name(&redacted, name, replacement);
+        }
+    }
+    Cow::Owned(redacted)
+}
+
+// Redact every string in `value`
+pub fn value(value: &mut Value) {
+    if REDACTOR.get().is_none() {
+        return;
+    }
+    match value {
+        Value::String(string) => {
+            if let Cow::Owned(redacted) = text(string) {
+                *string = redacted;
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(self::value),
+        Value::Object(members) => members.values_mut().for_each(self::value),
+        _ => {}
+    }
+}
+
+impl Redactor {
+    // The first 5 bytes of the keyed hash, in brackets so it reads as a placeholder
+    fn hash(&self, component: &str) -> String {
+        let hash = blake3::keyed_hash(&self.salt, component.as_bytes());
+        format!("[{}]", hex::encode(&hash.as_bytes()[..5]))
+    }
+
+    fn redact_path(&self, path: &str) -> String {
+        let mut redacted = String::with_capacity(path.len());
+        let mut rest = path;
+        while let Some(index) = rest.find(is_separator) {
+            redacted.push_str(&self.redact_component(&rest[..index]));
+            redacted.push_str(&rest[index..index + 1]);
+            rest = &rest[index + 1..];
+        }
+        redacted.push_str(&self.redact_component(rest));
+        redacted
+    }
+
+    fn redact_component(&self, component: &str) -> String {
+        match is_name(component) {
+            true => self.hash(component),
+            false => component.to_string(),
+        }
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == std::path::MAIN_SEPARATOR
+}
+
+// Whether a component is a name, rather than empty (a root), `.` or `..`
+fn is_name(component: &str) -> bool {
+    !matches!(component, "" | "." | "..")
+}
+
+// Replace `name` in `text` where it stands on its own, not as part of a longer name: `a.txt` is replaced in
+// "a.txt: missing" and "Encrypted a.txt.", but not in "data.txt" or "a.txt.enc"
+fn replace_name(text: &str, name: &str, replacement: &str) -> String {
+    let name_char = |c: char| c.is_alphanumeric() || matches!(c, '-' | '_');
+    let mut replaced = String::with_capacity(text.len());
+    let mut last = 0;
+    for (index, _) in text.match_indices(name) {
+        let before = text[..index].chars().next_back();
+        let mut after = text[index + name.len()..].chars();
+        let continued = match after.next() {
+            Some('.') => after.next().is_some_and(name_char),
+            next => next.is_some_and(name_char),
+        };
+        if index < last || before.is_some_and(|c| name_char(c) || c == '.') || continued {
+            continue;
+        }
+        replaced.push_str(&text[last..index]);
+        replaced.push_str(replacement);
+        last = index + name.len();
+    }
+    replaced.push_str(&text[last..]);
+    replaced
+}
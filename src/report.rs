@@ -0,0 +1,119 @@
+// `--json`: one JSON object per file on stdout instead of the status lines, so orchestration tools can parse results:
+//
+//   {"algorithm":"aes256gcm","bytes":1024,"duration_ms":12.507,"error":null,"input":"test.txt",
+//    "operation":"encrypt","output":"test.txt.enc","status":"ok"}
+//   {"algorithm":"aes256gcm","bytes":1163,"duration_ms":3.1,"error":{"code":"ENC_AUTH_FAIL","exit_code":4,
+//    "message":"...","retryable":false},"input":"test.txt.enc","operation":"decrypt","output":null,"status":"error"}
+//
+// When a chunk of a chunked file fails authentication, the error also has the `chunk` index and the byte `offset`
+// in the file where it starts. When only some of them do, that is the first damaged chunk, and `damaged` lists the
+// runs of damaged chunks, e.g. [{"first_chunk":3,"last_chunk":4,"offset":3145800,"len":2097184}], with `salvaged`
+// naming the output `decrypt --keep-going` wrote the rest into (or null).
+// `bytes` is the size of the input file. `algorithm` is the cipher of the native file written or read, and null for
+// age, JWE and CMS files and for inputs whose header can't be read. `verify` writes nothing, so its output is null.
+// The same objects are recorded as the "file" events of a --debug-transcript.
+use encryptor::encoding;
+use encryptor::header::{self, Header};
+use encryptor::EncryptError;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// How much of a binary file to read for its header; even with 255 recipients it is far shorter
+const HEADER_PREFIX: u64 = 128 << 10;
+
+// What is known about a file operation before it runs
+pub struct Operation<'a> {
+    name: &'a str,
+    input: &'a Path,
+    bytes: Option<u64>,
+    algorithm: Option<&'static str>,
+    start: Instant,
+}
+
+impl<'a> Operation<'a> {
+    // Start timing the operation `name` (encrypt, decrypt or verify) on `input`, which is about to be read with
+    // the cipher `algorithm`
+    pub fn start(name: &'a str, input: &'a Path, algorithm: Option<&'static str>) -> Self {
+        Operation {
+            name,
+            input,
+            bytes: fs::metadata(input).ok().map(|metadata| metadata.len()),
+            algorithm,
+            start: Instant::now(),
+        }
+    }
+
+    // The report of the operation, once it ended with `result`
+    // Paths are shown as text, like they are in the status lines
+    pub fn finish(self, result: &Result<PathBuf, EncryptError>) -> Value {
+        let output = match result {
+            Ok(output) if self.name != "verify" => Some(output.to_string_lossy()),
+            _ => None,
+        };
+        let error = result.as_ref().err().map(|err| {
+            let code = err.code();
+            let mut error = json!({
+                "code": code.as_str(),
+                "exit_code": code.number(),
+                "retryable": err.is_retryable(),
+                "message": err.to_string(),
+            });
+            // Where a chunked file stopped authenticating
+            if let EncryptError::CorruptChunk { index, offset } = err {
+                error["chunk"] = json!(index);
+                error["offset"] = json!(offset);
+            }
+            if let EncryptError::DamagedChunks { ranges, salvaged } = err {
+                error["chunk"] = json!(ranges[0].first_chunk);
+                error["offset"] = json!(ranges[0].offset);
+                error["damaged"] = ranges
+                    .iter()
+                    .map(|range| {
+                        json!({
+                            "first_chunk": range.first_chunk,
+                            "last_chunk": range.last_chunk,
+                            "offset": range.offset,
+                            "len": range.len,
+                        })
+                    })
+                    .collect();
+                error["salvaged"] = json!(salvaged.as_deref().map(Path::to_string_lossy));
+            }
+            error
+        });
+        json!({
+            "operation": self.name,
+            "status": if result.is_ok() { "ok" } else { "error" },
+            "input": self.input.to_string_lossy(),
+            "output": output,
+            "bytes": self.bytes,
+            "algorithm": self.algorithm,
+            "duration_ms": (self.start.elapsed().as_secs_f64() * 1e6).round() / 1000.0,
+            "error": error,
+        })
+    }
+}
+
+// The cipher recorded in the header of the native file at `file_path`
+pub fn native_algorithm(file_path: &Path) -> Option<&'static str> {
+    native_header(file_path).map(|header| header.algorithm.name())
+}
+
+// The header of the native file at `file_path`. A binary file only has its start read; armored and other encoded
+// files are read whole, like decrypting them does.
+pub fn native_header(file_path: &Path) -> Option<Header> {
+    let mut start = Vec::new();
+    File::open(file_path)
+        .ok()?
+        .take(HEADER_PREFIX)
+        .read_to_end(&mut start)
+        .ok()?;
+    if !start.starts_with(header::MAGIC) {
+        start = encoding::decode(fs::read(file_path).ok()?).ok()?;
+    }
+    let (header, _) = Header::parse(&start).ok()?;
+    Some(header)
+}
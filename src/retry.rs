@@ -0,0 +1,76 @@
+//! Retrying transient I/O failures, for files on network filesystems or object store mounts.
+//!
+//! Reading the input and writing the output are each retried as a whole: a failed read starts over from the
+//! beginning of the file, and a failed write recreates the output. Between attempts the policy waits with
+//! exponential backoff and full jitter: before retry `n` it sleeps a random time between zero and
+//! `backoff * 2^(n-1)`, so many workers failing at once don't all retry at the same moment.
+//! Only errors for which [`crate::EncryptError::is_retryable`] is true are retried; anything else, such as a
+//! missing file or a wrong password, fails at once.
+use crate::{cipher, CancellationToken, EncryptError};
+use std::thread;
+use std::time::Duration;
+
+// Backoff is capped so that a large retry count doesn't end up waiting for hours
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How often and how patiently to retry transient I/O failures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub retries: u32,      // Attempts after the first one; 0 turns retrying off
+    pub backoff: Duration, // The longest wait before the first retry, doubled for every further one
+}
+
+impl Default for RetryPolicy {
+    /// No retries
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Call `operation` until it succeeds, fails with an error that isn't retryable, or runs out of retries.
+    /// Stops waiting with [`EncryptError::Cancelled`] once `cancel` is triggered.
+    pub fn run<T>(
+        &self,
+        cancel: &CancellationToken,
+        mut operation: impl FnMut() -> Result<T, EncryptError>,
+    ) -> Result<T, EncryptError> {
+        let mut attempt = 0;
+        loop {
+            match operation() {
+                Err(err) if err.is_retryable() && attempt < self.retries => {
+                    let delay = self.delay(attempt);
+                    log::info!(
+                        "Retrying in {} ms ({} of {} retries) after: {}",
+                        delay.as_millis(),
+                        attempt + 1,
+                        self.retries,
+                        err
+                    );
+                    thread::sleep(delay);
+                    cancel.check()?;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // A random wait up to the backoff doubled `attempt` times
+    fn delay(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_DELAY);
+        let mut random = [0u8; 4];
+        // Without randomness the full delay is used, which is still a valid backoff
+        let fraction = match cipher::fill_random(&mut random) {
+            Ok(()) => u32::from_le_bytes(random) as f64 / u32::MAX as f64,
+            Err(_) => 1.0,
+        };
+        ceiling.mul_f64(fraction)
+    }
+}
@@ -0,0 +1,92 @@
+//! Where an [`Encryptor`](crate::Encryptor) draws the random bytes of its outputs from: salts, nonces, file keys,
+//! ephemeral X25519 keys and the random names of [`Encryptor::with_name_encryption`](crate::Encryptor::with_name_encryption).
+//! By default that is [`SystemRandom`], the crypto backend's secure generator.
+//!
+//! With the test-only `fixed-test-vectors` feature, `Encryptor::with_rng` takes any other [`RandomSource`], such as a
+//! [`SeededRandom`] whose bytes follow from a seed, and `Encryptor::with_fixed_time` records a given creation time
+//! instead of the clock's. The same inputs then give byte-identical outputs, to compare with golden files:
+//!
+//! ```ignore
+//! use encryptor::rng::SeededRandom;
+//! use encryptor::Encryptor;
+//! use std::sync::Arc;
+//!
+//! let encryptor = || {
+//!     Encryptor::new("fixture password")
+//!         .with_rng(Arc::new(SeededRandom::new([1; 32])))
+//!         .with_fixed_time(1_700_000_000)
+//! };
+//! assert_eq!(encryptor().encrypt_bytes(b"hello")?, encryptor().encrypt_bytes(b"hello")?);
+//! # Ok::<(), encryptor::EncryptError>(())
+//! ```
+//!
+//! Never enable it in a build that handles real data: two encryptors with the same seed seal with the same nonces.
+use crate::{cipher, EncryptError};
+#[cfg(feature = "fixed-test-vectors")]
+use crate::{header::Algorithm, KEY_LEN, NONCE_LEN};
+#[cfg(feature = "fixed-test-vectors")]
+use std::sync::Mutex;
+
+/// A source of random bytes for an [`Encryptor`](crate::Encryptor)
+pub trait RandomSource: Send + Sync {
+    /// Fill `dest` with random bytes
+    fn fill(&self, dest: &mut [u8]) -> Result<(), EncryptError>;
+}
+
+/// The crypto backend's secure random generator, which every encryptor uses by default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemRandom;
+
+impl RandomSource for SystemRandom {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), EncryptError> {
+        Ok(cipher::fill_random(dest)?)
+    }
+}
+
+/// Bytes that follow from a 32-byte seed: the ChaCha20 keystream of the seed, a fresh nonce for every call. Shared
+/// by the clones of an encryptor, so their outputs depend on the order they are sealed in. For tests only.
+#[cfg(feature = "fixed-test-vectors")]
+pub struct SeededRandom {
+    seed: [u8; KEY_LEN],
+    calls: Mutex<u64>,
+}
+
+#[cfg(feature = "fixed-test-vectors")]
+impl SeededRandom {
+    /// A source whose bytes follow from `seed`
+    pub fn new(seed: [u8; KEY_LEN]) -> Self {
+        SeededRandom {
+            seed,
+            calls: Mutex::new(0),
+        }
+    }
+}
+
+#[cfg(feature = "fixed-test-vectors")]
+impl RandomSource for SeededRandom {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), EncryptError> {
+        let mut calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[..8].copy_from_slice(&calls.to_le_bytes());
+        *calls += 1;
+        drop(calls);
+        // Sealing zeros gives the keystream, followed by a tag that is cut off
+        let mut stream = vec![0u8; dest.len()];
+        cipher::seal_in_place(
+            Algorithm::ChaCha20Poly1305,
+            &self.seed,
+            &nonce,
+            &[],
+            &mut stream,
+        )?;
+        dest.copy_from_slice(&stream[..dest.len()]);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "fixed-test-vectors")]
+impl std::fmt::Debug for SeededRandom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SeededRandom").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,78 @@
+// `encryptor rotate`: rekeying the files of an archive that are still encrypted under an old key. Every file records
+// the ID of the key or password it was encrypted under (`encrypt --key-id`), so rotate walks the given files and
+// directory trees and picks the native files whose key ID isn't the new one, or is the one given with --from.
+// Files without a key ID count as encrypted under an old one, unless --from is given. The files picked are then
+// rekeyed like `rekey` does, which only rewrites the header of files with a wrapped data key (`--wrap-key`).
+use crate::path_filter::{PathFilter, Walk};
+use crate::{redact, report};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The files under `paths` that `filter` takes, to rotate to `key_id`, in a stable order. Symbolic links inside
+// directories aren't followed, and files that can't be read or aren't encrypted in the native format are skipped.
+pub fn select(
+    paths: &[PathBuf],
+    key_id: &str,
+    from: Option<&str>,
+    filter: &PathFilter,
+) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in paths {
+        match path.exists() {
+            true => walk(path, "", &mut filter.walk(), &mut files),
+            false => log::warn!("{} doesn't exist", path.display()),
+        }
+    }
+    // The files found inside the trees are shown redacted too
+    files.iter().for_each(redact::path);
+    files.retain(|file_path| {
+        let Some(header) = report::native_header(file_path) else {
+            log::debug!("Skipping {}: not an encrypted file", file_path.display());
+            return false;
+        };
+        let recorded = header.key_id.as_deref();
+        let rotate = match from {
+            Some(from) => recorded == Some(from),
+            None => recorded != Some(key_id),
+        };
+        if !rotate {
+            log::debug!(
+                "Skipping {}: under key ID {}",
+                file_path.display(),
+                recorded.unwrap_or("(none)")
+            );
+        }
+        rotate
+    });
+    files
+}
+
+// Add `path`, at `relative` under the path it was found in, or the files under it that `tree` takes
+pub fn walk(path: &Path, relative: &str, tree: &mut Walk, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return;
+    }
+    let Ok(entries) = fs::read_dir(path) else {
+        log::warn!("Cannot read the directory {}", path.display());
+        return;
+    };
+    let mut entries = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|kind| !kind.is_symlink()))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    tree.enter(path, relative);
+    for entry in entries {
+        let name = entry.file_name().unwrap_or_default().to_string_lossy();
+        let entry_relative = match relative {
+            "" => name.into_owned(),
+            relative => format!("{}/{}", relative, name),
+        };
+        if tree.keeps(&entry_relative, entry.is_dir()) {
+            walk(&entry, &entry_relative, tree, files);
+        }
+    }
+    tree.leave(relative);
+}
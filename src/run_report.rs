@@ -0,0 +1,270 @@
+// `--report <path>`: a summary of a batch run to attach to change tickets, written when the command finishes,
+// whether it succeeded or not. A path ending in .json gets a JSON document, any other a self-contained HTML page
+// with the same contents:
+//
+//   {"report": 1, "version": "...", "command": "encrypt", "started_at": 1760000000, "duration_ms": 1520.3,
+//    "exit_code": 0, "totals": {"files": 2, "ok": 2, "failed": 0, "bytes": 1048576},
+//    "settings": {...}, "warnings": ["..."], "files": [{"operation": "encrypt", "status": "ok", ...}, ...]}
+//
+// The files are the objects printed by --json, in command line order, and the settings those recorded by
+// --debug-transcript, so the secret is only described by its kind. Warnings are those logged or printed during
+// the run, whatever the verbosity.
+use crate::{redact, units};
+use serde_json::{json, Value};
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const FORMAT_VERSION: u32 = 1;
+
+struct Report {
+    path: String,
+    command: String,
+    inputs: Vec<String>,
+    started_at: u64,
+    start: Instant,
+    settings: Value,
+    warnings: Vec<String>,
+    files: Vec<Value>,
+}
+
+// The report of this run, when --report was given
+static REPORT: Mutex<Option<Report>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<Report>> {
+    // A worker that panicked while recording leaves the report consistent
+    REPORT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Start collecting the report of `command` over `inputs` with `settings`, to be written to `path` by `finish`. The
+// inputs are reported as they are shown.
+pub fn start(path: &str, command: &str, inputs: &[PathBuf], settings: Value) {
+    *lock() = Some(Report {
+        path: path.to_string(),
+        command: command.to_string(),
+        inputs: inputs
+            .iter()
+            .map(|input| input.to_string_lossy().into_owned())
+            .collect(),
+        started_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs()),
+        start: Instant::now(),
+        settings,
+        warnings: Vec::new(),
+        files: Vec::new(),
+    });
+}
+
+// Whether a report is being collected
+pub fn active() -> bool {
+    lock().is_some()
+}
+
+pub fn warning(message: &str) {
+    if let Some(report) = lock().as_mut() {
+        report.warnings.push(message.to_string());
+    }
+}
+
+// Record the outcome of one file, as reported by --json
+pub fn file(outcome: &Value) {
+    if let Some(report) = lock().as_mut() {
+        report.files.push(outcome.clone());
+    }
+}
+
+// Write the report, if one was started. Failing to write it doesn't change the exit status.
+pub fn finish(exit_code: i32) {
+    let Some(mut report) = lock().take() else {
+        return;
+    };
+    // Workers finish in any order
+    let position = |outcome: &Value| {
+        report
+            .inputs
+            .iter()
+            .position(|input| outcome["input"] == input.as_str())
+    };
+    let mut files = std::mem::take(&mut report.files);
+    files.sort_by_key(|outcome| position(outcome));
+    let ok = files
+        .iter()
+        .filter(|outcome| outcome["status"] == "ok")
+        .count();
+    let mut document = json!({
+        "report": FORMAT_VERSION,
+        "version": env!("CARGO_PKG_VERSION"),
+        "command": report.command,
+        "started_at": report.started_at,
+        "duration_ms": (report.start.elapsed().as_secs_f64() * 1e6).round() / 1000.0,
+        "exit_code": exit_code,
+        "totals": {
+            "files": files.len(),
+            "ok": ok,
+            "failed": files.len() - ok,
+            "bytes": files.iter().filter_map(|outcome| outcome["bytes"].as_u64()).sum::<u64>(),
+        },
+        "settings": report.settings,
+        "warnings": report.warnings,
+        "files": files,
+    });
+    redact::value(&mut document);
+    let text = match report.path.to_lowercase().ends_with(".json") {
+        true => serde_json::to_string_pretty(&document).unwrap_or_default() + "\n",
+        false => html(&document),
+    };
+    if let Err(err) = fs::write(&report.path, text) {
+        eprintln!("Cannot write the report {}: {}", report.path, err);
+    }
+}
+
+// The report as one HTML page with its styles inline, so it can be attached and opened anywhere
+fn html(document: &Value) -> String {
+    let mut page = String::new();
+    let command = text(&document["command"]);
+    let _ = write!(
+        page,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>encryptor {} report</title>\n<style>\n\
+         body {{ font-family: sans-serif; margin: 2em; color: #222; }}\n\
+         table {{ border-collapse: collapse; margin-bottom: 2em; }}\n\
+         th, td {{ border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }}\n\
+         th {{ background: #f3f3f3; }}\n\
+         td.number {{ text-align: right; }}\n\
+         .ok {{ color: #1a7f37; }}\n.error {{ color: #cf222e; }}\n\
+         </style>\n</head>\n<body>\n<h1>encryptor {}</h1>\n",
+        escape(&command),
+        escape(&command)
+    );
+
+    let totals = &document["totals"];
+    let summary = [
+        ("Version", text(&document["version"])),
+        (
+            "Started",
+            document["started_at"]
+                .as_u64()
+                .map(units::time)
+                .unwrap_or_default(),
+        ),
+        ("Duration", milliseconds(&document["duration_ms"])),
+        ("Exit code", text(&document["exit_code"])),
+        ("Files", text(&totals["files"])),
+        ("Succeeded", text(&totals["ok"])),
+        ("Failed", text(&totals["failed"])),
+        ("Input", bytes(&totals["bytes"])),
+    ];
+    page.push_str("<h2>Summary</h2>\n<table>\n");
+    for (name, value) in summary {
+        let _ = writeln!(
+            page,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            name,
+            escape(&value)
+        );
+    }
+    page.push_str("</table>\n");
+
+    page.push_str("<h2>Warnings</h2>\n");
+    match document["warnings"].as_array() {
+        Some(warnings) if !warnings.is_empty() => {
+            page.push_str("<ul>\n");
+            for warning in warnings {
+                let _ = writeln!(page, "<li>{}</li>", escape(&text(warning)));
+            }
+            page.push_str("</ul>\n");
+        }
+        _ => page.push_str("<p>None</p>\n"),
+    }
+
+    page.push_str(
+        "<h2>Files</h2>\n<table>\n<tr><th>Status</th><th>Input</th><th>Output</th>\
+                   <th>Size</th><th>Cipher</th><th>Duration</th><th>Error</th></tr>\n",
+    );
+    for outcome in document["files"].as_array().into_iter().flatten() {
+        let status = text(&outcome["status"]);
+        let error = match &outcome["error"] {
+            Value::Null => String::new(),
+            error => format!("{}: {}", text(&error["code"]), text(&error["message"])),
+        };
+        let _ = writeln!(
+            page,
+            "<tr><td class=\"{}\">{}</td><td>{}</td><td>{}</td><td class=\"number\">{}</td><td>{}</td>\
+             <td class=\"number\">{}</td><td>{}</td></tr>",
+            escape(&status),
+            escape(&status),
+            escape(&text(&outcome["input"])),
+            escape(&text(&outcome["output"])),
+            escape(&bytes(&outcome["bytes"])),
+            escape(&text(&outcome["algorithm"])),
+            escape(&milliseconds(&outcome["duration_ms"])),
+            escape(&error)
+        );
+    }
+    page.push_str("</table>\n");
+
+    page.push_str("<h2>Settings</h2>\n<table>\n");
+    settings_rows(&mut page, "", &document["settings"]);
+    page.push_str("</table>\n</body>\n</html>\n");
+    page
+}
+
+// One row per setting, with nested objects such as "kdf" flattened into "kdf.memory"
+fn settings_rows(page: &mut String, prefix: &str, settings: &Value) {
+    for (name, value) in settings.as_object().into_iter().flatten() {
+        let name = format!("{}{}", prefix, name);
+        match value {
+            Value::Object(_) => settings_rows(page, &format!("{}.", name), value),
+            _ => {
+                let _ = writeln!(
+                    page,
+                    "<tr><th>{}</th><td>{}</td></tr>",
+                    escape(&name),
+                    escape(&text(value))
+                );
+            }
+        }
+    }
+}
+
+// A JSON value as shown in the page: strings without quotes, and nothing for null
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+// A byte count of the document shown with --human or --raw, or nothing when it isn't known
+fn bytes(value: &Value) -> String {
+    value.as_u64().map(units::size).unwrap_or_default()
+}
+
+// A number of milliseconds of the document shown with --human or --raw
+fn milliseconds(value: &Value) -> String {
+    value
+        .as_f64()
+        .map(|ms| units::duration(Duration::from_secs_f64(ms.max(0.0) / 1000.0)))
+        .unwrap_or_default()
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,592 @@
+// s3://bucket/key paths: files encrypted straight into Amazon S3 or an S3-compatible store (MinIO, Ceph, R2...)
+// with `encrypt --out s3://...`, and decrypted or verified straight out of it with `decrypt s3://...` and
+// `verify s3://...`. Nothing is staged on local disk: the ciphertext is uploaded in parts as it is encrypted (a
+// multipart upload, aborted if anything fails, so no half-written object is ever left), and a download is
+// decrypted chunk by chunk as it comes in, through the library's streaming adapters. Uploads are therefore
+// written like EncryptingWriter writes them: in chunks, uncompressed, with no name or length in the header.
+//
+// Requests are signed with AWS Signature Version 4. The credentials, region and endpoint are found like the AWS
+// tools find them: AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and AWS_SESSION_TOKEN, or else the AWS_PROFILE
+// (default `default`) of ~/.aws/credentials; AWS_REGION, AWS_DEFAULT_REGION or the profile's region in
+// ~/.aws/config (default us-east-1); and AWS_ENDPOINT_URL_S3 or AWS_ENDPOINT_URL for another store, whose buckets
+// are addressed by path (https://host/bucket/key) rather than by host name. Uploads go to the storage class
+// `encrypt --storage-class` (or `storage-class` in this tool's config file) names, or else the bucket's default.
+//
+// Needs the `s3` cargo feature; without it s3:// paths fail with an explanation.
+#[cfg(feature = "s3")]
+use crate::interrupt;
+use crate::streamed::Existing;
+#[cfg(feature = "s3")]
+use crate::streamed::{self, from_io};
+#[cfg(feature = "s3")]
+use encryptor::EncryptingWriter;
+use encryptor::{EncryptError, Encryptor};
+#[cfg(feature = "s3")]
+use hmac::{Hmac, Mac};
+#[cfg(feature = "s3")]
+use sha2::{Digest, Sha256};
+use std::io;
+#[cfg(feature = "s3")]
+use std::io::Write;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "s3")]
+use zeroize::Zeroizing;
+
+const SCHEME: &str = "s3://";
+
+// The size of the parts of an upload, doubled every 2,000 parts: S3 takes at most 10,000 parts of at least 5 MiB
+// (but the last), so this reaches 496 GiB while keeping at most 128 MiB in memory
+#[cfg(feature = "s3")]
+const PART_SIZE: usize = 8 << 20;
+#[cfg(feature = "s3")]
+const PARTS_PER_SIZE: usize = 2000;
+
+// Whether `path` names an S3 object or prefix rather than a local file
+pub fn is_uri(path: &Path) -> bool {
+    path.to_str().is_some_and(|path| path.starts_with(SCHEME))
+}
+
+// Encrypt the file at `file_path` into the object `uri` names, or into `<uri>/<file name>.enc` when it ends in /
+// or `several` files go to it, in `storage_class` if one is given. Returns the URI of the object.
+#[cfg(feature = "s3")]
+pub fn encrypt(
+    encryptor: &Encryptor,
+    file_path: &Path,
+    uri: &Path,
+    several: bool,
+    storage_class: Option<&str>,
+) -> Result<PathBuf, EncryptError> {
+    let uri = uri.to_string_lossy();
+    let uri = match (uri.ends_with('/'), several) {
+        (false, false) => uri.into_owned(),
+        (prefix, _) => {
+            // Object keys are text, so a name that isn't valid UTF-8 loses the bytes that aren't
+            let name = encryptor::names::file_name(file_path).to_string_lossy();
+            let separator = if prefix { "" } else { "/" };
+            format!("{}{}{}.enc", uri, separator, name)
+        }
+    };
+    let object = Object::parse(&uri)?;
+    let client = Client::from_env()?;
+    let input = std::fs::File::open(file_path)?;
+    let upload = Upload::new(&client, &object, storage_class);
+    let mut writer = EncryptingWriter::new(upload, encryptor)?;
+    io::copy(&mut interrupt::Reader(input), &mut writer).map_err(from_io)?;
+    writer.finish()?.complete()?;
+    Ok(PathBuf::from(uri))
+}
+
+// Decrypt the object `uri` names to `out_path`, or into it when it is a directory, ends in a separator or
+// `several` objects go to it; without it, to the object's name without its extension in the current directory.
+// Returns the path written.
+#[cfg(feature = "s3")]
+pub fn decrypt(
+    encryptor: &Encryptor,
+    uri: &Path,
+    out_path: Option<&Path>,
+    several: bool,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    let object = Object::parse(&uri.to_string_lossy())?;
+    let name = object.key.rsplit('/').next().unwrap_or(&object.key);
+    let output_path = streamed::output_path(name.as_ref(), out_path, several, existing)?;
+    let client = Client::from_env()?;
+    let body = client
+        .send("GET", &object, "", &[], &[])?
+        .into_body()
+        .into_reader();
+    streamed::decrypt(body, encryptor, &output_path)
+}
+
+// Check that the object `uri` names decrypts, without writing the plaintext anywhere, returning the URI
+#[cfg(feature = "s3")]
+pub fn verify(encryptor: &Encryptor, uri: &Path) -> Result<PathBuf, EncryptError> {
+    let object = Object::parse(&uri.to_string_lossy())?;
+    let client = Client::from_env()?;
+    let body = client
+        .send("GET", &object, "", &[], &[])?
+        .into_body()
+        .into_reader();
+    streamed::verify(body, encryptor)?;
+    Ok(uri.to_path_buf())
+}
+
+// The object an s3:// URI names
+#[cfg(feature = "s3")]
+struct Object {
+    bucket: String,
+    key: String,
+}
+
+#[cfg(feature = "s3")]
+impl Object {
+    fn parse(uri: &str) -> Result<Object, EncryptError> {
+        match uri
+            .strip_prefix(SCHEME)
+            .and_then(|rest| rest.split_once('/'))
+        {
+            Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => Ok(Object {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            }),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} doesn't name an object: expected s3://<bucket>/<key>",
+                    uri
+                ),
+            )
+            .into()),
+        }
+    }
+}
+
+// The access key of the requests, and the secret they are signed with
+#[cfg(feature = "s3")]
+struct Credentials {
+    access_key: String,
+    secret_key: Zeroizing<String>,
+    session_token: Option<String>,
+}
+
+// Signs requests and sends them to the endpoint
+#[cfg(feature = "s3")]
+struct Client {
+    agent: ureq::Agent,
+    credentials: Credentials,
+    region: String,
+    endpoint: Option<String>, // Another store than AWS, addressed by path
+}
+
+#[cfg(feature = "s3")]
+impl Client {
+    fn from_env() -> Result<Client, EncryptError> {
+        let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let home = env("HOME")
+            .or_else(|| env("USERPROFILE"))
+            .unwrap_or_default();
+        let profile = env("AWS_PROFILE").unwrap_or_else(|| "default".to_string());
+        let credentials_file = env("AWS_SHARED_CREDENTIALS_FILE")
+            .unwrap_or_else(|| format!("{}/.aws/credentials", home));
+        let config_file = env("AWS_CONFIG_FILE").unwrap_or_else(|| format!("{}/.aws/config", home));
+        // The config file names its sections `profile <name>`, but for the default one
+        let config_section = match profile.as_str() {
+            "default" => profile.clone(),
+            _ => format!("profile {}", profile),
+        };
+        let from_file = |key: &str| ini_value(&credentials_file, &profile, key);
+        let credentials = match (env("AWS_ACCESS_KEY_ID"), env("AWS_SECRET_ACCESS_KEY")) {
+            (Some(access_key), Some(secret_key)) => Credentials {
+                access_key,
+                secret_key: Zeroizing::new(secret_key),
+                session_token: env("AWS_SESSION_TOKEN"),
+            },
+            _ => match (
+                from_file("aws_access_key_id"),
+                from_file("aws_secret_access_key"),
+            ) {
+                (Some(access_key), Some(secret_key)) => Credentials {
+                    access_key,
+                    secret_key: Zeroizing::new(secret_key),
+                    session_token: from_file("aws_session_token"),
+                },
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!(
+                            "No AWS credentials: set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, or add profile {} to {}",
+                            profile, credentials_file
+                        ),
+                    )
+                    .into())
+                }
+            },
+        };
+        let region = env("AWS_REGION")
+            .or_else(|| env("AWS_DEFAULT_REGION"))
+            .or_else(|| ini_value(&config_file, &config_section, "region"))
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let endpoint = env("AWS_ENDPOINT_URL_S3")
+            .or_else(|| env("AWS_ENDPOINT_URL"))
+            .map(|endpoint| endpoint.trim_end_matches('/').to_string());
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Ok(Client {
+            agent,
+            credentials,
+            region,
+            endpoint,
+        })
+    }
+
+    // Send a signed request about `object`, with `query` (already canonical, e.g. "partNumber=1&uploadId=..."),
+    // `body` and the x-amz-* headers in `extra`, failing unless S3 answers with success
+    fn send(
+        &self,
+        method: &str,
+        object: &Object,
+        query: &str,
+        body: &[u8],
+        extra: &[(&'static str, String)],
+    ) -> Result<ureq::http::Response<ureq::Body>, EncryptError> {
+        let key = uri_encode(&object.key, false);
+        // Bucket names with dots don't match the wildcard certificate of virtual hosts
+        let (base, path) = match &self.endpoint {
+            Some(endpoint) => (endpoint.clone(), format!("/{}/{}", object.bucket, key)),
+            None if object.bucket.contains('.') => (
+                format!("https://s3.{}.amazonaws.com", self.region),
+                format!("/{}/{}", object.bucket, key),
+            ),
+            None => (
+                format!("https://{}.s3.{}.amazonaws.com", object.bucket, self.region),
+                format!("/{}", key),
+            ),
+        };
+        let host = base
+            .split_once("://")
+            .map_or(base.as_str(), |(_, host)| host);
+        let url = match query {
+            "" => format!("{}{}", base, path),
+            query => format!("{}{}?{}", base, path, query),
+        };
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let digits: String = crate::units::utc(now)
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect();
+        let date = format!("{}T{}Z", &digits[..8], &digits[8..14]);
+        let payload_hash = hex::encode(Sha256::digest(body));
+        let mut headers = vec![
+            ("host", host.to_string()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", date.clone()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        // Signed headers are listed in order
+        headers.extend_from_slice(extra);
+        headers.sort_by_key(|(name, _)| *name);
+        let signed_headers: Vec<&str> = headers.iter().map(|(name, _)| *name).collect();
+        let signed_headers = signed_headers.join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, path, query, canonical_headers, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", &date[..8], self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let secret = Zeroizing::new(format!("AWS4{}", self.credentials.secret_key.as_str()));
+        let mut signing_key = hmac(secret.as_bytes(), &date.as_bytes()[..8]);
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.credentials.access_key, scope, signed_headers, signature
+        );
+
+        let mut request = ureq::http::Request::builder()
+            .method(method)
+            .uri(&url)
+            .header("authorization", authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.header(*name, value);
+        }
+        let sent = match method {
+            "GET" | "DELETE" => request.body(()).map(|request| self.agent.run(request)),
+            _ => request.body(body).map(|request| self.agent.run(request)),
+        };
+        let mut response = sent
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?
+            .map_err(|err| io::Error::other(format!("{} {}: {}", method, url, err)))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        // S3 explains failures in an XML body: <Error><Code>NoSuchKey</Code><Message>...</Message></Error>
+        let text = response.body_mut().read_to_string().unwrap_or_default();
+        let explanation = [xml_value(&text, "Code"), xml_value(&text, "Message")]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(": ");
+        let kind = match status.as_u16() {
+            401 | 403 => io::ErrorKind::PermissionDenied,
+            404 => io::ErrorKind::NotFound,
+            _ => io::ErrorKind::Other,
+        };
+        Err(io::Error::new(
+            kind,
+            format!(
+                "s3://{}/{}: S3 answered {} {}",
+                object.bucket, object.key, status, explanation
+            ),
+        )
+        .into())
+    }
+}
+
+// A Write that uploads what is written to it into an object: in one request if it all fits in one part, or else
+// as the parts of a multipart upload, which is aborted if it is dropped before `complete`
+#[cfg(feature = "s3")]
+struct Upload<'a> {
+    client: &'a Client,
+    object: &'a Object,
+    headers: Vec<(&'static str, String)>, // Sent with the request that creates the object
+    upload_id: Option<String>,
+    part: Vec<u8>,
+    etags: Vec<String>,
+    completed: bool,
+}
+
+#[cfg(feature = "s3")]
+impl<'a> Upload<'a> {
+    fn new(client: &'a Client, object: &'a Object, storage_class: Option<&str>) -> Self {
+        let headers = storage_class
+            .map(|class| ("x-amz-storage-class", class.to_string()))
+            .into_iter()
+            .collect();
+        Upload {
+            client,
+            object,
+            headers,
+            upload_id: None,
+            part: Vec::new(),
+            etags: Vec::new(),
+            completed: false,
+        }
+    }
+
+    fn part_size(&self) -> usize {
+        PART_SIZE << (self.etags.len() / PARTS_PER_SIZE)
+    }
+
+    // Upload the part gathered so far, starting the multipart upload with the first one
+    fn upload_part(&mut self) -> Result<(), EncryptError> {
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.clone(),
+            None => {
+                let mut response =
+                    self.client
+                        .send("POST", self.object, "uploads=", &[], &self.headers)?;
+                let text = response
+                    .body_mut()
+                    .read_to_string()
+                    .map_err(io::Error::other)?;
+                let upload_id = xml_value(&text, "UploadId").ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "S3 started no multipart upload")
+                })?;
+                self.upload_id = Some(upload_id.clone());
+                upload_id
+            }
+        };
+        let query = format!(
+            "partNumber={}&uploadId={}",
+            self.etags.len() + 1,
+            uri_encode(&upload_id, true)
+        );
+        let response = self
+            .client
+            .send("PUT", self.object, &query, &self.part, &[])?;
+        let etag = response
+            .headers()
+            .get("etag")
+            .and_then(|etag| etag.to_str().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "S3 returned no ETag"))?;
+        self.etags.push(etag.to_string());
+        self.part.clear();
+        Ok(())
+    }
+
+    // Upload what is left and put the object together
+    fn complete(mut self) -> Result<(), EncryptError> {
+        let Some(upload_id) = self.upload_id.clone() else {
+            self.client
+                .send("PUT", self.object, "", &self.part, &self.headers)?;
+            self.completed = true;
+            return Ok(());
+        };
+        if !self.part.is_empty() {
+            self.upload_part()?;
+        }
+        let parts: String = self
+            .etags
+            .iter()
+            .enumerate()
+            .map(|(index, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    index + 1,
+                    etag
+                )
+            })
+            .collect();
+        let body = format!(
+            "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+            parts
+        );
+        let query = format!("uploadId={}", uri_encode(&upload_id, true));
+        let mut response = self
+            .client
+            .send("POST", self.object, &query, body.as_bytes(), &[])?;
+        // A failure to put the parts together can still come with 200 OK
+        let text = response.body_mut().read_to_string().unwrap_or_default();
+        if text.contains("<Error>") {
+            return Err(io::Error::other(format!(
+                "s3://{}/{}: completing the upload failed: {}",
+                self.object.bucket,
+                self.object.key,
+                xml_value(&text, "Message").unwrap_or_default()
+            ))
+            .into());
+        }
+        self.completed = true;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "s3")]
+impl Write for Upload<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // A full part is only sent once more follows it, so that the last one is never empty
+        if self.part.len() == self.part_size() {
+            self.upload_part().map_err(into_io)?;
+        }
+        let len = buf.len().min(self.part_size() - self.part.len());
+        self.part.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "s3")]
+impl Drop for Upload<'_> {
+    fn drop(&mut self) {
+        if let (Some(upload_id), false) = (&self.upload_id, self.completed) {
+            let query = format!("uploadId={}", uri_encode(upload_id, true));
+            if let Err(err) = self.client.send("DELETE", self.object, &query, &[], &[]) {
+                log::warn!(
+                    "Cannot abort the upload of s3://{}/{}: {}",
+                    self.object.bucket,
+                    self.object.key,
+                    err
+                );
+            }
+        }
+    }
+}
+
+// Percent-encode everything but the unreserved characters, and slashes too if `slash`
+#[cfg(feature = "s3")]
+fn uri_encode(value: &str, slash: bool) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            b'/' if !slash => "/".to_string(),
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(feature = "s3")]
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// The text of the first <tag> element of an S3 response
+#[cfg(feature = "s3")]
+fn xml_value(text: &str, tag: &str) -> Option<String> {
+    let start = text.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + text[start..].find(&format!("</{}>", tag))?;
+    Some(text[start..end].to_string())
+}
+
+// The value of `key` in `[section]` of the INI file at `path`, as in ~/.aws/credentials
+#[cfg(feature = "s3")]
+fn ini_value(path: &str, section: &str, key: &str) -> Option<String> {
+    let text = std::fs::read_to_string(path).ok()?;
+    let mut in_section = false;
+    for line in text.lines().map(str::trim) {
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            in_section = name.trim() == section;
+        } else if let (true, Some((name, value))) = (in_section, line.split_once('=')) {
+            if name.trim() == key {
+                return Some(value.trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+#[cfg(feature = "s3")]
+fn into_io(error: EncryptError) -> io::Error {
+    match error {
+        EncryptError::IoError(err) => err,
+        err => io::Error::other(err),
+    }
+}
+
+#[cfg(not(feature = "s3"))]
+pub fn encrypt(
+    _encryptor: &Encryptor,
+    _file_path: &Path,
+    _uri: &Path,
+    _several: bool,
+    _storage_class: Option<&str>,
+) -> Result<PathBuf, EncryptError> {
+    Err(EncryptError::IoError(unavailable()))
+}
+
+#[cfg(not(feature = "s3"))]
+pub fn decrypt(
+    _encryptor: &Encryptor,
+    _uri: &Path,
+    _out_path: Option<&Path>,
+    _several: bool,
+    _existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    Err(EncryptError::IoError(unavailable()))
+}
+
+#[cfg(not(feature = "s3"))]
+pub fn verify(_encryptor: &Encryptor, _uri: &Path) -> Result<PathBuf, EncryptError> {
+    Err(EncryptError::IoError(unavailable()))
+}
+
+#[cfg(not(feature = "s3"))]
+fn unavailable() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "s3:// paths are not supported by this build (enable the 's3' feature)",
+    )
+}
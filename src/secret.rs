@@ -0,0 +1,78 @@
+//! Plaintext held in memory as a secret, for [`Encryptor::decrypt_to_secret`](crate::Encryptor::decrypt_to_secret):
+//! configuration files and credentials an application decrypts at startup without ever writing them to disk.
+//!
+//! A [`SecretVec`] wipes its bytes when it is dropped, doesn't print them with `{:?}`, and only hands them out
+//! through [`SecretVec::expose_secret`], so the places that read them are easy to find. Once `harden::enable` was
+//! called (with the `harden` feature), its bytes are also locked into RAM, so they are never swapped out.
+//!
+//! ```no_run
+//! use encryptor::Encryptor;
+//!
+//! let config = Encryptor::new("correct horse battery staple").decrypt_to_secret("config.toml.enc")?;
+//! let text = std::str::from_utf8(config.expose_secret()).expect("the configuration is UTF-8");
+//! # Ok::<(), encryptor::EncryptError>(())
+//! ```
+use zeroize::Zeroizing;
+
+/// A buffer of secret bytes, wiped when it is dropped and kept out of `Debug` output
+pub struct SecretVec {
+    inner: Zeroizing<Vec<u8>>,
+}
+
+impl SecretVec {
+    // An empty secret with room for `capacity` bytes, locked into RAM before anything is written to it
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        let mut inner = Zeroizing::new(vec![0; capacity]);
+        crate::protect(&inner);
+        inner.clear();
+        SecretVec { inner }
+    }
+
+    // Append `bytes`. A secret that runs out of room moves to a bigger locked buffer by hand, since letting the
+    // vector reallocate would leave a copy behind that is neither wiped nor locked.
+    pub(crate) fn extend_from_slice(&mut self, bytes: &[u8]) {
+        if self.inner.capacity() - self.inner.len() < bytes.len() {
+            let capacity = (self.inner.len() + bytes.len()).max(self.inner.capacity() * 2);
+            let mut grown = SecretVec::with_capacity(capacity);
+            grown.inner.extend_from_slice(&self.inner);
+            *self = grown;
+        }
+        self.inner.extend_from_slice(bytes);
+    }
+
+    /// The secret itself. Anything copied out of it isn't wiped for you.
+    pub fn expose_secret(&self) -> &[u8] {
+        &self.inner
+    }
+
+    /// The secret itself, to change it in place
+    pub fn expose_secret_mut(&mut self) -> &mut [u8] {
+        &mut self.inner
+    }
+
+    /// How many bytes the secret holds
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Whether the secret is empty
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+/// Takes over `bytes` without copying them, locking them into RAM if hardening is enabled
+impl From<Vec<u8>> for SecretVec {
+    fn from(bytes: Vec<u8>) -> Self {
+        crate::protect(&bytes);
+        SecretVec {
+            inner: Zeroizing::new(bytes),
+        }
+    }
+}
+
+impl std::fmt::Debug for SecretVec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SecretVec([REDACTED; {}])", self.inner.len())
+    }
+}
@@ -0,0 +1,217 @@
+//! Known-answer tests of the build, for packagers and users who want to validate a binary before trusting it with
+//! data.
+//!
+//! [`run`] seals fixed inputs with every [`Algorithm`] and derives a fixed key with Argon2id, comparing the results
+//! with vectors computed by an independent implementation (and, for X25519, those of RFC 7748). A miscompiled or
+//! mislinked crypto backend, or one that isn't the one expected, fails here instead of writing files that nothing
+//! else can read. It then round-trips buffers through the whole file format, whole and chunked, with a password and
+//! a raw key, and checks that a flipped bit is caught.
+//!
+//! ```
+//! let checks = encryptor::selftest::run();
+//! assert!(checks.iter().all(|check| check.result.is_ok()));
+//! ```
+use crate::{cipher, derive_key, Algorithm, Encryptor, KdfParams, KEY_LEN};
+
+// The inputs of every AEAD vector
+const KEY: [u8; KEY_LEN] = {
+    let mut key = [0u8; KEY_LEN];
+    let mut i = 0;
+    while i < KEY_LEN {
+        key[i] = i as u8;
+        i += 1;
+    }
+    key
+};
+const NONCE: [u8; 12] = [
+    0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xab,
+];
+const AAD: &[u8] = b"encryptor selftest";
+const PLAINTEXT: &[u8] = b"The quick brown fox jumps over the lazy dog";
+
+// The ciphertext and tag of PLAINTEXT for each algorithm
+const AEAD_VECTORS: &[(Algorithm, &str)] = &[
+    (
+        Algorithm::Aes256Gcm,
+        "b270190d34be6bdc0945e5a1680daefe16c32130f8c22f1cef2e49f01ad95575ba136793ce582a1d3bf363924233134da1f7520f11889b01d62127",
+    ),
+    (
+        Algorithm::ChaCha20Poly1305,
+        "58c31d7f3c93abcecb2f9166938d93dbfb31ab9f291f0dd3c7f8b4c71410e37804e536e6cfb386aaa2a3928cd61c590adebe8d5c4f1761cffa20bf",
+    ),
+    (
+        Algorithm::Aes256GcmSiv,
+        "ab0208fa181a28f8e756971b0b2c490b1df82046cc5e762999ffbab401af2b4fa2df03024984130b1015892dd07404fe2185703439fc1584900072",
+    ),
+    // Keyed with the first 16 bytes of KEY
+    (
+        Algorithm::Aes128Gcm,
+        "feee5d9b0ffc5a69e158d7722965de4035815b28e96250df56301eed27ffafdcf2c6655d6e90954d1bb185cdc64fe20373611cc1d7d27ee0bfe8dc",
+    ),
+];
+
+// Argon2id of "correct horse battery staple" with 16 bytes of 0x5a as the salt, AAD as the associated data,
+// 64 KiB, 2 iterations and 1 lane
+const ARGON2ID_KEY: &str = "5b88bbc52b59bdc9f85392dddde3825653920c8f7170d02902f2552733c1b3b7";
+
+/// One check of the self-test
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// What was checked, e.g. "aes256gcm known answer"
+    pub name: String,
+    /// Why it failed, if it did
+    pub result: Result<(), String>,
+}
+
+/// The name of the crypto backend AES-GCM, ChaCha20-Poly1305 and random numbers come from in this build:
+/// `ring`, `rustcrypto` or `openssl`
+pub fn backend() -> &'static str {
+    cipher::backend_name()
+}
+
+/// Run every check, returning them all whether they pass or not
+pub fn run() -> Vec<Check> {
+    let mut checks = Vec::new();
+    let mut check = |name: String, result: Result<(), String>| checks.push(Check { name, result });
+    for (algorithm, expected) in AEAD_VECTORS {
+        check(
+            format!("{} known answer", algorithm.name()),
+            aead_known_answer(*algorithm, expected),
+        );
+    }
+    check("Argon2id known answer".to_string(), argon2id_known_answer());
+    #[cfg(feature = "x25519")]
+    check("X25519 known answer".to_string(), x25519_known_answer());
+    check("Random numbers".to_string(), random_numbers());
+    for algorithm in AEAD_VECTORS.iter().map(|(algorithm, _)| *algorithm) {
+        check(
+            format!("{} file round trip", algorithm.name()),
+            file_round_trip(algorithm),
+        );
+    }
+    checks
+}
+
+fn aead_known_answer(algorithm: Algorithm, expected: &str) -> Result<(), String> {
+    let mut sealed = PLAINTEXT.to_vec();
+    cipher::seal_in_place(algorithm, &KEY, &NONCE, AAD, &mut sealed)
+        .map_err(|err| format!("sealing failed: {}", err))?;
+    if hex(&sealed) != expected {
+        return Err(format!("sealed to {}, expected {}", hex(&sealed), expected));
+    }
+    let mut opened = sealed.clone();
+    cipher::open_in_place(algorithm, &KEY, &NONCE, AAD, &mut opened)
+        .map_err(|err| format!("opening failed: {}", err))?;
+    if opened != PLAINTEXT {
+        return Err("opened to the wrong plaintext".to_string());
+    }
+    sealed[0] ^= 1;
+    match cipher::open_in_place(algorithm, &KEY, &NONCE, AAD, &mut sealed) {
+        Ok(()) => Err("a damaged ciphertext was opened".to_string()),
+        Err(_) => Ok(()),
+    }
+}
+
+fn argon2id_known_answer() -> Result<(), String> {
+    let params = KdfParams {
+        memory: 64,
+        iterations: 2,
+    };
+    let key = derive_key("correct horse battery staple", &[0x5a; 16], params, AAD)
+        .map_err(|err| format!("deriving failed: {}", err))?;
+    match hex(&key) == ARGON2ID_KEY {
+        true => Ok(()),
+        false => Err(format!("derived {}, expected {}", hex(&key), ARGON2ID_KEY)),
+    }
+}
+
+// RFC 7748, section 6.1
+#[cfg(feature = "x25519")]
+fn x25519_known_answer() -> Result<(), String> {
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    let secret = |hex: &str| -> [u8; 32] {
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("valid hex"))
+            .collect();
+        bytes.try_into().expect("32 bytes")
+    };
+    let alice = StaticSecret::from(secret(
+        "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a",
+    ));
+    let bob = StaticSecret::from(secret(
+        "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb",
+    ));
+    let expected = [
+        (
+            hex(PublicKey::from(&alice).as_bytes()),
+            "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a",
+        ),
+        (
+            hex(PublicKey::from(&bob).as_bytes()),
+            "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f",
+        ),
+        (
+            hex(alice.diffie_hellman(&PublicKey::from(&bob)).as_bytes()),
+            "4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742",
+        ),
+    ];
+    match expected.iter().find(|(found, expected)| found != expected) {
+        Some((found, expected)) => Err(format!("computed {}, expected {}", found, expected)),
+        None => Ok(()),
+    }
+}
+
+fn random_numbers() -> Result<(), String> {
+    let mut first = [0u8; 32];
+    let mut second = [0u8; 32];
+    cipher::fill_random(&mut first).map_err(|err| err.to_string())?;
+    cipher::fill_random(&mut second).map_err(|err| err.to_string())?;
+    match first != second && first != [0; 32] {
+        true => Ok(()),
+        false => Err("the random number generator repeated itself".to_string()),
+    }
+}
+
+// Whole and chunked buffers, with a password and with a raw key, decrypt to what was encrypted, and a flipped bit
+// in any of them fails
+fn file_round_trip(algorithm: Algorithm) -> Result<(), String> {
+    let params = KdfParams {
+        memory: 64,
+        iterations: 1,
+    };
+    let plaintext = PLAINTEXT.repeat(50);
+    let encryptors = [
+        Encryptor::new("correct horse battery staple").with_kdf_params(params),
+        Encryptor::from_key(KEY),
+    ];
+    for encryptor in encryptors {
+        let encryptor = encryptor.with_algorithm(algorithm);
+        let chunked = encryptor
+            .clone()
+            .with_stream_threshold(0)
+            .with_chunk_size(256);
+        for encryptor in [encryptor, chunked] {
+            let mut sealed = encryptor
+                .encrypt_bytes(&plaintext)
+                .map_err(|err| format!("encrypting failed: {}", err))?;
+            let opened = encryptor
+                .decrypt_bytes(&sealed)
+                .map_err(|err| format!("decrypting failed: {}", err))?;
+            if opened != plaintext {
+                return Err("decrypted to the wrong plaintext".to_string());
+            }
+            let middle = sealed.len() / 2;
+            sealed[middle] ^= 1;
+            if encryptor.decrypt_bytes(&sealed).is_ok() {
+                return Err("a damaged file was decrypted".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
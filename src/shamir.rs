@@ -0,0 +1,194 @@
+//! Shamir secret sharing of a 256-bit key, so that no single custodian can decrypt alone.
+//!
+//! [`split`] turns a key into `count` shares, any `threshold` of which rebuild it with [`combine`]; fewer than that
+//! reveal nothing about it. Each byte of the key is the constant term of its own random polynomial of degree
+//! `threshold - 1` over GF(2^8), and share `i` holds the values of all of them at `x = i`. Every share of a split
+//! also carries a random ID of the split and the threshold, so shares of different splits aren't mixed up.
+//!
+//! `encrypt --shares 5 --threshold 3` encrypts with a random raw key split this way, and `decrypt --share` rebuilds it:
+//!
+//! ```
+//! use encryptor::{shamir, Encryptor};
+//!
+//! let key = encryptor::generate_key()?;
+//! let sealed = Encryptor::from_key(key).encrypt_bytes(b"hello")?;
+//! let shares = shamir::split(&key, 3, 5)?;
+//!
+//! let key = shamir::combine(&[shares[4].clone(), shares[0].clone(), shares[2].clone()])?;
+//! assert_eq!(Encryptor::from_key(*key).decrypt_bytes(&sealed)?, b"hello");
+//! assert!(shamir::combine(&shares[..2]).is_err());
+//! # Ok::<(), encryptor::EncryptError>(())
+//! ```
+use crate::{cipher, EncryptError, KeyBytes, KEY_LEN};
+use zeroize::Zeroizing;
+
+// The serialized form starts with these bytes, and a version for later forms
+const MAGIC: &[u8; 4] = b"ESHR";
+const VERSION: u8 = 1;
+const SPLIT_ID_LEN: usize = 8;
+/// Length in bytes of a serialized [`Share`]
+pub const SHARE_LEN: usize = MAGIC.len() + 1 + SPLIT_ID_LEN + 2 + KEY_LEN;
+
+/// One share of a key split with [`split`]
+#[derive(Clone)]
+pub struct Share {
+    split_id: [u8; SPLIT_ID_LEN],
+    threshold: u8,
+    index: u8,
+    value: KeyBytes,
+}
+
+impl Share {
+    /// Which share of its split this is, from 1
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// How many shares of its split rebuild the key
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// The share as [`SHARE_LEN`] bytes, to store or hand to its custodian
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut bytes = Zeroizing::new(Vec::with_capacity(SHARE_LEN));
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.split_id);
+        bytes.push(self.threshold);
+        bytes.push(self.index);
+        bytes.extend_from_slice(&self.value[..]);
+        bytes
+    }
+
+    /// Read a share written by [`Share::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EncryptError> {
+        let invalid = || EncryptError::KeyModeMismatch("not a key share".to_string());
+        if bytes.len() != SHARE_LEN || !bytes.starts_with(MAGIC) || bytes[MAGIC.len()] != VERSION {
+            return Err(invalid());
+        }
+        let (split_id, rest) = bytes[MAGIC.len() + 1..].split_at(SPLIT_ID_LEN);
+        let (threshold, index) = (rest[0], rest[1]);
+        if threshold == 0 || index == 0 {
+            return Err(invalid());
+        }
+        Ok(Share {
+            split_id: split_id.try_into().map_err(|_| invalid())?,
+            threshold,
+            index,
+            value: Zeroizing::new(rest[2..].try_into().map_err(|_| invalid())?),
+        })
+    }
+}
+
+/// Split `key` into `count` shares, any `threshold` of which rebuild it. The threshold must be at least 2, since
+/// with 1 every share would be the key itself, and at most `count`.
+pub fn split(key: &[u8; KEY_LEN], threshold: u8, count: u8) -> Result<Vec<Share>, EncryptError> {
+    if threshold < 2 || threshold > count {
+        return Err(EncryptError::KeyModeMismatch(format!(
+            "a key can be split into shares with a threshold between 2 and their number, not {} of {}",
+            threshold, count
+        )));
+    }
+    let mut split_id = [0u8; SPLIT_ID_LEN];
+    cipher::fill_random(&mut split_id)?;
+    // The coefficients of the terms above the constant one, threshold - 1 of them for each byte of the key
+    let degree = threshold as usize - 1;
+    let mut coefficients = Zeroizing::new(vec![0u8; KEY_LEN * degree]);
+    cipher::fill_random(&mut coefficients)?;
+
+    let shares = (1..=count)
+        .map(|x| {
+            let mut value = Zeroizing::new([0u8; KEY_LEN]);
+            for (i, byte) in value.iter_mut().enumerate() {
+                // Horner's rule, from the highest term down to the key byte
+                let terms = &coefficients[i * degree..(i + 1) * degree];
+                let y = terms.iter().rev().fold(0, |y, &a| mul(y, x) ^ a);
+                *byte = mul(y, x) ^ key[i];
+            }
+            Share {
+                split_id,
+                threshold,
+                index: x,
+                value,
+            }
+        })
+        .collect();
+    Ok(shares)
+}
+
+/// Rebuild the key from at least the threshold of shares of one split, in any order. Fails with
+/// [`EncryptError::KeyModeMismatch`] when there are too few of them or they come from different splits. A damaged
+/// share rebuilds a wrong key, which then fails to decrypt.
+pub fn combine(shares: &[Share]) -> Result<Zeroizing<[u8; KEY_LEN]>, EncryptError> {
+    let Some(first) = shares.first() else {
+        return Err(EncryptError::KeyModeMismatch(
+            "no key shares were given".to_string(),
+        ));
+    };
+    if shares
+        .iter()
+        .any(|share| share.split_id != first.split_id || share.threshold != first.threshold)
+    {
+        return Err(EncryptError::KeyModeMismatch(
+            "the key shares come from different splits".to_string(),
+        ));
+    }
+    let mut distinct: Vec<&Share> = Vec::new();
+    for share in shares {
+        if distinct.iter().all(|other| other.index != share.index) {
+            distinct.push(share);
+        }
+    }
+    let threshold = first.threshold as usize;
+    if distinct.len() < threshold {
+        return Err(EncryptError::KeyModeMismatch(format!(
+            "{} different key shares were given, but {} are needed",
+            distinct.len(),
+            threshold
+        )));
+    }
+
+    // Lagrange interpolation at x = 0 through the first `threshold` shares
+    let points = &distinct[..threshold];
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    for share in points {
+        let basis = points
+            .iter()
+            .filter(|other| other.index != share.index)
+            .fold(1, |basis, other| {
+                mul(basis, mul(other.index, inverse(other.index ^ share.index)))
+            });
+        for (byte, y) in key.iter_mut().zip(share.value.iter()) {
+            *byte ^= mul(*y, basis);
+        }
+    }
+    Ok(key)
+}
+
+// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x + 1, without branches or tables that depend on the operands
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = a >> 7;
+        a = (a << 1) ^ (0x1b & 0u8.wrapping_sub(carry));
+        b >>= 1;
+    }
+    product
+}
+
+// The multiplicative inverse in GF(2^8), a^254
+fn inverse(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul(result, base);
+        }
+        base = mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
@@ -0,0 +1,91 @@
+// Key shares for `encrypt --shares N --threshold K` and `decrypt --share`: the files are encrypted with a random raw
+// key, split with encryptor::shamir into N shares written to <prefix>.1.share ... <prefix>.N.share, one for each
+// custodian, and any K of them rebuild the key. A share file holds a comment line saying which share it is and the
+// share itself as base64, and only its owner can read it.
+use crate::keyfile;
+use base64::Engine;
+use encryptor::shamir::{self, Share};
+use encryptor::EncryptError;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+// Generate the key of a run and write its `count` shares next to `prefix`, returning the key and the share files.
+// Existing files are never overwritten, and a split that can't be written completely is removed again, so that
+// nothing is encrypted with a key whose shares aren't all out.
+pub fn split(
+    prefix: &Path,
+    threshold: u8,
+    count: u8,
+) -> Result<(Zeroizing<[u8; 32]>, Vec<PathBuf>), String> {
+    let key = Zeroizing::new(encryptor::generate_key().map_err(message)?);
+    let shares = shamir::split(&key, threshold, count)
+        .map_err(|err| format!("Cannot split the key: {}", message(err)))?;
+    let paths: Vec<PathBuf> = (1..=count)
+        .map(|index| encryptor::names::with_suffix(prefix, &format!(".{}.share", index)))
+        .collect();
+    if let Some(path) = paths.iter().find(|path| path.exists()) {
+        return Err(format!(
+            "Cannot write the key share {}: it exists already",
+            path.display()
+        ));
+    }
+
+    for (written, (share, path)) in shares.iter().zip(&paths).enumerate() {
+        let contents = Zeroizing::new(format!(
+            "# encryptor key share {} of {}: any {} of them decrypt (decrypt --share)\n{}\n",
+            share.index(),
+            count,
+            threshold,
+            base64::engine::general_purpose::STANDARD.encode(&share.to_bytes()[..])
+        ));
+        if let Err(err) = keyfile::create_private(path, contents.as_bytes()) {
+            paths[..written].iter().for_each(|path| {
+                let _ = fs::remove_file(path);
+            });
+            return Err(format!(
+                "Cannot write the key share {}: {}",
+                path.display(),
+                err
+            ));
+        }
+    }
+    Ok((key, paths))
+}
+
+// Rebuild the key from the share files given with --share
+pub fn combine(paths: &[String]) -> Result<Zeroizing<[u8; 32]>, String> {
+    let shares = paths
+        .iter()
+        .map(|path| read(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    shamir::combine(&shares)
+        .map_err(|err| format!("Cannot rebuild the key from the shares: {}", message(err)))
+}
+
+fn read(path: &str) -> Result<Share, String> {
+    let contents = Zeroizing::new(
+        fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read the key share {}: {}", path, err))?,
+    );
+    let text: Zeroizing<String> = Zeroizing::new(
+        contents
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(str::trim)
+            .collect(),
+    );
+    base64::engine::general_purpose::STANDARD
+        .decode(text.as_bytes())
+        .ok()
+        .map(Zeroizing::new)
+        .and_then(|bytes| Share::from_bytes(&bytes).ok())
+        .ok_or_else(|| format!("{} isn't a key share written by encrypt --shares", path))
+}
+
+fn message(err: EncryptError) -> String {
+    match err {
+        EncryptError::KeyModeMismatch(message) => message,
+        err => err.to_string(),
+    }
+}
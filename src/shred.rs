@@ -0,0 +1,141 @@
+// Overwriting a file with random data before removing it, for `encrypt --shred`, and the old blocks of a file
+// replaced by `--in-place --shred`.
+// On SSDs and on copy-on-write or log-structured filesystems the old blocks can survive elsewhere on the disk,
+// so this only makes recovering the original harder; full-disk encryption is what protects it there.
+// `caveat` spots those cases where it can, so the command line tool can warn about them.
+use encryptor::EncryptError;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const CHUNK: usize = 64 * 1024;
+
+// Filesystems that write changed blocks to new places instead of overwriting them in place
+#[cfg(target_os = "linux")]
+const COPY_ON_WRITE: &[&str] = &["btrfs", "zfs", "bcachefs", "f2fs", "nilfs2"];
+
+// Overwrite the file at `path` with random data, sync it to disk and remove it. A file that is already gone
+// counts as shredded, so a run interrupted between the two steps can simply be repeated.
+pub fn shred(path: &Path) -> io::Result<()> {
+    let file = match OpenOptions::new().write(true).open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    overwrite(file)?;
+    encryptor::fault::point("shred");
+    fs::remove_file(path)
+}
+
+// Run `replace`, which renames a new file over the one at `path`, then overwrite the old file's blocks through
+// a handle opened beforehand. The rename has already happened by then, so a crash can't lose the file either way.
+pub fn replacing(
+    path: &Path,
+    replace: impl FnOnce() -> Result<PathBuf, EncryptError>,
+) -> Result<PathBuf, EncryptError> {
+    let old = OpenOptions::new().write(true).open(path)?;
+    let output_path = replace()?;
+    overwrite(old)?;
+    Ok(output_path)
+}
+
+// Overwrite the whole file with random data and sync it to disk
+fn overwrite(mut file: File) -> io::Result<()> {
+    let mut random = vec![0u8; CHUNK];
+    let mut remaining = file.metadata()?.len();
+    while remaining > 0 {
+        let len = remaining.min(CHUNK as u64) as usize;
+        encryptor::fill_random(&mut random[..len]).map_err(io::Error::other)?;
+        file.write_all(&random[..len])?;
+        remaining -= len as u64;
+    }
+    file.sync_all()
+}
+
+// Why overwriting the file at `path` may not destroy its old contents, e.g. "an SSD" or "a copy-on-write
+// filesystem (btrfs)", as far as can be told. Only Linux is checked, through /proc/self/mountinfo and /sys.
+pub fn caveat(path: &Path) -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        let (fs_type, device) = mount_of(path)?;
+        let copy_on_write = COPY_ON_WRITE.contains(&fs_type.as_str());
+        let ssd = device.as_deref().is_some_and(is_ssd);
+        match (copy_on_write, ssd) {
+            (true, true) => Some(format!(
+                "an SSD with a copy-on-write filesystem ({})",
+                fs_type
+            )),
+            (true, false) => Some(format!("a copy-on-write filesystem ({})", fs_type)),
+            (false, true) => Some("an SSD".to_string()),
+            (false, false) => None,
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+// The filesystem type and source device of the mount holding `path`: the mount point that is its longest prefix
+#[cfg(target_os = "linux")]
+fn mount_of(path: &Path) -> Option<(String, Option<String>)> {
+    let path = fs::canonicalize(path).ok()?;
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            // <id> <parent> <major:minor> <root> <mount point> <options> [optional fields] - <type> <source> ...
+            let (before, after) = line.split_once(" - ")?;
+            let mount_point = unescape(before.split(' ').nth(4)?);
+            let mut after = after.split(' ');
+            let fs_type = after.next()?.to_string();
+            let source = after.next().filter(|source| source.starts_with("/dev/"));
+            path.starts_with(&mount_point)
+                .then(|| (mount_point.len(), fs_type, source.map(str::to_string)))
+        })
+        // The last of equally long mount points is the one mounted on top
+        .max_by_key(|(len, ..)| *len)
+        .map(|(_, fs_type, source)| (fs_type, source))
+}
+
+// Mount points escape spaces, tabs, newlines and backslashes as octal (e.g. \040)
+#[cfg(target_os = "linux")]
+fn unescape(field: &str) -> String {
+    let mut out = String::new();
+    let mut rest = field;
+    while let Some(index) = rest.find('\\') {
+        out.push_str(&rest[..index]);
+        let code = rest
+            .get(index + 1..index + 4)
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match code {
+            Some(code) => {
+                out.push(code as char);
+                rest = &rest[index + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Whether the block device is non-rotational. A partition has no queue of its own, so its disk's is used.
+#[cfg(target_os = "linux")]
+fn is_ssd(device: &str) -> bool {
+    let Some(name) = fs::canonicalize(device).ok().and_then(|device| {
+        device
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    }) else {
+        return false;
+    };
+    ["queue/rotational", "../queue/rotational"]
+        .iter()
+        .find_map(|queue| fs::read_to_string(format!("/sys/class/block/{}/{}", name, queue)).ok())
+        .is_some_and(|rotational| rotational.trim() == "0")
+}
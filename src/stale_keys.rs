@@ -0,0 +1,102 @@
+// `encryptor report stale-keys`: the files whose password was set longer ago than --max-password-age allows. Every
+// file encrypted with a password alone records when the password was set (`encrypt --password-set`, or the time of
+// encryption), masked with the file's key, so each file is opened with the password to read it. Files it doesn't
+// open, and files that don't record the time, are counted apart rather than listed.
+use crate::output::Printer;
+use crate::path_filter::PathFilter;
+use crate::{redact, report, rotate, units};
+use encryptor::Encryptor;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// How old a password `report stale-keys` lets pass when no --max-password-age is given or configured
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(365 * 86400);
+
+// When the password of the native file at `file_path` was set and how long ago, if longer than `max_age`.
+// Nothing for a file the password doesn't open or that doesn't record the time.
+pub fn stale(encryptor: &Encryptor, file_path: &Path, max_age: Duration) -> Option<String> {
+    describe(encryptor.password_set(file_path).ok()??, max_age)
+}
+
+// List the files under `paths` that `filter` takes whose password was set longer ago than `max_age`, with a summary,
+// and return how many there are
+pub fn run(
+    encryptor: &Encryptor,
+    paths: &[PathBuf],
+    max_age: Duration,
+    filter: &PathFilter,
+    printer: &Printer,
+) -> usize {
+    let mut files = Vec::new();
+    for path in paths {
+        match path.exists() {
+            true => rotate::walk(path, "", &mut filter.walk(), &mut files),
+            false => printer.warn(&format!("{} doesn't exist", path.display())),
+        }
+    }
+    files.iter().for_each(redact::path);
+    // Only files encrypted with a password alone record when it was set
+    files.retain(|file_path| {
+        report::native_header(file_path).is_some_and(|header| header.password_check.is_some())
+    });
+
+    let (mut stale, mut unopened, mut unknown) = (0, 0, 0);
+    for file_path in &files {
+        match encryptor.password_set(file_path) {
+            Ok(Some(set)) => {
+                if let Some(age) = describe(set, max_age) {
+                    printer.note(&format!("{}: {}", file_path.display(), age));
+                    stale += 1;
+                }
+            }
+            Ok(None) => unknown += 1,
+            Err(err) => {
+                log::debug!("Cannot open {}: {}", file_path.display(), err);
+                unopened += 1;
+            }
+        }
+    }
+    if unopened > 0 {
+        printer.warn(&format!(
+            "{} of {} files don't open with this password, so their passwords' ages are unknown",
+            unopened,
+            files.len()
+        ));
+    }
+    if unknown > 0 {
+        printer.warn(&format!(
+            "{} of {} files don't record when their password was set",
+            unknown,
+            files.len()
+        ));
+    }
+    match stale {
+        0 => printer.ok(&format!(
+            "No password among {} files was set more than {} ago",
+            files.len(),
+            units::duration(max_age)
+        )),
+        _ => printer.failed(&format!(
+            "{} of {} files have a password set more than {} ago; change it with `encryptor rekey`",
+            stale,
+            files.len(),
+            units::duration(max_age)
+        )),
+    }
+    stale
+}
+
+// When a password was set `set` seconds after the Unix epoch and how long ago, if longer than `max_age`
+fn describe(set: u64, max_age: Duration) -> Option<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let age = now.saturating_sub(set);
+    (age > max_age.as_secs()).then(|| {
+        format!(
+            "its password was set on {}, {} ago",
+            units::time(set),
+            units::duration(Duration::from_secs(age))
+        )
+    })
+}
@@ -0,0 +1,324 @@
+// Opt-in usage statistics that never leave the machine. Once `encryptor stats enable` has been run, every batch of
+// files (encrypt, decrypt, verify, rekey and rotate) appends one line to <config>/history.jsonl:
+//
+//   {"at": 1760000000, "command": "encrypt", "exit_code": 3, "duration_ms": 152.3, "files": 4, "ok": 3,
+//    "failed": 1, "bytes": 1048576, "errors": {"ENC_IO": 1}}
+//
+// Nothing in it identifies a file, a user or a machine: no paths, names, settings or secrets. `stats local`
+// summarizes the history per day and per command, offline, and `stats disable` stops recording and deletes it.
+use crate::units;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+struct Run {
+    path: PathBuf,
+    command: String,
+    at: u64,
+    start: Instant,
+    totals: Totals,
+}
+
+// The runs, files, failures, bytes and error codes of some part of the history
+#[derive(Default)]
+struct Totals {
+    runs: u64,
+    files: u64,
+    ok: u64,
+    failed: u64,
+    bytes: u64,
+    errors: BTreeMap<String, u64>,
+}
+
+impl Totals {
+    fn add(&mut self, other: &Totals) {
+        self.runs += other.runs;
+        self.files += other.files;
+        self.ok += other.ok;
+        self.failed += other.failed;
+        self.bytes += other.bytes;
+        for (code, count) in &other.errors {
+            *self.errors.entry(code.clone()).or_default() += count;
+        }
+    }
+
+    // Read the totals of one line of the history
+    fn from_json(record: &Value) -> Totals {
+        let count = |name: &str| record[name].as_u64().unwrap_or_default();
+        Totals {
+            runs: 1,
+            files: count("files"),
+            ok: count("ok"),
+            failed: count("failed"),
+            bytes: count("bytes"),
+            errors: record["errors"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(code, count)| (code.clone(), count.as_u64().unwrap_or_default()))
+                .collect(),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "runs": self.runs,
+            "files": self.files,
+            "ok": self.ok,
+            "failed": self.failed,
+            "error_rate": self.error_rate(),
+            "bytes": self.bytes,
+            "errors": self.errors,
+        })
+    }
+
+    // The share of files that failed
+    fn error_rate(&self) -> f64 {
+        match self.files {
+            0 => 0.0,
+            files => self.failed as f64 / files as f64,
+        }
+    }
+
+    fn row(&self, name: &str) -> String {
+        format!(
+            "{:<12} {:>6} {:>8} {:>7} {:>9.1}% {:>12}",
+            name,
+            self.runs,
+            self.files,
+            self.failed,
+            self.error_rate() * 100.0,
+            units::size(self.bytes)
+        )
+    }
+}
+
+// The run being recorded, when the history is enabled
+static RUN: Mutex<Option<Run>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<Run>> {
+    // A worker that panicked while recording leaves the totals consistent
+    RUN.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Where the history is kept
+fn history_path() -> Result<PathBuf, String> {
+    Ok(crate::config_dir()?.join("history.jsonl"))
+}
+
+// Start recording the history
+pub fn enable() -> Result<String, String> {
+    let path = history_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| format!("Cannot create {}: {}", parent.display(), err))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|err| format!("Cannot create {}: {}", path.display(), err))?;
+    Ok(format!(
+        "Recording usage statistics in {}; nothing is sent anywhere, and `encryptor stats disable` deletes them",
+        path.display()
+    ))
+}
+
+// Stop recording the history, and delete it
+pub fn disable() -> Result<String, String> {
+    let path = history_path()?;
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(format!(
+            "Stopped recording usage statistics and deleted {}",
+            path.display()
+        )),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            Ok("Usage statistics were not being recorded".to_string())
+        }
+        Err(err) => Err(format!("Cannot delete {}: {}", path.display(), err)),
+    }
+}
+
+// Start recording a batch of `command`, if the history is enabled
+pub fn start(command: &str) {
+    let Ok(path) = history_path() else {
+        return;
+    };
+    if !path.is_file() {
+        return;
+    }
+    *lock() = Some(Run {
+        path,
+        command: command.to_string(),
+        at: now(),
+        start: Instant::now(),
+        totals: Totals {
+            runs: 1,
+            ..Totals::default()
+        },
+    });
+}
+
+// Whether a batch is being recorded
+pub fn active() -> bool {
+    lock().is_some()
+}
+
+// Count the outcome of one file, as reported by --json
+pub fn file(outcome: &Value) {
+    if let Some(run) = lock().as_mut() {
+        let totals = &mut run.totals;
+        totals.files += 1;
+        totals.bytes += outcome["bytes"].as_u64().unwrap_or_default();
+        match outcome["error"]["code"].as_str() {
+            Some(code) => {
+                totals.failed += 1;
+                *totals.errors.entry(code.to_string()).or_default() += 1;
+            }
+            None => totals.ok += 1,
+        }
+    }
+}
+
+// Append the batch to the history, if one was started. Failing to do so doesn't change the exit status.
+pub fn finish(exit_code: i32) {
+    let Some(run) = lock().take() else {
+        return;
+    };
+    let totals = &run.totals;
+    let record = json!({
+        "at": run.at,
+        "command": run.command,
+        "exit_code": exit_code,
+        "duration_ms": (run.start.elapsed().as_secs_f64() * 1e6).round() / 1000.0,
+        "files": totals.files,
+        "ok": totals.ok,
+        "failed": totals.failed,
+        "bytes": totals.bytes,
+        "errors": totals.errors,
+    });
+    // One write per line, so batches that finish at the same time don't interleave
+    let written = OpenOptions::new()
+        .append(true)
+        .open(&run.path)
+        .and_then(|mut history| history.write_all(format!("{}\n", record).as_bytes()));
+    if let Err(err) = written {
+        eprintln!(
+            "Cannot record usage statistics in {}: {}",
+            run.path.display(),
+            err
+        );
+    }
+}
+
+// Summarize the history of the last `days` days (all of it by default) per day, per command and per error code
+pub fn local(days: Option<u64>, as_json: bool) -> Result<String, String> {
+    let path = history_path()?;
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Err(
+            "No usage statistics are recorded; start recording them with `encryptor stats enable`"
+                .to_string(),
+        ),
+        Err(err) => return Err(format!("Cannot read {}: {}", path.display(), err)),
+    };
+    let since = days.map_or(0, |days| {
+        (now() / SECONDS_PER_DAY).saturating_sub(days.saturating_sub(1)) * SECONDS_PER_DAY
+    });
+
+    let mut total = Totals::default();
+    let mut per_day = BTreeMap::<String, Totals>::new();
+    let mut per_command = BTreeMap::<String, Totals>::new();
+    let mut skipped = 0;
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        // A line cut short by a crash, or edited by hand, doesn't spoil the rest
+        let Ok(record) = serde_json::from_str::<Value>(line) else {
+            skipped += 1;
+            continue;
+        };
+        let (Some(at), Some(command)) = (record["at"].as_u64(), record["command"].as_str()) else {
+            skipped += 1;
+            continue;
+        };
+        if at < since {
+            continue;
+        }
+        let totals = Totals::from_json(&record);
+        total.add(&totals);
+        per_day.entry(day(at)).or_default().add(&totals);
+        per_command
+            .entry(command.to_string())
+            .or_default()
+            .add(&totals);
+    }
+    if skipped > 0 {
+        log::warn!("Skipped {} damaged lines of {}", skipped, path.display());
+    }
+
+    if as_json {
+        let object = |totals: &BTreeMap<String, Totals>| {
+            totals
+                .iter()
+                .map(|(name, totals)| (name.clone(), totals.to_json()))
+                .collect::<Map<_, _>>()
+        };
+        let summary = json!({
+            "since": days.map(|_| day(since)),
+            "total": total.to_json(),
+            "days": object(&per_day),
+            "commands": object(&per_command),
+        });
+        return Ok(serde_json::to_string_pretty(&summary).unwrap_or_default());
+    }
+
+    let mut lines = vec![match (per_day.keys().next(), per_day.keys().last()) {
+        (Some(first), Some(last)) => format!(
+            "Usage from {} to {}, recorded in {}",
+            first,
+            last,
+            path.display()
+        ),
+        _ => format!("No usage recorded in {} yet", path.display()),
+    }];
+    let header = format!(
+        "{:<12} {:>6} {:>8} {:>7} {:>10} {:>12}",
+        "", "runs", "files", "failed", "error rate", "data"
+    );
+    for (title, totals) in [("day", &per_day), ("command", &per_command)] {
+        if totals.is_empty() {
+            continue;
+        }
+        lines.push(String::new());
+        lines.push(header.replacen(&" ".repeat(title.len()), title, 1));
+        lines.extend(totals.iter().map(|(name, totals)| totals.row(name)));
+        lines.push(total.row("total"));
+    }
+    if !total.errors.is_empty() {
+        lines.push(String::new());
+        lines.push(format!("{:<24} {:>8}", "error", "files"));
+        lines.extend(
+            total
+                .errors
+                .iter()
+                .map(|(code, count)| format!("{:<24} {:>8}", code, count)),
+        );
+    }
+    Ok(lines.join("\n"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+// The UTC date of `at`, e.g. 2026-10-16
+fn day(at: u64) -> String {
+    units::utc(at)[..10].to_string()
+}
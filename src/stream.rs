@@ -0,0 +1,254 @@
+//! Chunked payloads, for files too large to seal in one piece.
+//!
+//! Sealing a payload in one piece is the cheapest way to encrypt a small file, but it needs the whole file in memory
+//! and is limited to what one nonce can seal (see [`Algorithm::max_payload_len`]). Payloads of at least
+//! [`Encryptor::with_stream_threshold`](crate::Encryptor::with_stream_threshold) bytes are instead split into chunks
+//! of [`Encryptor::with_chunk_size`](crate::Encryptor::with_chunk_size) bytes, each sealed with its own tag, so
+//! [`Encryptor::encrypt_file`](crate::Encryptor::encrypt_file) and
+//! [`Encryptor::decrypt_file`](crate::Encryptor::decrypt_file) only ever hold one chunk in memory.
+//!
+//! Every chunk is authenticated together with the whole header, and its nonce is derived from the header's random
+//! nonce, its index and whether it is the last chunk, so chunks can't be reordered, dropped, duplicated, moved
+//! between files, or cut off at a chunk boundary without decryption failing. Decrypting a file writes each chunk
+//! as it authenticates, to a temporary file that only replaces the output once every chunk did.
+//!
+//! Each chunk counts as one output toward the number of outputs a password's key seals before a new salt and key
+//! are picked (see [`Algorithm::max_messages_per_key`]).
+//!
+//! Since every chunk is sealed on its own, [`Encryptor::with_stream_workers`](crate::Encryptor::with_stream_workers)
+//! can seal the chunks of one file on several threads: a reader thread reads them in order, the workers seal them
+//! in whatever order they get to them, and the chunks are written in order again. At most a few chunks per worker
+//! are in memory at once.
+use crate::progress::read_full;
+use crate::{cipher, Algorithm, CryptoError, EncryptError, KeyBytes, KEY_LEN, NONCE_LEN, TAG_LEN};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::sync::{mpsc, Arc, Mutex};
+use zeroize::Zeroizing;
+
+/// Payloads at least this long (8 MiB) are sealed in chunks by default
+pub const DEFAULT_THRESHOLD: u64 = 8 << 20;
+/// The number of plaintext bytes in each chunk by default (1 MiB)
+pub const DEFAULT_CHUNK_SIZE: u32 = 1 << 20;
+
+/// How many chunks a payload of `payload_len` bytes is split into. Even an empty payload has one (empty) chunk,
+/// and a payload that fills its last chunk exactly doesn't get an empty one after it.
+pub fn chunk_count(payload_len: u64, chunk_size: u32) -> u64 {
+    payload_len.div_ceil(chunk_size as u64).max(1)
+}
+
+// The number of chunks in `ciphertext_len` bytes of chunks sealed with `chunk_size` bytes each. Only the last chunk
+// can be shorter than the others, and it still has its tag.
+pub(crate) fn ciphertext_chunk_count(
+    ciphertext_len: u64,
+    chunk_size: u32,
+) -> Result<u64, EncryptError> {
+    let sealed_chunk = chunk_size as u64 + TAG_LEN as u64;
+    let count = ciphertext_len.div_ceil(sealed_chunk);
+    let last = ciphertext_len - (count.max(1) - 1) * sealed_chunk;
+    if count == 0 || last < TAG_LEN as u64 {
+        return Err(EncryptError::MetadataMismatch(format!(
+            "{} bytes of ciphertext can't be chunks of {} bytes (the file was truncated or extended)",
+            ciphertext_len, chunk_size
+        )));
+    }
+    if count > u32::MAX as u64 {
+        return Err(too_many_chunks(chunk_size));
+    }
+    Ok(count)
+}
+
+// The nonce of the chunk at `index`, see the `header` module. The index only has 32 bits in the nonce, so the chunk
+// after the last one it can count would get the nonce of the first one again.
+fn chunk_nonce(nonce: &[u8], index: u64, last: bool) -> Result<[u8; NONCE_LEN], EncryptError> {
+    let index = u32::try_from(index).map_err(|_| {
+        EncryptError::NonceReuse(format!(
+            "chunk {} would reuse the nonce of chunk {}, since a payload has at most {} chunks",
+            index,
+            index % (1 << 32),
+            u32::MAX as u64 + 1
+        ))
+    })?;
+    let mut chunk_nonce: [u8; NONCE_LEN] = nonce.try_into().map_err(|_| CryptoError)?;
+    for (byte, index_byte) in chunk_nonce[7..11].iter_mut().zip(index.to_be_bytes()) {
+        *byte ^= index_byte;
+    }
+    chunk_nonce[11] ^= last as u8;
+    Ok(chunk_nonce)
+}
+
+// Check that a payload of `payload_len` bytes can be split into chunks of `chunk_size` bytes, returning how many
+pub(crate) fn checked_chunk_count(payload_len: u64, chunk_size: u32) -> Result<u64, EncryptError> {
+    match chunk_count(payload_len, chunk_size) {
+        count if count > u32::MAX as u64 => Err(too_many_chunks(chunk_size)),
+        count => Ok(count),
+    }
+}
+
+fn too_many_chunks(chunk_size: u32) -> EncryptError {
+    EncryptError::TooLarge(format!(
+        "a payload can have at most {} chunks of {} bytes",
+        u32::MAX,
+        chunk_size
+    ))
+}
+
+// Seals or opens the chunks of one payload, in order
+pub(crate) struct Chunks {
+    algorithm: Algorithm,
+    key: KeyBytes,
+    nonce: Vec<u8>,
+    aad: Vec<u8>,
+    index: u64,
+}
+
+impl Chunks {
+    // `aad` is the header the chunks are bound to, and `nonce` the nonce recorded in it
+    pub(crate) fn new(algorithm: Algorithm, key: &[u8; KEY_LEN], nonce: &[u8], aad: &[u8]) -> Self {
+        Chunks {
+            algorithm,
+            key: Zeroizing::new(*key),
+            nonce: nonce.to_vec(),
+            aad: aad.to_vec(),
+            index: 0,
+        }
+    }
+
+    // Encrypt the next chunk in place and append its tag. `last` must be set on the last chunk, and only on it.
+    pub(crate) fn seal(&mut self, chunk: &mut Vec<u8>, last: bool) -> Result<(), EncryptError> {
+        self.seal_at(self.index, chunk, last)?;
+        self.index += 1;
+        Ok(())
+    }
+
+    // Encrypt the chunk at `index` in place and append its tag, whichever chunks were sealed before
+    fn seal_at(&self, index: u64, chunk: &mut Vec<u8>, last: bool) -> Result<(), EncryptError> {
+        let nonce = chunk_nonce(&self.nonce, index, last)?;
+        cipher::seal_in_place(self.algorithm, &self.key, &nonce, &self.aad, chunk)?;
+        Ok(())
+    }
+
+    // Decrypt the next chunk (its ciphertext and tag) in place, leaving its plaintext
+    pub(crate) fn open(&mut self, chunk: &mut Vec<u8>, last: bool) -> Result<(), EncryptError> {
+        self.open_at(self.index, chunk, last)?;
+        self.index += 1;
+        Ok(())
+    }
+
+    // Decrypt the chunk at `index` in place, whichever chunks were opened before
+    pub(crate) fn open_at(
+        &self,
+        index: u64,
+        chunk: &mut Vec<u8>,
+        last: bool,
+    ) -> Result<(), EncryptError> {
+        let nonce = chunk_nonce(&self.nonce, index, last)?;
+        cipher::open_in_place(self.algorithm, &self.key, &nonce, &self.aad, chunk)?;
+        Ok(())
+    }
+
+    // Continue with the chunk at `index`, skipping those before it
+    pub(crate) fn seek(&mut self, index: u64) {
+        self.index = index;
+    }
+}
+
+// Where the pipeline hands each sealed chunk, with its index
+pub(crate) type ChunkSink<'a> = dyn FnMut(u64, &[u8]) -> Result<(), EncryptError> + 'a;
+
+// Seal the chunks from `first` to `count` of a payload read from `reader` on `workers` threads, passing each one to
+// `write` in order with its index. `len` is the plaintext length of the chunk at an index; a reader that ends
+// early, or has more to read after the last chunk, fails with `changed`. No more than two chunks per worker are
+// read ahead of the last one written.
+pub(crate) fn seal_pipelined(
+    chunks: &Chunks,
+    workers: usize,
+    (first, count): (u64, u64),
+    len: impl Fn(u64) -> usize + Sync,
+    reader: &mut (dyn Read + Send),
+    changed: impl Fn() -> EncryptError + Sync,
+    write: &mut ChunkSink,
+) -> Result<(), EncryptError> {
+    let workers = workers.max(1);
+    std::thread::scope(|scope| {
+        // The reader takes a credit for every chunk it reads, and gets it back once the chunk is written
+        let window = 2 * workers;
+        let (give_credit, take_credit) = mpsc::sync_channel::<()>(window);
+        for _ in 0..window {
+            give_credit
+                .send(())
+                .expect("the credits fit in the channel");
+        }
+        let (to_workers, jobs) = mpsc::channel::<(u64, Vec<u8>)>();
+        let (to_writer, sealed) = mpsc::channel::<Result<(u64, Vec<u8>), EncryptError>>();
+
+        let (len, changed) = (&len, &changed);
+        let reading = scope.spawn(move || -> Result<(), EncryptError> {
+            let mut reader = reader;
+            for index in first..count {
+                // Without credits or workers left the writer has stopped, and will report why
+                if take_credit.recv().is_err() {
+                    return Ok(());
+                }
+                let mut chunk = Vec::with_capacity(len(index) + TAG_LEN);
+                chunk.resize(len(index), 0);
+                if read_full(&mut reader, &mut chunk)? != chunk.len() {
+                    return Err(changed());
+                }
+                if to_workers.send((index, chunk)).is_err() {
+                    return Ok(());
+                }
+            }
+            match read_full(&mut reader, &mut [0])? {
+                0 => Ok(()),
+                _ => Err(changed()),
+            }
+        });
+
+        // The workers drop the receiver of the jobs once the writer is gone, which stops the reader too
+        let jobs = Arc::new(Mutex::new(jobs));
+        for _ in 0..workers {
+            let (jobs, to_writer) = (jobs.clone(), to_writer.clone());
+            scope.spawn(move || loop {
+                let job = jobs.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                let Ok((index, mut chunk)) = job else {
+                    break;
+                };
+                let result = chunks
+                    .seal_at(index, &mut chunk, index == count - 1)
+                    .map(|()| (index, chunk));
+                if to_writer.send(result).is_err() {
+                    break;
+                }
+            });
+        }
+        drop((jobs, to_writer));
+
+        let mut next = first;
+        let mut pending = BTreeMap::new();
+        let written = (|| {
+            // Ends early when the reader stops, after the chunks it read were sealed
+            while let Ok(result) = sealed.recv() {
+                let (index, chunk) = result?;
+                pending.insert(index, chunk);
+                while let Some(chunk) = pending.remove(&next) {
+                    write(next, &chunk)?;
+                    next += 1;
+                    let _ = give_credit.send(());
+                }
+                if next == count {
+                    break;
+                }
+            }
+            Ok(())
+        })();
+        drop((give_credit, sealed));
+
+        let read = reading.join().expect("the reader thread doesn't panic");
+        written.and(read)?;
+        match next == count {
+            true => Ok(()),
+            false => Err(changed()),
+        }
+    })
+}
@@ -0,0 +1,168 @@
+// Decrypting through the library's io adapters rather than the file functions of Encryptor: s3:// objects,
+// http(s):// URLs and split volumes, which aren't one local file, with DecryptingReader, and the byte range of
+// `decrypt --range` with SeekableReader. Where their plaintext goes, and writing it there only once all of it has authenticated.
+use crate::interrupt;
+use encryptor::{names, DecryptingReader, EncryptError, Encryptor, SeekableReader};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+// What happens to a file already where an output goes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Existing {
+    Refuse,  // Fail before anything is written
+    Replace, // Write over it (--force)
+    Rename,  // Write to the first free name next to it instead (--auto-rename)
+}
+
+// The path the output meant for `path` is written to, failing if a file is there and `existing` refuses it
+pub fn claim(path: PathBuf, existing: Existing) -> Result<PathBuf, EncryptError> {
+    match existing {
+        Existing::Replace => Ok(path),
+        Existing::Rename => Ok(encryptor::unclaimed_path(&path)),
+        Existing::Refuse if fs::symlink_metadata(&path).is_err() => Ok(path),
+        Existing::Refuse => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        )
+        .into()),
+    }
+}
+
+// Where the plaintext of the stream named `name` goes: `out_path`, or into it when it is a directory, ends in a
+// separator or `several` streams go to it; without it, to `name` without its extension in the current directory.
+// A file already there is handled as `existing` says.
+pub fn output_path(
+    name: &OsStr,
+    out_path: Option<&Path>,
+    several: bool,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    let (name, _) = names::split_extension(name);
+    let output_path = match out_path {
+        Some(out_path) if several || ends_with_separator(out_path) || out_path.is_dir() => {
+            out_path.join(name)
+        }
+        Some(out_path) => out_path.to_path_buf(),
+        None => PathBuf::from(name),
+    };
+    claim(output_path, existing)
+}
+
+// Whether `path` is an s3:// object or an http(s):// URL, which is only ever streamed
+pub fn is_remote(path: &Path) -> bool {
+    crate::s3::is_uri(path) || crate::http::is_url(path)
+}
+
+// Whether `path` ends in a separator, as a directory meant to be created would be written
+pub fn ends_with_separator(path: &Path) -> bool {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    bytes.ends_with(b"/") || bytes.ends_with(std::path::MAIN_SEPARATOR_STR.as_bytes())
+}
+
+// Decrypt `input` into `output_path`, through a temporary file that only replaces it once every chunk has
+// authenticated. Returns the path written.
+pub fn decrypt(
+    input: impl Read,
+    encryptor: &Encryptor,
+    output_path: &Path,
+) -> Result<PathBuf, EncryptError> {
+    write(DecryptingReader::new(input, encryptor)?, output_path)
+}
+
+// Decrypt `len` bytes (or all the rest) from `offset` of the plaintext of the chunked file at `file_path` to
+// `out_path` like a whole stream, reading only the chunks that cover them. Returns the path written.
+pub fn decrypt_range(
+    encryptor: &Encryptor,
+    file_path: &Path,
+    (offset, len): (u64, Option<u64>),
+    out_path: Option<&Path>,
+    several: bool,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    let mut reader = SeekableReader::new(File::open(file_path)?, encryptor)?;
+    if offset > reader.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} decrypts to {} bytes, so it has nothing at offset {}",
+                file_path.display(),
+                reader.len(),
+                offset
+            ),
+        )
+        .into());
+    }
+    let len = len.unwrap_or(u64::MAX).min(reader.len() - offset);
+    let name = names::file_name(file_path);
+    let output_path = output_path(name, out_path, several, existing)?;
+    reader.seek(SeekFrom::Start(offset))?;
+    log::debug!(
+        "Decrypting {} bytes from {} of {}",
+        len,
+        offset,
+        file_path.display()
+    );
+    write(reader.take(len), &output_path)
+}
+
+// Write `plaintext` to `output_path` through a temporary file that only replaces it once all of it was read.
+// Returns the path written.
+pub fn write(
+    mut plaintext: impl Read,
+    output_path: impl AsRef<Path>,
+) -> Result<PathBuf, EncryptError> {
+    let output_path = output_path.as_ref();
+    write_with(output_path, |output| {
+        io::copy(&mut interrupt::Reader(&mut plaintext), output).map_err(from_io)?;
+        Ok(())
+    })?;
+    Ok(output_path.to_path_buf())
+}
+
+// Have `write` write `output_path` through a temporary file, which only replaces it once `write` succeeded
+pub fn write_with<T>(
+    output_path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<T, EncryptError>,
+) -> Result<T, EncryptError> {
+    let mut temp_path = output_path.to_path_buf().into_os_string();
+    temp_path.push(format!(".{}.tmp", std::process::id()));
+    let written = File::create(&temp_path)
+        .map_err(EncryptError::from)
+        .and_then(|output| {
+            let mut output = BufWriter::new(output);
+            let written = write(&mut output)?;
+            output.into_inner().map_err(io::Error::from)?.sync_all()?;
+            Ok(written)
+        })
+        .and_then(|written| {
+            fs::rename(&temp_path, output_path)?;
+            Ok(written)
+        });
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    written
+}
+
+// Check that `input` decrypts, without writing the plaintext anywhere
+pub fn verify(input: impl Read, encryptor: &Encryptor) -> Result<(), EncryptError> {
+    let mut reader = DecryptingReader::new(input, encryptor)?;
+    io::copy(&mut reader, &mut io::sink()).map_err(from_io)?;
+    Ok(())
+}
+
+// The EncryptError a DecryptingReader or EncryptingWriter failed with, or the I/O error itself
+pub fn from_io(error: io::Error) -> EncryptError {
+    match error
+        .get_ref()
+        .is_some_and(|inner| inner.is::<EncryptError>())
+    {
+        true => *error
+            .into_inner()
+            .and_then(|inner| inner.downcast().ok())
+            .expect("checked above"),
+        false => EncryptError::IoError(error),
+    }
+}
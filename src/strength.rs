@@ -0,0 +1,680 @@
+// Password strength estimation for `encrypt`, in the manner of zxcvbn: the password is split into the patterns
+// an attacker tries first (common passwords and words, also reversed or with l33t substitutions, the names of
+// the files being encrypted, sequences like abc or 9876, repeats, runs of keys on the keyboard and years), with
+// whatever is left guessed character by character, and the split that needs the fewest guesses counts.
+// The score buckets the guesses like zxcvbn does, from 0 (guessed within a thousand tries) to 4 (more than ten
+// billion), and the crack time assumes an attacker who can spend the memory bandwidth of a few GPUs on Argon2id.
+use encryptor::KdfParams;
+
+// Scores below this get a warning, unless --min-strength refuses them outright
+pub const WEAK: u8 = 3;
+
+// The memory bandwidth (bytes per second) assumed for an offline attacker, roughly that of a few high-end GPUs.
+// Every Argon2id pass writes and then reads its whole memory, so this bounds how fast guesses can be tested.
+const ATTACKER_BANDWIDTH: f64 = 1e12;
+
+// Each extra pattern in a split multiplies the guesses by this, so a split into many tiny patterns doesn't win
+const PATTERN_PENALTY: f64 = 1e4;
+
+// Only the start of a very long password is looked at, which is plenty to rate it
+const MAX_LEN: usize = 100;
+
+// Common passwords and words, most common first: the guesses for one is its rank
+const COMMON: &[&str] = &[
+    "123456",
+    "password",
+    "12345678",
+    "qwerty",
+    "123456789",
+    "12345",
+    "1234",
+    "111111",
+    "1234567",
+    "dragon",
+    "123123",
+    "baseball",
+    "abc123",
+    "football",
+    "monkey",
+    "letmein",
+    "696969",
+    "shadow",
+    "master",
+    "666666",
+    "qwertyuiop",
+    "123321",
+    "mustang",
+    "1234567890",
+    "michael",
+    "654321",
+    "superman",
+    "1qaz2wsx",
+    "7777777",
+    "121212",
+    "000000",
+    "qazwsx",
+    "123qwe",
+    "killer",
+    "trustno1",
+    "jordan",
+    "jennifer",
+    "zxcvbnm",
+    "asdfgh",
+    "hunter",
+    "buster",
+    "soccer",
+    "harley",
+    "batman",
+    "andrew",
+    "tigger",
+    "sunshine",
+    "iloveyou",
+    "2000",
+    "charlie",
+    "robert",
+    "thomas",
+    "hockey",
+    "ranger",
+    "daniel",
+    "starwars",
+    "klaster",
+    "112233",
+    "george",
+    "computer",
+    "michelle",
+    "jessica",
+    "pepper",
+    "1111",
+    "zxcvbn",
+    "555555",
+    "11111111",
+    "131313",
+    "freedom",
+    "777777",
+    "pass",
+    "maggie",
+    "159753",
+    "aaaaaa",
+    "ginger",
+    "princess",
+    "joshua",
+    "cheese",
+    "amanda",
+    "summer",
+    "love",
+    "ashley",
+    "nicole",
+    "chelsea",
+    "biteme",
+    "matthew",
+    "access",
+    "yankees",
+    "987654321",
+    "dallas",
+    "austin",
+    "thunder",
+    "taylor",
+    "matrix",
+    "admin",
+    "welcome",
+    "login",
+    "passw0rd",
+    "secret",
+    "solo",
+    "whatever",
+    "hello",
+    "flower",
+    "lovely",
+    "guest",
+    "changeme",
+    "default",
+    "root",
+    "test",
+    "qwerty123",
+    "password1",
+    "letmein1",
+    "encrypt",
+    "encryptor",
+    "backup",
+    "correct",
+    "horse",
+    "battery",
+    "staple",
+    "the",
+    "and",
+    "you",
+    "that",
+    "was",
+    "for",
+    "are",
+    "with",
+    "his",
+    "they",
+    "this",
+    "have",
+    "from",
+    "one",
+    "had",
+    "word",
+    "but",
+    "not",
+    "what",
+    "all",
+    "were",
+    "when",
+    "your",
+    "can",
+    "said",
+    "there",
+    "use",
+    "each",
+    "which",
+    "she",
+    "how",
+    "their",
+    "will",
+    "other",
+    "about",
+    "out",
+    "many",
+    "then",
+    "them",
+    "these",
+    "some",
+    "her",
+    "would",
+    "make",
+    "like",
+    "him",
+    "into",
+    "time",
+    "has",
+    "look",
+    "two",
+    "more",
+    "write",
+    "see",
+    "number",
+    "way",
+    "could",
+    "people",
+    "than",
+    "first",
+    "water",
+    "been",
+    "call",
+    "who",
+    "now",
+    "find",
+    "long",
+    "down",
+    "day",
+    "did",
+    "get",
+    "come",
+    "made",
+    "may",
+    "part",
+    "over",
+    "new",
+    "sound",
+    "take",
+    "only",
+    "little",
+    "work",
+    "know",
+    "place",
+    "year",
+    "live",
+    "back",
+    "give",
+    "most",
+    "very",
+    "after",
+    "thing",
+    "our",
+    "just",
+    "name",
+    "good",
+    "sentence",
+    "man",
+    "think",
+    "say",
+    "great",
+    "where",
+    "help",
+    "through",
+    "much",
+    "before",
+    "line",
+    "right",
+    "too",
+    "mean",
+    "old",
+    "any",
+    "same",
+    "tell",
+    "boy",
+    "follow",
+    "came",
+    "want",
+    "show",
+    "also",
+    "around",
+    "form",
+    "three",
+    "small",
+    "set",
+    "put",
+    "end",
+    "does",
+    "another",
+    "well",
+    "large",
+    "must",
+    "big",
+    "even",
+    "such",
+    "because",
+    "turn",
+    "here",
+    "why",
+    "ask",
+    "went",
+    "men",
+    "read",
+    "need",
+    "land",
+    "different",
+    "home",
+    "move",
+    "try",
+    "kind",
+    "hand",
+    "picture",
+    "again",
+    "change",
+    "off",
+    "play",
+    "spell",
+    "air",
+    "away",
+    "animal",
+    "house",
+    "point",
+    "page",
+    "letter",
+    "mother",
+    "answer",
+    "found",
+    "study",
+    "still",
+    "learn",
+    "should",
+    "america",
+    "world",
+    "money",
+    "family",
+    "friend",
+    "happy",
+    "music",
+    "blue",
+    "red",
+    "green",
+    "black",
+    "white",
+    "orange",
+    "purple",
+    "silver",
+    "golden",
+    "winter",
+    "spring",
+    "autumn",
+    "january",
+    "april",
+    "june",
+    "july",
+    "august",
+    "october",
+    "december",
+    "monday",
+    "friday",
+    "sunday",
+    "dog",
+    "cat",
+    "tiger",
+    "lion",
+    "bear",
+    "eagle",
+    "apple",
+    "banana",
+    "cherry",
+    "coffee",
+    "chocolate",
+    "pizza",
+    "beer",
+    "game",
+    "star",
+    "moon",
+    "sun",
+    "fire",
+    "angel",
+    "devil",
+    "heart",
+    "magic",
+    "power",
+    "king",
+    "queen",
+    "prince",
+    "knight",
+    "dream",
+    "life",
+];
+
+// The rows of a US keyboard, for runs like qwer or 7654
+const KEYBOARD: &[&str] = &["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+// The weakest kind of pattern found, for the warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    Common,
+    FileName,
+    Sequence,
+    Repeat,
+    Keyboard,
+    Year,
+    Short,
+}
+
+impl Pattern {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Pattern::Common => "it is built on a common password or word",
+            Pattern::FileName => "it contains the name of a file it encrypts",
+            Pattern::Sequence => "it contains a sequence like abc or 9876",
+            Pattern::Repeat => "it contains repeated characters or patterns",
+            Pattern::Keyboard => "it contains a run of keys on the keyboard",
+            Pattern::Year => "it contains a year",
+            Pattern::Short => "it is short",
+        }
+    }
+}
+
+pub struct Estimate {
+    pub guesses: f64,
+    pub score: u8,
+    pub pattern: Pattern, // What made it guessable
+}
+
+impl Estimate {
+    // About how long an offline attacker needs to guess it, at this Argon2id cost
+    pub fn crack_time(&self, params: KdfParams) -> String {
+        let bytes_per_guess = 2.0 * 1024.0 * params.memory as f64 * params.iterations as f64;
+        let seconds = self.guesses / 2.0 * bytes_per_guess / ATTACKER_BANDWIDTH;
+        let units = [
+            ("minute", 60.0),
+            ("hour", 60.0 * 60.0),
+            ("day", 24.0 * 60.0 * 60.0),
+            ("month", 31.0 * 24.0 * 60.0 * 60.0),
+            ("year", 365.0 * 24.0 * 60.0 * 60.0),
+        ];
+        if seconds < 60.0 {
+            return "less than a minute".to_string();
+        }
+        if seconds >= 100.0 * 365.0 * 24.0 * 60.0 * 60.0 {
+            return "centuries".to_string();
+        }
+        let (unit, length) = units
+            .iter()
+            .rev()
+            .find(|(_, length)| seconds >= *length)
+            .expect("at least a minute");
+        let count = (seconds / length).round() as u64;
+        format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+    }
+}
+
+// The guesses for a split of the start of the password, and its weakest pattern with the guesses that takes
+type Split = (f64, Option<(f64, Pattern)>);
+
+// A pattern found at chars[start..end], and the guesses it takes
+struct Match {
+    start: usize,
+    end: usize,
+    guesses: f64,
+    pattern: Pattern,
+}
+
+// Estimate how many guesses `password` takes, knowing the names of the files it will encrypt
+pub fn estimate(password: &str, file_names: &[&str]) -> Estimate {
+    let chars: Vec<char> = password.chars().take(MAX_LEN).collect();
+    let mut matches = Vec::new();
+    dictionary_matches(&chars, file_names, &mut matches);
+    sequence_matches(&chars, &mut matches);
+    repeat_matches(&chars, &mut matches);
+    keyboard_matches(&chars, &mut matches);
+    year_matches(&chars, &mut matches);
+
+    // best[end][count]: the fewest guesses for chars[..end] split into `count` patterns, with the guesses and kind
+    // of its weakest pattern
+    let n = chars.len();
+    let mut best: Vec<Vec<Option<Split>>> = vec![vec![None; n + 1]; n + 1];
+    best[0][0] = Some((1.0, None));
+    for end in 1..=n {
+        for start in 0..end {
+            // Anything can be guessed character by character, at ten guesses each like zxcvbn
+            let brute_force = 10f64.powi((end - start) as i32).max(11.0);
+            let found = matches
+                .iter()
+                .filter(|m| m.start == start && m.end == end)
+                .map(|m| (m.guesses, Some(m.pattern)));
+            for (guesses, pattern) in found.chain([(brute_force, None)]) {
+                for count in 0..end {
+                    let Some((before, weakest)) = best[start][count] else {
+                        continue;
+                    };
+                    let total = before * guesses;
+                    let weakest = match (weakest, pattern) {
+                        (Some((least, _)), _) if least <= guesses => weakest,
+                        (_, Some(pattern)) => Some((guesses, pattern)),
+                        _ => weakest,
+                    };
+                    if best[end][count + 1].is_none_or(|(current, _)| total < current) {
+                        best[end][count + 1] = Some((total, weakest));
+                    }
+                }
+            }
+        }
+    }
+    let (guesses, pattern) = (1..=n)
+        .filter_map(|count| {
+            let (product, weakest) = best[n][count]?;
+            let factorial: f64 = (1..=count).map(|k| k as f64).product();
+            let guesses = factorial * product + PATTERN_PENALTY.powi(count as i32 - 1);
+            Some((guesses, weakest.map(|(_, pattern)| pattern)))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .unwrap_or((1.0, None));
+    let score = match guesses {
+        g if g < 1e3 + 5.0 => 0,
+        g if g < 1e6 + 5.0 => 1,
+        g if g < 1e8 + 5.0 => 2,
+        g if g < 1e10 + 5.0 => 3,
+        _ => 4,
+    };
+    Estimate {
+        guesses,
+        score,
+        pattern: pattern.unwrap_or(Pattern::Short),
+    }
+}
+
+// Common words and file names, as typed, reversed or with l33t substitutions, in any case
+fn dictionary_matches(chars: &[char], file_names: &[&str], matches: &mut Vec<Match>) {
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    if lower.len() != chars.len() {
+        return; // A character whose lowercase is longer; positions wouldn't line up
+    }
+    let unleet: Vec<char> = lower.iter().map(|&c| unleet(c)).collect();
+    let names: Vec<String> = file_names
+        .iter()
+        .filter_map(|name| std::path::Path::new(name).file_stem()?.to_str())
+        .map(str::to_lowercase)
+        .filter(|name| name.chars().count() >= 3)
+        .collect();
+    let words = COMMON
+        .iter()
+        .enumerate()
+        .map(|(rank, word)| (*word, (rank + 1) as f64, Pattern::Common))
+        .chain(
+            names
+                .iter()
+                .map(|name| (name.as_str(), 1.0, Pattern::FileName)),
+        );
+    for (word, rank, pattern) in words {
+        let word: Vec<char> = word.chars().collect();
+        let reversed: Vec<char> = word.iter().rev().copied().collect();
+        for start in 0..chars.len() {
+            let end = start + word.len();
+            if end > chars.len() {
+                break;
+            }
+            let typed = &chars[start..end];
+            let mut guesses = match () {
+                _ if lower[start..end] == word[..] => rank,
+                _ if lower[start..end] == reversed[..] => rank * 2.0,
+                _ if unleet[start..end] == word[..] => rank * 2.0,
+                _ => continue,
+            };
+            // Capitalized or all uppercase are tried early, other mixes later
+            if typed.iter().any(|c| c.is_uppercase()) {
+                let capitalized =
+                    typed[0].is_uppercase() && typed[1..].iter().all(|c| !c.is_uppercase());
+                let upper = typed.iter().all(|c| !c.is_lowercase());
+                guesses *= if capitalized || upper {
+                    2.0
+                } else {
+                    2f64.powi(word.len() as i32 / 2)
+                };
+            }
+            matches.push(Match {
+                start,
+                end,
+                guesses,
+                pattern,
+            });
+        }
+    }
+}
+
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '8' => 'b',
+        '(' | '{' | '[' | '<' => 'c',
+        '3' => 'e',
+        '6' | '9' => 'g',
+        '1' | '!' | '|' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '+' | '7' => 't',
+        '2' => 'z',
+        c => c,
+    }
+}
+
+// Three or more characters that go up or down one at a time, like abc, 4567 or zyx
+fn sequence_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let mut start = 0;
+    while start + 2 < chars.len() {
+        let step = chars[start + 1] as i64 - chars[start] as i64;
+        let mut end = start + 1;
+        while end < chars.len() && chars[end] as i64 - chars[end - 1] as i64 == step {
+            end += 1;
+        }
+        if step.abs() == 1 && end - start >= 3 {
+            let first = chars[start].to_ascii_lowercase();
+            let base = match first {
+                'a' | 'z' | '0' | '1' | '9' => 4.0,
+                c if c.is_ascii_digit() => 10.0,
+                _ => 26.0,
+            };
+            let direction = if step < 0 { 2.0 } else { 1.0 };
+            matches.push(Match {
+                start,
+                end,
+                guesses: base * direction * (end - start) as f64,
+                pattern: Pattern::Sequence,
+            });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+}
+
+// A character or a short block repeated, like aaaa or abab
+fn repeat_matches(chars: &[char], matches: &mut Vec<Match>) {
+    for block in 1..=4 {
+        for start in 0..chars.len() {
+            let mut end = start + block;
+            while end + block <= chars.len()
+                && chars[end..end + block] == chars[start..start + block]
+            {
+                end += block;
+            }
+            let count = (end - start) / block;
+            if count >= 2 && end - start >= 3 {
+                let block_guesses = 10f64.powi(block as i32).max(11.0);
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses: block_guesses * count as f64,
+                    pattern: Pattern::Repeat,
+                });
+            }
+        }
+    }
+}
+
+// Four or more neighboring keys of one row, either way
+fn keyboard_matches(chars: &[char], matches: &mut Vec<Match>) {
+    // Keyboard rows are ASCII, so byte positions are character positions in what is checked
+    if !chars.iter().all(char::is_ascii) {
+        return;
+    }
+    let lower: String = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    for row in KEYBOARD {
+        let reversed: String = row.chars().rev().collect();
+        for keys in [*row, reversed.as_str()] {
+            for start in 0..chars.len() {
+                let len = (4..=chars.len() - start)
+                    .rev()
+                    .find(|len| keys.contains(&lower[start..start + len]));
+                if let Some(len) = len {
+                    matches.push(Match {
+                        start,
+                        end: start + len,
+                        guesses: 40.0 * len as f64,
+                        pattern: Pattern::Keyboard,
+                    });
+                }
+            }
+        }
+    }
+}
+
+// Years from 1900 to 2099
+fn year_matches(chars: &[char], matches: &mut Vec<Match>) {
+    for start in 0..chars.len().saturating_sub(3) {
+        let digits: String = chars[start..start + 4].iter().collect();
+        if matches!(digits.parse::<u32>(), Ok(1900..=2099)) {
+            matches.push(Match {
+                start,
+                end: start + 4,
+                guesses: 200.0,
+                pattern: Pattern::Year,
+            });
+        }
+    }
+}
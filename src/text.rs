@@ -0,0 +1,187 @@
+// `encrypt-text` and `decrypt-text`: short secrets shared without a file on disk. encrypt-text seals a text given on
+// the command line, piped to stdin or, with --clipboard, copied to the clipboard, and prints it as ASCII armor to
+// paste into a message; decrypt-text reads that armor back the same ways and prints the text. With --clipboard the
+// result goes onto the clipboard instead of stdout, through the platform's own tools: pbcopy and pbpaste on macOS,
+// PowerShell on Windows, and wl-copy and wl-paste, xclip or xsel elsewhere, whichever is installed. The clipboard
+// tools keep serving what was copied after encryptor exits. With --qr the text is encrypted into a QR code instead
+// (see the qr module), and decrypt-text also takes the base64 line scanned from one.
+use crate::qr;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use encryptor::encoding::Armor;
+use encryptor::header::Header;
+use encryptor::{EncryptError, Encryptor};
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+// Where the text comes from and where the result goes
+pub struct Text {
+    pub argument: Option<String>, // The text itself, or the armor to decrypt; otherwise stdin is read
+    pub clipboard: bool,
+    pub qr: Option<qr::Target>, // Encrypt into a QR code instead of armor
+}
+
+impl Text {
+    // Whether the result is printed on stdout, rather than put onto the clipboard or into an image
+    pub fn prints(&self) -> bool {
+        !self.clipboard && !matches!(self.qr, Some(qr::Target::Png(_)))
+    }
+}
+
+// Encrypt or decrypt the text, returning what to tell the user once the result is on the clipboard or in an image
+pub fn run(
+    encryptor: Encryptor,
+    decrypt: bool,
+    text: &Text,
+) -> Result<Option<String>, EncryptError> {
+    let input = match (&text.argument, text.clipboard) {
+        (Some(argument), _) => argument.clone().into_bytes(),
+        (None, true) => paste()?.into_bytes(),
+        (None, false) => {
+            let mut input = Vec::new();
+            io::stdin().lock().read_to_end(&mut input)?;
+            input
+        }
+    };
+    let output = match (decrypt, &text.qr) {
+        (true, _) => encryptor.decrypt_bytes(&scanned(&input).unwrap_or(input))?,
+        (false, None) => encryptor
+            .with_encoder(Arc::new(Armor))
+            .encrypt_bytes(&input)?,
+        (false, Some(target)) => {
+            let payload = STANDARD.encode(encryptor.encrypt_bytes(&input)?);
+            match target {
+                qr::Target::Terminal => {
+                    qr::terminal(&payload, io::stdout().is_terminal())?.into_bytes()
+                }
+                qr::Target::Png(path) => {
+                    qr::png(&payload, path)?;
+                    return Ok(Some(format!("Wrote the QR code to {}", path)));
+                }
+            }
+        }
+    };
+    if text.clipboard {
+        let output = String::from_utf8(output).map_err(|_| {
+            invalid("The decrypted contents aren't text, so they can't go onto the clipboard")
+        })?;
+        copy(&output)?;
+        return Ok(Some(match decrypt {
+            false => "Copied the encrypted text to the clipboard".to_string(),
+            true => "Copied the decrypted text to the clipboard".to_string(),
+        }));
+    }
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&output)?;
+    // A text typed without a newline shouldn't leave the prompt at the end of it
+    if !output.ends_with(b"\n") && io::stdout().is_terminal() {
+        stdout.write_all(b"\n")?;
+    }
+    stdout.flush()?;
+    Ok(None)
+}
+
+// The encrypted file in `input`, when it is the base64 line of a scanned QR code rather than armor
+fn scanned(input: &[u8]) -> Option<Vec<u8>> {
+    let line: String = std::str::from_utf8(input)
+        .ok()?
+        .split_whitespace()
+        .collect();
+    let bytes = STANDARD.decode(line).ok()?;
+    Header::parse(&bytes).is_ok().then_some(bytes)
+}
+
+// The commands that put text onto the clipboard and read it back, in the order they are tried
+#[cfg(target_os = "macos")]
+fn tools() -> Vec<(&'static [&'static str], &'static [&'static str])> {
+    vec![(&["pbcopy"], &["pbpaste"])]
+}
+
+#[cfg(windows)]
+fn tools() -> Vec<(&'static [&'static str], &'static [&'static str])> {
+    vec![(
+        &[
+            "powershell",
+            "-NoProfile",
+            "-Command",
+            "$input | Set-Clipboard",
+        ],
+        &["powershell", "-NoProfile", "-Command", "Get-Clipboard -Raw"],
+    )]
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn tools() -> Vec<(&'static [&'static str], &'static [&'static str])> {
+    let mut tools: Vec<(&'static [&'static str], &'static [&'static str])> = Vec::new();
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push((&["wl-copy"], &["wl-paste", "--no-newline"]));
+    }
+    if std::env::var_os("DISPLAY").is_some() {
+        tools.push((
+            &["xclip", "-selection", "clipboard"],
+            &["xclip", "-selection", "clipboard", "-out"],
+        ));
+        tools.push((
+            &["xsel", "--clipboard", "--input"],
+            &["xsel", "--clipboard", "--output"],
+        ));
+    }
+    tools
+}
+
+// Run the first clipboard tool that is installed, giving it `input` on stdin, and return what it printed. Only
+// pasting reads its output: xclip and xsel go on serving a copy in the background, holding on to their stdout.
+fn clipboard(paste: bool, input: &str) -> Result<String, EncryptError> {
+    let piped = |piped: bool| match piped {
+        true => Stdio::piped(),
+        false => Stdio::null(),
+    };
+    for (copy_command, paste_command) in tools() {
+        let command = match paste {
+            true => paste_command,
+            false => copy_command,
+        };
+        let [program, args @ ..] = command else {
+            continue;
+        };
+        let child = Command::new(program)
+            .args(args)
+            .stdin(piped(!paste))
+            .stdout(piped(paste))
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            child => child?,
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(EncryptError::IoError(io::Error::other(format!(
+                "{} failed ({})",
+                program, output.status
+            ))));
+        }
+        return String::from_utf8(output.stdout)
+            .map_err(|_| invalid("The clipboard doesn't hold text"));
+    }
+    Err(EncryptError::IoError(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "No clipboard tool found: install wl-clipboard, xclip or xsel, or leave out --clipboard",
+    )))
+}
+
+fn invalid(message: &str) -> EncryptError {
+    EncryptError::IoError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+fn paste() -> Result<String, EncryptError> {
+    clipboard(true, "")
+}
+
+fn copy(text: &str) -> Result<(), EncryptError> {
+    clipboard(false, text).map(|_| ())
+}
@@ -0,0 +1,208 @@
+// `--debug-transcript <path>`: a JSON record of what the tool decided and did during one run, to attach to bug
+// reports. It is written when the command finishes, whether it succeeded or not:
+//
+//   {"transcript": 1, "version": "...", "command": "encrypt", "started_at": 1760000000, "events": [
+//     {"event": "settings", "elapsed_ms": 0.1, "algorithm": "aes256gcm", "kdf": {...}, ...},
+//     {"event": "log", "elapsed_ms": 25.3, "level": "debug", "message": "Derived a key with Argon2id ..."},
+//     {"event": "file", "elapsed_ms": 48.0, "operation": "encrypt", "input": "a.txt", "status": "ok", ...},
+//     {"event": "finished", "elapsed_ms": 48.2, "exit_code": 0}]}
+//
+// Secrets are kept out by construction: events are built from parsed settings, file paths, sizes, timings, error
+// codes and the log messages of this crate, never from the command line as typed, file contents or keys. On top
+// of that, every password and key loaded during the run is remembered, and any string in the transcript that
+// contains one (or a key's hex or base64 form) has it replaced with "[redacted]" before the file is written.
+use crate::{batch, redact, Options, Secret};
+use base64::Engine;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const FORMAT_VERSION: u32 = 1;
+const REDACTED: &str = "[redacted]";
+
+struct Transcript {
+    path: String,
+    command: String,
+    started_at: u64,
+    start: Instant,
+    events: Vec<Value>,
+    secrets: Vec<String>, // Never written, only searched for
+}
+
+// The transcript of this run, when --debug-transcript was given
+static TRANSCRIPT: Mutex<Option<Transcript>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<Transcript>> {
+    // A worker that panicked while recording leaves the events consistent
+    TRANSCRIPT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Start recording the run of `command`, to be written to `path` by `finish`
+pub fn start(path: &str, command: &str) {
+    *lock() = Some(Transcript {
+        path: path.to_string(),
+        command: command.to_string(),
+        started_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs()),
+        start: Instant::now(),
+        events: Vec::new(),
+        secrets: Vec::new(),
+    });
+}
+
+// Whether a transcript is being recorded
+pub fn active() -> bool {
+    lock().is_some()
+}
+
+// Record the event `event`, with the members of the `fields` object
+pub fn record(event: &str, fields: Value) {
+    if let Some(transcript) = lock().as_mut() {
+        let mut members = Map::new();
+        members.insert("event".to_string(), json!(event));
+        members.insert(
+            "elapsed_ms".to_string(),
+            json!((transcript.start.elapsed().as_secs_f64() * 1e6).round() / 1000.0),
+        );
+        if let Value::Object(fields) = fields {
+            members.extend(fields);
+        }
+        transcript.events.push(Value::Object(members));
+    }
+}
+
+// Record a log message
+pub fn log(record: &log::Record) {
+    self::record(
+        "log",
+        json!({
+            "level": record.level().as_str().to_lowercase(),
+            "message": record.args().to_string(),
+        }),
+    );
+}
+
+// Remember a password, so that it is redacted wherever it would appear
+pub fn secret(password: &str) {
+    if let Some(transcript) = lock().as_mut() {
+        if !password.is_empty() {
+            transcript.secrets.push(password.to_string());
+        }
+    }
+}
+
+// Remember a key, so that its hex and base64 forms are redacted wherever they would appear
+pub fn secret_key(key: &[u8; 32]) {
+    secret(&hex::encode(key));
+    secret(&hex::encode_upper(key));
+    secret(&base64::engine::general_purpose::STANDARD.encode(key));
+    secret(&base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key));
+}
+
+// Record how the run ended and write the transcript. Failing to write it doesn't change the exit status.
+pub fn finish(exit_code: i32) {
+    record("finished", json!({ "exit_code": exit_code }));
+    let Some(transcript) = lock().take() else {
+        return;
+    };
+    let mut document = json!({
+        "transcript": FORMAT_VERSION,
+        "version": env!("CARGO_PKG_VERSION"),
+        "command": transcript.command,
+        "started_at": transcript.started_at,
+        "events": transcript.events,
+    });
+    scrub(&mut document, &transcript.secrets);
+    redact::value(&mut document);
+    let written = serde_json::to_string_pretty(&document)
+        .map_err(|err| err.to_string())
+        .and_then(|text| fs::write(&transcript.path, text + "\n").map_err(|err| err.to_string()));
+    if let Err(err) = written {
+        eprintln!(
+            "Cannot write the debug transcript {}: {}",
+            transcript.path, err
+        );
+    }
+}
+
+// Replace every secret in the strings of `value` with "[redacted]"
+fn scrub(value: &mut Value, secrets: &[String]) {
+    match value {
+        Value::String(text) => {
+            for secret in secrets {
+                if text.contains(secret.as_str()) {
+                    *text = text.replace(secret.as_str(), REDACTED);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| scrub(value, secrets)),
+        Value::Object(members) => members.values_mut().for_each(|value| scrub(value, secrets)),
+        _ => {}
+    }
+}
+
+// The settings a file command runs with, for the "settings" event. The secret is only described by its kind.
+pub fn settings(options: &Options, secret: &Secret, files: usize) -> Value {
+    let secret = match secret {
+        Secret::Password(_, recipients) if recipients.is_empty() => "password",
+        Secret::Password(..) => "password and recipients",
+        Secret::Key(_) => "key",
+        Secret::Identity(_) => "identity",
+        Secret::Recipients(_) => "recipients",
+        Secret::Certificates(_) => "certificates",
+        Secret::Grants(_) => "grants",
+        Secret::Fido2(_) => "fido2",
+    };
+    json!({
+        "files": files,
+        "secret": secret,
+        "team": options.team,
+        "recipients": options.recipients.len() + options.ssh_recipients.len(),
+        "algorithm": options.algorithm.name(),
+        "kdf": {
+            "memory": options.kdf_params.memory,
+            "iterations": options.kdf_params.iterations,
+            "target_ms": options.kdf_target.map(|target| target.as_millis() as u64),
+        },
+        "compression": {
+            "codec": options.compression.codec.name(),
+            "level": options.compression.level,
+            "dictionary": options.dict_path,
+        },
+        "format": options.format.name(),
+        "encoding": options.encoder.name(),
+        "stream_threshold": options.stream_threshold,
+        "chunk_size": options.chunk_size,
+        "jobs": options.jobs.unwrap_or_else(batch::default_jobs),
+        "bind_metadata": options.bind_metadata,
+        "header_backup": options.header_backup,
+        "checksum": options.checksum,
+        "padding": options.padding.map(|padding| padding.name()),
+        "wrap_keys": options.wrap_keys,
+        "key_id": options.key_id,
+        "encrypt_names": options.encrypt_names,
+        "in_place": options.in_place,
+        "out": options.out,
+        "force": options.force,
+        "auto_rename": options.auto_rename,
+        "resume": options.resume,
+        "shred": options.shred,
+        "remove_to_trash": options.remove_to_trash,
+        "archive": options.archive,
+        "manifest": options.manifest,
+        "audit_log": options.audit_log,
+        "extract": options.extract,
+        "mmap": options.mmap,
+        "io_buffer": options.io_buffer,
+        "retry": {
+            "retries": options.retry.retries,
+            "backoff_ms": options.retry.backoff.as_millis() as u64,
+        },
+        "json": options.json,
+        "grant": options.grant,
+    })
+}
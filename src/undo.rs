@@ -0,0 +1,237 @@
+// `encrypt --remove-to-trash` moves each original to the trash of the desktop (the freedesktop.org trash on Linux
+// and BSD, the Recycle Bin on Windows, the Finder's Trash on macOS) once its output is written, instead of leaving it
+// or shredding it, and `encryptor undo` puts the originals of the last such run back and removes their outputs.
+// Each file is recorded in <config>/undo.json as soon as its original is in the trash:
+//
+//   {"session": 4242, "files": [{"input": "/home/me/a.txt", "output": "/home/me/a.txt.enc", "output_len": 1234}]}
+//
+// A path that isn't valid UTF-8 is recorded escaped like a file name in a header (see `encryptor::names`), with
+// "input_escaped" or "output_escaped": true. Only the last run can be undone, since the next one replaces the record, and only from the login
+// session it was run in (the session ID of the terminal on Unix), so an undo in another terminal or after logging in
+// again doesn't bring back files that were deliberately thrown away since. An output that changed since it was
+// written is left alone with its original, and so is an original whose path was taken again.
+use encryptor::{names, EncryptError};
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// What one run moved to the trash
+pub struct Record {
+    path: PathBuf,
+    entry: Mutex<Value>,
+}
+
+impl Record {
+    // Start the record of a new run, replacing that of the last one
+    pub fn start() -> Result<Self, String> {
+        let path = record_path()?;
+        let entry = json!({"session": session(), "files": []});
+        save(&path, &entry).map_err(|err| format!("Cannot write {}: {}", path.display(), err))?;
+        Ok(Record {
+            path,
+            entry: Mutex::new(entry),
+        })
+    }
+
+    // Move the original `file_path` of `output_path` to the trash, and record both. The output is kept when the
+    // original can't be moved, and the file reported as failed.
+    pub fn trash(&self, file_path: &Path, output_path: &Path) -> Result<(), EncryptError> {
+        let file_path = std::path::absolute(file_path)?;
+        let output_path = std::path::absolute(output_path)?;
+        let output_len = fs::metadata(&output_path)?.len();
+        trash::delete(&file_path).map_err(|err| {
+            io::Error::other(format!(
+                "the original couldn't be moved to the trash: {}",
+                err
+            ))
+        })?;
+        let mut entry = self.entry.lock().unwrap_or_else(|e| e.into_inner());
+        let mut file = json!({"output_len": output_len});
+        record_path_in(&mut file, "input", &file_path);
+        record_path_in(&mut file, "output", &output_path);
+        if let Some(files) = entry["files"].as_array_mut() {
+            files.push(file);
+        }
+        save(&self.path, &entry)?;
+        Ok(())
+    }
+}
+
+// `encryptor undo`: put back the originals the last --remove-to-trash run moved to the trash, and remove their
+// outputs. The files that can't be are kept in the record to try again.
+pub fn undo() -> Result<String, String> {
+    let path = record_path()?;
+    let entry: Value = match fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str(&text)
+            .map_err(|_| format!("{} is not a record of files to undo", path.display()))?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(
+                "Nothing to undo: no `encrypt --remove-to-trash` run was recorded".to_string(),
+            )
+        }
+        Err(err) => return Err(format!("Cannot read {}: {}", path.display(), err)),
+    };
+    if entry["session"] != json!(session()) {
+        return Err(
+            "The last `encrypt --remove-to-trash` run was in another session, so its originals are only put back from the trash by hand"
+                .to_string(),
+        );
+    }
+    let files = entry["files"].as_array().cloned().unwrap_or_default();
+
+    let mut failed = Vec::new();
+    let mut messages = Vec::new();
+    for file in &files {
+        let (Some(file_path), Some(output_path), Some(output_len)) = (
+            read_path(file, "input"),
+            read_path(file, "output"),
+            file["output_len"].as_u64(),
+        ) else {
+            return Err(format!(
+                "{} is not a record of files to undo",
+                path.display()
+            ));
+        };
+        match put_back(&file_path, &output_path, output_len) {
+            Ok(()) => messages.push(format!("Restored {}", file_path.display())),
+            Err(reason) => {
+                messages.push(format!(
+                    "Cannot restore {}: {}",
+                    file_path.display(),
+                    reason
+                ));
+                failed.push(file.clone());
+            }
+        }
+    }
+    let restored = files.len() - failed.len();
+    match failed.is_empty() {
+        true => fs::remove_file(&path)
+            .map_err(|err| format!("Cannot remove {}: {}", path.display(), err))?,
+        false => {
+            let mut entry = entry;
+            entry["files"] = json!(failed);
+            save(&path, &entry)
+                .map_err(|err| format!("Cannot write {}: {}", path.display(), err))?;
+        }
+    }
+    messages.push(format!(
+        "Restored {} of {} originals and removed their outputs",
+        restored,
+        files.len()
+    ));
+    match failed.is_empty() {
+        true => Ok(messages.join("\n")),
+        false => Err(messages.join("\n")),
+    }
+}
+
+// Restore the original `file_path` from the trash, then remove its output, unless the output changed or the path
+// was taken again since
+fn put_back(file_path: &Path, output_path: &Path, output_len: u64) -> Result<(), String> {
+    match fs::metadata(output_path) {
+        Ok(metadata) if metadata.len() == output_len => {}
+        Ok(_) => {
+            return Err(format!(
+                "{} changed since it was written",
+                output_path.display()
+            ))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(format!("{}: {}", output_path.display(), err)),
+    }
+    if fs::symlink_metadata(file_path).is_ok() {
+        return Err("a file of that name exists again".to_string());
+    }
+    restore(file_path)?;
+    match fs::remove_file(output_path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(format!(
+            "it is back, but its output {} couldn't be removed: {}",
+            output_path.display(),
+            err
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Restore the item the trash holds for `file_path`, the last one moved there if it holds several
+#[cfg(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+))]
+fn restore(file_path: &Path) -> Result<(), String> {
+    let items =
+        trash::os_limited::list().map_err(|err| format!("cannot list the trash: {}", err))?;
+    let item = items
+        .into_iter()
+        .filter(|item| item.original_path() == file_path)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| "it is no longer in the trash".to_string())?;
+    trash::os_limited::restore_all([item]).map_err(|err| err.to_string())
+}
+
+// The Finder restores items only through its own user interface
+#[cfg(not(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+)))]
+fn restore(_: &Path) -> Result<(), String> {
+    Err("only the Finder puts items back from the Trash on macOS; use Put Back there".to_string())
+}
+
+// Record `path` under `key` of `file`, escaped if it isn't valid UTF-8
+fn record_path_in(file: &mut Value, key: &str, path: &Path) {
+    let (recorded, escaped) = names::record(path.as_os_str());
+    file[key] = json!(recorded);
+    if escaped {
+        file[format!("{}_escaped", key)] = json!(true);
+    }
+}
+
+fn read_path(file: &Value, key: &str) -> Option<PathBuf> {
+    let recorded = file[key].as_str()?;
+    match file[format!("{}_escaped", key)].as_bool() {
+        Some(true) => names::unescape(recorded).map(PathBuf::from),
+        _ => Some(PathBuf::from(recorded)),
+    }
+}
+
+// Write the record at `path`, aside and renamed over it, so a crash leaves the old one or the new one
+fn save(path: &Path, entry: &Value) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = names::with_suffix(path, ".partial");
+    fs::write(&partial, format!("{}\n", entry))?;
+    fs::rename(&partial, path)
+}
+
+// Where the record of the last run is kept
+fn record_path() -> Result<PathBuf, String> {
+    Ok(crate::config_dir()?.join("undo.json"))
+}
+
+// The login session this process belongs to: the session ID on Unix, which every process started from one terminal
+// shares. Elsewhere there is no such thing, and any run can be undone.
+#[cfg(unix)]
+fn session() -> Option<u32> {
+    // getsid(0) can't fail for the calling process
+    Some(unsafe { libc::getsid(0) } as u32)
+}
+
+#[cfg(not(unix))]
+fn session() -> Option<u32> {
+    None
+}
@@ -0,0 +1,103 @@
+// Sizes, durations and times as every command shows them: for people by default (`--human`), as 12.1 KiB, 850.0 ms,
+// 3 days or 2026-10-16 12:27:40 UTC, or for scripts with `--raw`, as 12345 bytes, 0.85 s, 259200 s or the seconds
+// since the Unix epoch. The mode is chosen once for the whole run, before any output, and read by the inspect,
+// stats, bench, dry run, report and progress output alike. JSON (--json, `stats local --json`, a .json --report)
+// always carries the raw numbers.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+static RAW: AtomicBool = AtomicBool::new(false);
+
+// Show raw numbers instead of human-readable ones for the rest of the run
+pub fn set_raw(raw: bool) {
+    RAW.store(raw, Ordering::Relaxed);
+}
+
+pub fn raw() -> bool {
+    RAW.load(Ordering::Relaxed)
+}
+
+// A byte count, e.g. "12.1 KiB" (in the largest binary unit that keeps it at least 1) or "12345 bytes"
+pub fn size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    if raw() {
+        return format!("{} bytes", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
+
+// How fast `bytes` were worked through in `time`, e.g. "310.0 MiB/s" or "325058560 bytes/s"
+pub fn speed(bytes: u64, time: Duration) -> String {
+    format!(
+        "{}/s",
+        size((bytes as f64 / time.as_secs_f64().max(f64::EPSILON)) as u64)
+    )
+}
+
+// A length of time, e.g. "12.3 µs", "850.0 ms", "4.2 s", "3 min 20 s", "2 h 5 min" or "3 days", or in seconds
+pub fn duration(time: Duration) -> String {
+    if raw() {
+        return format!("{} s", time.as_secs_f64());
+    }
+    let secs = time.as_secs();
+    match time.as_nanos() {
+        nanos if nanos < 10_000 => format!("{} ns", nanos),
+        nanos if nanos < 10_000_000 => format!("{:.1} µs", nanos as f64 / 1e3),
+        nanos if nanos < 1_000_000_000 => format!("{:.1} ms", nanos as f64 / 1e6),
+        _ if secs < 60 => format!("{:.1} s", time.as_secs_f64()),
+        _ if secs < 3600 => format!("{} min {} s", secs / 60, secs % 60),
+        _ if secs < SECONDS_PER_DAY => format!("{} h {} min", secs / 3600, secs / 60 % 60),
+        _ => match secs / SECONDS_PER_DAY {
+            1 => "1 day".to_string(),
+            days => format!("{} days", days),
+        },
+    }
+}
+
+// A time in seconds since the Unix epoch, as a UTC date and time or the number itself
+pub fn time(secs: u64) -> String {
+    match raw() {
+        true => secs.to_string(),
+        false => utc(secs),
+    }
+}
+
+// A time in seconds since the Unix epoch as a UTC date and time, e.g. "2026-10-16 12:27:40 UTC"
+pub fn utc(secs: u64) -> String {
+    let (days, time) = (secs / 86400, secs % 86400);
+    // Howard Hinnant's days_from_civil, inverted: shift the epoch to 0000-03-01 so leap days end each 400-year era
+    let days = days as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
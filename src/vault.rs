@@ -0,0 +1,212 @@
+// `vault init|set|get|list|rm`: named secrets such as API tokens and credentials, kept together in one encrypted
+// file instead of a tiny `.enc` file each. The vault is an ordinary encrypted file, sealed with a password or key like
+// any other, holding JSON:
+//
+//   {"vault": 1, "secrets": {"github-token": {"value": "...", "updated_at": 1760000000}, ...}}
+//
+// It is vault.enc in the config directory unless --vault names another file. Every change decrypts the whole vault
+// and seals it again under a fresh salt and nonce, with the cipher and Argon2id cost it was created with, through a
+// temporary file that only replaces it once complete. Two changes made at the same time may lose one of them.
+use crate::streamed;
+use encryptor::header::{Header, Kdf};
+use encryptor::{EncryptError, Encryptor};
+use rpassword::prompt_password;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+const FORMAT_VERSION: u64 = 1;
+
+// What to do with the vault
+pub enum Action {
+    Init { force: bool }, // Replace a vault that already exists
+    Set { name: String, value: Option<String> }, // Without a value, it is read from stdin or the terminal
+    Get { name: String },
+    List,
+    Remove { name: String },
+}
+
+// The vault the command works on, and what it does with it
+pub struct Vault {
+    pub path: Option<String>, // --vault, or else vault.enc in the config directory
+    pub action: Action,
+}
+
+// Carry out `action` on the vault at `path`, returning what to tell the user. `get` and `list` print what they read
+// on stdout instead.
+pub fn run(
+    encryptor: Encryptor,
+    path: &Path,
+    action: &Action,
+) -> Result<Option<String>, EncryptError> {
+    if let Action::Init { force } = action {
+        if path.exists() && !force {
+            return Err(EncryptError::IoError(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists; give --force to replace it, and every secret in it",
+                    path.display()
+                ),
+            )));
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        write(&encryptor, path, Map::new())?;
+        return Ok(Some(format!(
+            "Created an empty vault in {}",
+            path.display()
+        )));
+    }
+
+    let (encryptor, mut secrets) = open(encryptor, path)?;
+    let missing = |name: &str| {
+        EncryptError::IoError(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "The vault {} holds no secret named '{}'",
+                path.display(),
+                name
+            ),
+        ))
+    };
+    match action {
+        Action::Init { .. } => unreachable!("a new vault is written above"),
+        Action::Set { name, value } => {
+            let value = match value {
+                Some(value) => Zeroizing::new(value.clone()),
+                None => read_value(name)?,
+            };
+            let updated_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs());
+            let replaced = secrets
+                .insert(
+                    name.clone(),
+                    json!({ "value": value.as_str(), "updated_at": updated_at }),
+                )
+                .is_some();
+            write(&encryptor, path, secrets)?;
+            let done = if replaced { "Updated" } else { "Added" };
+            Ok(Some(format!("{} '{}' in {}", done, name, path.display())))
+        }
+        Action::Get { name } => {
+            let value = secrets
+                .get(name)
+                .and_then(|secret| secret["value"].as_str())
+                .ok_or_else(|| missing(name))?;
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(value.as_bytes())?;
+            // A secret read at the terminal shouldn't leave the prompt at the end of it
+            if io::stdout().is_terminal() {
+                stdout.write_all(b"\n")?;
+            }
+            stdout.flush()?;
+            Ok(None)
+        }
+        Action::List => {
+            let mut stdout = io::stdout().lock();
+            for name in secrets.keys() {
+                writeln!(stdout, "{}", name)?;
+            }
+            Ok(None)
+        }
+        Action::Remove { name } => {
+            secrets.remove(name).ok_or_else(|| missing(name))?;
+            write(&encryptor, path, secrets)?;
+            Ok(Some(format!("Removed '{}' from {}", name, path.display())))
+        }
+    }
+}
+
+// Decrypt the vault at `path`, returning its secrets and the Encryptor that seals it again the way it was sealed
+fn open(
+    encryptor: Encryptor,
+    path: &Path,
+) -> Result<(Encryptor, Map<String, Value>), EncryptError> {
+    let vault = fs::read(path).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "There is no vault at {}: create one with `vault init`",
+                path.display()
+            ),
+        ),
+        _ => err,
+    })?;
+    let (header, _) = Header::parse(&vault)?;
+    let plaintext = Zeroizing::new(encryptor.decrypt_bytes(&vault)?);
+    let mut document: Value = serde_json::from_slice(&plaintext)
+        .map_err(|_| invalid(format!("{} is not a vault", path.display())))?;
+    if document["vault"] != FORMAT_VERSION {
+        return Err(invalid(format!(
+            "{} is not a vault, or is from a newer version",
+            path.display()
+        )));
+    }
+    let Value::Object(secrets) = document["secrets"].take() else {
+        return Err(invalid(format!("The vault {} is damaged", path.display())));
+    };
+    let encryptor = encryptor.with_algorithm(header.algorithm);
+    let encryptor = match header.kdf {
+        Kdf::Argon2id => encryptor.with_kdf_params(header.kdf_params),
+        _ => encryptor,
+    };
+    Ok((encryptor, secrets))
+}
+
+// Seal `secrets` into the vault at `path`, replacing it once the new one is complete
+fn write(
+    encryptor: &Encryptor,
+    path: &Path,
+    secrets: Map<String, Value>,
+) -> Result<(), EncryptError> {
+    let document = Zeroizing::new(
+        json!({
+            "vault": FORMAT_VERSION,
+            "secrets": secrets,
+        })
+        .to_string(),
+    );
+    let sealed = encryptor.encrypt_bytes(document.as_bytes())?;
+    streamed::write(&sealed[..], path)?;
+    Ok(())
+}
+
+// The value of a secret to set, from a hidden prompt on the terminal or else everything piped to stdin, one trailing
+// newline left out
+fn read_value(name: &str) -> Result<Zeroizing<String>, EncryptError> {
+    let mut value = match io::stdin().is_terminal() {
+        true => Zeroizing::new(prompt_password(format!("Value of {}: ", name))?),
+        false => {
+            let mut value = Zeroizing::new(String::new());
+            io::stdin()
+                .lock()
+                .read_to_string(&mut value)
+                .map_err(|err| match err.kind() {
+                    io::ErrorKind::InvalidData => {
+                        invalid("The value piped to stdin isn't text".to_string())
+                    }
+                    _ => err.into(),
+                })?;
+            value
+        }
+    };
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    if value.is_empty() {
+        return Err(invalid("The value must not be empty".to_string()));
+    }
+    Ok(value)
+}
+
+fn invalid(message: String) -> EncryptError {
+    EncryptError::IoError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
@@ -0,0 +1,280 @@
+// `encrypt --split-size`: an encrypted file written as numbered volumes of at most a given size, `backup.enc.001`,
+// `backup.enc.002`..., for FAT32 media (4 GiB per file at most) or upload size limits. The volumes are one
+// encrypted stream cut into pieces with nothing added, so `cat backup.enc.* > backup.enc` gives a file that
+// decrypts like any other. `decrypt` and `verify` take the first volume (or the path before its number) and read
+// all of them in order as one stream: a missing, extra or cut-short volume is reported by name before anything is
+// decrypted, and volumes that were swapped or replaced fail to authenticate.
+//
+// Volumes are written through the library's streaming adapters, like s3:// objects: in chunks, uncompressed, with
+// no name or length in the header.
+use crate::interrupt;
+use crate::streamed::{self, from_io, Existing};
+use encryptor::{names, EncryptError, EncryptingWriter, Encryptor};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+// The name of volume `number` (from 1) of `path`
+fn volume(path: &Path, number: usize) -> PathBuf {
+    names::with_suffix(path, &format!(".{:03}", number))
+}
+
+// The path and number of the volume `file_path` names, if its last extension is a volume number
+fn volume_number(file_path: &Path) -> Option<(&Path, usize)> {
+    let (path, number) = names::split_extension(file_path.as_os_str());
+    let number = number.to_str()?.strip_prefix('.')?;
+    if number.len() < 3 || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    Some((Path::new(path), number.parse().ok()?))
+}
+
+// The path whose volumes `file_path` is about: it's the first volume, or doesn't exist but its first volume does
+pub fn split_path(file_path: &Path) -> Option<&Path> {
+    match volume_number(file_path) {
+        Some((path, 1)) => Some(path),
+        _ if !file_path.exists() && volume(file_path, 1).is_file() => Some(file_path),
+        _ => None,
+    }
+}
+
+// `files` without the later volumes of the split files whose first volume is among them, so that a shell glob
+// such as `backup.enc.*` decrypts the file once
+pub fn without_later_volumes(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let first: HashSet<PathBuf> = files
+        .iter()
+        .filter_map(|file_path| split_path(file_path))
+        .map(Path::to_path_buf)
+        .collect();
+    files
+        .into_iter()
+        .filter(|file_path| {
+            !volume_number(file_path)
+                .is_some_and(|(path, number)| number > 1 && first.contains(path))
+        })
+        .collect()
+}
+
+// Encrypt the file at `file_path` into volumes of at most `split_size` bytes of `out_path`, or of the usual name
+// inside it when it is a directory, or by default of `<file_path>.enc`. Volumes of an earlier output that this
+// one doesn't reach are removed. Returns the path of the first volume.
+pub fn encrypt(
+    encryptor: &Encryptor,
+    file_path: &Path,
+    out_path: Option<&Path>,
+    split_size: u64,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    let path = match out_path {
+        Some(out_path) if out_path.is_dir() => out_path.join(names::with_suffix(
+            Path::new(names::file_name(file_path)),
+            ".enc",
+        )),
+        Some(out_path) => out_path.to_path_buf(),
+        None => names::with_suffix(file_path, ".enc"),
+    };
+    // The first volume stands for the output: renamed, `<output> (1).001` and on are written
+    let first = streamed::claim(volume(&path, 1), existing)?;
+    let path = names::strip_extension(&first).to_path_buf();
+    let input = File::open(file_path)?;
+    let mut writer = EncryptingWriter::new(Writer::new(&path, split_size), encryptor)?;
+    io::copy(&mut interrupt::Reader(input), &mut writer).map_err(from_io)?;
+    let count = writer.finish()?.commit()?;
+    log::info!("Wrote {} in {} volumes", path.display(), count);
+    Ok(first)
+}
+
+// Decrypt the volumes of `path` (see split_path) to `out_path` like an s3:// object, returning the path written
+pub fn decrypt(
+    encryptor: &Encryptor,
+    path: &Path,
+    out_path: Option<&Path>,
+    several: bool,
+    existing: Existing,
+) -> Result<PathBuf, EncryptError> {
+    let volumes = Reader::open(path)?;
+    let output_path = streamed::output_path(names::file_name(path), out_path, several, existing)?;
+    streamed::decrypt(volumes, encryptor, &output_path)
+}
+
+// Check that the volumes of `path` decrypt, without writing the plaintext anywhere
+pub fn verify(encryptor: &Encryptor, path: &Path) -> Result<(), EncryptError> {
+    streamed::verify(Reader::open(path)?, encryptor)
+}
+
+// A Write that fills volumes of `split_size` bytes one after the other, each under a temporary name until commit
+// renames them all into place. Dropped before that, it removes them.
+struct Writer {
+    path: PathBuf,
+    split_size: u64,
+    // The temporary and final paths of the volumes so far, and how much of the last one is written
+    volumes: Vec<(PathBuf, PathBuf)>,
+    file: Option<File>,
+    written: u64,
+}
+
+impl Writer {
+    fn new(path: &Path, split_size: u64) -> Self {
+        Writer {
+            path: path.to_path_buf(),
+            split_size,
+            volumes: Vec::new(),
+            file: None,
+            written: 0,
+        }
+    }
+
+    // Sync the last volume and start the next
+    fn next_volume(&mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        let final_path = volume(&self.path, self.volumes.len() + 1);
+        let temp_path = names::with_suffix(&final_path, &format!(".{}.tmp", std::process::id()));
+        self.file = Some(File::create(&temp_path)?);
+        self.volumes.push((temp_path, final_path));
+        self.written = 0;
+        Ok(())
+    }
+
+    // Move the volumes into place and remove those an earlier, longer output left after them, returning how many
+    // there are
+    fn commit(mut self) -> io::Result<usize> {
+        if self.file.is_none() {
+            self.next_volume()?;
+        }
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        for (temp_path, final_path) in &self.volumes {
+            fs::rename(temp_path, final_path)?;
+        }
+        let count = self.volumes.len();
+        self.volumes.clear();
+        let mut stale = count + 1;
+        while fs::remove_file(volume(&self.path, stale)).is_ok() {
+            stale += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.file.is_none() || self.written == self.split_size {
+            self.next_volume()?;
+        }
+        let len = (buf.len() as u64).min(self.split_size - self.written) as usize;
+        let file = self.file.as_mut().expect("a volume was started");
+        let written = file.write(&buf[..len])?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        self.file = None;
+        for (temp_path, _) in &self.volumes {
+            let _ = fs::remove_file(temp_path);
+        }
+    }
+}
+
+// A Read over the volumes of a path, one after the other
+struct Reader {
+    volumes: std::vec::IntoIter<PathBuf>,
+    file: Option<File>,
+}
+
+impl Reader {
+    // Find the volumes of `path` and check that they make a whole: numbered from 1 with none missing, and all as
+    // large as the first but the last, which may be smaller
+    fn open(path: &Path) -> Result<Reader, EncryptError> {
+        let file_name = names::file_name(path);
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut numbers: Vec<usize> = fs::read_dir(dir)?
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                let (stem, number) = volume_number(Path::new(&name))?;
+                (stem.as_os_str() == file_name).then_some(number)
+            })
+            .collect();
+        numbers.sort_unstable();
+        let mut volumes = Vec::new();
+        for (index, number) in numbers.iter().enumerate() {
+            if *number != index + 1 {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is missing", volume(path, index + 1).display()),
+                )
+                .into());
+            }
+            volumes.push(volume(path, *number));
+        }
+        let sizes = volumes
+            .iter()
+            .map(|volume| Ok(fs::metadata(volume)?.len()))
+            .collect::<io::Result<Vec<u64>>>()?;
+        let Some(&split_size) = sizes.first() else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not found", volume(path, 1).display()),
+            )
+            .into());
+        };
+        for (number, size) in sizes.iter().enumerate().skip(1) {
+            let last = number == sizes.len() - 1;
+            if *size > split_size || (!last && *size < split_size) || *size == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} is {} bytes and {} is {}: one of them was cut short or doesn't belong",
+                        volumes[0].display(),
+                        split_size,
+                        volumes[number].display(),
+                        size
+                    ),
+                )
+                .into());
+            }
+        }
+        log::debug!("Reading {} volumes of {}", volumes.len(), path.display());
+        Ok(Reader {
+            volumes: volumes.into_iter(),
+            file: None,
+        })
+    }
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(file) = &mut self.file {
+                let read = file.read(buf)?;
+                if read > 0 || buf.is_empty() {
+                    return Ok(read);
+                }
+            }
+            match self.volumes.next() {
+                Some(path) => self.file = Some(File::open(path)?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
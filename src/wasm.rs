@@ -0,0 +1,77 @@
+//! Encrypting and decrypting byte buffers from JavaScript, for browser apps.
+//!
+//! Built for `wasm32-unknown-unknown` with `wasm-pack build --target web -- --no-default-features --features wasm`,
+//! this exports [`encrypt_bytes`] and friends as `encryptBytes`, `decryptBytes`, `encryptBytesWithKey` and
+//! `decryptBytesWithKey`, which take and return `Uint8Array`s. Their output is the same format as
+//! [`Encryptor::encrypt_bytes`], so what a page encrypts client-side decrypts with the command line tool, and the
+//! other way around:
+//!
+//! ```js
+//! import init, { encryptBytes, decryptBytes } from "./pkg/encryptor.js";
+//!
+//! await init();
+//! const sealed = encryptBytes("correct horse battery staple", new TextEncoder().encode("hello"));
+//! const plaintext = decryptBytes("correct horse battery staple", sealed);
+//! ```
+//!
+//! A failure throws an `Error` whose message starts with the stable code of the [`EncryptError`] (e.g.
+//! `ENC_AUTH_FAIL`). A browser has no filesystem, so the file functions of the library fail with an I/O error
+//! there; random numbers come from `crypto.getRandomValues` and the time recorded in headers from `Date.now()`.
+//! Deriving a key with the default Argon2id cost takes a while in wasm, so call these from a Web Worker to keep
+//! the page responsive.
+//!
+//! Available with the `wasm` feature.
+use crate::{EncryptError, Encryptor, KEY_LEN};
+use wasm_bindgen::prelude::*;
+
+/// Encrypt `plaintext` with a key derived from `password`, like [`Encryptor::encrypt_bytes`].
+#[wasm_bindgen(js_name = encryptBytes)]
+pub fn encrypt_bytes(password: &str, plaintext: &[u8]) -> Result<Vec<u8>, JsError> {
+    Encryptor::new(password)
+        .encrypt_bytes(plaintext)
+        .map_err(js_error)
+}
+
+/// Decrypt `data` with a key derived from `password`, like [`Encryptor::decrypt_bytes`].
+#[wasm_bindgen(js_name = decryptBytes)]
+pub fn decrypt_bytes(password: &str, data: &[u8]) -> Result<Vec<u8>, JsError> {
+    Encryptor::new(password)
+        .decrypt_bytes(data)
+        .map_err(js_error)
+}
+
+/// Encrypt `plaintext` with a raw 32-byte `key`, like [`Encryptor::from_key`].
+#[wasm_bindgen(js_name = encryptBytesWithKey)]
+pub fn encrypt_bytes_with_key(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, JsError> {
+    Encryptor::from_key(raw_key(key)?)
+        .encrypt_bytes(plaintext)
+        .map_err(js_error)
+}
+
+/// Decrypt `data` with a raw 32-byte `key`, like [`Encryptor::from_key`].
+#[wasm_bindgen(js_name = decryptBytesWithKey)]
+pub fn decrypt_bytes_with_key(key: &[u8], data: &[u8]) -> Result<Vec<u8>, JsError> {
+    Encryptor::from_key(raw_key(key)?)
+        .decrypt_bytes(data)
+        .map_err(js_error)
+}
+
+// The time since the Unix epoch, from JavaScript's clock: SystemTime has none to read in a browser
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) fn since_epoch() -> Option<std::time::Duration> {
+    Some(std::time::Duration::from_millis(js_sys::Date::now() as u64))
+}
+
+fn raw_key(key: &[u8]) -> Result<[u8; KEY_LEN], JsError> {
+    key.try_into().map_err(|_| {
+        JsError::new(&format!(
+            "a raw key is {} bytes, not {}",
+            KEY_LEN,
+            key.len()
+        ))
+    })
+}
+
+fn js_error(error: EncryptError) -> JsError {
+    JsError::new(&error.to_string())
+}
@@ -0,0 +1,188 @@
+// The `watch` command: a drop folder whose new and changed files are encrypted into another directory as they
+// appear, until the process is stopped. Filesystem notifications (inotify, FSEvents, ReadDirectoryChangesW) say
+// which paths changed; a file is only encrypted once it has gone --debounce without changing, so that one being
+// copied in is encrypted once, whole, rather than at every write. Outputs mirror the watched tree, and replace the
+// output of an earlier version of the same file.
+use crate::output::Printer;
+use crate::redact;
+use encryptor::EncryptError;
+use glob::Pattern;
+use notify::event::{AccessKind, AccessMode, EventKind, ModifyKind, RenameMode};
+use notify::{RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+// How long to wait for an event when no file is waiting to settle
+const IDLE: Duration = Duration::from_secs(3600);
+
+// The directories of a watch, as given (for messages and outputs) and as found on disk (for events)
+struct Dirs<'a> {
+    dir: &'a Path,
+    out_dir: &'a Path,
+    root: PathBuf,
+    out: PathBuf,
+    excludes: &'a [Pattern],
+}
+
+// Watch `dir` and its subdirectories until the process is stopped. Each file that appears or changes and no pattern
+// of `excludes` matches is handed to `encrypt` with the directory its output goes in, once it has been left alone
+// for `debounce`. Fails only if the watch can't be set up; a file that fails to encrypt is reported and skipped.
+pub fn run(
+    dir: &Path,
+    out: &Path,
+    debounce: Duration,
+    excludes: &[Pattern],
+    printer: &Printer,
+    mut encrypt: impl FnMut(&Path, &Path) -> Result<PathBuf, EncryptError>,
+) -> Result<(), String> {
+    let root = fs::canonicalize(dir)
+        .ok()
+        .filter(|root| root.is_dir())
+        .ok_or_else(|| format!("Cannot watch {}: not a directory", dir.display()))?;
+    let dirs = fs::create_dir_all(out)
+        .and_then(|()| fs::canonicalize(out))
+        .map(|canonical| Dirs {
+            dir,
+            out_dir: out,
+            root,
+            out: canonical,
+            excludes,
+        })
+        .map_err(|err| {
+            format!(
+                "Cannot create the output directory {}: {}",
+                out.display(),
+                err
+            )
+        })?;
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)
+        .map_err(|err| format!("Cannot watch {}: {}", dir.display(), err))?;
+    watcher
+        .watch(&dirs.root, RecursiveMode::Recursive)
+        .map_err(|err| format!("Cannot watch {}: {}", dir.display(), err))?;
+    printer.note(&format!(
+        "Watching {} for new and changed files, encrypting them into {}; stop with Ctrl-C",
+        dir.display(),
+        out.display()
+    ));
+    watch(&dirs, &events, debounce, printer, &mut encrypt)
+}
+
+fn watch(
+    dirs: &Dirs,
+    events: &mpsc::Receiver<notify::Result<notify::Event>>,
+    debounce: Duration,
+    printer: &Printer,
+    encrypt: &mut impl FnMut(&Path, &Path) -> Result<PathBuf, EncryptError>,
+) -> Result<(), String> {
+    // The files waiting to settle, with when each last changed
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        let timeout = pending.values().min().map_or(IDLE, |last| {
+            (*last + debounce).saturating_duration_since(Instant::now())
+        });
+        match events.recv_timeout(timeout) {
+            Ok(Ok(event)) => {
+                for path in changed_files(dirs, &event) {
+                    log::debug!("{} changed", path.display());
+                    pending.insert(path, Instant::now());
+                }
+            }
+            Ok(Err(err)) => printer.warn(&format!("Watch error: {}", err)),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        let now = Instant::now();
+        let settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, last)| now >= **last + debounce)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in settled {
+            pending.remove(&path);
+            encrypt_file(dirs, &path, printer, encrypt);
+        }
+    }
+}
+
+// The files an event is about that may need encrypting: every file of a directory created or moved in (whose
+// files bring no events of their own), and changed files outside the output directory that aren't excluded
+fn changed_files(dirs: &Dirs, event: &notify::Event) -> Vec<PathBuf> {
+    let new = match event.kind {
+        EventKind::Create(_)
+        | EventKind::Modify(ModifyKind::Name(RenameMode::To | RenameMode::Both)) => true,
+        EventKind::Modify(ModifyKind::Metadata(_)) => return Vec::new(),
+        EventKind::Modify(_) | EventKind::Access(AccessKind::Close(AccessMode::Write)) => false,
+        _ => return Vec::new(),
+    };
+    let mut files = Vec::new();
+    let mut paths: Vec<PathBuf> = event.paths.clone();
+    while let Some(path) = paths.pop() {
+        if path.starts_with(&dirs.out) || excluded(dirs, &path) {
+            continue;
+        }
+        match fs::read_dir(&path) {
+            Ok(entries) if new => paths.extend(entries.flatten().map(|entry| entry.path())),
+            _ if path.is_dir() => {}
+            _ => files.push(path),
+        }
+    }
+    files
+}
+
+// Whether a pattern matches the path relative to the watched directory, or any of its parts, so that both
+// `*.tmp` and `.git` work
+fn excluded(dirs: &Dirs, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(&dirs.root) else {
+        return true;
+    };
+    dirs.excludes.iter().any(|pattern| {
+        pattern.matches_path(relative)
+            || relative
+                .iter()
+                .any(|part| part.to_str().is_some_and(|part| pattern.matches(part)))
+    })
+}
+
+// Encrypt a settled file into the same place under the output directory, unless it's gone again
+fn encrypt_file(
+    dirs: &Dirs,
+    path: &Path,
+    printer: &Printer,
+    encrypt: &mut impl FnMut(&Path, &Path) -> Result<PathBuf, EncryptError>,
+) {
+    let Ok(relative) = path.strip_prefix(&dirs.root) else {
+        return;
+    };
+    if !path.is_file() {
+        return;
+    }
+    let file_path = dirs.dir.join(relative);
+    let out_dir = dirs
+        .out_dir
+        .join(relative.parent().unwrap_or(Path::new("")));
+    redact::path(&file_path);
+    log::info!("Encryption of {} started", file_path.display());
+    let result = fs::create_dir_all(&out_dir)
+        .map_err(EncryptError::from)
+        .and_then(|()| encrypt(&file_path, &out_dir));
+    match result {
+        Ok(output_path) => {
+            redact::path(&output_path);
+            printer.ok(&format!(
+                "Encrypted {} -> {}",
+                file_path.display(),
+                output_path.display()
+            ));
+        }
+        Err(err) => printer.failed(&format!(
+            "Encryption error: {}: {}",
+            file_path.display(),
+            err
+        )),
+    }
+}
@@ -0,0 +1,233 @@
+//! Encrypts and decrypts through the std::io adapters, checking that their output is the chunked format the other
+//! functions read, that unfinished, damaged and cut off streams fail instead of ending early, and that any range
+//! of a chunked file reads back on its own. Deterministic encryption must repeat itself, and refuse streams and
+//! ciphers a repeated nonce breaks. Empty and cut off files are reported before any key is derived, and a failed
+//! tag check hands back no plaintext.
+
+use encryptor::header::HeaderError;
+use encryptor::{
+    Algorithm, DecryptingReader, EncryptError, EncryptingWriter, Encryptor, KdfParams,
+    SeekableReader,
+};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+const PLAINTEXT: &[u8] = b"The quick brown fox jumps over the lazy dog. 0123456789\n";
+
+fn encryptor() -> Encryptor {
+    Encryptor::new("correct horse")
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 1,
+        })
+        .with_chunk_size(256)
+}
+
+fn encrypt(encryptor: &Encryptor, plaintext: &[u8]) -> Vec<u8> {
+    let mut writer = EncryptingWriter::new(Vec::new(), encryptor).unwrap();
+    // Written in uneven pieces, so that chunks are gathered across writes
+    for piece in plaintext.chunks(100) {
+        writer.write_all(piece).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+fn decrypt(encryptor: &Encryptor, sealed: &[u8]) -> io::Result<Vec<u8>> {
+    let mut plaintext = Vec::new();
+    DecryptingReader::new(sealed, encryptor)
+        .map_err(io::Error::other)?
+        .read_to_end(&mut plaintext)?;
+    Ok(plaintext)
+}
+
+#[test]
+fn streams_round_trip_in_the_chunked_format() {
+    let encryptor = encryptor();
+    // Empty, less than a chunk, exactly a chunk, and many chunks
+    for len in [0, 10, 256, PLAINTEXT.len() * 100] {
+        let plaintext = &PLAINTEXT.repeat(100)[..len];
+        let sealed = encrypt(&encryptor, plaintext);
+        assert_eq!(encryptor.decrypt_bytes(&sealed).unwrap(), plaintext);
+        assert_eq!(decrypt(&encryptor, &sealed).unwrap(), plaintext);
+    }
+
+    // A payload sealed in one piece is read whole, and one that other functions chunked streams too
+    let sealed = encryptor.encrypt_bytes(PLAINTEXT).unwrap();
+    assert_eq!(decrypt(&encryptor, &sealed).unwrap(), PLAINTEXT);
+    let sealed = encryptor
+        .clone()
+        .with_stream_threshold(0)
+        .encrypt_bytes(&PLAINTEXT.repeat(20))
+        .unwrap();
+    assert_eq!(decrypt(&encryptor, &sealed).unwrap(), PLAINTEXT.repeat(20));
+
+    // The length of a stream is known before it is written, with or without the header backup
+    for backup in [false, true] {
+        let encryptor = encryptor.clone().with_header_backup(backup);
+        for len in [0, 256, 257, PLAINTEXT.len() * 100] {
+            let mut writer = EncryptingWriter::new(Vec::new(), &encryptor).unwrap();
+            let output_len = writer.output_len(len as u64);
+            writer.write_all(&PLAINTEXT.repeat(100)[..len]).unwrap();
+            assert_eq!(writer.finish().unwrap().len() as u64, output_len);
+        }
+    }
+}
+
+#[test]
+fn damaged_cut_off_or_unfinished_streams_fail() {
+    let plaintext = PLAINTEXT.repeat(100);
+    let encryptor = encryptor();
+    let sealed = encrypt(&encryptor, &plaintext);
+    let (_, header_len) = encryptor::header::Header::parse(&sealed).unwrap();
+
+    let mut damaged = sealed.clone();
+    damaged[header_len + 3 * (256 + 16) + 10] ^= 1;
+    let mut reader = DecryptingReader::new(&damaged[..], &encryptor).unwrap();
+    let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    let inner = error
+        .into_inner()
+        .unwrap()
+        .downcast::<EncryptError>()
+        .unwrap();
+    assert!(
+        matches!(*inner, EncryptError::CorruptChunk { index: 3, .. }),
+        "{}",
+        inner
+    );
+    // Reading on doesn't look like the end of the stream
+    assert!(reader.read(&mut [0; 16]).is_err());
+
+    // Cut off at a chunk boundary, with the header backup put back after it
+    let trailer_len = header_len + 4;
+    let mut cut = sealed[..header_len + 4 * (256 + 16)].to_vec();
+    cut.extend_from_slice(&sealed[sealed.len() - trailer_len..]);
+    assert!(decrypt(&encryptor, &cut).is_err());
+    assert!(decrypt(&encryptor, &sealed[..100]).is_err());
+
+    // A writer dropped without finish() leaves a stream without its last chunk
+    let mut unfinished = Vec::new();
+    let mut writer = EncryptingWriter::new(&mut unfinished, &encryptor).unwrap();
+    writer.write_all(&plaintext).unwrap();
+    drop(writer);
+    assert!(decrypt(&encryptor, &unfinished).is_err());
+    assert!(encryptor.decrypt_bytes(&unfinished).is_err());
+}
+
+#[test]
+fn seekable_reader_reads_any_range() {
+    let plaintext = PLAINTEXT.repeat(100);
+    let encryptor = encryptor();
+    // Written by the adapters, and by encrypt_file with a hidden name in front of the contents
+    let dir = std::env::temp_dir().join(format!("encryptor-adapters-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file_path = dir.join("plain.txt").to_string_lossy().into_owned();
+    std::fs::write(&file_path, &plaintext).unwrap();
+    let encrypted_path = encryptor
+        .clone()
+        .with_stream_threshold(0)
+        .with_name_encryption(true)
+        .encrypt_file(&file_path)
+        .unwrap();
+    let named = std::fs::read(&encrypted_path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    for sealed in [encrypt(&encryptor, &plaintext), named] {
+        let mut reader = SeekableReader::new(Cursor::new(&sealed), &encryptor).unwrap();
+        assert_eq!(reader.len(), plaintext.len() as u64);
+        // Within a chunk, across chunk boundaries, up to the end and past it
+        for (offset, len) in [(0, 10), (250, 20), (1000, 600), (plaintext.len() - 5, 20)] {
+            let mut buf = vec![0; len];
+            let read = reader.read_at(offset as u64, &mut buf).unwrap();
+            let expected = &plaintext[offset..(offset + len).min(plaintext.len())];
+            assert_eq!(&buf[..read], expected);
+        }
+        assert_eq!(
+            reader
+                .read_at(plaintext.len() as u64 + 1, &mut [0; 8])
+                .unwrap(),
+            0
+        );
+
+        let mut rest = Vec::new();
+        reader.seek(SeekFrom::End(-300)).unwrap();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &plaintext[plaintext.len() - 300..]);
+    }
+
+    // Only the chunks read are checked
+    let mut damaged = encrypt(&encryptor, &plaintext);
+    let (_, header_len) = encryptor::header::Header::parse(&damaged).unwrap();
+    damaged[header_len + 3 * (256 + 16) + 10] ^= 1;
+    let mut reader = SeekableReader::new(Cursor::new(&damaged), &encryptor).unwrap();
+    assert!(reader.read_at(0, &mut [0; 256]).is_ok());
+    assert!(matches!(
+        reader.read_at(3 * 256, &mut [0; 8]),
+        Err(EncryptError::CorruptChunk { index: 3, .. })
+    ));
+
+    // A payload sealed in one piece has no chunks to seek to
+    let whole = encryptor.encrypt_bytes(&plaintext).unwrap();
+    assert!(SeekableReader::new(Cursor::new(&whole), &encryptor).is_err());
+}
+
+#[test]
+fn deterministic_encryption_needs_the_whole_payload() {
+    let encryptor = encryptor()
+        .with_algorithm(Algorithm::Aes256GcmSiv)
+        .with_stream_threshold(0)
+        .with_deterministic("notes.txt");
+    // Chunked in memory, the same payload seals the same way every time
+    let sealed = encryptor.encrypt_bytes(PLAINTEXT).unwrap();
+    assert_eq!(encryptor.encrypt_bytes(PLAINTEXT).unwrap(), sealed);
+    assert_eq!(encryptor.decrypt_bytes(&sealed).unwrap(), PLAINTEXT);
+    assert!(EncryptingWriter::new(Vec::new(), &encryptor).is_err());
+}
+
+#[test]
+fn deterministic_encryption_needs_a_nonce_misuse_resistant_cipher() {
+    for algorithm in [
+        Algorithm::Aes256Gcm,
+        Algorithm::ChaCha20Poly1305,
+        Algorithm::Aes128Gcm,
+    ] {
+        let encryptor = encryptor()
+            .with_algorithm(algorithm)
+            .with_deterministic("notes.txt");
+        let err = encryptor.encrypt_bytes(PLAINTEXT).unwrap_err();
+        assert!(err.to_string().contains("aes256gcmsiv"), "{}", err);
+    }
+    let raw_key = Encryptor::from_key([7; 32]).with_deterministic("notes.txt");
+    assert!(raw_key.encrypt_bytes(PLAINTEXT).is_err());
+    assert!(raw_key
+        .with_algorithm(Algorithm::Aes256GcmSiv)
+        .encrypt_bytes(PLAINTEXT)
+        .is_ok());
+}
+
+#[test]
+fn damaged_files_hand_back_no_plaintext() {
+    assert!(matches!(
+        encryptor().decrypt_bytes(b""),
+        Err(EncryptError::HeaderError(HeaderError::Empty))
+    ));
+
+    // Without a recorded length, a payload cut off inside its tag is still reported as cut off
+    let unbound = encryptor().with_metadata_binding(false);
+    let sealed = unbound.encrypt_bytes(PLAINTEXT).unwrap();
+    let cut_off = &sealed[..sealed.len() - PLAINTEXT.len() - 1];
+    assert!(matches!(
+        unbound.decrypt_bytes(cut_off),
+        Err(EncryptError::MetadataMismatch(_))
+    ));
+
+    // In place, the buffer is emptied rather than left holding whatever the cipher wrote over the ciphertext
+    let encryptor = Encryptor::from_key([7; 32]);
+    let mut buffer = PLAINTEXT.to_vec();
+    encryptor.encrypt_in_place(&mut buffer).unwrap();
+    let last = buffer.len() - 1;
+    buffer[last] ^= 1;
+    assert!(matches!(
+        encryptor.decrypt_in_place(&mut buffer),
+        Err(EncryptError::AeadError(_))
+    ));
+    assert!(buffer.is_empty());
+}
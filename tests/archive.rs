@@ -0,0 +1,110 @@
+//! Packs a tree with `encryptor encrypt --archive` in a scratch directory and extracts it again, to check that the
+//! archive is encrypted in chunks as it is packed, that the options it can't stream are refused, and that a damaged
+//! archive keeps only the files restored from before the damage.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+// A scratch directory holding a password file and a tree with a small file and one of several chunks
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("archive", name);
+        fs::create_dir_all(scratch.dir.join("tree/sub")).unwrap();
+        fs::write(scratch.dir.join("tree/a.txt"), "The quick brown fox\n").unwrap();
+        let large: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(scratch.dir.join("tree/sub/b.bin"), large).unwrap();
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    // Run `encryptor <args> --passfile pass` in the directory; `info` takes no password
+    fn run(&self, args: &[&str]) -> Output {
+        let mut command = self.encryptor(args);
+        if args[0] != "info" {
+            command.args(["--passfile", "pass"]);
+        }
+        command.output().unwrap()
+    }
+
+    fn archive(&self, args: &[&str]) -> Output {
+        let mut all = vec!["encrypt", "--archive", "tree", "-o", "tree.enc"];
+        all.extend(["--kdf-memory", "1024", "--chunk-size", "64K"]);
+        all.extend(args);
+        self.run(&all)
+    }
+
+    fn extract(&self) -> Output {
+        self.run(&["decrypt", "--extract", "tree.enc", "--out", "restored"])
+    }
+}
+
+#[test]
+fn archives_are_encrypted_in_chunks() {
+    let scratch = Scratch::new("chunks");
+    let output = scratch.archive(&[]);
+    assert!(output.status.success(), "{:?}", output);
+    let info = scratch.run(&["info", "tree.enc"]);
+    let info = String::from_utf8_lossy(&info.stdout);
+    assert!(
+        info.contains("layout:        chunks of 64.0 KiB"),
+        "{}",
+        info
+    );
+
+    let output = scratch.extract();
+    assert!(output.status.success(), "{:?}", output);
+    for file in ["a.txt", "sub/b.bin"] {
+        assert_eq!(
+            fs::read(scratch.dir.join("restored/tree").join(file)).unwrap(),
+            fs::read(scratch.dir.join("tree").join(file)).unwrap(),
+            "{}",
+            file
+        );
+    }
+}
+
+#[test]
+fn what_an_archive_cant_stream_is_refused() {
+    let scratch = Scratch::new("refused");
+    for flags in [
+        &["--compress", "zstd"][..],
+        &["--armor"][..],
+        &["--pad", "padme"][..],
+    ] {
+        let output = scratch.archive(flags);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(2), "{:?}", output);
+        assert!(
+            stderr.contains("can't be combined with --archive"),
+            "{}",
+            stderr
+        );
+        assert!(!scratch.dir.join("tree.enc").exists());
+    }
+}
+
+#[test]
+fn a_damaged_archive_keeps_only_what_authenticated() {
+    let scratch = Scratch::new("damaged");
+    let output = scratch.archive(&["--no-header-backup"]);
+    assert!(output.status.success(), "{:?}", output);
+    // a.txt is in the first chunk, and the middle of the archive in a later chunk of b.bin
+    let mut sealed = fs::read(scratch.dir.join("tree.enc")).unwrap();
+    let middle = sealed.len() / 2;
+    sealed[middle] ^= 1;
+    fs::write(scratch.dir.join("tree.enc"), sealed).unwrap();
+
+    let output = scratch.extract();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(stderr.contains("ENC_AUTH_FAIL"), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("restored/tree/a.txt")).unwrap(),
+        "The quick brown fox\n"
+    );
+    assert!(!scratch.dir.join("restored/tree/sub/b.bin").exists());
+}
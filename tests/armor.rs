@@ -0,0 +1,85 @@
+//! Encrypts with `encryptor encrypt --armor`, to check that the file is base64 text between the BEGIN and END lines,
+//! and that `decrypt` detects the armor on its own, also once it was indented and re-wrapped as pasting into a YAML
+//! file would.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+// A scratch directory holding a password file and notes.txt.enc, encrypted with --armor
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("armor", name);
+        fs::write(scratch.dir.join("notes.txt"), "remember the milk\n").unwrap();
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        let output = scratch.run(&["encrypt", "notes.txt", "--armor", "--kdf-memory", "1024"]);
+        assert!(output.status.success(), "{:?}", output);
+        fs::remove_file(scratch.dir.join("notes.txt")).unwrap();
+        scratch
+    }
+
+    // Run `encryptor <args> --passfile pass` in the directory
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args)
+            .args(["--passfile", "pass"])
+            .output()
+            .unwrap()
+    }
+
+    // Decrypt notes.txt.enc, and check it comes out as it went in
+    fn decrypts(&self) {
+        let output = self.run(&["decrypt", "notes.txt.enc"]);
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(
+            fs::read_to_string(self.dir.join("notes.txt")).unwrap(),
+            "remember the milk\n"
+        );
+    }
+}
+
+#[test]
+fn armor_is_base64_between_markers() {
+    let scratch = Scratch::new("markers");
+    let armor = fs::read_to_string(scratch.dir.join("notes.txt.enc")).unwrap();
+    let lines: Vec<&str> = armor.lines().collect();
+    assert_eq!(lines[0], "-----BEGIN ENCRYPTOR FILE-----", "{}", armor);
+    assert_eq!(
+        lines[lines.len() - 1],
+        "-----END ENCRYPTOR FILE-----",
+        "{}",
+        armor
+    );
+    for line in &lines[1..lines.len() - 1] {
+        assert!(
+            line.len() <= 64
+                && line
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"+/=".contains(&b)),
+            "{}",
+            armor
+        );
+    }
+    scratch.decrypts();
+}
+
+#[test]
+fn pasted_armor_is_detected() {
+    let scratch = Scratch::new("pasted");
+    let armor = fs::read_to_string(scratch.dir.join("notes.txt.enc")).unwrap();
+    // Indented, with the base64 joined and split again at 40 characters
+    let mut lines = armor.lines();
+    let begin = lines.next().unwrap();
+    let rest: Vec<&str> = lines.collect();
+    let (end, body) = rest.split_last().unwrap();
+    let body = body.concat();
+    let mut pasted = format!("  {}\n", begin);
+    for piece in body.as_bytes().chunks(40) {
+        pasted.push_str(&format!("  {}\n", std::str::from_utf8(piece).unwrap()));
+    }
+    pasted.push_str(&format!("  {}\n", end));
+    fs::write(scratch.dir.join("notes.txt.enc"), pasted).unwrap();
+    scratch.decrypts();
+}
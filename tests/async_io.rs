@@ -0,0 +1,91 @@
+//! Encrypts and decrypts through the tokio API of the `async_io` module, checking that its output is the chunked
+//! format the other functions read, and that short, long and damaged streams fail.
+//! Run with `cargo test --features async`.
+#![cfg(feature = "async")]
+
+use encryptor::{EncryptError, Encryptor, KdfParams};
+use std::future::Future;
+use std::io;
+
+const PLAINTEXT: &[u8] = b"The quick brown fox jumps over the lazy dog. 0123456789\n";
+
+fn encryptor() -> Encryptor {
+    Encryptor::new("correct horse")
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 1,
+        })
+        .with_chunk_size(256)
+}
+
+fn run<T>(future: impl Future<Output = T>) -> T {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+// Servers spawn the futures on a multi-threaded runtime, which needs them to be Send
+fn assert_send<T: Send>(_: &T) {}
+
+#[test]
+fn streams_round_trip_in_the_chunked_format() {
+    let plaintext = PLAINTEXT.repeat(100);
+    let encryptor = encryptor();
+    let mut sealed = Vec::new();
+    let encrypting = encryptor.encrypt_reader(&plaintext[..], &mut sealed, plaintext.len() as u64);
+    assert_send(&encrypting);
+    run(encrypting).unwrap();
+    assert_eq!(encryptor.decrypt_bytes(&sealed).unwrap(), plaintext);
+
+    let mut decrypted = Vec::new();
+    let decrypting = encryptor.decrypt_reader(&sealed[..], &mut decrypted);
+    assert_send(&decrypting);
+    let written = run(decrypting).unwrap();
+    assert_eq!(written, plaintext.len() as u64);
+    assert_eq!(decrypted, plaintext);
+
+    // A payload sealed in one piece is read whole
+    let sealed = encryptor.encrypt_bytes(PLAINTEXT).unwrap();
+    let mut decrypted = Vec::new();
+    run(encryptor.decrypt_reader(&sealed[..], &mut decrypted)).unwrap();
+    assert_eq!(decrypted, PLAINTEXT);
+}
+
+#[test]
+fn reader_of_another_length_is_refused() {
+    let encryptor = encryptor();
+    for len in [PLAINTEXT.len() - 1, PLAINTEXT.len() + 1] {
+        let error = run(encryptor.encrypt_reader(PLAINTEXT, Vec::new(), len as u64)).unwrap_err();
+        assert!(
+            matches!(&error, EncryptError::IoError(err) if err.kind() == io::ErrorKind::InvalidData),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn damaged_or_cut_off_streams_fail() {
+    let plaintext = PLAINTEXT.repeat(100);
+    let encryptor = encryptor();
+    let mut sealed = Vec::new();
+    run(encryptor.encrypt_reader(&plaintext[..], &mut sealed, plaintext.len() as u64)).unwrap();
+
+    let mut damaged = sealed.clone();
+    let (_, header_len) = encryptor::header::Header::parse(&sealed).unwrap();
+    damaged[header_len + 3 * (256 + 16) + 10] ^= 1;
+    let error = run(encryptor.decrypt_reader(&damaged[..], Vec::new())).unwrap_err();
+    assert!(
+        matches!(error, EncryptError::CorruptChunk { index: 3, .. }),
+        "{}",
+        error
+    );
+
+    // Cut off at a chunk boundary, with the header backup put back after it
+    let trailer_len = header_len + 4;
+    let mut cut = sealed[..header_len + 4 * (256 + 16)].to_vec();
+    cut.extend_from_slice(&sealed[sealed.len() - trailer_len..]);
+    assert!(run(encryptor.decrypt_reader(&cut[..], Vec::new())).is_err());
+    assert!(run(encryptor.decrypt_reader(&sealed[..100], Vec::new())).is_err());
+}
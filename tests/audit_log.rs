@@ -0,0 +1,119 @@
+//! Runs `encryptor` with `--audit-log` on scratch files, then checks the log with `audit verify` before and after
+//! tampering with it.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Output;
+
+// A scratch directory with a file to encrypt, a password file and its own config directory (where the audit key is
+// created)
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("audit-log", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        fs::write(scratch.dir.join("a.txt"), "audited contents\n").unwrap();
+        scratch
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args).output().unwrap()
+    }
+
+    // Encrypt a.txt, then decrypt it back, both recorded in audit.log
+    fn audited_round_trip(&self) {
+        let encrypted = self.run(&[
+            "encrypt",
+            "a.txt",
+            "--passfile",
+            "pass",
+            "--key-id",
+            "team-2026",
+            "--audit-log",
+            "audit.log",
+            "--kdf-memory",
+            "1024",
+            "--kdf-iterations",
+            "1",
+        ]);
+        assert!(encrypted.status.success(), "{:?}", encrypted);
+        let decrypted = self.run(&[
+            "decrypt",
+            "a.txt.enc",
+            "--passfile",
+            "pass",
+            "--force",
+            "--audit-log",
+            "audit.log",
+        ]);
+        assert!(decrypted.status.success(), "{:?}", decrypted);
+    }
+}
+
+#[test]
+fn every_file_is_recorded_in_an_intact_chain() {
+    let scratch = Scratch::new("chain");
+    scratch.audited_round_trip();
+    let log = fs::read_to_string(scratch.path("audit.log")).unwrap();
+    let entries: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 2, "{}", log);
+    assert_eq!(entries[0]["operation"], "encrypt");
+    assert_eq!(entries[0]["output"], "a.txt.enc");
+    assert_eq!(entries[1]["operation"], "decrypt");
+    assert_eq!(entries[1]["input"], "a.txt.enc");
+    for entry in &entries {
+        assert_eq!(entry["key_id"], "team-2026");
+        assert_eq!(entry["status"], "ok");
+    }
+    assert_eq!(entries[1]["prev"], entries[0]["mac"]);
+
+    let verified = scratch.run(&["audit", "verify", "audit.log"]);
+    assert!(verified.status.success(), "{:?}", verified);
+    assert!(String::from_utf8_lossy(&verified.stdout).contains("2 entries, chain intact"));
+}
+
+#[test]
+fn tampering_is_detected() {
+    let scratch = Scratch::new("tamper");
+    scratch.audited_round_trip();
+    let log = fs::read_to_string(scratch.path("audit.log")).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+
+    // A changed line fails its MAC
+    fs::write(
+        scratch.path("edited.log"),
+        log.replace("\"status\":\"ok\"", "\"status\":\"error\""),
+    )
+    .unwrap();
+    let verified = scratch.run(&["audit", "verify", "edited.log"]);
+    assert!(!verified.status.success(), "{:?}", verified);
+    assert!(String::from_utf8_lossy(&verified.stderr).contains("line 1"));
+
+    // A removed line breaks the chain
+    fs::write(scratch.path("removed.log"), format!("{}\n", lines[1])).unwrap();
+    let verified = scratch.run(&["audit", "verify", "removed.log"]);
+    assert!(!verified.status.success(), "{:?}", verified);
+
+    // And a log that doesn't verify isn't appended to
+    fs::copy(scratch.path("edited.log"), scratch.path("audit.log")).unwrap();
+    let encrypted = scratch.run(&[
+        "encrypt",
+        "a.txt",
+        "--passfile",
+        "pass",
+        "--force",
+        "--audit-log",
+        "audit.log",
+    ]);
+    assert!(!encrypted.status.success(), "{:?}", encrypted);
+}
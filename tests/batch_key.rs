@@ -0,0 +1,123 @@
+//! Encrypts several files with one password in a scratch directory: they share the Argon2id salt but not their
+//! keys, decrypt one by one and together, and a single file keeps the plain Argon2id format. Library clones with
+//! `with_batch_key` read each other's outputs, and a file salt changed in the header fails to decrypt.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use encryptor::header::{Header, Kdf};
+use encryptor::{Encryptor, KdfParams};
+use std::fs;
+use std::process::Output;
+
+// A scratch directory with a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("batch-key", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args).output().unwrap()
+    }
+
+    fn header(&self, name: &str) -> Header {
+        Header::parse(&fs::read(self.dir.join(name)).unwrap())
+            .unwrap()
+            .0
+    }
+}
+
+const KDF: [&str; 4] = ["--kdf-memory", "1024", "--kdf-iterations", "1"];
+
+#[test]
+fn a_batch_shares_one_argon2id_salt() {
+    let scratch = Scratch::new("cli");
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(scratch.dir.join(name), format!("contents of {}\n", name)).unwrap();
+    }
+    let mut args = vec!["encrypt", "a.txt", "b.txt", "c.txt", "--passfile", "pass"];
+    args.extend(KDF);
+    let output = scratch.run(&args);
+    assert!(output.status.success(), "{:?}", output);
+
+    let headers: Vec<Header> = ["a.txt.enc", "b.txt.enc", "c.txt.enc"]
+        .iter()
+        .map(|name| scratch.header(name))
+        .collect();
+    for header in &headers {
+        assert_eq!(header.kdf, Kdf::Argon2idHkdf);
+        assert_eq!(header.salt.len(), 32);
+        assert_eq!(header.salt[..16], headers[0].salt[..16]);
+    }
+    assert_ne!(headers[0].salt[16..], headers[1].salt[16..]);
+
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::remove_file(scratch.dir.join(name)).unwrap();
+    }
+    let output = scratch.run(&["decrypt", "b.txt.enc", "--passfile", "pass"]);
+    assert!(output.status.success(), "{:?}", output);
+    fs::remove_file(scratch.dir.join("b.txt")).unwrap();
+    let output = scratch.run(&[
+        "decrypt",
+        "a.txt.enc",
+        "b.txt.enc",
+        "c.txt.enc",
+        "--passfile",
+        "pass",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        assert_eq!(
+            fs::read_to_string(scratch.dir.join(name)).unwrap(),
+            format!("contents of {}\n", name)
+        );
+    }
+
+    // One file alone is written like before
+    let mut args = vec![
+        "encrypt",
+        "a.txt",
+        "--out",
+        "alone.enc",
+        "--passfile",
+        "pass",
+    ];
+    args.extend(KDF);
+    let output = scratch.run(&args);
+    assert!(output.status.success(), "{:?}", output);
+    let header = scratch.header("alone.enc");
+    assert_eq!((header.kdf, header.salt.len()), (Kdf::Argon2id, 16));
+}
+
+#[test]
+fn clones_read_each_other_and_the_file_salt_is_bound() {
+    let encryptor = Encryptor::new("correct horse")
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 1,
+        })
+        .with_batch_key(true);
+    let first = encryptor.encrypt_bytes(b"first").unwrap();
+    let second = encryptor.clone().encrypt_bytes(b"second").unwrap();
+    assert_eq!(
+        Encryptor::new("correct horse")
+            .decrypt_bytes(&first)
+            .unwrap(),
+        b"first"
+    );
+    assert_eq!(encryptor.decrypt_bytes(&second).unwrap(), b"second");
+    assert!(Encryptor::new("wrong horse").decrypt_bytes(&first).is_err());
+
+    // Flip a bit of the file's own half of the salt
+    let (header, _) = Header::parse(&first).unwrap();
+    let mut changed = first.clone();
+    let at = first
+        .windows(32)
+        .position(|window| window == &header.salt[..])
+        .unwrap();
+    changed[at + 31] ^= 1;
+    assert!(encryptor.decrypt_bytes(&changed).is_err());
+}
@@ -0,0 +1,145 @@
+//! Encrypts files with `--checksum` in a scratch directory, in one piece and in chunks: they decrypt, `verify
+//! --checksum-only` accepts their decrypted files until one is changed, and the checksum survives a rekey. The
+//! library records a differently masked checksum in every output, even of the same plaintext.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use encryptor::header::Header;
+use encryptor::{Encryptor, KdfParams};
+use std::fs;
+use std::process::Output;
+
+// A scratch directory with a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("checksum", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args).output().unwrap()
+    }
+
+    fn header(&self, name: &str) -> Header {
+        Header::parse(&fs::read(self.dir.join(name)).unwrap())
+            .unwrap()
+            .0
+    }
+}
+
+const KDF: [&str; 4] = ["--kdf-memory", "1024", "--kdf-iterations", "1"];
+
+#[test]
+fn decrypted_files_are_checked_against_the_checksum() {
+    let scratch = Scratch::new("cli");
+    fs::write(scratch.dir.join("notes.txt"), "buy milk\n").unwrap();
+    let large: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(scratch.dir.join("disk.img"), &large).unwrap();
+    let mut args = vec!["encrypt", "notes.txt", "disk.img", "--passfile", "pass"];
+    args.extend(KDF);
+    args.extend([
+        "--checksum",
+        "--stream-threshold",
+        "64K",
+        "--chunk-size",
+        "16K",
+    ]);
+    let output = scratch.run(&args);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(scratch.header("notes.txt.enc").checksum.is_some());
+    assert!(scratch.header("disk.img.enc").chunk_size.is_some());
+
+    fs::remove_file(scratch.dir.join("notes.txt")).unwrap();
+    fs::remove_file(scratch.dir.join("disk.img")).unwrap();
+    let output = scratch.run(&[
+        "decrypt",
+        "notes.txt.enc",
+        "disk.img.enc",
+        "--passfile",
+        "pass",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(fs::read(scratch.dir.join("disk.img")).unwrap(), large);
+
+    let files = ["notes.txt.enc", "disk.img.enc"];
+    let verify = |files: &[&str]| {
+        let mut args = vec!["verify", "--checksum-only", "--passfile", "pass"];
+        args.extend(files);
+        scratch.run(&args)
+    };
+    let output = verify(&files);
+    assert!(output.status.success(), "{:?}", output);
+
+    // One changed byte in the decrypted file is caught without decrypting again
+    let mut changed = large.clone();
+    changed[100_000] ^= 1;
+    fs::write(scratch.dir.join("disk.img"), &changed).unwrap();
+    let output = verify(&files);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("disk.img.enc: ENC_METADATA_MISMATCH")
+            && !stderr.contains("notes.txt.enc:"),
+        "{}",
+        stderr
+    );
+
+    // Rekeying carries the checksum over to the new key
+    fs::write(scratch.dir.join("disk.img"), &large).unwrap();
+    fs::write(scratch.dir.join("new"), "another horse battery staple\n").unwrap();
+    let output = scratch.run(&[
+        "rekey",
+        "file:pass",
+        "file:new",
+        "notes.txt.enc",
+        "disk.img.enc",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.run(&[
+        "verify",
+        "--checksum-only",
+        "--passfile",
+        "new",
+        "notes.txt.enc",
+        "disk.img.enc",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+
+    // A file encrypted without --checksum has nothing to check against
+    let mut args = vec!["encrypt", "notes.txt", "--passfile", "pass", "--force"];
+    args.extend(KDF);
+    assert!(scratch.run(&args).status.success());
+    let output = verify(&["notes.txt.enc"]);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("records no checksum"), "{}", stderr);
+}
+
+#[test]
+fn every_output_masks_its_checksum_differently() {
+    let kdf_params = KdfParams {
+        memory: 1024,
+        iterations: 1,
+    };
+    let encryptor = Encryptor::new("correct horse battery staple")
+        .with_kdf_params(kdf_params)
+        .with_checksum(true);
+    let first = encryptor.encrypt_bytes(b"the same plaintext").unwrap();
+    let second = encryptor.encrypt_bytes(b"the same plaintext").unwrap();
+    let checksums: Vec<_> = [&first, &second]
+        .iter()
+        .map(|output| Header::parse(output).unwrap().0.checksum.unwrap())
+        .collect();
+    assert_ne!(checksums[0], checksums[1]);
+    assert_eq!(
+        encryptor.decrypt_bytes(&second).unwrap(),
+        b"the same plaintext"
+    );
+
+    // Another password decrypts nothing, and so unmasks nothing either
+    let other = Encryptor::new("another horse battery staple");
+    assert!(other.decrypt_bytes(&first).is_err());
+}
@@ -0,0 +1,65 @@
+//! Encrypts with `--cipher chacha20poly1305` and with `Encryptor::with_algorithm`, to check that the cipher is
+//! recorded in the header and that decryption picks it up from there without being told.
+mod common;
+
+use encryptor::header::{Algorithm, Header};
+use encryptor::{Encryptor, KdfParams};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+fn encryptor() -> Encryptor {
+    Encryptor::new(PASSWORD).with_kdf_params(KdfParams {
+        memory: 1024,
+        iterations: 2,
+    })
+}
+
+#[test]
+fn the_cipher_is_read_from_the_header() {
+    let sealed = encryptor()
+        .with_algorithm(Algorithm::ChaCha20Poly1305)
+        .encrypt_bytes(b"The quick brown fox\n")
+        .unwrap();
+    let (header, _) = Header::parse(&sealed).unwrap();
+    assert_eq!(header.algorithm, Algorithm::ChaCha20Poly1305);
+    // The default is AES-256-GCM, which doesn't matter to decryption
+    assert_eq!(
+        encryptor().decrypt_bytes(&sealed).unwrap(),
+        b"The quick brown fox\n"
+    );
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn cipher_chacha20poly1305_round_trips() {
+    use common::Scratch;
+    use std::fs;
+
+    let scratch = Scratch::create("cipher", "chacha");
+    fs::write(scratch.dir.join("notes.txt"), "remember the milk\n").unwrap();
+    fs::write(scratch.dir.join("pass"), format!("{}\n", PASSWORD)).unwrap();
+    let output = scratch
+        .encryptor(&["encrypt", "notes.txt", "--cipher", "chacha20poly1305"])
+        .args(["--kdf-memory", "1024", "--passfile", "pass"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    fs::remove_file(scratch.dir.join("notes.txt")).unwrap();
+
+    let output = scratch
+        .encryptor(&["info", "notes.txt.enc"])
+        .output()
+        .unwrap();
+    let info = String::from_utf8_lossy(&output.stdout);
+    assert!(info.contains("cipher:        chacha20poly1305"), "{}", info);
+
+    let output = scratch
+        .encryptor(&["decrypt", "notes.txt.enc", "--passfile", "pass"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("notes.txt")).unwrap(),
+        "remember the milk\n"
+    );
+}
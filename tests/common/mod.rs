@@ -0,0 +1,48 @@
+//! What the integration tests share. A test that uses it declares `mod common;` and adds the helpers it needs to
+//! `Scratch` in an `impl` block of its own.
+#![allow(dead_code)] // Each test uses what it needs of this
+
+use std::fs;
+use std::path::PathBuf;
+#[cfg(feature = "cli")]
+use std::process::Command;
+
+// A scratch directory a test creates in the temp directory, removed when the test is done
+pub struct Scratch {
+    pub dir: PathBuf,
+}
+
+impl Scratch {
+    // An empty scratch directory, encryptor-<prefix>-<name>-<pid>, replacing what a killed run left there: `prefix`
+    // names the test file and `name` the test
+    pub fn create(prefix: &str, name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "encryptor-{}-{}-{}",
+            prefix,
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Scratch { dir }
+    }
+
+    // `encryptor <args> --color never` in the directory, with a config directory of its own so the user's config
+    // isn't read, to be given what else the test needs and run
+    #[cfg(feature = "cli")]
+    pub fn encryptor(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_encryptor"));
+        command
+            .current_dir(&self.dir)
+            .env("ENCRYPTOR_CONFIG_DIR", self.dir.join("conf"))
+            .args(args)
+            .args(["--color", "never"]);
+        command
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,166 @@
+//! Decrypts the fixtures in `tests/fixtures`, written by every released format version with each algorithm,
+//! to check that the current code still reads all of them. Regenerate them with `tests/fixtures/regenerate.sh`.
+use encryptor::header::{MIN_VERSION, VERSION};
+use encryptor::{Algorithm, Encryptor};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The secrets tests/fixtures/generate.rs encrypted the fixtures with
+const PASSWORD: &str = "fixture password";
+#[cfg(feature = "x25519")]
+const KEY: [u8; 32] = [7; 32];
+#[cfg(feature = "x25519")]
+const IDENTITY: [u8; 32] = [9; 32];
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn algorithms() -> impl Iterator<Item = Algorithm> {
+    (1..).map_while(Algorithm::from_id)
+}
+
+// The first format version written by a build that had the algorithm, and so the first with a fixture for it
+fn first_version(algorithm: Algorithm) -> u8 {
+    match algorithm {
+        Algorithm::Aes128Gcm => 4,
+        _ => MIN_VERSION,
+    }
+}
+
+// How to open the fixture with the given name, or None when this build can't (its codec or feature is missing)
+fn encryptor(name: &str) -> Option<Encryptor> {
+    let needs = |tag: &str, built: bool| !name.contains(tag) || built;
+    if !needs("-zstd", cfg!(feature = "zstd"))
+        || !needs("-lz4", cfg!(feature = "lz4"))
+        || !needs("-brotli", cfg!(feature = "brotli"))
+        || !needs("-armor", cfg!(feature = "armor"))
+    {
+        return None;
+    }
+    if name.ends_with("-password-and-recipient.enc") {
+        return Some(Encryptor::new(PASSWORD));
+    }
+    #[cfg(feature = "x25519")]
+    {
+        use encryptor::recipient::Identity;
+
+        if name.ends_with("-key.enc") {
+            return Some(Encryptor::from_key(KEY));
+        }
+        if name.ends_with("-recipient.enc") {
+            return Some(Encryptor::from_identity(Identity::from_bytes(IDENTITY)));
+        }
+    }
+    #[cfg(not(feature = "x25519"))]
+    if name.ends_with("-key.enc") || name.ends_with("-recipient.enc") {
+        return None;
+    }
+    Some(Encryptor::new(PASSWORD))
+}
+
+#[test]
+fn every_fixture_decrypts() {
+    let plaintext = fs::read(fixtures().join("plaintext.txt")).unwrap();
+    let mut checked = 0;
+    for entry in fs::read_dir(fixtures()).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        if !name.ends_with(".enc") {
+            continue;
+        }
+        let Some(encryptor) = encryptor(&name) else {
+            continue;
+        };
+        let decrypted = encryptor
+            .decrypt_bytes(&fs::read(&path).unwrap())
+            .unwrap_or_else(|err| panic!("{} no longer decrypts: {}", name, err));
+        assert_eq!(
+            decrypted, plaintext,
+            "{} decrypts to the wrong plaintext",
+            name
+        );
+        checked += 1;
+    }
+    assert!(
+        checked > 0,
+        "no fixtures found, run tests/fixtures/regenerate.sh"
+    );
+}
+
+#[test]
+fn every_version_and_algorithm_has_a_fixture() {
+    for version in MIN_VERSION..=VERSION {
+        for algorithm in algorithms().filter(|&algorithm| first_version(algorithm) <= version) {
+            let name = format!("v{}-{}.enc", version, algorithm.name());
+            assert!(
+                fixtures().join(&name).exists(),
+                "{} is missing, run tests/fixtures/regenerate.sh",
+                name
+            );
+        }
+    }
+}
+
+#[test]
+fn an_algorithm_from_a_newer_release_is_refused() {
+    let mut contents = fs::read(fixtures().join(format!("v{}-aes256gcm.enc", VERSION))).unwrap();
+    // The algorithm id follows the magic and the version
+    contents[5] = 200;
+    let err = Encryptor::new(PASSWORD)
+        .decrypt_bytes(&contents)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("unknown algorithm id 200"), "{}", err);
+    assert!(err.contains("newer version"), "{}", err);
+}
+
+#[test]
+fn password_checks_and_set_times_are_read_from_their_fixtures() {
+    // Version 4 records a password check, and version 5 also when the password was set if the encryptor was told
+    let wrong = Encryptor::new("wrong password");
+    for version in 4..=VERSION {
+        let path = fixtures().join(format!("v{}-aes256gcm.enc", version));
+        assert!(
+            matches!(
+                wrong.check_password(&path),
+                Err(encryptor::EncryptError::WrongPassword)
+            ),
+            "{}",
+            path.display()
+        );
+        assert_eq!(Encryptor::new(PASSWORD).password_set(&path).unwrap(), None);
+    }
+    let path = fixtures().join(format!("v{}-aes256gcm-password-set.enc", VERSION));
+    assert_eq!(
+        Encryptor::new(PASSWORD).password_set(&path).unwrap(),
+        Some(1577836800)
+    );
+}
+
+#[test]
+fn legacy_fixture_decrypts() {
+    // Written by the command line of the first releases, whose password was the key and whose nonce was given
+    let nonce = [246, 231, 118, 136, 232, 16, 173, 214, 11, 241, 220, 114];
+    let contents = fs::read(fixtures().join("v0-aes256gcm.legacy")).unwrap();
+    let decrypted = encryptor::legacy::decrypt(
+        b"12345678901234567890123456789012",
+        &nonce,
+        contents.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        decrypted,
+        fs::read(fixtures().join("plaintext.txt")).unwrap()
+    );
+
+    let mut wrong_nonce = nonce;
+    wrong_nonce[0] ^= 1;
+    assert!(encryptor::legacy::decrypt(
+        b"12345678901234567890123456789012",
+        &wrong_nonce,
+        contents.clone()
+    )
+    .is_err());
+    assert!(encryptor::legacy::decrypt(b"too short", &nonce, contents).is_err());
+}
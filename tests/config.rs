@@ -0,0 +1,160 @@
+//! Runs the `encryptor` binary with defaults in a config file and the environment, to check that a flag wins over
+//! its environment variable, which wins over the config file, and that a bad config fails instead of being ignored.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+// A scratch directory holding a password file, `test.txt` and an empty config directory
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("config", name);
+        fs::create_dir_all(scratch.dir.join("conf")).unwrap();
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        fs::write(
+            scratch.dir.join("test.txt"),
+            "The quick brown fox jumps over the lazy dog",
+        )
+        .unwrap();
+        scratch
+    }
+
+    fn write_config(&self, name: &str, text: &str) {
+        fs::write(self.dir.join(name), text).unwrap();
+    }
+
+    // Run `encryptor <args> --passfile pass` in the directory, with the given environment variables; `info` takes
+    // no password
+    fn run(&self, env: &[(&str, &str)], args: &[&str]) -> Output {
+        let mut command = self.encryptor(args);
+        command.envs(env.iter().copied());
+        if args[0] != "info" {
+            command.args(["--passfile", "pass"]);
+        }
+        command.output().unwrap()
+    }
+
+    // The cipher `info` shows for `file`
+    fn cipher(&self, file: &str) -> String {
+        let output = self.run(&[], &["info", file]);
+        let info = String::from_utf8(output.stdout).unwrap();
+        let line = info.lines().find(|line| line.contains("cipher:")).unwrap();
+        line.split_whitespace().last().unwrap().to_string()
+    }
+}
+
+#[test]
+fn config_file_sets_defaults() {
+    let scratch = Scratch::new("defaults");
+    scratch.write_config(
+        "conf/config.toml",
+        "cipher = \"chacha20poly1305\"\nkdf-memory = 1024\noutput-dir = \"out\"\njson = true\n",
+    );
+    let output = scratch.run(&[], &["encrypt", "test.txt"]);
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains("\"operation\":\"encrypt\""), "{}", report);
+    assert_eq!(scratch.cipher("out/test.txt.enc"), "chacha20poly1305");
+}
+
+#[test]
+fn flags_win_over_environment_over_config() {
+    let scratch = Scratch::new("precedence");
+    scratch.write_config(
+        "conf/config.toml",
+        "cipher = \"chacha20poly1305\"\nkdf-memory = 1024\n",
+    );
+    let env = [("ENCRYPTOR_CIPHER", "aes256gcmsiv")];
+    let encrypt = |env: &[(&str, &str)], args: &[&str], out: &str| {
+        let args = [&["encrypt", "test.txt", "--out", out], args].concat();
+        assert!(scratch.run(env, &args).status.success());
+        scratch.cipher(out)
+    };
+    assert_eq!(encrypt(&[], &[], "a.enc"), "chacha20poly1305");
+    assert_eq!(encrypt(&env, &[], "b.enc"), "aes256gcmsiv");
+    assert_eq!(
+        encrypt(&env, &["--cipher", "aes256gcm"], "c.enc"),
+        "aes256gcm"
+    );
+    assert_eq!(
+        encrypt(&env, &["--cipher", "aes128-gcm"], "d.enc"),
+        "aes128gcm"
+    );
+}
+
+#[test]
+fn config_flag_replaces_config_file() {
+    let scratch = Scratch::new("flag");
+    scratch.write_config("conf/config.toml", "cipher = \"chacha20poly1305\"\n");
+    scratch.write_config("ci.toml", "cipher = \"aes256gcmsiv\"\nkdf-memory = 1024\n");
+    let output = scratch.run(&[], &["encrypt", "test.txt", "--config", "ci.toml"]);
+    assert!(output.status.success());
+    assert_eq!(scratch.cipher("test.txt.enc"), "aes256gcmsiv");
+}
+
+#[test]
+fn config_file_redacts_paths() {
+    let scratch = Scratch::new("redact");
+    scratch.write_config(
+        "conf/config.toml",
+        "kdf-memory = 1024\nredact-paths = true\n",
+    );
+    let output = scratch.run(&[], &["encrypt", "test.txt"]);
+    assert!(output.status.success());
+    let status = String::from_utf8(output.stdout).unwrap();
+    assert!(status.contains("Encrypted"), "{}", status);
+    assert!(!status.contains("test.txt"), "{}", status);
+
+    // The environment wins over the config file, so it can turn the default off
+    let output = scratch.run(
+        &[("ENCRYPTOR_REDACT_PATHS", "false")],
+        &["encrypt", "test.txt", "--force"],
+    );
+    assert!(output.status.success());
+    let status = String::from_utf8(output.stdout).unwrap();
+    assert!(status.contains("test.txt"), "{}", status);
+}
+
+#[test]
+fn config_storage_class_is_left_out_for_local_outputs() {
+    let scratch = Scratch::new("storage-class");
+    scratch.write_config(
+        "conf/config.toml",
+        "kdf-memory = 1024\nstorage-class = \"DEEP_ARCHIVE\"\n",
+    );
+    // Unlike --storage-class, which only an s3:// --out takes
+    let output = scratch.run(&[], &["encrypt", "test.txt"]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.run(
+        &[],
+        &[
+            "encrypt",
+            "test.txt",
+            "--force",
+            "--storage-class",
+            "GLACIER",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+}
+
+#[test]
+fn bad_config_fails() {
+    let scratch = Scratch::new("bad");
+    scratch.write_config("typo.toml", "ciper = \"aes256gcm\"\n");
+    scratch.write_config("value.toml", "kdf-memory = \"lots\"\n");
+    scratch.write_config("class.toml", "storage-class = \"cold storage\"\n");
+    for args in [
+        ["encrypt", "test.txt", "--config", "typo.toml"],
+        ["encrypt", "test.txt", "--config", "value.toml"],
+        ["encrypt", "test.txt", "--config", "class.toml"],
+        ["encrypt", "test.txt", "--config", "missing.toml"],
+    ] {
+        let output = scratch.run(&[], &args);
+        assert_eq!(output.status.code(), Some(1));
+        assert!(!scratch.dir.join("test.txt.enc").exists());
+    }
+}
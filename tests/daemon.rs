@@ -0,0 +1,125 @@
+//! Starts `encryptor daemon` on a socket in a scratch directory, and has `encryptor client` encrypt and decrypt files
+//! through it until it is stopped or its TTL runs out.
+#![cfg(all(feature = "cli", unix))]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A scratch directory with a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("daemon", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = self.encryptor(args);
+        command.args(["--socket", "daemon.sock"]);
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    // Start a daemon with `ttl`, returning once it listens
+    fn daemon(&self, ttl: &str) -> Child {
+        let child = self
+            .command(&["daemon", "--passfile", "pass", "--ttl", ttl])
+            .args(["--kdf-memory", "1024", "--kdf-iterations", "1"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        while !self.dir.join("daemon.sock").exists() {
+            assert!(started.elapsed() < Duration::from_secs(10), "no socket");
+            thread::sleep(Duration::from_millis(20));
+        }
+        child
+    }
+}
+
+#[test]
+fn the_client_encrypts_and_decrypts_through_the_daemon() {
+    let scratch = Scratch::new("files");
+    fs::write(scratch.dir.join("a.txt"), "first file\n").unwrap();
+    fs::write(scratch.dir.join("b.txt"), "second file\n").unwrap();
+    let mut daemon = scratch.daemon("5m");
+
+    let output = scratch.run(&["client", "encrypt", "a.txt", "b.txt"]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.run(&["client", "decrypt", "a.txt.enc", "--out", "restored.txt"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("restored.txt")).unwrap(),
+        "first file\n"
+    );
+
+    // What the daemon wrote is an ordinary file under the password
+    let output = scratch
+        .encryptor(&[
+            "decrypt",
+            "b.txt.enc",
+            "--passfile",
+            "pass",
+            "--out",
+            "b2.txt",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("b2.txt")).unwrap(),
+        "second file\n"
+    );
+
+    // Failures carry the file's own exit code
+    let output = scratch.run(&["client", "encrypt", "a.txt"]);
+    assert_eq!(output.status.code(), Some(3), "{:?}", output);
+    fs::write(scratch.dir.join("pass"), "another horse battery staple\n").unwrap();
+    let output = scratch
+        .encryptor(&[
+            "encrypt",
+            "b.txt",
+            "--passfile",
+            "pass",
+            "--out",
+            "other.enc",
+        ])
+        .args(["--kdf-memory", "1024", "--kdf-iterations", "1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.run(&["client", "decrypt", "other.enc", "--out", "other.txt"]);
+    assert_eq!(output.status.code(), Some(13), "{:?}", output);
+
+    let output = scratch.run(&["client", "status"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("keeps its key for another"), "{}", stdout);
+    let output = scratch.run(&["client", "stop"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(daemon.wait().unwrap().success());
+    assert!(!scratch.dir.join("daemon.sock").exists());
+
+    let output = scratch.run(&["client", "encrypt", "a.txt", "--force"]);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No daemon is listening"), "{}", stderr);
+}
+
+#[test]
+fn the_daemon_exits_when_its_ttl_runs_out() {
+    let scratch = Scratch::new("ttl");
+    let mut daemon = scratch.daemon("1s");
+    let started = Instant::now();
+    assert!(daemon.wait().unwrap().success());
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert!(!scratch.dir.join("daemon.sock").exists());
+}
@@ -0,0 +1,159 @@
+//! Runs `encryptor decrypt` on empty, cut off, tampered and wrongly keyed files in a scratch directory, to check that
+//! each is reported for what it is and that no output file is left behind for any of them, that the damaged
+//! chunks of a chunked file are all listed and the rest salvaged with --keep-going, and that the password check in
+//! the header tells a wrong password from a damaged payload.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+// A scratch directory holding a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("damaged", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args)
+            .args(["--passfile", "pass"])
+            .output()
+            .unwrap()
+    }
+
+    // Encrypt `contents` as `name`, removing the plaintext so that only a decryption can bring it back
+    fn seal(&self, name: &str, contents: &[u8], args: &[&str]) -> Vec<u8> {
+        fs::write(self.dir.join(name), contents).unwrap();
+        let mut command = vec!["encrypt", name, "--kdf-memory", "1024"];
+        command.extend_from_slice(args);
+        let output = self.run(&command);
+        assert!(output.status.success(), "{:?}", output);
+        fs::remove_file(self.dir.join(name)).unwrap();
+        fs::read(self.dir.join(format!("{}.enc", name))).unwrap()
+    }
+
+    // Decrypt `name`.enc, which must fail with `expected` in the message and leave no `name` behind
+    fn refuses(&self, name: &str, expected: &str) {
+        let output = self.run(&["decrypt", &format!("{}.enc", name)]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "{:?}", output);
+        assert!(stderr.contains(expected), "{}", stderr);
+        assert!(!self.dir.join(name).exists(), "{} was written", name);
+    }
+}
+
+#[test]
+fn damaged_files_leave_no_output() {
+    let scratch = Scratch::new("outputs");
+    fs::write(scratch.dir.join("empty.enc"), b"").unwrap();
+    scratch.refuses("empty", "the file is empty");
+
+    let sealed = scratch.seal("short.txt", b"hello\n", &["--no-bind-metadata"]);
+    fs::write(
+        scratch.dir.join("short.txt.enc"),
+        &sealed[..sealed.len() - 8],
+    )
+    .unwrap();
+    scratch.refuses("short.txt", "was truncated");
+
+    let large: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    let mut sealed = scratch.seal(
+        "large.bin",
+        &large,
+        &["--stream-threshold", "0", "--chunk-size", "64K"],
+    );
+    let last = sealed.len() - 100;
+    sealed[last] ^= 1;
+    fs::write(scratch.dir.join("large.bin.enc"), &sealed).unwrap();
+    scratch.refuses("large.bin", "ENC_AUTH_FAIL");
+
+    scratch.seal("notes.txt", b"The quick brown fox\n", &[]);
+    fs::write(scratch.dir.join("pass"), "another password\n").unwrap();
+    scratch.refuses("notes.txt", "wrong password");
+}
+
+#[test]
+fn wrong_passwords_are_told_from_damage() {
+    let scratch = Scratch::new("check");
+    let mut sealed = scratch.seal("notes.txt", b"The quick brown fox\n", &[]);
+    fs::write(scratch.dir.join("pass"), "another password\n").unwrap();
+    let output = scratch.run(&["decrypt", "notes.txt.enc"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(13), "{:?}", output);
+    assert!(stderr.contains("ENC_WRONG_PASSWORD"), "{}", stderr);
+
+    // With the right password, a payload that fails to open can only be damaged
+    fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+    // The header is also copied to the end of the file, followed by 4 bytes; the sealed text is between them
+    let header_len = (sealed.len() - 20 - 16 - 4) / 2;
+    sealed[header_len + 5] ^= 1;
+    fs::write(scratch.dir.join("notes.txt.enc"), &sealed).unwrap();
+    let output = scratch.run(&["decrypt", "notes.txt.enc"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ENC_AUTH_FAIL"), "{}", stderr);
+    assert!(!stderr.contains("ENC_WRONG_PASSWORD"), "{}", stderr);
+    assert!(!scratch.dir.join("notes.txt").exists());
+}
+
+#[test]
+fn damaged_chunks_are_listed_and_salvaged() {
+    let scratch = Scratch::new("salvage");
+    let large: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    let mut sealed = scratch.seal(
+        "large.bin",
+        &large,
+        &["--stream-threshold", "0", "--chunk-size", "64K"],
+    );
+    // Chunks of 64K and their 16-byte tags follow the header; damage the first chunk, and the third and fourth
+    let sealed_chunk = (64 << 10) + 16;
+    // The header is also copied to the end of the file, followed by 4 bytes
+    let header_len = (sealed.len() - large.len() - 5 * 16 - 4) / 2;
+    for chunk in [0, 2, 3] {
+        sealed[header_len + chunk * sealed_chunk + 100] ^= 1;
+    }
+    fs::write(scratch.dir.join("large.bin.enc"), &sealed).unwrap();
+
+    let output = scratch.run(&["verify", "large.bin.enc"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{:?}", output);
+    assert!(
+        stderr.contains("3 chunks failed authentication"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains(&format!("chunk 0 at bytes {}-", header_len)),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("chunks 2-3 at bytes"), "{}", stderr);
+    scratch.refuses("large.bin", "chunks 2-3 at bytes");
+
+    let output = scratch.run(&["decrypt", "large.bin.enc", "--keep-going"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{:?}", output);
+    assert!(stderr.contains("salvaged into large.bin"), "{}", stderr);
+    let salvaged = fs::read(scratch.dir.join("large.bin")).unwrap();
+    assert_eq!(salvaged.len(), large.len());
+    for (index, (salvaged, original)) in salvaged
+        .chunks(64 << 10)
+        .zip(large.chunks(64 << 10))
+        .enumerate()
+    {
+        match index {
+            0 | 2 | 3 => assert!(salvaged.iter().all(|&byte| byte == 0), "chunk {}", index),
+            _ => assert_eq!(salvaged, original, "chunk {}", index),
+        }
+    }
+
+    // A wrong password fails every chunk, so there is nothing to salvage
+    fs::remove_file(scratch.dir.join("large.bin")).unwrap();
+    fs::write(scratch.dir.join("pass"), "another password\n").unwrap();
+    let output = scratch.run(&["decrypt", "large.bin.enc", "--keep-going"]);
+    assert!(!output.status.success());
+    assert!(!scratch.dir.join("large.bin").exists());
+}
@@ -0,0 +1,151 @@
+//! Plans runs with `encryptor encrypt --dry-run` and `decrypt --dry-run` in a scratch directory: the outputs are
+//! printed and clashes caught, without a password being asked for or anything being written.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::{Output, Stdio};
+
+// A scratch directory with two files and a directory
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("dry-run", name);
+        fs::create_dir_all(scratch.dir.join("photos")).unwrap();
+        fs::write(scratch.dir.join("notes.txt"), "remember the milk\n").unwrap();
+        fs::write(scratch.dir.join("todo.txt"), "call the bank\n").unwrap();
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    // Run with no stdin, so a password prompt would fail
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args).stdin(Stdio::null()).output().unwrap()
+    }
+
+    fn entries(&self) -> Vec<String> {
+        let mut entries: Vec<String> = fs::read_dir(&self.dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        entries.sort();
+        entries
+    }
+}
+
+#[test]
+fn a_dry_run_prints_the_plan_and_writes_nothing() {
+    let scratch = Scratch::new("plan");
+    let before = scratch.entries();
+    let output = scratch.run(&[
+        "encrypt",
+        "notes.txt",
+        "todo.txt",
+        "--out",
+        "vault/",
+        "--dry-run",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Would encrypt notes.txt -> vault/notes.txt.enc (18 B)"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Would encrypt todo.txt -> vault/todo.txt.enc"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("free on the filesystem of ."), "{}", stdout);
+    assert!(
+        stdout.contains("2 files would be encrypted; nothing was written"),
+        "{}",
+        stdout
+    );
+    assert_eq!(scratch.entries(), before);
+}
+
+#[test]
+fn problems_are_caught_up_front() {
+    let scratch = Scratch::new("problems");
+    fs::write(scratch.dir.join("taken.enc"), "already here").unwrap();
+    let output = scratch.run(&["encrypt", "notes.txt", "--out", "taken.enc", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("taken.enc already exists, and only --force replaces it"),
+        "{}",
+        stderr
+    );
+
+    let output = scratch.run(&[
+        "encrypt",
+        "notes.txt",
+        "photos",
+        "missing",
+        "notes.txt",
+        "--dry-run",
+    ]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Would fail: photos: a directory"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("Would fail: missing:"), "{}", stderr);
+    assert!(
+        stderr.contains("notes.txt.enc is also the output of notes.txt"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("3 of 4 files would fail"), "{}", stderr);
+
+    let output = scratch.run(&["decrypt", "todo.txt", "--dry-run"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("todo.txt: not an encrypted file"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn decrypting_is_planned_from_the_headers() {
+    let scratch = Scratch::new("decrypt");
+    let output = scratch.run(&[
+        "encrypt",
+        "notes.txt",
+        "--passfile",
+        "pass",
+        "--kdf-memory",
+        "1024",
+        "--kdf-iterations",
+        "1",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.run(&["decrypt", "notes.txt.enc", "--dry-run"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+
+    let output = scratch.run(&["decrypt", "notes.txt.enc", "--dry-run", "--force"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Would decrypt notes.txt.enc -> notes.txt (")
+            && stdout.contains("replacing the existing file"),
+        "{}",
+        stdout
+    );
+
+    let output = scratch.run(&["decrypt", "notes.txt.enc", "--dry-run", "--auto-rename"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Would decrypt notes.txt.enc -> notes (1).txt (")
+            && stdout.contains("renamed, since notes.txt exists"),
+        "{}",
+        stdout
+    );
+}
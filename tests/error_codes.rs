@@ -0,0 +1,93 @@
+//! Makes decryption fail with a wrong password, a truncated header and a damaged payload, to check that each failure
+//! carries its stable code in `EncryptError::code`, in its message, and from the command line in the exit code and
+//! the `--json` result alike.
+mod common;
+
+use encryptor::{EncryptError, Encryptor, ErrorCode, KdfParams};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+fn encryptor(password: &str) -> Encryptor {
+    Encryptor::new(password)
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 2,
+        })
+        .with_header_backup(false)
+}
+
+// The code a failed decryption of `sealed` under `password` comes with, checking it leads its message
+fn failure(password: &str, sealed: &[u8]) -> ErrorCode {
+    let err: EncryptError = encryptor(password).decrypt_bytes(sealed).unwrap_err();
+    let message = err.to_string();
+    assert!(
+        message.starts_with(&format!("{}: ", err.code().as_str())),
+        "{}",
+        message
+    );
+    assert!(!err.is_retryable(), "{}", message);
+    err.code()
+}
+
+#[test]
+fn each_failure_has_its_code() {
+    let sealed = encryptor(PASSWORD)
+        .encrypt_bytes(b"The quick brown fox\n")
+        .unwrap();
+
+    let code = failure("hunter2", &sealed);
+    assert_eq!(code, ErrorCode::WrongPassword);
+    assert_eq!((code.as_str(), code.number()), ("ENC_WRONG_PASSWORD", 13));
+
+    let code = failure(PASSWORD, &sealed[..10]);
+    assert_eq!(code, ErrorCode::BadHeader);
+    assert_eq!((code.as_str(), code.number()), ("ENC_BAD_HEADER", 6));
+
+    let mut damaged = sealed.clone();
+    *damaged.last_mut().unwrap() ^= 1;
+    let code = failure(PASSWORD, &damaged);
+    assert_eq!(code, ErrorCode::AuthFail);
+    assert_eq!((code.as_str(), code.number()), ("ENC_AUTH_FAIL", 4));
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn the_cli_exits_with_the_code() {
+    use common::Scratch;
+    use std::fs;
+
+    let scratch = Scratch::create("error-codes", "cli");
+    fs::write(scratch.dir.join("notes.txt"), "remember the milk\n").unwrap();
+    fs::write(scratch.dir.join("pass"), format!("{}\n", PASSWORD)).unwrap();
+    fs::write(scratch.dir.join("wrong"), "hunter2\n").unwrap();
+    let output = scratch
+        .encryptor(&["encrypt", "notes.txt", "--kdf-memory", "1024"])
+        .args(["--passfile", "pass"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = scratch
+        .encryptor(&["decrypt", "notes.txt.enc", "--passfile", "wrong", "--force"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(13), "{:?}", output);
+    assert!(
+        stderr.contains("ENC_WRONG_PASSWORD: wrong password"),
+        "{}",
+        stderr
+    );
+
+    let output = scratch
+        .encryptor(&["decrypt", "notes.txt.enc", "--passfile", "wrong", "--force"])
+        .arg("--json")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(13), "{:?}", output);
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["status"], "error");
+    assert_eq!(result["error"]["code"], "ENC_WRONG_PASSWORD");
+    assert_eq!(result["error"]["exit_code"], 13);
+    assert_eq!(result["error"]["retryable"], false);
+}
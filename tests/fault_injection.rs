@@ -0,0 +1,721 @@
+//! Runs the `encryptor` binary with failures injected through ENCRYPTOR_FAULTS, to check that outputs stay
+//! all-or-nothing, runs that won't fit stop before they start, damage is detected, a repeated nonce is refused,
+//! interrupted `--resume` streams continue (or start over when what they would seal again changed), Ctrl-C leaves
+//! no unfinished output, and interrupted `--shred` batches and damaged headers can be recovered.
+//! Run with `cargo test --features fault-injection`.
+#![cfg(feature = "fault-injection")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const PLAINTEXT: &[u8] = b"The quick brown fox jumps over the lazy dog. 0123456789\n";
+
+// A scratch directory holding a password file and `test.txt`
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("faults", name);
+        fs::write(scratch.dir.join("pass"), "correct horse\n").unwrap();
+        fs::write(scratch.dir.join("test.txt"), PLAINTEXT.repeat(100)).unwrap();
+        scratch
+    }
+
+    // Run `encryptor <args> --passfile pass` in the directory, with `faults` injected. `recover`, `header`, `info`,
+    // `rekey` and `rotate` (which name their secrets themselves) and commands given a --keyfile take no password,
+    // and only the commands that encrypt take a KDF cost.
+    fn run(&self, faults: &str, args: &[&str]) -> Output {
+        let mut command = self.encryptor(args);
+        command.env("ENCRYPTOR_FAULTS", faults);
+        if !matches!(args[0], "recover" | "header" | "info" | "rekey" | "rotate")
+            && !args.contains(&"--keyfile")
+        {
+            command.args(["--passfile", "pass"]);
+        }
+        if matches!(args[0], "encrypt" | "resume-journal") {
+            command.args(["--kdf-memory", "1024"]);
+        }
+        command.output().unwrap()
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.path(name).exists()
+    }
+
+    fn read(&self, name: &str) -> Vec<u8> {
+        fs::read(self.path(name)).unwrap()
+    }
+}
+
+fn aborted(output: &Output) -> bool {
+    output.status.code().is_none()
+}
+
+fn exit_code(output: &Output) -> i32 {
+    output.status.code().expect("the process exited normally")
+}
+
+#[test]
+fn short_reads_round_trip() {
+    let scratch = Scratch::new("short-read");
+    assert!(scratch
+        .run("short-read", &["encrypt", "test.txt"])
+        .status
+        .success());
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    assert!(scratch
+        .run("short-read", &["decrypt", "test.txt.enc"])
+        .status
+        .success());
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+}
+
+#[test]
+fn no_space_leaves_no_partial_output() {
+    let scratch = Scratch::new("enospc");
+    let output = scratch.run("enospc@100", &["encrypt", "test.txt"]);
+    assert_eq!(exit_code(&output), 3);
+    assert!(!scratch.exists("test.txt.enc"));
+    assert_eq!(temporary_files(&scratch), 0);
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+
+    assert!(scratch.run("", &["encrypt", "test.txt"]).status.success());
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    let output = scratch.run("enospc@10", &["decrypt", "test.txt.enc"]);
+    assert_eq!(exit_code(&output), 3);
+    assert!(!scratch.exists("test.txt"));
+}
+
+#[test]
+fn preflight_stops_runs_that_would_not_fit() {
+    let scratch = Scratch::new("preflight");
+    let output = scratch.run("free@1000", &["encrypt", "test.txt"]);
+    assert_eq!(exit_code(&output), 3);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Not enough free space"),
+        "{:?}",
+        output
+    );
+    assert!(!scratch.exists("test.txt.enc"));
+
+    // Only the estimate was short, so the run itself goes through
+    let skipped = scratch.run("free@1000", &["encrypt", "test.txt", "--no-preflight"]);
+    assert!(skipped.status.success(), "{:?}", skipped);
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    assert_eq!(
+        exit_code(&scratch.run("free@1000", &["decrypt", "test.txt.enc"])),
+        3
+    );
+    assert!(!scratch.exists("test.txt"));
+    assert!(scratch
+        .run("", &["decrypt", "test.txt.enc"])
+        .status
+        .success());
+
+    // A disk that fills up anyway says so
+    fs::remove_file(scratch.path("test.txt.enc")).unwrap();
+    let output = scratch.run("enospc@100", &["encrypt", "test.txt"]);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("filesystem is full"),
+        "{:?}",
+        output
+    );
+}
+
+#[test]
+fn flipped_ciphertext_bit_is_detected() {
+    let scratch = Scratch::new("flip");
+    // Well past the header, inside the ciphertext
+    assert!(scratch
+        .run("flip@1000", &["encrypt", "test.txt"])
+        .status
+        .success());
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+
+    assert_eq!(exit_code(&scratch.run("", &["verify", "test.txt.enc"])), 4);
+    assert_eq!(exit_code(&scratch.run("", &["decrypt", "test.txt.enc"])), 4);
+    assert!(!scratch.exists("test.txt"));
+}
+
+#[test]
+fn flipped_header_bit_is_salvaged_from_the_backup() {
+    let scratch = Scratch::new("salvage");
+    // A bit of the recorded Argon2id memory cost, which changes the derived key
+    assert!(scratch
+        .run("flip@8", &["encrypt", "test.txt"])
+        .status
+        .success());
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    assert_ne!(exit_code(&scratch.run("", &["decrypt", "test.txt.enc"])), 0);
+
+    let output = scratch.run("", &["recover", "test.txt.enc", "--out", "fixed.enc"]);
+    assert!(output.status.success());
+    fs::rename(scratch.path("fixed.enc"), scratch.path("test.txt.enc")).unwrap();
+    assert!(scratch
+        .run("", &["decrypt", "test.txt.enc"])
+        .status
+        .success());
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+}
+
+#[test]
+fn abort_mid_write_leaves_no_output() {
+    let scratch = Scratch::new("abort-write");
+    // The process dies partway through the header; only the temporary file is left behind
+    assert!(aborted(&scratch.run("abort@50", &["encrypt", "test.txt"])));
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+    assert!(!scratch.exists("test.txt.enc"));
+    assert_eq!(temporary_files(&scratch), 1);
+
+    // An earlier output under the final name is kept whole
+    assert!(scratch.run("", &["encrypt", "test.txt"]).status.success());
+    let encrypted = scratch.read("test.txt.enc");
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    assert!(scratch
+        .run("", &["decrypt", "test.txt.enc"])
+        .status
+        .success());
+    assert!(aborted(
+        &scratch.run("abort@50", &["encrypt", "test.txt", "--force"])
+    ));
+    assert_eq!(scratch.read("test.txt.enc"), encrypted);
+}
+
+#[test]
+fn streamed_files_stay_all_or_nothing() {
+    let scratch = Scratch::new("stream");
+    let encrypt = [
+        "encrypt",
+        "test.txt",
+        "--stream-threshold",
+        "0",
+        "--chunk-size",
+        "256",
+        "--force",
+    ];
+    let output = scratch.run("enospc@1000", &encrypt);
+    assert_eq!(exit_code(&output), 3);
+    assert!(!scratch.exists("test.txt.enc"));
+    assert_eq!(temporary_files(&scratch), 0);
+
+    assert!(scratch.run("short-read", &encrypt).status.success());
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    assert!(scratch
+        .run("short-read", &["decrypt", "test.txt.enc"])
+        .status
+        .success());
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+
+    // The chunks before the flipped bit decrypt and are written before it is found, but are never kept
+    assert!(scratch.run("flip@3000", &encrypt).status.success());
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    assert_eq!(exit_code(&scratch.run("", &["decrypt", "test.txt.enc"])), 4);
+    assert!(!scratch.exists("test.txt"));
+    assert_eq!(temporary_files(&scratch), 0);
+}
+
+#[test]
+fn mapped_streams_stay_all_or_nothing() {
+    let scratch = Scratch::new("mmap");
+    let encrypt = [
+        "encrypt",
+        "test.txt",
+        "--stream-threshold",
+        "0",
+        "--chunk-size",
+        "256",
+        "--mmap",
+        "--force",
+    ];
+    // The output is buffered, so the failure only comes when the buffer is written out
+    let output = scratch.run("enospc@1000", &encrypt);
+    assert_eq!(exit_code(&output), 3);
+    assert!(!scratch.exists("test.txt.enc"));
+    assert_eq!(temporary_files(&scratch), 0);
+
+    assert!(scratch.run("short-read", &encrypt).status.success());
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    let decrypt = ["decrypt", "test.txt.enc", "--mmap"];
+    assert!(scratch.run("short-read", &decrypt).status.success());
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+
+    assert!(scratch.run("flip@3000", &encrypt).status.success());
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    assert_eq!(exit_code(&scratch.run("", &decrypt)), 4);
+    assert!(!scratch.exists("test.txt"));
+    assert_eq!(temporary_files(&scratch), 0);
+}
+
+#[test]
+fn every_io_buffer_size_stays_all_or_nothing() {
+    let scratch = Scratch::new("io-buffer");
+    // Off, smaller than a chunk, a few chunks, and larger than the whole output
+    for io_buffer in ["0", "100", "1K", "1M"] {
+        let encrypt = [
+            "encrypt",
+            "test.txt",
+            "--stream-threshold",
+            "0",
+            "--chunk-size",
+            "256",
+            "--io-buffer",
+            io_buffer,
+            "--force",
+        ];
+        let output = scratch.run("enospc@1000", &encrypt);
+        assert_eq!(exit_code(&output), 3, "--io-buffer {}", io_buffer);
+        assert!(!scratch.exists("test.txt.enc"));
+        assert_eq!(temporary_files(&scratch), 0);
+
+        assert!(scratch.run("short-read", &encrypt).status.success());
+        let decrypt = [
+            "decrypt",
+            "test.txt.enc",
+            "--out",
+            "decrypted.txt",
+            "--io-buffer",
+            io_buffer,
+            "--force",
+        ];
+        assert!(scratch.run("short-read", &decrypt).status.success());
+        assert_eq!(scratch.read("decrypted.txt"), PLAINTEXT.repeat(100));
+        fs::remove_file(scratch.path("test.txt.enc")).unwrap();
+    }
+}
+
+#[test]
+fn interrupted_stream_resumes_from_its_last_chunk() {
+    let scratch = Scratch::new("resume");
+    let encrypt = [
+        "encrypt",
+        "test.txt",
+        "--stream-threshold",
+        "0",
+        "--chunk-size",
+        "256",
+        "--resume",
+    ];
+    // The process dies partway through; what it wrote is kept as a partial output
+    assert!(aborted(&scratch.run("abort@3000", &encrypt)));
+    assert!(!scratch.exists("test.txt.enc"));
+    let partial = scratch.read("test.txt.enc.partial");
+    assert_eq!(partial.len(), 3000);
+
+    // Running it again continues the same output (a new one would have another salt and nonce)
+    assert!(scratch.run("", &encrypt).status.success());
+    assert!(!scratch.exists("test.txt.enc.partial"));
+    assert_eq!(scratch.read("test.txt.enc")[..2500], partial[..2500]);
+    assert_eq!(temporary_files(&scratch), 0);
+
+    let decrypt = ["decrypt", "test.txt.enc", "--resume"];
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    assert!(aborted(&scratch.run("abort@3000", &decrypt)));
+    assert!(!scratch.exists("test.txt"));
+    assert!(scratch.run("", &decrypt).status.success());
+    assert!(!scratch.exists("test.txt.partial"));
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+
+    // A partial output with other contents is reported and kept, rather than continued
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    assert!(aborted(&scratch.run("abort@3000", &decrypt)));
+    let mut partial = scratch.read("test.txt.partial");
+    partial[0] ^= 1;
+    fs::write(scratch.path("test.txt.partial"), &partial).unwrap();
+    assert_eq!(exit_code(&scratch.run("", &decrypt)), 3);
+    assert!(!scratch.exists("test.txt"));
+    assert_eq!(scratch.read("test.txt.partial"), partial);
+}
+
+#[test]
+fn resume_starts_over_when_the_input_changed() {
+    let scratch = Scratch::new("resume-changed");
+    let encrypt = [
+        "encrypt",
+        "test.txt",
+        "--stream-threshold",
+        "0",
+        "--chunk-size",
+        "256",
+        "--resume",
+    ];
+    assert!(aborted(&scratch.run("abort@3000", &encrypt)));
+    let partial = scratch.read("test.txt.enc.partial");
+
+    // Only the first chunk changes, and the file keeps its length; continuing after the kept chunks would seal the
+    // rest with the nonces they were sealed with
+    let mut changed = PLAINTEXT.repeat(100);
+    changed[10] ^= 1;
+    fs::write(scratch.path("test.txt"), &changed).unwrap();
+    assert!(scratch.run("", &encrypt).status.success());
+    assert!(!scratch.exists("test.txt.enc.partial"));
+    let output = scratch.read("test.txt.enc");
+    assert_ne!(output[..64], partial[..64]);
+
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    assert!(scratch
+        .run("", &["decrypt", "test.txt.enc"])
+        .status
+        .success());
+    assert_eq!(scratch.read("test.txt"), changed);
+}
+
+#[test]
+fn resume_starts_over_when_the_cut_off_chunk_changed() {
+    let scratch = Scratch::new("resume-cut-off");
+    let encrypt = [
+        "encrypt",
+        "test.txt",
+        "--stream-threshold",
+        "0",
+        "--chunk-size",
+        "256",
+        "--resume",
+    ];
+    assert!(aborted(&scratch.run("abort@3000", &encrypt)));
+    let partial = scratch.read("test.txt.enc.partial");
+    let info = scratch.run("", &["info", "test.txt.enc.partial", "--raw"]);
+    let header_len: usize = String::from_utf8_lossy(&info.stdout)
+        .split("header:")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|len| len.parse().ok())
+        .unwrap_or_else(|| panic!("no header length in {:?}", info));
+
+    // The complete chunks keep their contents, but the chunk cut off partway through changes: sealing it again
+    // under its nonce would seal two contents
+    let (complete, cut_off) = ((3000 - header_len) / 272, (3000 - header_len) % 272);
+    assert!(cut_off > 0);
+    let mut changed = PLAINTEXT.repeat(100);
+    changed[complete * 256] ^= 1;
+    fs::write(scratch.path("test.txt"), &changed).unwrap();
+    assert!(scratch.run("", &encrypt).status.success());
+    let output = scratch.read("test.txt.enc");
+    assert_ne!(output[..header_len], partial[..header_len]);
+
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    assert!(scratch
+        .run("", &["decrypt", "test.txt.enc"])
+        .status
+        .success());
+    assert_eq!(scratch.read("test.txt"), changed);
+}
+
+#[test]
+fn corrupt_chunk_is_reported_with_its_offset() {
+    let scratch = Scratch::new("corrupt-chunk");
+    let encrypt = [
+        "encrypt",
+        "test.txt",
+        "--stream-threshold",
+        "0",
+        "--chunk-size",
+        "256",
+    ];
+    assert!(scratch.run("flip@3000", &encrypt).status.success());
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+
+    let output = scratch.run("", &["verify", "test.txt.enc"]);
+    assert_eq!(exit_code(&output), 4);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let (start, end): (u64, u64) = stderr
+        .split("at bytes ")
+        .nth(1)
+        .and_then(|rest| rest.split(')').next())
+        .and_then(|range| range.split_once('-'))
+        .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)))
+        .unwrap_or_else(|| panic!("no chunk range in {:?}", stderr));
+    // The flipped byte is inside the one reported chunk of 256 bytes and its 16-byte tag
+    assert!(start <= 3000 && 3000 <= end, "{}", stderr);
+    assert_eq!(end - start + 1, 256 + 16, "{}", stderr);
+    assert!(
+        stderr.contains("1 chunk failed authentication"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("the other chunks are intact"), "{}", stderr);
+}
+
+#[test]
+fn interrupted_rekey_keeps_the_old_file() {
+    let scratch = Scratch::new("rekey");
+    fs::write(scratch.path("new-pass"), "battery staple\n").unwrap();
+    let encrypt = [
+        "encrypt",
+        "test.txt",
+        "--stream-threshold",
+        "0",
+        "--chunk-size",
+        "256",
+    ];
+    assert!(scratch.run("", &encrypt).status.success());
+    let encrypted = scratch.read("test.txt.enc");
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+
+    // Running out of space partway through the chunks leaves the file as it was, under the old password
+    let rekey = ["rekey", "file:pass", "file:new-pass", "test.txt.enc"];
+    assert_eq!(exit_code(&scratch.run("enospc@1000", &rekey)), 3);
+    assert_eq!(scratch.read("test.txt.enc"), encrypted);
+    assert_eq!(temporary_files(&scratch), 0);
+
+    // Once it goes through, only the new password opens the file
+    assert!(scratch.run("short-read", &rekey).status.success());
+    assert_eq!(exit_code(&scratch.run("", &["verify", "test.txt.enc"])), 13);
+    fs::rename(scratch.path("new-pass"), scratch.path("pass")).unwrap();
+    assert!(scratch
+        .run("", &["decrypt", "test.txt.enc"])
+        .status
+        .success());
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+}
+
+#[test]
+fn rekey_of_a_wrapped_key_only_rewrites_the_header() {
+    let scratch = Scratch::new("rewrap");
+    fs::write(scratch.path("new-pass"), "battery staple\n").unwrap();
+    let encrypt = [
+        "encrypt",
+        "test.txt",
+        "--wrap-key",
+        "--stream-threshold",
+        "0",
+        "--chunk-size",
+        "256",
+    ];
+    assert!(scratch.run("", &encrypt).status.success());
+    let encrypted = scratch.read("test.txt.enc");
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+
+    // Both headers wrap the key for one password, so they are as long as each other, and the chunks between the
+    // header and its backup are copied byte for byte
+    let rekey = ["rekey", "file:pass", "file:new-pass", "test.txt.enc"];
+    assert!(scratch.run("", &rekey).status.success());
+    let rekeyed = scratch.read("test.txt.enc");
+    let export = scratch.run("", &["header", "export", "test.txt.enc"]);
+    let header: serde_json::Value = serde_json::from_slice(&export.stdout).unwrap();
+    let header_len = header["header_len"].as_u64().unwrap() as usize;
+    assert!(header["rewrappable"].as_bool().unwrap());
+    assert_eq!(rekeyed.len(), encrypted.len());
+    let chunks = header_len..encrypted.len() - header_len - 4;
+    assert_eq!(rekeyed[chunks.clone()], encrypted[chunks]);
+    assert_ne!(rekeyed[..header_len], encrypted[..header_len]);
+
+    fs::rename(scratch.path("new-pass"), scratch.path("pass")).unwrap();
+    assert!(scratch
+        .run("", &["decrypt", "test.txt.enc"])
+        .status
+        .success());
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+}
+
+#[test]
+fn repeated_nonce_under_one_key_is_refused() {
+    let scratch = Scratch::new("nonce");
+    fs::write(scratch.path("key"), [7u8; 32]).unwrap();
+    fs::write(scratch.path("other.txt"), b"other").unwrap();
+    let output = scratch.run(
+        "repeat-nonce",
+        &[
+            "encrypt",
+            "test.txt",
+            "other.txt",
+            "--keyfile",
+            "key",
+            "--jobs",
+            "1",
+        ],
+    );
+    assert_eq!(exit_code(&output), 15);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ENC_NONCE_REUSE"));
+    assert!(scratch.exists("test.txt.enc"));
+    assert!(!scratch.exists("other.txt.enc"));
+    assert_eq!(temporary_files(&scratch), 0);
+}
+
+#[test]
+fn repeated_nonce_prefix_of_a_chunked_payload_is_refused() {
+    let scratch = Scratch::new("nonce-prefix");
+    fs::write(scratch.path("key"), [7u8; 32]).unwrap();
+    fs::write(scratch.path("other.txt"), PLAINTEXT).unwrap();
+    // test.txt is chunked, and other.txt is sealed in one piece under a nonce that starts with the prefix every
+    // chunk of test.txt was derived from
+    let stream = [
+        "--stream-threshold",
+        "1K",
+        "--chunk-size",
+        "64",
+        "--jobs",
+        "1",
+    ];
+    let with_key = ["encrypt", "test.txt", "other.txt", "--keyfile", "key"];
+    let output = scratch.run("repeat-nonce", &[&with_key[..], &stream].concat());
+    assert_eq!(exit_code(&output), 15);
+    assert!(scratch.exists("test.txt.enc"));
+    assert!(!scratch.exists("other.txt.enc"));
+
+    // With a password every file gets its own key, so the same nonces are harmless
+    fs::remove_file(scratch.path("test.txt.enc")).unwrap();
+    let with_password = ["encrypt", "test.txt", "other.txt"];
+    let output = scratch.run("repeat-nonce", &[&with_password[..], &stream].concat());
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+// How many `*.tmp` files an interrupted write left in the scratch directory
+fn temporary_files(scratch: &Scratch) -> usize {
+    fs::read_dir(&scratch.dir)
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .ends_with(".tmp")
+        })
+        .count()
+}
+
+#[test]
+fn shred_batch_resumes_after_abort() {
+    let scratch = Scratch::new("resume");
+    fs::write(scratch.path("other.txt"), b"other").unwrap();
+    let output = scratch.run(
+        "abort:journal-verified",
+        &["encrypt", "test.txt", "other.txt", "--shred", "--jobs", "1"],
+    );
+    assert!(aborted(&output));
+    assert!(scratch.exists("encryptor.journal"));
+    assert!(scratch.exists("test.txt"));
+
+    let output = scratch.run("", &["resume-journal", "encryptor.journal"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!scratch.exists("encryptor.journal"));
+    assert!(!scratch.exists("test.txt"));
+    assert!(!scratch.exists("other.txt"));
+
+    assert!(scratch
+        .run("", &["decrypt", "test.txt.enc", "other.txt.enc"])
+        .status
+        .success());
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+    assert_eq!(scratch.read("other.txt"), b"other");
+}
+
+#[test]
+fn shred_batch_rolls_back_after_abort_mid_shred() {
+    let scratch = Scratch::new("rollback");
+    // The original is overwritten with random data but not yet removed
+    assert!(aborted(
+        &scratch.run("abort:shred", &["encrypt", "test.txt", "--shred"])
+    ));
+    let overwritten = scratch.read("test.txt");
+    assert_eq!(overwritten.len(), PLAINTEXT.len() * 100);
+    assert_ne!(overwritten, PLAINTEXT.repeat(100));
+
+    let output = scratch.run("", &["resume-journal", "encryptor.journal", "--rollback"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+    assert!(!scratch.exists("test.txt.enc"));
+    assert!(!scratch.exists("encryptor.journal"));
+}
+
+#[test]
+fn interrupted_rotation_resumes_where_it_stopped() {
+    let scratch = Scratch::new("rotate");
+    fs::write(scratch.path("new-pass"), "battery staple\n").unwrap();
+    fs::write(scratch.path("short.txt"), PLAINTEXT).unwrap();
+    let encrypt = ["encrypt", "short.txt", "test.txt", "--key-id", "2025"];
+    assert!(scratch.run("", &encrypt).status.success());
+    fs::remove_file(scratch.path("short.txt")).unwrap();
+    fs::remove_file(scratch.path("test.txt")).unwrap();
+    let key_id = |name: &str| {
+        let info = scratch.run("", &["info", name]);
+        String::from_utf8(info.stdout)
+            .unwrap()
+            .contains("key ID:        2026")
+    };
+
+    // Only the output longer than the fault's offset fails, and stays under the old key
+    let rotate = [
+        "rotate",
+        "file:pass",
+        "file:new-pass",
+        ".",
+        "--key-id",
+        "2026",
+        "--jobs",
+        "1",
+    ];
+    assert_eq!(exit_code(&scratch.run("enospc@1000", &rotate)), 3);
+    assert!(key_id("short.txt.enc"));
+    assert!(!key_id("test.txt.enc"));
+    assert_eq!(temporary_files(&scratch), 0);
+
+    // Running it again picks up only the file that wasn't rotated
+    let output = scratch.run("", &rotate);
+    assert!(output.status.success());
+    let status = String::from_utf8(output.stdout).unwrap();
+    assert!(status.contains("Rotated ./test.txt.enc"));
+    assert!(!status.contains("short.txt.enc"));
+    assert!(key_id("test.txt.enc"));
+    fs::rename(scratch.path("new-pass"), scratch.path("pass")).unwrap();
+    assert!(scratch
+        .run("", &["decrypt", "short.txt.enc", "test.txt.enc"])
+        .status
+        .success());
+    assert_eq!(scratch.read("test.txt"), PLAINTEXT.repeat(100));
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_removes_the_unfinished_output() {
+    let scratch = Scratch::new("interrupt");
+    fs::write(scratch.path("big.txt"), PLAINTEXT.repeat(100_000)).unwrap();
+    // Short reads slow the run down enough to interrupt it while the output is being written
+    let child = scratch
+        .encryptor(&["encrypt", "big.txt", "test.txt", "--jobs", "1"])
+        .env("ENCRYPTOR_FAULTS", "short-read")
+        .args(["--stream-threshold", "0", "--passfile", "pass"])
+        .args(["--kdf-memory", "1024"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    while temporary_files(&scratch) == 0 {
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    let killed = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(exit_code(&output), 7, "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Interrupted: 2 of 2 files weren't finished"),
+        "{}",
+        stderr
+    );
+    assert!(!scratch.exists("big.txt.enc"));
+    assert!(!scratch.exists("test.txt.enc"));
+    assert_eq!(temporary_files(&scratch), 0);
+}
@@ -0,0 +1,87 @@
+//! Calls the C interface of the `ffi` module the way a C application would, checking that its buffers round trip
+//! with the library, and that failures return the error code and a description.
+//! Run with `cargo test --features ffi`.
+#![cfg(feature = "ffi")]
+
+use encryptor::ffi::*;
+use encryptor::{Encryptor, ErrorCode};
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+const PASSWORD: &CStr = c"correct horse";
+
+// Call `function` on `input`, returning its status and the output it allocated (freed again)
+fn call(
+    function: unsafe extern "C" fn(
+        *const c_char,
+        *const u8,
+        usize,
+        *mut *mut u8,
+        *mut usize,
+    ) -> i32,
+    password: &CStr,
+    input: &[u8],
+) -> (i32, Vec<u8>) {
+    let mut output = ptr::null_mut();
+    let mut output_len = 0;
+    unsafe {
+        let status = function(
+            password.as_ptr(),
+            input.as_ptr(),
+            input.len(),
+            &mut output,
+            &mut output_len,
+        );
+        if output.is_null() {
+            return (status, Vec::new());
+        }
+        let bytes = std::slice::from_raw_parts(output, output_len).to_vec();
+        encryptor_free_buf(output, output_len);
+        (status, bytes)
+    }
+}
+
+fn last_error() -> String {
+    unsafe { CStr::from_ptr(encryptor_last_error()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn buffers_round_trip_with_the_library() {
+    let (status, sealed) = call(encryptor_encrypt_buf, PASSWORD, b"hello from C");
+    assert_eq!(status, ENCRYPTOR_OK);
+    let encryptor = Encryptor::new("correct horse");
+    assert_eq!(encryptor.decrypt_bytes(&sealed).unwrap(), b"hello from C");
+
+    let sealed = encryptor.encrypt_bytes(b"").unwrap();
+    assert_eq!(
+        call(encryptor_decrypt_buf, PASSWORD, &sealed),
+        (ENCRYPTOR_OK, Vec::new())
+    );
+}
+
+#[test]
+fn failures_return_the_error_code_and_a_description() {
+    let mut sealed = Encryptor::new("correct horse")
+        .encrypt_bytes(b"hello")
+        .unwrap();
+    let (status, output) = call(encryptor_decrypt_buf, c"wrong horse", &sealed);
+    assert_eq!(status, ErrorCode::WrongPassword.number());
+    assert!(output.is_empty());
+    assert!(
+        last_error().starts_with("ENC_WRONG_PASSWORD"),
+        "{}",
+        last_error()
+    );
+
+    sealed.truncate(10);
+    let (status, _) = call(encryptor_decrypt_buf, PASSWORD, &sealed);
+    assert_eq!(status, ErrorCode::BadHeader.number());
+
+    let mut output = ptr::null_mut();
+    let status = unsafe { encryptor_encrypt_buf(ptr::null(), ptr::null(), 0, &mut output, &mut 0) };
+    assert_eq!(status, ENCRYPTOR_USAGE);
+    assert!(output.is_null());
+    assert!(last_error().contains("password"), "{}", last_error());
+}
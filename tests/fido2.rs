@@ -0,0 +1,107 @@
+//! Encrypts and decrypts with a software stand-in for a FIDO2 security key, checking that the file key is wrapped
+//! in a stanza for the credential, that a batch asks the security key once, and that another credential or another
+//! security key can't open the files.
+#![cfg(feature = "fido2")]
+
+use blake2::digest::Mac;
+use blake2::Blake2sMac256;
+use encryptor::fido2::{Authenticator, SALT_LEN};
+use encryptor::header::{Header, StanzaKind};
+use encryptor::{EncryptError, Encryptor, KdfParams};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+const CREDENTIAL: &[u8] = b"credential on the first key";
+
+// A security key holding one credential, whose hmac-secret is a MAC of the salt under a secret of its own
+struct SoftKey {
+    secret: [u8; 32],
+    touches: AtomicUsize,
+}
+
+impl SoftKey {
+    fn new(secret: u8) -> Arc<Self> {
+        Arc::new(SoftKey {
+            secret: [secret; 32],
+            touches: AtomicUsize::new(0),
+        })
+    }
+
+    fn touches(&self) -> usize {
+        self.touches.load(Ordering::SeqCst)
+    }
+}
+
+impl Authenticator for SoftKey {
+    fn hmac_secret(
+        &self,
+        credential: &[u8],
+        salt: &[u8; SALT_LEN],
+    ) -> Result<Zeroizing<[u8; 32]>, EncryptError> {
+        self.touches.fetch_add(1, Ordering::SeqCst);
+        let mut mac = Blake2sMac256::new_from_slice(&self.secret).unwrap();
+        mac.update(credential);
+        mac.update(salt);
+        Ok(Zeroizing::new(mac.finalize().into_bytes().into()))
+    }
+}
+
+#[test]
+fn a_batch_asks_the_security_key_once() {
+    let key = SoftKey::new(1);
+    let encryptor = Encryptor::from_fido2(CREDENTIAL.to_vec(), key.clone());
+    let first = encryptor.encrypt_bytes(b"first file").unwrap();
+    let second = encryptor.clone().encrypt_bytes(b"second file").unwrap();
+    assert_eq!(key.touches(), 1);
+
+    let (header, _) = Header::parse(&first).unwrap();
+    assert_eq!(header.recipients.len(), 1);
+    assert_eq!(header.recipients[0].kind, StanzaKind::Fido2);
+    assert!(header.recipients[0].body.ends_with(CREDENTIAL));
+
+    // Another run draws no salt of its own to decrypt, so both files need one touch again
+    let key = SoftKey::new(1);
+    let decryptor = Encryptor::from_fido2(CREDENTIAL.to_vec(), key.clone());
+    assert_eq!(decryptor.decrypt_bytes(&first).unwrap(), b"first file");
+    assert_eq!(decryptor.decrypt_bytes(&second).unwrap(), b"second file");
+    assert_eq!(key.touches(), 1);
+}
+
+#[test]
+fn only_the_same_credential_on_the_same_key_decrypts() {
+    let sealed = Encryptor::from_fido2(CREDENTIAL.to_vec(), SoftKey::new(1))
+        .encrypt_bytes(b"secret")
+        .unwrap();
+
+    let other_credential = Encryptor::from_fido2(b"another credential".to_vec(), SoftKey::new(1));
+    assert!(matches!(
+        other_credential.decrypt_bytes(&sealed),
+        Err(EncryptError::KeyModeMismatch(_))
+    ));
+    let other_key = Encryptor::from_fido2(CREDENTIAL.to_vec(), SoftKey::new(2));
+    assert!(matches!(
+        other_key.decrypt_bytes(&sealed),
+        Err(EncryptError::AeadError(_))
+    ));
+    let password = Encryptor::new("correct horse");
+    assert!(password.decrypt_bytes(&sealed).is_err());
+}
+
+#[test]
+fn a_password_can_back_up_the_security_key() {
+    let key = SoftKey::new(1);
+    let sealed = Encryptor::from_fido2(CREDENTIAL.to_vec(), key.clone())
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 1,
+        })
+        .with_extra_password("correct horse")
+        .encrypt_bytes(b"secret")
+        .unwrap();
+
+    let password = Encryptor::new("correct horse");
+    assert_eq!(password.decrypt_bytes(&sealed).unwrap(), b"secret");
+    let fido2 = Encryptor::from_fido2(CREDENTIAL.to_vec(), key);
+    assert_eq!(fido2.decrypt_bytes(&sealed).unwrap(), b"secret");
+}
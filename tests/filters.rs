@@ -0,0 +1,136 @@
+//! Packs a project tree with `encryptor encrypt --archive` and the --exclude, --include and --respect-gitignore
+//! filters in a scratch directory, and checks which files the extracted archive holds.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+// A scratch directory holding a small project
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("filters", name);
+        for (path, contents) in [
+            ("project/src/main.rs", "fn main() {}\n"),
+            ("project/src/main.o", "object\n"),
+            ("project/target/debug/app", "binary\n"),
+            ("project/vendor/target/notes.md", "not a build directory\n"),
+            ("project/docs/guide.pdf", "pdf\n"),
+            ("project/docs/draft.txt", "draft\n"),
+            ("project/docs/keep.txt", "kept\n"),
+            ("project/.git/HEAD", "ref: refs/heads/main\n"),
+            ("project/.gitignore", "*.o\n/target\n"),
+            ("project/docs/.gitignore", "*.txt\n!keep.txt\n"),
+        ] {
+            let path = scratch.dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args).output().unwrap()
+    }
+
+    // Archive the project with `filters`, and list the files it extracts to, under the project
+    fn archived(&self, filters: &[&str]) -> Vec<String> {
+        let _ = fs::remove_file(self.dir.join("project.enc"));
+        let _ = fs::remove_dir_all(self.dir.join("restored"));
+        let mut args = vec!["encrypt", "--archive", "project", "--passfile", "pass"];
+        args.extend(["--kdf-memory", "1024", "--kdf-iterations", "1"]);
+        args.extend(filters);
+        let output = self.run(&args);
+        assert!(output.status.success(), "{:?}", output);
+        let output = self.run(&[
+            "decrypt",
+            "--extract",
+            "project.enc",
+            "--out",
+            "restored",
+            "--passfile",
+            "pass",
+        ]);
+        assert!(output.status.success(), "{:?}", output);
+        let mut files = Vec::new();
+        list(&self.dir.join("restored/project"), "", &mut files);
+        files.sort();
+        files
+    }
+}
+
+// The files and directories under `dir`, directories with a trailing slash
+fn list(dir: &Path, prefix: &str, files: &mut Vec<String>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let entry = entry.unwrap();
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        match entry.file_type().unwrap().is_dir() {
+            true => {
+                files.push(format!("{}/", name));
+                list(&entry.path(), &format!("{}/", name), files);
+            }
+            false => files.push(name),
+        }
+    }
+}
+
+#[test]
+fn excluded_and_not_included_files_are_left_out() {
+    let scratch = Scratch::new("globs");
+    let files = scratch.archived(&[
+        "--exclude",
+        "*.o",
+        "--exclude",
+        "target/**",
+        "--exclude",
+        ".git/",
+    ]);
+    assert_eq!(
+        files,
+        [
+            ".gitignore",
+            "docs/",
+            "docs/.gitignore",
+            "docs/draft.txt",
+            "docs/guide.pdf",
+            "docs/keep.txt",
+            "src/",
+            "src/main.rs",
+            "vendor/",
+            "vendor/target/",
+            "vendor/target/notes.md",
+        ]
+    );
+
+    // Directories left without files aren't packed
+    let files = scratch.archived(&["--include", "*.pdf", "--include", "src/*.rs"]);
+    assert_eq!(files, ["docs/", "docs/guide.pdf", "src/", "src/main.rs"]);
+
+    let output = scratch.run(&["encrypt", "project/src/main.rs", "--exclude", "*.o"]);
+    assert!(!output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn gitignore_files_are_respected() {
+    let scratch = Scratch::new("gitignore");
+    let files = scratch.archived(&["--respect-gitignore"]);
+    assert_eq!(
+        files,
+        [
+            ".gitignore",
+            "docs/",
+            "docs/.gitignore",
+            "docs/guide.pdf",
+            "docs/keep.txt",
+            "src/",
+            "src/main.rs",
+            "vendor/",
+            "vendor/target/",
+            "vendor/target/notes.md",
+        ]
+    );
+}
@@ -0,0 +1,94 @@
+// Writes the compatibility fixtures of one format version with the library it is built against.
+// regenerate.sh builds it against the last commit that wrote each version, with the features that version has:
+// `compression` (version 2 on), `v3` (version 3 on: raw keys, recipients, header backups, armor and chunked
+// payloads), `aes128` (version 4 on: AES-128-GCM) and `v5` (version 5 on: when the password was set).
+//
+// Usage: generate <version> <fixtures directory>
+use encryptor::{Algorithm, Encryptor, KdfParams};
+use std::{env, fs};
+
+// The secrets tests/compat.rs decrypts the fixtures with
+const PASSWORD: &str = "fixture password";
+#[cfg(feature = "v3")]
+const KEY: [u8; 32] = [7; 32];
+#[cfg(feature = "v3")]
+const IDENTITY: [u8; 32] = [9; 32];
+#[cfg(feature = "v5")]
+const PASSWORD_SET: u64 = 1577836800; // 2020-01-01
+// Cheap, so the tests stay fast
+const PARAMS: KdfParams = KdfParams {
+    memory: 1024,
+    iterations: 1,
+};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let [_, version, dir] = &args[..] else {
+        panic!("usage: generate <version> <fixtures directory>");
+    };
+    let plaintext = fs::read(format!("{}/plaintext.txt", dir)).unwrap();
+    let write = |name: &str, encryptor: Encryptor| {
+        let sealed = encryptor.encrypt_bytes(&plaintext).unwrap();
+        fs::write(format!("{}/v{}-{}.enc", dir, version, name), sealed).unwrap();
+    };
+
+    #[allow(unused_mut)]
+    let mut algorithms = vec![
+        ("aes256gcm", Algorithm::Aes256Gcm),
+        ("chacha20poly1305", Algorithm::ChaCha20Poly1305),
+        ("aes256gcmsiv", Algorithm::Aes256GcmSiv),
+    ];
+    #[cfg(feature = "aes128")]
+    algorithms.push(("aes128gcm", Algorithm::Aes128Gcm));
+    for (name, algorithm) in algorithms {
+        let password = || {
+            Encryptor::new(PASSWORD)
+                .with_algorithm(algorithm)
+                .with_kdf_params(PARAMS)
+        };
+        write(name, password());
+
+        #[cfg(feature = "compression")]
+        for codec in ["zstd", "lz4", "brotli"] {
+            let compression = encryptor::Compression::parse(codec).unwrap();
+            write(
+                &format!("{}-{}", name, codec),
+                password().with_compression(compression),
+            );
+        }
+
+        #[cfg(feature = "v3")]
+        {
+            use encryptor::recipient::Identity;
+
+            let recipient = Identity::from_bytes(IDENTITY).recipient();
+            write(
+                &format!("{}-key", name),
+                Encryptor::from_key(KEY).with_algorithm(algorithm),
+            );
+            write(
+                &format!("{}-recipient", name),
+                Encryptor::for_recipients(vec![recipient.clone()]).with_algorithm(algorithm),
+            );
+            write(
+                &format!("{}-password-and-recipient", name),
+                password().with_extra_recipients(vec![recipient]),
+            );
+            write(
+                &format!("{}-no-backup", name),
+                password().with_header_backup(false),
+            );
+            write(&format!("{}-armor", name), password().with_armor(true));
+            write(
+                &format!("{}-chunked", name),
+                password().with_stream_threshold(0).with_chunk_size(64),
+            );
+        }
+
+        #[cfg(feature = "v5")]
+        write(
+            &format!("{}-password-set", name),
+            password().with_password_set(PASSWORD_SET),
+        );
+    }
+}
@@ -0,0 +1,63 @@
+#!/bin/sh
+# Regenerates the compatibility fixtures checked by tests/compat.rs. Every format version is written by the last
+# commit that wrote it, so the fixtures are exactly what those builds produced; the current version is written by
+# the working tree. When the format version is bumped, pin the previous one to its last commit below.
+#
+# Usage, from the repository root: tests/fixtures/regenerate.sh
+set -eu
+
+root=$(pwd)
+fixtures="$root/tests/fixtures"
+work=$(mktemp -d)
+trap 'rm -rf "$work"; git -C "$root" worktree prune' EXIT
+
+# generate <version> <commit, or "current"> <generator features>
+generate() {
+    if [ "$2" = current ]; then
+        library="$root"
+    else
+        library="$work/v$1-library"
+        git -C "$root" worktree add --quiet --detach "$library" "$2"
+    fi
+
+    crate="$work/v$1-generate"
+    mkdir -p "$crate/src"
+    cp "$fixtures/generate.rs" "$crate/src/main.rs"
+    # Cargo.lock isn't checked in; starting from the current one keeps dependency versions close to it
+    [ -f "$root/Cargo.lock" ] && cp "$root/Cargo.lock" "$crate/Cargo.lock"
+    cat > "$crate/Cargo.toml" <<TOML
+[package]
+name = "generate"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+encryptor = { path = "$library" }
+
+[features]
+compression = []
+v3 = []
+aes128 = []
+v5 = []
+TOML
+
+    rm -f "$fixtures"/v"$1"-*.enc
+    cargo run --quiet --manifest-path "$crate/Cargo.toml" --features "$3" -- "$1" "$fixtures"
+}
+
+# The first releases had no header or library, so their output is written by the command line of the baseline
+legacy() {
+    library="$work/v0-library"
+    git -C "$root" worktree add --quiet --detach "$library" 08b1f71
+    cp "$fixtures/plaintext.txt" "$library/plaintext.txt"
+    (cd "$library" && cargo run --quiet -- encrypt 12345678901234567890123456789012 plaintext.txt \
+        "[246, 231, 118, 136, 232, 16, 173, 214, 11, 241, 220, 114]")
+    cp "$library/plaintext.txt.enc" "$fixtures/v0-aes256gcm.legacy"
+}
+
+legacy
+generate 1 2c23c10 ""
+generate 2 8748e2e "compression"
+generate 3 80f9664 "compression v3"
+generate 4 6cd8090 "compression v3 aes128"
+generate 5 current "compression v3 aes128 v5"
@@ -0,0 +1,73 @@
+//! Runs `encryptor git-filter` the way git does, contents on stdin and out on stdout, to check that cleaning is
+//! deterministic per path, that smudging restores the contents, and that contents already in the other form pass
+//! through unchanged.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::io::Write;
+use std::process::{Output, Stdio};
+
+// A scratch directory holding a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("git-filter", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn filter(&self, mode: &str, path: &str, input: &[u8]) -> Output {
+        let mut child = self
+            .encryptor(&["git-filter", mode, path, "--passfile", "pass"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    // The output of a filter that must succeed
+    fn run(&self, mode: &str, path: &str, input: &[u8]) -> Vec<u8> {
+        let output = self.filter(mode, path, input);
+        assert!(output.status.success(), "{:?}", output);
+        output.stdout
+    }
+}
+
+#[test]
+fn clean_is_deterministic_and_smudge_restores() {
+    let scratch = Scratch::new("roundtrip");
+    let contents = b"DATABASE_URL=postgres://app:hunter2@db/app\n";
+
+    let cleaned = scratch.run("clean", "config/.env", contents);
+    assert!(cleaned.starts_with(b"ENCR"));
+    assert!(!cleaned.windows(7).any(|window| window == b"hunter2"));
+    assert_eq!(scratch.run("clean", "config/.env", contents), cleaned);
+    // Another path, or other contents, seal differently
+    assert_ne!(scratch.run("clean", "other/.env", contents), cleaned);
+    assert_ne!(
+        scratch.run(
+            "clean",
+            "config/.env",
+            b"DATABASE_URL=postgres://app:hunter3@db/app\n"
+        ),
+        cleaned
+    );
+
+    assert_eq!(scratch.run("smudge", "config/.env", &cleaned), contents);
+    // Already encrypted contents aren't encrypted again, and plaintext blobs check out as they are
+    assert_eq!(scratch.run("clean", "config/.env", &cleaned), cleaned);
+    assert_eq!(
+        scratch.run("smudge", "README.md", b"# Notes\n"),
+        b"# Notes\n"
+    );
+
+    fs::write(scratch.dir.join("pass"), "another password\n").unwrap();
+    let output = scratch.filter("smudge", "config/.env", &cleaned);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
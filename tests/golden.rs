@@ -0,0 +1,132 @@
+//! Encrypts `tests/fixtures/plaintext.txt` with seeded randomness and a fixed creation time, and compares every
+//! output byte for byte with the golden file of the same name in `tests/fixtures/golden`, so a change to what the
+//! library writes shows up as a failing test rather than only as fixtures that still decrypt. When the format
+//! changes on purpose, rewrite the golden files with `ENCRYPTOR_BLESS=1 cargo test --features fixed-test-vectors
+//! --test golden` and review the diff.
+#![cfg(feature = "fixed-test-vectors")]
+
+use encryptor::rng::SeededRandom;
+use encryptor::{Algorithm, Encryptor, KdfParams};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const PASSWORD: &str = "fixture password";
+#[cfg(feature = "x25519")]
+const KEY: [u8; 32] = [7; 32];
+#[cfg(feature = "x25519")]
+const IDENTITY: [u8; 32] = [9; 32];
+const SEED: [u8; 32] = [42; 32];
+// 2026-01-01T00:00:00Z
+const TIME: u64 = 1_767_225_600;
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+// `encryptor` drawing from a fresh seeded source at a fixed time, so each output is the same on every run
+fn seeded(encryptor: Encryptor) -> Encryptor {
+    encryptor
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 1,
+        })
+        .with_rng(Arc::new(SeededRandom::new(SEED)))
+        .with_fixed_time(TIME)
+}
+
+// The golden outputs, by file name
+fn cases() -> Vec<(&'static str, Encryptor)> {
+    let password = || seeded(Encryptor::new(PASSWORD));
+    #[allow(unused_mut)]
+    let mut cases = vec![
+        ("aes256gcm.enc", password()),
+        (
+            "chacha20poly1305.enc",
+            password().with_algorithm(Algorithm::ChaCha20Poly1305),
+        ),
+        (
+            "aes256gcm-chunked.enc",
+            password().with_stream_threshold(0).with_chunk_size(64),
+        ),
+        ("aes256gcm-wrapped.enc", password().with_key_wrapping(true)),
+        (
+            "aes128gcm.enc",
+            password().with_algorithm(Algorithm::Aes128Gcm),
+        ),
+    ];
+    #[cfg(feature = "x25519")]
+    {
+        use encryptor::recipient::Identity;
+
+        let recipient = Identity::from_bytes(IDENTITY).recipient();
+        cases.push(("aes256gcm-key.enc", seeded(Encryptor::from_key(KEY))));
+        cases.push((
+            "aes256gcm-password-and-recipient.enc",
+            password().with_extra_recipients(vec![recipient]),
+        ));
+    }
+    cases
+}
+
+#[test]
+fn outputs_match_the_golden_files() {
+    let plaintext = fs::read(fixtures().join("plaintext.txt")).unwrap();
+    let golden = fixtures().join("golden");
+    let bless = std::env::var_os("ENCRYPTOR_BLESS").is_some();
+    for (name, encryptor) in cases() {
+        let sealed = encryptor.encrypt_bytes(&plaintext).unwrap();
+        assert_eq!(encryptor.decrypt_bytes(&sealed).unwrap(), plaintext);
+        let path = golden.join(name);
+        if bless {
+            fs::create_dir_all(&golden).unwrap();
+            fs::write(&path, &sealed).unwrap();
+            continue;
+        }
+        let expected = fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+        assert!(
+            sealed == expected,
+            "{} no longer matches what the library writes",
+            name
+        );
+    }
+}
+
+#[test]
+fn the_same_seed_gives_the_same_bytes() {
+    let plaintext = b"a short note";
+    let first = seeded(Encryptor::new(PASSWORD));
+    let again = seeded(Encryptor::new(PASSWORD));
+    assert_eq!(
+        first.encrypt_bytes(plaintext).unwrap(),
+        again.encrypt_bytes(plaintext).unwrap()
+    );
+    // The source moves on, so the next output of the same encryptor has a fresh nonce
+    assert_ne!(
+        first.encrypt_bytes(plaintext).unwrap(),
+        seeded(Encryptor::new(PASSWORD))
+            .encrypt_bytes(plaintext)
+            .unwrap()
+    );
+
+    // Files with random names get the same name too
+    let dir = std::env::temp_dir().join(format!("encryptor-golden-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut outputs = Vec::new();
+    for run in ["first", "again"] {
+        let file_path = dir.join(run).join("notes.txt");
+        fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+        fs::write(&file_path, plaintext).unwrap();
+        let output_path = seeded(Encryptor::new(PASSWORD))
+            .with_name_encryption(true)
+            .encrypt_file(&file_path)
+            .unwrap();
+        outputs.push((
+            output_path.file_name().unwrap().to_owned(),
+            fs::read(&output_path).unwrap(),
+        ));
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(outputs[0], outputs[1]);
+}
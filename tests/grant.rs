@@ -0,0 +1,165 @@
+//! Mints decryption grants with `grant::mint` and `encryptor grant`, and redeems them with `Encryptor::from_grant`
+//! and `decrypt --grant`, to check that a grant opens the files it lists for the operator it was minted for, and is
+//! refused with `ENC_GRANT` for other files, other operators, untrusted issuers and once it expired; and that the
+//! command line logs every attempt.
+#![cfg(feature = "grant")]
+
+mod common;
+
+use encryptor::grant::{self, Grant, IssuerKey};
+use encryptor::recipient::Identity;
+use encryptor::{Encryptor, ErrorCode, KdfParams};
+use std::time::Duration;
+
+const PASSWORD: &str = "correct horse battery staple";
+
+fn encryptor() -> Encryptor {
+    Encryptor::new(PASSWORD).with_kdf_params(KdfParams {
+        memory: 1024,
+        iterations: 2,
+    })
+}
+
+#[test]
+fn a_grant_opens_only_what_it_lists() {
+    let (issuer, operator) = (
+        IssuerKey::generate().unwrap(),
+        Identity::generate().unwrap(),
+    );
+    let listed = encryptor().encrypt_bytes(b"one\n").unwrap();
+    let unlisted = encryptor().encrypt_bytes(b"two\n").unwrap();
+    let token = grant::mint(
+        &encryptor(),
+        &[&listed],
+        &operator.recipient(),
+        &issuer,
+        "incident 42",
+        Duration::from_secs(3600),
+    )
+    .unwrap();
+
+    let verified = Grant::verify(&token, &[issuer.issuer()]).unwrap();
+    assert_eq!(verified.purpose(), "incident 42");
+    assert_eq!(verified.file_ids().count(), 1);
+    let redeemed = Encryptor::from_grant(verified, operator);
+    assert_eq!(redeemed.decrypt_bytes(&listed).unwrap(), b"one\n");
+    let err = redeemed.decrypt_bytes(&unlisted).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::Grant, "{}", err);
+
+    let stranger = Identity::generate().unwrap();
+    let grant = Grant::verify(&token, &[issuer.issuer()]).unwrap();
+    let err = Encryptor::from_grant(grant, stranger)
+        .decrypt_bytes(&listed)
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::Grant, "{}", err);
+
+    let untrusted = IssuerKey::generate().unwrap();
+    let err = Grant::verify(&token, &[untrusted.issuer()]).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::Grant, "{}", err);
+}
+
+#[test]
+fn an_expired_grant_is_refused() {
+    let (issuer, operator) = (
+        IssuerKey::generate().unwrap(),
+        Identity::generate().unwrap(),
+    );
+    let sealed = encryptor().encrypt_bytes(b"one\n").unwrap();
+    let token = grant::mint(
+        &encryptor(),
+        &[&sealed],
+        &operator.recipient(),
+        &issuer,
+        "incident 42",
+        Duration::from_secs(1),
+    )
+    .unwrap();
+    // The expiry is recorded in whole seconds
+    std::thread::sleep(Duration::from_secs(2));
+    let result = Grant::verify(&token, &[issuer.issuer()])
+        .and_then(|grant| Encryptor::from_grant(grant, operator).decrypt_bytes(&sealed));
+    let err = result.unwrap_err();
+    assert_eq!(err.code(), ErrorCode::Grant, "{}", err);
+    assert!(err.to_string().contains("expired"), "{}", err);
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn decrypt_grant_redeems_and_logs() {
+    use common::Scratch;
+    use std::fs;
+
+    let scratch = Scratch::create("grant", "cli");
+    fs::write(scratch.dir.join("a.txt"), "one\n").unwrap();
+    fs::write(scratch.dir.join("b.txt"), "two\n").unwrap();
+    fs::write(scratch.dir.join("pass"), format!("{}\n", PASSWORD)).unwrap();
+    let run = |args: &[&str]| {
+        let output = scratch.encryptor(args).output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+    };
+    run(&[
+        "encrypt",
+        "a.txt",
+        "b.txt",
+        "--kdf-memory",
+        "1024",
+        "--passfile",
+        "pass",
+    ]);
+    fs::remove_file(scratch.dir.join("a.txt")).unwrap();
+    fs::remove_file(scratch.dir.join("b.txt")).unwrap();
+    run(&["keygen", "--signing", "--out", "issuer.key"]);
+    run(&["keygen", "--asymmetric", "--out", "operator.key"]);
+    run(&[
+        "grant",
+        "a.txt.enc",
+        "--passfile",
+        "pass",
+        "--grant-to",
+        "operator.key.pub",
+        "--signing-key",
+        "issuer.key",
+        "--expires",
+        "1h",
+        "--purpose",
+        "incident 42",
+        "--out",
+        "grant.tok",
+    ]);
+
+    let redeem = |file: &str| {
+        scratch
+            .encryptor(&["decrypt", file, "--grant", "grant.tok"])
+            .args([
+                "--grant-issuer",
+                "issuer.key.pub",
+                "--identity",
+                "operator.key",
+            ])
+            .output()
+            .unwrap()
+    };
+    let output = redeem("a.txt.enc");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("a.txt")).unwrap(),
+        "one\n"
+    );
+    let output = redeem("b.txt.enc");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(12), "{:?}", output);
+    assert!(stderr.contains("ENC_GRANT"), "{}", stderr);
+    assert!(!scratch.dir.join("b.txt").exists());
+
+    let log = fs::read_to_string(scratch.dir.join("grant.tok.log")).unwrap();
+    let entries: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 2, "{}", log);
+    assert_eq!(entries[0]["file"], "a.txt.enc");
+    assert_eq!(entries[0]["purpose"], "incident 42");
+    assert_eq!(entries[0]["result"], "ok");
+    assert_eq!(entries[1]["file"], "b.txt.enc");
+    assert_eq!(entries[1]["result"], "ENC_GRANT");
+}
@@ -0,0 +1,39 @@
+//! Enables hardening, which is process-wide and can't be undone (hence a test binary of its own), and checks that
+//! encryption works as before with the secrets locked.
+#![cfg(feature = "harden")]
+
+use encryptor::{harden, Encryptor, KdfParams};
+
+#[test]
+fn encrypts_and_decrypts_with_hardening_enabled() {
+    assert!(!harden::is_enabled());
+    let hardening = harden::enable();
+    assert!(harden::is_enabled());
+    if cfg!(target_os = "linux") {
+        assert!(hardening.core_dumps_disabled);
+        assert!(hardening.undumpable);
+    }
+
+    // Locked or not (RLIMIT_MEMLOCK may be 0 here), the secrets work the same, in clones too
+    let encryptor = Encryptor::new("correct horse")
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 1,
+        })
+        .with_extra_password("battery staple");
+    let sealed = encryptor.encrypt_bytes(b"hello").unwrap();
+    assert_eq!(encryptor.clone().decrypt_bytes(&sealed).unwrap(), b"hello");
+    assert_eq!(
+        Encryptor::new("battery staple")
+            .decrypt_bytes(&sealed)
+            .unwrap(),
+        b"hello"
+    );
+    let key = encryptor::generate_key().unwrap();
+    let sealed = Encryptor::from_key(key).encrypt_bytes(b"hello").unwrap();
+    assert_eq!(
+        Encryptor::from_key(key).decrypt_bytes(&sealed).unwrap(),
+        b"hello"
+    );
+    harden::protect(b"not a secret, but locking it is harmless");
+}
@@ -0,0 +1,113 @@
+//! Exports the header of an encrypted file with `encryptor header export`, damages the file's header and grafts the
+//! exported one back with `header graft`, to check that the JSON holds the header's fields, that the grafted file
+//! decrypts again, and that a header edited in the JSON doesn't.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+// A scratch directory holding notes.txt.enc, without a header backup so the damaged header is the only one, its
+// header exported to header.json, and damaged.enc, a copy whose magic bytes were overwritten
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("header-json", name);
+        fs::write(scratch.dir.join("notes.txt"), "remember the milk\n").unwrap();
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        let output = scratch.run(&[
+            "encrypt",
+            "notes.txt",
+            "--kdf-memory",
+            "1024",
+            "--no-header-backup",
+        ]);
+        assert!(output.status.success(), "{:?}", output);
+        fs::remove_file(scratch.dir.join("notes.txt")).unwrap();
+
+        let output = scratch.run(&["header", "export", "notes.txt.enc"]);
+        assert!(output.status.success(), "{:?}", output);
+        fs::write(scratch.dir.join("header.json"), output.stdout).unwrap();
+
+        let mut sealed = fs::read(scratch.dir.join("notes.txt.enc")).unwrap();
+        sealed[..4].copy_from_slice(b"XXXX");
+        fs::write(scratch.dir.join("damaged.enc"), sealed).unwrap();
+        fs::remove_file(scratch.dir.join("notes.txt.enc")).unwrap();
+        scratch
+    }
+
+    // Run `encryptor <args>` in the directory, with --passfile pass for `decrypt`; `header` takes no password
+    fn run(&self, args: &[&str]) -> Output {
+        let mut command = self.encryptor(args);
+        if args[0] != "header" {
+            command.args(["--passfile", "pass"]);
+        }
+        command.output().unwrap()
+    }
+
+    fn header(&self) -> serde_json::Value {
+        serde_json::from_slice(&fs::read(self.dir.join("header.json")).unwrap()).unwrap()
+    }
+}
+
+#[test]
+fn the_export_holds_the_header() {
+    let scratch = Scratch::new("export");
+    let header = scratch.header();
+    assert_eq!(header["version"], 5);
+    assert_eq!(header["algorithm"], "aes256gcm");
+    assert_eq!(header["kdf"], "argon2id");
+    assert_eq!(header["kdf_params"]["memory"], 1024);
+    assert_eq!(header["file_name"], "notes.txt");
+    assert_eq!(header["payload_len"], 18);
+    assert_eq!(header["header_backup"], false);
+    assert_eq!(header["salt"].as_str().unwrap().len(), 32);
+    assert_eq!(header["nonce"].as_str().unwrap().len(), 24);
+}
+
+#[test]
+fn grafting_the_export_repairs_the_file() {
+    let scratch = Scratch::new("graft");
+    let output = scratch.run(&["decrypt", "damaged.enc", "--out", "notes.txt"]);
+    assert_eq!(output.status.code(), Some(6), "{:?}", output);
+
+    let output = scratch.run(&[
+        "header",
+        "graft",
+        "header.json",
+        "damaged.enc",
+        "--out",
+        "notes.txt.enc",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.run(&["decrypt", "notes.txt.enc"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("notes.txt")).unwrap(),
+        "remember the milk\n"
+    );
+}
+
+#[test]
+fn an_edited_header_doesnt_decrypt() {
+    let scratch = Scratch::new("edited");
+    let mut header = scratch.header();
+    header["kdf_params"]["iterations"] = 1.into();
+    fs::write(scratch.dir.join("header.json"), header.to_string()).unwrap();
+
+    let output = scratch.run(&[
+        "header",
+        "graft",
+        "header.json",
+        "damaged.enc",
+        "--out",
+        "notes.txt.enc",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.run(&["decrypt", "notes.txt.enc"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(13), "{:?}", output);
+    assert!(stderr.contains("ENC_WRONG_PASSWORD"), "{}", stderr);
+    assert!(!scratch.dir.join("notes.txt").exists());
+}
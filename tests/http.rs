@@ -0,0 +1,245 @@
+//! Runs `encryptor` against a minimal in-process web server, to check that files encrypted to `http://` URLs arrive in
+//! one request with a Content-Length and the headers given, decrypt and verify back from there, and that a refused
+//! or missing file is reported.
+#![cfg(feature = "http")]
+
+mod common;
+
+use common::Scratch;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+
+// The bodies stored, by path, and the method and headers of every upload
+#[derive(Default)]
+struct Store {
+    files: HashMap<String, Vec<u8>>,
+    uploads: Vec<(String, HashMap<String, String>)>,
+}
+
+// Serve files on a free port until the test ends, refusing requests without the bearer token
+fn serve() -> (String, Arc<Mutex<Store>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let store = Arc::new(Mutex::new(Store::default()));
+    let shared = Arc::clone(&store);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let store = Arc::clone(&shared);
+            std::thread::spawn(move || handle(stream, &store));
+        }
+    });
+    (endpoint, store)
+}
+
+fn handle(stream: TcpStream, store: &Mutex<Store>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut stream = stream;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut words = line.split_whitespace();
+        let method = words.next().unwrap_or_default().to_string();
+        let target = words.next().unwrap_or_default().to_string();
+        let mut headers = HashMap::new();
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+            }
+        }
+        let length = headers
+            .get("content-length")
+            .map_or(0, |length| length.parse().unwrap());
+        let mut body = vec![0; length];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+        let path = target.split_once('?').map_or(&*target, |(path, _)| path);
+        let mut store = store.lock().unwrap();
+        let authorized = headers.get("authorization").map(String::as_str) == Some("Bearer t0ken");
+        let (status, reply) = match method.as_str() {
+            _ if !authorized => ("401 Unauthorized", "no token".as_bytes().to_vec()),
+            "PUT" | "POST" => {
+                store.files.insert(path.to_string(), body);
+                store.uploads.push((method, headers));
+                ("201 Created", Vec::new())
+            }
+            _ => match store.files.get(path) {
+                Some(file) => ("200 OK", file.clone()),
+                None => ("404 Not Found", "no such file".as_bytes().to_vec()),
+            },
+        };
+        drop(store);
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n",
+            status,
+            reply.len()
+        );
+        if stream.write_all(head.as_bytes()).is_err() || stream.write_all(&reply).is_err() {
+            return;
+        }
+    }
+}
+
+// A scratch directory holding a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("http", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args)
+            .args(["--passfile", "pass"])
+            .output()
+            .unwrap()
+    }
+}
+
+#[test]
+fn encrypt_to_and_decrypt_from_a_url() {
+    let (endpoint, store) = serve();
+    let scratch = Scratch::new("roundtrip");
+    let small = b"The quick brown fox jumps over the lazy dog".to_vec();
+    // Several chunks, so the body is sent as it is encrypted
+    let large: Vec<u8> = (0..3 << 20).map(|i: u32| (i % 251) as u8).collect();
+    fs::write(scratch.dir.join("small notes.txt"), &small).unwrap();
+    fs::write(scratch.dir.join("large.bin"), &large).unwrap();
+
+    let share = format!("{}/dav/", endpoint);
+    let output = scratch.run(&[
+        "encrypt",
+        "small notes.txt",
+        "large.bin",
+        "--out",
+        &share,
+        "--http-header",
+        "Authorization: Bearer t0ken",
+        "--kdf-memory",
+        "1024",
+        "--kdf-iterations",
+        "1",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    {
+        let store = store.lock().unwrap();
+        assert_eq!(store.uploads.len(), 2);
+        for (method, headers) in &store.uploads {
+            assert_eq!(method, "PUT");
+            assert!(!headers.contains_key("transfer-encoding"), "{:?}", headers);
+        }
+        assert!(store.files["/dav/large.bin.enc"].len() > large.len());
+        // The name is percent-encoded in the URL
+        assert!(store.files.contains_key("/dav/small%20notes.txt.enc"));
+    }
+
+    let url = format!("{}/dav/large.bin.enc", endpoint);
+    let output = scratch.run(&[
+        "verify",
+        &url,
+        "--http-header",
+        "Authorization: Bearer t0ken",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.run(&[
+        "decrypt",
+        &format!("{}/dav/small%20notes.txt.enc", endpoint),
+        &url,
+        "--out",
+        "plain",
+        "--http-header",
+        "Authorization: Bearer t0ken",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read(scratch.dir.join("plain/small notes.txt")).unwrap(),
+        small
+    );
+    assert_eq!(
+        fs::read(scratch.dir.join("plain/large.bin")).unwrap(),
+        large
+    );
+}
+
+#[test]
+fn a_post_goes_to_the_url_as_given() {
+    let (endpoint, store) = serve();
+    let scratch = Scratch::new("post");
+    fs::write(scratch.dir.join("report.txt"), "quarterly numbers\n").unwrap();
+
+    let url = format!("{}/upload/report?signature=abc", endpoint);
+    let output = scratch.run(&[
+        "encrypt",
+        "report.txt",
+        "--out",
+        &url,
+        "--http-method",
+        "post",
+        "--http-header",
+        "Authorization: Bearer t0ken",
+        "--http-header",
+        "X-Upload-Note: nightly",
+        "--kdf-memory",
+        "1024",
+        "--kdf-iterations",
+        "1",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let store = store.lock().unwrap();
+    let (method, headers) = &store.uploads[0];
+    assert_eq!(method, "POST");
+    assert_eq!(headers["x-upload-note"], "nightly");
+    assert_eq!(
+        headers["content-length"],
+        store.files["/upload/report"].len().to_string()
+    );
+}
+
+#[test]
+fn refused_and_missing_files_fail() {
+    let (endpoint, _) = serve();
+    let scratch = Scratch::new("missing");
+    fs::write(scratch.dir.join("report.txt"), "quarterly numbers\n").unwrap();
+
+    // Without the token
+    let output = scratch.run(&[
+        "encrypt",
+        "report.txt",
+        "--out",
+        &format!("{}/dav/", endpoint),
+        "--kdf-memory",
+        "1024",
+        "--kdf-iterations",
+        "1",
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("401") && stderr.contains("no token"),
+        "{}",
+        stderr
+    );
+
+    let output = scratch.run(&[
+        "decrypt",
+        &format!("{}/dav/nothing.enc", endpoint),
+        "--http-header",
+        "Authorization: Bearer t0ken",
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("404"), "{}", stderr);
+    assert!(!scratch.dir.join("nothing").exists());
+}
@@ -0,0 +1,119 @@
+//! Runs `encryptor encrypt --incremental` on a scratch tree more than once, to check that only new and changed files
+//! are encrypted again and that --delete removes the outputs of deleted ones.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Output;
+
+// A scratch directory with a tree to back up and a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("incremental", name);
+        fs::create_dir_all(scratch.dir.join("tree/sub")).unwrap();
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        fs::write(scratch.dir.join("tree/a.txt"), "first file\n").unwrap();
+        fs::write(scratch.dir.join("tree/sub/b.txt"), "second file\n").unwrap();
+        scratch
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    // `encryptor encrypt --incremental tree --out backup` with `extra` flags
+    fn backup(&self, extra: &[&str]) -> Output {
+        let output = self
+            .encryptor(&["encrypt", "tree", "--incremental", "--state", "state.db"])
+            .args(["--out", "backup", "--passfile", "pass"])
+            .args(["--kdf-memory", "1024", "--kdf-iterations", "1"])
+            .args(extra)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        output
+    }
+
+    fn decrypt(&self, name: &str) -> String {
+        let output = self
+            .encryptor(&["decrypt", name, "--out", "plain", "--force"])
+            .args(["--passfile", "pass"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        fs::read_to_string(self.path("plain")).unwrap()
+    }
+}
+
+#[test]
+fn only_changed_files_are_encrypted_again() {
+    let scratch = Scratch::new("changed");
+    scratch.backup(&[]);
+    let first = fs::read(scratch.path("backup/a.txt.enc")).unwrap();
+    let second = fs::read(scratch.path("backup/sub/b.txt.enc")).unwrap();
+    assert_eq!(scratch.decrypt("backup/sub/b.txt.enc"), "second file\n");
+
+    // Encryption is randomized, so an output encrypted again never has the same bytes
+    fs::write(scratch.path("tree/sub/b.txt"), "second file, edited\n").unwrap();
+    fs::write(scratch.path("tree/c.txt"), "third file\n").unwrap();
+    let output = scratch.backup(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("2 of 3 files changed"), "{}", stdout);
+    assert_eq!(fs::read(scratch.path("backup/a.txt.enc")).unwrap(), first);
+    assert_ne!(
+        fs::read(scratch.path("backup/sub/b.txt.enc")).unwrap(),
+        second
+    );
+    assert_eq!(
+        scratch.decrypt("backup/sub/b.txt.enc"),
+        "second file, edited\n"
+    );
+    assert_eq!(scratch.decrypt("backup/c.txt.enc"), "third file\n");
+
+    // A file written again with the same contents is hashed and left alone
+    fs::write(scratch.path("tree/a.txt"), "first file\n").unwrap();
+    let output = scratch.backup(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("0 of 3 files changed"), "{}", stdout);
+    assert_eq!(fs::read(scratch.path("backup/a.txt.enc")).unwrap(), first);
+
+    // A missing output is written again
+    fs::remove_file(scratch.path("backup/a.txt.enc")).unwrap();
+    scratch.backup(&[]);
+    assert_eq!(scratch.decrypt("backup/a.txt.enc"), "first file\n");
+}
+
+#[test]
+fn delete_removes_the_outputs_of_deleted_files() {
+    let scratch = Scratch::new("delete");
+    scratch.backup(&[]);
+    fs::remove_file(scratch.path("tree/sub/b.txt")).unwrap();
+
+    // Without --delete the output is kept
+    let output = scratch.backup(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1 files were deleted"), "{}", stdout);
+    assert!(scratch.path("backup/sub/b.txt.enc").is_file());
+
+    scratch.backup(&["--delete"]);
+    assert!(!scratch.path("backup/sub/b.txt.enc").exists());
+    assert!(scratch.path("backup/a.txt.enc").is_file());
+}
+
+#[test]
+fn the_state_needs_the_same_password() {
+    let scratch = Scratch::new("password");
+    scratch.backup(&[]);
+    fs::write(scratch.path("other"), "another password entirely\n").unwrap();
+    let output = scratch
+        .encryptor(&["encrypt", "tree", "--incremental", "--state", "state.db"])
+        .args(["--out", "backup", "--passfile", "other"])
+        .args(["--kdf-memory", "1024", "--kdf-iterations", "1"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("State error"));
+}
@@ -0,0 +1,63 @@
+//! Rewrites the format version, the cipher and the Argon2id cost in the header of an encrypted file, the way an
+//! attacker downgrading it to something cheaper to guess would, and checks that none of them decrypts: the key is
+//! derived with them bound in (see `Header::key_context`), so it comes out different and fails the password check
+//! or the payload's authentication.
+use encryptor::header::VERSION;
+use encryptor::{EncryptError, Encryptor, KdfParams};
+
+const PASSWORD: &str = "correct horse battery staple";
+const PLAINTEXT: &[u8] = b"The quick brown fox\n";
+
+// Without a header backup, so the rewritten header is the only one there is, nor the payload length, so a header
+// read in another version's layout isn't refused for its length before the key is derived
+fn encryptor() -> Encryptor {
+    Encryptor::new(PASSWORD)
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 2,
+        })
+        .with_header_backup(false)
+        .with_metadata_binding(false)
+}
+
+// Encrypt PLAINTEXT, rewrite its header with `rewrite` and check that decrypting it fails as a wrong password or
+// a damaged file would
+fn refused_after(what: &str, rewrite: impl Fn(&mut Vec<u8>)) {
+    let mut sealed = encryptor().encrypt_bytes(PLAINTEXT).unwrap();
+    assert_eq!(encryptor().decrypt_bytes(&sealed).unwrap(), PLAINTEXT);
+    rewrite(&mut sealed);
+    match encryptor().decrypt_bytes(&sealed) {
+        Err(EncryptError::WrongPassword | EncryptError::AeadError(_)) => {}
+        other => panic!("with {} rewritten: {:?}", what, other),
+    }
+}
+
+#[test]
+fn a_rewritten_version_fails_to_decrypt() {
+    // The version follows the 4 magic bytes
+    for version in 3..VERSION {
+        refused_after("the version", |sealed| sealed[4] = version);
+    }
+}
+
+#[test]
+fn a_rewritten_cipher_fails_to_decrypt() {
+    // AES-256-GCM (1) to ChaCha20-Poly1305 (2)
+    refused_after("the cipher", |sealed| {
+        assert_eq!(sealed[5], 1);
+        sealed[5] = 2;
+    });
+}
+
+#[test]
+fn a_weaker_argon2id_cost_fails_to_decrypt() {
+    // The memory (KiB) and iterations follow the version, cipher and KDF, as u32 little endian
+    refused_after("the memory", |sealed| {
+        assert_eq!(sealed[7..11], 1024u32.to_le_bytes());
+        sealed[7..11].copy_from_slice(&8u32.to_le_bytes());
+    });
+    refused_after("the iterations", |sealed| {
+        assert_eq!(sealed[11..15], 2u32.to_le_bytes());
+        sealed[11..15].copy_from_slice(&1u32.to_le_bytes());
+    });
+}
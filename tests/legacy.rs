@@ -0,0 +1,152 @@
+//! Runs `encryptor decrypt --legacy`, the positional `decrypt` of the first releases and `encryptor migrate` on the
+//! fixture written by those releases, which had no header, in a scratch directory, and the positional form on files
+//! of the current format.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const NONCE: &str = "[246, 231, 118, 136, 232, 16, 173, 214, 11, 241, 220, 114]";
+
+// A scratch directory holding the legacy fixture as notes.txt.enc and the password it was sealed with
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("legacy", name);
+        fs::write(
+            scratch.dir.join("pass"),
+            "12345678901234567890123456789012\n",
+        )
+        .unwrap();
+        fs::copy(
+            fixtures().join("v0-aes256gcm.legacy"),
+            scratch.dir.join("notes.txt.enc"),
+        )
+        .unwrap();
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args)
+            .args(["--passfile", "pass"])
+            .output()
+            .unwrap()
+    }
+}
+
+impl Scratch {
+    // Run `encryptor <args>` exactly as given, as a script written for the first releases would
+    fn run_positional(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_encryptor"))
+            .current_dir(&self.dir)
+            .env("ENCRYPTOR_CONFIG_DIR", self.dir.join("conf"))
+            .args(args)
+            .output()
+            .unwrap()
+    }
+}
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+#[test]
+fn legacy_files_decrypt_with_their_nonce() {
+    let scratch = Scratch::new("decrypt");
+    let plaintext = fs::read(fixtures().join("plaintext.txt")).unwrap();
+
+    // Without --legacy there is no header to read
+    let output = scratch.run(&["decrypt", "notes.txt.enc"]);
+    assert!(!output.status.success(), "{:?}", output);
+
+    let wrong = NONCE.replace("246", "247");
+    let output = scratch.run(&["decrypt", "notes.txt.enc", "--legacy", "--nonce", &wrong]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(!scratch.dir.join("notes.txt").exists());
+
+    let output = scratch.run(&["decrypt", "notes.txt.enc", "--legacy", "--nonce", NONCE]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(fs::read(scratch.dir.join("notes.txt")).unwrap(), plaintext);
+    assert!(scratch.dir.join("notes.txt.enc").exists());
+}
+
+#[test]
+fn positional_decrypt_of_the_first_releases_reads_their_files() {
+    let scratch = Scratch::new("positional");
+    let plaintext = fs::read(fixtures().join("plaintext.txt")).unwrap();
+
+    let output = scratch.run_positional(&[
+        "decrypt",
+        "12345678901234567890123456789012",
+        "notes.txt.enc",
+        NONCE,
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(fs::read(scratch.dir.join("notes.txt")).unwrap(), plaintext);
+    let notice = String::from_utf8_lossy(&output.stderr);
+    assert!(notice.contains("\"deprecated\""), "{}", notice);
+    assert!(notice.contains("--legacy --nonce"), "{}", notice);
+
+    // The nonce isn't ignored: a wrong one fails to authenticate
+    fs::remove_file(scratch.dir.join("notes.txt")).unwrap();
+    let wrong = NONCE.replace("246", "247");
+    let output = scratch.run_positional(&[
+        "decrypt",
+        "12345678901234567890123456789012",
+        "notes.txt.enc",
+        &wrong,
+    ]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(!scratch.dir.join("notes.txt").exists());
+}
+
+#[test]
+fn positional_form_round_trips_through_the_current_format() {
+    let scratch = Scratch::new("round-trip");
+    let password = "12345678901234567890123456789012";
+    fs::write(scratch.dir.join("new.txt"), "written since\n").unwrap();
+
+    let output = scratch.run_positional(&["encrypt", password, "new.txt", NONCE]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("\"deprecated\""));
+    fs::remove_file(scratch.dir.join("new.txt")).unwrap();
+
+    // The output has a header, so it is decrypted as it is rather than as a file of the first releases
+    let output = scratch.run_positional(&["decrypt", password, "new.txt.enc", NONCE]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("new.txt")).unwrap(),
+        "written since\n"
+    );
+}
+
+#[test]
+fn migrated_files_decrypt_in_the_current_format() {
+    let scratch = Scratch::new("migrate");
+    let plaintext = fs::read(fixtures().join("plaintext.txt")).unwrap();
+    let legacy = fs::read(scratch.dir.join("notes.txt.enc")).unwrap();
+
+    let wrong = NONCE.replace("246", "247");
+    let output = scratch.run(&["migrate", "notes.txt.enc", "--nonce", &wrong]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert_eq!(fs::read(scratch.dir.join("notes.txt.enc")).unwrap(), legacy);
+
+    let output = scratch.run(&["migrate", "notes.txt.enc", "--nonce", NONCE]);
+    assert!(output.status.success(), "{:?}", output);
+    let names: Vec<_> = fs::read_dir(&scratch.dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(names.len(), 2, "{:?}", names);
+
+    // A second migration finds the header the first one wrote
+    let output = scratch.run(&["migrate", "notes.txt.enc", "--nonce", NONCE]);
+    assert!(!output.status.success(), "{:?}", output);
+
+    let output = scratch.run(&["decrypt", "notes.txt.enc"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(fs::read(scratch.dir.join("notes.txt")).unwrap(), plaintext);
+}
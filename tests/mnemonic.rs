@@ -0,0 +1,132 @@
+//! Backs keys up as words with `encryptor keygen --mnemonic` and `key export --mnemonic`, and restores them with
+//! `key import --mnemonic`, in a scratch directory. The words match the BIP39 test vectors for the same entropy.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::io::Write;
+use std::process::{Output, Stdio};
+
+// A scratch directory
+impl Scratch {
+    fn new(name: &str) -> Self {
+        Scratch::create("mnemonic", name)
+    }
+
+    // Run the command with `stdin` piped to it
+    fn run(&self, args: &[&str], stdin: &str) -> Output {
+        let mut child = self
+            .encryptor(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(stdin.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+}
+
+// The words printed by a command, without their numbers
+fn words(output: &Output) -> Vec<String> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip_while(|line| !line.is_empty())
+        .flat_map(str::split_whitespace)
+        .filter(|token| !token.ends_with('.'))
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn exported_words_match_bip39() {
+    let scratch = Scratch::new("bip39");
+    fs::write(scratch.dir.join("zero.key"), "00".repeat(32)).unwrap();
+    fs::write(scratch.dir.join("7f.key"), "7f".repeat(32)).unwrap();
+
+    let output = scratch.run(&["key", "export", "--mnemonic", "zero.key"], "");
+    assert!(output.status.success(), "{:?}", output);
+    let mut expected = vec!["abandon"; 23];
+    expected.push("art");
+    assert_eq!(words(&output), expected);
+
+    let output = scratch.run(&["key", "export", "--mnemonic", "7f.key"], "");
+    let phrase = "legal winner thank year wave sausage worth useful ".repeat(3);
+    let mut expected: Vec<&str> = phrase.split_whitespace().collect();
+    *expected.last_mut().unwrap() = "title";
+    assert_eq!(words(&output), expected);
+}
+
+#[test]
+fn keys_and_identities_are_restored_from_their_words() {
+    let scratch = Scratch::new("restore");
+    let output = scratch.run(&["keygen", "--out", "backup.key", "--mnemonic"], "");
+    assert!(output.status.success(), "{:?}", output);
+    // As printed, numbers included
+    let printed = String::from_utf8_lossy(&output.stdout).to_string();
+    let output = scratch.run(
+        &["key", "import", "--mnemonic", "--out", "restored.key"],
+        &printed[printed.find(" 1.").unwrap()..],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read(scratch.dir.join("backup.key")).unwrap(),
+        fs::read(scratch.dir.join("restored.key")).unwrap()
+    );
+
+    let output = scratch.run(&["keygen", "--out", "id", "--asymmetric", "--mnemonic"], "");
+    assert!(output.status.success(), "{:?}", output);
+    // Abbreviated to four letters
+    let typed: Vec<String> = words(&output)
+        .iter()
+        .map(|word| word.chars().take(4).collect())
+        .collect();
+    let output = scratch.run(
+        &[
+            "key",
+            "import",
+            "--mnemonic",
+            "--asymmetric",
+            "--out",
+            "id2",
+        ],
+        &typed.join(" "),
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read(scratch.dir.join("id.pub")).unwrap(),
+        fs::read(scratch.dir.join("id2.pub")).unwrap()
+    );
+}
+
+#[test]
+fn wrong_words_are_caught() {
+    let scratch = Scratch::new("typos");
+    let phrase = "legal winner thank year wave sausage worth useful ".repeat(3);
+
+    // The last word is the checksum's
+    let output = scratch.run(&["key", "import", "--mnemonic", "--out", "a.key"], &phrase);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(stderr.contains("checksum"), "{}", stderr);
+
+    let typo = phrase.replacen("winner", "wineer", 1);
+    let output = scratch.run(&["key", "import", "--mnemonic", "--out", "a.key"], &typo);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Word 2, 'wineer'"), "{}", stderr);
+
+    let output = scratch.run(
+        &["key", "import", "--mnemonic", "--out", "a.key"],
+        "legal winner thank",
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("but 3 were given"), "{}", stderr);
+    assert!(!scratch.dir.join("a.key").exists());
+}
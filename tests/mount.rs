@@ -0,0 +1,112 @@
+//! Runs `encryptor mount` on an archive in a scratch directory, to check that the mounted tree lists and reads back
+//! the archived files, refuses writes and is unmounted on SIGTERM. Skipped where this process can't mount FUSE
+//! filesystems (no /dev/fuse, or not root and no fusermount3).
+#![cfg(all(feature = "fuse", target_os = "linux"))]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+// A scratch directory holding a password file and a small tree to archive, removed when the test is done
+struct Scratch {
+    dir: PathBuf,
+}
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("encryptor-mount-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tree/docs")).unwrap();
+        fs::create_dir_all(dir.join("mnt")).unwrap();
+        fs::write(dir.join("pass"), "correct horse battery staple\n").unwrap();
+        fs::write(dir.join("tree/notes.txt"), "The quick brown fox\n").unwrap();
+        let large: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(dir.join("tree/docs/large.bin"), large).unwrap();
+        Scratch { dir }
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_encryptor"));
+        command
+            .current_dir(&self.dir)
+            .env("ENCRYPTOR_CONFIG_DIR", self.dir.join("conf"))
+            .args(args)
+            .args(["--passfile", "pass", "--color", "never"]);
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = Command::new("umount")
+            .arg(self.dir.join("mnt"))
+            .stderr(Stdio::null())
+            .status();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn mounted_archive_reads_back() {
+    let scratch = Scratch::new("roundtrip");
+    // Chunked, so files are read through SeekableReader
+    let output = scratch.run(&[
+        "encrypt",
+        "--archive",
+        "tree",
+        "-o",
+        "tree.enc",
+        "--stream-threshold",
+        "0",
+        "--kdf-memory",
+        "1024",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let mut mount = scratch
+        .command(&["mount", "tree.enc", "mnt"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let notes = scratch.dir.join("mnt/tree/notes.txt");
+    let started = Instant::now();
+    while !notes.exists() {
+        if let Some(status) = mount.try_wait().unwrap() {
+            let output = mount.wait_with_output().unwrap();
+            eprintln!(
+                "skipped, FUSE mounts are unavailable here ({}): {}",
+                status,
+                String::from_utf8_lossy(&output.stdout)
+            );
+            return;
+        }
+        assert!(started.elapsed() < Duration::from_secs(30), "not mounted");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    assert_eq!(fs::read_to_string(&notes).unwrap(), "The quick brown fox\n");
+    assert_eq!(
+        fs::read(scratch.dir.join("mnt/tree/docs/large.bin")).unwrap(),
+        fs::read(scratch.dir.join("tree/docs/large.bin")).unwrap()
+    );
+    let mut names: Vec<String> = fs::read_dir(scratch.dir.join("mnt/tree"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["docs", "notes.txt"]);
+    assert!(fs::write(scratch.dir.join("mnt/tree/new.txt"), "x").is_err());
+
+    // SAFETY: the pid is the mount process spawned above, which hasn't been waited for
+    unsafe { libc::kill(mount.id() as libc::pid_t, libc::SIGTERM) };
+    let output = mount.wait_with_output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!notes.exists());
+}
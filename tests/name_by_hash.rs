@@ -0,0 +1,109 @@
+//! Runs `encryptor encrypt --name-by-hash` on scratch files, to check that each output is named after the BLAKE3 hash
+//! of its bytes and decrypts back to the name it was encrypted from.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Output;
+
+// A scratch directory with files to encrypt and a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("name-by-hash", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        fs::write(scratch.dir.join("report.txt"), "quarterly numbers\n").unwrap();
+        fs::write(scratch.dir.join("notes.txt"), "meeting notes\n").unwrap();
+        scratch
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        let output = self
+            .encryptor(args)
+            .args(["--passfile", "pass"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        output
+    }
+
+    // The files in the directory `name`
+    fn list(&self, name: &str) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(self.path(name))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+        files
+    }
+}
+
+#[test]
+fn outputs_are_named_by_their_hash_and_decrypt_to_the_original_names() {
+    let scratch = Scratch::new("roundtrip");
+    scratch.run(&[
+        "encrypt",
+        "report.txt",
+        "notes.txt",
+        "--name-by-hash",
+        "--out",
+        "store",
+        "--kdf-memory",
+        "1024",
+        "--kdf-iterations",
+        "1",
+    ]);
+    let outputs = scratch.list("store");
+    assert_eq!(outputs.len(), 2, "{:?}", outputs);
+    for output in &outputs {
+        let hash = blake3::hash(&fs::read(output).unwrap())
+            .to_hex()
+            .to_string();
+        assert_eq!(
+            output.file_name().unwrap().to_str().unwrap(),
+            format!("{}.enc", hash)
+        );
+    }
+
+    for output in &outputs {
+        let name = output.file_name().unwrap().to_str().unwrap();
+        scratch.run(&["decrypt", &format!("store/{}", name)]);
+    }
+    assert_eq!(
+        fs::read_to_string(scratch.path("store/report.txt")).unwrap(),
+        "quarterly numbers\n"
+    );
+    assert_eq!(
+        fs::read_to_string(scratch.path("store/notes.txt")).unwrap(),
+        "meeting notes\n"
+    );
+}
+
+#[test]
+fn info_shows_the_recorded_name() {
+    let scratch = Scratch::new("info");
+    scratch.run(&[
+        "encrypt",
+        "report.txt",
+        "--name-by-hash",
+        "--kdf-memory",
+        "1024",
+        "--kdf-iterations",
+        "1",
+    ]);
+    let output = scratch
+        .list("")
+        .into_iter()
+        .find(|path| path.extension().is_some_and(|extension| extension == "enc"))
+        .unwrap();
+    let info = scratch.encryptor(&["info"]).arg(&output).output().unwrap();
+    let stdout = String::from_utf8_lossy(&info.stdout);
+    assert!(stdout.contains("report.txt"), "{}", stdout);
+    assert!(stdout.contains("named by its hash"), "{}", stdout);
+}
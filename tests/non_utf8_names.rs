@@ -0,0 +1,136 @@
+//! File names that aren't valid UTF-8: they are encrypted and decrypted under their own bytes, the header records
+//! them escaped, and a renamed file is caught like any other.
+#![cfg(all(feature = "cli", unix))]
+
+mod common;
+
+use common::Scratch;
+use encryptor::names;
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::process::Command;
+
+// A scratch directory with a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("non-utf8", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    // `encryptor <command>`, to be given its files and run
+    fn command(&self, command: &str) -> Command {
+        self.encryptor(&[command])
+    }
+}
+
+#[test]
+fn names_are_escaped_only_when_they_need_to_be() {
+    let name = OsStr::from_bytes(b"caf\xe9 a\\b.txt");
+    let (recorded, escaped) = names::record(name);
+    assert_eq!(recorded, "caf\\xe9 a\\\\b.txt");
+    assert!(escaped);
+    assert_eq!(names::unescape(&recorded).unwrap(), name);
+
+    // A valid name is recorded as it always was, backslashes and all
+    assert_eq!(
+        names::record(OsStr::new("a\\b.txt")),
+        ("a\\b.txt".into(), false)
+    );
+    assert_eq!(names::unescape("a\\qb"), None);
+    assert_eq!(names::unescape("a\\x4"), None);
+}
+
+#[test]
+fn the_cli_round_trips_names_that_arent_utf8() {
+    let scratch = Scratch::new("cli");
+    let name = OsStr::from_bytes(b"caf\xe9.txt");
+    let encrypted = OsStr::from_bytes(b"caf\xe9.txt.enc");
+    fs::write(scratch.dir.join(name), "latin-1 name\n").unwrap();
+
+    let output = scratch
+        .command("encrypt")
+        .arg(name)
+        .args([
+            "--passfile",
+            "pass",
+            "--kdf-memory",
+            "1024",
+            "--kdf-iterations",
+            "1",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(scratch.dir.join(encrypted).is_file());
+
+    let output = scratch.command("info").arg(encrypted).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("caf\\xe9.txt (not valid UTF-8"),
+        "{}",
+        stdout
+    );
+
+    fs::remove_file(scratch.dir.join(name)).unwrap();
+    let output = scratch
+        .command("decrypt")
+        .arg(encrypted)
+        .args(["--passfile", "pass"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join(name)).unwrap(),
+        "latin-1 name\n"
+    );
+
+    // The escaped name is checked like any other
+    fs::copy(
+        scratch.dir.join(encrypted),
+        scratch.dir.join("cafe.txt.enc"),
+    )
+    .unwrap();
+    let output = scratch
+        .command("decrypt")
+        .arg("cafe.txt.enc")
+        .args(["--passfile", "pass"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(8), "{:?}", output);
+}
+
+#[test]
+fn a_sealed_name_that_isnt_utf8_comes_back() {
+    let scratch = Scratch::new("sealed");
+    let name = OsStr::from_bytes(b"r\xe9sum\xe9.pdf");
+    fs::write(scratch.dir.join(name), "hidden name\n").unwrap();
+
+    let output = scratch
+        .command("encrypt")
+        .arg(name)
+        .args(["--encrypt-names", "--passfile", "pass"])
+        .args(["--kdf-memory", "1024", "--kdf-iterations", "1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    fs::remove_file(scratch.dir.join(name)).unwrap();
+    let sealed = fs::read_dir(&scratch.dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .find(|entry| entry.as_bytes().ends_with(b".enc"))
+        .expect("the output has a random name");
+
+    let output = scratch
+        .command("decrypt")
+        .arg(&sealed)
+        .args(["--passfile", "pass"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join(name)).unwrap(),
+        "hidden name\n"
+    );
+}
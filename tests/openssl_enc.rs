@@ -0,0 +1,130 @@
+//! Files compatible with `openssl enc -aes-256-cbc -pbkdf2 -salt`: a file written by OpenSSL decrypts, in binary and
+//! in base64, and `encrypt --format openssl` writes files that decrypt the same way, with a warning that they aren't
+//! authenticated.
+#![cfg(all(feature = "cli", feature = "openssl-enc"))]
+
+mod common;
+
+use common::Scratch;
+use encryptor::openssl_enc;
+use std::fs;
+use std::process::Output;
+
+// `printf 'The quick brown fox jumps over the lazy dog\n' | openssl enc -a -aes-256-cbc -pbkdf2 -salt
+// -pass pass:'correct horse battery staple'`, with the salt 0001020304050607
+const OPENSSL_OUTPUT: &str = "U2FsdGVkX18AAQIDBAUGB6zR0/vWrAkECATTN987VRmx/EwvTPcNwlNi/jGGV8nK
+FuUrNdU4nCrHpCCEuom9Cw==
+";
+const PASSWORD: &str = "correct horse battery staple";
+const PLAINTEXT: &[u8] = b"The quick brown fox jumps over the lazy dog\n";
+
+// A scratch directory with a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("openssl", name);
+        fs::write(scratch.dir.join("pass"), format!("{}\n", PASSWORD)).unwrap();
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args).output().unwrap()
+    }
+}
+
+#[test]
+fn openssl_output_decrypts_and_ours_round_trips() {
+    assert_eq!(
+        openssl_enc::decrypt(OPENSSL_OUTPUT.as_bytes(), PASSWORD, 10_000).unwrap(),
+        PLAINTEXT
+    );
+    // Nothing records the iteration count, so another one derives another key
+    assert!(openssl_enc::decrypt(OPENSSL_OUTPUT.as_bytes(), PASSWORD, 1000).is_err());
+
+    let sealed = openssl_enc::encrypt(PLAINTEXT, PASSWORD, 1000).unwrap();
+    assert!(sealed.starts_with(b"Salted__"));
+    assert_eq!(sealed.len(), 16 + 48);
+    assert_eq!(
+        openssl_enc::decrypt(&sealed, PASSWORD, 1000).unwrap(),
+        PLAINTEXT
+    );
+    let text = openssl_enc::to_base64(&sealed);
+    assert!(openssl_enc::is_openssl(&text));
+    assert_eq!(
+        openssl_enc::decrypt(&text, PASSWORD, 1000).unwrap(),
+        PLAINTEXT
+    );
+}
+
+#[test]
+fn the_cli_reads_and_writes_openssl_files() {
+    let scratch = Scratch::new("cli");
+    fs::write(scratch.dir.join("fox.txt.enc"), OPENSSL_OUTPUT).unwrap();
+    let output = scratch.run(&["decrypt", "fox.txt.enc", "--passfile", "pass"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(fs::read(scratch.dir.join("fox.txt")).unwrap(), PLAINTEXT);
+
+    fs::write(scratch.dir.join("notes.txt"), "buy milk\n").unwrap();
+    let output = scratch.run(&[
+        "encrypt",
+        "notes.txt",
+        "--format",
+        "openssl",
+        "--pbkdf2-iter",
+        "2000",
+        "--armor",
+        "--passfile",
+        "pass",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("without authentication"), "{}", stderr);
+    let written = fs::read(scratch.dir.join("notes.txt.enc")).unwrap();
+    assert!(written.starts_with(b"U2FsdGVkX1"));
+    assert_eq!(
+        openssl_enc::decrypt(&written, PASSWORD, 2000).unwrap(),
+        b"buy milk\n"
+    );
+
+    let output = scratch.run(&[
+        "decrypt",
+        "notes.txt.enc",
+        "--pbkdf2-iter",
+        "2000",
+        "--passfile",
+        "pass",
+        "--force",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read(scratch.dir.join("notes.txt")).unwrap(),
+        b"buy milk\n"
+    );
+
+    // --pbkdf2-iter means nothing to the native format, and OpenSSL derives keys from passwords only
+    let output = scratch.run(&[
+        "encrypt",
+        "notes.txt",
+        "--pbkdf2-iter",
+        "2000",
+        "--passfile",
+        "pass",
+    ]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    fs::write(scratch.dir.join("key"), [7u8; 32]).unwrap();
+    let output = scratch.run(&[
+        "encrypt",
+        "notes.txt",
+        "--format",
+        "openssl",
+        "--keyfile",
+        "key",
+        "--force",
+    ]);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("only be encrypted with a password"),
+        "{}",
+        stderr
+    );
+}
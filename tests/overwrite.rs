@@ -0,0 +1,117 @@
+//! Runs `encryptor encrypt` and `decrypt` onto outputs that already exist: they are left alone by default,
+//! replaced with --force and written next to with --auto-rename.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+// A scratch directory with a password file and notes.txt
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("overwrite", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        fs::write(scratch.dir.join("notes.txt"), "buy milk\n").unwrap();
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args)
+            .args(["--passfile", "pass", "--quiet"])
+            .output()
+            .unwrap()
+    }
+
+    fn encrypt(&self, args: &[&str]) -> Output {
+        let mut all = vec!["encrypt", "notes.txt", "--kdf-memory", "1024"];
+        all.extend(["--kdf-iterations", "1"]);
+        all.extend(args);
+        self.run(&all)
+    }
+
+    fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.dir.join(name)).unwrap()
+    }
+}
+
+#[test]
+fn existing_outputs_are_left_alone() {
+    let scratch = Scratch::new("refuse");
+    assert!(scratch.encrypt(&[]).status.success());
+    let encrypted = fs::read(scratch.dir.join("notes.txt.enc")).unwrap();
+
+    let output = scratch.encrypt(&[]);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("ENC_IO") && stderr.contains("notes.txt.enc already exists"),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        fs::read(scratch.dir.join("notes.txt.enc")).unwrap(),
+        encrypted
+    );
+
+    // The plaintext was edited since it was encrypted, so decrypting must not clobber it
+    fs::write(scratch.dir.join("notes.txt"), "buy milk and eggs\n").unwrap();
+    let output = scratch.run(&["decrypt", "notes.txt.enc"]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert_eq!(scratch.read("notes.txt"), "buy milk and eggs\n");
+
+    let output = scratch.run(&["decrypt", "notes.txt.enc", "--force"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(scratch.read("notes.txt"), "buy milk\n");
+}
+
+#[test]
+fn auto_rename_writes_next_to_them() {
+    let scratch = Scratch::new("rename");
+    assert!(scratch.encrypt(&[]).status.success());
+    fs::write(scratch.dir.join("notes.txt"), "buy eggs\n").unwrap();
+    let output = scratch.encrypt(&["--auto-rename"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(scratch.dir.join("notes.txt (1).enc").exists());
+
+    // The renamed file records its new name
+    fs::remove_file(scratch.dir.join("notes.txt")).unwrap();
+    let output = scratch.run(&["decrypt", "notes.txt.enc", "notes.txt (1).enc"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(scratch.read("notes.txt"), "buy milk\n");
+    assert_eq!(scratch.read("notes.txt (1)"), "buy eggs\n");
+
+    let output = scratch.run(&["decrypt", "notes.txt.enc", "--auto-rename"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(scratch.read("notes (1).txt"), "buy milk\n");
+    assert_eq!(scratch.read("notes.txt"), "buy milk\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn outputs_are_only_readable_by_their_owner() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let scratch = Scratch::new("private");
+    assert!(scratch.encrypt(&[]).status.success());
+    let mode = fs::metadata(scratch.dir.join("notes.txt.enc"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600, "{:o}", mode);
+
+    // Refusing to replace an output leaves no temporary file behind
+    assert!(!scratch.encrypt(&[]).status.success());
+    let names: Vec<_> = fs::read_dir(&scratch.dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert!(
+        !names
+            .iter()
+            .any(|name| name.to_string_lossy().ends_with(".tmp")),
+        "{:?}",
+        names
+    );
+}
@@ -0,0 +1,194 @@
+//! Encrypts files with `--pad` in a scratch directory, in one piece and in chunks: outputs of files of different
+//! sizes come out the same size, decrypt to exactly what was encrypted, and keep their padding through a rekey. The
+//! library's readers cut the padding off too, and PADMÉ never adds more than 12%.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use encryptor::header::Header;
+use encryptor::{DecryptingReader, Encryptor, KdfParams, Padding, SeekableReader};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use std::process::Output;
+
+// A scratch directory with a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("padding", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        let output = self.encryptor(args).output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        output
+    }
+
+    fn size(&self, name: &str) -> u64 {
+        fs::metadata(self.path(name)).unwrap().len()
+    }
+}
+
+const KDF: [&str; 4] = ["--kdf-memory", "1024", "--kdf-iterations", "1"];
+
+// Contents that don't compress or repeat, so a cut in the wrong place shows
+fn contents(len: u32) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+        .collect()
+}
+
+#[test]
+fn files_of_different_sizes_come_out_the_same_size() {
+    let scratch = Scratch::new("block");
+    fs::write(scratch.path("short.txt"), contents(100)).unwrap();
+    fs::write(scratch.path("longer.txt"), contents(3000)).unwrap();
+    let mut args = vec!["encrypt", "short.txt", "longer.txt", "--passfile", "pass"];
+    args.extend(KDF);
+    // The names are sealed too, since their lengths would tell the files apart as well
+    args.extend(["--pad", "block:4K", "--encrypt-names"]);
+    scratch.run(&args);
+    let outputs: Vec<PathBuf> = fs::read_dir(&scratch.dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "enc"))
+        .collect();
+    assert_eq!(outputs.len(), 2, "{:?}", outputs);
+    let sizes: Vec<u64> = outputs
+        .iter()
+        .map(|path| fs::metadata(path).unwrap().len())
+        .collect();
+    assert_eq!(sizes[0], sizes[1]);
+
+    let info = scratch.run(&["info", &outputs[0].to_string_lossy()]);
+    let stdout = String::from_utf8_lossy(&info.stdout);
+    assert!(stdout.contains("padding:       block:4096"), "{}", stdout);
+
+    fs::remove_file(scratch.path("short.txt")).unwrap();
+    fs::remove_file(scratch.path("longer.txt")).unwrap();
+    for output in &outputs {
+        scratch.run(&["decrypt", &output.to_string_lossy(), "--passfile", "pass"]);
+    }
+    assert_eq!(fs::read(scratch.path("short.txt")).unwrap(), contents(100));
+    assert_eq!(
+        fs::read(scratch.path("longer.txt")).unwrap(),
+        contents(3000)
+    );
+}
+
+#[test]
+fn chunked_files_decrypt_and_rekey_without_their_padding() {
+    let scratch = Scratch::new("chunked");
+    let large = contents(300_000);
+    fs::write(scratch.path("disk.img"), &large).unwrap();
+    fs::write(scratch.path("notes.txt"), "buy milk\n").unwrap();
+    let mut args = vec!["encrypt", "disk.img", "notes.txt", "--passfile", "pass"];
+    args.extend(KDF);
+    args.extend(["--pad", "padme", "--checksum"]);
+    args.extend(["--stream-threshold", "64K", "--chunk-size", "16K"]);
+    scratch.run(&args);
+    let header = Header::parse(&fs::read(scratch.path("disk.img.enc")).unwrap())
+        .unwrap()
+        .0;
+    assert!(header.chunk_size.is_some());
+    assert_eq!(
+        header.padding.map(|(scheme, _)| scheme),
+        Some(Padding::Padme)
+    );
+    // 300000 bytes round up to 303104 with PADMÉ
+    assert_eq!(header.payload_len, Some(303_104));
+
+    // Rekeyed, then decrypted under the new password
+    fs::write(scratch.path("new"), "another horse battery staple\n").unwrap();
+    scratch.run(&[
+        "rekey",
+        "file:pass",
+        "file:new",
+        "disk.img.enc",
+        "notes.txt.enc",
+    ]);
+    let size = scratch.size("disk.img.enc");
+    scratch.run(&[
+        "decrypt",
+        "disk.img.enc",
+        "notes.txt.enc",
+        "--passfile",
+        "new",
+        "--force",
+    ]);
+    assert_eq!(fs::read(scratch.path("disk.img")).unwrap(), large);
+    assert_eq!(
+        fs::read_to_string(scratch.path("notes.txt")).unwrap(),
+        "buy milk\n"
+    );
+    assert!(size > 303_104);
+}
+
+#[test]
+fn readers_cut_the_padding_off() {
+    let encryptor = Encryptor::new("correct horse")
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 1,
+        })
+        .with_padding(Padding::Block(1000))
+        .with_stream_threshold(0)
+        .with_chunk_size(256);
+    let plaintext = contents(2500);
+    let dir =
+        std::env::temp_dir().join(format!("encryptor-padding-readers-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file_path = dir.join("plain.bin");
+    fs::write(&file_path, &plaintext).unwrap();
+    let encrypted_path = encryptor.encrypt_file(&file_path).unwrap();
+    let sealed = fs::read(&encrypted_path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(Header::parse(&sealed).unwrap().0.payload_len, Some(3000));
+
+    let mut read = Vec::new();
+    DecryptingReader::new(&sealed[..], &encryptor)
+        .unwrap()
+        .read_to_end(&mut read)
+        .unwrap();
+    assert_eq!(read, plaintext);
+
+    let mut reader = SeekableReader::new(Cursor::new(&sealed), &encryptor).unwrap();
+    assert_eq!(reader.len(), plaintext.len() as u64);
+    let mut tail = vec![0; 100];
+    let len = reader.read_at(2450, &mut tail).unwrap();
+    assert_eq!(&tail[..len], &plaintext[2450..]);
+}
+
+#[test]
+fn padme_adds_at_most_twelve_percent() {
+    let encryptor = Encryptor::new("correct horse")
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 1,
+        })
+        .with_padding(Padding::Padme);
+    for len in [0, 1, 9, 100, 1000, 12345, 65537] {
+        let plaintext = contents(len);
+        let sealed = encryptor.encrypt_bytes(&plaintext).unwrap();
+        let payload_len = Header::parse(&sealed).unwrap().0.payload_len.unwrap();
+        let added = (payload_len - len as u64) as f64;
+        assert!(
+            added <= len as f64 * 0.12,
+            "{} bytes padded by {}",
+            len,
+            added
+        );
+        assert_eq!(encryptor.decrypt_bytes(&sealed).unwrap(), plaintext);
+    }
+    assert_eq!(Padding::Padme.padded_len(1000), 1024);
+    assert_eq!(Padding::Block(512).padded_len(0), 512);
+    assert_eq!(Padding::from_name("block:64"), Some(Padding::Block(64)));
+    assert_eq!(Padding::from_name("block:0"), None);
+}
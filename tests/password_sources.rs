@@ -0,0 +1,95 @@
+//! Decrypts with the password from `--passfile`, `--pass-env` and `--pass-fd`, to check that each one opens the file
+//! without a prompt, that a missing variable is reported, and that `--password` still works but warns it's seen
+//! through `ps`.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+const PASSWORD: &str = "correct horse battery staple";
+
+// A scratch directory holding notes.txt.enc, encrypted under PASSWORD with the deprecated --password
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("password-sources", name);
+        fs::write(scratch.dir.join("notes.txt"), "remember the milk\n").unwrap();
+        let output = scratch
+            .encryptor(&["encrypt", "notes.txt", "--password", PASSWORD])
+            .args(["--kdf-memory", "1024"])
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{:?}", output);
+        assert!(
+            stderr.contains(
+                "warning: --password is deprecated, since other users can see it through `ps`"
+            ),
+            "{}",
+            stderr
+        );
+        fs::remove_file(scratch.dir.join("notes.txt")).unwrap();
+        scratch
+    }
+
+    // Check that `output` decrypted notes.txt.enc, and remove what it decrypted for the next run
+    fn decrypted(&self, output: Output) {
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(
+            fs::read_to_string(self.dir.join("notes.txt")).unwrap(),
+            "remember the milk\n"
+        );
+        fs::remove_file(self.dir.join("notes.txt")).unwrap();
+    }
+}
+
+#[test]
+fn passfile_and_pass_env_open_the_file() {
+    let scratch = Scratch::new("file-env");
+    fs::write(scratch.dir.join("pass"), format!("{}\n", PASSWORD)).unwrap();
+    let output = scratch
+        .encryptor(&["decrypt", "notes.txt.enc", "--passfile", "pass"])
+        .output()
+        .unwrap();
+    scratch.decrypted(output);
+
+    let output = scratch
+        .encryptor(&["decrypt", "notes.txt.enc", "--pass-env", "NOTES_PASSWORD"])
+        .env("NOTES_PASSWORD", PASSWORD)
+        .output()
+        .unwrap();
+    scratch.decrypted(output);
+}
+
+#[test]
+fn a_missing_variable_is_reported() {
+    let scratch = Scratch::new("missing");
+    let output = scratch
+        .encryptor(&["decrypt", "notes.txt.enc", "--pass-env", "NOTES_PASSWORD"])
+        .env_remove("NOTES_PASSWORD")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(
+        stderr.contains("Cannot read the password from $NOTES_PASSWORD"),
+        "{}",
+        stderr
+    );
+    assert!(!scratch.dir.join("notes.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn pass_fd_reads_the_descriptor() {
+    let scratch = Scratch::new("fd");
+    fs::write(scratch.dir.join("pass"), format!("{}\n", PASSWORD)).unwrap();
+    let output = scratch
+        .encryptor(&["decrypt", "notes.txt.enc", "--pass-fd", "0"])
+        .stdin(fs::File::open(scratch.dir.join("pass")).unwrap())
+        .output()
+        .unwrap();
+    scratch.decrypted(output);
+}
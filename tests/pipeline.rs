@@ -0,0 +1,82 @@
+//! Encrypts files with their chunks sealed on several threads, checking that the output reads back like that of one
+//! thread, that progress is reported in order, and that a file growing while it is read still fails.
+
+mod common;
+
+use common::Scratch;
+use encryptor::{CancellationToken, EncryptError, Encryptor, KdfParams};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+// A scratch directory
+impl Scratch {
+    fn new(name: &str) -> Self {
+        Scratch::create("pipeline", name)
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.dir.join(name).to_string_lossy().into_owned()
+    }
+}
+
+fn encryptor() -> Encryptor {
+    Encryptor::new("correct horse")
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 1,
+        })
+        .with_stream_threshold(0)
+        .with_chunk_size(256)
+}
+
+#[test]
+fn chunks_sealed_on_several_threads_read_back() {
+    let scratch = Scratch::new("round-trip");
+    let file_path = scratch.path("plain.bin");
+    // One chunk, a full last chunk, and many chunks with a short last one
+    for len in [100, 256 * 8, 256 * 300 + 17] {
+        let plaintext: Vec<u8> = (0..len).map(|i| (i * 7 % 251) as u8).collect();
+        fs::write(&file_path, &plaintext).unwrap();
+        let mut done = Vec::new();
+        let encrypted_path = encryptor()
+            .with_stream_workers(4)
+            .encrypt_file_with(
+                &file_path,
+                |progress| done.push(progress.bytes_done),
+                &CancellationToken::new(),
+            )
+            .unwrap();
+        assert!(done.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", done);
+        assert_eq!(done.last(), Some(&(len as u64)));
+
+        fs::remove_file(&file_path).unwrap();
+        encryptor().decrypt_file(&encrypted_path).unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), plaintext);
+        fs::remove_file(&encrypted_path).unwrap();
+    }
+}
+
+#[test]
+fn a_file_growing_meanwhile_fails() {
+    let scratch = Scratch::new("growing");
+    let file_path = scratch.path("log.txt");
+    fs::write(&file_path, vec![b'x'; 256 * 100]).unwrap();
+    let mut appended = false;
+    let result = encryptor().with_stream_workers(2).encrypt_file_with(
+        &file_path,
+        |_| {
+            // The reader is only a few chunks ahead of the writer, so it sees this
+            if !appended {
+                appended = true;
+                let mut file = OpenOptions::new().append(true).open(&file_path).unwrap();
+                file.write_all(b"one more line\n").unwrap();
+            }
+        },
+        &CancellationToken::new(),
+    );
+    match result {
+        Err(EncryptError::IoError(err)) => assert!(err.to_string().contains("changed")),
+        other => panic!("{:?}", other),
+    }
+    assert!(!scratch.dir.join("log.txt.enc").exists());
+}
@@ -0,0 +1,189 @@
+//! Runs `encryptor decrypt` with the password typed at the prompt, in a session of its own so there is no terminal
+//! and the prompt reads the lines piped to stdin, to check that a wrong password is asked for again, that
+//! `--password-attempts` limits how many times, and that `--try-typos` finds the password a typo was made in.
+#![cfg(all(feature = "cli", unix))]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::process::{Output, Stdio};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+// A scratch directory holding notes.txt.enc, encrypted under PASSWORD
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("prompt", name);
+        scratch.seal(PASSWORD);
+        scratch
+    }
+
+    // Encrypt notes.txt to notes.txt.enc under `password`, taken as it is from the environment
+    fn seal(&self, password: &str) {
+        fs::write(self.dir.join("notes.txt"), "remember the milk\n").unwrap();
+        let output = self
+            .encryptor(&["encrypt", "notes.txt", "--pass-env", "SEALED_WITH"])
+            .env("SEALED_WITH", password)
+            .args(["--kdf-memory", "1024", "--force"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        fs::remove_file(self.dir.join("notes.txt")).unwrap();
+    }
+
+    // Run `encryptor <args>` with no controlling terminal and `typed` on stdin
+    fn run(&self, args: &[&str], typed: &str) -> Output {
+        let mut command = self.encryptor(args);
+        command
+            .env_remove("ENCRYPTOR_PASSWORD_CMD")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        // SAFETY: setsid is async-signal-safe, and only detaches the child from the test's terminal
+        unsafe {
+            command.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+        let mut child = command.spawn().unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(typed.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+}
+
+#[test]
+fn a_wrong_password_is_asked_for_again() {
+    let scratch = Scratch::new("again");
+    let output = scratch.run(
+        &["decrypt", "notes.txt.enc"],
+        &format!("hunter2\n{}\n", PASSWORD),
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(stderr.matches("Password: ").count(), 2, "{}", stderr);
+    assert_eq!(stderr.matches("try again").count(), 1, "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("notes.txt")).unwrap(),
+        "remember the milk\n"
+    );
+}
+
+#[test]
+fn password_attempts_limits_the_prompts() {
+    let scratch = Scratch::new("limit");
+    // The right password comes third, after the two attempts allowed
+    let output = scratch.run(
+        &["decrypt", "notes.txt.enc", "--password-attempts", "2"],
+        &format!("hunter2\nhunter3\n{}\n", PASSWORD),
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(13), "{:?}", output);
+    assert!(stderr.contains("ENC_WRONG_PASSWORD"), "{}", stderr);
+    assert_eq!(stderr.matches("Password: ").count(), 2, "{}", stderr);
+    assert_eq!(stderr.matches("try again").count(), 1, "{}", stderr);
+    assert!(!scratch.dir.join("notes.txt").exists());
+}
+
+#[test]
+fn a_password_from_a_file_isnt_asked_for_again() {
+    let scratch = Scratch::new("passfile");
+    fs::write(scratch.dir.join("wrong"), "hunter2\n").unwrap();
+    let output = scratch.run(
+        &["decrypt", "notes.txt.enc", "--passfile", "wrong"],
+        &format!("{}\n", PASSWORD),
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(13), "{:?}", output);
+    assert!(!stderr.contains("Password: "), "{}", stderr);
+    assert!(!stderr.contains("try again"), "{}", stderr);
+}
+
+#[test]
+fn try_typos_says_which_typo_opened_the_file() {
+    let scratch = Scratch::new("typos");
+    for (sealed_with, typed, typo) in [
+        ("Correct Horse", "cORRECT hORSE", "with caps lock inverted"),
+        (
+            "correct horse",
+            "corretc horse",
+            "with two neighbouring characters swapped",
+        ),
+        (
+            "correct horse",
+            " correct horse ",
+            "without the spaces around it",
+        ),
+        ("correct horse ", "correct horse", "with a trailing space"),
+        (
+            "lazy zebra",
+            "layz yebra",
+            "as typed on a QWERTZ keyboard (Y and Z swapped)",
+        ),
+    ] {
+        scratch.seal(sealed_with);
+        let output = scratch.run(
+            &["decrypt", "notes.txt.enc", "--try-typos"],
+            &format!("{}\n", typed),
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{}: {:?}", typo, output);
+        assert!(
+            stderr.contains(&format!(
+                "The password as typed doesn't open notes.txt.enc, but it does {}; decrypting with that",
+                typo
+            )),
+            "{}",
+            stderr
+        );
+        assert!(!stderr.contains("try again"), "{}", stderr);
+        assert_eq!(
+            fs::read_to_string(scratch.dir.join("notes.txt")).unwrap(),
+            "remember the milk\n"
+        );
+        fs::remove_file(scratch.dir.join("notes.txt")).unwrap();
+    }
+}
+
+#[test]
+fn typos_that_come_out_the_same_are_tried_once() {
+    let scratch = Scratch::new("same");
+    // "yz" typed on QWERTZ and with its two characters swapped are both "zy", and without the spaces around it is
+    // "yz" itself: only "YZ", "yz ", "yz\n", "zy" and "yw" (on AZERTY) are left to try after the password as typed
+    let output = scratch.run(
+        &[
+            "decrypt",
+            "notes.txt.enc",
+            "--try-typos",
+            "--password-attempts",
+            "1",
+            "-vv",
+        ],
+        "yz\n",
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(13), "{:?}", output);
+    assert_eq!(stderr.matches("Derived a key").count(), 6, "{}", stderr);
+}
+
+#[test]
+fn an_empty_password_has_no_typos_to_try() {
+    let scratch = Scratch::new("empty");
+    let output = scratch.run(&["decrypt", "notes.txt.enc", "--try-typos", "-vv"], "\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(
+        stderr.contains("The password must not be empty"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("Derived a key"), "{}", stderr);
+}
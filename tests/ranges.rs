@@ -0,0 +1,141 @@
+//! Serves byte ranges of chunked files through a ChunkIndex, as a web service answering Range requests would: every
+//! range decrypts to the same bytes of the plaintext, fetching only the chunks that hold it, with a sealed name and
+//! padding left out. A tampered chunk fails with its index, and files that can't be read at an offset are refused.
+
+use encryptor::header::{Header, HeaderError};
+use encryptor::{ChunkIndex, EncryptError, EncryptingWriter, Encryptor, KdfParams, Padding};
+use std::fs;
+use std::io::Write;
+
+fn encryptor() -> Encryptor {
+    Encryptor::new("correct horse")
+        .with_kdf_params(KdfParams {
+            memory: 1024,
+            iterations: 1,
+        })
+        .with_stream_threshold(0)
+        .with_chunk_size(256)
+}
+
+// Contents that don't repeat, so a range from the wrong place shows
+fn contents(len: u32) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+        .collect()
+}
+
+// Encrypt `plaintext` as a file named `name`, returning the encrypted file
+fn encrypt_file(encryptor: &Encryptor, name: &str, plaintext: &[u8]) -> Vec<u8> {
+    let dir =
+        std::env::temp_dir().join(format!("encryptor-ranges-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file_path = dir.join(name);
+    fs::write(&file_path, plaintext).unwrap();
+    let encrypted_path = encryptor.encrypt_file(&file_path).unwrap();
+    let sealed = fs::read(encrypted_path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    sealed
+}
+
+fn slice(sealed: &[u8], range: std::ops::Range<u64>) -> &[u8] {
+    &sealed[range.start as usize..range.end as usize]
+}
+
+#[test]
+fn every_range_decrypts_to_the_same_bytes() {
+    let encryptor = encryptor()
+        .with_name_encryption(true)
+        .with_padding(Padding::Block(1000));
+    let plaintext = contents(2500);
+    let sealed = encrypt_file(&encryptor, "report.pdf", &plaintext);
+    let index = ChunkIndex::new(&sealed[..1024], sealed.len() as u64, &encryptor).unwrap();
+    assert_eq!(index.len(), 2500);
+    assert!(index.header().sealed_name);
+
+    for (start, end) in [
+        (0, 1),
+        (0, 2500),
+        (100, 356),
+        (255, 257),
+        (2400, 2500),
+        (2499, 9000),
+    ] {
+        // Only the chunks holding the range are fetched
+        let fetched = index.ciphertext_range(start..end);
+        let chunks = index.chunks_for(start..end);
+        assert_eq!(fetched.start, index.sealed_chunk(chunks.start).start);
+        assert_eq!(fetched.end, index.sealed_chunk(chunks.end - 1).end);
+        let range = index
+            .decrypt_range(start..end, slice(&sealed, fetched))
+            .unwrap();
+        assert_eq!(range, &plaintext[start as usize..(end as usize).min(2500)]);
+    }
+    // An empty range needs nothing
+    assert!(index.ciphertext_range(700..700).is_empty());
+    assert!(index.decrypt_range(700..700, &[]).unwrap().is_empty());
+
+    // Chunk by chunk, the plaintext of every chunk put together is the whole file, the padding after it aside
+    let mut whole = Vec::new();
+    for chunk in 0..index.chunk_count() {
+        let plaintext_of = index.plaintext_of(chunk);
+        let opened = index
+            .decrypt_chunk(chunk, slice(&sealed, index.sealed_chunk(chunk)))
+            .unwrap();
+        assert_eq!(opened.len() as u64, plaintext_of.end - plaintext_of.start);
+        whole.extend(opened);
+    }
+    assert_eq!(whole, plaintext);
+    assert_eq!(index.chunk_at(0), 0);
+    assert!(index.plaintext_of(index.chunk_count() - 1).is_empty());
+}
+
+#[test]
+fn a_tampered_chunk_fails_with_its_index() {
+    let encryptor = encryptor();
+    let mut writer = EncryptingWriter::new(Vec::new(), &encryptor).unwrap();
+    writer.write_all(&contents(1000)).unwrap();
+    let mut sealed = writer.finish().unwrap();
+    let index = ChunkIndex::new(&sealed, sealed.len() as u64, &encryptor).unwrap();
+    let second = index.sealed_chunk(2);
+    sealed[second.start as usize + 10] ^= 1;
+
+    // The chunks around it still open
+    let fetched = index.ciphertext_range(0..512);
+    assert_eq!(
+        index
+            .decrypt_range(0..512, slice(&sealed, fetched))
+            .unwrap(),
+        &contents(1000)[..512]
+    );
+    let fetched = index.ciphertext_range(500..800);
+    match index.decrypt_range(500..800, slice(&sealed, fetched)) {
+        Err(EncryptError::CorruptChunk { index: 2, offset }) => assert_eq!(offset, second.start),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    // A chunk passed at the wrong index doesn't open either
+    assert!(index
+        .decrypt_chunk(1, slice(&sealed, index.sealed_chunk(0)))
+        .is_err());
+    assert!(index.decrypt_chunk(index.chunk_count(), &[]).is_err());
+}
+
+#[test]
+fn files_sealed_in_one_piece_are_refused() {
+    let encryptor = encryptor().with_stream_threshold(u64::MAX);
+    let sealed = encryptor.encrypt_bytes(b"a short note").unwrap();
+    match ChunkIndex::new(&sealed, sealed.len() as u64, &encryptor) {
+        Err(EncryptError::IoError(err)) => {
+            assert_eq!(err.kind(), std::io::ErrorKind::Unsupported)
+        }
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+
+    // A start without the sealed name asks for more
+    let encryptor = self::encryptor().with_name_encryption(true);
+    let sealed = encrypt_file(&encryptor, "notes.txt", &contents(2000));
+    let header_len = Header::parse(&sealed).unwrap().1;
+    match ChunkIndex::new(&sealed[..header_len + 10], sealed.len() as u64, &encryptor) {
+        Err(EncryptError::HeaderError(HeaderError::Truncated)) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
@@ -0,0 +1,101 @@
+//! Runs `encryptor encrypt --recovery-code` in a scratch directory and decrypts its output with the password and,
+//! leniently typed, with the recovery code it printed.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+// A scratch directory with a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("recovery", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args).output().unwrap()
+    }
+}
+
+// Encrypt notes.txt with the password and a recovery code, returning the code
+fn encrypt(scratch: &Scratch) -> String {
+    fs::write(scratch.dir.join("notes.txt"), "if found, call\n").unwrap();
+    let output = scratch.run(&[
+        "encrypt",
+        "notes.txt",
+        "--recovery-code",
+        "--quiet",
+        "--passfile",
+        "pass",
+        "--kdf-memory",
+        "1024",
+        "--kdf-iterations",
+        "1",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    fs::remove_file(scratch.dir.join("notes.txt")).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let line = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("Recovery code: "))
+        .unwrap_or_else(|| panic!("no recovery code printed: {}", stderr));
+    line.to_string()
+}
+
+#[test]
+fn the_recovery_code_or_the_password_decrypts() {
+    let scratch = Scratch::new("decrypt");
+    let code = encrypt(&scratch);
+    assert_eq!(code.len(), 29);
+
+    // In lower case, without dashes and with O for 0
+    let typed = code.replace('-', " ").to_lowercase().replace('0', "o");
+    fs::write(scratch.dir.join("code"), typed).unwrap();
+    let output = scratch.run(&[
+        "decrypt",
+        "notes.txt.enc",
+        "--recovery-code",
+        "--passfile",
+        "code",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("notes.txt")).unwrap(),
+        "if found, call\n"
+    );
+
+    fs::remove_file(scratch.dir.join("notes.txt")).unwrap();
+    let output = scratch.run(&["decrypt", "notes.txt.enc", "--passfile", "pass"]);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn another_code_fails() {
+    let scratch = Scratch::new("wrong");
+    let code = encrypt(&scratch);
+
+    let other = match code.starts_with('A') {
+        true => format!("B{}", &code[1..]),
+        false => format!("A{}", &code[1..]),
+    };
+    fs::write(scratch.dir.join("code"), other).unwrap();
+    let args = [
+        "decrypt",
+        "notes.txt.enc",
+        "--recovery-code",
+        "--passfile",
+        "code",
+    ];
+    let output = scratch.run(&args);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(!scratch.dir.join("notes.txt").exists());
+
+    fs::write(scratch.dir.join("code"), "not a code").unwrap();
+    let output = scratch.run(&args);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("isn't a recovery code"), "{}", stderr);
+}
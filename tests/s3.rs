@@ -0,0 +1,287 @@
+//! Runs `encryptor` against a minimal in-process S3 server, to check that files encrypted to `s3://` URIs, in one
+//! request or a multipart upload, decrypt and verify back from there, that uploads go to the storage class asked
+//! for, and that a missing object is reported.
+#![cfg(feature = "s3")]
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+
+// The objects stored, by path, with the storage class each was uploaded to, and the parts and storage class of
+// the multipart upload in progress
+#[derive(Default)]
+struct Store {
+    objects: HashMap<String, Vec<u8>>,
+    classes: HashMap<String, String>,
+    parts: BTreeMap<u32, Vec<u8>>,
+    upload_class: Option<String>,
+}
+
+// Serve path-style S3 requests on a free port until the test ends; signatures aren't checked
+fn serve() -> (String, Arc<Mutex<Store>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let store = Arc::new(Mutex::new(Store::default()));
+    let shared = Arc::clone(&store);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let store = Arc::clone(&shared);
+            std::thread::spawn(move || handle(stream, &store));
+        }
+    });
+    (endpoint, store)
+}
+
+fn handle(stream: TcpStream, store: &Mutex<Store>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut stream = stream;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut words = line.split_whitespace();
+        let method = words.next().unwrap_or_default().to_string();
+        let target = words.next().unwrap_or_default().to_string();
+        let mut length = 0;
+        let mut class = None;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap();
+                }
+                if name.eq_ignore_ascii_case("x-amz-storage-class") {
+                    class = Some(value.trim().to_string());
+                }
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+        let mut store = store.lock().unwrap();
+        let (status, headers, reply) = match (method.as_str(), query) {
+            ("PUT", "") => {
+                store.objects.insert(path.to_string(), body);
+                if let Some(class) = class {
+                    store.classes.insert(path.to_string(), class);
+                }
+                ("200 OK", String::new(), Vec::new())
+            }
+            ("POST", "uploads=") => {
+                store.parts.clear();
+                store.upload_class = class;
+                let reply = "<InitiateMultipartUploadResult><UploadId>u1</UploadId></InitiateMultipartUploadResult>";
+                ("200 OK", String::new(), reply.as_bytes().to_vec())
+            }
+            ("PUT", query) => {
+                let number: u32 = query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("partNumber="))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                store.parts.insert(number, body);
+                let etag = format!("ETag: \"p{}\"\r\n", number);
+                ("200 OK", etag, Vec::new())
+            }
+            ("POST", _) => {
+                let object = std::mem::take(&mut store.parts).into_values().flatten();
+                store.objects.insert(path.to_string(), object.collect());
+                if let Some(class) = store.upload_class.take() {
+                    store.classes.insert(path.to_string(), class);
+                }
+                let reply = "<CompleteMultipartUploadResult></CompleteMultipartUploadResult>";
+                ("200 OK", String::new(), reply.as_bytes().to_vec())
+            }
+            ("GET", _) => match store.objects.get(path) {
+                Some(object) => ("200 OK", String::new(), object.clone()),
+                None => {
+                    let reply = "<Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message></Error>";
+                    ("404 Not Found", String::new(), reply.as_bytes().to_vec())
+                }
+            },
+            _ => ("204 No Content", String::new(), Vec::new()),
+        };
+        drop(store);
+        let head = format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\n\r\n",
+            status,
+            headers,
+            reply.len()
+        );
+        if stream.write_all(head.as_bytes()).is_err() || stream.write_all(&reply).is_err() {
+            return;
+        }
+    }
+}
+
+// A scratch directory holding a password file, removed when the test is done
+struct Scratch {
+    dir: PathBuf,
+    endpoint: String,
+}
+
+impl Scratch {
+    fn new(name: &str, endpoint: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("encryptor-s3-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pass"), "correct horse battery staple\n").unwrap();
+        Scratch {
+            dir,
+            endpoint: endpoint.to_string(),
+        }
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_encryptor"))
+            .current_dir(&self.dir)
+            .env("ENCRYPTOR_CONFIG_DIR", self.dir.join("conf"))
+            .env("AWS_ACCESS_KEY_ID", "AKIDTEST")
+            .env("AWS_SECRET_ACCESS_KEY", "secret")
+            .env("AWS_REGION", "eu-west-1")
+            .env("AWS_ENDPOINT_URL", &self.endpoint)
+            .env("AWS_CONFIG_FILE", self.dir.join("aws-config"))
+            .args(args)
+            .args(["--passfile", "pass", "--color", "never"])
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn encrypt_to_and_decrypt_from_s3() {
+    let (endpoint, store) = serve();
+    let scratch = Scratch::new("roundtrip", &endpoint);
+    let small = b"The quick brown fox jumps over the lazy dog".to_vec();
+    // Larger than a part, to go through a multipart upload
+    let large: Vec<u8> = (0..9 << 20).map(|i: u32| (i % 251) as u8).collect();
+    fs::write(scratch.dir.join("small.txt"), &small).unwrap();
+    fs::write(scratch.dir.join("large.bin"), &large).unwrap();
+
+    let output = scratch.run(&[
+        "encrypt",
+        "small.txt",
+        "large.bin",
+        "--out",
+        "s3://bucket/backups/",
+        "--kdf-memory",
+        "1024",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    {
+        let store = store.lock().unwrap();
+        assert!(store.objects.contains_key("/bucket/backups/small.txt.enc"));
+        assert!(store.objects["/bucket/backups/large.bin.enc"].len() > large.len());
+    }
+
+    let output = scratch.run(&["verify", "s3://bucket/backups/large.bin.enc"]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.run(&[
+        "decrypt",
+        "s3://bucket/backups/small.txt.enc",
+        "s3://bucket/backups/large.bin.enc",
+        "--out",
+        "plain",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read(scratch.dir.join("plain/small.txt")).unwrap(),
+        small
+    );
+    assert_eq!(
+        fs::read(scratch.dir.join("plain/large.bin")).unwrap(),
+        large
+    );
+}
+
+#[test]
+fn uploads_go_to_the_storage_class_asked_for() {
+    let (endpoint, store) = serve();
+    let scratch = Scratch::new("storage-class", &endpoint);
+    fs::write(scratch.dir.join("small.txt"), b"The quick brown fox").unwrap();
+    let large: Vec<u8> = (0..9 << 20).map(|i: u32| (i % 251) as u8).collect();
+    fs::write(scratch.dir.join("large.bin"), &large).unwrap();
+    let encrypt = |extra: &[&str]| {
+        let mut args = vec![
+            "encrypt",
+            "small.txt",
+            "large.bin",
+            "--out",
+            "s3://bucket/cold/",
+            "--kdf-memory",
+            "1024",
+        ];
+        args.extend_from_slice(extra);
+        let output = scratch.run(&args);
+        assert!(output.status.success(), "{:?}", output);
+        let store = store.lock().unwrap();
+        let class = |key: &str| store.classes.get(key).cloned();
+        (
+            class("/bucket/cold/small.txt.enc"),
+            class("/bucket/cold/large.bin.enc"),
+        )
+    };
+
+    // The bucket's default, unless the config file or the command names one, the command first
+    assert_eq!(encrypt(&[]), (None, None));
+    fs::create_dir_all(scratch.dir.join("conf")).unwrap();
+    fs::write(
+        scratch.dir.join("conf/config.toml"),
+        "storage-class = \"glacier\"\n",
+    )
+    .unwrap();
+    let glacier = Some("GLACIER".to_string());
+    assert_eq!(encrypt(&[]), (glacier.clone(), glacier));
+    let infrequent = Some("STANDARD_IA".to_string());
+    assert_eq!(
+        encrypt(&["--storage-class", "standard_ia"]),
+        (infrequent.clone(), infrequent)
+    );
+
+    // Only s3:// outputs have one
+    let output = scratch.run(&["encrypt", "small.txt", "--storage-class", "GLACIER"]);
+    assert_eq!(output.status.code(), Some(2));
+    let output = scratch.run(&[
+        "encrypt",
+        "small.txt",
+        "--out",
+        "s3://bucket/",
+        "--storage-class",
+        "cold storage",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn missing_object_fails() {
+    let (endpoint, _store) = serve();
+    let scratch = Scratch::new("missing", &endpoint);
+    let output = scratch.run(&["decrypt", "s3://bucket/missing.enc", "--out", "plain"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stderr.contains("NoSuchKey") || stdout.contains("NoSuchKey"),
+        "{:?}",
+        output
+    );
+    assert!(!scratch.dir.join("plain").exists());
+}
@@ -0,0 +1,69 @@
+//! Decrypts encrypted files into memory with `Encryptor::decrypt_to_secret`, as an application loading its
+//! credentials at startup would: the plaintext comes back without anything being written, doesn't show in `Debug`
+//! output, and a renamed file is refused like `decrypt_file` refuses it.
+use encryptor::{EncryptError, Encryptor, KdfParams};
+use std::fs;
+
+fn encryptor() -> Encryptor {
+    Encryptor::new("correct horse battery staple").with_kdf_params(KdfParams {
+        memory: 1024,
+        iterations: 1,
+    })
+}
+
+#[test]
+fn files_decrypt_into_a_secret_buffer() {
+    let dir = std::env::temp_dir().join(format!("encryptor-secret-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let plaintext = b"database_password = \"hunter2\"\n";
+    fs::write(dir.join("credentials.toml"), plaintext).unwrap();
+    let encrypted = encryptor()
+        .encrypt_file(dir.join("credentials.toml"))
+        .unwrap();
+    fs::remove_file(dir.join("credentials.toml")).unwrap();
+
+    let secret = encryptor().decrypt_to_secret(&encrypted).unwrap();
+    assert_eq!(secret.expose_secret(), plaintext);
+    assert_eq!(secret.len(), plaintext.len());
+    assert!(!format!("{:?}", secret).contains("hunter2"));
+    // Nothing was written next to the file
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    // Chunked files too
+    let chunked = encryptor()
+        .with_stream_threshold(0)
+        .with_chunk_size(8)
+        .encrypt_bytes(plaintext)
+        .unwrap();
+    fs::write(dir.join("chunked.bin"), chunked).unwrap();
+    let secret = encryptor()
+        .decrypt_to_secret(dir.join("chunked.bin"))
+        .unwrap();
+    assert_eq!(secret.expose_secret(), plaintext);
+
+    // With the name sealed in front of the first chunk, which isn't part of the secret
+    fs::write(dir.join("sealed.toml"), plaintext).unwrap();
+    let sealed = encryptor()
+        .with_stream_threshold(0)
+        .with_chunk_size(8)
+        .with_name_encryption(true)
+        .encrypt_file(dir.join("sealed.toml"))
+        .unwrap();
+    fs::remove_file(dir.join("sealed.toml")).unwrap();
+    let secret = encryptor().decrypt_to_secret(&sealed).unwrap();
+    assert_eq!(secret.expose_secret(), plaintext);
+    fs::remove_file(&sealed).unwrap();
+
+    fs::rename(&encrypted, dir.join("other.toml.enc")).unwrap();
+    assert!(matches!(
+        encryptor().decrypt_to_secret(dir.join("other.toml.enc")),
+        Err(EncryptError::MetadataMismatch(_))
+    ));
+    // Reported like decrypt_file reports it for a chunked file, by the password check before any chunk
+    assert!(matches!(
+        Encryptor::new("wrong").decrypt_to_secret(dir.join("chunked.bin")),
+        Err(EncryptError::WrongPassword)
+    ));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,58 @@
+//! Runs the self-test through `selftest::run` and `encryptor selftest`, to check that every cipher and the KDF has a
+//! known answer that passes in this build, and that the command prints each check and exits successfully.
+mod common;
+
+use encryptor::header::Algorithm;
+use encryptor::selftest;
+
+#[test]
+fn every_check_passes() {
+    let checks = selftest::run();
+    for check in &checks {
+        assert!(check.result.is_ok(), "{}: {:?}", check.name, check.result);
+    }
+    let names: Vec<&str> = checks.iter().map(|check| check.name.as_str()).collect();
+    for algorithm in [
+        Algorithm::Aes256Gcm,
+        Algorithm::ChaCha20Poly1305,
+        Algorithm::Aes256GcmSiv,
+        Algorithm::Aes128Gcm,
+    ] {
+        let name = format!("{} known answer", algorithm.name());
+        assert!(names.contains(&name.as_str()), "{:?}", names);
+    }
+    assert!(names.contains(&"Argon2id known answer"), "{:?}", names);
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn selftest_prints_every_check() {
+    use common::Scratch;
+
+    let scratch = Scratch::create("selftest", "cli");
+    let output = scratch.encryptor(&["selftest"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        stdout.starts_with(&format!(
+            "encryptor {} with the {} backend\n",
+            env!("CARGO_PKG_VERSION"),
+            selftest::backend()
+        )),
+        "{}",
+        stdout
+    );
+    let checks = selftest::run();
+    for check in &checks {
+        assert!(
+            stdout.contains(&format!("ok     {}\n", check.name)),
+            "{}",
+            stdout
+        );
+    }
+    assert!(
+        stdout.contains(&format!("All {} checks passed", checks.len())),
+        "{}",
+        stdout
+    );
+}
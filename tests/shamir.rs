@@ -0,0 +1,124 @@
+//! Splits keys into Shamir shares and rebuilds them from every subset, and runs `encryptor encrypt --shares` and
+//! `decrypt --share` in a scratch directory: any threshold of the share files decrypts, fewer don't.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use encryptor::shamir::{self, Share};
+use encryptor::EncryptError;
+use std::fs;
+use std::process::Output;
+
+// A scratch directory
+impl Scratch {
+    fn new(name: &str) -> Self {
+        Scratch::create("shamir", name)
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args).output().unwrap()
+    }
+}
+
+#[test]
+fn every_threshold_of_shares_rebuilds_the_key() {
+    let key = [0x5a; 32];
+    let shares = shamir::split(&key, 3, 5).unwrap();
+    assert_eq!(shares.len(), 5);
+    for a in 0..5 {
+        for b in 0..5 {
+            for c in 0..5 {
+                if a == b || b == c || a == c {
+                    continue;
+                }
+                let subset = [shares[a].clone(), shares[b].clone(), shares[c].clone()];
+                assert_eq!(*shamir::combine(&subset).unwrap(), key);
+            }
+        }
+    }
+    // A share given twice doesn't count twice
+    let twice = [shares[0].clone(), shares[0].clone(), shares[1].clone()];
+    assert!(matches!(
+        shamir::combine(&twice),
+        Err(EncryptError::KeyModeMismatch(_))
+    ));
+    assert_ne!(*shares[0].to_bytes(), *shares[1].to_bytes());
+}
+
+#[test]
+fn shares_of_different_splits_are_not_combined() {
+    let first = shamir::split(&[1; 32], 2, 3).unwrap();
+    let second = shamir::split(&[1; 32], 2, 3).unwrap();
+    let mixed = [first[0].clone(), second[1].clone()];
+    assert!(matches!(
+        shamir::combine(&mixed),
+        Err(EncryptError::KeyModeMismatch(_))
+    ));
+
+    let bytes = first[2].to_bytes();
+    let share = Share::from_bytes(&bytes).unwrap();
+    assert_eq!((share.index(), share.threshold()), (3, 2));
+    assert!(Share::from_bytes(&bytes[1..]).is_err());
+    assert!(shamir::split(&[1; 32], 1, 3).is_err());
+    assert!(shamir::split(&[1; 32], 4, 3).is_err());
+}
+
+#[test]
+fn any_three_of_five_share_files_decrypt() {
+    let scratch = Scratch::new("files");
+    fs::write(scratch.dir.join("vault.txt"), "break glass\n").unwrap();
+
+    let output = scratch.run(&["encrypt", "vault.txt", "--shares", "5", "--threshold", "3"]);
+    assert!(output.status.success(), "{:?}", output);
+    for index in 1..=5 {
+        assert!(scratch
+            .dir
+            .join(format!("vault.txt.{}.share", index))
+            .exists());
+    }
+    fs::remove_file(scratch.dir.join("vault.txt")).unwrap();
+
+    let output = scratch.run(&[
+        "decrypt",
+        "vault.txt.enc",
+        "--share",
+        "vault.txt.2.share",
+        "--share",
+        "vault.txt.5.share",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(stderr.contains("but 3 are needed"), "{}", stderr);
+    assert!(!scratch.dir.join("vault.txt").exists());
+
+    let output = scratch.run(&[
+        "decrypt",
+        "vault.txt.enc",
+        "--share",
+        "vault.txt.2.share",
+        "--share",
+        "vault.txt.5.share",
+        "--share",
+        "vault.txt.4.share",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("vault.txt")).unwrap(),
+        "break glass\n"
+    );
+
+    // Existing shares are never overwritten
+    let output = scratch.run(&[
+        "encrypt",
+        "vault.txt",
+        "--shares",
+        "2",
+        "--threshold",
+        "2",
+        "--out",
+        "again.enc",
+    ]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(!scratch.dir.join("again.enc").exists());
+}
@@ -0,0 +1,91 @@
+//! Encrypts to the SSH Ed25519 public key in `tests/fixtures/ssh` with `--recipient-ssh` and decrypts with its
+//! private key as `--identity`, in a scratch directory, and checks that the library converts both halves of the key
+//! to the same X25519 key pair. Keys that can't be converted are refused.
+#![cfg(feature = "ssh")]
+
+mod common;
+
+use common::Scratch;
+use encryptor::recipient::{Identity, Recipient};
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+// A scratch directory
+impl Scratch {
+    fn new(name: &str) -> Self {
+        Scratch::create("ssh", name)
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args).output().unwrap()
+    }
+}
+
+fn key(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/ssh");
+    path.join(name).to_str().unwrap().to_string()
+}
+
+#[test]
+fn ssh_keys_encrypt_and_decrypt() {
+    let scratch = Scratch::new("round-trip");
+    fs::write(scratch.dir.join("notes.txt"), "for my ssh key\n").unwrap();
+
+    let output = scratch.run(&[
+        "encrypt",
+        "notes.txt",
+        "--recipient-ssh",
+        &key("id_ed25519.pub"),
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    fs::remove_file(scratch.dir.join("notes.txt")).unwrap();
+
+    let output = scratch.run(&["decrypt", "notes.txt.enc", "--identity", &key("id_ed25519")]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("notes.txt")).unwrap(),
+        "for my ssh key\n"
+    );
+
+    // The public key can be given as is too
+    let line = fs::read_to_string(key("id_ed25519.pub")).unwrap();
+    let output = scratch.run(&[
+        "encrypt",
+        "notes.txt",
+        "--recipient-ssh",
+        line.trim(),
+        "--out",
+        "again.enc",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn keys_other_than_ed25519_are_refused() {
+    let scratch = Scratch::new("rsa");
+    fs::write(scratch.dir.join("notes.txt"), "for my ssh key\n").unwrap();
+
+    let output = scratch.run(&[
+        "encrypt",
+        "notes.txt",
+        "--recipient-ssh",
+        &key("id_rsa.pub"),
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(stderr.contains("only ssh-ed25519 keys"), "{}", stderr);
+    assert!(!scratch.dir.join("notes.txt.enc").exists());
+}
+
+#[test]
+fn both_halves_of_an_ed25519_key_convert_to_one_x25519_key_pair() {
+    for seed in [[0u8; 32], [7; 32], [0xff; 32]] {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&seed);
+        let public_key = signing_key.verifying_key().to_bytes();
+        assert_eq!(
+            Identity::from_ed25519(&seed).recipient(),
+            Recipient::from_ed25519(&public_key).unwrap()
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Runs `encryptor` on files encrypted under passwords set at different times, to check that `report stale-keys`
+//! lists the directory's files whose password is older than --max-password-age (or the config's), that `decrypt`
+//! warns about them, and that the time isn't readable without the password.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+// A scratch directory holding a password file and a tree of files to encrypt
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("stale", name);
+        fs::create_dir_all(scratch.dir.join("tree/sub")).unwrap();
+        fs::create_dir_all(scratch.dir.join("conf")).unwrap();
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    // Run `encryptor <args> --passfile pass` in the directory; `info` takes no password
+    fn run(&self, args: &[&str]) -> Output {
+        let mut command = self.encryptor(args);
+        if args[0] != "info" {
+            command.args(["--passfile", "pass"]);
+        }
+        command.output().unwrap()
+    }
+
+    // Encrypt `name` under the tree, removing the plaintext
+    fn seal(&self, name: &str, args: &[&str]) {
+        let path = format!("tree/{}", name);
+        fs::write(self.dir.join(&path), name).unwrap();
+        let mut command = vec!["encrypt", &path, "--kdf-memory", "1024"];
+        command.extend_from_slice(args);
+        let output = self.run(&command);
+        assert!(output.status.success(), "{:?}", output);
+        fs::remove_file(self.dir.join(&path)).unwrap();
+    }
+}
+
+#[test]
+fn old_passwords_are_reported() {
+    let scratch = Scratch::new("report");
+    scratch.seal("old.txt", &["--password-set", "2020-02-29"]);
+    scratch.seal("sub/older.txt", &["--password-set", "2019-06-01"]);
+    scratch.seal("new.txt", &[]);
+
+    let output = scratch.run(&["report", "stale-keys", "tree"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert!(
+        stdout.contains("tree/old.txt.enc: its password was set on 2020-02-29 00:00:00 UTC"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("tree/sub/older.txt.enc"), "{}", stdout);
+    assert!(!stdout.contains("new.txt"), "{}", stdout);
+    assert!(
+        stderr.contains("2 of 3 files have a password set more than 365 days ago"),
+        "{}",
+        stderr
+    );
+    // Without --password-set, when it was set isn't known rather than taken to be when the file was encrypted
+    assert!(
+        stderr.contains("1 of 3 files don't record when their password was set"),
+        "{}",
+        stderr
+    );
+
+    // The config's age applies when none is given
+    fs::write(
+        scratch.dir.join("conf/config.toml"),
+        "max-password-age = \"36500d\"\n",
+    )
+    .unwrap();
+    let output = scratch.run(&["report", "stale-keys", "tree"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    // Another password can't tell how old the files' password is
+    fs::write(scratch.dir.join("pass"), "another password\n").unwrap();
+    let output = scratch.run(&["report", "stale-keys", "tree", "--max-password-age", "1d"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        stderr.contains("3 of 3 files don't open with this password"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn decrypt_warns_about_old_passwords() {
+    let scratch = Scratch::new("decrypt");
+    scratch.seal("old.txt", &["--password-set", "2020-01-01"]);
+    let output = scratch.run(&["decrypt", "tree/old.txt.enc", "--max-password-age", "30d"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        stderr.contains("its password was set on 2020-01-01 00:00:00 UTC"),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        fs::read(scratch.dir.join("tree/old.txt")).unwrap(),
+        b"old.txt"
+    );
+
+    let output = scratch.run(&["info", "tree/old.txt.enc"]);
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("2020-01-01"));
+}
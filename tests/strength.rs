@@ -0,0 +1,68 @@
+//! Encrypts with a common password and a long passphrase, to check that the weak one is warned about with its
+//! estimated crack time, that `--min-strength` refuses it without writing anything, and that a strong one passes.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+// A scratch directory holding notes.txt and the passwords `weak` and `strong`
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("strength", name);
+        fs::write(scratch.dir.join("notes.txt"), "remember the milk\n").unwrap();
+        fs::write(scratch.dir.join("weak"), "password\n").unwrap();
+        fs::write(
+            scratch.dir.join("strong"),
+            "correct horse battery staple gleaming\n",
+        )
+        .unwrap();
+        scratch
+    }
+
+    // Encrypt notes.txt with the password in `passfile`, and `args`
+    fn encrypt(&self, passfile: &str, args: &[&str]) -> Output {
+        self.encryptor(&["encrypt", "notes.txt", "--passfile", passfile])
+            .args(["--kdf-memory", "1024", "--force"])
+            .args(args)
+            .output()
+            .unwrap()
+    }
+}
+
+#[test]
+fn a_weak_password_is_warned_about() {
+    let scratch = Scratch::new("warned");
+    let output = scratch.encrypt("weak", &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        stderr.contains("Weak password (strength 0/4): it is built on a common password or word"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("it could be cracked in less than a minute at this Argon2id cost"),
+        "{}",
+        stderr
+    );
+    assert!(scratch.dir.join("notes.txt.enc").exists());
+}
+
+#[test]
+fn min_strength_refuses_a_weak_password() {
+    let scratch = Scratch::new("refused");
+    let output = scratch.encrypt("weak", &["--min-strength", "3"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(stderr.contains("(--min-strength is 3)"), "{}", stderr);
+    assert!(!scratch.dir.join("notes.txt.enc").exists());
+
+    let output = scratch.encrypt("strong", &["--min-strength", "3"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!stderr.contains("Weak password"), "{}", stderr);
+    assert!(scratch.dir.join("notes.txt.enc").exists());
+}
@@ -0,0 +1,217 @@
+//! Archives a tree holding symbolic links and a FIFO with `encryptor encrypt --archive` in a scratch directory:
+//! skipped with warnings by default, followed with --follow-symlinks, or stored and recreated with
+//! --preserve-symlinks. An archive whose link would let a later file or link be written outside the target is
+//! refused.
+#![cfg(all(feature = "cli", unix))]
+
+mod common;
+
+use common::Scratch;
+use encryptor::{Encryptor, KdfParams};
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// A scratch directory holding a tree with links
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("symlinks", name);
+        fs::create_dir_all(scratch.dir.join("tree/real")).unwrap();
+        fs::write(scratch.dir.join("tree/real/notes.txt"), "notes\n").unwrap();
+        symlink("real", scratch.dir.join("tree/shortcut")).unwrap();
+        symlink("..", scratch.dir.join("tree/real/up")).unwrap();
+        symlink("missing", scratch.dir.join("tree/dangling")).unwrap();
+        let fifo = Command::new("mkfifo")
+            .arg(scratch.dir.join("tree/pipe"))
+            .status()
+            .is_ok_and(|status| status.success());
+        assert!(fifo, "mkfifo failed");
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args).output().unwrap()
+    }
+
+    // Archive the tree with `flag`, extract it into `out`, and return what the archiving run printed
+    fn round_trip(&self, flag: Option<&str>, out: &str) -> String {
+        let mut args = vec!["encrypt", "--archive", "tree", "--out", "tree.enc"];
+        args.extend([
+            "--passfile",
+            "pass",
+            "--kdf-memory",
+            "1024",
+            "--kdf-iterations",
+            "1",
+        ]);
+        args.extend(flag);
+        let _ = fs::remove_file(self.dir.join("tree.enc"));
+        let output = self.run(&args);
+        assert!(output.status.success(), "{:?}", output);
+        let extracted = self.run(&[
+            "decrypt",
+            "--extract",
+            "tree.enc",
+            "--out",
+            out,
+            "--passfile",
+            "pass",
+        ]);
+        assert!(extracted.status.success(), "{:?}", extracted);
+        format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )
+    }
+}
+
+#[test]
+fn links_are_skipped_followed_or_preserved() {
+    let scratch = Scratch::new("policies");
+
+    let printed = scratch.round_trip(None, "skipped");
+    assert!(
+        printed.contains("Skipping tree/shortcut: a symbolic link"),
+        "{}",
+        printed
+    );
+    assert!(
+        printed.contains("Skipping tree/pipe: a FIFO"),
+        "{}",
+        printed
+    );
+    assert!(printed.contains("4 skipped"), "{}", printed);
+    let skipped = scratch.dir.join("skipped/tree");
+    assert!(skipped.join("real/notes.txt").exists());
+    assert!(fs::symlink_metadata(skipped.join("shortcut")).is_err());
+
+    let printed = scratch.round_trip(Some("--follow-symlinks"), "followed");
+    assert!(
+        printed.contains("a symbolic link to nothing"),
+        "{}",
+        printed
+    );
+    assert!(
+        printed.contains("a symbolic link to a directory it is in"),
+        "{}",
+        printed
+    );
+    let followed = scratch.dir.join("followed/tree/shortcut");
+    assert!(!fs::symlink_metadata(&followed)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(
+        fs::read_to_string(followed.join("notes.txt")).unwrap(),
+        "notes\n"
+    );
+
+    let printed = scratch.round_trip(Some("--preserve-symlinks"), "preserved");
+    assert!(printed.contains("and 3 symbolic links"), "{}", printed);
+    let preserved = scratch.dir.join("preserved/tree");
+    assert_eq!(
+        fs::read_link(preserved.join("shortcut")).unwrap(),
+        PathBuf::from("real")
+    );
+    assert_eq!(
+        fs::read_link(preserved.join("dangling")).unwrap(),
+        PathBuf::from("missing")
+    );
+    assert_eq!(
+        fs::read_link(preserved.join("real/up")).unwrap(),
+        PathBuf::from("..")
+    );
+    assert!(fs::symlink_metadata(preserved.join("pipe")).is_err());
+}
+
+#[test]
+fn nothing_is_extracted_through_a_link() {
+    let scratch = Scratch::new("escape");
+    let outside = scratch.dir.join("outside");
+    fs::create_dir_all(&outside).unwrap();
+
+    // A link to a directory outside the target, then a file under the link's name
+    let mut file = 5u64.to_le_bytes().to_vec();
+    file.extend_from_slice(b"owned");
+    scratch.seal_archive(
+        "evil.enc",
+        &[
+            (3, "evil", link(&outside.to_string_lossy())),
+            (1, "evil/planted", file),
+        ],
+    );
+    let output = scratch.extract("evil.enc", "target");
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(!outside.join("planted").exists());
+}
+
+#[test]
+fn no_link_is_made_under_a_link() {
+    let scratch = Scratch::new("nested");
+    let outside = scratch.dir.join("outside");
+    fs::create_dir_all(&outside).unwrap();
+
+    // A link to a directory outside the target, then another link under the first one's name
+    scratch.seal_archive(
+        "nested.enc",
+        &[
+            (3, "a", link(&outside.to_string_lossy())),
+            (3, "a/sub/x", link("/etc/passwd")),
+        ],
+    );
+    let output = scratch.extract("nested.enc", "target");
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("which is under the symbolic link"),
+        "{:?}",
+        output
+    );
+    assert!(fs::read_dir(&outside).unwrap().next().is_none());
+}
+
+// The target of an archived link, as its entry stores it
+fn link(target: &str) -> Vec<u8> {
+    let mut link = (target.len() as u16).to_le_bytes().to_vec();
+    link.extend_from_slice(target.as_bytes());
+    link
+}
+
+impl Scratch {
+    // Write an archive holding `entries` (kind, path, and what follows the mode and mtime) to `name`, encrypted
+    // with the password of the pass file
+    fn seal_archive(&self, name: &str, entries: &[(u8, &str, Vec<u8>)]) {
+        let mut container = b"ENCA\x02".to_vec();
+        for (kind, path, rest) in entries {
+            container.push(*kind);
+            container.extend_from_slice(&(path.len() as u16).to_le_bytes());
+            container.extend_from_slice(path.as_bytes());
+            container.extend_from_slice(&0o644u32.to_le_bytes());
+            container.extend_from_slice(&0i64.to_le_bytes());
+            container.extend_from_slice(rest);
+        }
+        container.push(0);
+        let sealed = Encryptor::new("correct horse battery staple")
+            .with_kdf_params(KdfParams {
+                memory: 1024,
+                iterations: 1,
+            })
+            .encrypt_bytes(&container)
+            .unwrap();
+        fs::write(self.dir.join(name), sealed).unwrap();
+    }
+
+    fn extract(&self, name: &str, out: &str) -> Output {
+        self.run(&[
+            "decrypt",
+            "--extract",
+            name,
+            "--out",
+            out,
+            "--passfile",
+            "pass",
+        ])
+    }
+}
@@ -0,0 +1,172 @@
+//! Runs `encryptor encrypt-text` and `decrypt-text` through arguments, stdin and, with a stand-in for xclip, the
+//! clipboard.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+// A scratch directory with a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("text", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = self.encryptor(args);
+        command.args(["--passfile", "pass"]);
+        if args[0] == "encrypt-text" {
+            command.args(["--kdf-memory", "1024", "--kdf-iterations", "1"]);
+        }
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    // Run with `input` on stdin
+    fn pipe(&self, args: &[&str], input: &[u8]) -> Output {
+        let mut child = self
+            .command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        child.wait_with_output().unwrap()
+    }
+}
+
+#[test]
+fn a_text_round_trips_through_armor() {
+    let scratch = Scratch::new("armor");
+    let output = scratch.run(&["encrypt-text", "meet at noon"]);
+    assert!(output.status.success(), "{:?}", output);
+    let armor = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        armor.starts_with("-----BEGIN ENCRYPTOR FILE-----\n"),
+        "{}",
+        armor
+    );
+
+    // The armor can be given as the argument, starting with dashes as it does
+    let output = scratch.run(&["decrypt-text", &armor]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(output.stdout, b"meet at noon");
+
+    let output = scratch.pipe(&["decrypt-text"], armor.as_bytes());
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(output.stdout, b"meet at noon");
+
+    let output = scratch.pipe(&["encrypt-text"], b"line one\nline two\n");
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.pipe(&["decrypt-text"], &output.stdout);
+    assert_eq!(output.stdout, b"line one\nline two\n");
+
+    let output = scratch.pipe(&["decrypt-text"], b"not armor");
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Decryption error"), "{}", stderr);
+}
+
+#[cfg(unix)]
+#[test]
+fn clipboard_is_read_and_written() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let scratch = Scratch::new("clipboard");
+    // An xclip that keeps the clipboard in a file
+    let bin = scratch.dir.join("bin");
+    fs::create_dir(&bin).unwrap();
+    let clipboard = scratch.dir.join("clipboard");
+    fs::write(
+        bin.join("xclip"),
+        format!(
+            "#!/bin/sh\ncase \"$*\" in *-out) cat '{0}' ;; *) cat > '{0}' ;; esac\n",
+            clipboard.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(bin.join("xclip"), fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let with_clipboard = |args: &[&str]| {
+        scratch
+            .command(args)
+            .env("PATH", &path)
+            .env("DISPLAY", ":0")
+            .env_remove("WAYLAND_DISPLAY")
+            .output()
+            .unwrap()
+    };
+
+    fs::write(&clipboard, "the safe code is 4711").unwrap();
+    let output = with_clipboard(&["encrypt-text", "--clipboard"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Copied the encrypted text to the clipboard"),
+        "{}",
+        stdout
+    );
+    let armor = fs::read_to_string(&clipboard).unwrap();
+    assert!(
+        armor.starts_with("-----BEGIN ENCRYPTOR FILE-----"),
+        "{}",
+        armor
+    );
+
+    let output = with_clipboard(&["decrypt-text", "--clipboard"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(&clipboard).unwrap(),
+        "the safe code is 4711"
+    );
+}
+
+#[test]
+fn the_line_scanned_from_a_qr_code_decrypts() {
+    let scratch = Scratch::new("scanned");
+    let output = scratch.run(&["encrypt-text", "meet at noon"]);
+    assert!(output.status.success(), "{:?}", output);
+    // A QR code holds the base64 of the armor, without its BEGIN and END lines
+    let armor = String::from_utf8(output.stdout).unwrap();
+    let line: String = armor
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let output = scratch.run(&["decrypt-text", &line]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(output.stdout, b"meet at noon");
+}
+
+#[cfg(feature = "qr")]
+#[test]
+fn qr_codes_are_printed_or_written_as_images() {
+    let scratch = Scratch::new("qr");
+    let output = scratch.run(&["encrypt-text", "meet at noon", "--qr"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains('█') && stdout.contains('▀'), "{}", stdout);
+
+    let output = scratch.run(&["encrypt-text", "meet at noon", "--qr-out", "code.png"]);
+    assert!(output.status.success(), "{:?}", output);
+    let image = fs::read(scratch.dir.join("code.png")).unwrap();
+    assert!(image.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+    let long = "x".repeat(4000);
+    let output = scratch.run(&["encrypt-text", &long, "--qr"]);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("too long for a QR code"), "{}", stderr);
+}
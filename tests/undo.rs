@@ -0,0 +1,115 @@
+//! Runs `encryptor encrypt --remove-to-trash` on scratch files with a trash of their own, to check that the originals
+//! go to the trash once encrypted and that `encryptor undo` puts them back and removes the outputs, leaving alone an
+//! output that changed since.
+#![cfg(all(feature = "cli", target_os = "linux"))]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Output;
+
+// A scratch directory with files to encrypt, a password file and the trash
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("undo", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        fs::write(scratch.dir.join("report.txt"), "quarterly numbers\n").unwrap();
+        fs::write(scratch.dir.join("notes.txt"), "meeting notes\n").unwrap();
+        scratch
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args)
+            // The freedesktop.org trash goes under XDG_DATA_HOME, on the same filesystem as the files
+            .env("XDG_DATA_HOME", self.path("data"))
+            .output()
+            .unwrap()
+    }
+
+    fn encrypt(&self) {
+        let output = self.run(&[
+            "encrypt",
+            "report.txt",
+            "notes.txt",
+            "--passfile",
+            "pass",
+            "--kdf-memory",
+            "1024",
+            "--kdf-iterations",
+            "1",
+            "--remove-to-trash",
+        ]);
+        assert!(output.status.success(), "{:?}", output);
+    }
+}
+
+#[test]
+fn undo_puts_the_originals_back() {
+    let scratch = Scratch::new("back");
+    scratch.encrypt();
+    assert!(!scratch.path("report.txt").exists());
+    assert!(!scratch.path("notes.txt").exists());
+    assert!(scratch.path("report.txt.enc").exists());
+    assert_eq!(
+        fs::read_to_string(scratch.path("data/Trash/files/report.txt")).unwrap(),
+        "quarterly numbers\n"
+    );
+
+    let output = scratch.run(&["undo"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Restored 2 of 2 originals"), "{}", stdout);
+    assert_eq!(
+        fs::read_to_string(scratch.path("report.txt")).unwrap(),
+        "quarterly numbers\n"
+    );
+    assert_eq!(
+        fs::read_to_string(scratch.path("notes.txt")).unwrap(),
+        "meeting notes\n"
+    );
+    assert!(!scratch.path("report.txt.enc").exists());
+    assert!(!scratch.path("notes.txt.enc").exists());
+
+    // There is nothing left to undo
+    let output = scratch.run(&["undo"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Nothing to undo"));
+}
+
+#[test]
+fn a_changed_output_keeps_its_original_in_the_trash() {
+    let scratch = Scratch::new("changed");
+    scratch.encrypt();
+    fs::write(scratch.path("notes.txt.enc"), "replaced").unwrap();
+
+    let output = scratch.run(&["undo"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("changed since it was written"),
+        "{}",
+        stderr
+    );
+    assert!(scratch.path("report.txt").exists());
+    assert!(!scratch.path("report.txt.enc").exists());
+    assert!(!scratch.path("notes.txt").exists());
+    assert_eq!(
+        fs::read_to_string(scratch.path("notes.txt.enc")).unwrap(),
+        "replaced"
+    );
+
+    // The file that failed stays recorded, and is put back once its output is out of the way
+    fs::remove_file(scratch.path("notes.txt.enc")).unwrap();
+    let output = scratch.run(&["undo"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(scratch.path("notes.txt")).unwrap(),
+        "meeting notes\n"
+    );
+}
@@ -0,0 +1,111 @@
+//! Runs `encryptor` with `--human` and `--raw` to check that `info`, `--dry-run` and `report stale-keys` show the
+//! same sizes, durations and times for people (the default) or as bytes and seconds, and that the two don't mix.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+// A scratch directory holding a password file and notes.txt encrypted under a password set on 2020-01-01
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("units", name);
+        fs::create_dir_all(scratch.dir.join("conf")).unwrap();
+        fs::write(scratch.dir.join("notes.txt"), "remember the milk\n").unwrap();
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        let output = scratch.run(&[
+            "encrypt",
+            "notes.txt",
+            "--passfile",
+            "pass",
+            "--kdf-memory",
+            "1024",
+            "--password-set",
+            "2020-01-01",
+        ]);
+        assert!(output.status.success(), "{:?}", output);
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.encryptor(args).output().unwrap()
+    }
+
+    fn stdout(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+}
+
+#[test]
+fn sizes_and_times_are_human_by_default() {
+    let scratch = Scratch::new("human");
+    for flags in [&[][..], &["--human"][..]] {
+        let info = scratch.stdout(&[&["info", "notes.txt.enc"][..], flags].concat());
+        assert!(
+            info.contains("payload:       18 B, the size of the original file"),
+            "{}",
+            info
+        );
+        assert!(info.contains(" UTC\n"), "{}", info);
+    }
+
+    let plan = scratch.stdout(&["encrypt", "notes.txt", "--dry-run", "--force"]);
+    assert!(
+        plan.contains("(18 B, replacing the existing file)"),
+        "{}",
+        plan
+    );
+
+    let report = scratch.stdout(&["report", "stale-keys", ".", "--passfile", "pass"]);
+    assert!(
+        report.contains("its password was set on 2020-01-01 00:00:00 UTC, ")
+            && report.contains(" days ago"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn raw_shows_bytes_and_seconds() {
+    let scratch = Scratch::new("raw");
+    let info = scratch.stdout(&["info", "notes.txt.enc", "--raw"]);
+    assert!(
+        info.contains("payload:       18 bytes, the size of the original file"),
+        "{}",
+        info
+    );
+    assert!(!info.contains(" UTC"), "{}", info);
+
+    let plan = scratch.stdout(&["encrypt", "notes.txt", "--dry-run", "--force", "--raw"]);
+    assert!(
+        plan.contains("(18 bytes, replacing the existing file)"),
+        "{}",
+        plan
+    );
+    assert!(!plan.contains("iB"), "{}", plan);
+
+    let output = scratch.run(&[
+        "report",
+        "stale-keys",
+        ".",
+        "--passfile",
+        "pass",
+        "--max-password-age",
+        "30d",
+        "--raw",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stdout.contains("its password was set on 1577836800, "),
+        "{}",
+        stdout
+    );
+    assert!(stderr.contains("more than 2592000 s ago"), "{}", stderr);
+
+    let output = scratch.run(&["info", "notes.txt.enc", "--raw", "--human"]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+}
@@ -0,0 +1,117 @@
+//! Runs `encryptor vault` in a scratch directory: secrets are set from arguments and stdin, listed, read back and
+//! removed, and the vault keeps the KDF cost it was created with.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use encryptor::header::Header;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+// A scratch directory with a password file
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("vault", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = self.encryptor(&[&["vault"][..], args].concat());
+        command.args(["--passfile", "pass"]);
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.command(args).stdin(Stdio::null()).output().unwrap()
+    }
+
+    // Run with `input` on stdin
+    fn pipe(&self, args: &[&str], input: &[u8]) -> Output {
+        let mut child = self
+            .command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        child.wait_with_output().unwrap()
+    }
+}
+
+#[test]
+fn secrets_are_set_listed_read_and_removed() {
+    let scratch = Scratch::new("secrets");
+    let output = scratch.run(&["init", "--kdf-memory", "1024", "--kdf-iterations", "1"]);
+    assert!(output.status.success(), "{:?}", output);
+    let vault = scratch.dir.join("conf").join("vault.enc");
+    assert!(vault.is_file());
+
+    let output = scratch.run(&["set", "github-token", "ghp_0123456789"]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.pipe(&["set", "aws-secret"], b"-----wJalrXUtnFEMI\n");
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.run(&["list"]);
+    assert_eq!(output.stdout, b"aws-secret\ngithub-token\n");
+
+    let output = scratch.run(&["get", "aws-secret"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(output.stdout, b"-----wJalrXUtnFEMI");
+
+    // A value set again is replaced, and the vault is sealed again at the cost it was created with
+    let output = scratch.run(&["set", "github-token", "ghp_rotated"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Updated 'github-token'"), "{}", stdout);
+    assert_eq!(scratch.run(&["get", "github-token"]).stdout, b"ghp_rotated");
+    let (header, _) = Header::parse(&fs::read(&vault).unwrap()).unwrap();
+    assert_eq!(header.kdf_params.memory, 1024);
+
+    let output = scratch.run(&["rm", "aws-secret"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(scratch.run(&["list"]).stdout, b"github-token\n");
+    let output = scratch.run(&["get", "aws-secret"]);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("no secret named 'aws-secret'"),
+        "{}",
+        stderr
+    );
+
+    // An existing vault is only replaced with --force
+    let output = scratch.run(&["init", "--kdf-memory", "1024", "--kdf-iterations", "1"]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert_eq!(scratch.run(&["list"]).stdout, b"github-token\n");
+}
+
+#[test]
+fn a_vault_opens_only_with_its_password() {
+    let scratch = Scratch::new("password");
+    let output = scratch.run(&["get", "anything", "--vault", "team.enc"]);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("vault init"), "{}", stderr);
+
+    let output = scratch.run(&[
+        "init",
+        "--vault",
+        "team.enc",
+        "--kdf-memory",
+        "1024",
+        "--kdf-iterations",
+        "1",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let output = scratch.run(&["set", "db", "hunter2", "--vault", "team.enc"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    fs::write(scratch.dir.join("pass"), "another horse battery staple\n").unwrap();
+    let output = scratch.run(&["get", "db", "--vault", "team.enc"]);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ENC_WRONG_PASSWORD"), "{}", stderr);
+    assert!(output.stdout.is_empty());
+}
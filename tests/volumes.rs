@@ -0,0 +1,112 @@
+//! Runs `encryptor encrypt --split-size` in a scratch directory, to check that the volumes decrypt back as one file
+//! and that a missing or cut-short volume is reported instead of decrypted.
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// A scratch directory holding a password file and `data.bin`, removed when the test is done
+struct Scratch {
+    dir: PathBuf,
+    data: Vec<u8>,
+}
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("encryptor-volumes-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pass"), "correct horse battery staple\n").unwrap();
+        let data: Vec<u8> = (0..350_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(dir.join("data.bin"), &data).unwrap();
+        Scratch { dir, data }
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_encryptor"))
+            .current_dir(&self.dir)
+            .env("ENCRYPTOR_CONFIG_DIR", self.dir.join("conf"))
+            .args(args)
+            .args(["--passfile", "pass", "--color", "never"])
+            .output()
+            .unwrap()
+    }
+
+    // Encrypt data.bin into volumes of 100K
+    fn split(&self) {
+        let output = self.run(&[
+            "encrypt",
+            "data.bin",
+            "--split-size",
+            "100K",
+            "--kdf-memory",
+            "1024",
+        ]);
+        assert!(output.status.success(), "{:?}", output);
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn volumes_decrypt_as_one_file() {
+    let scratch = Scratch::new("roundtrip");
+    scratch.split();
+    for volume in ["data.bin.enc.001", "data.bin.enc.002", "data.bin.enc.003"] {
+        let len = fs::metadata(scratch.dir.join(volume)).unwrap().len();
+        assert_eq!(len, 100 << 10, "{}", volume);
+    }
+    assert!(scratch.dir.join("data.bin.enc.004").exists());
+    assert!(!scratch.dir.join("data.bin.enc.005").exists());
+
+    // Every volume given, as a glob would, still decrypts the file once
+    fs::remove_file(scratch.dir.join("data.bin")).unwrap();
+    let output = scratch.run(&[
+        "decrypt",
+        "data.bin.enc.001",
+        "data.bin.enc.002",
+        "data.bin.enc.003",
+        "data.bin.enc.004",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read(scratch.dir.join("data.bin")).unwrap(),
+        scratch.data
+    );
+
+    let output = scratch.run(&["decrypt", "data.bin.enc", "--out", "copy.bin"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read(scratch.dir.join("copy.bin")).unwrap(),
+        scratch.data
+    );
+}
+
+#[test]
+fn missing_or_short_volumes_fail() {
+    let scratch = Scratch::new("damaged");
+    scratch.split();
+    fs::rename(
+        scratch.dir.join("data.bin.enc.002"),
+        scratch.dir.join("held"),
+    )
+    .unwrap();
+    let output = scratch.run(&["verify", "data.bin.enc.001"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("data.bin.enc.002 is missing"),
+        "{:?}",
+        output
+    );
+
+    fs::write(scratch.dir.join("data.bin.enc.002"), b"short").unwrap();
+    let output = scratch.run(&["decrypt", "data.bin.enc", "--out", "copy.bin"]);
+    assert!(!output.status.success());
+    assert!(!scratch.dir.join("copy.bin").exists());
+}
@@ -0,0 +1,35 @@
+//! Round trips through the functions the `wasm` module exports to JavaScript, natively: their output must be the
+//! format the rest of the library reads. Their errors are JavaScript objects, which only exist in a wasm runtime.
+//! Run with `cargo test --features wasm`.
+#![cfg(feature = "wasm")]
+
+use encryptor::{wasm, Encryptor};
+use wasm_bindgen::JsError;
+
+// JsError has no Debug to unwrap() with
+fn ok(result: Result<Vec<u8>, JsError>) -> Vec<u8> {
+    result.ok().expect("the exported function failed")
+}
+
+#[test]
+fn exported_functions_use_the_library_format() {
+    let encryptor = Encryptor::new("correct horse");
+    let sealed = ok(wasm::encrypt_bytes(
+        "correct horse",
+        b"hello from a browser",
+    ));
+    assert_eq!(
+        encryptor.decrypt_bytes(&sealed).unwrap(),
+        b"hello from a browser"
+    );
+    let sealed = encryptor.encrypt_bytes(b"hello").unwrap();
+    assert_eq!(ok(wasm::decrypt_bytes("correct horse", &sealed)), b"hello");
+
+    let key = [7; 32];
+    let sealed = ok(wasm::encrypt_bytes_with_key(&key, b"hello"));
+    assert_eq!(
+        Encryptor::from_key(key).decrypt_bytes(&sealed).unwrap(),
+        b"hello"
+    );
+    assert_eq!(ok(wasm::decrypt_bytes_with_key(&key, &sealed)), b"hello");
+}
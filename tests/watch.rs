@@ -0,0 +1,112 @@
+//! Runs `encryptor watch` on a scratch directory, to check that new, changed and moved-in files are encrypted into
+//! the output tree and excluded ones aren't.
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+// A running `encryptor watch in --out out` in a scratch directory, stopped and removed when the test is done
+struct Watch {
+    dir: PathBuf,
+    child: Child,
+}
+
+impl Watch {
+    fn start(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("encryptor-watch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("in")).unwrap();
+        fs::write(dir.join("pass"), "correct horse battery staple\n").unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_encryptor"))
+            .current_dir(&dir)
+            .env("ENCRYPTOR_CONFIG_DIR", dir.join("conf"))
+            .args(["watch", "in", "--out", "out", "--passfile", "pass"])
+            .args(["--kdf-memory", "1024", "--debounce", "200ms"])
+            .args(["--exclude", "*.tmp", "--color", "never"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let watch = Watch { dir, child };
+        // The output directory is created once the watch is set up
+        watch.wait_for("out");
+        std::thread::sleep(Duration::from_millis(200));
+        watch
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    // Wait up to 10 seconds for `name` to exist
+    fn wait_for(&self, name: &str) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(10) {
+            if self.path(name).exists() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    fn decrypt(&self, name: &str) -> Vec<u8> {
+        let output = Command::new(env!("CARGO_BIN_EXE_encryptor"))
+            .current_dir(&self.dir)
+            .args([
+                "decrypt",
+                name,
+                "--out",
+                "plain",
+                "--force",
+                "--passfile",
+                "pass",
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        fs::read(self.path("plain")).unwrap()
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn new_and_changed_files_are_encrypted() {
+    let watch = Watch::start("files");
+    fs::write(watch.path("in/a.txt"), "first").unwrap();
+    fs::write(watch.path("in/skip.tmp"), "temporary").unwrap();
+    assert!(watch.wait_for("out/a.txt.enc"));
+    assert_eq!(watch.decrypt("out/a.txt.enc"), b"first");
+
+    fs::write(watch.path("in/a.txt"), "second").unwrap();
+    let start = Instant::now();
+    while watch.decrypt("out/a.txt.enc") != b"second" {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "the change wasn't encrypted"
+        );
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    assert!(!watch.path("out/skip.tmp.enc").exists());
+}
+
+#[test]
+fn moved_in_directories_are_encrypted() {
+    let watch = Watch::start("tree");
+    let staging = watch.path("staging");
+    fs::create_dir_all(staging.join("nested")).unwrap();
+    fs::write(staging.join("nested/b.txt"), "moved").unwrap();
+    fs::rename(&staging, watch.path("in/staging")).unwrap();
+    assert!(watch.wait_for("out/staging/nested/b.txt.enc"));
+    assert_eq!(watch.decrypt("out/staging/nested/b.txt.enc"), b"moved");
+}
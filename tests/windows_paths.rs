@@ -0,0 +1,117 @@
+//! Paths longer than Windows' 260 characters, through the library's file functions and `encrypt --archive`, and
+//! `--archive --ads`, which packs the NTFS alternate data streams of the files and `--extract` restores. Elsewhere
+//! long paths are nothing special and there are no streams, so only the round trips themselves are checked there.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::Scratch;
+use encryptor::{Encryptor, KdfParams};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+// A scratch directory
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let scratch = Scratch::create("windows-paths", name);
+        fs::write(scratch.dir.join("pass"), "correct horse battery staple\n").unwrap();
+        scratch
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        let output = self
+            .encryptor(args)
+            .args(["--passfile", "pass"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        output
+    }
+}
+
+// A directory under `root` nested deep enough that its path is over 300 characters, created through its long form
+fn deep_directory(root: &Path) -> PathBuf {
+    let mut dir = root.to_path_buf();
+    while dir.as_os_str().len() <= 300 {
+        dir.push("a-directory-with-a-rather-long-name");
+    }
+    fs::create_dir_all(encryptor::names::long_path(&dir)).unwrap();
+    dir
+}
+
+#[test]
+fn files_past_the_path_limit_round_trip() {
+    let scratch = Scratch::new("long");
+    let dir = deep_directory(&scratch.dir.join("tree"));
+    let file_path = dir.join("notes.txt");
+    fs::write(encryptor::names::long_path(&file_path), "deep notes\n").unwrap();
+
+    let encryptor = Encryptor::new("correct horse battery staple").with_kdf_params(KdfParams {
+        memory: 1024,
+        iterations: 1,
+    });
+    let encrypted = encryptor.encrypt_file(&file_path).unwrap();
+    fs::remove_file(encryptor::names::long_path(&file_path)).unwrap();
+    let decrypted = encryptor.decrypt_file(&encrypted).unwrap();
+    assert_eq!(fs::read_to_string(&decrypted).unwrap(), "deep notes\n");
+    assert_eq!(decrypted.file_name().unwrap(), "notes.txt");
+
+    scratch.run(&["encrypt", "--archive", "tree", "--out", "tree.enc"]);
+    scratch.run(&["decrypt", "--extract", "tree.enc", "--out", "out"]);
+    let relative = file_path.strip_prefix(&scratch.dir).unwrap();
+    let extracted =
+        encryptor::names::long_path(&scratch.dir.join("out").join(relative)).into_owned();
+    assert_eq!(fs::read_to_string(extracted).unwrap(), "deep notes\n");
+}
+
+#[test]
+fn alternate_data_streams_are_packed_with_ads() {
+    let scratch = Scratch::new("ads");
+    fs::create_dir_all(scratch.dir.join("tree")).unwrap();
+    fs::write(scratch.dir.join("tree/download.zip"), "zip\n").unwrap();
+    #[cfg(windows)]
+    fs::write(
+        scratch.dir.join("tree/download.zip:Zone.Identifier"),
+        "[ZoneTransfer]\r\nZoneId=3\r\n",
+    )
+    .unwrap();
+
+    let archived = scratch.run(&["encrypt", "--archive", "tree", "--out", "tree.enc", "--ads"]);
+    let extracted = scratch.run(&["decrypt", "--extract", "tree.enc", "--out", "out"]);
+    assert_eq!(
+        fs::read_to_string(scratch.dir.join("out/tree/download.zip")).unwrap(),
+        "zip\n"
+    );
+    #[cfg(windows)]
+    {
+        assert!(
+            String::from_utf8_lossy(&archived.stdout).contains("with 1 alternate data streams"),
+            "{:?}",
+            archived
+        );
+        assert!(
+            String::from_utf8_lossy(&extracted.stdout).contains("with 1 alternate data streams"),
+            "{:?}",
+            extracted
+        );
+        assert_eq!(
+            fs::read_to_string(scratch.dir.join("out/tree/download.zip:Zone.Identifier")).unwrap(),
+            "[ZoneTransfer]\r\nZoneId=3\r\n"
+        );
+
+        // Without --ads the stream is left behind
+        scratch.run(&["encrypt", "--archive", "tree", "--out", "plain.enc"]);
+        scratch.run(&["decrypt", "--extract", "plain.enc", "--out", "plain"]);
+        assert!(fs::read(scratch.dir.join("plain/tree/download.zip:Zone.Identifier")).is_err());
+    }
+    #[cfg(not(windows))]
+    {
+        assert!(
+            String::from_utf8_lossy(&archived.stderr).contains("only NTFS on Windows"),
+            "{:?}",
+            archived
+        );
+        assert!(!String::from_utf8_lossy(&extracted.stdout).contains("streams"));
+    }
+}